@@ -0,0 +1,95 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The benchmarks of the programs in `starlark::bench`, which needs the `bench` feature.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use starlark::{
+    bench::{MICRO_BENCHMARKS, MODULE_LOAD, MODULE_LOAD_LIBRARY},
+    collections::SmallMap,
+    environment::{Globals, Module},
+    eval::{Evaluator, ReturnFileLoader},
+    syntax::{AstModule, Dialect},
+};
+use std::collections::HashMap;
+
+pub fn criterion_micro_benchmark(c: &mut Criterion, globals: &Globals) {
+    for (name, code) in MICRO_BENCHMARKS {
+        c.bench_function(name, |b| {
+            let env = Module::new();
+            let mut context = Evaluator::new(&env, globals);
+            let ast =
+                AstModule::parse("benchmark.sky", (*code).to_owned(), &Dialect::Standard).unwrap();
+            let bench_function = context.eval_module(ast).unwrap();
+            b.iter(move || context.eval_function(bench_function, &[], &[]).unwrap())
+        });
+    }
+}
+
+pub fn criterion_small_map_benchmark(c: &mut Criterion) {
+    // Remove half the entries, from the front, which is the worst case for shift_remove
+    fn remove_half(remove: fn(&mut SmallMap<i32, i32>, &i32) -> Option<i32>) {
+        let mut m = (0..1000).map(|i| (i, i)).collect::<SmallMap<_, _>>();
+        for i in 0..500 {
+            remove(&mut m, &i);
+        }
+    }
+    c.bench_function("small_map_shift_remove", |b| {
+        b.iter(|| remove_half(|m, k| m.shift_remove(k)))
+    });
+    c.bench_function("small_map_swap_remove", |b| {
+        b.iter(|| remove_half(|m, k| m.swap_remove(k)))
+    });
+}
+
+pub fn criterion_load_benchmark(c: &mut Criterion, globals: &Globals) {
+    let library = {
+        let env = Module::new();
+        let mut context = Evaluator::new(&env, globals);
+        let ast = AstModule::parse(
+            "library.star",
+            MODULE_LOAD_LIBRARY.to_owned(),
+            &Dialect::Standard,
+        )
+        .unwrap();
+        context.eval_module(ast).unwrap();
+        env.freeze()
+    };
+    let mut modules = HashMap::new();
+    modules.insert("library.star", &library);
+    c.bench_function("module_load", |b| {
+        b.iter(|| {
+            let env = Module::new();
+            let mut loader = ReturnFileLoader { modules: &modules };
+            let mut context = Evaluator::new(&env, globals);
+            context.set_loader(&mut loader);
+            let ast = AstModule::parse("benchmark.sky", MODULE_LOAD.to_owned(), &Dialect::Standard)
+                .unwrap();
+            context.eval_module(ast).unwrap();
+        })
+    });
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    let g = Globals::extended();
+    criterion_micro_benchmark(c, &g);
+    criterion_load_benchmark(c, &g);
+    criterion_small_map_benchmark(c);
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -16,46 +16,98 @@
  */
 
 use crate::types::Message;
+use anyhow::anyhow;
 use itertools::Either;
 use starlark::{
+    analysis::LintConfig,
+    collections::SmallMap,
     environment::{FrozenModule, Globals, Module},
     eval::Evaluator,
     syntax::{AstModule, Dialect},
+    values::{dict::Dict, FunctionProfile, Heap, Value},
 };
 use std::{
-    fs, iter,
+    convert::TryFrom,
+    fmt, fs, iter,
     path::{Path, PathBuf},
 };
 
+/// Chooses the [`Dialect`] for a file from its path, returning `None` to use the default.
+pub struct DialectForFile(pub Box<dyn Fn(&Path) -> Option<Dialect>>);
+
+impl fmt::Debug for DialectForFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DialectForFile")
+    }
+}
+
 #[derive(Debug)]
 pub struct Context {
     pub check: bool,
     pub info: bool,
     pub run: bool,
     pub prelude: Vec<FrozenModule>,
+    pub lint_config: LintConfig,
+    pub dialect: Dialect,
+    // Overrides `dialect` for some files, e.g. to apply stricter rules to `BUILD` files
+    pub dialect_for_file: Option<DialectForFile>,
+    pub globals: Globals,
+    // Variables to bind before running, whose presence causes the result to be printed as JSON
+    pub inputs: Vec<(String, serde_json::Value)>,
+    // Print a table of the time spent in each function after running
+    pub profile: bool,
+    // Rather than just running, call the `_test` functions and report on each
+    pub test: bool,
 }
 
 impl Context {
-    pub fn new(check: bool, info: bool, run: bool, prelude: &[PathBuf]) -> anyhow::Result<Self> {
-        let globals = globals();
-        let prelude = prelude
+    pub fn new(
+        check: bool,
+        info: bool,
+        run: bool,
+        prelude: &[PathBuf],
+        lint_config: LintConfig,
+    ) -> anyhow::Result<Self> {
+        let mut res = Self {
+            check,
+            info,
+            run,
+            prelude: Vec::new(),
+            lint_config,
+            dialect: Dialect::Extended,
+            dialect_for_file: None,
+            globals: Globals::extended(),
+            inputs: Vec::new(),
+            profile: false,
+            test: false,
+        };
+        res.load_prelude(prelude)?;
+        Ok(res)
+    }
+
+    /// The dialect to parse `file` with.
+    pub fn file_dialect(&self, file: &Path) -> Dialect {
+        self.dialect_for_file
+            .as_ref()
+            .and_then(|x| (x.0)(file))
+            .unwrap_or_else(|| self.dialect.clone())
+    }
+
+    /// Replace the prelude with the given files, evaluated using the current
+    /// dialect and globals. On error the existing prelude is left unchanged.
+    pub fn load_prelude(&mut self, prelude: &[PathBuf]) -> anyhow::Result<()> {
+        self.prelude = prelude
             .iter()
             .map(|x| {
                 let env = Module::new();
 
-                let mut context = Evaluator::new(&env, &globals);
-                let module = AstModule::parse_file(x, &dialect())?;
+                let mut context = Evaluator::new(&env, &self.globals);
+                let module = AstModule::parse_file(x, &self.file_dialect(x))?;
                 context.eval_module(module)?;
                 Ok(env.freeze())
             })
             .collect::<anyhow::Result<_>>()?;
-
-        Ok(Self {
-            check,
-            info,
-            run,
-            prelude,
-        })
+        Ok(())
     }
 
     fn go(&self, file: &str, module: AstModule) -> impl Iterator<Item = Message> {
@@ -88,7 +140,7 @@ impl Context {
         let file = "expression";
         Self::err(
             file,
-            AstModule::parse(file, content, &dialect()).map(|module| self.go(file, module)),
+            AstModule::parse(file, content, &self.dialect).map(|module| self.go(file, module)),
         )
     }
 
@@ -107,20 +159,87 @@ impl Context {
         filename: &str,
         content: String,
     ) -> impl Iterator<Item = Message> {
-        Self::err(
-            filename,
-            AstModule::parse(filename, content, &dialect()).map(|module| self.go(filename, module)),
-        )
+        // Report every syntax error, so an editor can show them all at once
+        match AstModule::parse_all(filename, content, &self.file_dialect(Path::new(filename))) {
+            Ok(module) => Either::Left(self.go(filename, module)),
+            Err(errors) => Either::Right(
+                errors
+                    .into_iter()
+                    .map(|e| Message::from_anyhow(filename, e.into()))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ),
+        }
     }
 
     fn run(&self, file: &str, module: AstModule) -> impl Iterator<Item = Message> {
+        if self.test {
+            Either::Left(self.run_tests(file, module).into_iter())
+        } else {
+            Either::Right(Self::err(
+                file,
+                self.run_module(module).map(|_| iter::empty()),
+            ))
+        }
+    }
+
+    // Run the module, then call each top-level function whose name ends in `_test`
+    // with no arguments, printing whether it passed. Returns a message for each failure.
+    // The results go to stderr, so they don't corrupt `--json` or `--sarif` output.
+    fn run_tests(&self, file: &str, module: AstModule) -> Vec<Message> {
+        let tests = module
+            .exported_symbols()
+            .into_iter()
+            .filter(|(_, name)| name.ends_with("_test"))
+            .map(|(_, name)| name.to_owned())
+            .collect::<Vec<_>>();
         let env = Module::new();
         for p in &self.prelude {
             env.import_public_symbols(p)
         }
-        let globals = globals();
-        let mut context = Evaluator::new(&env, &globals);
-        Self::err(file, context.eval_module(module).map(|_| iter::empty()))
+        let mut context = Evaluator::new(&env, &self.globals);
+        if let Err(e) = context.eval_module(module) {
+            return vec![Message::from_anyhow(file, e)];
+        }
+
+        let mut failures = Vec::new();
+        for name in tests {
+            let test = match env.get(&name) {
+                Some(test) if test.get_aref().is_function() => test,
+                _ => continue,
+            };
+            match context.eval_function(test, &[], &[]) {
+                Ok(_) => eprintln!("PASS {}", name),
+                Err(e) => {
+                    eprintln!("FAIL {}", name);
+                    failures.push(Message::from_anyhow(file, e));
+                }
+            }
+        }
+        failures
+    }
+
+    fn run_module(&self, module: AstModule) -> anyhow::Result<()> {
+        let env = Module::new();
+        for p in &self.prelude {
+            env.import_public_symbols(p)
+        }
+        for (name, value) in &self.inputs {
+            env.inject(name, json_to_value(value, env.heap())?);
+        }
+        let mut context = Evaluator::new(&env, &self.globals);
+        if self.profile {
+            context.enable_profiling();
+        }
+        let res = context.eval_module(module);
+        if self.profile {
+            print_profile(&env.heap().profile_report());
+        }
+        let res = res?;
+        if !self.inputs.is_empty() {
+            println!("{}", res.to_json());
+        }
+        Ok(())
     }
 
     fn info(&self, module: &AstModule) {
@@ -132,24 +251,85 @@ impl Context {
     }
 
     fn check(&self, module: &AstModule) -> impl Iterator<Item = Message> {
-        let mut globals = Vec::new();
+        // The builtins are always known, so we can spot them being shadowed
+        let builtins = self.globals.names();
+        let builtins = builtins.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        let mut prelude = Vec::new();
         for x in &self.prelude {
-            globals.extend(x.names());
+            prelude.extend(x.names());
         }
-        let globals = if self.prelude.is_empty() {
-            None
-        } else {
-            Some(globals.as_slice())
-        };
 
-        module.lint(globals).into_iter().map(Message::from_lint)
+        // Only report undefined variables if there is a prelude, or they were asked for,
+        // as otherwise the file is probably run with globals we don't know about
+        let undefined = !self.prelude.is_empty()
+            || self
+                .lint_config
+                .enabled
+                .as_ref()
+                .map_or(false, |x| x.contains("using-undefined"));
+        let mut globals = builtins.clone();
+        globals.extend(prelude.iter().copied());
+        let globals = Some(globals.as_slice()).filter(|_| undefined);
+
+        module
+            .lint_with_config(globals, &builtins, &prelude, &self.lint_config)
+            .into_iter()
+            .map(Message::from_lint)
     }
 }
 
-pub fn globals() -> Globals {
-    Globals::extended()
+// Convert JSON into the equivalent Starlark value
+fn json_to_value<'v>(x: &serde_json::Value, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+    use serde_json::Value as Json;
+    Ok(match x {
+        Json::Null => Value::new_none(),
+        Json::Bool(x) => Value::new_bool(*x),
+        Json::Number(x) => match x.as_i64().and_then(|x| i32::try_from(x).ok()) {
+            Some(x) => Value::new_int(x),
+            None => return Err(anyhow!("JSON number `{}` is not a Starlark int", x)),
+        },
+        Json::String(x) => heap.alloc(x.as_str()),
+        Json::Array(xs) => heap.alloc(
+            xs.iter()
+                .map(|x| json_to_value(x, heap))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        Json::Object(xs) => {
+            let mut res = SmallMap::with_capacity(xs.len());
+            for (k, v) in xs {
+                res.insert_hashed(
+                    heap.alloc(k.as_str()).get_hashed()?,
+                    json_to_value(v, heap)?,
+                );
+            }
+            heap.alloc(Dict::new(res))
+        }
+    })
 }
 
-pub fn dialect() -> Dialect {
-    Dialect::Extended
+fn print_profile(report: &[FunctionProfile]) {
+    let width = report
+        .iter()
+        .map(|x| x.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("Function".len());
+    println!(
+        "{:<width$} {:>8} {:>10} {:>10}",
+        "Function",
+        "Calls",
+        "Self(s)",
+        "Total(s)",
+        width = width
+    );
+    for x in report {
+        println!(
+            "{:<width$} {:>8} {:>10.3} {:>10.3}",
+            x.name,
+            x.calls,
+            x.self_time.as_secs_f64(),
+            x.total_time.as_secs_f64(),
+            width = width
+        );
+    }
 }
@@ -21,22 +21,105 @@ use crate::{
     eval::Context,
     types::{Message as StarlarkMessage, Severity},
 };
+use anyhow::anyhow;
 use lsp_server::{Connection, Message, Notification};
 use lsp_types::{
     notification::{
-        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage,
-        PublishDiagnostics,
+        DidChangeConfiguration, DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        LogMessage, PublishDiagnostics,
     },
-    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
-    DidOpenTextDocumentParams, InitializeParams, LogMessageParams, MessageType, NumberOrString,
-    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
-    TextDocumentSyncKind, Url,
+    Diagnostic, DiagnosticSeverity, DidChangeConfigurationParams, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, InitializeParams, LogMessageParams,
+    MessageType, NumberOrString, Position, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentContentChangeEvent, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
 };
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize};
+use starlark::{
+    environment::{Globals, LibraryExtension},
+    syntax::Dialect,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    path::PathBuf,
+    time::Duration,
+};
+
+/// How long to wait after the last change to a document before validating it,
+/// so we don't re-validate on every keystroke.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+struct Document {
+    text: String,
+    version: Option<i64>,
+}
+
+/// Settings the client can pass as `initializationOptions`, or as the `starlark`
+/// section of the workspace configuration, so that each project is checked against
+/// the Starlark it actually uses. Anything not given keeps its current value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Settings {
+    /// Either `standard` or `extended`, which also picks the matching globals.
+    dialect: Option<String>,
+    /// Individual dialect features to turn on or off, e.g. `"enable_load": false`.
+    features: HashMap<String, bool>,
+    /// The names of the [`LibraryExtension`]s to use on top of the standard globals.
+    extensions: Option<Vec<String>>,
+    /// Files to evaluate and make available to every document, relative to the workspace root.
+    prelude: Option<Vec<PathBuf>>,
+}
+
+fn to_dialect(name: &str) -> anyhow::Result<(Dialect, Globals)> {
+    match name {
+        "standard" => Ok((Dialect::Standard, Globals::standard())),
+        "extended" => Ok((Dialect::Extended, Globals::extended())),
+        _ => Err(anyhow!(
+            "Unknown dialect `{}`, expected `standard` or `extended`",
+            name
+        )),
+    }
+}
+
+fn set_feature(dialect: &mut Dialect, name: &str, value: bool) -> anyhow::Result<()> {
+    let feature = match name {
+        "enable_def" => &mut dialect.enable_def,
+        "enable_lambda" => &mut dialect.enable_lambda,
+        "enable_load" => &mut dialect.enable_load,
+        "enable_keyword_only_arguments" => &mut dialect.enable_keyword_only_arguments,
+        "enable_types" => &mut dialect.enable_types,
+        "enable_tabs" => &mut dialect.enable_tabs,
+        "enable_load_reexport" => &mut dialect.enable_load_reexport,
+        "enable_top_level_stmt" => &mut dialect.enable_top_level_stmt,
+        "enable_while" => &mut dialect.enable_while,
+        "enable_recursion" => &mut dialect.enable_recursion,
+        "enable_f_strings" => &mut dialect.enable_f_strings,
+        "enable_string_iteration" => &mut dialect.enable_string_iteration,
+        "enable_byte_strings" => &mut dialect.enable_byte_strings,
+        "enable_dict_views" => &mut dialect.enable_dict_views,
+        _ => return Err(anyhow!("Unknown dialect feature `{}`", name)),
+    };
+    *feature = value;
+    Ok(())
+}
+
+fn to_extension(name: &str) -> anyhow::Result<LibraryExtension> {
+    LibraryExtension::all()
+        .iter()
+        .copied()
+        .find(|x| format!("{:?}", x) == name)
+        .ok_or_else(|| anyhow!("Unknown library extension `{}`", name))
+}
 
 struct Backend {
     connection: Connection,
     starlark: Context,
+    /// The workspace root, against which relative prelude paths are resolved.
+    root: Option<PathBuf>,
+    /// The open documents, kept up to date by applying incremental changes.
+    documents: HashMap<Url, Document>,
+    /// Documents that have changed since they were last validated.
+    pending: HashSet<Url>,
 }
 
 fn to_severity(x: Severity) -> DiagnosticSeverity {
@@ -67,44 +150,163 @@ fn to_diagnostic(x: StarlarkMessage) -> Diagnostic {
     )
 }
 
+/// Convert an LSP position to a byte offset in `text`. LSP counts characters in
+/// UTF-16 code units, and positions past the end of a line or file are clamped.
+fn to_offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut character = 0;
+    for (i, c) in line.char_indices() {
+        if character >= position.character {
+            return line_start + i;
+        }
+        character += c.len_utf16() as u64;
+    }
+    line_start + line.len()
+}
+
+fn apply_change(text: &mut String, change: TextDocumentContentChangeEvent) {
+    match change.range {
+        None => *text = change.text,
+        Some(range) => {
+            let start = to_offset(text, range.start);
+            let end = to_offset(text, range.end).max(start);
+            text.replace_range(start..end, &change.text);
+        }
+    }
+}
+
 /// The logic implementations of stuff
 impl Backend {
     fn server_capabilities() -> ServerCapabilities {
         ServerCapabilities {
-            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                TextDocumentSyncKind::Incremental,
+            )),
             ..ServerCapabilities::default()
         }
     }
 
-    fn validate(&self, uri: Url, version: Option<i64>, text: String) {
-        let diags = self
-            .starlark
-            .file_with_contents(&uri.to_string(), text)
-            .map(to_diagnostic)
-            .collect();
-        self.publish_diagnostics(uri, diags, version)
+    fn validate(&self, uri: Url) {
+        if let Some(doc) = self.documents.get(&uri) {
+            let diags = self
+                .starlark
+                .file_with_contents(&uri.to_string(), doc.text.clone())
+                .map(to_diagnostic)
+                .collect();
+            self.publish_diagnostics(uri, diags, doc.version)
+        }
     }
 
-    fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.validate(
-            params.text_document.uri,
-            Some(params.text_document.version),
-            params.text_document.text,
-        )
+    fn validate_pending(&mut self) {
+        for uri in mem::take(&mut self.pending) {
+            self.validate(uri)
+        }
     }
 
-    fn did_change(&self, params: DidChangeTextDocumentParams) {
-        // We asked for Sync full, so can just grab all the text from params
-        let change = params.content_changes.into_iter().next().unwrap();
-        self.validate(
-            params.text_document.uri,
-            params.text_document.version,
-            change.text,
+    fn did_open(&mut self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.insert(
+            uri.clone(),
+            Document {
+                text: params.text_document.text,
+                version: Some(params.text_document.version),
+            },
         );
+        // Validate straight away, so a newly opened file shows diagnostics immediately
+        self.pending.remove(&uri);
+        self.validate(uri)
+    }
+
+    fn did_change(&mut self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        if let Some(doc) = self.documents.get_mut(&uri) {
+            for change in params.content_changes {
+                apply_change(&mut doc.text, change);
+            }
+            doc.version = params.text_document.version;
+            self.pending.insert(uri);
+        }
+    }
+
+    fn did_close(&mut self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.remove(&uri);
+        self.pending.remove(&uri);
+        self.publish_diagnostics(uri, Vec::new(), None)
+    }
+
+    fn did_change_configuration(&mut self, params: DidChangeConfigurationParams) {
+        // Clients either send just our section, or all their settings
+        let settings = match params.settings {
+            serde_json::Value::Object(mut x) if x.contains_key("starlark") => {
+                x.remove("starlark").unwrap()
+            }
+            x => x,
+        };
+        if !settings.is_null() {
+            self.apply_settings(settings)
+        }
+    }
+
+    fn apply_settings(&mut self, settings: serde_json::Value) {
+        let res = serde_json::from_value(settings)
+            .map_err(anyhow::Error::from)
+            .and_then(|x| self.configure(x));
+        match res {
+            // Everything open may now have different diagnostics
+            Ok(()) => self.pending.extend(self.documents.keys().cloned()),
+            Err(e) => self.log_message(
+                MessageType::Error,
+                &format!("Invalid Starlark configuration: {:#}", e),
+            ),
+        }
     }
 
-    fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.publish_diagnostics(params.text_document.uri, Vec::new(), None)
+    /// Update the dialect, globals and prelude. On error, nothing is changed.
+    fn configure(&mut self, settings: Settings) -> anyhow::Result<()> {
+        let mut dialect = self.starlark.dialect.clone();
+        let mut globals = self.starlark.globals.clone();
+        if let Some(name) = &settings.dialect {
+            let (d, g) = to_dialect(name)?;
+            dialect = d;
+            globals = g;
+        }
+        for (name, value) in &settings.features {
+            set_feature(&mut dialect, name, *value)?;
+        }
+        if let Some(names) = &settings.extensions {
+            let extensions = names
+                .iter()
+                .map(|x| to_extension(x))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            globals = Globals::extended_by(&extensions);
+        }
+
+        let old_dialect = mem::replace(&mut self.starlark.dialect, dialect);
+        let old_globals = mem::replace(&mut self.starlark.globals, globals);
+        if let Some(prelude) = settings.prelude {
+            let prelude = prelude
+                .into_iter()
+                .map(|x| match &self.root {
+                    Some(root) => root.join(x),
+                    None => x,
+                })
+                .collect::<Vec<_>>();
+            if let Err(e) = self.starlark.load_prelude(&prelude) {
+                self.starlark.dialect = old_dialect;
+                self.starlark.globals = old_globals;
+                return Err(e);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -130,9 +332,28 @@ impl Backend {
         ));
     }
 
-    fn main_loop(&self, _params: InitializeParams) -> anyhow::Result<()> {
+    fn main_loop(&mut self, params: InitializeParams) -> anyhow::Result<()> {
         self.log_message(MessageType::Info, "Starlark server initialised");
-        for msg in &self.connection.receiver {
+        if let Some(options) = params.initialization_options {
+            self.apply_settings(options);
+        }
+        loop {
+            // If there are changes waiting to be validated, only wait for the debounce period
+            let msg = if self.pending.is_empty() {
+                match self.connection.receiver.recv() {
+                    Ok(msg) => msg,
+                    Err(_) => break,
+                }
+            } else {
+                match self.connection.receiver.recv_timeout(DEBOUNCE) {
+                    Ok(msg) => msg,
+                    Err(e) if e.is_timeout() => {
+                        self.validate_pending();
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            };
             match msg {
                 Message::Request(req) => {
                     if self.connection.handle_shutdown(&req)? {
@@ -147,6 +368,8 @@ impl Backend {
                         self.did_change(params)
                     } else if let Some(params) = as_notification::<DidCloseTextDocument>(&x) {
                         self.did_close(params)
+                    } else if let Some(params) = as_notification::<DidChangeConfiguration>(&x) {
+                        self.did_change_configuration(params)
                     }
                 }
                 Message::Response(_) => {
@@ -166,10 +389,18 @@ pub fn server(starlark: Context) -> anyhow::Result<()> {
     // Run the server and wait for the two threads to end (typically by trigger LSP Exit event).
     let server_capabilities = serde_json::to_value(&Backend::server_capabilities()).unwrap();
     let initialization_params = connection.initialize(server_capabilities)?;
-    let initialization_params = serde_json::from_value(initialization_params).unwrap();
+    let initialization_params: InitializeParams =
+        serde_json::from_value(initialization_params).unwrap();
+    let root = initialization_params
+        .root_uri
+        .as_ref()
+        .and_then(|x| x.to_file_path().ok());
     Backend {
         connection,
         starlark,
+        root,
+        documents: HashMap::new(),
+        pending: HashSet::new(),
     }
     .main_loop(initialization_params)?;
     io_threads.join()?;
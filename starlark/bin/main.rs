@@ -23,20 +23,34 @@
 #![cfg_attr(feature = "custom_linter", allow(deprecated))] // :(
 #![cfg_attr(feature = "custom_linter", plugin(linter))]
 
-use crate::types::{LintMessage, Message, Severity};
+use crate::{
+    sarif::SarifLog,
+    types::{LintMessage, Message, Severity},
+};
 use anyhow::anyhow;
-use eval::Context;
+use eval::{Context, DialectForFile};
 use gazebo::prelude::*;
 use itertools::Either;
 use rustyline::{error::ReadlineError, Editor};
-use std::{ffi::OsStr, fmt, fmt::Display, fs, path::PathBuf, sync::Arc};
+use starlark::{analysis::LintConfig, syntax::Dialect};
+use std::{
+    ffi::OsStr,
+    fmt,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use structopt::{clap::AppSettings, StructOpt};
 use walkdir::WalkDir;
 
 mod dap;
 mod eval;
 mod lsp;
+mod sarif;
 mod types;
+#[cfg(feature = "watch")]
+mod watch;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -59,15 +73,49 @@ pub struct Args {
     #[structopt(long = "dap", help = "Start a DAP server.")]
     dap: bool,
 
-    #[structopt(long = "check", help = "Run checks and lints.")]
+    #[structopt(
+        long = "test",
+        help = "Call the functions whose names end in `_test`, reporting which pass."
+    )]
+    test: bool,
+
+    #[structopt(long = "check", alias = "lint", help = "Run checks and lints.")]
     check: bool,
 
+    #[structopt(
+        long = "enable-lint",
+        help = "Only report these lints, e.g. `unused-load` (default: all)."
+    )]
+    enable_lint: Vec<String>,
+
+    #[structopt(long = "disable-lint", help = "Lints to not report.")]
+    disable_lint: Vec<String>,
+
+    #[cfg(feature = "watch")]
+    #[structopt(
+        long = "watch",
+        help = "After running the files, keep watching them and run any that change."
+    )]
+    watch: bool,
+
+    #[structopt(
+        long = "profile",
+        help = "Show the time spent in each function after running each file."
+    )]
+    profile: bool,
+
     #[structopt(long = "info", help = "Show information about the code.")]
     info: bool,
 
     #[structopt(long = "json", help = "Show output as JSON lines.")]
     json: bool,
 
+    #[structopt(
+        long = "sarif",
+        help = "Show output as a SARIF log, printed once all files are checked."
+    )]
+    sarif: bool,
+
     #[structopt(
         long = "extension",
         help = "File extension when searching directories."
@@ -77,6 +125,13 @@ pub struct Args {
     #[structopt(long = "prelude", help = "Files to load in advance.")]
     prelude: Vec<PathBuf>,
 
+    #[structopt(
+        long = "dialect-for",
+        name = "FILE=DIALECT",
+        help = "Use DIALECT (`standard` or `extended`) for files with this name or .extension."
+    )]
+    dialect_for: Vec<String>,
+
     #[structopt(
         long = "expression",
         short = "e",
@@ -85,6 +140,13 @@ pub struct Args {
     )]
     evaluate: Vec<String>,
 
+    #[structopt(
+        long = "input",
+        name = "NAME=FILE",
+        help = "Bind the JSON in FILE to the variable NAME, and print results as JSON."
+    )]
+    input: Vec<String>,
+
     #[structopt(name = "FILE", help = "Files to evaluate.")]
     // String instead of PathBuf so we can expand @file things
     files: Vec<String>,
@@ -109,11 +171,49 @@ fn expand_args(args: Vec<String>) -> anyhow::Result<Vec<PathBuf>> {
     Ok(res)
 }
 
+// Parse `NAME=FILE` arguments, reading the JSON from each file.
+fn parse_inputs(args: Vec<String>) -> anyhow::Result<Vec<(String, serde_json::Value)>> {
+    args.into_try_map(|x| match x.split1_opt('=') {
+        None => Err(anyhow!("Expected `--input NAME=FILE`, got `{}`", x)),
+        Some((name, file)) => {
+            let value = serde_json::from_str(&fs::read_to_string(file)?)?;
+            Ok((name.to_owned(), value))
+        }
+    })
+}
+
+// Parse `FILE=DIALECT` arguments into a function choosing the dialect of each file,
+// where `FILE` is either a file name such as `BUILD` or an extension such as `.bzl`.
+fn parse_dialects(args: Vec<String>) -> anyhow::Result<Option<DialectForFile>> {
+    if args.is_empty() {
+        return Ok(None);
+    }
+    let dialects = args.into_try_map(|x| match x.split1_opt('=') {
+        Some((file, "standard")) => Ok((file.to_owned(), Dialect::Standard)),
+        Some((file, "extended")) => Ok((file.to_owned(), Dialect::Extended)),
+        _ => Err(anyhow!(
+            "Expected `--dialect-for FILE=DIALECT` with a dialect of `standard` or `extended`, got `{}`",
+            x
+        )),
+    })?;
+    let matches = |file: &str, path: &Path| match file.strip_prefix('.') {
+        Some(ext) => path.extension() == Some(OsStr::new(ext)),
+        None => path.file_name() == Some(OsStr::new(file)),
+    };
+    let choose = move |path: &Path| {
+        dialects
+            .iter()
+            .find(|(file, _)| matches(file, path))
+            .map(|(_, dialect)| dialect.clone())
+    };
+    Ok(Some(DialectForFile(box choose)))
+}
+
 // Treat directories as things to recursively walk for .<extension> files,
 // and everything else as normal files.
-fn expand_dirs(extension: &str, xs: Vec<PathBuf>) -> impl Iterator<Item = PathBuf> {
+fn expand_dirs(extension: &str, xs: &[PathBuf]) -> impl Iterator<Item = PathBuf> {
     let extension = Arc::new(extension.to_owned());
-    xs.into_iter().flat_map(move |x| {
+    xs.to_vec().into_iter().flat_map(move |x| {
         // Have to keep cloning extension so we keep ownership
         let extension = extension.dupe();
         if x.is_dir() {
@@ -163,13 +263,26 @@ impl Stats {
     }
 }
 
-fn drain(xs: impl Iterator<Item = Message>, json: bool, stats: &mut Stats) {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    // Messages are collected and printed at the end
+    Sarif,
+}
+
+fn drain(
+    xs: impl Iterator<Item = Message>,
+    format: Format,
+    stats: &mut Stats,
+    collected: &mut Vec<Message>,
+) {
     for x in xs {
         stats.increment(x.severity);
-        if json {
-            println!("{}", serde_json::to_string(&LintMessage::new(x)).unwrap());
-        } else {
-            println!("{}", x);
+        match format {
+            Format::Text => println!("{}", x),
+            Format::Json => println!("{}", serde_json::to_string(&LintMessage::new(x)).unwrap()),
+            Format::Sarif => collected.push(x),
         }
     }
 }
@@ -182,7 +295,12 @@ fn interactive(ctx: &Context) -> anyhow::Result<()> {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
                 let mut stats = Stats::default();
-                drain(ctx.expression(line), false, &mut stats);
+                drain(
+                    ctx.expression(line),
+                    Format::Text,
+                    &mut stats,
+                    &mut Vec::new(),
+                );
             }
             // User pressed EOF - disconnected terminal, or similar
             Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => return Ok(()),
@@ -200,22 +318,61 @@ fn main() -> anyhow::Result<()> {
         .as_ref()
         .map_or("bzl", |x| x.as_str())
         .trim_start_match('.');
+    let lint_config = LintConfig {
+        enabled: if args.enable_lint.is_empty() {
+            None
+        } else {
+            Some(args.enable_lint.into_iter().collect())
+        },
+        disabled: args.disable_lint.into_iter().collect(),
+    };
     let mut ctx = Context::new(
         args.check,
         args.info,
         !args.check && !args.info,
-        &expand_dirs(ext, args.prelude).collect::<Vec<_>>(),
+        &[],
+        lint_config,
     )?;
+    // Set before loading the prelude, so it uses the dialect for each file too
+    ctx.dialect_for_file = parse_dialects(args.dialect_for)?;
+    ctx.load_prelude(&expand_dirs(ext, &args.prelude).collect::<Vec<_>>())?;
+    ctx.inputs = parse_inputs(args.input)?;
+    ctx.profile = args.profile;
+    ctx.test = args.test;
 
+    let format = if args.sarif {
+        Format::Sarif
+    } else if args.json {
+        Format::Json
+    } else {
+        Format::Text
+    };
     let mut stats = Stats::default();
+    let mut collected = Vec::new();
     for e in args.evaluate {
         stats.increment_file();
-        drain(ctx.expression(e), args.json, &mut stats);
+        drain(ctx.expression(e), format, &mut stats, &mut collected);
     }
 
-    for file in expand_dirs(ext, expand_args(args.files)?) {
+    let files = expand_args(args.files)?;
+    for file in expand_dirs(ext, &files) {
         stats.increment_file();
-        drain(ctx.file(&file), args.json, &mut stats);
+        drain(ctx.file(&file), format, &mut stats, &mut collected);
+    }
+
+    if format == Format::Sarif {
+        println!(
+            "{}",
+            serde_json::to_string(&SarifLog::new(collected)).unwrap()
+        );
+    }
+
+    #[cfg(feature = "watch")]
+    if args.watch {
+        if format == Format::Text {
+            println!("{}", stats);
+        }
+        watch::watch(&ctx, ext, &files, format)?;
     }
 
     if args.interactive {
@@ -231,7 +388,7 @@ fn main() -> anyhow::Result<()> {
         dap::server(ctx)
     }
 
-    if !args.json {
+    if format == Format::Text {
         println!("{}", stats);
         if stats.error > 0 {
             return Err(anyhow!("Failed with {} errors", stats.error));
@@ -239,3 +396,18 @@ fn main() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_flags() {
+        for flag in &["--check", "--lint"] {
+            let args = Args::from_iter_safe(&["starlark", *flag, "x.star"]).unwrap();
+            assert!(args.check, "{}", flag);
+            assert_eq!(args.files, vec!["x.star".to_owned()]);
+        }
+        assert!(!Args::from_iter_safe(&["starlark", "x.star"]).unwrap().check);
+    }
+}
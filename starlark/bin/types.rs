@@ -105,7 +105,7 @@ impl Message {
         Self {
             path: x.location.file.name().to_owned(),
             span: Some(LineColSpan::from_span_loc(&x.location)),
-            severity: if x.serious {
+            severity: if x.warning {
                 Severity::Warning
             } else {
                 // Start with all non-serious errors disabled, and ramp up from there
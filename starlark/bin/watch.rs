@@ -0,0 +1,84 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Re-run files as they change, for `--watch`.
+
+use crate::{drain, eval::Context, sarif::SarifLog, Format, Stats};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::Duration,
+};
+
+// Editors often write a file several times when saving, so wait for things to settle
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch the given files and directories, and every time a file changes, run it again
+/// and print the resulting messages. Only returns if watching fails.
+pub fn watch(
+    ctx: &Context,
+    extension: &str,
+    paths: &[PathBuf],
+    format: Format,
+) -> anyhow::Result<()> {
+    let (tx, rx) = channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(tx, DEBOUNCE)?;
+    // Files given explicitly are run whatever their extension,
+    // for directories we only care about files with the extension.
+    let mut files = Vec::new();
+    for path in paths {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+        if !path.is_dir() {
+            files.push(path.canonicalize()?);
+        }
+    }
+    let is_wanted = |x: &Path| {
+        x.extension() == Some(OsStr::new(extension))
+            || x.canonicalize().map_or(false, |x| files.contains(&x))
+    };
+
+    loop {
+        let file = match rx.recv()? {
+            DebouncedEvent::Create(x) | DebouncedEvent::Write(x) | DebouncedEvent::Rename(_, x) => {
+                x
+            }
+            DebouncedEvent::Error(e, _) => return Err(e.into()),
+            _ => continue,
+        };
+        if !is_wanted(&file) {
+            continue;
+        }
+
+        let mut stats = Stats::default();
+        let mut collected = Vec::new();
+        if format == Format::Text {
+            println!("Changed {}", file.display());
+        }
+        stats.increment_file();
+        drain(ctx.file(&file), format, &mut stats, &mut collected);
+        match format {
+            Format::Text => println!("{}", stats),
+            Format::Json => {}
+            Format::Sarif => println!(
+                "{}",
+                serde_json::to_string(&SarifLog::new(collected)).unwrap()
+            ),
+        }
+    }
+}
@@ -199,6 +199,12 @@ fn stmt(x: &AstStmt, res: &mut Vec<Bind>) {
             stmt(body, res);
             flow(res)
         }
+        Stmt::While(cond, body) => {
+            expr(cond, res);
+            flow(res);
+            stmt(body, res);
+            flow(res)
+        }
         Stmt::Load(_, args, _) => {
             for x in args {
                 res.push(Bind::Set(Assigner::Load, x.0.clone()))
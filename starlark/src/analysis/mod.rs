@@ -15,7 +15,7 @@
  * limitations under the License.
  */
 
-pub use types::{LineColSpan, Lint};
+pub use types::{LineColSpan, Lint, LintConfig};
 
 use crate::{analysis::types::LintT, syntax::AstModule};
 
@@ -25,6 +25,7 @@ mod exported;
 mod flow;
 mod incompatible;
 mod names;
+mod typecheck;
 mod types;
 
 impl AstModule {
@@ -32,6 +33,19 @@ impl AstModule {
     /// they can be passed as the `globals` argument, resulting in name-resolution lint errors.
     /// The precise checks run by the linter are not considered stable between versions.
     pub fn lint(&self, globals: Option<&[&str]>) -> Vec<Lint> {
+        self.lint_with_config(globals, &[], &[], &LintConfig::default())
+    }
+
+    /// Like [`lint`](AstModule::lint), but only reports the lints enabled by the [`LintConfig`].
+    /// Assignments to the names of `builtins`, or of `prelude`, are reported as shadowing them,
+    /// whether or not `globals` are given.
+    pub fn lint_with_config(
+        &self,
+        globals: Option<&[&str]>,
+        builtins: &[&str],
+        prelude: &[&str],
+        config: &LintConfig,
+    ) -> Vec<Lint> {
         let mut res = Vec::new();
         res.extend(flow::flow_issues(self).into_iter().map(LintT::erase));
         res.extend(
@@ -41,10 +55,11 @@ impl AstModule {
         );
         res.extend(dubious::dubious(self).into_iter().map(LintT::erase));
         res.extend(
-            names::name_warnings(self, globals)
+            names::name_warnings(self, globals, builtins, prelude)
                 .into_iter()
                 .map(LintT::erase),
         );
+        res.retain(|x| config.is_enabled(&x.short_name));
         res
     }
 }
@@ -47,6 +47,12 @@ pub(crate) enum NameWarning {
     UnderscoreFunction(String),
     #[error("Used ignored variable `{}`", .0)]
     UsingIgnored(String),
+    #[error("Assignment to `{}` shadows a builtin", .0)]
+    ShadowedBuiltin(String),
+    #[error("Assignment to `{}` shadows a name from the prelude", .0)]
+    ShadowedPrelude(String),
+    #[error("Local variable `{}` shadows a module-level variable", .0)]
+    ShadowedModuleVariable(String),
 }
 
 impl LintWarning for NameWarning {
@@ -56,6 +62,16 @@ impl LintWarning for NameWarning {
             _ => false,
         }
     }
+
+    fn is_warning(&self) -> bool {
+        match self {
+            Self::UnusedLoad(..)
+            | Self::UnusedAssign(..)
+            | Self::UnusedArgument(..)
+            | Self::ShadowedModuleVariable(..) => true,
+            x => x.is_serious(),
+        }
+    }
 }
 
 impl NameWarning {
@@ -75,15 +91,32 @@ impl NameWarning {
 pub(crate) fn name_warnings(
     module: &AstModule,
     globals: Option<&[&str]>,
+    builtins: &[&str],
+    prelude: &[&str],
 ) -> Vec<LintT<NameWarning>> {
     let mut res = Vec::new();
     let scope = bind::scope(module);
     unused_variable(&module.codemap, &scope, true, &mut res);
     duplicate_assign(&module.codemap, &scope, true, &mut res);
     unassigned_variable(&module.codemap, &scope, &mut res);
+    shadowed_module_variable(&module.codemap, &scope, &mut res);
     if let Some(globals) = globals {
         undefined_variable(&module.codemap, &scope, globals, &mut res);
     }
+    shadowed_global(
+        &module.codemap,
+        &scope,
+        builtins,
+        NameWarning::ShadowedBuiltin,
+        &mut res,
+    );
+    shadowed_global(
+        &module.codemap,
+        &scope,
+        prelude,
+        NameWarning::ShadowedPrelude,
+        &mut res,
+    );
     inappropriate_underscore(&module.codemap, &module.statement, true, &mut res);
     use_ignored(&module.codemap, &scope, None, &mut res);
     res
@@ -107,6 +140,71 @@ fn undefined_variable(
     }
 }
 
+// An assignment to one of `globals`, reported with `warning`, so builtins and names from
+// the prelude can be told apart.
+fn shadowed_global(
+    codemap: &CodeMap,
+    scope: &Scope,
+    globals: &[&str],
+    warning: fn(String) -> NameWarning,
+    res: &mut Vec<LintT<NameWarning>>,
+) {
+    fn f(
+        codemap: &CodeMap,
+        scope: &Scope,
+        globals: &HashSet<&str>,
+        warning: fn(String) -> NameWarning,
+        res: &mut Vec<LintT<NameWarning>>,
+    ) {
+        for (name, (_, span)) in &scope.bound {
+            if globals.contains(name.as_str()) {
+                res.push(LintT::new(codemap, *span, warning(name.clone())))
+            }
+        }
+        for x in &scope.inner {
+            if let Bind::Scope(scope) = x {
+                f(codemap, scope, globals, warning, res)
+            }
+        }
+    }
+
+    let globals: HashSet<&str> = globals.iter().copied().collect();
+    f(codemap, scope, &globals, warning, res)
+}
+
+// A variable bound in a def, lambda or comprehension with the same name as one bound
+// at the top level, which is then inaccessible there. Underscore names are ignored,
+// as they are usually throwaways like `_`, and so are parameters, which are part of
+// the signature and usually named after what is passed in.
+fn shadowed_module_variable(codemap: &CodeMap, scope: &Scope, res: &mut Vec<LintT<NameWarning>>) {
+    fn f(
+        codemap: &CodeMap,
+        scope: &Scope,
+        module: &HashMap<String, (Assigner, Span)>,
+        res: &mut Vec<LintT<NameWarning>>,
+    ) {
+        for x in &scope.inner {
+            if let Bind::Scope(scope) = x {
+                for (name, (typ, span)) in &scope.bound {
+                    if *typ != Assigner::Argument
+                        && !name.starts_with('_')
+                        && module.contains_key(name)
+                    {
+                        res.push(LintT::new(
+                            codemap,
+                            *span,
+                            NameWarning::ShadowedModuleVariable(name.clone()),
+                        ))
+                    }
+                }
+                f(codemap, scope, module, res)
+            }
+        }
+    }
+
+    f(codemap, scope, &scope.bound, res)
+}
+
 fn duplicate_assign(
     codemap: &CodeMap,
     scope: &Scope,
@@ -288,6 +386,9 @@ mod test {
                 NameWarning::UsingUndefined(x) => x,
                 NameWarning::UnderscoreFunction(x) => x,
                 NameWarning::UsingIgnored(x) => x,
+                NameWarning::ShadowedBuiltin(x) => x,
+                NameWarning::ShadowedPrelude(x) => x,
+                NameWarning::ShadowedModuleVariable(x) => x,
             }
         }
     }
@@ -416,6 +517,80 @@ def foo():
         assert_eq!(res, &["no1", "no2"])
     }
 
+    #[test]
+    fn test_lint_shadowed_builtin() {
+        let m = module(
+            r#"
+load("test", "no1")
+no2 = 1
+ok = 2
+def foo(no3, x):
+    no4 = [len(x) for no5 in x]
+    return no4
+"#,
+        );
+        let mut res = Vec::new();
+        let scope = bind::scope(&m);
+        shadowed_global(
+            &m.codemap,
+            &scope,
+            &["no1", "no2", "no3", "no4", "no5", "len", "x2"],
+            NameWarning::ShadowedBuiltin,
+            &mut res,
+        );
+        let mut res = res.map(|x| x.problem.about());
+        res.sort();
+        assert_eq!(res, &["no1", "no2", "no3", "no4", "no5"])
+    }
+
+    #[test]
+    fn test_lint_shadowed_prelude() {
+        let m = module(
+            r#"
+len = 1
+from_prelude = 2
+"#,
+        );
+        let res = name_warnings(&m, None, &["len"], &["from_prelude"]);
+        let mut res = res
+            .into_iter()
+            .filter_map(|x| match x.problem {
+                NameWarning::ShadowedBuiltin(x) => Some(format!("builtin {}", x)),
+                NameWarning::ShadowedPrelude(x) => Some(format!("prelude {}", x)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        res.sort();
+        assert_eq!(res, &["builtin len", "prelude from_prelude"])
+    }
+
+    #[test]
+    fn test_lint_shadowed_module_variable() {
+        let m = module(
+            r#"
+load("test", "loaded")
+x = 1
+_private = 2
+def foo(x, ok):
+    no2 = 1
+    _private = 3
+    return [no3 for no3 in no2] + [lambda x: x]
+def no1(): pass
+no2, no3 = 1, 2
+def bar():
+    loaded = 1
+    def no1(): pass
+    return [_ for _ in [x]]
+"#,
+        );
+        let mut res = Vec::new();
+        let scope = bind::scope(&m);
+        shadowed_module_variable(&m.codemap, &scope, &mut res);
+        let mut res = res.map(|x| x.problem.about());
+        res.sort();
+        assert_eq!(res, &["loaded", "no1", "no2", "no3"])
+    }
+
     #[test]
     fn test_lint_inappropriate_underscore() {
         let m = module(
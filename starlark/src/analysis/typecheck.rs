@@ -0,0 +1,393 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A static check of type annotations, which only reasons about values whose type is
+//! obvious from the source, e.g. literals, and annotations that refer to builtin types.
+
+use crate::{
+    analysis::types::{Lint, LintT, LintWarning},
+    codemap::CodeMap,
+    environment::Globals,
+    syntax::{
+        ast::{
+            Argument, AstArgument, AstExpr, AstLiteral, AstParameter, AstStmt, BinOp, Expr,
+            Parameter, Stmt,
+        },
+        AstModule,
+    },
+    values::{
+        bool::BOOL_TYPE, dict::Dict, function::FUNCTION_TYPE, int::INT_TYPE, list::List,
+        none::NoneType, string::STRING_TYPE, tuple::Tuple, Heap,
+    },
+};
+use either::Either;
+use gazebo::variants::VariantName;
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum TypecheckIssue {
+    #[error("Argument `{}` has type annotation `{}`, but is given a value of type `{}`", .0, .1, .2)]
+    IncompatibleArgument(String, String, String),
+    #[error("Default value of argument `{}` has type `{}`, but the type annotation is `{}`", .0, .2, .1)]
+    IncompatibleDefault(String, String, String),
+    #[error("Return type annotation is `{}`, but returns a value of type `{}`", .0, .1)]
+    IncompatibleReturn(String, String),
+}
+
+impl LintWarning for TypecheckIssue {
+    fn is_serious(&self) -> bool {
+        true
+    }
+}
+
+// Types that are "" or start with "_" are wildcards, matching everything
+fn is_wildcard(x: &str) -> bool {
+    x == "" || x.starts_with('_')
+}
+
+/// The parameters of a top-level `def`, in order, with their type annotation.
+struct Signature<'a> {
+    params: Vec<(&'a Parameter, Option<String>)>,
+}
+
+struct Typecheck<'a> {
+    codemap: &'a CodeMap,
+    globals: &'a Globals,
+    heap: Heap,
+    signatures: HashMap<&'a str, Signature<'a>>,
+    res: Vec<LintT<TypecheckIssue>>,
+}
+
+impl<'a> Typecheck<'a> {
+    /// The type a type annotation refers to, if it is one we understand and not a wildcard.
+    fn annotation(&self, x: &AstExpr) -> Option<String> {
+        match &**x {
+            Expr::Identifier(x) if x.node == "None" => Some(NoneType::TYPE.to_owned()),
+            Expr::Literal(AstLiteral::StringLiteral(x)) if !is_wildcard(&x.node) => {
+                Some(x.node.clone())
+            }
+            Expr::Dot(box x, attr) if attr.node == "type" => match &**x {
+                Expr::Identifier(x) => {
+                    let v = self.globals.get_frozen(&x.node)?.to_value();
+                    let (_, typ) = v.get_attr("type", &self.heap).ok()?;
+                    typ.unpack_str()
+                        .filter(|x| !is_wildcard(x))
+                        .map(|x| x.to_owned())
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn parameter_annotation(&self, x: &AstParameter) -> Option<String> {
+        match &**x {
+            Parameter::Normal(_, Some(t)) | Parameter::WithDefaultValue(_, Some(t), _) => {
+                self.annotation(t)
+            }
+            _ => None,
+        }
+    }
+
+    /// The type of an expression, if it is obvious from the source.
+    fn infer(x: &AstExpr, locals: &HashMap<&str, String>) -> Option<String> {
+        let res = match &**x {
+            Expr::Literal(AstLiteral::IntLiteral(_)) => INT_TYPE,
+            Expr::Literal(AstLiteral::StringLiteral(_)) => STRING_TYPE,
+            Expr::Identifier(x) => match x.node.as_str() {
+                "None" => NoneType::TYPE,
+                "True" | "False" => BOOL_TYPE,
+                x => return locals.get(x).cloned(),
+            },
+            Expr::Tuple(_) => Tuple::TYPE,
+            Expr::List(_) | Expr::ListComprehension(..) => List::TYPE,
+            Expr::Dict(_) | Expr::DictComprehension(..) => Dict::TYPE,
+            Expr::Lambda(..) => FUNCTION_TYPE,
+            Expr::Not(_) => BOOL_TYPE,
+            Expr::Op(_, op, _) => match op {
+                BinOp::EqualsTo
+                | BinOp::Different
+                | BinOp::LessThan
+                | BinOp::GreaterThan
+                | BinOp::LessOrEqual
+                | BinOp::GreaterOrEqual
+                | BinOp::In
+                | BinOp::NotIn => BOOL_TYPE,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(res.to_owned())
+    }
+
+    fn check(
+        &mut self,
+        x: &AstExpr,
+        locals: &HashMap<&str, String>,
+        expected: &str,
+        issue: impl FnOnce(String) -> TypecheckIssue,
+    ) {
+        if let Some(got) = Self::infer(x, locals) {
+            if got != expected {
+                self.res.push(LintT::new(self.codemap, x.span, issue(got)))
+            }
+        }
+    }
+
+    fn stmt(&mut self, x: &AstStmt, locals: &HashMap<&str, String>, ret: Option<&str>) {
+        match &**x {
+            Stmt::Def(_, params, ret_type, body) => self.def(params, ret_type, body, locals),
+            Stmt::Return(e) => {
+                if let Some(ret) = ret {
+                    match e {
+                        Some(e) => {
+                            self.expr(e, locals);
+                            self.check(e, locals, ret, |got| {
+                                TypecheckIssue::IncompatibleReturn(ret.to_owned(), got)
+                            })
+                        }
+                        None if ret != NoneType::TYPE => self.res.push(LintT::new(
+                            self.codemap,
+                            x.span,
+                            TypecheckIssue::IncompatibleReturn(
+                                ret.to_owned(),
+                                NoneType::TYPE.to_owned(),
+                            ),
+                        )),
+                        None => {}
+                    }
+                } else {
+                    e.iter().for_each(|e| self.expr(e, locals));
+                }
+            }
+            _ => {
+                let mut stmts = Vec::new();
+                let mut exprs = Vec::new();
+                x.visit_children(|x| match x {
+                    Either::Left(x) => stmts.push(x),
+                    Either::Right(x) => exprs.push(x),
+                });
+                exprs.into_iter().for_each(|x| self.expr(x, locals));
+                stmts.into_iter().for_each(|x| self.stmt(x, locals, ret));
+            }
+        }
+    }
+
+    fn def(
+        &mut self,
+        params: &[AstParameter],
+        ret_type: &Option<Box<AstExpr>>,
+        body: &AstStmt,
+        locals: &HashMap<&str, String>,
+    ) {
+        // Parameters that get reassigned might change type, so we don't trust their annotation
+        fn assigned<'b>(x: &'b AstStmt, res: &mut HashSet<&'b str>) {
+            match &**x {
+                Stmt::Assign(lhs, _, _) => lhs.visit_expr_lvalue(|x| {
+                    res.insert(&x.node);
+                }),
+                Stmt::For(box (var, _, _)) => var.visit_expr_lvalue(|x| {
+                    res.insert(&x.node);
+                }),
+                _ => {}
+            }
+            x.visit_stmt(|x| assigned(x, res))
+        }
+
+        let mut reassigned = HashSet::new();
+        assigned(body, &mut reassigned);
+
+        let mut inner = HashMap::new();
+        for p in params {
+            let (name, _, default) = p.split();
+            if let Some(default) = default {
+                self.expr(default, locals);
+            }
+            if let Some(typ) = self.parameter_annotation(p) {
+                let name = name.unwrap();
+                if let Some(default) = default {
+                    self.check(default, locals, &typ, |got| {
+                        TypecheckIssue::IncompatibleDefault(name.node.clone(), typ.clone(), got)
+                    });
+                }
+                if !reassigned.contains(name.node.as_str()) {
+                    inner.insert(name.node.as_str(), typ);
+                }
+            }
+        }
+        let ret = ret_type.as_ref().and_then(|x| self.annotation(x));
+        self.stmt(body, &inner, ret.as_deref());
+    }
+
+    fn expr(&mut self, x: &AstExpr, locals: &HashMap<&str, String>) {
+        match &**x {
+            Expr::Call(box f, args) => {
+                if let Expr::Identifier(f) = &**f {
+                    self.call(&f.node, args, locals);
+                }
+            }
+            // These introduce new variables, which might shadow the ones we know about
+            Expr::Lambda(..) | Expr::ListComprehension(..) | Expr::DictComprehension(..) => {
+                let empty = HashMap::new();
+                x.visit_expr(|x| self.expr(x, &empty));
+                return;
+            }
+            _ => {}
+        }
+        x.visit_expr(|x| self.expr(x, locals));
+    }
+
+    fn call(&mut self, f: &str, args: &[AstArgument], locals: &HashMap<&str, String>) {
+        let sig = match self.signatures.get(f) {
+            None => return,
+            Some(sig) => sig,
+        };
+        let mut expected = Vec::new();
+        let mut positional = true;
+        let mut index = 0;
+        for arg in args {
+            match &**arg {
+                Argument::Positional(e) if positional => {
+                    match sig.params.get(index) {
+                        Some((Parameter::Normal(name, _), Some(typ)))
+                        | Some((Parameter::WithDefaultValue(name, _, _), Some(typ))) => {
+                            expected.push((e, name.node.clone(), typ.clone()))
+                        }
+                        Some((Parameter::Normal(..), None))
+                        | Some((Parameter::WithDefaultValue(..), None)) => {}
+                        // Anything else means we can't line up positional arguments any more
+                        _ => positional = false,
+                    }
+                    index += 1;
+                }
+                Argument::Named(name, e) => {
+                    for (p, typ) in &sig.params {
+                        match (p, typ) {
+                            (Parameter::Normal(n, _), Some(typ))
+                            | (Parameter::WithDefaultValue(n, _, _), Some(typ))
+                                if n.node == name.node =>
+                            {
+                                expected.push((e, name.node.clone(), typ.clone()))
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Argument::ArgsArray(_) => positional = false,
+                _ => {}
+            }
+        }
+        for (e, name, typ) in expected {
+            self.check(e, locals, &typ, |got| {
+                TypecheckIssue::IncompatibleArgument(name, typ.clone(), got)
+            })
+        }
+    }
+}
+
+pub(crate) fn typecheck_issues(
+    module: &AstModule,
+    globals: &Globals,
+) -> Vec<LintT<TypecheckIssue>> {
+    let mut check = Typecheck {
+        codemap: &module.codemap,
+        globals,
+        heap: Heap::new(),
+        signatures: HashMap::new(),
+        res: Vec::new(),
+    };
+
+    // Calls can only be checked against the functions defined at the top-level
+    let mut signatures = HashMap::new();
+    module.statement.visit_stmt(|x| {
+        if let Stmt::Def(name, params, _, _) = &**x {
+            let params = params
+                .iter()
+                .map(|p| (&p.node, check.parameter_annotation(p)))
+                .collect();
+            signatures.insert(name.node.as_str(), Signature { params });
+        }
+    });
+    check.signatures = signatures;
+
+    check.stmt(&module.statement, &HashMap::new(), None);
+    check.res
+}
+
+impl AstModule {
+    /// Statically check the type annotations on function parameters and return types, before
+    /// the module is evaluated. The `globals` are used to resolve annotations such as `int.type`.
+    ///
+    /// The check only considers values whose type is obvious from the source, so it will not
+    /// find every error that would be found at runtime, but any problem it reports would be an error
+    /// if that code was executed.
+    pub fn typecheck(&self, globals: &Globals) -> Vec<Lint> {
+        typecheck_issues(self, globals)
+            .into_iter()
+            .map(LintT::erase)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::Dialect;
+    use gazebo::prelude::*;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    #[test]
+    fn test_typecheck() {
+        let m = module(
+            r#"
+def f(x: int.type, y: str.type = 1, z: "" = 3) -> str.type:
+    if x == 1:
+        return x
+    elif x == 2:
+        return
+    return "ok"
+
+def g(x: int.type, y: [int.type]) -> None:
+    x = "reassigned"
+    f(x, "ok")
+    return 1 == 2
+
+f(1, "a")
+f("no", z = 1)
+f(1, y = [])
+g(1, True)
+"#,
+        );
+        let res = typecheck_issues(&m, &Globals::standard());
+        let res = res.map(|x| x.problem.to_string());
+        assert_eq!(
+            res,
+            &[
+                "Default value of argument `y` has type `int`, but the type annotation is `string`",
+                "Return type annotation is `string`, but returns a value of type `int`",
+                "Return type annotation is `string`, but returns a value of type `NoneType`",
+                "Return type annotation is `NoneType`, but returns a value of type `bool`",
+                "Argument `x` has type annotation `int`, but is given a value of type `string`",
+                "Argument `y` has type annotation `string`, but is given a value of type `list`",
+            ]
+        );
+    }
+}
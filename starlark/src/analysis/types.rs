@@ -17,10 +17,19 @@
 
 use crate::codemap::{CodeMap, LineCol, Span, SpanLoc};
 use gazebo::variants::VariantName;
-use std::fmt::{self, Display};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+};
 
 pub(crate) trait LintWarning: Display + VariantName {
     fn is_serious(&self) -> bool;
+
+    // Should this be reported by default, which is always the case for serious lints,
+    // but also for some which usually point at leftover or confusing code
+    fn is_warning(&self) -> bool {
+        self.is_serious()
+    }
 }
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
@@ -128,12 +137,37 @@ pub struct Lint {
     /// Is this code highly-likely to be wrong, rather
     /// than merely stylistically non-ideal.
     pub serious: bool,
+    /// Should this be reported by default. True for all [`serious`](Lint::serious) lints,
+    /// and for some which aren't, but usually point at leftover or confusing code,
+    /// e.g. `unused-load`.
+    pub warning: bool,
     /// A description of the underlying problem.
     pub problem: String,
     /// The source code at [`location`](Lint::location).
     pub original: String,
 }
 
+/// Which lints [`AstModule::lint_with_config`](crate::syntax::AstModule::lint_with_config)
+/// reports, selected by their [`short_name`](Lint::short_name). By default all lints are reported.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    /// If [`Some`], only report lints whose short name is in this set.
+    pub enabled: Option<HashSet<String>>,
+    /// Never report lints whose short name is in this set, even if enabled.
+    pub disabled: HashSet<String>,
+}
+
+impl LintConfig {
+    /// Should a lint with this short name (e.g. `unused-load`) be reported.
+    pub fn is_enabled(&self, short_name: &str) -> bool {
+        !self.disabled.contains(short_name)
+            && self
+                .enabled
+                .as_ref()
+                .map_or(true, |x| x.contains(short_name))
+    }
+}
+
 impl Display for Lint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.location, self.problem)
@@ -161,6 +195,7 @@ impl<T: LintWarning> LintT<T> {
             location: self.location,
             short_name: kebab(self.problem.variant_name()),
             serious: self.problem.is_serious(),
+            warning: self.problem.is_warning(),
             problem: self.problem.to_string(),
             original: self.original,
         }
@@ -202,6 +237,22 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_lint_config() {
+        let all = LintConfig::default();
+        assert!(all.is_enabled("unused-load"));
+
+        let mut config = LintConfig::default();
+        config.disabled.insert("unused-load".to_owned());
+        assert!(!config.is_enabled("unused-load"));
+        assert!(config.is_enabled("unreachable"));
+
+        config.enabled = Some(hashset! {"unreachable".to_owned(), "unused-load".to_owned()});
+        assert!(config.is_enabled("unreachable"));
+        assert!(!config.is_enabled("unused-load"));
+        assert!(!config.is_enabled("missing-return"));
+    }
+
     #[test]
     fn test_line_col_span_display_point() {
         let line_col = LineCol { line: 0, column: 0 };
@@ -23,13 +23,13 @@ use crate::{
     environment::{FrozenModule, Globals, GlobalsBuilder, Module},
     errors::Diagnostic,
     eval::{Evaluator, ReturnFileLoader},
+    stdlib::testing,
     syntax::{
         lexer::{Lexer, Token},
         AstModule, Dialect,
     },
     values::{none::NoneType, structs::Struct, OwnedFrozenValue, Value},
 };
-use anyhow::anyhow;
 use gazebo::prelude::*;
 use once_cell::sync::Lazy;
 use std::{collections::HashMap, sync::Arc};
@@ -42,7 +42,10 @@ static GLOBALS: Lazy<Globals> = Lazy::new(|| mk_environment().build());
 
 static ASSERT_STAR: Lazy<FrozenModule> = Lazy::new(|| {
     let g = GlobalsBuilder::new()
-        .with_struct("assert", assert_star)
+        .with_struct("assert", |x| {
+            testing::assert_members(x);
+            assert_star(x)
+        })
         .build();
     let m = Module::new();
     m.frozen_heap().add_reference(g.heap());
@@ -52,14 +55,6 @@ static ASSERT_STAR: Lazy<FrozenModule> = Lazy::new(|| {
     m.freeze()
 });
 
-fn assert_equals<'v>(a: Value<'v>, b: Value<'v>) -> anyhow::Result<NoneType> {
-    if !a.equals(b)? {
-        Err(anyhow!("assert_eq: expected {}, got {}", a, b))
-    } else {
-        Ok(NoneType)
-    }
-}
-
 /// How often we garbage collection _should_ be transparent to the tests,
 /// so we run each test in three configurations.
 #[derive(Clone, Copy, Dupe)]
@@ -69,47 +64,22 @@ enum GcStrategy {
     Always, // GC as aggressively as we can
 }
 
-/// Definitions to support assert.star as used by the Go test suite
+/// Definitions to support assert.star as used by the Go test suite,
+/// in addition to those from [`testing::assert_members`].
 #[starlark_module]
 fn assert_star(builder: &mut GlobalsBuilder) {
-    fn eq(a: Value, b: Value) -> NoneType {
-        assert_equals(a, b)
-    }
-
-    fn ne(a: Value, b: Value) -> NoneType {
-        if a.equals(b)? {
-            Err(anyhow!(
-                "assert.ne: expected {} and {} different, but the same",
-                a,
-                b
-            ))
-        } else {
-            Ok(NoneType)
-        }
-    }
-
-    fn contains(xs: Value, x: Value) -> NoneType {
-        if !xs.is_in(x)? {
-            Err(anyhow!("assert.contains: expected {} to be in {}", x, xs))
-        } else {
-            Ok(NoneType)
-        }
-    }
-
-    fn r#true(x: Value) -> NoneType {
-        assert_equals(Value::new_bool(x.to_bool()), Value::new_bool(true))
-    }
-
     // We don't allow this at runtime - just to be compatible with the Go Starlark test suite
     fn freeze(x: Value) -> Value<'v> {
         Ok(x)
     }
 
+    // The Go Starlark test suite expects the Go error messages, which differ from ours,
+    // so unlike the builtin `assert.fails`, only check that `f` fails
     fn fails(f: Value, _msg: &str) -> NoneType {
         let invoke = f.new_invoker(heap)?;
         match invoke.invoke(f, None, ctx) {
-            Err(_e) => Ok(NoneType), // We don't actually check the message
-            Ok(_) => Err(anyhow!("assert.fails: didn't fail")),
+            Err(_) => Ok(NoneType),
+            Ok(_) => Err(anyhow::anyhow!("assert.fails: didn't fail")),
         }
     }
 }
@@ -129,10 +99,6 @@ fn test_methods(builder: &mut GlobalsBuilder) {
         Ok(xs)
     }
 
-    fn assert_eq(a: Value, b: Value) -> NoneType {
-        assert_equals(a, b)
-    }
-
     // This is only safe to call at the top-level of a Starlark module
     fn garbage_collect() -> NoneType {
         if ctx.is_module_scope {
@@ -347,6 +313,14 @@ impl Assert {
             original
         })
     }
+
+    /// Run a program once, with the default garbage collection settings,
+    /// returning the error rather than panicking.
+    pub(crate) fn execute_result(&self, program: &str) -> anyhow::Result<()> {
+        let env = Module::new();
+        self.execute("assert.bzl", program, &env, GcStrategy::Auto)
+            .map(|_| ())
+    }
 }
 
 /// Execution tests.
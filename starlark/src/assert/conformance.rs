@@ -49,6 +49,42 @@ impl Assert {
             panic!("Exception given but not used, `{}`", missed);
         }
     }
+
+    /// Run a conformance test file, e.g. one of the upstream Go or Java spec tests,
+    /// recording the outcome of each test case rather than panicking on the first divergence.
+    /// Any test case containing one of the `skip` substrings is skipped, wherever it occurs.
+    pub fn conformance_report(&self, code: &str, skip: &[&str]) -> ConformanceReport {
+        let mut report = ConformanceReport::default();
+        for x in ConformanceTest::parse(code) {
+            if skip.iter().any(|s| x.code.contains(s)) {
+                report.skipped += 1;
+            } else {
+                match x.check(self) {
+                    Ok(()) => report.passed += 1,
+                    Err(e) => report.failed.push((x.code, e)),
+                }
+            }
+        }
+        report
+    }
+}
+
+/// The outcome of [`Assert::conformance_report`].
+#[derive(Debug, Default)]
+pub struct ConformanceReport {
+    /// Number of test cases which behaved as the test file expected.
+    pub passed: usize,
+    /// Number of test cases which matched the skip-list.
+    pub skipped: usize,
+    /// Each test case which diverged, paired with a description of how.
+    pub failed: Vec<(String, String)>,
+}
+
+impl ConformanceReport {
+    /// Did every test case that was run behave as expected.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
 }
 
 /// Describe a conformance test
@@ -75,17 +111,37 @@ impl ConformanceTest {
             .collect()
     }
 
-    fn test(&self, assert: &Assert) {
-        fn get_line(err: &anyhow::Error) -> Option<usize> {
-            match err.downcast_ref::<Diagnostic>() {
-                Some(Diagnostic {
-                    span: Some((span, codemap)),
-                    ..
-                }) => Some(codemap.look_up_span(*span).begin.line + 1),
-                _ => None,
+    fn get_line(err: &anyhow::Error) -> Option<usize> {
+        match err.downcast_ref::<Diagnostic>() {
+            Some(Diagnostic {
+                span: Some((span, codemap)),
+                ..
+            }) => Some(codemap.look_up_span(*span).begin.line + 1),
+            _ => None,
+        }
+    }
+
+    /// Like `test`, but describes any divergence in the result, rather than panicking.
+    fn check(&self, assert: &Assert) -> Result<(), String> {
+        match (&self.error, assert.execute_result(&self.code)) {
+            (None, Ok(())) => Ok(()),
+            (None, Err(e)) => Err(format!("Expected success, got error: {:#}", e)),
+            (Some((line, _)), Ok(())) => Err(format!("Expected an error at line {}", line)),
+            (Some((line, _)), Err(e)) => {
+                let got = Self::get_line(&e);
+                if got == Some(*line) {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Expected an error at line {}, got {:?}: {:#}",
+                        line, got, e
+                    ))
+                }
             }
         }
+    }
 
+    fn test(&self, assert: &Assert) {
         match &self.error {
             None => {
                 assert.pass(&self.code);
@@ -94,7 +150,7 @@ impl ConformanceTest {
                 // We don't actually check error messages, since these tests were taken from upstream
                 // and our error messages are different
                 let err = assert.fail(&self.code, "");
-                let got = get_line(&err);
+                let got = Self::get_line(&err);
                 if got != Some(*line) {
                     panic!(
                         "starlark::assert::conformance, failed at wrong line!\nCode:\n{}\nError:\n{}\nExpected: {}\nGot: {:?}\n",
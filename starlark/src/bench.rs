@@ -0,0 +1,159 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A fixed set of Starlark programs for benchmarking the evaluator.
+//!
+//! Only available with the `bench` feature. The programs are kept stable, so that
+//! timings taken before and after an optimisation are measuring the same work.
+//! Each program defines a function `bench` taking no arguments, and evaluates
+//! to that function as the final statement, ready to be passed to
+//! [`Evaluator::eval_function`](crate::eval::Evaluator::eval_function).
+
+/// Calls to functions with positional, named, default and `*args` parameters.
+pub const FUNCTION_CALLS: &str = r#"
+def positional(a, b, c):
+    return a
+
+def named(a, b = 1, c = 2):
+    return b
+
+def varargs(*args, **kwargs):
+    return len(args) + len(kwargs)
+
+def bench():
+    for i in range(1000):
+        positional(i, 2, 3)
+        named(i, c = 3)
+        varargs(i, 2, x = 3)
+
+bench
+"#;
+
+/// Calls to builtin functions and methods, mostly with a fixed number of positional arguments.
+pub const NATIVE_CALLS: &str = r#"
+def bench():
+    xs = []
+    s = "starlark"
+    x = 0
+    for i in range(1000):
+        xs.append(i)
+        x += len(xs) + ord(s[i % 8])
+        if hasattr(s, "upper") and s.startswith("star"):
+            x += int(str(i))
+    if x <= 0:
+        fail("Wrong answer!")
+
+bench
+"#;
+
+/// Insertion, lookup, iteration and removal on dictionaries.
+pub const DICT_OPERATIONS: &str = r#"
+def bench():
+    d = {}
+    for i in range(1000):
+        d[i] = i
+    x = 0
+    for i in range(1000):
+        x += d[i]
+    for k, v in d.items():
+        x += v
+    for i in range(500):
+        d.pop(i)
+    if len(d) != 500:
+        fail("Wrong answer!")
+
+bench
+"#;
+
+/// Removal from the front, middle and back of a dictionary, interleaved with insertions,
+/// which must keep the remaining entries in insertion order.
+pub const DICT_REMOVAL: &str = r#"
+def bench():
+    d = {}
+    for i in range(1000):
+        d[i] = i
+    for i in range(300):
+        d.pop(i)
+        d.pop(999 - i)
+        d.pop(500 + i // 2, None)
+        d[1000 + i] = i
+    k, v = d.popitem()
+    if k != 300 or len(d) != 549:
+        fail("Wrong answer!")
+
+bench
+"#;
+
+/// Appending, indexing, slicing and sorting lists.
+pub const LIST_OPERATIONS: &str = r#"
+def bench():
+    xs = []
+    for i in range(1000):
+        xs.append(1000 - i)
+    x = 0
+    for i in range(1000):
+        x += xs[i]
+    ys = sorted(xs[100:900])
+    if ys[0] != 101:
+        fail("Wrong answer!")
+
+bench
+"#;
+
+/// Building strings with `%` formatting, `format` and concatenation.
+pub const STRING_FORMATTING: &str = r#"
+def bench():
+    for i in range(1000):
+        a = "%s: %d" % ("value", i)
+        b = "{}: {}".format("value", i)
+        c = "value: " + str(i)
+        if a != c or b != c:
+            fail("Wrong answer!")
+
+bench
+"#;
+
+/// A module loaded by [`MODULE_LOAD`] under the name `library.star`.
+pub const MODULE_LOAD_LIBRARY: &str = r#"
+def double(x):
+    return x * 2
+
+values = [double(i) for i in range(100)]
+"#;
+
+/// A module which loads [`MODULE_LOAD_LIBRARY`] as `library.star`.
+/// Unlike the other programs, the interesting cost is evaluating this module,
+/// not calling the `bench` function it returns.
+pub const MODULE_LOAD: &str = r#"
+load("library.star", "double", "values")
+
+def bench():
+    return double(len(values))
+
+bench
+"#;
+
+/// The function call, builtin call, collection and string programs, paired with a name,
+/// each intended to be measured by repeatedly calling `bench`.
+pub const MICRO_BENCHMARKS: &[(&str, &str)] = &[
+    ("function_calls", FUNCTION_CALLS),
+    ("native_calls", NATIVE_CALLS),
+    ("dict_operations", DICT_OPERATIONS),
+    ("dict_removal", DICT_REMOVAL),
+    ("list_operations", LIST_OPERATIONS),
+    ("string_formatting", STRING_FORMATTING),
+];
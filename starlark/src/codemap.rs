@@ -40,6 +40,7 @@ use gazebo::prelude::*;
 use std::{
     cmp, fmt,
     hash::{Hash, Hasher},
+    iter,
     ops::{Add, Deref, Sub},
     sync::Arc,
 };
@@ -241,6 +242,23 @@ impl File {
         LineCol { line, column }
     }
 
+    /// Gets the `Pos` of a line and column, the inverse of [`find_line_col`](File::find_line_col).
+    /// The column may be one past the last character of the line.
+    ///
+    /// Returns `None` if the line or column is out of range.
+    pub fn find_pos(&self, position: LineCol) -> Option<Pos> {
+        if position.line >= self.num_lines() {
+            return None;
+        }
+        let line = self.source_line(position.line);
+        let byte_col = line
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(iter::once(line.len()))
+            .nth(position.column)?;
+        Some(self.line_span(position.line).low + byte_col as u64)
+    }
+
     /// Gets the full source text of the file
     pub fn source(&self) -> &str {
         &self.source
@@ -458,3 +476,18 @@ fn test_multibyte() {
         }
     );
 }
+
+#[test]
+fn test_find_pos() {
+    let content = "65°00′N 18°00′W 汉语\n🔬";
+    let codemap = CodeMap::new("<test>".to_owned(), content.to_owned());
+    let file = codemap.get_file();
+
+    let pos = |line, column| file.find_pos(LineCol { line, column });
+    assert_eq!(pos(0, 0), Some(file.span.low()));
+    assert_eq!(pos(0, 15), Some(file.span.low() + 21));
+    assert_eq!(pos(0, 18), Some(file.span.low() + 28));
+    assert_eq!(pos(1, 1), Some(file.span.low() + 33));
+    assert_eq!(pos(1, 2), None);
+    assert_eq!(pos(2, 0), None);
+}
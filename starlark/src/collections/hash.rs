@@ -34,12 +34,20 @@ pub struct Hashed<K> {
 }
 
 /// A borrowed key and its hash.
-#[derive(Copy, Clone)]
 pub struct BorrowHashed<'a, Q: ?Sized> {
     hash: SmallHashResult,
     key: &'a Q,
 }
 
+// Derive would require `Q: Copy`, but we only hold a reference.
+impl<'a, Q: ?Sized> Clone for BorrowHashed<'a, Q> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, Q: ?Sized> Copy for BorrowHashed<'a, Q> {}
+
 impl SmallHashResult {
     /// Create a new [`SmallHashResult`] using the [`Hash`] trait
     /// for given key.
@@ -271,11 +271,20 @@ impl<K, V> Default for MapHolder<K, V> {
 
 /// An memory-efficient key-value map with determinstic order.
 ///
+/// Iteration is always in insertion order, whether the map is stored as a `Vec` or an
+/// [`IndexMap`](indexmap::IndexMap). [`remove`](SmallMap::remove) and
+/// [`shift_remove`](SmallMap::shift_remove) keep the rest of the map in that order, while
+/// [`swap_remove`](SmallMap::swap_remove) is faster but moves the last entry. The order never
+/// depends on the hashes of the keys, and those hashes are computed without a random seed, so
+/// the same operations produce the same map on every run and every machine.
+///
 /// Provides the standard container operations, modelled most closely on [`IndexMap`](indexmap::IndexMap), plus:
 ///
 /// * Variants which take an already hashed value, e.g. [`get_hashed`](SmallMap::get_hashed).
 ///
 /// * Functions which work with the position, e.g. [`get_index_of`](SmallMap::get_index_of).
+///
+/// * An [`entry_hashed`](SmallMap::entry_hashed) function to look up a key once, then read or insert.
 #[derive(Debug, Clone, Default_)]
 pub struct SmallMap<K, V> {
     state: MapHolder<K, V>,
@@ -410,6 +419,14 @@ impl<K, V> SmallMap<K, V> {
         }
     }
 
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        match &mut self.state {
+            MapHolder::Empty => None,
+            MapHolder::Vec(x) => x.get_index_mut(index),
+            MapHolder::Map(m) => m.get_index_mut(index).map(|(k, v)| (k.key(), v)),
+        }
+    }
+
     pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
     where
         Q: Hash + Equivalent<K> + ?Sized,
@@ -537,24 +554,115 @@ impl<K, V> SmallMap<K, V> {
         self.insert_hashed(Hashed::new(key), val)
     }
 
-    pub fn remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
+    /// Find the entry for a key, which can then be read, updated or inserted
+    /// without looking up or hashing the key again.
+    pub fn entry_hashed(&mut self, key: Hashed<K>) -> Entry<K, V>
+    where
+        K: Eq,
+    {
+        if let MapHolder::Map(ref mut m) = self.state {
+            return match m.entry(key) {
+                indexmap::map::Entry::Occupied(e) => {
+                    Entry::Occupied(OccupiedEntry(OccupiedEntryImpl::Map(e)))
+                }
+                indexmap::map::Entry::Vacant(e) => {
+                    Entry::Vacant(VacantEntry(VacantEntryImpl::Map(e)))
+                }
+            };
+        }
+        match self.get_index_of_hashed(key.borrow()) {
+            Some(i) => Entry::Occupied(OccupiedEntry(OccupiedEntryImpl::Small(self, i))),
+            None => Entry::Vacant(VacantEntry(VacantEntryImpl::Small(self, key))),
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<K, V>
+    where
+        K: Hash + Eq,
+    {
+        self.entry_hashed(Hashed::new(key))
+    }
+
+    // Insert a key which is known not to be present, when we are not yet a `Map`.
+    fn insert_vacant(&mut self, key: Hashed<K>, val: V) -> &mut V
+    where
+        K: Eq,
+    {
+        match self.state {
+            MapHolder::Empty => self
+                .upgrade_empty_to_vec()
+                .insert_unique_unchecked(key, val),
+            MapHolder::Vec(ref mut v) if v.len() + 1 < THRESHOLD => {
+                v.insert_unique_unchecked(key, val)
+            }
+            MapHolder::Vec(_) => {
+                let want = self.len() + 1;
+                self.upgrade_vec_to_map(want).entry(key).or_insert(val)
+            }
+            MapHolder::Map(ref mut m) => m.entry(key).or_insert(val),
+        }
+    }
+
+    /// Remove the entry for `key`, moving every later entry back one position, so the rest
+    /// of the map stays in insertion order. Takes time proportional to the number of later entries.
+    pub fn shift_remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
     where
         Q: ?Sized + Equivalent<K>,
         K: Eq,
     {
         match self.state {
             MapHolder::Empty => None,
-            MapHolder::Vec(ref mut v) => v.remove_hashed(key),
+            MapHolder::Vec(ref mut v) => v.shift_remove_hashed(key),
             MapHolder::Map(ref mut m) => m.shift_remove(&key),
         }
     }
 
+    pub fn shift_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+        K: Eq,
+    {
+        self.shift_remove_hashed(BorrowHashed::new(key))
+    }
+
+    /// Remove the entry for `key`, moving the last entry into its position. Takes constant time,
+    /// but the map is no longer in insertion order.
+    pub fn swap_remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
+    where
+        Q: ?Sized + Equivalent<K>,
+        K: Eq,
+    {
+        match self.state {
+            MapHolder::Empty => None,
+            MapHolder::Vec(ref mut v) => v.swap_remove_hashed(key),
+            MapHolder::Map(ref mut m) => m.swap_remove(&key),
+        }
+    }
+
+    pub fn swap_remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Hash + Equivalent<K>,
+        K: Eq,
+    {
+        self.swap_remove_hashed(BorrowHashed::new(key))
+    }
+
+    /// The same as [`shift_remove_hashed`](SmallMap::shift_remove_hashed).
+    pub fn remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
+    where
+        Q: ?Sized + Equivalent<K>,
+        K: Eq,
+    {
+        self.shift_remove_hashed(key)
+    }
+
+    /// The same as [`shift_remove`](SmallMap::shift_remove).
     pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
     where
         Q: ?Sized + Hash + Equivalent<K>,
         K: Eq,
     {
-        self.remove_hashed(BorrowHashed::new(key))
+        self.shift_remove(key)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -578,6 +686,111 @@ impl<K, V> SmallMap<K, V> {
     }
 }
 
+/// A view into a single entry of a [`SmallMap`], created by [`entry`](SmallMap::entry)
+/// or [`entry_hashed`](SmallMap::entry_hashed).
+pub enum Entry<'a, K, V> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+/// An [`Entry`] for a key which is in the map.
+pub struct OccupiedEntry<'a, K, V>(OccupiedEntryImpl<'a, K, V>);
+
+/// An [`Entry`] for a key which is not in the map.
+pub struct VacantEntry<'a, K, V>(VacantEntryImpl<'a, K, V>);
+
+enum OccupiedEntryImpl<'a, K, V> {
+    // The map is `Vec`, and this is the index of the key
+    Small(&'a mut SmallMap<K, V>, usize),
+    Map(indexmap::map::OccupiedEntry<'a, Hashed<K>, V>),
+}
+
+enum VacantEntryImpl<'a, K, V> {
+    // The map is `Empty` or `Vec`
+    Small(&'a mut SmallMap<K, V>, Hashed<K>),
+    Map(indexmap::map::VacantEntry<'a, Hashed<K>, V>),
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V
+    where
+        K: Eq,
+    {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> V) -> &'a mut V
+    where
+        K: Eq,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match &self.0 {
+            OccupiedEntryImpl::Small(m, i) => m.get_index(*i).unwrap().0,
+            OccupiedEntryImpl::Map(e) => e.key().key(),
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        match &self.0 {
+            OccupiedEntryImpl::Small(m, i) => m.get_index(*i).unwrap().1,
+            OccupiedEntryImpl::Map(e) => e.get(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        match &mut self.0 {
+            OccupiedEntryImpl::Small(m, i) => m.get_index_mut(*i).unwrap().1,
+            OccupiedEntryImpl::Map(e) => e.get_mut(),
+        }
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        match self.0 {
+            OccupiedEntryImpl::Small(m, i) => m.get_index_mut(i).unwrap().1,
+            OccupiedEntryImpl::Map(e) => e.into_mut(),
+        }
+    }
+
+    /// Replace the value, returning the old one.
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        match &self.0 {
+            VacantEntryImpl::Small(_, k) => k.key(),
+            VacantEntryImpl::Map(e) => e.key().key(),
+        }
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V
+    where
+        K: Eq,
+    {
+        match self.0 {
+            VacantEntryImpl::Small(m, k) => m.insert_vacant(k, value),
+            VacantEntryImpl::Map(e) => e.insert(value),
+        }
+    }
+}
+
 impl<K, V> FromIterator<(K, V)> for SmallMap<K, V>
 where
     K: Hash + Eq,
@@ -804,4 +1017,69 @@ mod tests {
         assert_eq!(i.next(), Some((3, "b")));
         assert_eq!(i.next(), None);
     }
+
+    #[test]
+    fn test_entry() {
+        // Go past THRESHOLD so we exercise both the `Vec` and `Map` representations
+        let mut m = SmallMap::new();
+        for i in 0..20 {
+            match m.entry_hashed(Hashed::new(i)) {
+                Entry::Occupied(_) => panic!("Unexpected entry"),
+                Entry::Vacant(e) => {
+                    assert_eq!(e.key(), &i);
+                    assert_eq!(*e.insert(i * 10), i * 10);
+                }
+            }
+            match m.entry(0) {
+                Entry::Occupied(mut e) => {
+                    assert_eq!(e.key(), &0);
+                    assert_eq!(e.insert(i), i);
+                    *e.get_mut() += 1;
+                    assert_eq!(e.get(), &(i + 1));
+                }
+                Entry::Vacant(_) => panic!("Missing entry"),
+            }
+        }
+        *m.entry(5).or_insert(0) += 1;
+        assert_eq!(m.entry(30).or_insert_with(|| 300), &mut 300);
+        assert_eq!(m.len(), 21);
+        assert_eq!(m.get(&0), Some(&20));
+        assert_eq!(m.get(&5), Some(&51));
+        assert_eq!(m.get_index(20), Some((&30, &300)));
+        assert!(m.keys().copied().eq((0..20).chain(vec![30])));
+    }
+
+    #[test]
+    fn test_insertion_order_after_remove() {
+        // Go past THRESHOLD so we exercise both the `Vec` and `Map` representations
+        for n in &[5, 20] {
+            let mut m = (0..*n).map(|i| (i, i)).collect::<SmallMap<_, _>>();
+            m.remove(&1);
+            m.remove_hashed(Hashed::new(3).borrow());
+            m.insert(1, 10);
+            m.insert(0, 0);
+            let mut expected = vec![0, 2];
+            expected.extend(4..*n);
+            expected.push(1);
+            assert_eq!(m.keys().copied().collect::<Vec<_>>(), expected);
+            assert_eq!(m.get_index_of(&1), Some(*n as usize - 2));
+        }
+    }
+
+    #[test]
+    fn test_swap_remove() {
+        for n in &[5, 20] {
+            let mut m = (0..*n).map(|i| (i, i * 10)).collect::<SmallMap<_, _>>();
+            assert_eq!(m.swap_remove(&1), Some(10));
+            assert_eq!(m.swap_remove_hashed(Hashed::new(1).borrow()), None);
+            let mut expected = vec![0, *n - 1];
+            expected.extend(2..*n - 1);
+            assert_eq!(m.keys().copied().collect::<Vec<_>>(), expected);
+            // The moved entry can still be found by key
+            assert_eq!(m.get(&(*n - 1)), Some(&((*n - 1) * 10)));
+            assert_eq!(m.swap_remove(&(*n - 1)), Some((*n - 1) * 10));
+            assert_eq!(m.get(&(*n - 2)), Some(&((*n - 2) * 10)));
+            assert_eq!(m.len(), *n as usize - 2);
+        }
+    }
 }
@@ -15,7 +15,10 @@
  * limitations under the License.
  */
 
-use crate::collections::small_map::SmallMap;
+use crate::collections::{
+    hash::{BorrowHashed, Hashed},
+    small_map::SmallMap,
+};
 use gazebo::prelude::*;
 use indexmap::Equivalent;
 use std::{
@@ -94,6 +97,21 @@ impl<T> SmallSet<T> {
         self.0.into_iter().map(|(t, _)| t)
     }
 
+    pub fn iter_hashed(&self) -> impl Iterator<Item = BorrowHashed<T>> {
+        self.0.iter_hashed().map(|(t, _)| t)
+    }
+
+    pub fn into_iter_hashed(self) -> impl Iterator<Item = Hashed<T>> {
+        self.0.into_iter_hashed().map(|(t, _)| t)
+    }
+
+    pub fn insert_hashed(&mut self, key: Hashed<T>) -> bool
+    where
+        T: Eq,
+    {
+        self.0.insert_hashed(key, ()).is_none()
+    }
+
     pub fn insert(&mut self, key: T) -> bool
     where
         T: Hash + Eq,
@@ -122,6 +140,14 @@ impl<T> SmallSet<T> {
         self.0.get_index_of(value)
     }
 
+    pub fn remove_hashed<Q>(&mut self, key: BorrowHashed<Q>)
+    where
+        Q: ?Sized + Equivalent<T>,
+        T: Eq,
+    {
+        self.0.remove_hashed(key);
+    }
+
     pub fn remove<Q>(&mut self, key: &Q)
     where
         Q: ?Sized + Hash + Equivalent<T>,
@@ -138,6 +164,14 @@ impl<T> SmallSet<T> {
         self.0.len()
     }
 
+    pub fn contains_hashed<Q>(&self, key: BorrowHashed<Q>) -> bool
+    where
+        Q: Equivalent<T> + ?Sized,
+        T: Eq,
+    {
+        self.0.contains_key_hashed(key)
+    }
+
     pub fn contains<Q>(&self, key: &Q) -> bool
     where
         Q: Hash + Equivalent<T> + ?Sized,
@@ -149,6 +183,74 @@ impl<T> SmallSet<T> {
     pub fn clear(&mut self) {
         self.0.clear()
     }
+
+    // The elements come from sets, so we reuse their hashes rather than recomputing them.
+    fn collect_hashed<'a>(xs: impl Iterator<Item = BorrowHashed<'a, T>>) -> Self
+    where
+        T: 'a + Eq + Clone,
+    {
+        let mut res = Self::new();
+        for x in xs {
+            res.insert_hashed(x.unborrow_clone());
+        }
+        res
+    }
+
+    /// The elements in either set: those in `self` in order, followed by those only in `other`.
+    pub fn union(&self, other: &Self) -> Self
+    where
+        T: Eq + Clone,
+    {
+        Self::collect_hashed(
+            self.iter_hashed()
+                .chain(other.iter_hashed().filter(|x| !self.contains_hashed(*x))),
+        )
+    }
+
+    /// The elements of `self` which are also in `other`, in the order of `self`.
+    pub fn intersection(&self, other: &Self) -> Self
+    where
+        T: Eq + Clone,
+    {
+        Self::collect_hashed(self.iter_hashed().filter(|x| other.contains_hashed(*x)))
+    }
+
+    /// The elements of `self` which are not in `other`, in the order of `self`.
+    pub fn difference(&self, other: &Self) -> Self
+    where
+        T: Eq + Clone,
+    {
+        Self::collect_hashed(self.iter_hashed().filter(|x| !other.contains_hashed(*x)))
+    }
+
+    /// The elements in exactly one of the sets: those only in `self` in order,
+    /// followed by those only in `other`.
+    pub fn symmetric_difference(&self, other: &Self) -> Self
+    where
+        T: Eq + Clone,
+    {
+        Self::collect_hashed(
+            self.iter_hashed()
+                .filter(|x| !other.contains_hashed(*x))
+                .chain(other.iter_hashed().filter(|x| !self.contains_hashed(*x))),
+        )
+    }
+
+    /// Whether every element of `self` is in `other`.
+    pub fn is_subset(&self, other: &Self) -> bool
+    where
+        T: Eq,
+    {
+        self.len() <= other.len() && self.iter_hashed().all(|x| other.contains_hashed(x))
+    }
+
+    /// Whether every element of `other` is in `self`.
+    pub fn is_superset(&self, other: &Self) -> bool
+    where
+        T: Eq,
+    {
+        other.is_subset(self)
+    }
 }
 
 /// Create a [`SmallSet`](SmallSet) from a list of values.
@@ -271,4 +373,40 @@ mod tests {
 
         assert_eq!(s.insert(5), false);
     }
+
+    #[test]
+    fn set_algebra() {
+        let a = smallset![1, 2, 3, 4];
+        let b = smallset![5, 4, 3];
+
+        assert!(a.union(&b).iter().eq(&[1, 2, 3, 4, 5]));
+        assert!(b.union(&a).iter().eq(&[5, 4, 3, 1, 2]));
+        assert!(a.intersection(&b).iter().eq(&[3, 4]));
+        assert!(b.intersection(&a).iter().eq(&[4, 3]));
+        assert!(a.difference(&b).iter().eq(&[1, 2]));
+        assert!(b.difference(&a).iter().eq(&[5]));
+        assert!(a.symmetric_difference(&b).iter().eq(&[1, 2, 5]));
+        assert!(a.union(&SmallSet::new()).iter().eq(a.iter()));
+        assert!(a.intersection(&SmallSet::new()).is_empty());
+
+        assert!(smallset![3, 1].is_subset(&a));
+        assert!(a.is_subset(&a));
+        assert!(!a.is_subset(&b));
+        assert!(!b.is_subset(&a));
+        assert!(a.is_superset(&smallset![4, 2]));
+        assert!(SmallSet::new().is_subset(&b));
+        assert!(!SmallSet::new().is_superset(&b));
+    }
+
+    #[test]
+    fn set_hashed() {
+        let mut s = SmallSet::new();
+        assert!(s.insert_hashed(Hashed::new("a")));
+        assert!(!s.insert_hashed(Hashed::new("a")));
+        assert!(s.insert("b"));
+        assert!(s.contains_hashed(BorrowHashed::new("b")));
+        s.remove_hashed(BorrowHashed::new("a"));
+        assert!(!s.contains("a"));
+        assert!(s.into_iter_hashed().map(|x| x.into_key()).eq(vec!["b"]));
+    }
 }
@@ -277,6 +277,10 @@ impl<K, V> VecMap<K, V> {
         self.values.get(index).map(|x| (&x.0, &x.1))
     }
 
+    pub fn get_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.values.get_mut(index).map(|x| (&x.0, &mut x.1))
+    }
+
     pub fn get_mut_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<&mut V>
     where
         Q: ?Sized + Equivalent<K>,
@@ -309,14 +313,21 @@ impl<K, V> VecMap<K, V> {
             mem::swap(v, &mut value);
             Some(value)
         } else {
-            let i = self.values.len();
-            self.hashes[i] = key.hash();
-            self.values.push((key.into_key(), value));
+            self.insert_unique_unchecked(key, value);
             None
         }
     }
 
-    pub fn remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
+    /// Insert a key which the caller guarantees is not already present,
+    /// and that there is room for without exceeding `THRESHOLD`.
+    pub fn insert_unique_unchecked(&mut self, key: Hashed<K>, value: V) -> &mut V {
+        let i = self.values.len();
+        self.hashes[i] = key.hash();
+        self.values.push((key.into_key(), value));
+        &mut self.values[i].1
+    }
+
+    pub fn shift_remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
     where
         Q: ?Sized + Equivalent<K>,
     {
@@ -336,6 +347,15 @@ impl<K, V> VecMap<K, V> {
         None
     }
 
+    pub fn swap_remove_hashed<Q>(&mut self, key: BorrowHashed<Q>) -> Option<V>
+    where
+        Q: ?Sized + Equivalent<K>,
+    {
+        let i = self.get_index_of_hashed(key)?;
+        self.hashes[i] = self.hashes[self.values.len() - 1];
+        Some(self.values.swap_remove(i).1)
+    }
+
     pub fn drain_to<S>(&mut self, map: &mut IndexMap<Hashed<K>, V, S>)
     where
         K: Eq,
@@ -15,7 +15,10 @@
  * limitations under the License.
  */
 
-use crate::{debug::inspect::to_scope_names, eval::Evaluator, syntax::AstModule, values::Value};
+use crate::{
+    codemap::Span, debug::inspect::to_scope_names, eval::Evaluator, syntax::AstModule,
+    values::Value,
+};
 use std::{collections::HashMap, mem};
 
 impl<'v, 'a> Evaluator<'v, 'a> {
@@ -95,12 +98,40 @@ impl<'v, 'a> Evaluator<'v, 'a> {
 
         res
     }
+
+    /// Evaluate `module`, returning the value of the expression at a 0-indexed line and column
+    /// (as found by [`find_expr_at`](AstModule::find_expr_at)) the first time it was evaluated,
+    /// along with its span. Returns `None` if there is no expression at that position, or it was
+    /// never evaluated, e.g. because it is in a function which wasn't called. Designed for IDE
+    /// hovers and debugging, where the type of the value can be obtained with
+    /// [`get_type`](Value::get_type).
+    ///
+    /// The module is evaluated once, so the value is the one it had in the frame where it was
+    /// evaluated, e.g. seeing the local variables of a function, and has no extra side effects.
+    /// The value of a variable being assigned to is the value assigned.
+    pub fn eval_module_at(
+        &mut self,
+        module: AstModule,
+        line: usize,
+        column: usize,
+    ) -> anyhow::Result<Option<(Span, Value<'v>)>> {
+        let span = module.find_expr_at(line, column);
+        let old_watched = self.watched.take();
+        let res = self.eval_module_watching(module, span);
+        let value = mem::replace(&mut self.watched, old_watched);
+        res?;
+        Ok(span.zip(value))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{self as starlark, assert, environment::GlobalsBuilder, syntax::Dialect};
+    use crate::{
+        self as starlark, assert,
+        environment::{Globals, GlobalsBuilder, Module},
+        syntax::Dialect,
+    };
     use itertools::Itertools;
 
     #[starlark_module]
@@ -169,4 +200,62 @@ def bar(y):
         );
         a.pass("load('test', 'bar'); assert_eq(bar(4), 4 + 7 + 2)");
     }
+
+    #[test]
+    fn test_eval_module_at() {
+        fn at_with(
+            code: &str,
+            dialect: &Dialect,
+            line: usize,
+            column: usize,
+        ) -> Option<(&'static str, String)> {
+            let module = AstModule::parse("test.bzl", code.to_owned(), dialect).unwrap();
+            let env = Module::new();
+            let globals = Globals::standard();
+            let mut eval = Evaluator::new(&env, &globals);
+            eval.eval_module_at(module, line, column)
+                .unwrap()
+                .map(|(_, x)| (x.get_type(), x.to_str()))
+        }
+        fn at(line: usize, column: usize) -> Option<(&'static str, String)> {
+            at_with("x = [1, 2]\ny = len(x)\n", &Dialect::Extended, line, column)
+        }
+
+        assert_eq!(at(0, 0), Some(("list", "[1, 2]".to_owned())));
+        assert_eq!(at(0, 4), Some(("list", "[1, 2]".to_owned())));
+        assert_eq!(at(1, 0), Some(("int", "2".to_owned())));
+        assert_eq!(at(1, 4).map(|x| x.0), Some("function"));
+        assert_eq!(at(1, 8), Some(("list", "[1, 2]".to_owned())));
+        assert_eq!(at(1, 9), Some(("int", "2".to_owned())));
+        assert_eq!(at(0, 1), None);
+
+        // Side effects only happen once
+        let code = "x = []\ndef f():\n    x.append(1)\n    return len(x)\ny = f()\n";
+        assert_eq!(
+            at_with(code, &Dialect::Extended, 4, 5),
+            Some(("int", "1".to_owned()))
+        );
+
+        // Names resolve to the locals of the frame, the first time it is evaluated
+        let code = "def g(a):\n    return a + 1\ng(5)\ng(6)\ndef h(b):\n    return b\n";
+        assert_eq!(
+            at_with(code, &Dialect::Extended, 1, 11),
+            Some(("int", "5".to_owned()))
+        );
+        assert_eq!(
+            at_with(code, &Dialect::Extended, 1, 13),
+            Some(("int", "6".to_owned()))
+        );
+        assert_eq!(at_with(code, &Dialect::Extended, 5, 11), None);
+
+        // The expression is evaluated in the dialect of the module
+        let dialect = Dialect {
+            enable_byte_strings: true,
+            ..Dialect::Extended
+        };
+        assert_eq!(
+            at_with("len('é')\n", &dialect, 0, 3),
+            Some(("int", "2".to_owned()))
+        );
+    }
 }
@@ -17,15 +17,25 @@
 
 use crate::{
     collections::SmallMap,
+    environment::EnvironmentError,
     stdlib,
+    syntax::{
+        ast::{AstExpr, Expr, Stmt, Visibility},
+        AstModule, Visitor,
+    },
     values::{
-        structs::FrozenStruct, AllocFrozenValue, FrozenHeap, FrozenHeapRef, FrozenValue, Value,
+        structs::FrozenStruct, type_name::TypeName, AllocFrozenValue, FrozenHeap, FrozenHeapRef,
+        FrozenValue, Value,
     },
 };
+use derivative::Derivative;
 use gazebo::prelude::*;
 use itertools::Itertools;
 use once_cell::sync::OnceCell;
-use std::{collections::HashMap, mem, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
 pub use crate::stdlib::LibraryExtension;
 
@@ -37,6 +47,27 @@ pub struct Globals(Arc<GlobalsData>);
 struct GlobalsData {
     heap: FrozenHeapRef,
     variables: HashMap<String, FrozenValue>,
+    lazy: HashMap<String, LazyGlobal>,
+}
+
+// A variable computed when first used, see `GlobalsBuilder::set_lazy`
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct LazyGlobal {
+    #[derivative(Debug = "ignore")]
+    init: Box<dyn Fn(&FrozenHeap) -> FrozenValue + Send + Sync>,
+    // Once computed, the value and the heap it was allocated in
+    value: OnceCell<(FrozenHeapRef, FrozenValue)>,
+}
+
+impl LazyGlobal {
+    fn get(&self) -> &(FrozenHeapRef, FrozenValue) {
+        self.value.get_or_init(|| {
+            let heap = FrozenHeap::new();
+            let value = (self.init)(&heap);
+            (heap.into_ref(), value)
+        })
+    }
 }
 
 /// Used to build a [`Globals`] value.
@@ -46,8 +77,34 @@ pub struct GlobalsBuilder {
     heap: FrozenHeap,
     // Normal top-level variables, e.g. True/hash
     variables: HashMap<String, FrozenValue>,
-    // Set to Some when we are in a struct builder, otherwise None
-    struct_fields: Option<SmallMap<String, FrozenValue>>,
+    // Top-level variables computed when first used
+    lazy: HashMap<String, LazyGlobal>,
+    // The fields of each struct being built, innermost last, empty if not in a struct builder
+    struct_fields: Vec<SmallMap<String, FrozenValue>>,
+}
+
+/// What [`GlobalsBuilder::merge`] does with a name defined by both builders.
+/// If both values are structs, e.g. from [`struct_`](GlobalsBuilder::struct_), their fields
+/// are merged instead, with the same policy for the fields defined by both.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// Fail, naming the duplicate, and leave the builder unchanged.
+    Error,
+    /// Keep the value already in the builder being merged into.
+    PreferLeft,
+    /// Replace it with the value from the builder being merged in.
+    PreferRight,
+}
+
+impl MergeConflict {
+    // Should the right definition of `name` replace the left one
+    fn prefer_right(self, name: &str) -> anyhow::Result<bool> {
+        match self {
+            Self::Error => Err(EnvironmentError::GlobalsMergeConflict(name.to_owned()).into()),
+            Self::PreferLeft => Ok(false),
+            Self::PreferRight => Ok(true),
+        }
+    }
 }
 
 impl Globals {
@@ -74,6 +131,13 @@ impl Globals {
         GlobalsBuilder::extended_by(extensions).build()
     }
 
+    /// Create a [`Globals`] combining those functions in the Starlark standard plus
+    /// the [`hermetic`](LibraryExtension::hermetic) extensions, so scripts can't observe
+    /// or affect anything outside the evaluation.
+    pub fn hermetic() -> Self {
+        GlobalsBuilder::hermetic().build()
+    }
+
     /// This function is only safe if you first call `heap` and keep a reference to it.
     /// Therefore, don't expose it on the public API.
     pub(crate) fn get<'v>(&'v self, name: &str) -> Option<Value<'v>> {
@@ -82,24 +146,88 @@ impl Globals {
 
     /// This function is only safe if you first call `heap` and keep a reference to it.
     /// Therefore, don't expose it on the public API.
+    /// Values set with [`set_lazy`](GlobalsBuilder::set_lazy) are in their own heap,
+    /// so the value must not outlive the [`Globals`].
     pub(crate) fn get_frozen(&self, name: &str) -> Option<FrozenValue> {
-        self.0.variables.get(name).copied()
+        match self.0.lazy.get(name) {
+            Some(x) => Some(x.get().1),
+            None => self.0.variables.get(name).copied(),
+        }
+    }
+
+    /// Like [`get_frozen`](Globals::get_frozen), but if the value was set with
+    /// [`set_lazy`](GlobalsBuilder::set_lazy), `heap` keeps alive the heap it is in.
+    pub(crate) fn get_frozen_into(&self, name: &str, heap: &FrozenHeap) -> Option<FrozenValue> {
+        match self.0.lazy.get(name) {
+            Some(x) => {
+                let (lazy_heap, value) = x.get();
+                heap.add_reference(lazy_heap);
+                Some(*value)
+            }
+            None => self.0.variables.get(name).copied(),
+        }
     }
 
     /// Get all the names defined in this environment.
     pub fn names(&self) -> Vec<String> {
-        self.0.variables.keys().cloned().collect()
+        self.0
+            .variables
+            .keys()
+            .chain(self.0.lazy.keys())
+            .cloned()
+            .collect()
     }
 
     pub(crate) fn heap(&self) -> &FrozenHeapRef {
         &self.0.heap
     }
 
+    /// The names defined in this environment which `module` refers to, in alphabetical order.
+    /// Globals can only be reached by name, so this includes every native function from here
+    /// the module could call. The answer is conservative, and also includes names used only on
+    /// branches that are never taken, or shadowed by local variables. Values obtained with `load`
+    /// are not included, they should be audited in the module that defines them.
+    pub fn referenced_by(&self, module: &AstModule) -> Vec<String> {
+        struct Names<'a> {
+            globals: &'a GlobalsData,
+            defines: HashMap<&'a str, Visibility>,
+            used: BTreeSet<String>,
+        }
+
+        impl Visitor for Names<'_> {
+            fn visit_expr(&mut self, x: &AstExpr) {
+                if let Expr::Identifier(name) = &x.node {
+                    if !self.defines.contains_key(name.node.as_str())
+                        && (self.globals.variables.contains_key(&name.node)
+                            || self.globals.lazy.contains_key(&name.node))
+                    {
+                        self.used.insert(name.node.clone());
+                    }
+                }
+            }
+        }
+
+        // Module-level definitions shadow globals everywhere in the module
+        let mut defines = HashMap::new();
+        Stmt::collect_defines(&module.statement, &mut defines);
+        let mut names = Names {
+            globals: &self.0,
+            defines,
+            used: BTreeSet::new(),
+        };
+        module.visit(&mut names);
+        names.used.into_iter().collect()
+    }
+
     /// Print information about the values in this object.
+    /// Computes any values set with [`set_lazy`](GlobalsBuilder::set_lazy) not yet used.
     pub fn describe(&self) -> String {
+        let lazy = self.0.lazy.iter().map(|(name, x)| (name, x.get().1));
         self.0
             .variables
             .iter()
+            .map(|(name, val)| (name, *val))
+            .chain(lazy)
             .map(|(name, val)| val.to_value().describe(name))
             .join("\n")
     }
@@ -111,7 +239,8 @@ impl GlobalsBuilder {
         Self {
             heap: FrozenHeap::new(),
             variables: HashMap::new(),
-            struct_fields: None,
+            lazy: HashMap::new(),
+            struct_fields: Vec::new(),
         }
     }
 
@@ -137,17 +266,19 @@ impl GlobalsBuilder {
         res
     }
 
+    /// Create a [`GlobalsBuilder`] combining those functions in the Starlark standard plus
+    /// the [`hermetic`](LibraryExtension::hermetic) extensions.
+    pub fn hermetic() -> Self {
+        Self::extended_by(LibraryExtension::hermetic())
+    }
+
     /// Add a nested struct to the builder. If `f` adds the definition `foo`,
     /// it will end up on a struct `name`, accessible as `name.foo`.
-    /// This function cannot be called recursively from inside `f`.
+    /// May be called from inside `f` to nest further, e.g. giving `name.inner.foo`.
     pub fn struct_(&mut self, name: &str, f: impl Fn(&mut GlobalsBuilder)) {
-        assert!(
-            self.struct_fields.is_none(),
-            "Can't recursively nest GlobalsBuilder::struct_"
-        );
-        self.struct_fields = Some(SmallMap::new());
+        self.struct_fields.push(SmallMap::new());
         f(self);
-        let fields = mem::take(&mut self.struct_fields).unwrap();
+        let fields = self.struct_fields.pop().unwrap();
         self.set(name, FrozenStruct { fields });
     }
 
@@ -163,11 +294,84 @@ impl GlobalsBuilder {
         self
     }
 
+    /// Add the definitions from `other`, for example to combine globals defined by several
+    /// crates. Names defined by both are resolved according to `conflict`.
+    /// Cannot be called from inside [`struct_`](GlobalsBuilder::struct_).
+    pub fn merge(&mut self, other: GlobalsBuilder, conflict: MergeConflict) -> anyhow::Result<()> {
+        assert!(
+            self.struct_fields.is_empty(),
+            "Can't call GlobalsBuilder::merge inside GlobalsBuilder::struct_"
+        );
+        // Our values may now point at those allocated by `other`
+        self.heap.add_reference(&other.heap.into_ref());
+
+        // Decide everything before changing anything, so an error leaves us unchanged
+        let mut variables = Vec::new();
+        for (name, right) in other.variables {
+            if let Some(left) = self.variables.get(&name) {
+                let value = self.merge_value(&name, *left, right, conflict)?;
+                variables.push((name, value));
+            } else if !self.lazy.contains_key(&name) || conflict.prefer_right(&name)? {
+                variables.push((name, right));
+            }
+        }
+        let mut lazy = Vec::new();
+        for (name, right) in other.lazy {
+            let defined = self.variables.contains_key(&name) || self.lazy.contains_key(&name);
+            if !defined || conflict.prefer_right(&name)? {
+                lazy.push((name, right));
+            }
+        }
+
+        for (name, value) in variables {
+            self.lazy.remove(&name);
+            self.variables.insert(name, value);
+        }
+        for (name, value) in lazy {
+            self.variables.remove(&name);
+            self.lazy.insert(name, value);
+        }
+        Ok(())
+    }
+
+    // The value of `path` after merging, where `left` is ours and `right` is from the builder
+    // being merged in. Structs are merged field by field.
+    fn merge_value(
+        &self,
+        path: &str,
+        left: FrozenValue,
+        right: FrozenValue,
+        conflict: MergeConflict,
+    ) -> anyhow::Result<FrozenValue> {
+        let left_struct = left.to_value().downcast_ref::<FrozenStruct>();
+        let right_struct = right.to_value().downcast_ref::<FrozenStruct>();
+        if let (Some(l), Some(r)) = (left_struct, right_struct) {
+            let mut fields = l.fields.clone();
+            for (name, right) in r.fields.iter() {
+                let value = match fields.get(name) {
+                    Some(left) => {
+                        let path = format!("{}.{}", path, name);
+                        self.merge_value(&path, *left, *right, conflict)?
+                    }
+                    None => *right,
+                };
+                fields.insert(name.clone(), value);
+            }
+            return Ok(self.alloc(FrozenStruct { fields }));
+        }
+        Ok(if conflict.prefer_right(path)? {
+            right
+        } else {
+            left
+        })
+    }
+
     /// Called at the end to build a [`Globals`].
     pub fn build(self) -> Globals {
         Globals(Arc::new(GlobalsData {
             heap: self.heap.into_ref(),
             variables: self.variables,
+            lazy: self.lazy,
         }))
     }
 
@@ -175,12 +379,49 @@ impl GlobalsBuilder {
     pub fn set<'v, V: AllocFrozenValue>(&'v mut self, name: &str, value: V) {
         let name = name.to_owned();
         let value = value.alloc_frozen_value(&self.heap);
-        match &mut self.struct_fields {
-            None => self.variables.insert(name, value),
+        match self.struct_fields.last_mut() {
+            None => {
+                self.lazy.remove(&name);
+                self.variables.insert(name, value)
+            }
             Some(fields) => fields.insert(name, value),
         };
     }
 
+    /// Set a value in the [`GlobalsBuilder`] which is computed by `f` the first time a module
+    /// using it is compiled, then shared by every later use of the [`Globals`]. Avoids the
+    /// cost of building values which are expensive to create but rarely used.
+    /// Cannot be called from inside [`struct_`](GlobalsBuilder::struct_).
+    pub fn set_lazy<V: AllocFrozenValue>(
+        &mut self,
+        name: &str,
+        f: impl Fn() -> V + Send + Sync + 'static,
+    ) {
+        assert!(
+            self.struct_fields.is_empty(),
+            "Can't call GlobalsBuilder::set_lazy inside GlobalsBuilder::struct_"
+        );
+        self.variables.remove(name);
+        self.lazy.insert(
+            name.to_owned(),
+            LazyGlobal {
+                init: box move |heap: &FrozenHeap| f().alloc_frozen_value(heap),
+                value: OnceCell::new(),
+            },
+        );
+    }
+
+    /// Register a global `name` standing for the type whose values have
+    /// [`get_type`](crate::values::StarlarkValue::get_type) equal to `typ`,
+    /// typically a type defined by the embedder. Scripts can then write `name.type`
+    /// in type annotations, and `isinstance(x, name)` with [`LibraryExtension::Typing`].
+    /// To register a constructor function instead, use `#[starlark_type("typ")]`
+    /// in a [`#[starlark_module]`](macro@starlark_module).
+    pub fn set_type_name(&mut self, name: &str, typ: &str) {
+        let typ = self.alloc(typ);
+        self.set(name, TypeName::new(name, typ));
+    }
+
     /// Allocate a value using the same underlying heap as the [`GlobalsBuilder`],
     /// only intended for values that are referred to by those which are passed
     /// to [`set`](GlobalsBuilder::set).
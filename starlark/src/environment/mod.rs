@@ -30,6 +30,7 @@ pub(crate) mod slots;
 pub use globals::*;
 pub use modules::*;
 
+use crate::errors::ErrorKind;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -37,14 +38,43 @@ pub(crate) enum EnvironmentError {
     /// Variables was no found.
     #[error("Variable `{0}` not found")]
     VariableNotFound(String),
+    /// Variable was not found, but one with a similar name exists
+    #[error("Variable `{0}` not found, did you mean `{1}`?")]
+    VariableNotFoundDidYouMean(String, String),
+    #[error("Variable `{0}` has type `{1}`, which can't be converted to `{2}`")]
+    VariableWrongType(String, String, String),
     #[error("Local variable `{0}` referenced before assignment")]
     LocalVariableReferencedBeforeAssignment(String),
     /// Cannot import private symbol, i.e. underscore prefixed
     #[error("Cannot import private symbol `{0}`")]
     CannotImportPrivateSymbol(String),
+    /// Cannot import a symbol left out of the exports given to `Module::set_exports`
+    #[error("Cannot import symbol `{0}`, which is not exported by the module")]
+    CannotImportUnexportedSymbol(String),
+    /// The `FileLoader` doesn't allow this file to load the symbol
+    #[error("Cannot load `{0}` from `{1}`: {2}")]
+    CannotLoadSymbol(String, String, String),
+    /// The `FileLoader` couldn't resolve the path given to `load()`
+    #[error("Cannot resolve load of `{0}`: {1}")]
+    CannotResolveLoad(String, String),
+    /// Variables set with `Module::inject` are read-only to code
+    #[error("Cannot assign to variable `{0}`, which was injected into the module")]
+    CannotSetInjectedVariable(String),
     /// Can't set variables unless in the root name
     #[error("Cannot set variable `{0}` at this point, must be in a non-frozen module context")]
     CannotSetVariable(String),
     #[error("No imports are available, you tried `{0}` (no call to `Evaluator.set_loader`)")]
     NoImportsAvailable(String),
+    /// Both `GlobalsBuilder`s given to `GlobalsBuilder::merge` define the name
+    #[error("Global `{0}` is defined by both of the `GlobalsBuilder`s being merged")]
+    GlobalsMergeConflict(String),
+}
+
+impl EnvironmentError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::VariableWrongType(..) => ErrorKind::Type,
+            _ => ErrorKind::Scope,
+        }
+    }
 }
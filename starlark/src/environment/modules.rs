@@ -27,13 +27,13 @@ use crate::{
         EnvironmentError,
     },
     values::{
-        Freezer, FrozenHeap, FrozenHeapRef, FrozenValue, Heap, OwnedFrozenValue, SimpleValue,
-        StarlarkValue, Value, ValueLike,
+        Freezer, FrozenDedup, FrozenHeap, FrozenHeapRef, FrozenValue, Heap, OwnedFrozenValue,
+        SimpleValue, StarlarkValue, UnpackValue, Value, ValueLike,
     },
 };
 use gazebo::{any::AnyLifetime, prelude::*};
 use itertools::Itertools;
-use std::{mem, sync::Arc};
+use std::{any::type_name, cell::RefCell, collections::HashSet, mem, sync::Arc};
 
 /// The result of freezing a [`Module`], making it and its contained values immutable.
 ///
@@ -54,6 +54,9 @@ pub(crate) struct FrozenModuleRef(pub(crate) Arc<FrozenModuleData>);
 pub(crate) struct FrozenModuleData {
     pub(crate) names: FrozenNames,
     pub(crate) slots: FrozenSlots,
+    pub(crate) docstring: Option<String>,
+    // The names given to `Module::set_exports`, if it was called
+    pub(crate) exports: Option<HashSet<String>>,
 }
 
 // When a definition is frozen, it still needs to get at some module info,
@@ -79,6 +82,12 @@ pub struct Module {
     heap: Heap,
     frozen_heap: FrozenHeap,
     names: MutableNames,
+    // The docstring of the first evaluated code which had one
+    docstring: RefCell<Option<String>>,
+    // Variables set by `inject`, which code may not assign to
+    injected: RefCell<HashSet<String>>,
+    // Variables which other modules may load, if restricted by `set_exports`
+    exports: RefCell<Option<HashSet<String>>>,
     // Should really be MutableSlots<'v>, where &'v self
     // Values are allocated from heap. Because of variance
     // you can inject the wrong values in, so make sure slots aren't
@@ -98,11 +107,57 @@ impl FrozenModule {
             .map(|x| OwnedFrozenValue::new(self.0.dupe(), x))
     }
 
+    /// Get the value of the variable `name`, converted to a Rust type such as
+    /// [`i32`], [`String`] or `Vec<String>`.
+    /// Fails if the variable isn't defined or has a value which can't be converted.
+    pub fn get_typed<T: for<'v> UnpackValue<'v>>(&self, name: &str) -> anyhow::Result<T> {
+        let value = self
+            .get(name)
+            .ok_or_else(|| EnvironmentError::VariableNotFound(name.to_owned()))?;
+        // The result can't borrow from the heap, so a temporary one will do
+        let heap = Heap::new();
+        let value = value.value();
+        T::unpack_value(value, &heap).ok_or_else(|| {
+            EnvironmentError::VariableWrongType(
+                name.to_owned(),
+                value.get_type().to_owned(),
+                type_name::<T>().to_owned(),
+            )
+            .into()
+        })
+    }
+
     /// Iterate through all the names defined in this module.
     pub fn names(&self) -> impl Iterator<Item = &str> {
         self.1.names()
     }
 
+    /// Iterate through the names defined in this module which other modules can `load`,
+    /// see [`is_exported`](FrozenModule::is_exported).
+    pub fn public_names(&self) -> impl Iterator<Item = &str> {
+        self.names().filter(move |x| self.is_exported(x))
+    }
+
+    /// Can other modules `load` the variable `name` from this module. Names starting with
+    /// an underscore are never exported, and if [`Module::set_exports`] was called before
+    /// freezing, only the names given to it are.
+    pub fn is_exported(&self, name: &str) -> bool {
+        Module::is_public_symbol(name)
+            && match &self.1.0.exports {
+                None => true,
+                Some(exports) => exports.contains(name),
+            }
+    }
+
+    /// Iterate through the variables defined in this module along with their values,
+    /// skipping any which were never assigned.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, OwnedFrozenValue)> {
+        self.1.0.names.symbols().filter_map(move |(name, slot)| {
+            let value = self.1.0.slots.get_slot(*slot)?;
+            Some((name.as_str(), OwnedFrozenValue::new(self.0.dupe(), value)))
+        })
+    }
+
     /// Obtain the [`FrozenHeapRef`] which owns the storage of all values defined in this module.
     pub fn frozen_heap(&self) -> &FrozenHeapRef {
         &self.0
@@ -112,6 +167,11 @@ impl FrozenModule {
     pub fn describe(&self) -> String {
         self.1.describe()
     }
+
+    /// The docstring at the start of the module, if it had one.
+    pub fn documentation(&self) -> Option<&str> {
+        self.1.0.docstring.as_deref()
+    }
 }
 
 impl FrozenModuleRef {
@@ -166,6 +226,9 @@ impl Module {
             heap: Heap::new(),
             frozen_heap: FrozenHeap::new(),
             names: MutableNames::new(),
+            docstring: RefCell::new(None),
+            injected: RefCell::new(HashSet::new()),
+            exports: RefCell::new(None),
             slots: MutableSlots::new(),
         }
     }
@@ -184,6 +247,14 @@ impl Module {
         &self.names
     }
 
+    /// Record the docstring of evaluated code, unless we already have one.
+    pub(crate) fn set_docstring(&self, docstring: &str) {
+        let mut x = self.docstring.borrow_mut();
+        if x.is_none() {
+            *x = Some(docstring.to_owned());
+        }
+    }
+
     pub(crate) fn slots<'v>(&'v self) -> &'v MutableSlots<'v> {
         // Not true because of variance, but mostly true. Don't export further.
         unsafe { transmute!(&'v MutableSlots<'static>, &'v MutableSlots<'v>, &self.slots) }
@@ -195,10 +266,80 @@ impl Module {
         self.slots().get_slot(slot)
     }
 
+    /// Record the values of the variables in this module, so that evaluation can be undone
+    /// with [`rollback`](Module::rollback), e.g. when code entered at a REPL fails halfway.
+    /// Replaces any previous checkpoint.
+    ///
+    /// Only the variables are recorded, not the values they point at, so a rollback doesn't
+    /// undo mutation, e.g. `xs.append(1)` on a list defined before the checkpoint.
+    /// Values allocated since the checkpoint stay on the [`heap`](Module::heap) until a
+    /// garbage collection finds they are no longer used.
+    ///
+    /// ```
+    /// # fn run() -> anyhow::Result<()> {
+    /// use starlark::environment::{Globals, Module};
+    /// use starlark::eval::Evaluator;
+    /// use starlark::syntax::{AstModule, Dialect};
+    ///
+    /// let module = Module::new();
+    /// let globals = Globals::standard();
+    /// let mut eval = Evaluator::new(&module, &globals);
+    /// let parse = |code: &str| AstModule::parse("repl", code.to_owned(), &Dialect::Standard);
+    ///
+    /// eval.eval_module(parse("x = 1")?)?;
+    /// module.checkpoint();
+    /// if eval.eval_module(parse("x = 2\ny = 3\nfail('oops')")?).is_err() {
+    ///     module.rollback();
+    /// }
+    /// assert_eq!(module.get("x").unwrap().unpack_int(), Some(1));
+    /// assert!(module.get("y").is_none());
+    /// # Ok(())
+    /// # }
+    /// # fn main(){ run().unwrap(); }
+    /// ```
+    pub fn checkpoint(&self) {
+        self.slots.checkpoint()
+    }
+
+    /// Restore the variables to their values at the last [`checkpoint`](Module::checkpoint),
+    /// with variables defined since then becoming undefined again, and no longer listed as
+    /// names of the module. The checkpoint is used up. Returns `false`, changing nothing,
+    /// if there was no checkpoint.
+    pub fn rollback(&self) -> bool {
+        match self.slots.rollback() {
+            None => false,
+            Some(count) => {
+                self.names.truncate(count);
+                true
+            }
+        }
+    }
+
+    /// Forget the last [`checkpoint`](Module::checkpoint), e.g. once evaluation has succeeded,
+    /// so the values it recorded can be garbage collected. Returns `false` if there was none.
+    pub fn discard_checkpoint(&self) -> bool {
+        self.slots.discard_checkpoint()
+    }
+
     /// Freeze the environment, all its value will become immutable afterwards.
     pub fn freeze(self) -> FrozenModule {
+        self.freeze_with(None)
+    }
+
+    /// Like [`freeze`](Module::freeze), but any string or tuple equal to one previously
+    /// frozen with the same [`FrozenDedup`] reuses that value, rather than being copied
+    /// onto the frozen heap of this module. Useful when freezing many modules which
+    /// contain the same values.
+    pub fn freeze_dedup(self, dedup: &FrozenDedup) -> FrozenModule {
+        self.freeze_with(Some(dedup.dupe()))
+    }
+
+    fn freeze_with(self, dedup: Option<FrozenDedup>) -> FrozenModule {
         let Module {
             names,
+            docstring,
+            injected: _,
+            exports,
             slots,
             frozen_heap,
             heap,
@@ -207,11 +348,13 @@ impl Module {
         // Note that we even freeze anonymous slots, since they are accessed by
         // slot-index in the code, and we don't walk into them, so don't know if
         // they are used.
-        let freezer = Freezer::new(frozen_heap);
+        let freezer = Freezer::new(frozen_heap, dedup);
         let slots = slots.freeze(&freezer);
         let rest = FrozenModuleRef(Arc::new(FrozenModuleData {
             names: names.freeze(),
             slots,
+            docstring: docstring.into_inner(),
+            exports: exports.into_inner(),
         }));
         FrozenModuleValue::set(&freezer, &rest);
         // The values MUST be alive up until this point (as the above line uses them),
@@ -231,6 +374,39 @@ impl Module {
         slots.set_slot(slot, value);
     }
 
+    /// Set a variable which code evaluated in this module can read, but not assign to,
+    /// for example configuration supplied by the host. Unlike [`Globals`](crate::environment::Globals),
+    /// these variables are specific to one module, so the same code can be evaluated
+    /// with different values. After freezing they are ordinary module variables.
+    ///
+    /// Evaluating code which assigns to the variable at the top level fails before any
+    /// of it runs. Functions may still define a local variable with the same name.
+    pub fn inject<'v>(&'v self, name: &str, value: Value<'v>) {
+        self.injected.borrow_mut().insert(name.to_owned());
+        self.set(name, value);
+    }
+
+    /// Fail if any of these top-level definitions would reassign an injected variable.
+    pub(crate) fn check_injected<'a>(
+        &self,
+        defines: impl Iterator<Item = &'a str>,
+    ) -> anyhow::Result<()> {
+        let injected = self.injected.borrow();
+        // Take the first name alphabetically, so the error is deterministic
+        match defines.filter(|x| injected.contains(*x)).min() {
+            None => Ok(()),
+            Some(name) => Err(EnvironmentError::CannotSetInjectedVariable(name.to_owned()).into()),
+        }
+    }
+
+    /// Restrict the variables which other modules can `load` from this module, once frozen,
+    /// to those in `names`, for example to keep helpers out of the API of a module without
+    /// renaming them all to start with an underscore. Names starting with an underscore
+    /// remain private even if they are listed. Replaces the names from any previous call.
+    pub fn set_exports<'a>(&self, names: impl IntoIterator<Item = &'a str>) {
+        *self.exports.borrow_mut() = Some(names.into_iter().map(str::to_owned).collect());
+    }
+
     fn is_public_symbol(symbol: &str) -> bool {
         !symbol.starts_with('_')
     }
@@ -239,7 +415,7 @@ impl Module {
     pub fn import_public_symbols(&self, env: &FrozenModule) {
         self.frozen_heap.add_reference(&env.0);
         for (k, slot) in env.1.0.names.symbols() {
-            if Self::is_public_symbol(k) {
+            if env.is_exported(k) {
                 if let Some(value) = env.1.0.slots.get_slot(*slot) {
                     self.set(k, Value::new_frozen(value))
                 }
@@ -257,6 +433,9 @@ impl Module {
         }
         match env.get(symbol) {
             None => Err(EnvironmentError::VariableNotFound(symbol.to_owned()).into()),
+            Some(_) if !env.is_exported(symbol) => {
+                Err(EnvironmentError::CannotImportUnexportedSymbol(symbol.to_owned()).into())
+            }
             Some(v) => Ok(v.owned_value(self)),
         }
     }
@@ -268,3 +447,97 @@ where
     FrozenModule: Send + Sync,
 {
 }
+
+#[test]
+fn test_freeze_dedup() {
+    let dedup = FrozenDedup::new();
+    let freeze = |dedup: Option<&FrozenDedup>| {
+        let module = Module::new();
+        let heap = module.heap();
+        let s = heap.alloc("hello".to_owned());
+        module.set("s", s);
+        module.set("t", heap.alloc((s, 1)));
+        module.set("u", heap.alloc(("hello".to_owned(), 1)));
+        match dedup {
+            None => module.freeze(),
+            Some(dedup) => module.freeze_dedup(dedup),
+        }
+    };
+    let get =
+        |m: &FrozenModule, name| Value::new_frozen(m.get(name).unwrap().unchecked_frozen_value());
+
+    let a = freeze(Some(&dedup));
+    let b = freeze(Some(&dedup));
+    let c = freeze(None);
+    // Within one module, and across modules
+    assert!(get(&a, "t").ptr_eq(get(&a, "u")));
+    assert!(get(&a, "s").ptr_eq(get(&b, "s")));
+    assert!(get(&a, "t").ptr_eq(get(&b, "u")));
+    assert!(!get(&a, "s").ptr_eq(get(&c, "s")));
+    // The values of b must stay alive without a
+    mem::drop(a);
+    mem::drop(dedup);
+    assert_eq!(get(&b, "t").to_repr(), "(\"hello\", 1)");
+}
+
+#[test]
+fn test_frozen_module_introspection() {
+    let module = Module::new();
+    let heap = module.heap();
+    module.set("x", Value::new_int(42));
+    module.set("_y", heap.alloc("private"));
+    module.set("zs", heap.alloc(vec!["a", "b"]));
+    let module = module.freeze();
+
+    let mut public = module.public_names().collect::<Vec<_>>();
+    public.sort_unstable();
+    assert_eq!(public, vec!["x", "zs"]);
+    assert_eq!(module.names().count(), 3);
+
+    let mut values = module
+        .iter()
+        .map(|(k, v)| (k, v.value().to_str()))
+        .collect::<Vec<_>>();
+    values.sort();
+    assert_eq!(
+        values,
+        vec![
+            ("_y", "private".to_owned()),
+            ("x", "42".to_owned()),
+            ("zs", "[\"a\", \"b\"]".to_owned())
+        ]
+    );
+
+    assert_eq!(module.get_typed::<i32>("x").unwrap(), 42);
+    assert_eq!(module.get_typed::<String>("_y").unwrap(), "private");
+    assert_eq!(
+        module.get_typed::<Vec<String>>("zs").unwrap(),
+        vec!["a".to_owned(), "b".to_owned()]
+    );
+    let err = module.get_typed::<i32>("zs").unwrap_err().to_string();
+    assert!(err.contains("`zs` has type `list`"), "{}", err);
+    assert!(module.get_typed::<i32>("missing").is_err());
+}
+
+#[test]
+fn test_exports() {
+    let module = Module::new();
+    module.set("x", Value::new_int(1));
+    module.set("helper", Value::new_int(2));
+    module.set("_y", Value::new_int(3));
+    module.set_exports(vec!["x", "_y"]);
+    let module = module.freeze();
+
+    assert!(module.is_exported("x"));
+    assert!(!module.is_exported("helper"));
+    assert!(!module.is_exported("_y"));
+    assert_eq!(module.public_names().collect::<Vec<_>>(), vec!["x"]);
+
+    let importer = Module::new();
+    importer.import_public_symbols(&module);
+    assert!(importer.get("x").is_some());
+    assert!(importer.get("helper").is_none());
+    let err = importer.load_symbol(&module, "helper").unwrap_err();
+    assert!(err.to_string().contains("not exported"), "{}", err);
+    assert!(importer.load_symbol(&module, "missing").is_err());
+}
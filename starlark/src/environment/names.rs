@@ -70,6 +70,12 @@ impl MutableNames {
         self.0.borrow_mut().remove(name);
     }
 
+    // Remove the names whose slots are `count` or later, i.e. those added since there were
+    // `count` names
+    pub fn truncate(&self, count: usize) {
+        self.0.borrow_mut().retain(|_, slot| *slot < count);
+    }
+
     pub fn all_names(&self) -> HashMap<String, usize> {
         self.0.borrow().clone()
     }
@@ -17,7 +17,7 @@
 
 use crate::values::{Freezer, FrozenValue, Heap, Value, ValueRef, Walker};
 use gazebo::prelude::*;
-use std::cell::{RefCell, RefMut};
+use std::cell::RefCell;
 
 /// Slots that are used in a local context, e.g. for a function that is executing.
 /// Always mutable, never frozen. Uses the `ValueRef` because they have reference
@@ -28,7 +28,11 @@ pub(crate) struct LocalSlots<'v>(Vec<ValueRef<'v>>);
 
 // Indexed slots of a module. May contain unassigned values
 #[derive(Debug)]
-pub(crate) struct MutableSlots<'v>(RefCell<Vec<Value<'v>>>);
+pub(crate) struct MutableSlots<'v> {
+    slots: RefCell<Vec<Value<'v>>>,
+    // The slots saved by `checkpoint`, which are GC roots too
+    checkpoint: RefCell<Option<Vec<Value<'v>>>>,
+}
 
 // Indexed slots of a module. May contain unassigned values
 #[derive(Debug)]
@@ -36,25 +40,24 @@ pub(crate) struct FrozenSlots(Vec<FrozenValue>);
 
 impl<'v> MutableSlots<'v> {
     pub fn new() -> Self {
-        Self(RefCell::new(Vec::new()))
-    }
-
-    pub(crate) fn get_slots_mut(&self) -> RefMut<Vec<Value<'v>>> {
-        self.0.borrow_mut()
+        Self {
+            slots: RefCell::new(Vec::new()),
+            checkpoint: RefCell::new(None),
+        }
     }
 
     pub fn get_slot(&self, slot: usize) -> Option<Value<'v>> {
-        let v = self.0.borrow()[slot];
+        let v = self.slots.borrow()[slot];
         if v.is_unassigned() { None } else { Some(v) }
     }
 
     pub fn set_slot(&self, slot: usize, value: Value<'v>) {
         assert!(!value.is_unassigned());
-        self.0.borrow_mut()[slot] = value;
+        self.slots.borrow_mut()[slot] = value;
     }
 
     pub fn ensure_slots(&self, count: usize) {
-        let mut slots = self.0.borrow_mut();
+        let mut slots = self.slots.borrow_mut();
         if slots.len() >= count {
             return;
         }
@@ -65,8 +68,38 @@ impl<'v> MutableSlots<'v> {
         }
     }
 
+    pub(crate) fn checkpoint(&self) {
+        *self.checkpoint.borrow_mut() = Some(self.slots.borrow().clone());
+    }
+
+    // Slots can be added after the checkpoint, but never removed, so any extra ones
+    // are reset to unassigned. Returns the number of slots there were at the checkpoint.
+    pub(crate) fn rollback(&self) -> Option<usize> {
+        let saved = self.checkpoint.borrow_mut().take()?;
+        let mut slots = self.slots.borrow_mut();
+        for (i, x) in slots.iter_mut().enumerate() {
+            *x = saved.get(i).copied().unwrap_or_else(Value::new_unassigned);
+        }
+        Some(saved.len())
+    }
+
+    pub(crate) fn discard_checkpoint(&self) -> bool {
+        self.checkpoint.borrow_mut().take().is_some()
+    }
+
+    pub(crate) fn walk(&self, walker: &Walker<'v>) {
+        for x in self.slots.borrow_mut().iter_mut() {
+            walker.walk(x);
+        }
+        if let Some(saved) = &mut *self.checkpoint.borrow_mut() {
+            for x in saved.iter_mut() {
+                walker.walk(x);
+            }
+        }
+    }
+
     pub(crate) fn freeze(self, freezer: &Freezer) -> FrozenSlots {
-        let slots = self.0.into_inner().map(|x| x.freeze(freezer));
+        let slots = self.slots.into_inner().map(|x| x.freeze(freezer));
         FrozenSlots(slots)
     }
 }
@@ -83,6 +116,10 @@ impl<'v> LocalSlots<'v> {
         Self(values)
     }
 
+    pub(crate) fn into_inner(self) -> Vec<ValueRef<'v>> {
+        self.0
+    }
+
     /// Gets a local variable. Returns None to indicate the variable is not yet assigned.
     pub fn get_slot(&self, slot: usize) -> Option<Value<'v>> {
         self.0[slot].get()
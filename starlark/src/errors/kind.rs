@@ -0,0 +1,132 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::{
+    environment::EnvironmentError,
+    errors::Diagnostic,
+    eval::{AssignError, EvalError, FunctionError},
+    stdlib::BuiltinError,
+    syntax::{
+        lexer::LexemeError,
+        parser::SyntaxError,
+        validate::{ArgumentDefinitionOrderError, ArgumentUseOrderError, ValidateError},
+        DialectError,
+    },
+    values::{
+        enumeration::EnumError,
+        interpolation::{FormatSpecError, StringInterpolationError},
+        ControlError, TypingError, ValueError,
+    },
+};
+
+/// The broad category of an error produced by Starlark, see [`ErrorKind::of`].
+///
+/// Lets an embedder tell a mistake in the Starlark code apart from a resource limit
+/// or a failure in its own Rust code, without matching on error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The code couldn't be parsed, or uses a feature its [`Dialect`](crate::syntax::Dialect) doesn't allow.
+    Parse,
+    /// A variable wasn't defined, or couldn't be assigned or loaded.
+    Scope,
+    /// A value had the wrong type for an operation, or a function was called with the wrong arguments.
+    Type,
+    /// A value had the right type but was invalid, e.g. dividing by zero. Includes calls to `fail()`.
+    Value,
+    /// A value was modified when it couldn't be, e.g. because it is frozen or being iterated over.
+    Control,
+    /// A resource limit was reached, e.g. too many nested calls.
+    Limit,
+    /// Not an error produced by Starlark, e.g. one from a native function or
+    /// [`FileLoader`](crate::eval::FileLoader) written by the embedder.
+    Other,
+}
+
+impl ErrorKind {
+    /// The kind of an error returned by Starlark, e.g. from
+    /// [`AstModule::parse`](crate::syntax::AstModule::parse) or
+    /// [`Evaluator::eval_module`](crate::eval::Evaluator::eval_module).
+    /// The error may be a [`Diagnostic`] or not, and may have had context added.
+    pub fn of(err: &anyhow::Error) -> Self {
+        let err = match err.downcast_ref::<Diagnostic>() {
+            Some(d) => &d.message,
+            None => err,
+        };
+        if err.is::<SyntaxError>()
+            || err.is::<LexemeError>()
+            || err.is::<DialectError>()
+            || err.is::<ValidateError>()
+            || err.is::<ArgumentDefinitionOrderError>()
+            || err.is::<ArgumentUseOrderError>()
+        {
+            Self::Parse
+        } else if let Some(e) = err.downcast_ref::<ValueError>() {
+            e.kind()
+        } else if let Some(e) = err.downcast_ref::<ControlError>() {
+            e.kind()
+        } else if let Some(e) = err.downcast_ref::<EnvironmentError>() {
+            e.kind()
+        } else if let Some(e) = err.downcast_ref::<AssignError>() {
+            e.kind()
+        } else if let Some(e) = err.downcast_ref::<BuiltinError>() {
+            e.kind()
+        } else if err.is::<FunctionError>() || err.is::<TypingError>() {
+            Self::Type
+        } else if err.is::<EvalError>()
+            || err.is::<EnumError>()
+            || err.is::<StringInterpolationError>()
+            || err.is::<FormatSpecError>()
+        {
+            Self::Value
+        } else {
+            Self::Other
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert;
+
+    #[test]
+    fn test_error_kind() {
+        let kind = |program: &str| ErrorKind::of(&assert::fails(program, &[]));
+        assert_eq!(kind("x = ("), ErrorKind::Parse);
+        assert_eq!(kind("def f(a, a): pass"), ErrorKind::Parse);
+        assert_eq!(kind("x = y"), ErrorKind::Scope);
+        assert_eq!(
+            kind("def f():\n    y = x\n    x = 1\nf()"),
+            ErrorKind::Scope
+        );
+        assert_eq!(kind("1 + 'a'"), ErrorKind::Type);
+        assert_eq!(kind("len(1, 2)"), ErrorKind::Type);
+        assert_eq!(kind("{[]: 1}"), ErrorKind::Type);
+        assert_eq!(kind("def f(x: int.type): pass\nf('a')"), ErrorKind::Type);
+        assert_eq!(kind("1 // 0"), ErrorKind::Value);
+        assert_eq!(kind("[1][2]"), ErrorKind::Value);
+        assert_eq!(kind("fail('oops')"), ErrorKind::Value);
+        assert_eq!(kind("'a'.index('b')"), ErrorKind::Value);
+        assert_eq!(kind("a, b = [1]"), ErrorKind::Value);
+        assert_eq!(
+            kind("xs = [1]\nfor x in xs: xs.append(x)"),
+            ErrorKind::Control
+        );
+        assert_eq!(kind("def f(): f()\nf()"), ErrorKind::Limit);
+        assert_eq!(ErrorKind::of(&anyhow::anyhow!("mine")), ErrorKind::Other);
+    }
+}
@@ -29,6 +29,10 @@ use std::{
     sync::Arc,
 };
 
+mod kind;
+
+pub use kind::ErrorKind;
+
 /// An error plus its origination location and call stack.
 ///
 /// The underlying [`message`](Diagnostic::message) is an [`anyhow::Error`].
@@ -87,6 +91,18 @@ impl Diagnostic {
         Self::modify(message.into(), |d| d.set_span(span, codemap))
     }
 
+    /// Convert an error to a [`Diagnostic`], without a span if it didn't have one.
+    pub(crate) fn from_anyhow(err: anyhow::Error) -> Diagnostic {
+        match err.downcast::<Diagnostic>() {
+            Ok(d) => d,
+            Err(message) => Diagnostic {
+                message,
+                span: None,
+                call_stack: Vec::new(),
+            },
+        }
+    }
+
     /// Modify an error by attaching diagnostic information to it - e.g. `span`/`call_stack`.
     /// If given an [`anyhow::Error`] which is a [`Diagnostic`], it will add the information to the
     /// existing [`Diagnostic`]. If not, it will wrap the error in [`Diagnostic`].
@@ -108,6 +124,11 @@ impl Diagnostic {
         }
     }
 
+    /// The kind of the underlying [`message`](Diagnostic::message), see [`ErrorKind::of`].
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::of(&self.message)
+    }
+
     /// Set the [`Diagnostic::span`] field, unless it's already been set.
     pub fn set_span(&mut self, span: Span, codemap: Arc<CodeMap>) {
         if self.span.is_none() {
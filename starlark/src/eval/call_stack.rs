@@ -29,7 +29,8 @@ use crate::{
     errors::Frame,
     values::{ControlError, Value, Walker},
 };
-use std::{cell::Cell, fmt, fmt::Debug, sync::Arc};
+use gazebo::prelude::*;
+use std::{cell::Cell, collections::HashMap, fmt, fmt::Debug, sync::Arc};
 
 // A value akin to Frame, but can be created cheaply, since it doesn't resolve
 // anything in advance.
@@ -68,6 +69,9 @@ impl Debug for CheapFrame<'_> {
 #[derive(Debug, Default)]
 pub(crate) struct CallStack<'v> {
     stack: Vec<CheapFrame<'v>>,
+    // The number of times each function is on the stack, keyed by its pointer,
+    // so checking for recursion doesn't need to search the stack
+    counts: HashMap<usize, usize>,
 }
 
 // At 50 we see the C stack overflowing, so limit to 40 (which seems quite
@@ -85,6 +89,7 @@ impl<'v> CallStack<'v> {
         if self.stack.len() > MAX_CALLSTACK_RECURSION {
             return Err(ControlError::TooManyRecursionLevel.into());
         }
+        *self.counts.entry(function.ptr_value()).or_default() += 1;
         self.stack.push(CheapFrame { function, location });
         Ok(())
     }
@@ -95,7 +100,27 @@ impl<'v> CallStack<'v> {
         assert!(
             old.is_some(),
             "CallStack.pop() called without preceding push()"
-        )
+        );
+        let key = old.unwrap().function.ptr_value();
+        let count = self.counts.get_mut(&key).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(&key);
+        }
+    }
+
+    /// Does the function at the top of the stack also occur further down the stack.
+    pub(crate) fn is_recursive(&self) -> bool {
+        self.stack
+            .last()
+            .map_or(false, |x| self.counts[&x.function.ptr_value()] > 1)
+    }
+
+    /// Is `function` the function at the top of the stack, i.e. the one running.
+    pub(crate) fn is_top(&self, function: Value<'v>) -> bool {
+        self.stack
+            .last()
+            .map_or(false, |x| x.function.ptr_eq(function))
     }
 
     /// The location at the top of the stack. May be `None` if
@@ -105,10 +130,21 @@ impl<'v> CallStack<'v> {
         self.stack.last()?.resolve_location()
     }
 
+    /// Like [`top_location`](CallStack::top_location), but without resolving the [`Span`].
+    pub(crate) fn top_span(&self) -> Option<(Span, Arc<CodeMap>)> {
+        let (codemap, span) = self.stack.last()?.location.as_ref()?;
+        Some((*span, codemap.dupe()))
+    }
+
     pub(crate) fn walk(&mut self, walker: &Walker<'v>) {
         for x in self.stack.iter_mut() {
             walker.walk(&mut x.function);
         }
+        // The functions may have moved, so their pointers have changed
+        self.counts.clear();
+        for x in &self.stack {
+            *self.counts.entry(x.function.ptr_value()).or_default() += 1;
+        }
     }
 
     pub fn to_diagnostic_frames(&self) -> Vec<Frame> {
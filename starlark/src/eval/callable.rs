@@ -0,0 +1,168 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Calling frozen functions from Rust, see [`FrozenCallable`].
+
+use crate::{
+    environment::{EnvironmentError, FrozenModule, Globals, Module},
+    eval::Evaluator,
+    values::{OwnedFrozenValue, Value},
+};
+use gazebo::prelude::*;
+use once_cell::sync::Lazy;
+
+// Code is only compiled against the globals, and a frozen function has already been compiled
+static NO_GLOBALS: Lazy<Globals> = Lazy::new(Globals::new);
+
+/// A frozen function, e.g. a `def` exported by a [`FrozenModule`], which can be called
+/// from Rust without setting up an [`Evaluator`]. A [`FrozenCallable`] is [`Send`] and [`Sync`],
+/// so can be called from many threads at once, each call allocating in its own [`Module`].
+///
+/// ```
+/// use starlark::environment::{Globals, Module};
+/// use starlark::eval::{Evaluator, FrozenCallable};
+/// use starlark::syntax::{AstModule, Dialect};
+/// use gazebo::prelude::*;
+///
+/// let module = Module::new();
+/// let globals = Globals::standard();
+/// let mut eval = Evaluator::new(&module, &globals);
+/// let program = "def greet(name, greeting = 'Hello'): return greeting + ' ' + name";
+/// let ast = AstModule::parse("greet.star", program.to_owned(), &Dialect::Standard).unwrap();
+/// eval.eval_module(ast).unwrap();
+/// let greet = FrozenCallable::from_module(&module.freeze(), "greet").unwrap();
+///
+/// let threads = (0..4).map(|i| {
+///     let greet = greet.dupe();
+///     std::thread::spawn(move || {
+///         let module = Module::new();
+///         let name = module.heap().alloc(format!("thread {}", i));
+///         let hi = module.heap().alloc("Hi");
+///         let res = greet.invoke(&module, &[name], &[("greeting", hi)]).unwrap();
+///         res.unpack_str().unwrap().to_owned()
+///     })
+/// });
+/// let threads = threads.collect::<Vec<_>>();
+/// let res = threads.into_iter().map(|x| x.join().unwrap()).collect::<Vec<_>>();
+/// assert_eq!(res, ["Hi thread 0", "Hi thread 1", "Hi thread 2", "Hi thread 3"]);
+/// ```
+#[derive(Debug, Clone, Dupe)]
+pub struct FrozenCallable(OwnedFrozenValue);
+
+impl FrozenCallable {
+    /// Wrap a frozen function. Calling a value which isn't a function fails when invoked.
+    pub fn new(function: OwnedFrozenValue) -> Self {
+        Self(function)
+    }
+
+    /// The variable `name` of `module`, failing if it isn't defined.
+    pub fn from_module(module: &FrozenModule, name: &str) -> anyhow::Result<Self> {
+        match module.get(name) {
+            Some(x) => Ok(Self(x)),
+            None => Err(EnvironmentError::VariableNotFound(name.to_owned()).into()),
+        }
+    }
+
+    /// The function being called.
+    pub fn function(&self) -> &OwnedFrozenValue {
+        &self.0
+    }
+
+    /// Call the function with `positional` and `named` arguments, returning the result.
+    /// The arguments and any values the function creates are allocated in `module`,
+    /// which is usually new for each call, and only used by a single thread.
+    pub fn invoke<'v>(
+        &self,
+        module: &'v Module,
+        positional: &[Value<'v>],
+        named: &[(&str, Value<'v>)],
+    ) -> anyhow::Result<Value<'v>> {
+        let mut eval = Evaluator::new(module, &NO_GLOBALS);
+        self.invoke_with(&mut eval, positional, named)
+    }
+
+    /// Like [`invoke`](FrozenCallable::invoke), but using an [`Evaluator`] set up by the
+    /// caller, e.g. with a [print handler](Evaluator::set_print_handler) or
+    /// [extra values](Evaluator::set_extra) for native functions to use.
+    pub fn invoke_with<'v>(
+        &self,
+        eval: &mut Evaluator<'v, '_>,
+        positional: &[Value<'v>],
+        named: &[(&str, Value<'v>)],
+    ) -> anyhow::Result<Value<'v>> {
+        let function = self.0.owned_value(eval.module_env);
+        eval.eval_function(function, positional, named)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert::Assert;
+    use std::thread;
+
+    #[test]
+    fn test_frozen_callable() {
+        let mut a = Assert::new();
+        a.module(
+            "lib.star",
+            r#"
+def append(xs, x):
+    xs.append(x)
+    return xs
+def fail_on(x):
+    if x == 3:
+        fail("three")
+    return x
+"#,
+        );
+        let append = FrozenCallable::new(a.pass("load('lib.star', 'append')\nappend"));
+        let threads = (0..8)
+            .map(|i| {
+                let append = append.dupe();
+                thread::spawn(move || {
+                    let module = Module::new();
+                    let xs = module.heap().alloc(vec![i]);
+                    let res = append
+                        .invoke(&module, &[xs], &[("x", Value::new_int(i * 2))])
+                        .unwrap();
+                    res.to_repr()
+                })
+            })
+            .collect::<Vec<_>>();
+        let res = threads
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(res[0], "[0, 0]");
+        assert_eq!(res[7], "[7, 14]");
+
+        let fail_on = FrozenCallable::new(a.pass("load('lib.star', 'fail_on')\nfail_on"));
+        let module = Module::new();
+        let err = fail_on
+            .invoke(&module, &[Value::new_int(3)], &[])
+            .unwrap_err();
+        assert!(err.to_string().contains("three"));
+        assert_eq!(
+            fail_on
+                .invoke(&module, &[Value::new_int(4)], &[])
+                .unwrap()
+                .unpack_int(),
+            Some(4)
+        );
+    }
+}
@@ -20,7 +20,10 @@
 use crate::{
     codemap::Span,
     collections::SmallMap,
-    eval::{context::Evaluator, stmt::AssignCompiled, thrw, Compiler, EvalCompiled, EvalException},
+    eval::{
+        add_iteration_site, context::Evaluator, provenance::Flow, stmt::AssignCompiled, thrw,
+        Compiler, EvalCompiled, EvalException,
+    },
     syntax::ast::{AstExpr, Clause, ForClause},
     values::{dict::Dict, Value},
 };
@@ -84,7 +87,7 @@ fn compile_clauses(
 ) -> Vec<ClauseCompiled> {
     // The first for.over is scoped before we enter the list comp
     let over_span = for_.over.span;
-    let over = compiler.expr(for_.over);
+    let over = compiler.iterable(for_.over);
 
     // Now everything else must be compiled with all the for variables in scope
     compiler.scope.add_compr(&for_.var);
@@ -112,7 +115,7 @@ fn compile_clauses(
             Some(f) => {
                 let over_span = f.over.span;
                 res.push(ClauseCompiled {
-                    over: compiler.expr(f.over),
+                    over: compiler.iterable(f.over),
                     var: compiler.assign(f.var),
                     over_span,
                     ifs,
@@ -185,17 +188,20 @@ fn eval_one_dimensional_comprehension_dict(
             // println!("eval1 {:?} {:?}", ***e, clauses);
             let iterable = (c.over)(context)?;
             let freeze_for_iteration = iterable.get_aref();
-            'f: for i in &thrw(iterable.iterate(context.heap), c.over_span, context)? {
-                (c.var)(i, context)?;
-                for ifc in &c.ifs {
-                    if !ifc(context)?.to_bool() {
-                        continue 'f;
+            let res: Result<(), EvalException> = try {
+                'f: for i in &thrw(iterable.iterate(context.heap), c.over_span, context)? {
+                    context.propagate_provenance(Flow::Container, i, &[iterable]);
+                    (c.var)(i, context)?;
+                    for ifc in &c.ifs {
+                        if !ifc(context)?.to_bool() {
+                            continue 'f;
+                        }
                     }
+                    rest(accumulator, context)?;
                 }
-                rest(accumulator, context)?;
-            }
+            };
             mem::drop(freeze_for_iteration);
-            Ok(())
+            res.map_err(|e| add_iteration_site(e, iterable, c.over_span, context))
         }
     } else {
         add
@@ -220,17 +226,20 @@ fn eval_one_dimensional_comprehension_list(
             // println!("eval1 {:?} {:?}", ***e, clauses);
             let iterable = (c.over)(context)?;
             let freeze_for_iteration = iterable.get_aref();
-            'f: for i in &thrw(iterable.iterate(context.heap), c.over_span, context)? {
-                (c.var)(i, context)?;
-                for ifc in &c.ifs {
-                    if !ifc(context)?.to_bool() {
-                        continue 'f;
+            let res: Result<(), EvalException> = try {
+                'f: for i in &thrw(iterable.iterate(context.heap), c.over_span, context)? {
+                    context.propagate_provenance(Flow::Container, i, &[iterable]);
+                    (c.var)(i, context)?;
+                    for ifc in &c.ifs {
+                        if !ifc(context)?.to_bool() {
+                            continue 'f;
+                        }
                     }
+                    rest(accumulator, context)?;
                 }
-                rest(accumulator, context)?;
-            }
+            };
             mem::drop(freeze_for_iteration);
-            Ok(())
+            res.map_err(|e| add_iteration_site(e, iterable, c.over_span, context))
         }
     } else {
         add
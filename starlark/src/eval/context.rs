@@ -19,14 +19,24 @@ pub use crate::eval::file_loader::FileLoader;
 use crate::{
     codemap::{CodeMap, Span, SpanLoc},
     environment::{
-        slots::LocalSlots, EnvironmentError, FrozenModuleRef, FrozenModuleValue, Globals, Module,
+        slots::LocalSlots, EnvironmentError, FrozenModule, FrozenModuleRef, FrozenModuleValue,
+        Globals, Module,
     },
     errors::{Diagnostic, Frame},
-    eval::call_stack::CallStack,
-    values::{FrozenHeap, Heap, Value, ValueRef, Walker},
+    eval::{
+        call_stack::CallStack,
+        memoize::{MemoizeCache, MemoizeStats},
+        provenance::{Flow, Provenance, ProvenancePolicy},
+    },
+    values::{
+        label::{BazelLabelSyntax, LabelSyntax},
+        FrozenHeap, Heap, Value, ValueRef, Walker,
+    },
 };
 use gazebo::any::AnyLifetime;
-use std::{mem, sync::Arc};
+#[cfg(feature = "time")]
+use std::time::SystemTime;
+use std::{any::TypeId, collections::HashMap, mem, sync::Arc};
 
 /// Holds everything about an ongoing evaluation (local variables, globals, module resolution etc).
 pub struct Evaluator<'v, 'a> {
@@ -49,10 +59,16 @@ pub struct Evaluator<'v, 'a> {
     pub(crate) call_stack: CallStack<'v>,
     // How we deal with a `load` function.
     pub(crate) loader: Option<&'a mut dyn FileLoader>,
+    // Modules whose exported variables are implicitly in scope, as set by `set_prelude`
+    pub(crate) prelude: Vec<FrozenModule>,
     // The codemap that corresponds to this module.
     pub(crate) codemap: Arc<CodeMap>,
     // Should we enable profiling or not
     pub(crate) profiling: bool,
+    // The origins of values, if enabled by `enable_provenance`
+    provenance: Option<Provenance>,
+    // The results of calls to functions wrapped with `memoize`
+    pub(crate) memoize: MemoizeCache<'v>,
     // Is GC disabled for some reason
     pub(crate) disable_gc: bool,
     // Size of the heap when we last performed a GC
@@ -61,16 +77,36 @@ pub struct Evaluator<'v, 'a> {
     pub(crate) heap: &'v Heap,
     // Should we do runtime checking of types (defaults to true)
     pub(crate) check_types: bool,
+    // Can lists and dicts share a frozen value until written (defaults to true)
+    pub(crate) thaw_on_write: bool,
+    // Are strings measured in bytes rather than characters, as set by the dialect
+    // of the code currently running
+    pub(crate) byte_strings: bool,
+    // Do dictionary methods return views rather than lists, as set by the dialect
+    // of the code currently running
+    pub(crate) dict_views: bool,
+    // Where `print` sends its output, if `None` then to stdout
+    print_handler: Option<&'a dyn Fn(&str)>,
+    // The result of `time.now()`, as set by `set_current_time`
+    #[cfg(feature = "time")]
+    current_time: Option<SystemTime>,
+    // How `Label()` parses labels, as set by `set_label_syntax`
+    label_syntax: Option<&'a dyn LabelSyntax>,
+    // The value of the expression `eval_module_at` is looking for, once it has been evaluated
+    pub(crate) watched: Option<Value<'v>>,
     /// Called on every statement with the [`Span`] and a reference to the containing [`Evaluator`].
     /// A list of all possible statements can be obtained in advance by
     /// [`AstModule::stmt_locations`](crate::syntax::AstModule::stmt_locations).
     pub on_stmt: Option<&'a dyn Fn(Span, &mut Evaluator<'v, 'a>)>,
     /// Field that can be used for any purpose you want (can store types you define).
     /// Typically accessed via native functions you also define.
+    /// To store several values, use [`set_extra`](Evaluator::set_extra) instead.
     pub extra: Option<&'a dyn AnyLifetime<'a>>,
     /// Field that can be used for any purpose you want (can store heap-resident [`Value<'v>`]).
     /// If this value is used, garbage collection is disabled.
     pub extra_v: Option<&'a dyn AnyLifetime<'v>>,
+    // Values stored by `set_extra`, keyed by their type
+    extras: HashMap<TypeId, &'a dyn AnyLifetime<'a>>,
 }
 impl<'v, 'a> Evaluator<'v, 'a> {
     /// Crate a new [`Evaluator`] specifying the [`Module`] used for module variables,
@@ -89,15 +125,27 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             local_variables_stack: Vec::new(),
             globals,
             loader: None, // TODO: Implement Default for CodeMap
+            prelude: Vec::new(),
             codemap: Arc::new(CodeMap::new(String::new(), String::new())), // Will be replaced before it is used
             extra: None,
             extra_v: None,
+            extras: HashMap::new(),
             last_heap_size: 0,
             disable_gc: false,
             profiling: false,
+            provenance: None,
+            memoize: MemoizeCache::default(),
             check_types: true,
+            thaw_on_write: true,
+            byte_strings: false,
+            dict_views: false,
             heap: env.heap(),
             on_stmt: None,
+            print_handler: None,
+            #[cfg(feature = "time")]
+            current_time: None,
+            label_syntax: None,
+            watched: None,
         }
     }
 
@@ -108,6 +156,60 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.disable_gc = true;
     }
 
+    /// Make a value available to native functions for the rest of the evaluation,
+    /// where it can be retrieved by type with [`get_extra`](Evaluator::get_extra).
+    /// Replaces any value previously set with the same type.
+    pub fn set_extra<T: AnyLifetime<'a>>(&mut self, x: &'a T) {
+        self.extras.insert(T::static_type_id(), x);
+    }
+
+    /// Obtain the value of type `T` given to [`set_extra`](Evaluator::set_extra),
+    /// or [`None`] if there isn't one.
+    pub fn get_extra<T: AnyLifetime<'a>>(&self) -> Option<&'a T> {
+        let x: &'a dyn AnyLifetime<'a> = *self.extras.get(&T::static_type_id())?;
+        x.downcast_ref::<T>()
+    }
+
+    /// Send the output of `print` to `handler`, one call per `print`, rather than to stdout.
+    /// Useful to capture the output of each evaluation separately.
+    pub fn set_print_handler(&mut self, handler: &'a dyn Fn(&str)) {
+        self.print_handler = Some(handler);
+    }
+
+    pub(crate) fn print(&self, text: &str) {
+        match self.print_handler {
+            Some(handler) => handler(text),
+            None => println!("{}", text),
+        }
+    }
+
+    /// Set the time returned by `time.now()`, from the
+    /// [`Time`](crate::environment::LibraryExtension::Time) extension, which is the same
+    /// throughout the evaluation. If not set, `time.now()` fails, so code can only depend on
+    /// the current time when the embedder chooses, e.g. passing the start time of a build.
+    /// Requires the `time` cargo feature.
+    #[cfg(feature = "time")]
+    pub fn set_current_time(&mut self, now: SystemTime) {
+        self.current_time = Some(now);
+    }
+
+    #[cfg(feature = "time")]
+    pub(crate) fn current_time(&self) -> Option<SystemTime> {
+        self.current_time
+    }
+
+    /// Set how labels are parsed by `Label()`, from the
+    /// [`LabelType`](crate::environment::LibraryExtension::LabelType) extension, and written
+    /// by `str()`, so labels use the syntax of the embedding build system.
+    /// If not set, labels use [`BazelLabelSyntax`].
+    pub fn set_label_syntax(&mut self, syntax: &'a dyn LabelSyntax) {
+        self.label_syntax = Some(syntax);
+    }
+
+    pub(crate) fn label_syntax(&self) -> &'a dyn LabelSyntax {
+        self.label_syntax.unwrap_or(&BazelLabelSyntax)
+    }
+
     /// Set the [`FileLoader`] used to resolve `load()` statements.
     /// A list of all load statements can be obtained through
     /// [`AstModule::loads`](crate::syntax::AstModule::loads).
@@ -115,7 +217,16 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.loader = Some(loader);
     }
 
-    /// Enable profiling, allowing [`Heap::write_profile`] to be used.
+    /// Set the modules whose exported variables are in scope for all code evaluated by this
+    /// [`Evaluator`], as though every file started by loading them, e.g. the symbols Bazel
+    /// makes available in every `BUILD` file. Variables defined by the code take priority over
+    /// the prelude, which takes priority over the [`Globals`]. If several prelude modules export
+    /// the same name, the last one wins. Names are resolved when the code is compiled.
+    pub fn set_prelude(&mut self, prelude: &[FrozenModule]) {
+        self.prelude = prelude.to_vec();
+    }
+
+    /// Enable profiling, allowing [`Heap::write_profile`] and [`Heap::profile_report`] to be used.
     /// Has the side effect of disabling garbage-collection.
     pub fn enable_profiling(&mut self) {
         self.profiling = true;
@@ -124,6 +235,65 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.disable_gc = true;
     }
 
+    /// Track which inputs each value was computed from, for example to check that no value
+    /// derived from a secret reaches a log. Mark the inputs with
+    /// [`add_provenance`](Evaluator::add_provenance), usually from a native function which
+    /// produces them, and the origins spread to the values computed from them as described by
+    /// `policy`. Query the result with [`provenance`](Evaluator::provenance).
+    ///
+    /// The tracking is coarse: a value gets the origins of everything it was derived from,
+    /// whether or not that was used. Ints, bools, `None` and frozen values are shared by all
+    /// code using them, so can't have origins, and values lose their origins when frozen.
+    pub fn enable_provenance(&mut self, policy: ProvenancePolicy) {
+        self.provenance = Some(Provenance::new(policy));
+    }
+
+    /// Record that `value` came from `origin`, if [`enable_provenance`](Evaluator::enable_provenance)
+    /// has been called. Does nothing for values which can't have origins, e.g. ints.
+    pub fn add_provenance(&mut self, value: Value<'v>, origin: &str) {
+        if let Some(p) = &mut self.provenance {
+            p.add(self.heap, value, origin);
+        }
+    }
+
+    /// The origins of `value`, sorted, see [`enable_provenance`](Evaluator::enable_provenance).
+    pub fn provenance(&self, value: Value<'v>) -> Vec<String> {
+        match &self.provenance {
+            Some(p) => p.get(self.heap, value),
+            None => Vec::new(),
+        }
+    }
+
+    // Give `result` the origins of `sources`, if the policy allows for `flow`.
+    pub(crate) fn propagate_provenance(
+        &mut self,
+        flow: Flow,
+        result: Value<'v>,
+        sources: &[Value<'v>],
+    ) {
+        if let Some(p) = &mut self.provenance {
+            p.propagate(self.heap, flow, result, sources);
+        }
+    }
+
+    pub(crate) fn tracks_provenance(&self) -> bool {
+        self.provenance.is_some()
+    }
+
+    /// How many calls to functions wrapped with `memoize` were answered from the cache,
+    /// see [`LibraryExtension::Memoize`](crate::environment::LibraryExtension::Memoize).
+    pub fn memoize_stats(&self) -> MemoizeStats {
+        self.memoize.stats()
+    }
+
+    /// Always copy lists and dicts immediately, rather than sharing the storage of a frozen value
+    /// until they are written, for code compiled and run from now onwards. See
+    /// [`ThawOnWriteStats`](crate::values::ThawOnWriteStats) for when sharing happens, and
+    /// [`Heap::thaw_on_write_stats`] to measure whether it is worthwhile.
+    pub fn disable_thaw_on_write(&mut self) {
+        self.thaw_on_write = false;
+    }
+
     /// Obtain the current call-stack, suitable for use with [`Diagnostic`].
     pub fn call_stack(&self) -> Vec<Frame> {
         self.call_stack.to_diagnostic_frames()
@@ -135,6 +305,14 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.call_stack.top_location()
     }
 
+    /// The location of the call to the function currently running, in the form used by
+    /// [`Diagnostic::span`]. Lets a native function point at the code which called it.
+    /// May be [`None`] if the function was called from Rust, e.g. by
+    /// [`eval_function`](Evaluator::eval_function).
+    pub fn call_span(&self) -> Option<(Span, Arc<CodeMap>)> {
+        self.call_stack.top_span()
+    }
+
     /// Given a [`Span`] resolve it to a concrete [`SpanLoc`] using
     /// whatever module is currently at the top of the stack.
     /// This function can be used in conjunction with [`on_stmt`](Evaluator::on_stmt).
@@ -157,6 +335,10 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         // Must always call .pop regardless
         let res = within(self).map_err(|e| {
             Diagnostic::modify(e, |d: &mut Diagnostic| {
+                // Errors from native functions have no span, so point at the call
+                if let Some((span, codemap)) = self.call_stack.top_span() {
+                    d.set_span(span, codemap);
+                }
                 // Make sure we capture the call_stack before popping things off it
                 d.set_call_stack(|| self.call_stack.to_diagnostic_frames());
             })
@@ -197,16 +379,17 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         // Restore them all back
         self.codemap = old_codemap;
         self.module_variables = old_module_variables;
-        self.local_variables = self.local_variables_stack.pop().unwrap();
+        let locals = mem::replace(
+            &mut self.local_variables,
+            self.local_variables_stack.pop().unwrap(),
+        );
+        self.heap.free_slots(locals.into_inner());
         self.is_module_scope = old_is_module_scope;
         res
     }
 
     pub(crate) fn walk(&mut self, walker: &Walker<'v>) {
-        let mut roots = self.module_env.slots().get_slots_mut();
-        for x in roots.iter_mut() {
-            walker.walk(x);
-        }
+        self.module_env.slots().walk(walker);
         for locals in self
             .local_variables_stack
             .iter_mut()
@@ -215,6 +398,10 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             locals.walk(walker);
         }
         self.call_stack.walk(walker);
+        self.memoize.walk(walker);
+        if let Some(x) = &mut self.watched {
+            walker.walk(x);
+        }
     }
 
     /// The active heap where [`Value`]s are allocated.
@@ -18,24 +18,24 @@
 //! Implementation of `def`.
 
 use crate::{
-    codemap::CodeMap,
+    codemap::{CodeMap, Span, SpanLoc},
     environment::{slots::LocalSlots, FrozenModuleValue},
     eval::{
         context::Evaluator,
-        parameters::{ParametersCollect, ParametersSpec},
+        parameters::{Parameter, ParametersCollect, ParametersSpec},
         scope::ScopeNames,
-        Compiler, EvalCompiled, EvalException,
+        thrw, Compiler, EvalCompiled, EvalException,
     },
     syntax::ast::{AstExpr, AstParameter, AstStmt, Parameter},
     values::{
         function::{FunctionInvoker, FunctionInvokerInner, FUNCTION_TYPE},
-        AllocValue, ComplexValue, Freezer, FrozenValue, Heap, SimpleValue, StarlarkValue, Value,
-        ValueLike, ValueRef, Walker,
+        AllocValue, ComplexValue, ControlError, Freezer, FrozenValue, Heap, SimpleValue,
+        StarlarkValue, Value, ValueError, ValueLike, ValueRef, Walker,
     },
 };
 use derivative::Derivative;
 use gazebo::{cell::ARef, prelude::*};
-use std::sync::Arc;
+use std::{mem, sync::Arc};
 
 enum ParameterCompiled {
     Normal(String, Option<EvalCompiled>),
@@ -75,23 +75,59 @@ struct DefInfo {
     // after the parameters are evaluated.
     #[derivative(Debug = "ignore")]
     body: EvalCompiled,
+    // Whether the dialect allowed this function to call itself
+    enable_recursion: bool,
+    // Whether the dialect measured strings in bytes rather than characters
+    enable_byte_strings: bool,
+    // Whether the dialect returned views from dictionary methods
+    enable_dict_views: bool,
+    // The docstring at the start of the body, if there is one
+    docstring: Option<String>,
+    // The span of the whole `def` statement or `lambda` expression
+    span: Span,
 }
 
 impl Compiler<'_> {
+    // A type annotation, which is checked to be a valid type when it is evaluated,
+    // so mistakes are reported at the annotation rather than when the function is called.
+    fn type_annotation(&mut self, t: AstExpr) -> EvalCompiled {
+        let span = t.span;
+        let t = self.expr(t);
+        box move |context| {
+            let v = t(context)?;
+            thrw(v.validate_type(), span, context)?;
+            Ok(v)
+        }
+    }
+
+    fn type_annotation_opt(&mut self, t: Option<Box<AstExpr>>) -> Option<EvalCompiled> {
+        match t {
+            None => None,
+            Some(t) => Some(self.type_annotation(*t)),
+        }
+    }
+
     fn parameter(&mut self, x: AstParameter) -> ParameterCompiled {
         match x.node {
-            Parameter::Normal(x, t) => ParameterCompiled::Normal(x.node, self.expr_opt(t)),
-            Parameter::WithDefaultValue(x, t, v) => {
-                ParameterCompiled::WithDefaultValue(x.node, self.expr_opt(t), self.expr(*v))
+            Parameter::Normal(x, t) => {
+                ParameterCompiled::Normal(x.node, self.type_annotation_opt(t))
             }
+            Parameter::WithDefaultValue(x, t, v) => ParameterCompiled::WithDefaultValue(
+                x.node,
+                self.type_annotation_opt(t),
+                self.expr(*v),
+            ),
             Parameter::NoArgs => ParameterCompiled::NoArgs,
-            Parameter::Args(x, t) => ParameterCompiled::Args(x.node, self.expr_opt(t)),
-            Parameter::KWArgs(x, t) => ParameterCompiled::KWArgs(x.node, self.expr_opt(t)),
+            Parameter::Args(x, t) => ParameterCompiled::Args(x.node, self.type_annotation_opt(t)),
+            Parameter::KWArgs(x, t) => {
+                ParameterCompiled::KWArgs(x.node, self.type_annotation_opt(t))
+            }
         }
     }
 
     pub fn function(
         &mut self,
+        span: Span,
         name: &str,
         params: Vec<AstParameter>,
         return_type: Option<Box<AstExpr>>,
@@ -103,14 +139,23 @@ impl Compiler<'_> {
         // The parameters run in the scope of the parent, so compile them with the outer
         // scope
         let params = params.into_map(|x| self.parameter(x));
-        let return_type = self.expr_opt(return_type);
+        let return_type = self.type_annotation_opt(return_type);
+        let docstring = suite.docstring().map(str::to_owned);
 
         self.scope
             .enter_def(params.iter().flat_map(ParameterCompiled::name), &suite);
         let body = self.stmt(suite);
         let scope_names = self.scope.exit_def();
 
-        let info = Arc::new(DefInfo { scope_names, body });
+        let info = Arc::new(DefInfo {
+            scope_names,
+            body,
+            enable_recursion: self.dialect.enable_recursion,
+            enable_byte_strings: self.dialect.enable_byte_strings,
+            enable_dict_views: self.dialect.enable_dict_views,
+            docstring,
+            span,
+        });
 
         fn run<'v>(
             x: &Option<EvalCompiled>,
@@ -222,8 +267,33 @@ impl<T1, T2> DefGen<T1, T2> {
     pub(crate) fn scope_names(&self) -> &ScopeNames {
         &self.stmt.scope_names
     }
+
+    /// Where this function was defined.
+    pub(crate) fn location(&self) -> SpanLoc {
+        self.codemap.look_up_span(self.stmt.span)
+    }
+
+    fn get_attr_impl<'v>(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        match attribute {
+            DOC_ATTR => Ok(match &self.stmt.docstring {
+                Some(x) => heap.alloc(x.as_str()),
+                None => Value::new_none(),
+            }),
+            LOCATION_ATTR => {
+                let loc = self.location();
+                Ok(heap.alloc(format!("{}:{}", loc.file.name(), loc.begin.line + 1)))
+            }
+            _ => ValueError::unsupported_owned(FUNCTION_TYPE, &format!(".{}", attribute), None),
+        }
+    }
 }
 
+// The attribute holding the docstring of a function, as in Python
+const DOC_ATTR: &str = "__doc__";
+// The attribute holding the `file:line` where a function was defined
+const LOCATION_ATTR: &str = "__location__";
+const ATTRS: &[&str] = &[DOC_ATTR, LOCATION_ATTR];
+
 impl SimpleValue for FrozenDef {}
 
 impl<'v> ComplexValue<'v> for Def<'v> {
@@ -278,15 +348,38 @@ impl<'v> StarlarkValue<'v> for FrozenDef {
         collector.push_str(&self.parameters.signature());
     }
 
+    fn signature(&self) -> Option<String> {
+        Some(self.parameters.signature())
+    }
+
+    fn parameters(&self) -> Option<Vec<Parameter<'v>>> {
+        Some(self.parameters.parameters())
+    }
+
+    fn get_attr(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.get_attr_impl(attribute, heap)
+    }
+
+    fn has_attr(&self, attribute: &str) -> bool {
+        ATTRS.contains(&attribute)
+    }
+
+    fn dir_attr(&self) -> Vec<String> {
+        ATTRS.map(|x| (*x).to_owned())
+    }
+
     fn new_invoker<'a>(
         &self,
         me: Value<'v>,
-        _heap: &'v Heap,
+        heap: &'v Heap,
     ) -> anyhow::Result<FunctionInvoker<'v, 'a>> {
         Ok(FunctionInvoker(FunctionInvokerInner::DefFrozen(
-            DefInvokerFrozen::new(ARef::map(me.get_aref(), |x| {
-                x.as_dyn_any().downcast_ref::<Self>().unwrap()
-            })),
+            DefInvokerFrozen::new(
+                ARef::map(me.get_aref(), |x| {
+                    x.as_dyn_any().downcast_ref::<Self>().unwrap()
+                }),
+                heap,
+            ),
         )))
     }
 }
@@ -302,15 +395,36 @@ impl<'v> StarlarkValue<'v> for Def<'v> {
         collector.push_str(&self.parameters.signature());
     }
 
+    fn signature(&self) -> Option<String> {
+        Some(self.parameters.signature())
+    }
+
+    fn parameters(&self) -> Option<Vec<Parameter<'v>>> {
+        Some(self.parameters.parameters())
+    }
+
+    fn get_attr(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.get_attr_impl(attribute, heap)
+    }
+
+    fn has_attr(&self, attribute: &str) -> bool {
+        ATTRS.contains(&attribute)
+    }
+
+    fn dir_attr(&self) -> Vec<String> {
+        ATTRS.map(|x| (*x).to_owned())
+    }
+
     fn new_invoker<'a>(
         &self,
         me: Value<'v>,
-        _heap: &'v Heap,
+        heap: &'v Heap,
     ) -> anyhow::Result<FunctionInvoker<'v, 'a>> {
         Ok(FunctionInvoker(FunctionInvokerInner::Def(DefInvoker::new(
             ARef::map(me.get_aref(), |x| {
                 x.as_dyn_any().downcast_ref::<Self>().unwrap()
             }),
+            heap,
         ))))
     }
 }
@@ -340,45 +454,62 @@ impl<'v> AsValueRef<'v> for ValueRef<'v> {
 }
 
 impl<'a, 'v, V: ValueLike<'v>, RefV: AsValueRef<'v>> DefInvokerGen<'v, 'a, V, RefV> {
-    fn new(def: ARef<'a, DefGen<V, RefV>>) -> Self {
+    fn new(def: ARef<'a, DefGen<V, RefV>>, heap: &'v Heap) -> Self {
         let slots = def.stmt.scope_names.used;
         let (def, params) = ARef::map_split(def, |x| (x, &x.parameters));
         Self {
             def,
-            collect: ParametersSpec::collect(params, slots),
+            collect: ParametersSpec::collect(params, slots, heap),
         }
     }
 
     pub fn invoke(self, context: &mut Evaluator<'v, '_>) -> anyhow::Result<Value<'v>> {
         // println!("invoking {}", self.def.stmt.name.node);
         let DefInvokerGen { collect, def } = self;
-        let slots = collect.done(context.heap)?;
-
-        if context.check_types {
-            for (i, arg_name, ty) in &def.parameter_types {
-                match slots[*i].get() {
-                    None => panic!("Not allowed optional unassigned with type annotations on them"),
-                    Some(v) => v.check_type(ty.to_value(), Some(arg_name))?,
+        // We are already on the call stack, so only recursive if we appear twice
+        if !def.stmt.enable_recursion && context.call_stack.is_recursive() {
+            return Err(ControlError::RecursionNotAllowed(def.parameters.signature()).into());
+        }
+        let mut slots = collect.done(context.heap)?;
+
+        // Each time round the loop is a call, the later ones from `return` calling this
+        // function again in tail position, which doesn't need a new frame. Calls only
+        // skip the frame when neither provenance nor profiling is being recorded.
+        let ret = loop {
+            if context.check_types {
+                for (i, arg_name, ty) in &def.parameter_types {
+                    match slots[*i].get() {
+                        None => {
+                            panic!("Not allowed optional unassigned with type annotations on them")
+                        }
+                        Some(v) => v.check_type(ty.to_value(), Some(arg_name))?,
+                    }
                 }
             }
-        }
 
-        let mut locals = LocalSlots::new(slots);
+            let mut locals = LocalSlots::new(slots);
 
-        // Copy over the parent slots
-        for ((_, me), captured) in def.stmt.scope_names.parent.iter().zip(def.captured.iter()) {
-            locals.set_slot_ref(*me, captured.to_value_ref());
-        }
-
-        let res =
-            context.with_function_context(def.module, locals, def.codemap.dupe(), |context| {
-                (def.stmt.body)(context)
-            });
+            // Copy over the parent slots
+            for ((_, me), captured) in def.stmt.scope_names.parent.iter().zip(def.captured.iter()) {
+                locals.set_slot_ref(*me, captured.to_value_ref());
+            }
 
-        let ret = match res {
-            Err(EvalException::Return(ret)) => ret,
-            Err(e) => return Err(e.into()),
-            Ok(_) => Value::new_none(),
+            let old_byte_strings =
+                mem::replace(&mut context.byte_strings, def.stmt.enable_byte_strings);
+            let old_dict_views = mem::replace(&mut context.dict_views, def.stmt.enable_dict_views);
+            let res =
+                context.with_function_context(def.module, locals, def.codemap.dupe(), |context| {
+                    (def.stmt.body)(context)
+                });
+            context.byte_strings = old_byte_strings;
+            context.dict_views = old_dict_views;
+
+            match res {
+                Err(EvalException::Return(ret)) => break ret,
+                Err(EvalException::TailCall(next)) => slots = next,
+                Err(e) => return Err(e.into()),
+                Ok(_) => break Value::new_none(),
+            }
         };
 
         if context.check_types {
@@ -398,4 +529,8 @@ impl<'a, 'v, V: ValueLike<'v>, RefV: AsValueRef<'v>> DefInvokerGen<'v, 'a, V, Re
     pub fn collect(&mut self) -> &mut ParametersCollect<'v, 'a, V> {
         &mut self.collect
     }
+
+    pub(crate) fn into_slots(self, heap: &'v Heap) -> anyhow::Result<Vec<ValueRef<'v>>> {
+        self.collect.done(heap)
+    }
 }
@@ -18,20 +18,29 @@
 //! Evaluation of an expression.
 use crate::{
     codemap::{Span, Spanned},
-    collections::{Hashed, SmallMap},
-    environment::EnvironmentError,
+    collections::SmallMap,
+    environment::{EnvironmentError, Globals},
     errors::Diagnostic,
-    eval::{context::Evaluator, scope::Slot, thrw, Compiler, EvalCompiled, EvalException},
-    syntax::ast::{Argument, AstExpr, AstLiteral, BinOp, Expr, Stmt, Visibility},
+    eval::{
+        context::Evaluator,
+        provenance::Flow,
+        scope::{closest_name, Slot},
+        thrw, Compiler, EvalCompiled, EvalException, ResolvedNames, Symbol,
+    },
+    syntax::ast::{Argument, AstArgument, AstExpr, AstLiteral, BinOp, Expr, Stmt, Visibility},
     values::{
-        dict::FrozenDict, fast_string, function::WrappedMethod, list::FrozenList, FrozenHeap,
-        FrozenValue, *,
+        dict::FrozenDict,
+        fast_string,
+        function::WrappedMethod,
+        list::{FrozenList, List},
+        FrozenHeap, FrozenValue, *,
     },
 };
 use either::Either;
 use function::{FunctionInvoker, NativeAttribute};
 use gazebo::prelude::*;
-use std::{cmp::Ordering, collections::HashMap};
+use once_cell::sync::OnceCell;
+use std::{cmp::Ordering, collections::HashMap, ptr};
 use thiserror::Error;
 
 #[derive(Debug, Clone, Error)]
@@ -79,6 +88,15 @@ fn eval_slice(
 ) -> EvalCompiled {
     box move |context| {
         let collection = collection(context)?;
+        if start.is_none() && stop.is_none() && stride.is_none() && context.thaw_on_write {
+            // `x[:]` of a frozen list can share it until either is written
+            if List::from_value(collection).is_some() {
+                if let Some(res) = context.heap.alloc_copy_on_write(collection) {
+                    context.propagate_provenance(Flow::Container, res, &[collection]);
+                    return Ok(res);
+                }
+            }
+        }
         let start = match start {
             Some(ref e) => Some(e(context)?),
             None => None,
@@ -91,43 +109,208 @@ fn eval_slice(
             Some(ref e) => Some(e(context)?),
             None => None,
         };
-        thrw(
-            collection.slice(start, stop, stride, context.heap),
-            span,
-            context,
-        )
+        let res = match collection.unpack_str() {
+            Some(s) if context.byte_strings => {
+                string::byte_slice(s, start, stop, stride, context.heap)
+            }
+            _ => collection.slice(start, stop, stride, context.heap),
+        };
+        let res = thrw(res, span, context)?;
+        context.propagate_provenance(Flow::Container, res, &[collection]);
+        Ok(res)
+    }
+}
+
+// A binary operator whose result gets the origins of both operands.
+fn eval_arith(
+    span: Span,
+    l: EvalCompiled,
+    r: EvalCompiled,
+    op: impl for<'v> Fn(Value<'v>, Value<'v>, &'v Heap) -> anyhow::Result<Value<'v>>
+        + Send
+        + Sync
+        + 'static,
+) -> EvalCompiled {
+    box move |context| {
+        let l = l(context)?;
+        let r = r(context)?;
+        let res = thrw(op(l, r, context.heap), span, context)?;
+        context.propagate_provenance(Flow::Operator, res, &[l, r]);
+        Ok(res)
+    }
+}
+
+fn add<'v>(l: Value<'v>, r: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+    // Addition of string is super common and pretty cheap, so have a special case for it.
+    if let Some(ls) = l.unpack_str() {
+        if let Some(rs) = r.unpack_str() {
+            if ls.is_empty() {
+                return Ok(r);
+            } else if rs.is_empty() {
+                return Ok(l);
+            } else {
+                return Ok(heap.alloc(fast_string::append(ls, rs)));
+            }
+        }
+    }
+
+    // Written using Value::add so that Rust Analyzer doesn't think it is an error.
+    Value::add(l, r, heap)
+}
+
+// The same as adding up `xs`, which must all be strings, but allocating at most once.
+fn add_strings<'v>(xs: &[Value<'v>], context: &mut Evaluator<'v, '_>) -> Value<'v> {
+    let strs = xs.map(|x| x.unpack_str().unwrap());
+    let res = match strs.iter().filter(|x| !x.is_empty()).count() {
+        // Like `add`, adding an empty string gives back the other string
+        0 => *xs.last().unwrap(),
+        1 => *xs.iter().find(|x| x.unpack_str() != Some("")).unwrap(),
+        _ => context.heap.alloc_str_concat(&strs),
+    };
+    context.propagate_provenance(Flow::Operator, res, xs);
+    res
+}
+
+// Evaluate `first + rest[0] + rest[1] + ...`, where each element of `rest` comes with the span
+// of the addition which adds it. Adding two strings can't fail, so runs of strings are
+// concatenated in one go, rather than allocating a string for each intermediate result.
+fn eval_addition_chain(first: EvalCompiled, rest: Vec<(Span, EvalCompiled)>) -> EvalCompiled {
+    box move |context| {
+        let mut res = first(context)?;
+        // If not empty, a run of strings whose concatenation is the real value of `res`
+        let mut strings = Vec::new();
+        for (span, r) in &rest {
+            let r = r(context)?;
+            if res.unpack_str().is_some() && r.unpack_str().is_some() {
+                if strings.is_empty() {
+                    strings.push(res);
+                }
+                strings.push(r);
+                continue;
+            }
+            if !strings.is_empty() {
+                res = add_strings(&strings, context);
+                strings.clear();
+            }
+            let l = res;
+            res = thrw(add(l, r, context.heap), *span, context)?;
+            context.propagate_provenance(Flow::Operator, res, &[l, r]);
+        }
+        if !strings.is_empty() {
+            res = add_strings(&strings, context);
+        }
+        Ok(res)
     }
 }
 
 enum ArgCompiled {
     Pos(EvalCompiled),
-    Named(String, Hashed<FrozenValue>, EvalCompiled),
+    Named(Symbol, EvalCompiled),
     Args(EvalCompiled),
     KWArgs(EvalCompiled),
 }
 
+// `name` is the name of the function at the call site, and `this` the object a method
+// is called on, both used to track provenance. `tail` is set for the call in `return f(...)`.
 fn eval_call(
     span: Span,
+    name: Option<String>,
     args: Vec<ArgCompiled>,
+    tail: bool,
 ) -> impl for<'v> Fn(
     FunctionInvoker<'v, '_>,
     Value<'v>,
+    Option<Value<'v>>,
     &mut Evaluator<'v, '_>,
 ) -> Result<Value<'v>, EvalException<'v>> {
-    move |mut invoker, function, context| {
+    let names = args
+        .iter()
+        .filter_map(|x| match x {
+            ArgCompiled::Named(name, _) => Some(name.dupe()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let cache = ResolvedNames::default();
+    move |mut invoker, function, this, context| {
+        let slots = if names.is_empty() {
+            None
+        } else {
+            invoker.resolve(&names, &cache)
+        };
+        let tracking = context.tracks_provenance();
+        let mut sources = Vec::new();
+        let mut named = 0;
         for x in &args {
+            let v = match x {
+                ArgCompiled::Pos(expr)
+                | ArgCompiled::Named(_, expr)
+                | ArgCompiled::Args(expr)
+                | ArgCompiled::KWArgs(expr) => expr(context)?,
+            };
+            if tracking {
+                sources.push(v);
+            }
             match x {
-                ArgCompiled::Pos(expr) => invoker.push_pos(expr(context)?),
-                ArgCompiled::Named(k, kk, expr) => {
-                    invoker.push_named(k, kk.to_hashed_value(), expr(context)?)
+                ArgCompiled::Pos(_) => invoker.push_pos(v),
+                ArgCompiled::Named(name, _) => {
+                    match slots {
+                        Some(slots) => invoker.push_named_slot(slots[named], name, v),
+                        None => invoker.push_named(name.name(), name.hashed_value(), v),
+                    }
+                    named += 1;
                 }
-                ArgCompiled::Args(expr) => invoker.push_args(expr(context)?, context.heap),
-                ArgCompiled::KWArgs(expr) => invoker.push_kwargs(expr(context)?),
+                ArgCompiled::Args(_) => invoker.push_args(v, context.heap),
+                ArgCompiled::KWArgs(_) => invoker.push_kwargs(v),
             }
         }
 
+        // Provenance and profiling are recorded for each call, so need a frame per call
+        if tail && !tracking && !context.profiling && context.call_stack.is_top(function) {
+            match invoker.into_def_slots(context.heap) {
+                Ok(slots) => return Err(EvalException::TailCall(thrw(slots, span, context)?)),
+                Err(inv) => invoker = inv,
+            }
+        }
         let res = invoker.invoke(function, Some(span), context);
-        thrw(res, span, context)
+        let res = thrw(res, span, context)?;
+        if tracking {
+            sources.extend(this);
+            context.propagate_provenance(Flow::Call(name.as_deref()), res, &sources);
+        }
+        Ok(res)
+    }
+}
+
+/// An inline cache for the attribute lookup at a single `x.f` callsite.
+/// The first type seen with [`StarlarkValue::get_members`] fills the cache,
+/// after which values whose type has the same members skip the hash lookup
+/// in the [`Globals`]. Other types fall back to [`Value::get_attr`].
+#[derive(Default)]
+struct AttrCache(OnceCell<(&'static Globals, Option<FrozenValue>)>);
+
+impl AttrCache {
+    /// Equivalent to [`Value::get_attr`], but consulting the cache.
+    fn get_attr<'v>(
+        &self,
+        x: Value<'v>,
+        attribute: &str,
+        heap: &'v Heap,
+    ) -> anyhow::Result<(bool, Value<'v>)> {
+        let aref = x.get_aref();
+        if let Some(members) = aref.get_members() {
+            let (cached, v) = self
+                .0
+                .get_or_init(|| (members, members.get_frozen(attribute)));
+            let v = if ptr::eq(*cached, members) {
+                *v
+            } else {
+                members.get_frozen(attribute)
+            };
+            if let Some(v) = v {
+                return Ok((true, v.to_value()));
+            }
+        }
+        aref.get_attr(attribute, heap).map(|v| (false, v))
     }
 }
 
@@ -138,19 +321,22 @@ fn eval_dot(
 ) -> impl for<'v> Fn(
     &mut Evaluator<'v, '_>,
 ) -> Result<Either<Value<'v>, WrappedMethod<'v>>, EvalException<'v>> {
+    let cache = AttrCache::default();
     move |context| {
         let left = e(context)?;
-        let (member, v) = thrw(left.get_attr(&s, context.heap), span, context)?;
-        if !member {
-            Ok(Either::Left(v))
+        let (member, v) = thrw(cache.get_attr(left, &s, context.heap), span, context)?;
+        let res = if !member {
+            v
         } else if v.get_aref().is_function() {
             // Insert self so the method see the object it is acting on
-            Ok(Either::Right(WrappedMethod::new(left, v)))
+            return Ok(Either::Right(WrappedMethod::new(left, v)));
         } else if let Some(v_attr) = v.downcast_ref::<NativeAttribute>() {
-            thrw(v_attr.call(left, context), span, context).map(Either::Left)
+            thrw(v_attr.call(left, context), span, context)?
         } else {
-            Ok(Either::Left(v))
-        }
+            v
+        };
+        context.propagate_provenance(Flow::Container, res, &[left]);
+        Ok(Either::Left(res))
     }
 }
 
@@ -252,6 +438,86 @@ impl Expr {
 }
 
 impl Compiler<'_> {
+    // Compile `left + right`, where `left` is itself an addition
+    fn addition_chain(&mut self, span: Span, mut left: AstExpr, right: AstExpr) -> EvalCompiled {
+        // a + b + c  associates as  (a + b) + c
+        let mut rest = vec![(span, right)];
+        while let Expr::Op(box l, BinOp::Addition, box r) = left.node {
+            rest.push((left.span, r));
+            left = l;
+        }
+        let first = self.expr(left);
+        let rest = rest
+            .into_iter()
+            .rev()
+            .map(|(span, x)| (span, self.expr(x)))
+            .collect();
+        eval_addition_chain(first, rest)
+    }
+
+    // Compile the call `left(args)`. If `tail`, the call is the value of a `return`, and
+    // if `left` is the function running, it is restarted instead of called again.
+    pub(crate) fn call(
+        &mut self,
+        span: Span,
+        left: AstExpr,
+        args: Vec<AstArgument>,
+        tail: bool,
+    ) -> EvalCompiled {
+        let args = args.into_map(|x| match x.node {
+            Argument::Positional(x) => ArgCompiled::Pos(self.expr(x)),
+            Argument::Named(name, value) => {
+                let name = self.symbols.intern(name.node, self.heap);
+                ArgCompiled::Named(name, self.expr(value))
+            }
+            Argument::ArgsArray(x) => ArgCompiled::Args(self.expr(x)),
+            Argument::KWArgsDict(x) => ArgCompiled::KWArgs(self.expr(x)),
+        });
+        let name = match &left.node {
+            Expr::Identifier(x) => Some(x.node.clone()),
+            Expr::Dot(_, x) => Some(x.node.clone()),
+            _ => None,
+        };
+        let call = eval_call(span, name, args, tail);
+        // A watched method needs a value, so is called like any other function
+        let watched = self.watch == Some(left.span);
+        match left.node {
+            Expr::Dot(e, s) if !watched => {
+                let e = self.expr(*e);
+                let dot = eval_dot(span, e, s.node);
+                box move |context| match dot(context)? {
+                    Either::Left(function) => {
+                        let invoker = thrw(function.new_invoker(context.heap), span, context)?;
+                        call(invoker, function, None, context)
+                    }
+                    Either::Right(wrapper) => {
+                        let invoker = thrw(wrapper.invoke(context.heap), span, context)?;
+                        let this = wrapper.get_self();
+                        call(invoker, wrapper.get_method(), Some(this), context)
+                    }
+                }
+            }
+            _ => {
+                let left = self.expr(left);
+                box move |context| {
+                    let function = left(context)?;
+                    let invoker = thrw(function.new_invoker(context.heap), span, context)?;
+                    call(invoker, function, None, context)
+                }
+            }
+        }
+    }
+
+    // A defined name similar to `name`, which isn't defined, for the error message
+    fn suggest_name(&self, name: &str) -> Option<String> {
+        let mut names = self.scope.all_names();
+        names.extend(self.globals.names());
+        for x in self.prelude {
+            names.extend(x.public_names().map(str::to_owned));
+        }
+        closest_name(name, names.iter().map(|x| x.as_str())).map(str::to_owned)
+    }
+
     pub fn expr_opt(&mut self, expr: Option<Box<AstExpr>>) -> Option<EvalCompiled> {
         match expr {
             None => None,
@@ -260,6 +526,27 @@ impl Compiler<'_> {
     }
 
     pub fn expr(&mut self, expr: AstExpr) -> EvalCompiled {
+        let span = expr.span;
+        let res = self.expr_unwatched(expr);
+        self.watch_expr(span, res)
+    }
+
+    // If the expression at `span` is being watched, record its value the first time it is
+    // evaluated. Nested expressions with the same span finish first, so the innermost wins.
+    fn watch_expr(&self, span: Span, res: EvalCompiled) -> EvalCompiled {
+        if self.watch != Some(span) {
+            return res;
+        }
+        box move |context| {
+            let v = res(context)?;
+            if context.watched.is_none() {
+                context.watched = Some(v);
+            }
+            Ok(v)
+        }
+    }
+
+    fn expr_unwatched(&mut self, expr: AstExpr) -> EvalCompiled {
         // println!("compile {}", expr.node);
         let span = expr.span;
         match expr.node {
@@ -278,18 +565,32 @@ impl Compiler<'_> {
                         box move |context| thrw(context.get_slot_module(slot, &name), span, context)
                     }
                     None => {
-                        // Must be a global, since we know all variables
-                        match self.globals.get_frozen(&name) {
+                        // Must be from the prelude or a global, since we know all variables
+                        let prelude = self
+                            .prelude
+                            .iter()
+                            .rev()
+                            .filter(|x| x.is_exported(&name))
+                            .find_map(|x| x.get(&name));
+                        let value = match prelude {
+                            Some(v) => Some(v.owned_frozen_value(self.heap)),
+                            None => self.globals.get_frozen_into(&name, self.heap),
+                        };
+                        match value {
                             Some(v) => box move |_| Ok(v.to_value()),
                             None => {
                                 let name = name.to_owned();
+                                let suggestion = self.suggest_name(&name);
                                 let codemap = self.codemap.dupe();
                                 let mk_err = move || {
-                                    Diagnostic::new(
-                                        EnvironmentError::VariableNotFound(name.clone()),
-                                        span,
-                                        codemap.dupe(),
-                                    )
+                                    let err = match &suggestion {
+                                        None => EnvironmentError::VariableNotFound(name.clone()),
+                                        Some(x) => EnvironmentError::VariableNotFoundDidYouMean(
+                                            name.clone(),
+                                            x.clone(),
+                                        ),
+                                    };
+                                    Diagnostic::new(err, span, codemap.dupe())
                                 };
                                 self.errors.push(mk_err());
                                 box move |_| Err(EvalException::Error(mk_err()))
@@ -300,27 +601,41 @@ impl Compiler<'_> {
             }
             Expr::Tuple(exprs) => {
                 let exprs = self.exprs(exprs);
-                box move |context| Ok(context.heap.alloc(tuple::Tuple::new(exprs(context)?)))
+                box move |context| {
+                    let xs = exprs(context)?;
+                    let res = context.heap.alloc_tuple(&xs);
+                    context.propagate_provenance(Flow::Container, res, &xs);
+                    Ok(res)
+                }
             }
             Expr::Lambda(params, box inner) => {
                 let suite = Spanned {
                     span: expr.span,
                     node: Stmt::Return(Some(inner)),
                 };
-                self.function("lambda", params, None, suite)
+                self.function(expr.span, "lambda", params, None, suite)
             }
             Expr::List(exprs) => {
                 if let Some(lits) = exprs
                     .iter()
                     .map(|e| e.unpack_immutable_literal())
                     .collect::<Option<Vec<_>>>()
+                    .filter(|_| self.thaw_on_write)
                 {
                     let vals: Vec<FrozenValue> = lits.map(|v| v.compile(self.heap));
                     let result = self.heap.alloc(FrozenList { content: vals });
                     box move |context| Ok(context.heap.alloc_thaw_on_write(result))
                 } else {
                     let exprs = self.exprs(exprs);
-                    box move |context| Ok(context.heap.alloc(exprs(context)?))
+                    box move |context| {
+                        let xs = exprs(context)?;
+                        if !context.tracks_provenance() {
+                            return Ok(context.heap.alloc(xs));
+                        }
+                        let res = context.heap.alloc(xs.clone());
+                        context.propagate_provenance(Flow::Container, res, &xs);
+                        Ok(res)
+                    }
                 }
             }
             Expr::Dict(exprs) => {
@@ -330,6 +645,7 @@ impl Compiler<'_> {
                         Some((k.unpack_immutable_literal()?, v.unpack_immutable_literal()?))
                     })
                     .collect::<Option<Vec<_>>>()
+                    .filter(|_| self.thaw_on_write)
                 {
                     let mut res = SmallMap::new();
                     for (k, v) in lits.iter() {
@@ -356,13 +672,20 @@ impl Compiler<'_> {
                         let k = k(context)?;
                         if r.insert_hashed(k.get_hashed()?, v(context)?).is_some() {
                             thrw(
-                                Err(EvalError::DuplicateDictionaryKey(k.to_string()).into()),
+                                Err(EvalError::DuplicateDictionaryKey(k.to_str_for_error()).into()),
                                 span,
                                 context,
                             )?;
                         }
                     }
-                    Ok(context.heap.alloc(dict::Dict::new(r)))
+                    let sources = if context.tracks_provenance() {
+                        r.iter().flat_map(|(k, v)| vec![*k, *v]).collect()
+                    } else {
+                        Vec::new()
+                    };
+                    let res = context.heap.alloc(dict::Dict::new(r));
+                    context.propagate_provenance(Flow::Container, res, &sources);
+                    Ok(res)
                 }
             }
             Expr::If(box (cond, then_expr, else_expr)) => {
@@ -385,56 +708,20 @@ impl Compiler<'_> {
                     Either::Right(v) => Ok(context.heap.alloc(v)),
                 }
             }
-            Expr::Call(left, args) => {
-                let args = args.into_map(|x| match x.node {
-                    Argument::Positional(x) => ArgCompiled::Pos(self.expr(x)),
-                    Argument::Named(name, value) => {
-                        let name_value = self
-                            .heap
-                            .alloc(name.node.as_str())
-                            .get_hashed()
-                            .expect("String is Hashable");
-                        ArgCompiled::Named(name.node, name_value, self.expr(value))
-                    }
-                    Argument::ArgsArray(x) => ArgCompiled::Args(self.expr(x)),
-                    Argument::KWArgsDict(x) => ArgCompiled::KWArgs(self.expr(x)),
-                });
-                let call = eval_call(span, args);
-                match left.node {
-                    Expr::Dot(e, s) => {
-                        let e = self.expr(*e);
-                        let dot = eval_dot(span, e, s.node);
-                        box move |context| match dot(context)? {
-                            Either::Left(function) => {
-                                let invoker =
-                                    thrw(function.new_invoker(context.heap), span, context)?;
-                                call(invoker, function, context)
-                            }
-                            Either::Right(wrapper) => {
-                                let invoker = thrw(wrapper.invoke(context.heap), span, context)?;
-                                call(invoker, wrapper.get_method(), context)
-                            }
-                        }
-                    }
-                    _ => {
-                        let left = self.expr(*left);
-                        box move |context| {
-                            let function = left(context)?;
-                            let invoker = thrw(function.new_invoker(context.heap), span, context)?;
-                            call(invoker, function, context)
-                        }
-                    }
-                }
-            }
+            Expr::Call(left, args) => self.call(span, *left, args, false),
             Expr::ArrayIndirection(box (array, index)) => {
                 let array = self.expr(array);
                 let index = self.expr(index);
                 box move |context| {
-                    thrw(
-                        array(context)?.at(index(context)?, context.heap),
-                        span,
-                        context,
-                    )
+                    let array = array(context)?;
+                    let index = index(context)?;
+                    let res = match array.unpack_str() {
+                        Some(s) if context.byte_strings => string::byte_at(s, index, context.heap),
+                        _ => array.at(index, context.heap),
+                    };
+                    let res = thrw(res, span, context)?;
+                    context.propagate_provenance(Flow::Container, res, &[array, index]);
+                    Ok(res)
                 }
             }
             Expr::Slice(collection, start, stop, stride) => {
@@ -470,12 +757,16 @@ impl Compiler<'_> {
             },
             Expr::BitNot(expr) => {
                 let expr = self.expr(*expr);
-                box move |context| Ok(Value::new_int(!expr(context)?.to_int()?))
+                box move |context| thrw(expr(context)?.bit_not(context.heap), span, context)
             }
             Expr::Op(left, op, right) => {
                 if let Some(x) = Expr::reduces_to_string(op, &left, &right) {
                     let val = self.heap.alloc(x);
                     box move |_| Ok(Value::new_frozen(val))
+                } else if op == BinOp::Addition
+                    && matches!(left.node, Expr::Op(_, BinOp::Addition, _))
+                {
+                    self.addition_chain(span, *left, *right)
                 } else {
                     let l = self.expr(*left);
                     let r = self.expr(*right);
@@ -508,60 +799,20 @@ impl Compiler<'_> {
                                 context,
                             )
                         },
-                        BinOp::Subtraction => box move |context| {
-                            thrw(l(context)?.sub(r(context)?, context.heap), span, context)
-                        },
-                        BinOp::Addition => box move |context| {
-                            // Addition of string is super common and pretty cheap, so have a special case for it.
-                            let l = l(context)?;
-                            let r = r(context)?;
-                            if let Some(ls) = l.unpack_str() {
-                                if let Some(rs) = r.unpack_str() {
-                                    if ls.is_empty() {
-                                        return Ok(r);
-                                    } else if rs.is_empty() {
-                                        return Ok(l);
-                                    } else {
-                                        return Ok(context.heap.alloc(fast_string::append(ls, rs)));
-                                    }
-                                }
-                            }
-
-                            // Written using Value::add so that Rust Analyzer doesn't think it is an error.
-                            thrw(Value::add(l, r, context.heap), span, context)
-                        },
-                        BinOp::Multiplication => box move |context| {
-                            thrw(l(context)?.mul(r(context)?, context.heap), span, context)
-                        },
-                        BinOp::Percent => box move |context| {
-                            thrw(
-                                l(context)?.percent(r(context)?, context.heap),
-                                span,
-                                context,
-                            )
-                        },
-                        BinOp::FloorDivision => box move |context| {
-                            thrw(
-                                l(context)?.floor_div(r(context)?, context.heap),
-                                span,
-                                context,
-                            )
-                        },
-                        BinOp::BitAnd => {
-                            box move |context| thrw(l(context)?.bit_and(r(context)?), span, context)
+                        BinOp::Subtraction => eval_arith(span, l, r, |l, r, heap| l.sub(r, heap)),
+                        BinOp::Addition => eval_arith(span, l, r, add),
+                        BinOp::Multiplication => {
+                            eval_arith(span, l, r, |l, r, heap| l.mul(r, heap))
                         }
-                        BinOp::BitOr => {
-                            box move |context| thrw(l(context)?.bit_or(r(context)?), span, context)
+                        BinOp::Percent => eval_arith(span, l, r, |l, r, heap| l.percent(r, heap)),
+                        BinOp::FloorDivision => {
+                            eval_arith(span, l, r, |l, r, heap| l.floor_div(r, heap))
                         }
-                        BinOp::BitXor => {
-                            box move |context| thrw(l(context)?.bit_xor(r(context)?), span, context)
-                        }
-                        BinOp::LeftShift => box move |context| {
-                            thrw(l(context)?.left_shift(r(context)?), span, context)
-                        },
-                        BinOp::RightShift => box move |context| {
-                            thrw(l(context)?.right_shift(r(context)?), span, context)
-                        },
+                        BinOp::BitAnd => eval_arith(span, l, r, |l, r, _| l.bit_and(r)),
+                        BinOp::BitOr => eval_arith(span, l, r, |l, r, _| l.bit_or(r)),
+                        BinOp::BitXor => eval_arith(span, l, r, |l, r, _| l.bit_xor(r)),
+                        BinOp::LeftShift => eval_arith(span, l, r, |l, r, _| l.left_shift(r)),
+                        BinOp::RightShift => eval_arith(span, l, r, |l, r, _| l.right_shift(r)),
                     }
                 }
             }
@@ -21,12 +21,32 @@
 use crate::environment::FrozenModule;
 use anyhow::anyhow;
 use gazebo::prelude::*;
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, fmt::Display};
+use thiserror::Error;
 
 /// A trait for turning a `path` given by a `load()` statement into a [`FrozenModule`].
 pub trait FileLoader {
-    /// Open the file given by the load statement `path`.
+    /// Open the file given by the load statement `path`, after it has been through [`resolve`](FileLoader::resolve).
     fn load(&mut self, path: &str) -> anyhow::Result<FrozenModule>;
+
+    /// Turn the string written in a `load()` statement into the `path` passed to
+    /// [`load`](FileLoader::load) and [`check_load`](FileLoader::check_load).
+    /// Embedders can use this to support relative paths, Bazel-style labels (see [`LoadLabel`])
+    /// or aliases, so that different spellings of the same file are loaded once.
+    /// Called for every `load()` statement before any of the code runs, with the error
+    /// reported at the string in the `load()` statement. Defaults to the string unchanged.
+    fn resolve(&self, path: &str) -> anyhow::Result<String> {
+        Ok(path.to_owned())
+    }
+
+    /// Check that the code being evaluated may load `symbol` from the file `path`,
+    /// allowing the loader to restrict which names each file can use from another.
+    /// Called for every symbol of every `load()` statement before any of the code runs,
+    /// so a disallowed load is reported even if it would never be executed.
+    /// The error is reported at the `load()` statement. Defaults to allowing everything.
+    fn check_load(&self, _path: &str, _symbol: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 /// [`FileLoader`] that looks up modules by name from a [`HashMap`].
@@ -49,3 +69,175 @@ impl<'a> FileLoader for ReturnFileLoader<'a> {
         }
     }
 }
+
+#[derive(Debug, Error)]
+enum LoadLabelError {
+    #[error("Invalid load label `{0}`, {1}")]
+    Invalid(String, &'static str),
+    #[error("Invalid load label `{0}`, the path goes above the root of the repository")]
+    AboveRoot(String),
+}
+
+/// A Bazel-style label naming a file to load, written `@repo//package:name`,
+/// for use in implementations of [`FileLoader::resolve`].
+///
+/// ```
+/// use starlark::eval::LoadLabel;
+///
+/// let current = LoadLabel::parse("//foo/bar:BUILD", None).unwrap();
+/// let label = LoadLabel::parse(":defs.bzl", Some(&current)).unwrap();
+/// assert_eq!(label.to_string(), "//foo/bar:defs.bzl");
+/// let label = LoadLabel::parse("../baz/defs.bzl", Some(&current)).unwrap();
+/// assert_eq!(label.to_string(), "//foo/baz:defs.bzl");
+/// assert_eq!(label.path(), "foo/baz/defs.bzl");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LoadLabel {
+    /// The repository after the `@`, or [`None`] for the main repository.
+    pub repository: Option<String>,
+    /// The `/` separated directory of the package, relative to the root of the repository.
+    /// Empty for the root package.
+    pub package: String,
+    /// The name of the file within the package, which may contain `/`.
+    pub name: String,
+}
+
+impl LoadLabel {
+    /// Parse a label written in a `load()` statement of the file `current`, which is used to
+    /// resolve the relative forms. The accepted forms are:
+    ///
+    /// * `@repo//package:name` and `//package:name`, naming a file from the root of a
+    ///   repository. The second form uses the repository of `current`.
+    /// * `:name`, naming a file in the package of `current`.
+    /// * A relative path such as `defs.bzl` or `../lib/defs.bzl`, resolved against the
+    ///   directory containing `current`. The resulting directory becomes the package.
+    ///
+    /// Only the first form can be used when `current` is [`None`].
+    pub fn parse(label: &str, current: Option<&LoadLabel>) -> anyhow::Result<Self> {
+        let invalid = |msg| -> anyhow::Result<Self> {
+            Err(LoadLabelError::Invalid(label.to_owned(), msg).into())
+        };
+        let (repository, rest) = match label.strip_prefix('@') {
+            Some(rest) => match rest.find("//") {
+                Some(i) if i > 0 && !rest[..i].contains('/') => {
+                    (Some(rest[..i].to_owned()), &rest[i..])
+                }
+                _ => return invalid("expected `@repo//package:name`"),
+            },
+            None => (current.and_then(|c| c.repository.clone()), label),
+        };
+        if let Some(rest) = rest.strip_prefix("//") {
+            let (package, name) = match rest.split_once(':') {
+                Some(x) => x,
+                None => return invalid("expected `:` before the file name"),
+            };
+            if !package.is_empty() && !Self::is_normal_path(package) {
+                return invalid("the package must be a normalized path");
+            }
+            if !Self::is_normal_path(name) {
+                return invalid("the file name must be a normalized path");
+            }
+            return Ok(Self {
+                repository,
+                package: package.to_owned(),
+                name: name.to_owned(),
+            });
+        }
+        let current = match current {
+            Some(current) => current,
+            None => return invalid("relative labels need the label of the loading file"),
+        };
+        if let Some(name) = rest.strip_prefix(':') {
+            if !Self::is_normal_path(name) {
+                return invalid("the file name must be a normalized path");
+            }
+            return Ok(Self {
+                repository,
+                package: current.package.clone(),
+                name: name.to_owned(),
+            });
+        }
+        if rest.starts_with('/') {
+            return invalid("absolute paths are not allowed, use `//package:name`");
+        }
+        if matches!(rest.rsplit('/').next(), Some("" | "." | "..")) {
+            return invalid("expected a path to a file");
+        }
+        // The directory of `current` as components, which the relative path is applied to
+        let current_path = current.path();
+        let mut path: Vec<&str> = current_path.split('/').filter(|x| !x.is_empty()).collect();
+        path.pop();
+        for x in rest.split('/') {
+            match x {
+                "" | "." => {}
+                ".." => {
+                    if path.pop().is_none() {
+                        return Err(LoadLabelError::AboveRoot(label.to_owned()).into());
+                    }
+                }
+                _ => path.push(x),
+            }
+        }
+        // Can't be empty, since the last component was a file name
+        let name = path.pop().unwrap();
+        Ok(Self {
+            repository,
+            package: path.join("/"),
+            name: name.to_owned(),
+        })
+    }
+
+    // Non-empty, with no empty, `.` or `..` components
+    fn is_normal_path(x: &str) -> bool {
+        !x.is_empty() && x.split('/').all(|x| !matches!(x, "" | "." | ".."))
+    }
+
+    /// The path of the file relative to the root of its repository, `package/name`.
+    pub fn path(&self) -> String {
+        if self.package.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}/{}", self.package, self.name)
+        }
+    }
+}
+
+impl Display for LoadLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(repository) = &self.repository {
+            write!(f, "@{}", repository)?;
+        }
+        write!(f, "//{}:{}", self.package, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_label() {
+        let current = LoadLabel::parse("@r//foo/bar:BUILD", None).unwrap();
+        let parse = |x: &str| LoadLabel::parse(x, Some(&current)).map(|x| x.to_string());
+        assert_eq!(parse("//a/b:c.bzl").unwrap(), "@r//a/b:c.bzl");
+        assert_eq!(parse("@s//:c.bzl").unwrap(), "@s//:c.bzl");
+        assert_eq!(parse(":c.bzl").unwrap(), "@r//foo/bar:c.bzl");
+        assert_eq!(parse(":sub/c.bzl").unwrap(), "@r//foo/bar:sub/c.bzl");
+        assert_eq!(parse("c.bzl").unwrap(), "@r//foo/bar:c.bzl");
+        assert_eq!(parse("./sub/c.bzl").unwrap(), "@r//foo/bar/sub:c.bzl");
+        assert_eq!(parse("../../c.bzl").unwrap(), "@r//:c.bzl");
+        assert!(parse("../../../c.bzl").is_err());
+        assert!(parse("//a/b").is_err());
+        assert!(parse("//a/../b:c.bzl").is_err());
+        assert!(parse("//a:").is_err());
+        assert!(parse("@//a:c.bzl").is_err());
+        assert!(parse("/a/c.bzl").is_err());
+        assert!(parse("sub/").is_err());
+        assert!(parse("..").is_err());
+        assert!(LoadLabel::parse(":c.bzl", None).is_err());
+
+        let label = LoadLabel::parse("//:c.bzl", None).unwrap();
+        assert_eq!(label.repository, None);
+        assert_eq!(label.path(), "c.bzl");
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The results of calls to functions wrapped with `memoize`, see
+//! [`Evaluator::memoize_stats`](crate::eval::Evaluator::memoize_stats).
+
+use crate::{
+    collections::{Hashed, SmallMap},
+    values::{FrozenValue, Value, Walker},
+};
+use std::collections::HashMap;
+
+/// How often calls to functions wrapped with `memoize` were answered from the cache,
+/// as returned by [`Evaluator::memoize_stats`](crate::eval::Evaluator::memoize_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoizeStats {
+    /// Calls whose result was already cached.
+    pub hits: usize,
+    /// Calls which ran the function and cached the result.
+    pub misses: usize,
+    /// Calls which ran the function without caching the result, because an argument
+    /// wasn't hashable, or the function hadn't been frozen.
+    pub uncached: usize,
+    /// The number of results in the cache.
+    pub entries: usize,
+}
+
+/// The cached results, which live on the heap of the [`Evaluator`](crate::eval::Evaluator),
+/// so last as long as it does.
+#[derive(Default)]
+pub(crate) struct MemoizeCache<'v> {
+    // Keyed by the address of the frozen memoized function, which never moves,
+    // then by its arguments
+    results: HashMap<usize, SmallMap<Value<'v>, Value<'v>>>,
+    stats: MemoizeStats,
+}
+
+impl<'v> MemoizeCache<'v> {
+    pub(crate) fn get(
+        &mut self,
+        function: FrozenValue,
+        key: Hashed<Value<'v>>,
+    ) -> Option<Value<'v>> {
+        let res = self
+            .results
+            .get(&function.to_value().ptr_value())
+            .and_then(|x| x.get_hashed(key.borrow()))
+            .copied();
+        if res.is_some() {
+            self.stats.hits += 1;
+        }
+        res
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        function: FrozenValue,
+        key: Hashed<Value<'v>>,
+        value: Value<'v>,
+    ) {
+        self.stats.misses += 1;
+        if self
+            .results
+            .entry(function.to_value().ptr_value())
+            .or_default()
+            .insert_hashed(key, value)
+            .is_none()
+        {
+            self.stats.entries += 1;
+        }
+    }
+
+    pub(crate) fn uncached(&mut self) {
+        self.stats.uncached += 1;
+    }
+
+    pub(crate) fn stats(&self) -> MemoizeStats {
+        self.stats
+    }
+
+    pub(crate) fn walk(&mut self, walker: &Walker<'v>) {
+        for results in self.results.values_mut() {
+            results.iter_mut().for_each(|(k, v)| {
+                walker.walk_dictionary_key(k);
+                walker.walk(v);
+            })
+        }
+    }
+}
@@ -20,27 +20,37 @@
 
 use crate::{
     codemap::{CodeMap, Span},
-    environment::{slots::LocalSlots, Globals},
-    errors::Diagnostic,
+    environment::{slots::LocalSlots, FrozenModule, Globals},
+    errors::{Diagnostic, ErrorKind},
     eval::scope::Scope,
-    syntax::ast::AstModule,
-    values::{FrozenHeap, Value, ValueRef},
+    syntax::{
+        ast::{AstModule, AstStmt, Stmt},
+        Dialect,
+    },
+    values::{ControlError, FrozenHeap, Value, ValueRef},
 };
 use anyhow::anyhow;
 use gazebo::prelude::*;
-use std::{fmt::Debug, mem, sync::Arc};
+use std::{collections::HashMap, fmt::Debug, mem, sync::Arc};
 use thiserror::Error;
 
 pub use crate::eval::file_loader::*;
+pub use callable::FrozenCallable;
 pub use context::Evaluator;
-pub(crate) use parameters::ParametersCollect;
-pub use parameters::{ParametersParser, ParametersSpec};
+pub(crate) use expr::EvalError;
+pub use memoize::MemoizeStats;
+pub(crate) use parameters::{FunctionError, ParametersCollect, ResolvedNames, Symbol, SymbolTable};
+pub use parameters::{Parameter, ParameterKind, ParametersParser, ParametersSpec};
+pub use provenance::ProvenancePolicy;
 pub(crate) use scope::ScopeNames;
 
 pub(crate) mod call_stack;
+mod callable;
 mod expr;
 mod file_loader;
+mod memoize;
 mod parameters;
+mod provenance;
 mod scope;
 mod stmt;
 
@@ -62,6 +72,9 @@ pub(crate) enum EvalException<'v> {
     Break,
     Continue,
     Return(Value<'v>),
+    // A `return f(...)` where `f` is the function already running, with the arguments
+    // for `f`. The running function starts again with them, rather than calling itself.
+    TailCall(Vec<ValueRef<'v>>),
     // Error bubbling up
     Error(anyhow::Error),
 }
@@ -82,6 +95,15 @@ pub(crate) enum AssignError {
     IncorrectNumberOfValueToUnpack(i32, i32),
 }
 
+impl AssignError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::IncorrectLeftValue => ErrorKind::Parse,
+            Self::IncorrectNumberOfValueToUnpack(..) => ErrorKind::Value,
+        }
+    }
+}
+
 /// Convert syntax error to spanned evaluation exception
 fn thrw<'v, T>(
     r: anyhow::Result<T>,
@@ -100,13 +122,43 @@ fn thrw<'v, T>(
     }
 }
 
+/// If the error was caused by mutating `iterable` while iterating over it,
+/// add the location where the iteration started.
+fn add_iteration_site<'v>(
+    e: EvalException<'v>,
+    iterable: Value<'v>,
+    over_span: Span,
+    context: &Evaluator<'v, '_>,
+) -> EvalException<'v> {
+    fn is_mutation_of(e: &anyhow::Error, iterable: Value) -> bool {
+        let message = match e.downcast_ref::<Diagnostic>() {
+            Some(d) => &d.message,
+            None => e,
+        };
+        match message.downcast_ref::<ControlError>() {
+            Some(ControlError::MutationDuringIteration(id)) => *id == iterable.ptr_value(),
+            _ => false,
+        }
+    }
+
+    match e {
+        EvalException::Error(e) if is_mutation_of(&e, iterable) => {
+            let site = context.look_up_span(over_span).to_string();
+            EvalException::Error(Diagnostic::modify(e, |d| {
+                d.message = ControlError::MutationDuringIterationStartedAt(site).into()
+            }))
+        }
+        e => e,
+    }
+}
+
 impl From<EvalException<'_>> for anyhow::Error {
     fn from(x: EvalException) -> Self {
         match x {
             EvalException::Error(e) => e,
             EvalException::Break => anyhow!("Break statement used outside of a loop"),
             EvalException::Continue => anyhow!("Continue statement used outside of a loop"),
-            EvalException::Return(..) => {
+            EvalException::Return(..) | EvalException::TailCall(..) => {
                 anyhow!("Return statement used outside of a function call")
             }
         }
@@ -117,39 +169,48 @@ pub(crate) struct Compiler<'a> {
     scope: Scope<'a>,
     heap: &'a FrozenHeap,
     globals: &'a Globals,
+    prelude: &'a [FrozenModule],
     errors: Vec<anyhow::Error>,
     codemap: Arc<CodeMap>,
+    dialect: &'a Dialect,
+    // Can constant list and dict literals share a frozen value, see `disable_thaw_on_write`
+    thaw_on_write: bool,
+    symbols: SymbolTable,
+    // Used to check each `load()` is allowed
+    loader: Option<&'a dyn FileLoader>,
+    // The span of an expression whose value is recorded in `Evaluator::watched` when it is
+    // first evaluated, as used by `eval_module_at`
+    watch: Option<Span>,
 }
 
 impl<'v, 'a> Evaluator<'v, 'a> {
     /// Evaluate an [`AstModule`] with this [`Evaluator`], modifying the in-scope
     /// [`Module`](crate::environment::Module) as appropriate.
     pub fn eval_module(&mut self, module: AstModule) -> anyhow::Result<Value<'v>> {
-        let AstModule { codemap, statement } = module;
-        let module_env = self.assert_module_env();
-
-        let scope = Scope::enter_module(module_env.names(), &statement);
-
-        let span = statement.span;
-
-        let mut compiler = Compiler {
-            scope,
-            heap: module_env.frozen_heap(),
-            globals: self.globals,
-            errors: Vec::new(),
-            codemap: codemap.dupe(),
-        };
-        let stmt = compiler.stmt(statement);
+        self.eval_module_watching(module, None)
+    }
 
-        // We want to grab the first error only, with ownership, so drop all but the first
-        compiler.errors.truncate(1);
-        if let Some(e) = compiler.errors.pop() {
-            // Static errors, reported even if the branch is not hit
-            return Err(e);
+    // Like `eval_module`, but record the value of the expression at `watch` in `watched`.
+    pub(crate) fn eval_module_watching(
+        &mut self,
+        module: AstModule,
+        watch: Option<Span>,
+    ) -> anyhow::Result<Value<'v>> {
+        let AstModule {
+            codemap,
+            statement,
+            dialect,
+            ..
+        } = module;
+        if let Some(docstring) = statement.docstring() {
+            self.assert_module_env().set_docstring(docstring);
         }
+        let span = statement.span;
 
-        let (module_slots, local_slots) = compiler.scope.exit_module();
-        module_env.slots().ensure_slots(module_slots);
+        let (stmt, local_slots) = self
+            .compile_module(&codemap, statement, &dialect, watch)
+            // Report the first error only
+            .map_err(|errors| errors.into_iter().next().unwrap())?;
         let old_locals = mem::replace(
             &mut self.local_variables,
             LocalSlots::new(vec![ValueRef::new_unassigned(); local_slots]),
@@ -157,6 +218,8 @@ impl<'v, 'a> Evaluator<'v, 'a> {
 
         // Set up the world to allow evaluation (do NOT use ? from now on)
         let old_codemap = mem::replace(&mut self.codemap, codemap.dupe());
+        let old_byte_strings = mem::replace(&mut self.byte_strings, dialect.enable_byte_strings);
+        let old_dict_views = mem::replace(&mut self.dict_views, dialect.enable_dict_views);
         self.call_stack
             .push(Value::new_none(), Some((codemap, span)))
             .unwrap();
@@ -173,12 +236,74 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             self.heap.record_call_exit();
         }
         self.codemap = old_codemap;
+        self.byte_strings = old_byte_strings;
+        self.dict_views = old_dict_views;
         self.local_variables = old_locals;
 
         // Return the result of evaluation
         Ok(res?)
     }
 
+    /// Find all the errors [`eval_module`](Evaluator::eval_module) would report before running
+    /// any of the module, e.g. variables which aren't defined anywhere, rather than just the
+    /// first. Returns an empty list if there are none. The module is not evaluated, but the
+    /// names of the variables it defines are added to the in-scope
+    /// [`Module`](crate::environment::Module), without values.
+    pub fn check_module(&self, module: AstModule) -> Vec<Diagnostic> {
+        let AstModule {
+            codemap,
+            statement,
+            dialect,
+            ..
+        } = module;
+        match self.compile_module(&codemap, statement, &dialect, None) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_map(Diagnostic::from_anyhow),
+        }
+    }
+
+    // Compile the statements of a module, returning the code and the number of local slots it
+    // needs. The errors found are reported even if the code containing them would never run,
+    // and on failure there is at least one.
+    fn compile_module(
+        &self,
+        codemap: &Arc<CodeMap>,
+        statement: AstStmt,
+        dialect: &Dialect,
+        watch: Option<Span>,
+    ) -> Result<(EvalCompiled, usize), Vec<anyhow::Error>> {
+        let module_env = self.assert_module_env();
+        let mut defines = HashMap::new();
+        Stmt::collect_defines(&statement, &mut defines);
+        module_env
+            .check_injected(defines.keys().copied())
+            .map_err(|e| vec![e])?;
+
+        let scope = Scope::enter_module(module_env.names(), &statement);
+        let mut compiler = Compiler {
+            scope,
+            heap: module_env.frozen_heap(),
+            globals: self.globals,
+            prelude: &self.prelude,
+            errors: Vec::new(),
+            codemap: codemap.dupe(),
+            dialect,
+            thaw_on_write: self.thaw_on_write,
+            symbols: SymbolTable::default(),
+            loader: self.loader.as_deref(),
+            watch,
+        };
+        let stmt = compiler.stmt(statement);
+        // Even if there are errors, the names are in the module, so need slots
+        let (module_slots, local_slots) = compiler.scope.exit_module();
+        module_env.slots().ensure_slots(module_slots);
+        if compiler.errors.is_empty() {
+            Ok((stmt, local_slots))
+        } else {
+            Err(compiler.errors)
+        }
+    }
+
     /// Evaluate a function stored in a [`Value`], passing in `positional` and `named` arguments.
     pub fn eval_function(
         &mut self,
@@ -186,15 +311,14 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         positional: &[Value<'v>],
         named: &[(&str, Value<'v>)],
     ) -> anyhow::Result<Value<'v>> {
-        self.with_call_stack(function, None, |context| {
-            let mut invoker = function.new_invoker(context.heap)?;
-            for x in positional {
-                invoker.push_pos(*x);
-            }
-            for (s, x) in named {
-                invoker.push_named(s, context.heap.alloc(*s).get_hashed()?, *x);
-            }
-            invoker.invoke(function, None, context)
-        })
+        // The invoker adds the function to the call stack
+        let mut invoker = function.new_invoker(self.heap)?;
+        for x in positional {
+            invoker.push_pos(*x);
+        }
+        for (s, x) in named {
+            invoker.push_named(s, self.heap.alloc(*s).get_hashed()?, *x);
+        }
+        invoker.invoke(function, None, self)
     }
 }
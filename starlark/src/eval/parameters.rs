@@ -19,14 +19,24 @@
 /// First build a Parameters structure, then use collect to collect the
 /// parameters into slots.
 use crate::{
-    collections::{BorrowHashed, Hashed, SmallMap},
+    collections::{BorrowHashed, Entry, Hashed, SmallMap},
     values::{
-        dict::Dict, tuple::Tuple, Freezer, FrozenValue, Heap, UnpackValue, Value, ValueError,
-        ValueLike, ValueRef, Walker,
+        dict::Dict, structs::Struct, Freezer, FrozenHeap, FrozenValue, Heap, UnpackValue, Value,
+        ValueError, ValueLike, ValueRef, Walker,
     },
 };
 use gazebo::{cell::ARef, prelude::*};
-use std::{cmp, mem, slice::Iter};
+use once_cell::sync::OnceCell;
+use std::{
+    cmp,
+    collections::HashMap,
+    mem,
+    slice::Iter,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 
 #[derive(Debug, Clone, Error)]
@@ -46,7 +56,7 @@ pub(crate) enum FunctionError {
     ArgsValueIsNotString,
     #[error("The argument provided for *args is not iterable")]
     ArgsArrayIsNotIterable,
-    #[error("The argument provided for **kwargs is not a dictionary")]
+    #[error("The argument provided for **kwargs is not a dictionary or struct")]
     KWArgsIsNotDict,
 }
 
@@ -78,17 +88,108 @@ impl<'v> ParameterDefault<Value<'v>> {
     }
 }
 
-/// Define a list of parameters. This code assumes that all names are distinct and that
-/// `*args`/`**kwargs` occur in well-formed locations.
+/// How the argument for a parameter can be supplied, see [`Parameter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    /// Only by position, a parameter before a `/`.
+    PositionalOnly,
+    /// Either by position or by name.
+    Normal,
+    /// Only by name, a parameter after a `*` or `*args`.
+    NamedOnly,
+    /// The `*args` parameter, collecting any extra positional arguments.
+    Args,
+    /// The `**kwargs` parameter, collecting any extra named arguments.
+    KWargs,
+}
+
+/// A description of a single parameter of a function, see [`ParametersSpec::parameters`].
+#[derive(Debug, Clone)]
+pub struct Parameter<'v> {
+    /// The name of the parameter.
+    pub name: String,
+    /// How the argument can be supplied.
+    pub kind: ParameterKind,
+    /// Whether the caller must supply an argument.
+    pub required: bool,
+    /// The value used if the caller doesn't supply an argument, if there is one.
+    pub default: Option<Value<'v>>,
+}
+
+/// A name used for a named argument, interned when the module is compiled.
+/// The string is allocated and hashed once per module, rather than on every call.
+#[derive(Debug, Clone, Dupe)]
+pub(crate) struct Symbol(Arc<(String, Hashed<FrozenValue>)>);
+
+impl Symbol {
+    pub(crate) fn name(&self) -> &str {
+        &(self.0).0
+    }
+
+    pub(crate) fn hashed_value<'v>(&self) -> Hashed<Value<'v>> {
+        (self.0).1.to_hashed_value()
+    }
+}
+
+/// The [`Symbol`]s of a module, so that each distinct name is only interned once.
+#[derive(Default)]
+pub(crate) struct SymbolTable(HashMap<String, Symbol>);
+
+impl SymbolTable {
+    pub(crate) fn intern(&mut self, name: String, heap: &FrozenHeap) -> Symbol {
+        if let Some(x) = self.0.get(&name) {
+            return x.dupe();
+        }
+        let value = heap
+            .alloc(name.as_str())
+            .get_hashed()
+            .expect("String is Hashable");
+        let res = Symbol(Arc::new((name.clone(), value)));
+        self.0.insert(name, res.dupe());
+        res
+    }
+}
+
+/// The slots that the named arguments at a callsite map to, resolved against the
+/// [`ParametersSpec`] of the first function called from there. Calls to that function
+/// (or another with the same parameters) then skip hashing the names.
+#[derive(Default)]
+pub(crate) struct ResolvedNames(OnceCell<(usize, Vec<Option<usize>>)>);
+
+impl ResolvedNames {
+    fn get<'c, V>(
+        &'c self,
+        params: &ParametersSpec<V>,
+        symbols: &[Symbol],
+    ) -> Option<&'c [Option<usize>]> {
+        let (id, slots) = self
+            .0
+            .get_or_init(|| (params.id, symbols.map(|x| params.resolve(x))));
+        if *id == params.id { Some(slots) } else { None }
+    }
+}
+
+fn fresh_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Define a list of parameters. Adding a parameter panics if its name is repeated,
+/// or if it occurs somewhere `*args`/`**kwargs` make invalid.
 #[derive(Debug, Clone)]
 // V = Value, or FrozenValue
 pub struct ParametersSpec<V> {
+    // Identifies the names and their indices, changed whenever they are,
+    // so the resolution of a callsite can be cached in ResolvedNames
+    id: usize,
     function_name: String, // Only for error messages
     // FIXME: I want to use &'ast str where I use String below
     names: Vec<(String, ParameterDefault<V>)>,
     indices: SmallMap<String, usize>,
     positional: usize, /* Number of arguments that can be filled positionally (exclude
                         * args/kwargs, *args k=1 etc) */
+    // Number of leading arguments up to and including the last positional-only one
+    pos_only: usize,
     no_args: bool,
     args: Option<usize>,
     kwargs: Option<usize>,
@@ -98,10 +199,12 @@ impl<V> ParametersSpec<V> {
     /// Create a new [`ParametersSpec`] with the given function name.
     pub fn new(function_name: String) -> Self {
         Self {
+            id: fresh_id(),
             function_name,
             names: Vec::new(),
             indices: SmallMap::new(),
             positional: 0,
+            pos_only: 0,
             no_args: false,
             args: None,
             kwargs: None,
@@ -111,20 +214,38 @@ impl<V> ParametersSpec<V> {
     /// Create a new [`ParametersSpec`] with the given function name and an advance capacity hint.
     pub fn with_capacity(function_name: String, capacity: usize) -> Self {
         Self {
+            id: fresh_id(),
             function_name,
             names: Vec::with_capacity(capacity),
             indices: SmallMap::with_capacity(capacity),
             positional: 0,
+            pos_only: 0,
             no_args: false,
             args: None,
             kwargs: None,
         }
     }
 
+    // Check the parameter can be added after those we already have
+    fn validate(&self, name: &str) {
+        assert!(
+            self.kwargs.is_none(),
+            "**kwargs must be the last parameter, but got `{}` after it",
+            name
+        );
+        assert!(
+            self.names.iter().all(|(x, _)| x != name),
+            "Parameter `{}` is repeated",
+            name
+        );
+    }
+
     fn add(&mut self, name: &str, val: ParameterDefault<V>) {
+        self.validate(name);
         let i = self.names.len();
         self.names.push((name.to_owned(), val));
         let old = self.indices.insert(name.to_owned(), i);
+        self.id = fresh_id();
         if self.args.is_none() && !self.no_args {
             // If you've already seen `args` or `no_args`, you can't enter these
             // positionally
@@ -133,27 +254,60 @@ impl<V> ParametersSpec<V> {
         assert!(old.is_none());
     }
 
+    fn add_pos_only(&mut self, name: &str, val: ParameterDefault<V>) {
+        // Positional-only parameters are left out of `indices`, so can't be matched by name
+        assert!(self.args.is_none() && !self.no_args);
+        assert!(
+            self.pos_only == self.names.len(),
+            "Positional-only parameter `{}` must come before any other parameters",
+            name
+        );
+        self.validate(name);
+        let i = self.names.len();
+        self.names.push((name.to_owned(), val));
+        self.id = fresh_id();
+        self.positional = i + 1;
+        self.pos_only = i + 1;
+    }
+
     /// Add a required parameter. Will be an error if the caller doesn't supply
-    /// it. If you want to supply a position-only argument, prepend a `$` to
-    /// the name.
+    /// it.
     pub fn required(&mut self, name: &str) {
         self.add(name, ParameterDefault::Required);
     }
 
     /// Add an optional parameter. Will be None if the caller doesn't supply it.
-    /// If you want to supply a position-only argument, prepend a `$` to the
-    /// name.
     pub fn optional(&mut self, name: &str) {
         self.add(name, ParameterDefault::Optional);
     }
 
-    /// Add an optional parameter. Will be the edefault value if the caller
-    /// doesn't supply it. If you want to supply a position-only argument,
-    /// prepend a `$` to the name.
+    /// Add an optional parameter. Will be the default value if the caller
+    /// doesn't supply it.
     pub fn defaulted(&mut self, name: &str, val: V) {
         self.add(name, ParameterDefault::Defaulted(val));
     }
 
+    /// Like [`required`](ParametersSpec::required), but the parameter can _only_ be
+    /// supplied by position, corresponding to a Python parameter before `/`.
+    /// Must be called before adding any other parameters, other than positional-only ones.
+    /// Replaces the old convention of prepending a `$` to the name, which is now treated
+    /// like any other name.
+    pub fn required_pos_only(&mut self, name: &str) {
+        self.add_pos_only(name, ParameterDefault::Required);
+    }
+
+    /// Like [`optional`](ParametersSpec::optional), but the parameter can _only_ be
+    /// supplied by position.
+    pub fn optional_pos_only(&mut self, name: &str) {
+        self.add_pos_only(name, ParameterDefault::Optional);
+    }
+
+    /// Like [`defaulted`](ParametersSpec::defaulted), but the parameter can _only_ be
+    /// supplied by position.
+    pub fn defaulted_pos_only(&mut self, name: &str, val: V) {
+        self.add_pos_only(name, ParameterDefault::Defaulted(val));
+    }
+
     /// Add an `*args` parameter which will be an iterable sequence of parameters,
     /// recorded into a [`Vec`]. A function can only have one `args`
     /// parameter. After this call, any subsequent [`required`](ParametersSpec::required),
@@ -161,6 +315,7 @@ impl<V> ParametersSpec<V> {
     /// parameters can _only_ be supplied by name.
     pub fn args(&mut self, name: &str) {
         assert!(self.args.is_none() && !self.no_args);
+        self.validate(name);
         self.names.push((name.to_owned(), ParameterDefault::Args));
         self.args = Some(self.names.len() - 1);
     }
@@ -169,18 +324,20 @@ impl<V> ParametersSpec<V> {
     /// After this call, any subsequent [`required`](ParametersSpec::required),
     /// [`optional`](ParametersSpec::optional) or [`defaulted`](ParametersSpec::defaulted)
     /// parameters can _only_ be supplied by name.
-    pub fn no_args(&mut self) {
+    pub fn kw_only(&mut self) {
         assert!(self.args.is_none() && !self.no_args);
         self.no_args = true;
     }
 
+    /// The same as [`kw_only`](ParametersSpec::kw_only).
+    pub fn no_args(&mut self) {
+        self.kw_only()
+    }
+
     /// Add a `**kwargs` parameter which will be a dictionary, recorded into a [`SmallMap`].
-    /// A function can only have one `kwargs` parameter.
-    /// parameter. After this call, any subsequent [`required`](ParametersSpec::required),
-    /// [`optional`](ParametersSpec::optional) or [`defaulted`](ParametersSpec::defaulted)
-    /// parameters can _only_ be supplied by position.
+    /// A function can only have one `kwargs` parameter, and it must be the last parameter.
     pub fn kwargs(&mut self, name: &str) {
-        assert!(self.kwargs.is_none());
+        self.validate(name);
         self.names.push((name.to_owned(), ParameterDefault::KWargs));
         self.kwargs = Some(self.names.len() - 1);
     }
@@ -188,16 +345,78 @@ impl<V> ParametersSpec<V> {
     pub(crate) fn collect<'v, 'a>(
         me: ARef<'a, Self>,
         slots: usize,
+        heap: &'v Heap,
     ) -> ParametersCollect<'v, 'a, V> {
         let len = me.names.len();
         ParametersCollect {
             params: me,
-            slots: vec![ValueRef::new_unassigned(); cmp::max(slots, len)],
+            slots: heap.alloc_slots(cmp::max(slots, len)),
             only_positional: true,
             next_position: 0,
             args: Vec::new(),
             kwargs: SmallMap::new(),
             err: None,
+            heap,
+        }
+    }
+
+    // The slot a named argument should go in, or None if it belongs in kwargs
+    fn resolve(&self, name: &Symbol) -> Option<usize> {
+        let value = name.hashed_value();
+        // Safe to use new_unchecked because hash for the Value and str are the same
+        let name_hash = BorrowHashed::new_unchecked(value.hash(), name.name());
+        self.indices.get_hashed(name_hash).copied()
+    }
+
+    // The number of parameters, if they are all required and can all be supplied positionally
+    pub(crate) fn fixed_arity(&self) -> Option<usize> {
+        if self.positional == self.names.len()
+            && self
+                .names
+                .iter()
+                .all(|(_, x)| matches!(x, ParameterDefault::Required))
+        {
+            Some(self.positional)
+        } else {
+            None
+        }
+    }
+
+    /// The name of the function these parameters belong to.
+    pub fn function_name(&self) -> &str {
+        &self.function_name
+    }
+
+    /// The names of the parameters, in the order they were declared,
+    /// including `*args` and `**kwargs`.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(|(x, _)| x.as_str())
+    }
+
+    /// The minimum and maximum number of positional arguments accepted.
+    /// The minimum is the number of required parameters that can be supplied
+    /// positionally, and the maximum is [`None`] if there is an `*args` parameter.
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        let min = self.names[..self.positional]
+            .iter()
+            .filter(|(_, x)| matches!(x, ParameterDefault::Required))
+            .count();
+        let max = if self.args.is_some() {
+            None
+        } else {
+            Some(self.positional)
+        };
+        (min, max)
+    }
+
+    fn kind(&self, i: usize) -> ParameterKind {
+        let (name, typ) = &self.names[i];
+        match typ {
+            ParameterDefault::Args => ParameterKind::Args,
+            ParameterDefault::KWargs => ParameterKind::KWargs,
+            _ if !self.indices.contains_key(name) => ParameterKind::PositionalOnly,
+            _ if i < self.positional => ParameterKind::Normal,
+            _ => ParameterKind::NamedOnly,
         }
     }
 
@@ -212,35 +431,75 @@ impl<V> ParametersSpec<V> {
     pub(crate) fn collect_repr(&self, collector: &mut String) {
         collector.push_str(&self.function_name);
         collector.push('(');
-        for (i, (name, typ)) in self.names.iter().enumerate() {
-            if i != 0 {
+        let mut first = true;
+        let mut separator = |collector: &mut String| {
+            if !mem::take(&mut first) {
                 collector.push_str(", ");
             }
-            // We prepend '$' on the front of variable names that are positional-only
-            // arguments to the native functions. We rip those off when
-            // displaying the signature.
+        };
+        for (i, (name, typ)) in self.names.iter().enumerate() {
+            if i != 0 && i == self.pos_only {
+                separator(collector);
+                collector.push('/');
+            }
+            if self.no_args && i == self.positional {
+                separator(collector);
+                collector.push('*');
+            }
+            separator(collector);
             match typ {
-                ParameterDefault::Required => collector.push_str(name.trim_start_matches('$')),
+                ParameterDefault::Required => collector.push_str(name),
                 ParameterDefault::Optional | ParameterDefault::Defaulted(_) => {
-                    collector.push_str(name.trim_start_matches('$'));
+                    collector.push_str(name);
                     collector.push_str(" = ...");
                 }
-                ParameterDefault::Args => collector.push_str("*args"),
-                ParameterDefault::KWargs => collector.push_str("**kwargs"),
+                ParameterDefault::Args => {
+                    collector.push('*');
+                    collector.push_str(name);
+                }
+                ParameterDefault::KWargs => {
+                    collector.push_str("**");
+                    collector.push_str(name);
+                }
             }
         }
+        if self.pos_only != 0 && self.pos_only == self.names.len() {
+            separator(collector);
+            collector.push('/');
+        }
         collector.push(')');
     }
 }
 
+impl<'v, V: ValueLike<'v>> ParametersSpec<V> {
+    /// Describe each of the parameters, in the order they were declared.
+    pub fn parameters(&self) -> Vec<Parameter<'v>> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(i, (name, typ))| Parameter {
+                name: name.clone(),
+                kind: self.kind(i),
+                required: matches!(typ, ParameterDefault::Required),
+                default: match typ {
+                    ParameterDefault::Defaulted(x) => Some(x.to_value()),
+                    _ => None,
+                },
+            })
+            .collect()
+    }
+}
+
 impl<'v> ParametersSpec<Value<'v>> {
     /// Used to freeze a [`ParametersSpec`].
     pub fn freeze(self, freezer: &Freezer) -> ParametersSpec<FrozenValue> {
         ParametersSpec {
+            id: self.id,
             function_name: self.function_name,
             names: self.names.into_map(|(s, v)| (s, v.freeze(freezer))),
             indices: self.indices,
             positional: self.positional,
+            pos_only: self.pos_only,
             no_args: self.no_args,
             args: self.args,
             kwargs: self.kwargs,
@@ -265,6 +524,8 @@ pub(crate) struct ParametersCollect<'v, 'a, V> {
     kwargs: SmallMap<Value<'v>, Value<'v>>,
     // We defer errors right until the end, to simplify the API
     err: Option<anyhow::Error>,
+    // For the names of struct fields passed as `**kwargs`
+    heap: &'v Heap,
 }
 
 impl<'v, 'a, V: ValueLike<'v>> ParametersCollect<'v, 'a, V> {
@@ -296,17 +557,42 @@ impl<'v, 'a, V: ValueLike<'v>> ParametersCollect<'v, 'a, V> {
     }
 
     pub fn named(&mut self, name: &str, name_value: Hashed<Value<'v>>, val: Value<'v>) {
-        self.only_positional = false;
         // Safe to use new_unchecked because hash for the Value and str are the same
         let name_hash = BorrowHashed::new_unchecked(name_value.hash(), name);
-        let repeated = match self.params.indices.get_hashed(name_hash) {
-            None => {
-                let old = self.kwargs.insert_hashed(name_value, val);
-                old.is_some()
-            }
+        let slot = self.params.indices.get_hashed(name_hash).copied();
+        self.named_slot(slot, name, name_value, val)
+    }
+
+    /// Resolve the named arguments of a callsite, see [`ResolvedNames`].
+    /// Returns [`None`] if the cache was filled by a function with different parameters.
+    pub(crate) fn resolve<'c>(
+        &self,
+        symbols: &[Symbol],
+        cache: &'c ResolvedNames,
+    ) -> Option<&'c [Option<usize>]> {
+        cache.get(&*self.params, symbols)
+    }
+
+    /// Like [`named`](ParametersCollect::named), but with the slot already resolved.
+    pub(crate) fn named_slot(
+        &mut self,
+        slot: Option<usize>,
+        name: &str,
+        name_value: Hashed<Value<'v>>,
+        val: Value<'v>,
+    ) {
+        self.only_positional = false;
+        let repeated = match slot {
+            None => match self.kwargs.entry_hashed(name_value) {
+                Entry::Occupied(_) => true,
+                Entry::Vacant(e) => {
+                    e.insert(val);
+                    false
+                }
+            },
             Some(i) => {
-                let res = !self.slots[*i].is_unassigned();
-                self.slots[*i].set(val);
+                let res = !self.slots[i].is_unassigned();
+                self.slots[i].set(val);
                 res
             }
         };
@@ -336,22 +622,29 @@ impl<'v, 'a, V: ValueLike<'v>> ParametersCollect<'v, 'a, V> {
 
     pub fn kwargs(&mut self, val: Value<'v>) {
         let res = try {
-            match Dict::from_value(val) {
-                Some(y) => {
-                    // We know that reservation isn't too memory hungry,
-                    // mostly because big maps don't actually properly reserve,
-                    // so reserve assuming all of these values might go into kwargs
-                    if self.params.kwargs.is_some() {
-                        self.kwargs.reserve(y.len());
-                    }
-                    for (n, v) in y.iter_hashed() {
-                        match n.key().unpack_str() {
-                            None => Err(FunctionError::ArgsValueIsNotString)?,
-                            Some(s) => self.named(s, n, v),
-                        }
+            if let Some(y) = Dict::from_value(val) {
+                // We know that reservation isn't too memory hungry,
+                // mostly because big maps don't actually properly reserve,
+                // so reserve assuming all of these values might go into kwargs
+                if self.params.kwargs.is_some() {
+                    self.kwargs.reserve(y.len());
+                }
+                for (n, v) in y.iter_hashed() {
+                    match n.key().unpack_str() {
+                        None => Err(FunctionError::ArgsValueIsNotString)?,
+                        Some(s) => self.named(s, n, v),
                     }
                 }
-                None => Err(FunctionError::KWArgsIsNotDict)?,
+            } else if let Some(y) = Struct::from_value(val) {
+                // Each field is passed as a named argument
+                if self.params.kwargs.is_some() {
+                    self.kwargs.reserve(y.fields.len());
+                }
+                for (n, v) in y.fields.iter() {
+                    self.named(n, self.heap.alloc(n.as_str()).get_hashed()?, *v);
+                }
+            } else {
+                Err(FunctionError::KWArgsIsNotDict)?
             }
         };
         match res {
@@ -390,7 +683,7 @@ impl<'v, 'a, V: ValueLike<'v>> ParametersCollect<'v, 'a, V> {
                 }
                 ParameterDefault::Args => {
                     let args = mem::take(&mut args);
-                    slot.set(heap.alloc(Tuple::new(args)));
+                    slot.set(heap.alloc_tuple(&args));
                 }
                 ParameterDefault::KWargs => {
                     let kwargs = mem::take(&mut kwargs);
@@ -418,11 +711,11 @@ impl<'v, 'a, V: ValueLike<'v>> ParametersCollect<'v, 'a, V> {
 
 /// Parse a series of parameters which were specified by [`ParametersSpec`].
 pub struct ParametersParser<'v, 'a> {
-    slots: Iter<'a, Option<Value<'v>>>,
+    slots: Iter<'a, ValueRef<'v>>,
 }
 
 impl<'v, 'a> ParametersParser<'v, 'a> {
-    pub(crate) fn new(slots: &'a [Option<Value<'v>>]) -> Self {
+    pub(crate) fn new(slots: &'a [ValueRef<'v>]) -> Self {
         Self {
             slots: slots.iter(),
         }
@@ -444,9 +737,9 @@ impl<'v, 'a> ParametersParser<'v, 'a> {
         // This unwrap is safe because we only call next one time per ParametersSpec.count()
         // and slots starts out with that many entries.
         let v = self.slots.next().unwrap();
-        match v {
+        match v.get() {
             None => Ok(None),
-            Some(v) => Ok(Some(Self::named_err(name, T::unpack_value(*v, heap))?)),
+            Some(v) => Ok(Some(Self::named_err(name, T::unpack_value(v, heap))?)),
         }
     }
 
@@ -463,7 +756,72 @@ impl<'v, 'a> ParametersParser<'v, 'a> {
         let v = self.slots.next().unwrap();
         // This is definitely not unassigned because ParametersCollect.done checked
         // that.
-        let v = v.as_ref().unwrap();
-        Self::named_err(name, T::unpack_value(*v, heap))
+        let v = v.get().unwrap();
+        Self::named_err(name, T::unpack_value(v, heap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let mut p = ParametersSpec::<FrozenValue>::new("f".to_owned());
+        p.required_pos_only("a");
+        p.optional_pos_only("b");
+        p.required("c");
+        p.kw_only();
+        p.defaulted("d", FrozenValue::new_none());
+        p.kwargs("extra");
+        assert_eq!(p.signature(), "f(a, b = ..., /, c, *, d = ..., **extra)");
+        assert_eq!(p.function_name(), "f");
+        assert_eq!(
+            p.names().collect::<Vec<_>>(),
+            vec!["a", "b", "c", "d", "extra"]
+        );
+        assert_eq!(p.arity(), (2, Some(3)));
+        assert_eq!(p.fixed_arity(), None);
+        assert_eq!(
+            p.parameters().map(|x| x.kind),
+            vec![
+                ParameterKind::PositionalOnly,
+                ParameterKind::PositionalOnly,
+                ParameterKind::Normal,
+                ParameterKind::NamedOnly,
+                ParameterKind::KWargs,
+            ]
+        );
+        assert!(p.parameters()[3].default.unwrap().is_none());
+
+        let mut p = ParametersSpec::<FrozenValue>::new("g".to_owned());
+        p.required_pos_only("x");
+        assert_eq!(p.signature(), "g(x, /)");
+        assert_eq!(p.fixed_arity(), Some(1));
+
+        let mut p = ParametersSpec::<FrozenValue>::new("h".to_owned());
+        p.args("rest");
+        p.required("y");
+        assert_eq!(p.signature(), "h(*rest, y)");
+        assert_eq!(p.arity(), (0, None));
+    }
+
+    #[test]
+    fn test_pos_only_not_named() {
+        let mut p = ParametersSpec::<FrozenValue>::new("f".to_owned());
+        p.required_pos_only("a");
+        p.required("b");
+        let heap = FrozenHeap::new();
+        let mut symbols = SymbolTable::default();
+        assert_eq!(p.resolve(&symbols.intern("a".to_owned(), &heap)), None);
+        assert_eq!(p.resolve(&symbols.intern("b".to_owned(), &heap)), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "must come before any other parameters")]
+    fn test_pos_only_after_normal() {
+        let mut p = ParametersSpec::<FrozenValue>::new("f".to_owned());
+        p.required("a");
+        p.required_pos_only("b");
     }
 }
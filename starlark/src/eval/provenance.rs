@@ -0,0 +1,138 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Tracking which inputs a value was computed from, see
+//! [`Evaluator::enable_provenance`](crate::eval::Evaluator::enable_provenance).
+
+use crate::values::{Heap, Value, ValueIdentity};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Controls how the origins recorded by
+/// [`Evaluator::add_provenance`](crate::eval::Evaluator::add_provenance) spread to other values.
+/// The results of operators such as `+` and `%` always get the origins of their operands.
+#[derive(Debug, Clone)]
+pub struct ProvenancePolicy {
+    /// Do the results of function calls get the origins of the arguments, and of the object
+    /// a method is called on. Defaults to `true`.
+    pub through_calls: bool,
+    /// Do containers built by list, tuple and dict literals get the origins of their elements,
+    /// and do values obtained by indexing, slicing, attribute access or iteration get the
+    /// origins of the value they were obtained from. Defaults to `true`.
+    pub through_containers: bool,
+    /// Names of functions whose results don't get the origins of their arguments, e.g. a
+    /// function which escapes a string. Matched against the name at the call site, so `quote`
+    /// covers both `quote(x)` and `shell.quote(x)`.
+    pub sanitizers: HashSet<String>,
+}
+
+impl Default for ProvenancePolicy {
+    fn default() -> Self {
+        Self {
+            through_calls: true,
+            through_containers: true,
+            sanitizers: HashSet::new(),
+        }
+    }
+}
+
+/// How a value was derived from others, to decide if the [`ProvenancePolicy`] lets origins flow.
+pub(crate) enum Flow<'a> {
+    Operator,
+    Container,
+    // The name of the function at the call site, if it has one
+    Call(Option<&'a str>),
+}
+
+impl ProvenancePolicy {
+    fn allows(&self, flow: Flow) -> bool {
+        match flow {
+            Flow::Operator => true,
+            Flow::Container => self.through_containers,
+            Flow::Call(name) => {
+                self.through_calls && !name.map_or(false, |x| self.sanitizers.contains(x))
+            }
+        }
+    }
+}
+
+/// The origins of each tagged value, keyed by identity so they survive garbage collection.
+pub(crate) struct Provenance {
+    policy: ProvenancePolicy,
+    origins: HashMap<ValueIdentity, BTreeSet<String>>,
+}
+
+impl Provenance {
+    pub(crate) fn new(policy: ProvenancePolicy) -> Self {
+        Self {
+            policy,
+            origins: HashMap::new(),
+        }
+    }
+
+    // Values not on the heap (ints, bools, None, frozen values) are shared by every use,
+    // so tagging them would tag unrelated code too.
+    fn taggable(value: Value) -> bool {
+        value.unpack_frozen().is_none()
+    }
+
+    pub(crate) fn add<'v>(&mut self, heap: &'v Heap, value: Value<'v>, origin: &str) {
+        if Self::taggable(value) {
+            self.origins
+                .entry(heap.identity(value))
+                .or_default()
+                .insert(origin.to_owned());
+        }
+    }
+
+    pub(crate) fn get<'v>(&self, heap: &'v Heap, value: Value<'v>) -> Vec<String> {
+        match heap.existing_identity(value) {
+            Some(id) => self
+                .origins
+                .get(&id)
+                .map(|x| x.iter().cloned().collect())
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn propagate<'v>(
+        &mut self,
+        heap: &'v Heap,
+        flow: Flow,
+        result: Value<'v>,
+        sources: &[Value<'v>],
+    ) {
+        if self.origins.is_empty() || !Self::taggable(result) || !self.policy.allows(flow) {
+            return;
+        }
+        let mut res = BTreeSet::new();
+        for x in sources {
+            if let Some(origins) = heap
+                .existing_identity(*x)
+                .and_then(|id| self.origins.get(&id))
+            {
+                res.extend(origins.iter().cloned());
+            }
+        }
+        if !res.is_empty() {
+            self.origins
+                .entry(heap.identity(result))
+                .or_default()
+                .extend(res);
+        }
+    }
+}
@@ -204,4 +204,68 @@ impl<'a> Scope<'a> {
             )
         })
     }
+
+    // All the names which `get_name` would find at this point
+    pub fn all_names(&self) -> Vec<String> {
+        let mut res: Vec<String> = self.module.all_names().into_iter().map(|x| x.0).collect();
+        for names in &self.locals {
+            res.extend(names.mp.keys().cloned());
+        }
+        res
+    }
+}
+
+/// The name from `candidates` most similar to `name`, if any is close enough to be
+/// a likely typo of it. Ties are broken alphabetically, so the result is deterministic.
+pub(crate) fn closest_name<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Option<&'a str> {
+    // Allow one edit for every three characters, so short names don't match everything
+    let max = name.chars().count() / 3;
+    candidates
+        .filter(|x| *x != name)
+        .map(|x| (edit_distance(name, x), x))
+        .filter(|(d, _)| *d <= max)
+        .min()
+        .map(|x| x.1)
+}
+
+// The Levenshtein distance between two strings, counted in characters
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the prefix of `a` seen so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev + if ca == *cb { 0 } else { 1 };
+            prev = row[j + 1];
+            row[j + 1] = substitute.min(prev + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_name() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+        let names = ["length", "len", "lens", "x", "y"];
+        assert_eq!(
+            closest_name("lenght", names.iter().copied()),
+            Some("length")
+        );
+        // Equally close, so the first alphabetically
+        assert_eq!(closest_name("lena", names.iter().copied()), Some("len"));
+        // Too short to guess
+        assert_eq!(closest_name("z", names.iter().copied()), None);
+        assert_eq!(closest_name("unrelated", names.iter().copied()), None);
+    }
 }
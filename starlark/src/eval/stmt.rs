@@ -25,13 +25,16 @@
 use crate::{
     codemap::{Span, Spanned},
     environment::EnvironmentError,
+    errors::Diagnostic,
     eval::{
-        context::Evaluator, scope::Slot, thrw, AssignError, Compiler, EvalCompiled, EvalException,
+        add_iteration_site, context::Evaluator, provenance::Flow, scope::Slot, thrw, AssignError,
+        Compiler, EvalCompiled, EvalException,
     },
-    syntax::ast::{AssignOp, AstExpr, AstStmt, Expr, Stmt, Visibility},
+    syntax::ast::{AssignOp, AstExpr, AstStmt, AstString, Expr, Stmt, Visibility},
     values::{
         fast_string,
         list::{FrozenList, List},
+        string_iter::{StringIterable, StringIterableKind},
         ControlError, Heap, Value,
     },
 };
@@ -64,14 +67,45 @@ fn eval_assign_list<'v>(
         let it2 = thrw(value.iterate(context.heap), span, context)?;
         let mut it2 = it2.iter();
         for _ in 0..l {
-            it1.next().unwrap()(it2.next().unwrap(), context)?;
+            let v = it2.next().unwrap();
+            context.propagate_provenance(Flow::Container, v, &[value]);
+            it1.next().unwrap()(v, context)?;
         }
         Ok(())
     }
 }
 
+// Apply the operator of `x op= y`, so the result gets the origins of both sides,
+// as it would for `x op y`.
+fn modify<'v>(
+    op: for<'x> fn(Value<'x>, Value<'x>, &mut Evaluator<'x, '_>) -> anyhow::Result<Value<'x>>,
+    l: Value<'v>,
+    r: Value<'v>,
+    context: &mut Evaluator<'v, '_>,
+) -> anyhow::Result<Value<'v>> {
+    let res = op(l, r, context)?;
+    context.propagate_provenance(Flow::Operator, res, &[l, r]);
+    Ok(res)
+}
+
 impl Compiler<'_> {
     pub fn assign(&mut self, expr: AstExpr) -> AssignCompiled {
+        let span = expr.span;
+        let res = self.assign_unwatched(expr);
+        if self.watch != Some(span) {
+            return res;
+        }
+        // The value of an expression being assigned to is the value assigned
+        box move |value, context| {
+            res(value, context)?;
+            if context.watched.is_none() {
+                context.watched = Some(value);
+            }
+            Ok(())
+        }
+    }
+
+    fn assign_unwatched(&mut self, expr: AstExpr) -> AssignCompiled {
         let span = expr.span;
         match expr.node {
             Expr::Dot(e, s) => {
@@ -134,7 +168,7 @@ impl Compiler<'_> {
                     thrw(
                         e.set_attr(
                             &s,
-                            thrw(op(v, rhs, context), span_op, context)?,
+                            thrw(modify(op, v, rhs, context), span_op, context)?,
                             context.heap,
                         ),
                         span,
@@ -155,7 +189,7 @@ impl Compiler<'_> {
                     thrw(
                         e.set_at(
                             idx,
-                            thrw(op(v, rhs, context), span_op, context)?,
+                            thrw(modify(op, v, rhs, context), span_op, context)?,
                             context.heap,
                         ),
                         span,
@@ -171,7 +205,7 @@ impl Compiler<'_> {
                         before_stmt(span, context);
                         let v = thrw(context.get_slot_local(slot, &name), span, context)?;
                         let rhs = rhs(context)?;
-                        let v = thrw(op(v, rhs, context), span_op, context)?;
+                        let v = thrw(modify(op, v, rhs, context), span_op, context)?;
                         context.set_slot_local(slot, v);
                         Ok(Value::new_none())
                     },
@@ -179,7 +213,7 @@ impl Compiler<'_> {
                         before_stmt(span, context);
                         let v = thrw(context.get_slot_module(slot, &name), span, context)?;
                         let rhs = rhs(context)?;
-                        let v = thrw(op(v, rhs, context), span_op, context)?;
+                        let v = thrw(modify(op, v, rhs, context), span_op, context)?;
                         context.set_slot_module(slot, v);
                         Ok(Value::new_none())
                     },
@@ -250,7 +284,8 @@ fn before_stmt(span: Span, context: &mut Evaluator) {
 }
 
 /// Implement lhs += rhs, which is special in Starlark, because lists are mutated,
-/// while all other types are not.
+/// while all other types are not, unless they implement
+/// [`ComplexValue::add_assign`](crate::values::ComplexValue::add_assign).
 fn add_assign<'v>(lhs: Value<'v>, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
     // Addition of strings is super common, so have a special case
     if let Some(ls) = lhs.unpack_str() {
@@ -271,6 +306,18 @@ fn add_assign<'v>(lhs: Value<'v>, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Res
     // We also have to carefully prod frozen lists, in case they are
     // copy-on-write.
     if lhs.downcast_ref::<List>().is_some() || lhs.downcast_ref::<FrozenList>().is_some() {
+        // Appending a different list is the common case, and we can copy straight from it
+        if !lhs.ptr_eq(rhs) {
+            if let Some(xs) = List::from_value(rhs) {
+                return match List::from_value_mut(lhs, heap)? {
+                    None => Err(ControlError::CannotMutateImmutableValue.into()),
+                    Some(mut list) => {
+                        list.content.extend(xs.content.iter().copied());
+                        Ok(lhs)
+                    }
+                };
+            }
+        }
         let xs = rhs.iterate_collect(heap)?;
         match List::from_value_mut(lhs, heap)? {
             None => Err(ControlError::CannotMutateImmutableValue.into()),
@@ -280,7 +327,41 @@ fn add_assign<'v>(lhs: Value<'v>, rhs: Value<'v>, heap: &'v Heap) -> anyhow::Res
             }
         }
     } else {
-        Value::add(lhs, rhs, heap)
+        // If `rhs` is `lhs`, we couldn't look at it while `lhs` is mutably borrowed
+        let res = if lhs.ptr_eq(rhs) {
+            None
+        } else {
+            match lhs.get_ref_mut_already_checked()? {
+                Some(mut v) => v.add_assign(rhs, heap),
+                None => None,
+            }
+        };
+        match res {
+            Some(res) => res.map(|()| lhs),
+            None => Value::add(lhs, rhs, heap),
+        }
+    }
+}
+
+impl Compiler<'_> {
+    /// Compile the expression a `for` loop or comprehension iterates over.
+    /// If the dialect permits iterating over strings, a string is replaced
+    /// by an iterable over its characters.
+    pub(crate) fn iterable(&mut self, over: AstExpr) -> EvalCompiled {
+        let over = self.expr(over);
+        if !self.dialect.enable_string_iteration {
+            return over;
+        }
+        box move |context| {
+            let v = over(context)?;
+            if v.unpack_str().is_some() {
+                Ok(context
+                    .heap
+                    .alloc(StringIterable::new(v, StringIterableKind::Elems)))
+            } else {
+                Ok(v)
+            }
+        }
     }
 }
 
@@ -326,11 +407,43 @@ impl Stmt {
 }
 
 impl Compiler<'_> {
+    // Turn the path written in a `load()` into the one given to the loader
+    fn resolve_load(&mut self, path: AstString) -> String {
+        if let Some(loader) = self.loader {
+            match loader.resolve(&path.node) {
+                Ok(resolved) => return resolved,
+                Err(e) => self.errors.push(Diagnostic::new(
+                    EnvironmentError::CannotResolveLoad(path.node.clone(), e.to_string()),
+                    path.span,
+                    self.codemap.dupe(),
+                )),
+            }
+        }
+        path.node
+    }
+
+    // Static check that the loader lets this file load `symbol` from `path`
+    fn check_load(&mut self, path: &str, symbol: &str, span: Span) {
+        if let Some(loader) = self.loader {
+            if let Err(e) = loader.check_load(path, symbol) {
+                self.errors.push(Diagnostic::new(
+                    EnvironmentError::CannotLoadSymbol(
+                        symbol.to_owned(),
+                        path.to_owned(),
+                        e.to_string(),
+                    ),
+                    span,
+                    self.codemap.dupe(),
+                ));
+            }
+        }
+    }
+
     pub(crate) fn stmt(&mut self, stmt: AstStmt) -> EvalCompiled {
         let span = stmt.span;
         match stmt.node {
             Stmt::Def(name, params, return_type, suite) => {
-                let rhs = self.function(&name.node, params, return_type, *suite);
+                let rhs = self.function(span, &name.node, params, return_type, *suite);
                 let lhs = self.assign(Spanned {
                     span: name.span,
                     node: Expr::Identifier(name),
@@ -344,14 +457,35 @@ impl Compiler<'_> {
             Stmt::For(box (var, over, body)) => {
                 let over_span = over.span;
                 let var = self.assign(var);
-                let over = self.expr(over);
+                let over = self.iterable(over);
                 let st = self.stmt(body);
                 box move |context| {
                     before_stmt(span, context);
                     let iterable = over(context)?;
                     let freeze_for_iteration = iterable.get_aref();
-                    for v in &thrw(iterable.iterate(context.heap), over_span, context)? {
-                        var(v, context)?;
+                    let res: Result<(), EvalException> = try {
+                        for v in &thrw(iterable.iterate(context.heap), over_span, context)? {
+                            context.propagate_provenance(Flow::Container, v, &[iterable]);
+                            var(v, context)?;
+                            match st(context) {
+                                Err(EvalException::Break) => break,
+                                Err(EvalException::Continue) => {}
+                                Err(e) => Err(e)?,
+                                _ => {}
+                            }
+                        }
+                    };
+                    mem::drop(freeze_for_iteration);
+                    res.map_err(|e| add_iteration_site(e, iterable, over_span, context))?;
+                    Ok(Value::new_none())
+                }
+            }
+            Stmt::While(cond, box body) => {
+                let cond = self.expr(cond);
+                let st = self.stmt(body);
+                box move |context| {
+                    before_stmt(span, context);
+                    while cond(context)?.to_bool() {
                         match st(context) {
                             Err(EvalException::Break) => break,
                             Err(EvalException::Continue) => {}
@@ -359,12 +493,20 @@ impl Compiler<'_> {
                             _ => {}
                         }
                     }
-                    mem::drop(freeze_for_iteration);
                     Ok(Value::new_none())
                 }
             }
             Stmt::Return(Some(e)) => {
-                let e = self.expr(e);
+                let e = match e.node {
+                    // Without recursion a call to the running function is an error, which a
+                    // tail call would skip. A tail call has no value, so can't be watched.
+                    Expr::Call(left, args)
+                        if self.dialect.enable_recursion && self.watch != Some(e.span) =>
+                    {
+                        self.call(e.span, *left, args, true)
+                    }
+                    _ => self.expr(e),
+                };
                 box move |context| {
                     before_stmt(span, context);
                     Err(EvalException::Return(e(context)?))
@@ -462,7 +604,10 @@ impl Compiler<'_> {
                 }
             }
             Stmt::Load(name, v, _) => {
-                let name = name.node;
+                let name = self.resolve_load(name);
+                for (x, y) in &v {
+                    self.check_load(&name, &y.node, x.span.merge(y.span));
+                }
                 let symbols = v.into_map(|(x, y)| {
                     (
                         self.scope.get_name_or_panic(&x.node),
@@ -18,11 +18,11 @@
 use crate::{
     self as starlark,
     assert::{self, Assert},
-    environment::{Globals, GlobalsBuilder, Module},
+    environment::{FrozenModule, Globals, GlobalsBuilder, MergeConflict, Module},
     errors::Diagnostic,
-    eval::Evaluator,
+    eval::{Evaluator, ParameterKind, ProvenancePolicy},
     syntax::{AstModule, Dialect},
-    values::{any::StarlarkAny, none::NoneType, Heap, Value},
+    values::{any::StarlarkAny, none::NoneType, Heap, ThawOnWriteStats, Value},
 };
 use gazebo::any::AnyLifetime;
 use itertools::Itertools;
@@ -154,6 +154,30 @@ def rec6(): rec2()
     assert::fail("def f(a, **kwargs, *args): pass", "parameter after another");
 }
 
+#[test]
+fn test_kwargs_struct() {
+    assert::pass(
+        r#"
+def f(a, b = 2, **kwargs):
+    return (a, b, kwargs)
+args = struct(a = 1, c = 3)
+assert_eq(f(**args), (1, 2, {"c": 3}))
+assert_eq(f(b = 4, **args), (1, 4, {"c": 3}))
+assert_eq(dict(**struct(x = 1, y = 2)), {"x": 1, "y": 2})
+"#,
+    );
+    // Fields are checked against the parameters, just like dictionary keys
+    assert::fail(
+        "def f(a): pass\nf(**struct(b = 1))",
+        "extra named parameter",
+    );
+    assert::fail(
+        "def f(a): pass\nf(a = 1, **struct(a = 2))",
+        "occurs both explicitly and in **kwargs",
+    );
+    assert::fail("def f(a): pass\nf(**[1])", "not a dictionary or struct");
+}
+
 #[test]
 fn funcall_extra_args_def() {
     fn f(x: &str) -> String {
@@ -206,6 +230,35 @@ fn test_extra_args_native() {
     assert::fail(r#""bonbon".find("on", 2, 3, 4)"#, "extra positional");
     assert::fail(r#""bonbon".find("on", needless="on")"#, "extra named");
     assert::fail(r#""bonbon".find()"#, "Missing parameter");
+    // Positional-only parameters are reported by their real names
+    assert::fails(
+        r#""bonbon".find(needle = "on")"#,
+        &[
+            "Missing parameter `needle`",
+            "find(this, needle, start = ..., end = ..., /)",
+        ],
+    );
+    assert::fail("sorted(x = [1])", "sorted(x, /, key = ..., reverse = ...)");
+}
+
+#[test]
+fn test_native_fixed_arity() {
+    // Functions whose parameters are all required and positional take a faster path,
+    // until they are given anything other than positional arguments.
+    assert::all_true(
+        r#"
+len([1, 2]) == 2
+"abc".removeprefix("a") == "bc"
+"abc".removeprefix(prefix = "a") == "bc"
+"abc".removeprefix(*["a"]) == "bc"
+"abc".removeprefix(**{"prefix": "a"}) == "bc"
+"#,
+    );
+    assert::fail("len()", "Missing parameter `a`");
+    assert::fail("len([], [])", "extra positional");
+    assert::fail("len(a = [])", "Missing parameter `a`");
+    assert::fail(r#""abc".removeprefix("a", "b")"#, "extra positional");
+    assert::fail(r#""abc".removeprefix("a", prefix = "b")"#, "occurs both");
 }
 
 #[test]
@@ -260,6 +313,146 @@ ys = [lambda x: x + y for y in [4,5,6]]
     );
 }
 
+#[test]
+fn test_while() {
+    assert::pass(
+        r#"
+def f(n):
+    i = 0
+    res = []
+    while True:
+        i += 1
+        if i > n:
+            break
+        if i % 2 == 0:
+            continue
+        res.append(i)
+    return res
+assert_eq(f(6), [1, 3, 5])
+x = 3
+while x:
+    x -= 1
+assert_eq(x, 0)
+"#,
+    );
+}
+
+#[test]
+fn test_recursion_dialect() {
+    let program = r#"
+def fact(n):
+    return 1 if n <= 1 else n * fact(n - 1)
+def apply(f, x):
+    return f(x)
+"#;
+    assert::pass(&format!("{}assert_eq(fact(5), 120)", program));
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_recursion = false);
+    a.pass(&format!("{}assert_eq(fact(1), 1)", program));
+    a.pass(&format!("{}assert_eq(apply(len, [1, 2]), 2)", program));
+    a.fail(
+        &format!("{}fact(5)", program),
+        "called recursively, which is not allowed",
+    );
+    a.fail(
+        &format!("{}apply(apply, lambda x: x)", program),
+        "called recursively",
+    );
+}
+
+#[test]
+fn test_tail_calls() {
+    // Deeper than the call stack allows, but each call is in tail position
+    assert::pass(
+        r#"
+def count(n, acc = 0):
+    if n == 0:
+        return acc
+    return count(n - 1, acc = acc + 1)
+assert_eq(count(1000), 1000)
+def collatz(n: int.type, steps: int.type) -> int.type:
+    if n == 1:
+        return steps
+    elif n % 2 == 0:
+        return collatz(n // 2, steps + 1)
+    return collatz(3 * n + 1, steps + 1)
+assert_eq(collatz(27, 0), 111)
+"#,
+    );
+    // Types are still checked on each call
+    assert::fail(
+        r#"
+def down(n: int.type):
+    if n == 0:
+        return down("x")
+    return down(n - 1)
+down(3)
+"#,
+        "does not match the type annotation",
+    );
+    // Only calls to the running function reuse its frame
+    assert::fail(
+        r#"
+def even(n):
+    return True if n == 0 else odd(n - 1)
+def odd(n):
+    if n == 0:
+        return False
+    return even(n - 1)
+even(1000)
+"#,
+        "recursion",
+    );
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_recursion = false);
+    a.fail(
+        "def f(n):\n    return f(n - 1)\nf(3)",
+        "called recursively, which is not allowed",
+    );
+}
+
+#[test]
+fn test_string_iteration() {
+    assert::pass(
+        r#"
+assert_eq(list("a世b".elems()), ["a", "世", "b"])
+assert_eq(list("a世b".codepoints()), ["a", "世", "b"])
+assert_eq(list("a世b".elem_ords()), [97, 19990, 98])
+assert_eq(list("a世b".codepoint_ords()), [97, 19990, 98])
+assert_eq(repr("ab".elems()), '"ab".elems()')
+assert_eq(type("ab".codepoint_ords()), "iterable")
+assert_eq([c for c in "ab"], ["a", "b"])
+def f():
+    res = []
+    for c in "xy":
+        res.append(c)
+    return res
+assert_eq(f(), ["x", "y"])
+"#,
+    );
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_string_iteration = false);
+    a.pass("assert_eq([c for c in 'ab'.elems()], ['a', 'b'])");
+    a.fail("[c for c in 'ab']", "not supported");
+    a.fail(
+        "def f():\n    for c in 'ab':\n        pass\nf()",
+        "not supported",
+    );
+}
+
+#[test]
+fn test_f_string() {
+    assert::pass(
+        r#"
+name = "world"
+assert_eq(f"hello {name}", "hello world")
+def f(x):
+    return f'{x}{{}}{x}'
+assert_eq(f(1), "1{}1")
+"#,
+    );
+}
+
 #[test]
 fn test_frozen_lambda() {
     let mut a = Assert::new();
@@ -300,6 +493,181 @@ fun
     assert_eq!(v.unpack_str(), Some("(8, \"hello\", 1)"))
 }
 
+#[test]
+fn test_function_reflection() {
+    let fun = assert::pass("def fun(a, *args, b = 1, **kwargs): pass\nfun");
+    let fun = fun.value();
+    assert_eq!(
+        fun.signature().as_deref(),
+        Some("fun(a, *args, b = ..., **kwargs)")
+    );
+    let params = fun.parameters().unwrap();
+    assert_eq!(
+        params
+            .iter()
+            .map(|x| (x.name.as_str(), x.kind, x.required))
+            .collect::<Vec<_>>(),
+        vec![
+            ("a", ParameterKind::Normal, true),
+            ("args", ParameterKind::Args, false),
+            ("b", ParameterKind::NamedOnly, false),
+            ("kwargs", ParameterKind::KWargs, false),
+        ]
+    );
+    assert_eq!(params[2].default.and_then(|x| x.unpack_int()), Some(1));
+
+    let len = assert::pass("len");
+    assert_eq!(len.value().signature().as_deref(), Some("len(a, /)"));
+    assert_eq!(
+        len.value().parameters().unwrap()[0].kind,
+        ParameterKind::PositionalOnly
+    );
+
+    assert_eq!(Value::new_int(1).signature(), None);
+    assert!(Value::new_int(1).parameters().is_none());
+}
+
+#[test]
+fn test_callable_value() {
+    use crate::{
+        eval::{ParametersParser, ParametersSpec},
+        values::{FrozenValue, StarlarkValue},
+    };
+
+    // Like `sep.join(args)`, but called as `join(*args, sep = " ")`
+    #[derive(Debug)]
+    struct Joiner(ParametersSpec<FrozenValue>);
+    starlark_simple_value!(Joiner);
+
+    impl<'v> StarlarkValue<'v> for Joiner {
+        starlark_type!("joiner");
+
+        fn invoke_parameters(&self) -> Option<&ParametersSpec<FrozenValue>> {
+            Some(&self.0)
+        }
+
+        fn invoke(
+            &self,
+            _me: Value<'v>,
+            mut args: ParametersParser<'v, '_>,
+            eval: &mut Evaluator<'v, '_>,
+        ) -> anyhow::Result<Value<'v>> {
+            let items: Vec<String> = args.next("args", eval.heap())?;
+            let sep: Option<String> = args.next_opt("sep", eval.heap())?;
+            Ok(eval.heap().alloc(items.join(sep.as_deref().unwrap_or(" "))))
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(|builder| {
+        let mut parameters = ParametersSpec::new("join".to_owned());
+        parameters.args("args");
+        parameters.optional("sep");
+        builder.set("join", Joiner(parameters))
+    });
+    a.all_true(
+        r#"
+join("a", "b") == "a b"
+join("a", "b", sep = "-") == "a-b"
+join(*["x", "y"], **{"sep": ""}) == "xy"
+join() == ""
+repr(join) == "join(*args, sep = ...)"
+type(join) == "joiner"
+"#,
+    );
+    let d = a.fail("def f(): join(end = '')\nf()", "extra named parameter");
+    assert!(d.to_string().contains("* join(*args, sep = ...)"));
+    a.fail("1()", "not supported");
+
+    let join = a.pass("join");
+    assert_eq!(
+        join.value().signature().as_deref(),
+        Some("join(*args, sep = ...)")
+    );
+    assert_eq!(join.value().parameters().unwrap().len(), 2);
+}
+
+#[test]
+fn test_value_iterator() {
+    use crate::values::{AllocValue, ComplexValue, Freezer, SimpleValue, StarlarkValue, Walker};
+
+    // A container which is iterated without implementing StarlarkIterable
+    #[derive(AnyLifetime, Debug)]
+    struct Ints {
+        mutable: bool,
+        values: Vec<i32>,
+    }
+
+    impl<'v> StarlarkValue<'v> for Ints {
+        starlark_type!("ints");
+
+        fn has_iterator(&self) -> bool {
+            true
+        }
+
+        fn iterator<'a>(&'a self, _heap: &'v Heap) -> Box<dyn Iterator<Item = Value<'v>> + 'a>
+        where
+            'v: 'a,
+        {
+            box self.values.iter().map(|x| Value::new_int(*x))
+        }
+
+        fn set_at(&mut self, index: Value<'v>, new_value: Value<'v>) -> anyhow::Result<()> {
+            self.values[index.to_int()? as usize] = new_value.to_int()?;
+            Ok(())
+        }
+    }
+
+    impl AllocValue<'_> for Ints {
+        fn alloc_value(self, heap: &Heap) -> Value {
+            heap.alloc_complex(self)
+        }
+    }
+
+    impl ComplexValue<'_> for Ints {
+        fn is_mutable(&self) -> bool {
+            self.mutable
+        }
+
+        fn freeze(mut self: Box<Self>, _freezer: &Freezer) -> Box<dyn SimpleValue> {
+            self.mutable = false;
+            self
+        }
+
+        unsafe fn walk(&mut self, _walker: &Walker) {}
+    }
+
+    impl SimpleValue for Ints {}
+
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn ints(n: i32) -> Ints {
+            Ok(Ints {
+                mutable: true,
+                values: (0..n).collect(),
+            })
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.is_true(
+        r#"
+xs = ints(3)
+ys = []
+for x in xs:
+    ys.append(x)
+ys == [0, 1, 2] and list(xs) == [0, 1, 2] and [x * 2 for x in xs] == [0, 2, 4]
+"#,
+    );
+    a.pass("xs = ints(3)\nxs[0] = 5\nassert_eq(list(xs), [5, 1, 2])");
+    a.fail(
+        "xs = ints(3)\nfor x in xs:\n    xs[0] = 5",
+        "mutate an iterable",
+    );
+    a.fail("for x in 1:\n    pass", "not supported");
+}
+
 #[test]
 fn test_nested_def() {
     assert::is_true(
@@ -331,6 +699,67 @@ f(1)(2)(3) == 6",
     );
 }
 
+#[test]
+fn test_attr_cache() {
+    // The same callsite must keep working as the type of the receiver changes.
+    assert::is_true(
+        r#"
+def index(x, y):
+    return x.index(y)
+def field(x):
+    return x.a if hasattr(x, "a") else x.keys
+res = [index([1, 2], 2), index("ab", "b"), index([3], 3), index("xyz", "z")]
+fields = [field(struct(a = 1)), field({}), field(struct(a = 2))]
+res == [1, 1, 0, 2] and fields[0] == 1 and fields[1]() == [] and fields[2] == 2"#,
+    );
+}
+
+#[test]
+fn test_named_args_callsite() {
+    // The same callsite passes named arguments to functions with different parameters.
+    assert::is_true(
+        r#"
+def call(f):
+    return f(a = 1, b = 2)
+def f1(a, b):
+    return [a, b]
+def f2(b, a, c = 3):
+    return [a, b, c]
+def f3(**kwargs):
+    return kwargs
+def f4(a, **kwargs):
+    return [a, kwargs]
+res = [call(f1), call(f2), call(f3), call(f4), call(f1)]
+res == [[1, 2], [1, 2, 3], {"a": 1, "b": 2}, [1, {"b": 2}], [1, 2]]"#,
+    );
+    assert::fail(
+        r#"
+def f(a, b):
+    pass
+def call():
+    f(1, a = 2)
+call()"#,
+        "occurs both",
+    );
+}
+
+#[test]
+fn test_reused_slots() {
+    // The slots of a finished call get reused by later calls,
+    // which must not disturb the variables captured by closures.
+    assert::is_true(
+        "
+def adder(x):
+    return lambda y: x + y
+def other(a, b, c):
+    return a + b + c
+add1 = adder(1)
+add2 = adder(2)
+other(10, 20, 30)
+add1(5) == 6 and add2(5) == 7 and adder(3)(other(1, 1, 1)) == 6",
+    );
+}
+
 #[test]
 fn test_garbage_collect() {
     assert::pass(
@@ -565,6 +994,26 @@ loop()"#,
     );
 }
 
+#[test]
+fn test_mutation_during_iteration_site() {
+    // The error reports the iteration over the mutated value, not the innermost one
+    assert::fails(
+        r#"
+xs = [1, 2, 3]
+ys = [4]
+def loop():
+    for x in xs:
+        for y in ys:
+            xs.append(4)
+loop()"#,
+        &["mutate an iterable", "started at assert.bzl:5:14"],
+    );
+    assert::fails(
+        "xs = [1]\n[xs.append(1) for x in xs]",
+        &["mutate an iterable", "started at assert.bzl:2:24"],
+    );
+}
+
 #[test]
 fn test_lvalue_once() {
     assert::is_true(
@@ -602,6 +1051,32 @@ xs == [1, 2, 10, 4]
     );
 }
 
+#[test]
+fn test_addition_chain() {
+    // Runs of strings in `a + b + c` are concatenated in one go
+    assert::all_true(
+        r#"
+a = "x"
+b = "yz"
+a + b + a + b == "xyzxyz"
+a + "" + "" == a
+"" + a + "" == a
+[1] + [2] + [3] == [1, 2, 3]
+1 + 2 + 3 + 4 == 10
+(a + b) + (a + b) == a + b + a + b
+"#,
+    );
+    assert::fail("'a' + 'b' + 1 + 'c'", "not supported");
+    assert::fail(
+        r#"
+def f():
+    fail("evaluated too soon")
+1 + 'a' + f()
+"#,
+        "not supported",
+    );
+}
+
 #[test]
 fn test_add_assign() {
     // += behaves differently on different types
@@ -758,107 +1233,949 @@ fn test_export_as() {
         }
     }
 
-    impl ComplexValue<'_> for Exporter {
-        fn is_mutable(&self) -> bool {
-            self.mutable
-        }
+    impl ComplexValue<'_> for Exporter {
+        fn is_mutable(&self) -> bool {
+            self.mutable
+        }
+
+        fn freeze(mut self: Box<Self>, _freezer: &Freezer) -> Box<dyn SimpleValue> {
+            self.mutable = false;
+            self
+        }
+
+        unsafe fn walk(&mut self, _walker: &Walker) {}
+
+        fn export_as(&mut self, _heap: &Heap, variable_name: &str) {
+            self.named = variable_name.to_owned();
+        }
+    }
+
+    impl SimpleValue for Exporter {}
+
+    #[starlark_module]
+    fn exporter(builder: &mut GlobalsBuilder) {
+        fn exporter(value: i32) -> Exporter {
+            Ok(Exporter {
+                mutable: true,
+                named: "unnamed".to_owned(),
+                value,
+            })
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(exporter);
+    a.module(
+        "a",
+        "x = exporter(1); y = x; longer_name = exporter(2); arrayed = [exporter(3)]",
+    );
+    // could reasonably be x=1 or y=1 twice, since the order
+    // of calls to export_as is not defined
+    let opt1 = "(x=1, x=1, longer_name=2, unnamed=3)".to_owned();
+    let opt2 = opt1.replace("x=", "y=");
+
+    a.is_true(&format!(
+        r#"
+load('a', 'x', 'y', 'longer_name', 'arrayed')
+v = str((x, y, longer_name, arrayed[0]))
+v == '{}' or v == '{}'"#,
+        opt1, opt2
+    ))
+}
+
+#[test]
+fn test_custom_operators() {
+    use crate as starlark;
+    use crate::values::{
+        AllocValue, ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueError,
+        Walker,
+    };
+    use gazebo::any::AnyLifetime;
+
+    #[derive(AnyLifetime, Debug)]
+    struct Counter {
+        mutable: bool,
+        total: i32,
+    }
+
+    impl<'v> StarlarkValue<'v> for Counter {
+        starlark_type!("counter");
+
+        fn collect_repr(&self, collector: &mut String) {
+            collector.push_str(&format!("counter({})", self.total));
+        }
+
+        fn is_in(&self, other: Value<'v>) -> anyhow::Result<bool> {
+            Ok(other.to_int()? <= self.total)
+        }
+
+        fn bit_not(&self, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+            Ok(Value::new_int(-self.total))
+        }
+
+        fn has_iterator(&self) -> bool {
+            true
+        }
+
+        fn iterator<'a>(&'a self, _heap: &'v Heap) -> Box<dyn Iterator<Item = Value<'v>> + 'a>
+        where
+            'v: 'a,
+        {
+            box (0..self.total).map(Value::new_int)
+        }
+
+        fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+            Ok(heap.alloc(Counter {
+                mutable: true,
+                total: self.total + other.to_int()?,
+            }))
+        }
+
+        fn radd(&self, lhs: Value<'v>, heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+            Some(self.add(lhs, heap))
+        }
+
+        fn rmul(&self, lhs: Value<'v>, heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+            Some(lhs.to_int().map(|x| {
+                heap.alloc(Counter {
+                    mutable: true,
+                    total: self.total * x,
+                })
+            }))
+        }
+    }
+
+    impl<'v> AllocValue<'v> for Counter {
+        fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+            heap.alloc_complex(self)
+        }
+    }
+
+    impl<'v> ComplexValue<'v> for Counter {
+        fn is_mutable(&self) -> bool {
+            self.mutable
+        }
+
+        fn freeze(mut self: Box<Self>, _freezer: &Freezer) -> Box<dyn SimpleValue> {
+            self.mutable = false;
+            self
+        }
+
+        unsafe fn walk(&mut self, _walker: &Walker<'v>) {}
+
+        fn add_assign(&mut self, other: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<()>> {
+            Some(match other.unpack_int() {
+                Some(x) => {
+                    self.total += x;
+                    Ok(())
+                }
+                None => ValueError::unsupported_with(&*self, "+=", other),
+            })
+        }
+    }
+
+    impl SimpleValue for Counter {}
+
+    #[starlark_module]
+    fn counter(builder: &mut GlobalsBuilder) {
+        fn counter() -> Counter {
+            Ok(Counter {
+                mutable: true,
+                total: 0,
+            })
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(counter);
+    a.pass(
+        r#"
+c = counter()
+alias = c
+c += 3
+c += 4
+assert_eq(repr(alias), "counter(7)")
+assert_eq(5 in c, True)
+assert_eq(8 in c, False)
+assert_eq(8 not in c, True)
+assert_eq(~c, -7)
+d = c + 1
+assert_eq(repr(c), "counter(7)")
+assert_eq(repr(d), "counter(8)")
+assert_eq(repr(1 + c), "counter(8)")
+assert_eq(repr(3 * c), "counter(21)")
+"#,
+    );
+    a.fail("counter() * 3", "not supported");
+    a.fail("c = counter()\nc += 'x'", "not supported");
+    a.fail("-counter()", "not supported");
+    a.fail(
+        r#"
+def f():
+    c = counter()
+    c += 2
+    for x in c:
+        c += 1
+f()
+"#,
+        "mutate an iterable",
+    );
+    // Frozen values fall back to `x = x + y`
+    a.module("m", "frozen = counter()");
+    a.pass(
+        r#"
+load("m", "frozen")
+x = frozen
+x += 2
+assert_eq(repr(x), "counter(2)")
+assert_eq(repr(frozen), "counter(0)")
+"#,
+    );
+    a.fail("~True", "not supported");
+}
+
+#[test]
+fn test_unpack_value() {
+    use either::Either;
+
+    #[derive(UnpackValue)]
+    struct Target<'v> {
+        name: &'v str,
+        srcs: Vec<&'v str>,
+        r#type: Option<String>,
+    }
+
+    #[derive(UnpackValue)]
+    struct Pair<'a, T> {
+        key: &'a str,
+        value: T,
+    }
+
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn describe(target: Target<'v>) -> String {
+            Ok(format!(
+                "{} {} {}",
+                target.name,
+                target.srcs.join(","),
+                target.r#type.as_deref().unwrap_or("-")
+            ))
+        }
+
+        fn entry(ref x: Pair<'v, i32>) -> String {
+            Ok(format!("{}={}", x.key, x.value))
+        }
+
+        fn pair(ref x: (i32, Either<i32, &str>)) -> String {
+            Ok(match x.1 {
+                Either::Left(i) => format!("{} {}", x.0, i),
+                Either::Right(s) => format!("{} {:?}", x.0, s),
+            })
+        }
+
+        fn triple_sum(ref x: (i32, i32, i32)) -> i32 {
+            Ok(x.0 + x.1 + x.2)
+        }
+
+        fn count_none(ref xs: Vec<Option<i32>>) -> i32 {
+            Ok(xs.iter().filter(|x| x.is_none()).count() as i32)
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.pass(
+        r#"
+assert_eq(describe({"name": "foo", "srcs": ["a", "b"]}), "foo a,b -")
+assert_eq(describe({"name": "foo", "srcs": [], "type": None}), "foo  -")
+assert_eq(describe(struct(name = "bar", srcs = ["c"], type = "lib")), "bar c lib")
+assert_eq(entry({"key": "a", "value": 1}), "a=1")
+assert_eq(pair((1, 2)), "1 2")
+assert_eq(pair((1, "x")), "1 \"x\"")
+assert_eq(triple_sum((1, 2, 3)), 6)
+assert_eq(count_none([1, None, 2, None]), 2)
+"#,
+    );
+    a.fail("describe({'srcs': []})", "Type of parameter");
+    a.fail(
+        "describe({'name': 'foo', 'srcs': [1]})",
+        "Type of parameter",
+    );
+    a.fail("describe(['foo'])", "Type of parameter");
+    a.fail("entry({'key': 'a', 'value': 'b'})", "Type of parameter");
+    a.fail("pair((1, True))", "Type of parameter");
+    a.fail("triple_sum((1, 2))", "Type of parameter");
+}
+
+#[test]
+fn test_module_parameters() {
+    use crate::values::ValueOf;
+
+    const STEP: i32 = 2 * 5;
+
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn scaled(
+            x: i32,
+            #[kw_only]
+            #[default(STEP + 1)]
+            by: i32,
+            #[kw_only] label: Option<&str>,
+        ) -> String {
+            Ok(format!("{}{}", label.unwrap_or(""), x * by))
+        }
+
+        fn offset(#[default(STEP - 3)] x: Value) -> Value<'v> {
+            Ok(x)
+        }
+
+        fn sum_ints(args: Vec<ValueOf<'v, i32>>) -> i32 {
+            Ok(args.iter().map(|x| x.typed).sum())
+        }
+
+        fn call_twice(f: Value) -> Value<'v> {
+            eval.eval_function(f, &[], &[])?;
+            eval.eval_function(f, &[], &[])
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.pass(
+        r#"
+assert_eq(scaled(2), "22")
+assert_eq(scaled(2, by = 3, label = "x"), "x6")
+assert_eq(repr(scaled), "scaled(x, *, by = ..., label = ...)")
+assert_eq(offset(), 7)
+assert_eq(offset(1), 1)
+assert_eq(sum_ints(), 0)
+assert_eq(sum_ints(1, 2, 3), 6)
+xs = []
+def push():
+    xs.append(1)
+    return len(xs)
+assert_eq(call_twice(push), 2)
+"#,
+    );
+    a.fail("scaled(2, 3)", "extra positional");
+    a.fail("sum_ints(1, 'a')", "Type of parameter");
+}
+
+#[test]
+fn test_into_value() {
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        path::PathBuf,
+        time::Duration,
+    };
+
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn small(x: i32) -> u8 {
+            Ok(x as u8)
+        }
+
+        fn size(x: i32) -> u64 {
+            Ok(x as u64 * 1000)
+        }
+
+        fn counts(ref xs: Vec<&str>) -> HashMap<String, usize> {
+            let mut res = HashMap::new();
+            for x in xs {
+                *res.entry(x.to_owned()).or_insert(0) += 1;
+            }
+            Ok(res)
+        }
+
+        fn ordered() -> BTreeMap<i32, bool> {
+            Ok(vec![(3, true), (1, false)].into_iter().collect())
+        }
+
+        fn unique(ref xs: Vec<i32>) -> HashSet<i32> {
+            Ok(xs.into_iter().collect())
+        }
+
+        fn unique_strings(ref xs: Vec<String>) -> BTreeSet<String> {
+            Ok(xs.into_iter().collect())
+        }
+
+        fn path(ref x: &str) -> PathBuf {
+            Ok(PathBuf::from(x))
+        }
+
+        fn timeout(ref x: Duration) -> Duration {
+            Ok(x * 2)
+        }
+
+        fn half(ref x: Duration) -> Duration {
+            Ok(x / 2)
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.pass(
+        r#"
+assert_eq(small(200), 200)
+assert_eq(size(1000), 1000000)
+assert_eq(counts(["b", "a", "b"]), {"a": 1, "b": 2})
+assert_eq(list(counts(["b", "a", "b"]).keys()), ["a", "b"])
+assert_eq(list(ordered().items()), [(1, False), (3, True)])
+assert_eq(unique([3, 1, 3, 2]), [1, 2, 3])
+assert_eq(unique_strings(["y", "x", "y"]), ["x", "y"])
+assert_eq(path("foo/bar.txt"), "foo/bar.txt")
+assert_eq(timeout(5), 10)
+assert_eq(half(4), 2)
+"#,
+    );
+    a.fail("size(3000000)", "overflow");
+    a.fail("timeout(-1)", "Type of parameter");
+    a.fail("half(3)", "not a whole number of seconds");
+}
+
+#[test]
+fn test_into_frozen_value() {
+    use crate::values::{FrozenHeap, IntoFrozenValue};
+    use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+    let heap = FrozenHeap::new();
+    let x = vec![(2, 3000u64), (1, 4000)]
+        .into_iter()
+        .collect::<HashMap<i32, u64>>();
+    assert_eq!(
+        x.into_frozen_value(&heap).unwrap().to_string(),
+        "{1: 4000, 2: 3000}"
+    );
+    let x = vec![("a", PathBuf::from("b"))]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(
+        x.into_frozen_value(&heap).unwrap().to_string(),
+        "{\"a\": \"b\"}"
+    );
+    assert_eq!(
+        Duration::from_secs(60)
+            .into_frozen_value(&heap)
+            .unwrap()
+            .to_string(),
+        "60"
+    );
+    assert!(u64::MAX.into_frozen_value(&heap).is_err());
+    assert!(Duration::from_millis(1500)
+        .into_frozen_value(&heap)
+        .is_err());
+}
+
+#[test]
+// Test that we can express something that loads symbols into the exported module
+fn test_load_symbols() {
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn load_symbol(name: &str, value: Value<'v>) -> NoneType {
+            ctx.set_module_variable_at_some_point(name, value, heap)?;
+            Ok(NoneType)
+        }
+    }
+
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.module("a", "load_symbol('x', 6*7)");
+    a.is_true("load('a', 'x'); x == 42")
+}
+
+#[test]
+// Test that we can express something that loads symbols into the exported module,
+// but not using the very dubious `set_module_variable_at_some_point`.
+fn test_load_symbols_extra() -> anyhow::Result<()> {
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn load_symbol(name: &str, value: Value<'v>) -> NoneType {
+            let extra = ctx.extra_v.unwrap().downcast_ref::<Extra<'v>>().unwrap();
+            extra.0.lock().unwrap().insert(name.to_owned(), value);
+            Ok(NoneType)
+        }
+    }
+
+    #[derive(AnyLifetime, Default)]
+    struct Extra<'v>(Arc<Mutex<HashMap<String, Value<'v>>>>);
+
+    let modu = Module::new();
+    let globals = GlobalsBuilder::extended().with(module).build();
+    let mut ctx = Evaluator::new(&modu, &globals);
+    let extra = Extra::default();
+    ctx.extra_v = Some(&extra);
+    ctx.eval_module(AstModule::parse(
+        "a",
+        "load_symbol('x', 6*7)".to_owned(),
+        &Dialect::Extended,
+    )?)?;
+
+    for (name, value) in extra.0.lock().unwrap().iter() {
+        modu.set(name, *value);
+    }
+    let mut a = Assert::new();
+    a.globals(globals);
+    a.module_add("a", modu.freeze());
+    a.is_true("load('a', 'x'); x == 42");
+    Ok(())
+}
+
+#[test]
+fn test_load_exports() {
+    let lib = Module::new();
+    lib.set("x", Value::new_int(1));
+    lib.set("helper", Value::new_int(2));
+    lib.set_exports(vec!["x"]);
+    let mut a = Assert::new();
+    a.module_add("lib", lib.freeze());
+    a.is_true("load('lib', 'x')\nx == 1");
+    a.fail("load('lib', 'helper')", "not exported");
+}
+
+#[test]
+fn test_load_allowlist() -> anyhow::Result<()> {
+    use crate::eval::{FileLoader, ReturnFileLoader};
+
+    // Only lets the names in `allowed` be loaded
+    struct AllowLoader<'a> {
+        loader: ReturnFileLoader<'a>,
+        allowed: &'a [&'a str],
+    }
+
+    impl FileLoader for AllowLoader<'_> {
+        fn load(&mut self, path: &str) -> anyhow::Result<FrozenModule> {
+            self.loader.load(path)
+        }
+
+        fn check_load(&self, _path: &str, symbol: &str) -> anyhow::Result<()> {
+            if self.allowed.contains(&symbol) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "only {} may be loaded",
+                    self.allowed.join(", ")
+                ))
+            }
+        }
+    }
+
+    let lib = Module::new();
+    lib.set("x", Value::new_int(1));
+    lib.set("y", Value::new_int(2));
+    let lib = lib.freeze();
+    let mut modules = HashMap::new();
+    modules.insert("lib", &lib);
+    let globals = Globals::standard();
+
+    let eval = |code: &str| -> anyhow::Result<()> {
+        let modu = Module::new();
+        let mut loader = AllowLoader {
+            loader: ReturnFileLoader { modules: &modules },
+            allowed: &["x"],
+        };
+        let mut ctx = Evaluator::new(&modu, &globals);
+        ctx.set_loader(&mut loader);
+        ctx.eval_module(AstModule::parse("a", code.to_owned(), &Dialect::Extended)?)?;
+        Ok(())
+    };
+
+    eval("load('lib', 'x')")?;
+    eval("load('lib', z = 'x')")?;
+    let err = eval("load('lib', 'x', 'y')").unwrap_err().to_string();
+    assert!(
+        err.contains("Cannot load `y` from `lib`: only x may be loaded"),
+        "{}",
+        err
+    );
+    // Checked before any of the code runs
+    let err = eval("fail('ran')\nload('lib', z = 'y')")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Cannot load `y`"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_load_resolve() -> anyhow::Result<()> {
+    use crate::eval::{FileLoader, LoadLabel, ReturnFileLoader};
+
+    // Resolves labels relative to `//foo:BUILD`, with `defs` as an alias
+    struct LabelLoader<'a> {
+        loader: ReturnFileLoader<'a>,
+        current: LoadLabel,
+    }
+
+    impl FileLoader for LabelLoader<'_> {
+        fn load(&mut self, path: &str) -> anyhow::Result<FrozenModule> {
+            self.loader.load(path)
+        }
+
+        fn resolve(&self, path: &str) -> anyhow::Result<String> {
+            let path = match path {
+                "defs" => "//lib:defs.bzl",
+                _ => path,
+            };
+            Ok(LoadLabel::parse(path, Some(&self.current))?.to_string())
+        }
+    }
+
+    let lib = Module::new();
+    lib.set("x", Value::new_int(1));
+    let lib = lib.freeze();
+    let mut modules = HashMap::new();
+    modules.insert("//lib:defs.bzl", &lib);
+    let globals = Globals::standard();
 
-        fn freeze(mut self: Box<Self>, _freezer: &Freezer) -> Box<dyn SimpleValue> {
-            self.mutable = false;
-            self
+    let eval = |code: &str| -> anyhow::Result<()> {
+        let modu = Module::new();
+        let mut loader = LabelLoader {
+            loader: ReturnFileLoader { modules: &modules },
+            current: LoadLabel::parse("//foo:BUILD", None)?,
+        };
+        let mut ctx = Evaluator::new(&modu, &globals);
+        ctx.set_loader(&mut loader);
+        ctx.eval_module(AstModule::parse("a", code.to_owned(), &Dialect::Extended)?)?;
+        Ok(())
+    };
+
+    eval("load('//lib:defs.bzl', 'x')")?;
+    eval("load('../lib/defs.bzl', 'x')")?;
+    eval("load('defs', 'x')")?;
+    // Resolved before any of the code runs, with the error at the path
+    let err = eval("fail('ran')\nload('//lib', 'x')").unwrap_err();
+    let diag = err.downcast_ref::<Diagnostic>().unwrap();
+    let (span, codemap) = diag.span.as_ref().unwrap();
+    assert_eq!(codemap.source_slice(*span), "'//lib'");
+    let err = err.to_string();
+    assert!(err.contains("Cannot resolve load of `//lib`"), "{}", err);
+    assert!(err.contains("expected `:` before the file name"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_prelude() -> anyhow::Result<()> {
+    let globals = Globals::standard();
+    let eval = |prelude: &[FrozenModule], code: &str| -> anyhow::Result<Module> {
+        let modu = Module::new();
+        {
+            let mut ctx = Evaluator::new(&modu, &globals);
+            ctx.set_prelude(prelude);
+            ctx.eval_module(AstModule::parse("a", code.to_owned(), &Dialect::Extended)?)?;
         }
+        Ok(modu)
+    };
 
-        unsafe fn walk(&mut self, _walker: &Walker) {}
+    let prelude = eval(
+        &[],
+        r#"
+def rule(name):
+    return "rule:" + name
+def len(x):
+    return -1
+_private = 1
+hidden = 2
+"#,
+    )?;
+    prelude.set_exports(vec!["rule", "len", "_private"]);
+    let prelude = prelude.freeze();
+    let other = Module::new();
+    other.set("rule", Value::new_int(3));
+    other.set("hidden", Value::new_int(4));
+    let other = other.freeze();
+
+    let modu = eval(
+        &[prelude.clone()],
+        r#"
+def f():
+    return rule("f")
+x = f()
+y = len([1, 2])
+"#,
+    )?;
+    assert_eq!(modu.get("x").unwrap().unpack_str(), Some("rule:f"));
+    // The prelude takes priority over the globals
+    assert_eq!(modu.get("y").unwrap().unpack_int(), Some(-1));
+    // Nothing from the prelude is added to the module
+    assert!(modu.get("rule").is_none());
+
+    // Definitions in the code take priority over the prelude
+    let modu = eval(&[prelude.clone()], "def rule(x): return x\nx = rule('a')")?;
+    assert_eq!(modu.get("x").unwrap().unpack_str(), Some("a"));
+
+    // Private and unexported names aren't in scope, and are reported before running
+    for name in &["_private", "hidden"] {
+        let code = format!("fail('ran')\nx = {}", name);
+        let err = eval(&[prelude.clone()], &code).unwrap_err().to_string();
+        assert!(err.contains("not found"), "{}", err);
+    }
+    // Later modules win
+    let modu = eval(&[prelude, other], "x = rule\ny = hidden")?;
+    assert_eq!(modu.get("x").unwrap().unpack_int(), Some(3));
+    assert_eq!(modu.get("y").unwrap().unpack_int(), Some(4));
+    Ok(())
+}
 
-        fn export_as(&mut self, _heap: &Heap, variable_name: &str) {
-            self.named = variable_name.to_owned();
+#[test]
+fn test_native_call_span() {
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn call_source() -> String {
+            let (span, codemap) = ctx.call_span().unwrap();
+            Ok(codemap.source_slice(span).to_owned())
+        }
+
+        fn bad() -> NoneType {
+            Err(anyhow::anyhow!("bad things"))
         }
     }
 
-    impl SimpleValue for Exporter {}
+    let mut a = Assert::new();
+    a.globals_add(module);
+    a.is_true("call_source() == 'call_source()'");
+    a.is_true("def f():\n    return call_source( )\nf() == 'call_source( )'");
+    // Errors from native functions point at the call
+    let err = a.fail("x = 1\nx = bad( )", "bad things");
+    let diag = err.downcast_ref::<Diagnostic>().unwrap();
+    let (span, codemap) = diag.span.as_ref().unwrap();
+    assert_eq!(codemap.source_slice(*span), "bad( )");
+}
 
+#[test]
+fn test_provenance() -> anyhow::Result<()> {
     #[starlark_module]
-    fn exporter(builder: &mut GlobalsBuilder) {
-        fn exporter(value: i32) -> Exporter {
-            Ok(Exporter {
-                mutable: true,
-                named: "unnamed".to_owned(),
-                value,
-            })
+    fn module(builder: &mut GlobalsBuilder) {
+        fn secret(name: &str) -> Value<'v> {
+            let res = heap.alloc(format!("secret-{}", name));
+            ctx.add_provenance(res, name);
+            Ok(res)
+        }
+
+        fn quote(x: &str) -> String {
+            Ok(format!("'{}'", x))
         }
     }
 
-    let mut a = Assert::new();
-    a.globals_add(exporter);
-    a.module(
+    let modu = Module::new();
+    let globals = GlobalsBuilder::extended().with(module).build();
+    let mut ctx = Evaluator::new(&modu, &globals);
+    let mut policy = ProvenancePolicy::default();
+    policy.sanitizers.insert("quote".to_owned());
+    ctx.enable_provenance(policy);
+    ctx.eval_module(AstModule::parse(
         "a",
-        "x = exporter(1); y = x; longer_name = exporter(2); arrayed = [exporter(3)]",
+        r#"
+key = secret("key")
+user = secret("user")
+greeting = "hello " + user
+label = "%s!" % user
+pair = (user, key)
+def wrap(x):
+    return {"v": x}
+wrapped = wrap(key)
+upper = user.upper()
+joined = "-".join([user, "a"])
+quoted = quote(key)
+first = [c for c in user.elems()][0]
+modified = "a"
+modified += key
+plain = "a" + "b" * 2
+"#
+        .to_owned(),
+        &Dialect::Extended,
+    )?)?;
+    let provenance = |name: &str| ctx.provenance(modu.get(name).unwrap());
+    assert_eq!(provenance("key"), vec!["key"]);
+    assert_eq!(provenance("greeting"), vec!["user"]);
+    assert_eq!(provenance("label"), vec!["user"]);
+    assert_eq!(provenance("pair"), vec!["key", "user"]);
+    assert_eq!(provenance("wrapped"), vec!["key"]);
+    assert_eq!(provenance("upper"), vec!["user"]);
+    assert_eq!(provenance("joined"), vec!["user"]);
+    assert_eq!(provenance("first"), vec!["user"]);
+    assert_eq!(provenance("modified"), vec!["key"]);
+    assert!(provenance("quoted").is_empty());
+    assert!(provenance("plain").is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_thaw_on_write_stats() -> anyhow::Result<()> {
+    let run = |disable: bool| -> anyhow::Result<ThawOnWriteStats> {
+        let modu = Module::new();
+        let globals = Globals::standard();
+        let mut ctx = Evaluator::new(&modu, &globals);
+        if disable {
+            ctx.disable_thaw_on_write();
+        }
+        ctx.eval_module(AstModule::parse(
+            "a",
+            r#"
+xs = [1, 2, 3]
+ys = {"a": 1}
+copy = xs[:]
+copy2 = list(copy)
+ys2 = dict(ys)
+ys3 = dict(ys, b = 2)
+xs.append(4)
+copy.append(5)
+ys2["c"] = 3
+# Avoid literals in the checks, since they would be counted too
+if len(xs) != 4 or len(copy) != 4 or copy[3] != 5 or len(copy2) != 3:
+    fail("lists were not copied")
+if len(ys) != 1 or len(ys2) != 2 or len(ys3) != 2:
+    fail("dicts were not copied")
+"#
+            .to_owned(),
+            &Dialect::Standard,
+        )?)?;
+        Ok(modu.heap().thaw_on_write_stats())
+    };
+    assert_eq!(
+        run(false)?,
+        ThawOnWriteStats {
+            shared: 5,
+            thawed: 3
+        }
     );
-    // could reasonably be x=1 or y=1 twice, since the order
-    // of calls to export_as is not defined
-    let opt1 = "(x=1, x=1, longer_name=2, unnamed=3)".to_owned();
-    let opt2 = opt1.replace("x=", "y=");
+    assert_eq!(run(true)?, ThawOnWriteStats::default());
+    Ok(())
+}
 
-    a.is_true(&format!(
-        r#"
-load('a', 'x', 'y', 'longer_name', 'arrayed')
-v = str((x, y, longer_name, arrayed[0]))
-v == '{}' or v == '{}'"#,
-        opt1, opt2
-    ))
+#[test]
+fn test_check_module() -> anyhow::Result<()> {
+    let modu = Module::new();
+    let globals = Globals::standard();
+    let ctx = Evaluator::new(&modu, &globals);
+    let parse = |code: &str| AstModule::parse("a", code.to_owned(), &Dialect::Standard);
+    let errors = ctx.check_module(parse("x = y\ndef f():\n    return z + len([])\nw = x + q")?);
+    assert_eq!(
+        errors
+            .iter()
+            .map(|e| e.message.to_string())
+            .collect::<Vec<_>>(),
+        vec![
+            "Variable `y` not found",
+            "Variable `z` not found",
+            "Variable `q` not found"
+        ]
+    );
+    // The module wasn't run
+    assert!(modu.get("x").is_none());
+    assert!(ctx.check_module(parse("x = len([])")?).is_empty());
+    Ok(())
 }
 
 #[test]
-// Test that we can express something that loads symbols into the exported module
-fn test_load_symbols() {
+fn test_did_you_mean() {
+    assert::fail(
+        "def f(values):\n    return valeus\nf([])",
+        "Variable `valeus` not found, did you mean `values`?",
+    );
+    assert::fail(
+        "prnt('hello')",
+        "Variable `prnt` not found, did you mean `print`?",
+    );
+    let mut a = Assert::new();
+    a.module("m", "helper = 1\n_private = 2");
+    a.fail(
+        "load('m', 'helper')\nhelpr",
+        "Variable `helpr` not found, did you mean `helper`?",
+    );
+}
+
+#[test]
+fn test_module_rollback() -> anyhow::Result<()> {
     #[starlark_module]
     fn module(builder: &mut GlobalsBuilder) {
-        fn load_symbol(name: &str, value: Value<'v>) -> NoneType {
-            ctx.set_module_variable_at_some_point(name, value, heap)?;
+        // Only safe at the top level of a module, which is the only place we call it
+        fn gc() -> NoneType {
+            unsafe { ctx.heap.garbage_collect(|walker| ctx.walk(walker)) };
             Ok(NoneType)
         }
     }
 
-    let mut a = Assert::new();
-    a.globals_add(module);
-    a.module("a", "load_symbol('x', 6*7)");
-    a.is_true("load('a', 'x'); x == 42")
+    let modu = Module::new();
+    let globals = GlobalsBuilder::extended().with(module).build();
+    let mut ctx = Evaluator::new(&modu, &globals);
+    let parse = |code: &str| AstModule::parse("a", code.to_owned(), &Dialect::Standard);
+    ctx.eval_module(parse("xs = [1]\ns = 'a' + str(xs)")?)?;
+    modu.checkpoint();
+    // After the GC, only the checkpoint refers to the old value of `s`
+    assert!(ctx
+        .eval_module(parse("xs.append(2)\ns = 'b'\nt = 1\ngc()\nfail('oops')")?)
+        .is_err());
+    assert!(modu.rollback());
+    assert!(!modu.rollback());
+    assert_eq!(modu.get("s").unwrap().unpack_str(), Some("a[1]"));
+    // Mutation isn't undone
+    assert_eq!(modu.get("xs").unwrap().to_str(), "[1, 2]");
+    assert!(modu.get("t").is_none());
+    assert!(modu.names().get_name("t").is_none());
+    ctx.eval_module(parse("t = s + '!'")?)?;
+    assert_eq!(modu.get("t").unwrap().unpack_str(), Some("a[1]!"));
+
+    modu.checkpoint();
+    assert!(modu.discard_checkpoint());
+    assert!(!modu.rollback());
+    assert!(modu.get("t").is_some());
+    Ok(())
 }
 
 #[test]
-// Test that we can express something that loads symbols into the exported module,
-// but not using the very dubious `set_module_variable_at_some_point`.
-fn test_load_symbols_extra() -> anyhow::Result<()> {
+fn test_frozen_string_cache() -> anyhow::Result<()> {
+    let modu = Module::new();
+    let globals = Globals::standard();
+    let mut ctx = Evaluator::new(&modu, &globals);
+    let code = "x = {'a': [1, 'b']}\ny = 'text'";
+    ctx.eval_module(AstModule::parse("a", code.to_owned(), &Dialect::Standard)?)?;
+    mem::drop(ctx);
+    let frozen = modu.freeze();
+    let x = frozen.get("x").unwrap();
+    assert_eq!(&*x.to_repr_cached(), "{\"a\": [1, \"b\"]}");
+    assert_eq!(&*x.to_json_cached(), "{\"a\": [1,\"b\"]}");
+    // Later lookups of the same value share the string
+    let again = frozen.get("x").unwrap();
+    assert!(Arc::ptr_eq(&x.to_repr_cached(), &again.to_repr_cached()));
+    assert!(Arc::ptr_eq(&x.to_json_cached(), &again.to_json_cached()));
+    let y = frozen.get("y").unwrap();
+    assert_eq!(&*y.to_str_cached(), "text");
+    assert_eq!(&*y.to_repr_cached(), "\"text\"");
+    Ok(())
+}
+
+#[test]
+fn test_extra_by_type() -> anyhow::Result<()> {
     #[starlark_module]
     fn module(builder: &mut GlobalsBuilder) {
-        fn load_symbol(name: &str, value: Value<'v>) -> NoneType {
-            let extra = ctx.extra_v.unwrap().downcast_ref::<Extra<'v>>().unwrap();
-            extra.0.lock().unwrap().insert(name.to_owned(), value);
-            Ok(NoneType)
+        fn tag(name: &str) -> String {
+            let count = ctx.get_extra::<Count>().unwrap();
+            *count.0.lock().unwrap() += 1;
+            Ok(format!("{}{}", ctx.get_extra::<Prefix>().unwrap().0, name))
         }
     }
 
+    #[derive(AnyLifetime)]
+    struct Prefix(String);
+
     #[derive(AnyLifetime, Default)]
-    struct Extra<'v>(Arc<Mutex<HashMap<String, Value<'v>>>>);
+    struct Count(Mutex<usize>);
 
     let modu = Module::new();
     let globals = GlobalsBuilder::extended().with(module).build();
     let mut ctx = Evaluator::new(&modu, &globals);
-    let extra = Extra::default();
-    ctx.extra_v = Some(&extra);
-    ctx.eval_module(AstModule::parse(
+    let ignored = Prefix("ignored-".to_owned());
+    let prefix = Prefix("tag-".to_owned());
+    let count = Count::default();
+    ctx.set_extra(&ignored);
+    ctx.set_extra(&prefix);
+    ctx.set_extra(&count);
+    let res = ctx.eval_module(AstModule::parse(
         "a",
-        "load_symbol('x', 6*7)".to_owned(),
+        "tag('x') + tag('y')".to_owned(),
         &Dialect::Extended,
     )?)?;
-
-    for (name, value) in extra.0.lock().unwrap().iter() {
-        modu.set(name, *value);
-    }
-    let mut a = Assert::new();
-    a.globals(globals);
-    a.module_add("a", modu.freeze());
-    a.is_true("load('a', 'x'); x == 42");
+    assert_eq!(res.unpack_str(), Some("tag-xtag-y"));
+    assert_eq!(*count.0.lock().unwrap(), 2);
     Ok(())
 }
 
@@ -1120,6 +2437,27 @@ xs == ys
     );
 }
 
+#[test]
+fn test_compare_deep() {
+    // Nested deeper than the limit on recursion, so must not recurse per level
+    let mut a = Assert::new();
+    a.disable_gc();
+    a.is_true(
+        r#"
+def nest(f):
+    x = 1
+    for i in range(1000):
+        x = f(x)
+    return x
+a = nest(lambda x: [x])
+b = nest(lambda x: [x])
+c = nest(lambda x: (x,))
+d = nest(lambda x: {"k": x})
+a == b and not (a < b) and {c: 1}[nest(lambda x: (x,))] == 1 and d == nest(lambda x: {"k": x})
+"#,
+    );
+}
+
 #[test]
 fn test_load_reexport() {
     let mut a = Assert::new();
@@ -1423,6 +2761,33 @@ mod value_of {
     }
 }
 
+#[test]
+fn test_in() {
+    // From the "Membership tests" section of the spec
+    assert::all_true(
+        r#"
+1 in [1, 2, 3]
+4 not in (1, 2, 3)
+"a" in {"a": 1}
+"b" not in {"a": 1}
+"bc" in "abcd"
+"" in "abc"
+"" in ""
+"abc" in "abc"
+"abcd" not in "abc"
+not ("" not in "x")
+"#,
+    );
+    assert::fail(
+        "3 in 'foo'",
+        "Operation `in` not supported for types `int` and `string`",
+    );
+    assert::fail(
+        "'a' in 1",
+        "Operation `in` not supported for types `string` and `int`",
+    );
+}
+
 #[test]
 fn test_in_range() {
     // Go Starlark considers this a type error (I think that is a mistake)
@@ -1508,11 +2873,6 @@ fn test_go() {
             // test_in_range
             "True in range(3)",
             "\"one\" in range(10)",
-            // Starlark spec demands elems() return a list of int, Go returns list of string
-            "\"abc\".elems()",
-            "\"def\".elems()",
-            "\"hijk\".elems()",
-            "(\"a\", \"d\", \"h\")", // end of a multiline that tests elems()
             // We added copy, which throws off the assert
             "dir({})[:3]",
             "dir([])[:3]",
@@ -1566,14 +2926,319 @@ fn test_go() {
     // Skip recursion.star, we don't support `while` loops, which is what this mostly tests
     // Skip set.star, we don't support set
     // Skip string.star, our String's are fundamentally different
-    assert.conformance(
-        // Our elems() works differently
-        &ignore_bad_lines(
-            &test_case!("tuple.star").replace("\"abc\".elems()", "[\"a\",\"b\",\"c\"]"),
-            &[
-                "1000000 * 1000000", // Some tests check that you can't create too large tuples, but that's not principled, so we allow it
-                // But it takes approximately forever, so doing it is a bad idea.
-            ],
-        ),
+    assert.conformance(&ignore_bad_lines(
+        test_case!("tuple.star"),
+        &[
+            "1000000 * 1000000", // Some tests check that you can't create too large tuples, but that's not principled, so we allow it
+                                 // But it takes approximately forever, so doing it is a bad idea.
+        ],
+    ));
+}
+
+#[test]
+fn test_freeze_cyclic() {
+    let program = r#"
+xs = [1, 2, 3]
+xs[1] = xs
+d = {"x": 1}
+d["x"] = [0, d]
+ys = []
+s = struct(y = ys)
+ys.append(s)
+"#;
+    let env = Module::new();
+    let globals = Globals::extended();
+    let mut eval = Evaluator::new(&env, &globals);
+    let ast = AstModule::parse("cycle.bzl", program.to_owned(), &Dialect::Extended).unwrap();
+    eval.eval_module(ast).unwrap();
+    let frozen = env.freeze();
+    assert_eq!(frozen.get("xs").unwrap().value().to_str(), "[1, [...], 3]");
+    assert_eq!(
+        frozen.get("d").unwrap().value().to_str(),
+        r#"{"x": [0, {...}]}"#
+    );
+    assert_eq!(frozen.get("s").unwrap().value().to_str(), "struct(y=[...])");
+
+    let mut a = Assert::new();
+    a.module_add("cycle.bzl", frozen);
+    a.pass(
+        r#"
+load("cycle.bzl", "xs", "d", "s")
+assert_eq(xs[1][1][1][2], 3)
+assert_eq(d["x"][1]["x"][0], 0)
+assert_eq(s.y[0].y[0].y, s.y)
+assert_eq(str([xs]), "[[1, [...], 3]]")
+"#,
+    );
+}
+
+#[test]
+fn test_conformance_report() {
+    let code = r#"
+load("assert.star", "assert")
+assert.eq(1 + 1, 2)
+---
+load("assert.star", "assert")
+assert.eq(1 + 1, 3)
+---
+x = 1
+fail("bad") ### bad
+---
+unsupported_thing()
+"#;
+    let report = Assert::new().conformance_report(code, &["unsupported_thing"]);
+    assert_eq!(report.passed, 2);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.failed[0].0.contains("1 + 1, 3"));
+    assert!(!report.is_success());
+}
+
+#[test]
+fn test_docstring() {
+    let program = r#"
+"""The module."""
+def f():
+    """The function."""
+    pass
+def g():
+    pass
+    "Not a docstring"
+"""Not a module docstring"""
+"#;
+    let env = Module::new();
+    let globals = Globals::standard();
+    let mut eval = Evaluator::new(&env, &globals);
+    let ast = AstModule::parse("doc.bzl", program.to_owned(), &Dialect::Standard).unwrap();
+    assert_eq!(ast.docstring(), Some("The module."));
+    eval.eval_module(ast).unwrap();
+    let frozen = env.freeze();
+    assert_eq!(frozen.documentation(), Some("The module."));
+
+    let mut a = Assert::new();
+    a.module_add("doc.bzl", frozen);
+    a.pass(
+        r#"
+load("doc.bzl", "f", "g")
+def h():
+    """Local."""
+assert_eq(f.__doc__, "The function.")
+assert_eq(g.__doc__, None)
+assert_eq(h.__doc__, "Local.")
+assert_eq(hasattr(f, "__doc__"), True)
+assert_eq(dir(h), ["__doc__", "__location__"])
+assert_eq((lambda: "x").__doc__, None)
+"#,
+    );
+}
+
+#[test]
+fn test_lazy_globals() {
+    let computed = Arc::new(AtomicUsize::new(0));
+    let globals = {
+        let computed = computed.clone();
+        GlobalsBuilder::standard()
+            .with(|builder| {
+                builder.set_lazy("table", move || {
+                    computed.fetch_add(1, Ordering::SeqCst);
+                    vec![1, 2, 3]
+                })
+            })
+            .build()
+    };
+    assert!(globals.names().contains(&"table".to_owned()));
+    let run = |program: &str| {
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        let ast = AstModule::parse("lazy.star", program.to_owned(), &Dialect::Extended).unwrap();
+        eval.eval_module(ast).unwrap();
+        module.freeze()
+    };
+    run("x = len([1])");
+    assert_eq!(computed.load(Ordering::SeqCst), 0);
+    run("x = table[0]");
+    let frozen = run("x = table");
+    assert_eq!(computed.load(Ordering::SeqCst), 1);
+    // The module keeps the value alive, even without the globals
+    drop(globals);
+    assert_eq!(frozen.get("x").unwrap().value().to_repr(), "[1, 2, 3]");
+}
+
+#[test]
+fn test_globals_merge() {
+    let left = || {
+        GlobalsBuilder::new().with(|builder| {
+            builder.set("a", 1);
+            builder.set("both", 1);
+            builder.struct_("ns", |builder| {
+                builder.set("x", 1);
+                builder.struct_("inner", |builder| builder.set("y", 1));
+            });
+        })
+    };
+    let right = |y| {
+        GlobalsBuilder::new().with(|builder| {
+            builder.set("both", 2);
+            builder.struct_("ns", |builder| {
+                builder.set("x", 2);
+                builder.struct_("inner", |builder| {
+                    builder.set("z", 2);
+                    if y {
+                        builder.set("y", 2);
+                    }
+                });
+            });
+        })
+    };
+    let check = |conflict, program| {
+        let mut globals = left();
+        globals.merge(right(false), conflict).unwrap();
+        let mut a = Assert::new();
+        a.globals(globals.build());
+        a.all_true(program);
+    };
+    check(
+        MergeConflict::PreferLeft,
+        "a == 1\nboth == 1\nns.x == 1\nns.inner.y == 1\nns.inner.z == 2",
+    );
+    check(
+        MergeConflict::PreferRight,
+        "a == 1\nboth == 2\nns.x == 2\nns.inner.y == 1\nns.inner.z == 2",
+    );
+
+    let mut globals = GlobalsBuilder::new().with(|builder| {
+        builder.struct_("ns", |builder| {
+            builder.struct_("inner", |builder| builder.set("y", 1))
+        })
+    });
+    let err = globals
+        .merge(right(true), MergeConflict::Error)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Global `ns.inner.y` is defined by both of the `GlobalsBuilder`s being merged"
+    );
+    // Nothing was merged
+    assert_eq!(globals.build().names(), vec!["ns".to_owned()]);
+}
+
+#[test]
+fn test_definition_location() {
+    let program = r#"
+def f():
+    pass
+
+g = lambda x: x
+"#;
+    let env = Module::new();
+    let globals = Globals::standard();
+    let mut eval = Evaluator::new(&env, &globals);
+    let ast = AstModule::parse("loc.bzl", program.to_owned(), &Dialect::Extended).unwrap();
+    eval.eval_module(ast).unwrap();
+    let frozen = env.freeze();
+    let f = frozen.get("f").unwrap();
+    let loc = f.value().definition_location().unwrap();
+    assert_eq!(loc.file.name(), "loc.bzl");
+    assert_eq!(loc.begin.line, 1);
+    let g = frozen.get("g").unwrap();
+    assert_eq!(g.value().definition_location().unwrap().begin.line, 4);
+    assert!(globals.get("len").unwrap().definition_location().is_none());
+
+    let mut a = Assert::new();
+    a.module_add("loc.bzl", frozen);
+    a.pass(
+        r#"
+load("loc.bzl", "f", "g")
+def h():
+    pass
+assert_eq(f.__location__, "loc.bzl:2")
+assert_eq(g.__location__, "loc.bzl:5")
+assert_eq(h.__location__, "assert.bzl:3")
+assert_eq(hasattr(len, "__location__"), False)
+"#,
     );
 }
+
+#[test]
+fn test_profile_report() {
+    let program = r#"
+def f(x):
+    return [x]
+def g():
+    return [f(i) for i in range(3)]
+g()
+"#;
+    let env = Module::new();
+    let globals = Globals::standard();
+    let mut eval = Evaluator::new(&env, &globals);
+    eval.enable_profiling();
+    let ast = AstModule::parse("prof.bzl", program.to_owned(), &Dialect::Standard).unwrap();
+    eval.eval_module(ast).unwrap();
+
+    let report = env.heap().profile_report();
+    let calls = |name: &str| report.iter().find(|x| x.name == name).map(|x| x.calls);
+    assert_eq!(calls("prof.bzl.f(x)"), Some(3));
+    assert_eq!(calls("prof.bzl.g()"), Some(1));
+    assert!(report.iter().all(|x| x.total_time >= x.self_time));
+    assert!(report.windows(2).all(|x| x[0].self_time >= x[1].self_time));
+}
+
+#[test]
+fn test_profile_report_tail_calls() {
+    // Calls in tail position are still recorded as calls when profiling
+    let program = r#"
+def count(n):
+    if n == 0:
+        return n
+    return count(n - 1)
+count(5)
+"#;
+    let env = Module::new();
+    let globals = Globals::standard();
+    let mut eval = Evaluator::new(&env, &globals);
+    eval.enable_profiling();
+    let ast = AstModule::parse("prof.bzl", program.to_owned(), &Dialect::Extended).unwrap();
+    eval.eval_module(ast).unwrap();
+
+    let report = env.heap().profile_report();
+    let calls = |name: &str| report.iter().find(|x| x.name == name).map(|x| x.calls);
+    assert_eq!(calls("prof.bzl.count(n)"), Some(6));
+}
+
+#[test]
+fn test_injected_variables() {
+    fn eval(config: &str, program: &str) -> anyhow::Result<String> {
+        let env = Module::new();
+        env.inject("config", env.heap().alloc(config));
+        let globals = Globals::standard();
+        let mut eval = Evaluator::new(&env, &globals);
+        let ast = AstModule::parse("config.bzl", program.to_owned(), &Dialect::Extended)?;
+        Ok(eval.eval_module(ast)?.to_str())
+    }
+
+    let program = r#"
+def f():
+    config = "local"
+    return config
+config + "!" + f()
+"#;
+    assert_eq!(eval("debug", program).unwrap(), "debug!local");
+    assert_eq!(eval("release", program).unwrap(), "release!local");
+
+    for program in &[
+        "config = 1",
+        "config += 'x'",
+        "x, config = 1, 2",
+        "for config in []: pass",
+        "def config(): pass",
+        "if False:\n    config = 1",
+    ] {
+        let err = eval("debug", program).unwrap_err().to_string();
+        assert!(
+            err.contains("Cannot assign to variable `config`, which was injected"),
+            "{}: {}",
+            program,
+            err
+        );
+    }
+}
@@ -278,6 +278,69 @@
 //! # fn main(){ run().unwrap(); }
 //! ```
 //!
+//! ## Call async Rust from Starlark
+//!
+//! Evaluation is synchronous, and the values a native function works with borrow from the
+//! heap of the evaluation, so can't be held across an `.await`. To call async code, run the
+//! evaluation on its own thread (e.g. with Tokio's `spawn_blocking`) and have native functions
+//! send requests to the async side, suspending the evaluation until the reply arrives.
+//! Only the evaluation thread waits, the async runtime carries on with other work.
+//! With Tokio the requests would go over a `tokio::sync::mpsc` channel serviced by a task,
+//! and the native function would wait on a `oneshot` reply with `blocking_recv`.
+//!
+//! ```
+//! #[macro_use]
+//! extern crate starlark_module;
+//! # fn run() -> anyhow::Result<()> {
+//! use starlark::environment::{GlobalsBuilder, Module};
+//! use starlark::eval::Evaluator;
+//! use starlark::syntax::{AstModule, Dialect};
+//! use gazebo::any::AnyLifetime;
+//! use std::sync::mpsc::{channel, Sender};
+//! use std::thread;
+//!
+//! // A URL to fetch, and where to send the response
+//! type Request = (String, Sender<String>);
+//!
+//! #[derive(AnyLifetime)]
+//! struct Fetcher(Sender<Request>);
+//!
+//! #[starlark_module]
+//! fn starlark_fetch(builder: &mut GlobalsBuilder) {
+//!     fn fetch(url: &str) -> String {
+//!         let (reply, response) = channel();
+//!         ctx.get_extra::<Fetcher>()
+//!             .unwrap()
+//!             .0
+//!             .send((url.to_owned(), reply))
+//!             .map_err(|_| anyhow::anyhow!("Fetcher has shut down"))?;
+//!         // Suspend the evaluation until the async side has answered
+//!         Ok(response.recv()?)
+//!     }
+//! }
+//!
+//! let (requests, incoming) = channel();
+//! let evaluation = thread::spawn(move || -> anyhow::Result<String> {
+//!     let content = "fetch('a') + fetch('b')";
+//!     let ast = AstModule::parse("fetch.star", content.to_owned(), &Dialect::Standard)?;
+//!     let globals = GlobalsBuilder::new().with(starlark_fetch).build();
+//!     let module = Module::new();
+//!     let fetcher = Fetcher(requests);
+//!     let mut eval = Evaluator::new(&module, &globals);
+//!     eval.set_extra(&fetcher);
+//!     Ok(eval.eval_module(ast)?.to_str())
+//! });
+//!
+//! // Stand-in for the async runtime, answering requests until the evaluation finishes
+//! for (url, reply) in incoming {
+//!     reply.send(format!("<{}>", url))?;
+//! }
+//! assert_eq!(evaluation.join().unwrap()?, "<a><b>");
+//! # Ok(())
+//! # }
+//! # fn main(){ run().unwrap(); }
+//! ```
+//!
 //! ## Defining Rust objects that are used from Starlark
 //!
 //! Finally, we can define our own types in Rust which live in the Starlark heap.
@@ -380,6 +443,8 @@ mod macros;
 pub mod analysis;
 
 pub mod assert;
+#[cfg(feature = "bench")]
+pub mod bench;
 pub mod codemap;
 pub mod collections;
 mod debug;
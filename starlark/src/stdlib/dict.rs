@@ -20,9 +20,13 @@
 use crate as starlark;
 use crate::{
     environment::GlobalsBuilder,
-    values::{dict::Dict, none::NoneType, Value},
+    values::{
+        dict::Dict,
+        dict_view::{DictView, DictViewKind},
+        none::NoneType,
+        Value,
+    },
 };
-use anyhow::anyhow;
 use gazebo::cell::ARef;
 use std::mem;
 
@@ -104,7 +108,8 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     ///
     /// `D.items()` returns a new list of key/value pairs, one per element in
     /// dictionary D, in the same order as they would be returned by a `for`
-    /// loop.
+    /// loop. If the dialect enables `enable_dict_views`, it returns a view
+    /// instead, which behaves like a list but doesn't copy the dictionary.
     ///
     /// Examples:
     ///
@@ -114,8 +119,11 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// x.items() == [("one", 1), ("two", 2)]
     /// # "#);
     /// ```
-    fn items(this: ARef<Dict>) -> Vec<(Value<'v>, Value<'v>)> {
-        Ok(this.items())
+    fn items(this: Value) -> Value<'v> {
+        if ctx.dict_views {
+            return Ok(heap.alloc(DictView::new(this, DictViewKind::Items)));
+        }
+        Ok(heap.alloc(Dict::from_value(this).unwrap().items()))
     }
 
     /// [dict.keys](
@@ -123,7 +131,8 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// ): get the list of keys of the dictionary.
     ///
     /// `D.keys()` returns a new list containing the keys of dictionary D, in
-    /// the same order as they would be returned by a `for` loop.
+    /// the same order as they would be returned by a `for` loop. Like `items`,
+    /// returns a view instead if the dialect enables them.
     ///
     /// Examples:
     ///
@@ -133,8 +142,11 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// x.keys() == ["one", "two"]
     /// # "#);
     /// ```
-    fn keys(this: ARef<Dict>) -> Vec<Value<'v>> {
-        Ok(this.keys())
+    fn keys(this: Value) -> Value<'v> {
+        if ctx.dict_views {
+            return Ok(heap.alloc(DictView::new(this, DictViewKind::Keys)));
+        }
+        Ok(heap.alloc(Dict::from_value(this).unwrap().keys()))
     }
 
     /// [dict.pop](
@@ -174,16 +186,16 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// ```
     fn pop(this: Value, ref key: Value, default: Option<Value>) -> Value<'v> {
         let mut me = Dict::from_value_mut(this, heap)?.unwrap();
-        match me.content.remove_hashed(key.get_hashed()?.borrow()) {
+        match me.content.shift_remove_hashed(key.get_hashed()?.borrow()) {
             Some(x) => Ok(x),
             None => match default {
                 Some(v) => Ok(v),
                 None => {
                     mem::drop(me);
-                    Err(anyhow!(
+                    Err(value_error!(
                         "Key `{}` not found in dictionary `{}`",
-                        key.to_repr(),
-                        this.to_repr()
+                        key.to_repr_for_error(),
+                        this.to_repr_for_error()
                     ))
                 }
             },
@@ -230,8 +242,11 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
             .next()
             .map(|(k, _)| k.unborrow_copy());
         match key {
-            Some(k) => Ok((*k.key(), this.content.remove_hashed(k.borrow()).unwrap())),
-            None => Err(anyhow!("Cannot .popitem() on an empty dictionary")),
+            Some(k) => Ok((
+                *k.key(),
+                this.content.shift_remove_hashed(k.borrow()).unwrap(),
+            )),
+            None => Err(value_error!("Cannot .popitem() on an empty dictionary")),
         }
     }
 
@@ -284,7 +299,8 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// insertions into dictionary D, then returns `None.`
     ///
     /// If the positional argument `pairs` is present, it must be `None`,
-    /// another `dict`, or some other iterable.
+    /// another `dict`, or some other iterable. `None` is the same as leaving
+    /// `pairs` out.
     /// If it is another `dict`, then its key/value pairs are inserted into D.
     /// If it is an iterable, it must provide a sequence of pairs (or other
     /// iterables of length 2), each of which is treated as a key/value pair
@@ -308,12 +324,12 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn update(this: Value, ref pairs: Option<Value>, kwargs: ARef<Dict>) -> NoneType {
-        let mut this = Dict::from_value_mut(this, heap)?.unwrap();
-        if let Some(pairs) = pairs {
+        // Collect the pairs before taking the mutable borrow, so that `x.update(x)`
+        // works, and iterating over `x` while updating it reports a proper error.
+        let mut items = Vec::new();
+        if let Some(pairs) = pairs.filter(|x| !x.is_none()) {
             if let Some(dict) = Dict::from_value(pairs) {
-                for (k, v) in dict.iter_hashed() {
-                    this.content.insert_hashed(k, v);
-                }
+                items.extend(dict.iter_hashed());
             } else {
                 for v in &pairs.iterate(heap)? {
                     let it = v.iterate(heap)?;
@@ -321,18 +337,24 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
                     let k = it.next();
                     let v = if k.is_some() { it.next() } else { None };
                     if v.is_none() || it.next().is_some() {
-                        return Err(anyhow!(
+                        return Err(type_error!(
                             "dict.update expect a list of pairs or a dictionary as first argument, got a list of non-pairs.",
                         ));
                     };
-                    this.content
-                        .insert_hashed(k.unwrap().get_hashed()?, v.unwrap());
+                    items.push((k.unwrap().get_hashed()?, v.unwrap()));
                 }
             }
         }
+        items.extend(
+            kwargs
+                .content
+                .iter_hashed()
+                .map(|(k, v)| (k.unborrow_copy(), *v)),
+        );
 
-        for (k, v) in kwargs.content.iter_hashed() {
-            this.content.insert_hashed(k.unborrow_copy(), *v);
+        let mut this = Dict::from_value_mut(this, heap)?.unwrap();
+        for (k, v) in items {
+            this.content.insert_hashed(k, v);
         }
         Ok(NoneType)
     }
@@ -343,7 +365,7 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     ///
     /// `D.values()` returns a new list containing the dictionary's values, in
     /// the same order as they would be returned by a `for` loop over the
-    /// dictionary.
+    /// dictionary. Like `items`, returns a view instead if the dialect enables them.
     ///
     /// Examples:
     ///
@@ -353,8 +375,11 @@ pub(crate) fn dict_members(registry: &mut GlobalsBuilder) {
     /// x.values() == [1, 2]
     /// # "#);
     /// ```
-    fn values(this: ARef<Dict>) -> Vec<Value<'v>> {
-        Ok(this.values())
+    fn values(this: Value) -> Value<'v> {
+        if ctx.dict_views {
+            return Ok(heap.alloc(DictView::new(this, DictViewKind::Values)));
+        }
+        Ok(heap.alloc(Dict::from_value(this).unwrap().values()))
     }
 }
 
@@ -368,8 +393,56 @@ mod tests {
         assert::fail("x = {}; x.popitem()", "empty");
     }
 
+    #[test]
+    fn test_dict_mutation_during_iteration() {
+        assert::fail(
+            "x = {1: 2}\nfor k in x:\n  x.update({3: 4})",
+            "mutate an iterable",
+        );
+        assert::fail(
+            "x = {1: 2}\nfor k in x:\n  x.setdefault(3)",
+            "mutate an iterable",
+        );
+        assert::fail(
+            "x = {1: 2}\nfor k in x:\n  x.popitem()",
+            "mutate an iterable",
+        );
+    }
+
+    #[test]
+    fn test_dict_update() {
+        assert::is_true(
+            r#"
+x = {"a": 1}
+x.update(x)
+x.update([["b", 2]], a=3)
+x.update(None, c=4)
+x.update(None)
+x == {"a": 3, "b": 2, "c": 4}
+"#,
+        );
+        assert::fail("{}.update([(1, 2, 3)])", "non-pairs");
+        assert::fail("{}.update(1)", "not supported");
+    }
+
     #[test]
     fn test_dict_add() {
         assert::fail("{1: 2} + {3: 4}", "not supported");
     }
+
+    #[test]
+    fn test_dict_keys_lists() {
+        assert::pass(
+            r#"
+x = {"a": 1, "b": 2}
+assert_eq(type(x.keys()), "list")
+for k in x.keys():
+    x[k + k] = 0
+assert_eq(len(x), 4)
+assert_eq(x.values().index(2), 1)
+assert_eq(x.items() < [("b", 1)], True)
+assert_eq([1] in x.values(), False)
+"#,
+        );
+    }
 }
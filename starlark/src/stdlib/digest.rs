@@ -0,0 +1,104 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Digests and encodings of strings, implemented natively.
+
+use crate as starlark;
+use crate::environment::GlobalsBuilder;
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    // Not `hash`, which would hide the standard `hash()` function
+    builder.struct_("hashlib", hashlib_members);
+    builder.struct_("encoding", encoding_members);
+}
+
+#[starlark_module]
+fn hashlib_members(builder: &mut GlobalsBuilder) {
+    /// The SHA-256 digest of the UTF-8 encoding of `x`, as 64 lowercase hex digits.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// hashlib.sha256("abc") == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    /// # "#);
+    /// ```
+    fn sha256(ref x: &str) -> String {
+        Ok(format!("{:x}", Sha256::digest(x.as_bytes())))
+    }
+
+    /// The MD5 digest of the UTF-8 encoding of `x`, as 32 lowercase hex digits.
+    /// MD5 is broken, so only use it to interoperate with tools that require it.
+    fn md5(ref x: &str) -> String {
+        Ok(format!("{:x}", Md5::digest(x.as_bytes())))
+    }
+}
+
+#[starlark_module]
+fn encoding_members(builder: &mut GlobalsBuilder) {
+    /// Encode the UTF-8 encoding of `x` as standard base64, with padding.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// encoding.base64_encode("hello") == "aGVsbG8="
+    /// # "#);
+    /// ```
+    fn base64_encode(ref x: &str) -> String {
+        Ok(base64::encode(x))
+    }
+
+    /// Decode standard base64 with padding, the inverse of `base64_encode`.
+    /// Fails if `x` isn't valid base64, or doesn't decode to UTF-8, as
+    /// there is no type for arbitrary bytes.
+    fn base64_decode(ref x: &str) -> String {
+        let bytes = match base64::decode(x) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(value_error!("base64_decode: {}", e)),
+        };
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(_) => Err(value_error!("base64_decode: result is not UTF-8")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_digest() {
+        assert::all_true(
+            r#"
+hashlib.sha256("") == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+hashlib.sha256("abc") == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+hashlib.md5("") == "d41d8cd98f00b204e9800998ecf8427e"
+hashlib.md5("abc") == "900150983cd24fb0d6963f7d28e17f72"
+hashlib.sha256("é") == "4a99557e4033c3539de2eb65472017cad5f9557f7a0625a09f1c3f6e2ba69c4c"
+encoding.base64_encode("") == ""
+encoding.base64_encode("hello") == "aGVsbG8="
+encoding.base64_encode("é") == "w6k="
+encoding.base64_decode("aGVsbG8=") == "hello"
+encoding.base64_decode(encoding.base64_encode("a\nb😀")) == "a\nb😀"
+hash("abc") == 96354
+"#,
+        );
+        assert::fail("hashlib.sha256(1)", "doesn't match");
+        assert::fail("encoding.base64_decode('a')", "base64_decode");
+        assert::fail("encoding.base64_decode('/w==')", "not UTF-8");
+    }
+}
@@ -17,12 +17,15 @@
 
 use crate::{
     self as starlark,
+    collections::Hashed,
     environment::GlobalsBuilder,
-    eval::ParametersSpec,
+    eval::{Evaluator, ParametersParser, ParametersSpec},
     values::{
+        dict::Dict,
         function::{FunctionInvoker, FUNCTION_TYPE},
         none::NoneType,
-        ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike, Walker,
+        ComplexValue, Freezer, FrozenValue, Heap, PrettyConfig, SimpleValue, StarlarkValue, Value,
+        ValueLike, Walker,
     },
 };
 use gazebo::any::AnyLifetime;
@@ -108,15 +111,56 @@ pub fn dedupe(builder: &mut GlobalsBuilder) {
     }
 }
 
+#[starlark_module]
+pub fn typing(builder: &mut GlobalsBuilder) {
+    fn isinstance(v: Value, ty: Value) -> bool {
+        // Constructors and type names stand for their `.type`
+        let ty = if ty.unpack_str().is_none() && ty.has_attr("type") {
+            ty.get_attr("type", heap)?.1
+        } else {
+            ty
+        };
+        v.is_type(ty)
+    }
+}
+
 #[starlark_module]
 pub fn print(builder: &mut GlobalsBuilder) {
     fn print(args: Vec<Value>) -> NoneType {
-        // In practice most users should want to put the print somewhere else, but this does for now
-        println!("{}", args.iter().join(" "));
+        ctx.print(&args.iter().join(" "));
+        Ok(NoneType)
+    }
+
+    /// Print the `repr` of `x` laid out over multiple lines, so each line fits in `width`
+    /// characters where possible, with nested values indented by `indent` spaces.
+    /// Values nested more than `depth` deep are shown as `[...]`, `{...}` etc.
+    fn pprint(x: Value, width @ 80: i32, indent @ 4: i32, depth: Option<i32>) -> NoneType {
+        let config = PrettyConfig {
+            width: width.max(0) as usize,
+            indent: indent.max(0) as usize,
+            max_depth: depth.map(|x| x.max(0) as usize),
+        };
+        ctx.print(&x.to_repr_pretty(&config));
         Ok(NoneType)
     }
 }
 
+#[starlark_module]
+pub fn memoize(builder: &mut GlobalsBuilder) {
+    fn memoize(func: Value) -> Memoized<'v> {
+        if !func.get_aref().is_function() {
+            return Err(type_error!(
+                "memoize() expects a function, got `{}`",
+                func.get_type()
+            ));
+        }
+        let mut signature = ParametersSpec::with_capacity("memoized".to_owned(), 2);
+        signature.args("args");
+        signature.kwargs("kwargs");
+        Ok(Memoized { func, signature })
+    }
+}
+
 #[derive(Debug)]
 struct PartialGen<V> {
     func: V,
@@ -177,9 +221,117 @@ where
     }
 }
 
+#[derive(Debug)]
+struct MemoizedGen<V> {
+    func: V,
+    signature: ParametersSpec<FrozenValue>,
+}
+
+starlark_complex_value!(Memoized);
+
+impl<'v> ComplexValue<'v> for Memoized<'v> {
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        box FrozenMemoized {
+            func: self.func.freeze(freezer),
+            signature: self.signature,
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        walker.walk(&mut self.func);
+    }
+}
+
+// The arguments of a call as a single value, or `None` if they aren't hashable.
+// Named arguments are sorted, so their order in the call doesn't matter.
+fn memoize_key<'v>(
+    args: Value<'v>,
+    kwargs: Value<'v>,
+    heap: &'v Heap,
+) -> Option<Hashed<Value<'v>>> {
+    let mut kwargs = Dict::from_value(kwargs).unwrap().items();
+    let key = if kwargs.is_empty() {
+        args
+    } else {
+        kwargs.sort_by(|a, b| a.0.unpack_str().cmp(&b.0.unpack_str()));
+        let mut key = vec![args];
+        for (k, v) in kwargs {
+            key.push(k);
+            key.push(v);
+        }
+        heap.alloc_tuple(&key)
+    };
+    key.get_hashed().ok()
+}
+
+impl<'v, V: ValueLike<'v>> StarlarkValue<'v> for MemoizedGen<V>
+where
+    Self: AnyLifetime<'v>,
+{
+    starlark_type!(FUNCTION_TYPE);
+
+    fn is_function(&self) -> bool {
+        true
+    }
+
+    fn invoke_parameters(&self) -> Option<&ParametersSpec<FrozenValue>> {
+        Some(&self.signature)
+    }
+
+    fn invoke(
+        &self,
+        me: Value<'v>,
+        mut params: ParametersParser<'v, '_>,
+        eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        let heap = eval.heap();
+        let args: Value = params.next("args", heap)?;
+        let kwargs: Value = params.next("kwargs", heap)?;
+        let call = |eval: &mut Evaluator<'v, '_>| {
+            let func = self.func.to_value();
+            let mut inv = func.new_invoker(heap)?;
+            inv.push_args(args, heap);
+            inv.push_kwargs(kwargs);
+            inv.invoke(func, None, eval)
+        };
+
+        // Until frozen the function can see module variables change, so isn't pure
+        let key = match me.unpack_frozen() {
+            Some(me) => memoize_key(args, kwargs, heap).map(|key| (me, key)),
+            None => None,
+        };
+        match key {
+            None => {
+                eval.memoize.uncached();
+                call(eval)
+            }
+            Some((me, key)) => match eval.memoize.get(me, key) {
+                Some(res) => Ok(res),
+                None => {
+                    let res = call(eval)?;
+                    eval.memoize.insert(me, key, res);
+                    Ok(res)
+                }
+            },
+        }
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str("memoize(");
+        self.func.collect_repr(collector);
+        collector.push(')');
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::assert;
+    use crate::{
+        assert::{self, Assert},
+        environment::{Globals, Module},
+        eval::{Evaluator, MemoizeStats, ReturnFileLoader},
+        syntax::{AstModule, Dialect},
+    };
+    use std::{cell::RefCell, collections::HashMap};
 
     #[test]
     fn test_filter() {
@@ -268,4 +420,138 @@ assert_eq(dedupe([a,b,a]), [a,b])
 "#,
         );
     }
+
+    #[test]
+    fn test_isinstance() {
+        assert::all_true(
+            r#"
+isinstance(1, int)
+isinstance("x", str)
+not isinstance(1, str)
+isinstance(None, [int.type, None])
+isinstance([1], "list")
+"#,
+        );
+
+        // Embedder types registered by name work with `isinstance` and type annotations
+        let mut a = Assert::new();
+        a.globals_add(|builder| builder.set_type_name("Range", "range"));
+        a.all_true(
+            r#"
+isinstance(range(3), Range)
+not isinstance([], Range)
+Range.type == "range"
+"#,
+        );
+        a.pass("def f(x: Range.type):\n    pass\nf(range(1))");
+        a.fail("def f(x: Range.type):\n    pass\nf(1)", "type annotation");
+    }
+
+    #[test]
+    fn test_print_handler() {
+        let output = RefCell::new(Vec::new());
+        let handler = |x: &str| output.borrow_mut().push(x.to_owned());
+        let globals = Globals::extended();
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        eval.set_print_handler(&handler);
+        eval.eval_module(
+            AstModule::parse(
+                "print.star",
+                "print('hello', 1)\nprint([True])".to_owned(),
+                &Dialect::Extended,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(*output.borrow(), vec!["hello 1", "[True]"]);
+    }
+
+    #[test]
+    fn test_pprint() {
+        let output = RefCell::new(Vec::new());
+        let handler = |x: &str| output.borrow_mut().push(x.to_owned());
+        let globals = Globals::extended();
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        eval.set_print_handler(&handler);
+        eval.eval_module(
+            AstModule::parse(
+                "pprint.star",
+                r#"
+x = {"a": [1, 2], "b": struct(c = (3,))}
+pprint(x)
+pprint(x, width = 16, indent = 2)
+pprint(x, depth = 0)
+"#
+                .to_owned(),
+                &Dialect::Extended,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            *output.borrow(),
+            vec![
+                "{\"a\": [1, 2], \"b\": struct(c=(3,))}",
+                "{\n  \"a\": [1, 2],\n  \"b\": struct(\n    c=(3,),\n  ),\n}",
+                "{\"a\": [...], \"b\": struct(...)}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memoize() {
+        let globals = Globals::extended();
+        let lib = Module::new();
+        {
+            let mut eval = Evaluator::new(&lib, &globals);
+            let ast = AstModule::parse(
+                "lib.star",
+                r#"
+def _square(x, scale = 1):
+    return [x * x * scale]
+square = memoize(_square)
+first = memoize(lambda xs: xs[0])
+assert_eq(square(3), [9])
+"#
+                .to_owned(),
+                &Dialect::Extended,
+            );
+            eval.eval_module(ast.unwrap()).unwrap();
+            // Calls from the module defining it aren't cached
+            assert_eq!(eval.memoize_stats().uncached, 1);
+        }
+        let lib = lib.freeze();
+
+        let modules = hashmap! {"lib.star" => &lib};
+        let mut loader = ReturnFileLoader { modules: &modules };
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        eval.set_loader(&mut loader);
+        let ast = AstModule::parse(
+            "main.star",
+            r#"
+load("lib.star", "square", "first")
+assert_eq(square(3), [9])
+assert_eq(square(3), [9])
+assert_eq(square(x = 3, scale = 2), [18])
+assert_eq(square(scale = 2, x = 3), [18])
+assert_eq(first([1]), 1)
+"#
+            .to_owned(),
+            &Dialect::Extended,
+        );
+        eval.eval_module(ast.unwrap()).unwrap();
+        assert_eq!(
+            eval.memoize_stats(),
+            MemoizeStats {
+                hits: 2,
+                misses: 2,
+                uncached: 1,
+                entries: 2,
+            }
+        );
+        assert::fail("memoize(1)", "expects a function");
+    }
 }
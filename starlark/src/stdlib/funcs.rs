@@ -22,6 +22,8 @@ use crate::{
     self as starlark,
     collections::SmallMap,
     environment::GlobalsBuilder,
+    eval::Evaluator,
+    stdlib::util::sort_values,
     values::{
         bool::BOOL_TYPE,
         dict::Dict,
@@ -32,10 +34,9 @@ use crate::{
         range::Range,
         string::STRING_TYPE,
         tuple::Tuple,
-        Heap, Value,
+        Heap, RefIterable, Value, ValueError,
     },
 };
-use anyhow::anyhow;
 use gazebo::prelude::*;
 use std::{cmp::Ordering, num::NonZeroI32};
 
@@ -53,12 +54,94 @@ fn unpack_pair<'v>(it: Value<'v>, heap: &'v Heap) -> anyhow::Result<(Value<'v>,
         }
         _ => {}
     }
-    Err(anyhow!(
+    Err(type_error!(
         "Found a non-pair element in the positional argument of dict(): {}",
-        it.to_repr(),
+        it.to_repr_for_error(),
     ))
 }
 
+/// Iterate over an argument of a builtin function, with an error naming the function.
+fn iterate_arg<'v>(
+    x: Value<'v>,
+    function: &str,
+    heap: &'v Heap,
+) -> anyhow::Result<RefIterable<'v>> {
+    x.iterate(heap).map_err(|e| {
+        // Only reword the generic "not iterable" error, anything else (e.g. iterating
+        // over a value which is being mutated) is more useful as it is.
+        let not_iterable = matches!(
+            e.downcast_ref::<ValueError>(),
+            Some(ValueError::OperationNotSupported { op, .. }) if op == "(iter)"
+        );
+        if not_iterable {
+            type_error!(
+                "{}() argument is not iterable, got value of type `{}`",
+                function,
+                x.get_type()
+            )
+        } else {
+            e
+        }
+    })
+}
+
+/// Shared implementation of `min` and `max`, returning the first element which
+/// isn't `worse` than any other.
+fn min_max<'v>(
+    function: &str,
+    worse: Ordering,
+    mut args: Vec<Value<'v>>,
+    key: Option<Value<'v>>,
+    ctx: &mut Evaluator<'v, '_>,
+) -> anyhow::Result<Value<'v>> {
+    let heap = ctx.heap();
+    let args = match args.len() {
+        0 => {
+            return Err(type_error!(
+                "{}() requires at least one positional argument",
+                function
+            ));
+        }
+        1 => args.swap_remove(0),
+        _ => heap.alloc(args),
+    };
+    let it = iterate_arg(args, function, heap)?;
+    let mut it = it.iter();
+    let mut best = match it.next() {
+        Some(x) => x,
+        None => {
+            return Err(value_error!(
+                "Argument is an empty iterable, {}() expect a non empty iterable",
+                function
+            ));
+        }
+    };
+    match key {
+        None => {
+            for i in it {
+                if best.compare(i)? == worse {
+                    best = i;
+                }
+            }
+        }
+        Some(key) => {
+            let mut invoker = key.new_invoker(heap)?;
+            invoker.push_pos(best);
+            let mut cached = invoker.invoke(key, None, ctx)?;
+            for i in it {
+                let mut invoker = key.new_invoker(heap)?;
+                invoker.push_pos(i);
+                let keyi = invoker.invoke(key, None, ctx)?;
+                if cached.compare(keyi)? == worse {
+                    best = i;
+                    cached = keyi;
+                }
+            }
+        }
+    };
+    Ok(best)
+}
+
 #[starlark_module]
 pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     const None: NoneType = NoneType;
@@ -74,7 +157,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#, "this is an error");
     /// ```
     fn fail(msg: Value) -> NoneType {
-        Err(anyhow!("fail(): {}", msg))
+        Err(value_error!("fail(): {}", msg))
     }
 
     /// [any](
@@ -94,7 +177,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn any(ref x: Value) -> bool {
-        for i in &x.iterate(heap)? {
+        for i in &iterate_arg(x, "any", heap)? {
             if i.to_bool() {
                 return Ok(true);
             }
@@ -122,7 +205,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn all(ref x: Value) -> bool {
-        for i in &x.iterate(heap)? {
+        for i in &iterate_arg(x, "all", heap)? {
             if !i.to_bool() {
                 return Ok(false);
             }
@@ -181,7 +264,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
         let cp = i.to_int()? as u32;
         match std::char::from_u32(cp) {
             Some(x) => Ok(x.to_string()),
-            None => Err(anyhow!(
+            None => Err(value_error!(
                 "chr() parameter value is 0x{:x} which is not a valid UTF-8 codepoint",
                 cp
             )),
@@ -225,6 +308,15 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
             // Save to skip regenerating as we know that kwargs will always be a copy
             None => Ok(kwargs),
             Some(a) => {
+                if ctx.thaw_on_write
+                    && Dict::from_value(a).is_some()
+                    && Dict::from_value(kwargs).unwrap().content.is_empty()
+                {
+                    // A frozen dict can be shared until either is written
+                    if let Some(res) = heap.alloc_copy_on_write(a) {
+                        return Ok(res);
+                    }
+                }
                 let mut result = SmallMap::new();
                 match Dict::from_value(a) {
                     Some(mp) => {
@@ -285,12 +377,11 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// enumerate(["one", "two"], 1) == [(1, "one"), (2, "two")]
     /// # "#);
     /// ```
-    fn enumerate(ref it: Value, offset @ 0: i32) -> Vec<(i32, Value<'v>)> {
-        let v = it
-            .iterate(heap)?
+    fn enumerate(ref it: Value, start @ 0: i32) -> Vec<(i32, Value<'v>)> {
+        let v = iterate_arg(it, "enumerate", heap)?
             .iter()
             .enumerate()
-            .map(|(k, v)| (k as i32 + offset, v))
+            .map(|(k, v)| (k as i32 + start, v))
             .collect();
         Ok(v)
     }
@@ -304,9 +395,13 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     ///
     /// `getattr(x, "f")` is equivalent to `x.f`.
     ///
+    /// `getattr(x, name, default)` returns `default` instead of failing
+    /// if x has no attribute named `name`.
+    ///
     /// ```
     /// # starlark::assert::all_true(r#"
     /// getattr("banana", "split")("a") == ["b", "n", "n", ""] # equivalent to "banana".split("a")
+    /// getattr("banana", "nope", 42) == 42
     /// # "#);
     /// ```
     fn getattr(ref a: Value, ref attr: &str, ref default: Option<Value>) -> Value<'v> {
@@ -347,15 +442,21 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// https://github.com/google/skylark/blob/a0e5de7e63b47e716cca7226662a4c95d47bf873/doc/spec.md#hash
     /// ): returns the hash number of a value.
     ///
-    /// `hash(x)`` returns an integer hash value for x such that `x == y`
-    /// implies `hash(x) == hash(y)``.
+    /// `hash(x)` returns an integer hash value for a string `x` such that `x == y`
+    /// implies `hash(x) == hash(y)`.
+    ///
+    /// As required by the spec, the hash is that of Java's `String.hashCode`, computed
+    /// over the UTF-16 encoding of the string, so is the same in every run, on every
+    /// platform and in other Starlark implementations. It is suitable for deciding
+    /// things like which shard a target goes in.
     ///
-    /// `hash` fails if x, or any value upon which its hash depends, is
-    /// unhashable.
+    /// `hash` fails if x is not a string.
     ///
     /// ```
     /// # starlark::assert::all_true(r#"
     /// hash("hello") != hash("world")
+    /// hash("") == 0
+    /// hash("hello") == 99162322
     /// # "#);
     /// ```
     fn hash(ref a: &str) -> i32 {
@@ -420,7 +521,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
                 None => 0,
             };
             if base == 1 || base < 0 || base > 36 {
-                return Err(anyhow!(
+                return Err(value_error!(
                     "{} is not a valid base, int() base must be >= 2 and <= 36",
                     base
                 ));
@@ -468,16 +569,16 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
             };
             match i32::from_str_radix(s, base) {
                 Ok(i) => Ok(sign * i),
-                Err(x) => Err(anyhow!(
+                Err(x) => Err(value_error!(
                     "{} is not a valid number in base {}: {}",
-                    a.to_repr(),
+                    a.to_repr_for_error(),
                     base,
                     x,
                 )),
             }
         } else {
             match base {
-                Some(base) => Err(anyhow!(
+                Some(base) => Err(type_error!(
                     "int() cannot convert non-string with explicit base '{}'",
                     base.to_repr()
                 )),
@@ -508,7 +609,10 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#, "not supported");
     /// ```
     fn len(ref a: Value) -> i32 {
-        Ok(a.length()?)
+        match a.unpack_str() {
+            Some(s) if ctx.byte_strings => Ok(s.len() as i32),
+            _ => Ok(a.length()?),
+        }
     }
 
     /// [list](
@@ -530,14 +634,20 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#, "not supported");
     /// ```
     #[starlark_type(List::TYPE)]
-    fn list(ref a: Option<Value>) -> Vec<Value<'v>> {
+    fn list(ref a: Option<Value>) -> Value<'v> {
         let mut l = Vec::new();
         if let Some(a) = a {
+            if ctx.thaw_on_write && List::from_value(a).is_some() {
+                // A frozen list can be shared until either is written
+                if let Some(res) = heap.alloc_copy_on_write(a) {
+                    return Ok(res);
+                }
+            }
             for x in &a.iterate(heap)? {
                 l.push(x)
             }
         }
-        Ok(l)
+        Ok(heap.alloc(List::new(l)))
     }
 
     /// [max](
@@ -559,46 +669,8 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// max("two", "three", "four", key=len)  == "three"  # the longest
     /// # "#);
     /// ```
-    fn max(mut args: Vec<Value>, key: Option<Value>) -> Value<'v> {
-        let args = if args.len() == 1 {
-            args.swap_remove(0)
-        } else {
-            heap.alloc(args)
-        };
-        let it = args.iterate(heap)?;
-        let mut it = it.iter();
-        let mut max = match it.next() {
-            Some(x) => x,
-            None => {
-                return Err(anyhow!(
-                    "Argument is an empty iterable, max() expect a non empty iterable"
-                ));
-            }
-        };
-        match key {
-            None => {
-                for i in it {
-                    if max.compare(i)? == Ordering::Less {
-                        max = i;
-                    }
-                }
-            }
-            Some(key) => {
-                let mut invoker = key.new_invoker(heap)?;
-                invoker.push_pos(max);
-                let mut cached = invoker.invoke(key, None, ctx)?;
-                for i in it {
-                    let mut invoker = key.new_invoker(heap)?;
-                    invoker.push_pos(i);
-                    let keyi = invoker.invoke(key, None, ctx)?;
-                    if cached.compare(keyi)? == Ordering::Less {
-                        max = i;
-                        cached = keyi;
-                    }
-                }
-            }
-        };
-        Ok(max)
+    fn max(args: Vec<Value>, key: Option<Value>) -> Value<'v> {
+        min_max("max", Ordering::Less, args, key, ctx)
     }
 
     /// [min](
@@ -617,46 +689,8 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// min("two", "three", "four", key=len)    == "two"   # the shortest
     /// # "#);
     /// ```
-    fn min(mut args: Vec<Value>, key: Option<Value>) -> Value<'v> {
-        let args = if args.len() == 1 {
-            args.swap_remove(0)
-        } else {
-            heap.alloc(args)
-        };
-        let it = args.iterate(heap)?;
-        let mut it = it.iter();
-        let mut min = match it.next() {
-            Some(x) => x,
-            None => {
-                return Err(anyhow!(
-                    "Argument is an empty iterable, min() expect a non empty iterable"
-                ));
-            }
-        };
-        match key {
-            None => {
-                for i in it {
-                    if min.compare(i)? == Ordering::Greater {
-                        min = i;
-                    }
-                }
-            }
-            Some(key) => {
-                let mut invoker = key.new_invoker(heap)?;
-                invoker.push_pos(min);
-                let mut cached = invoker.invoke(key, None, ctx)?;
-                for i in it {
-                    let mut invoker = key.new_invoker(heap)?;
-                    invoker.push_pos(i);
-                    let keyi = invoker.invoke(key, None, ctx)?;
-                    if cached.compare(keyi)? == Ordering::Greater {
-                        min = i;
-                        cached = keyi;
-                    }
-                }
-            }
-        };
-        Ok(min)
+    fn min(args: Vec<Value>, key: Option<Value>) -> Value<'v> {
+        min_max("min", Ordering::Greater, args, key, ctx)
     }
 
     /// [ord](
@@ -688,9 +722,9 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
                 }
             }
         }
-        Err(anyhow!(
+        Err(type_error!(
             "ord(): {} is not a single character string",
-            a.to_repr()
+            a.to_repr_for_error()
         ))
     }
 
@@ -735,7 +769,11 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
         let stop = a2.unwrap_or(a1);
         let step = match NonZeroI32::new(step) {
             Some(step) => step,
-            None => return Err(anyhow!("Third argument of range (step) cannot be zero")),
+            None => {
+                return Err(value_error!(
+                    "Third argument of range (step) cannot be zero"
+                ))
+            }
         };
         Ok(Range::new(start, stop, step))
     }
@@ -775,7 +813,7 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn reversed(ref a: Value) -> Vec<Value<'v>> {
-        let mut v: Vec<Value> = a.iterate(heap)?.iter().collect();
+        let mut v: Vec<Value> = iterate_arg(a, "reversed", heap)?.iter().collect();
         v.reverse();
         Ok(v)
     }
@@ -803,43 +841,8 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn sorted(ref x: Value, key: Option<Value>, reverse @ false: Value) -> Vec<Value<'v>> {
-        let it = x.iterate(heap)?;
-        let x = it.iter();
-        let mut it = match key {
-            None => x.map(|x| (x, x)).collect(),
-            Some(key) => {
-                let mut v = Vec::new();
-                for el in x {
-                    let mut inv = key.new_invoker(heap)?;
-                    inv.push_pos(el);
-                    v.push((el, inv.invoke(key, None, ctx)?));
-                }
-                v
-            }
-        };
-
-        let mut compare_ok = Ok(());
-
-        let reverse = reverse.to_bool();
-        it.sort_by(|x: &(Value, Value), y: &(Value, Value)| {
-            let ord_or_err = if reverse {
-                x.1.compare(y.1).map(Ordering::reverse)
-            } else {
-                x.1.compare(y.1)
-            };
-            match ord_or_err {
-                Ok(r) => r,
-                Err(e) => {
-                    compare_ok = Err(e);
-                    Ordering::Equal // does not matter
-                }
-            }
-        });
-
-        compare_ok?;
-
-        let result: Vec<Value> = it.into_map(|x| x.0);
-        Ok(result)
+        let x = iterate_arg(x, "sorted", heap)?.iter().collect();
+        sort_values(x, key, reverse.to_bool(), ctx)
     }
 
     /// [str](
@@ -880,14 +883,14 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     /// tuple([1,2,3]) == (1, 2, 3)
     /// # "#);
     #[starlark_type(Tuple::TYPE)]
-    fn tuple(ref a: Option<Value>) -> Tuple<'v> {
+    fn tuple(ref a: Option<Value>) -> Value<'v> {
         let mut l = Vec::new();
         if let Some(a) = a {
             for x in &a.iterate(heap)? {
                 l.push(x)
             }
         }
-        Ok(Tuple::new(l))
+        Ok(heap.alloc_tuple(&l))
     }
 
     /// [type](
@@ -928,9 +931,16 @@ pub(crate) fn global_functions(builder: &mut GlobalsBuilder) {
     fn zip(args: Vec<Value>) -> Vec<Value<'v>> {
         let mut v = Vec::new();
         let mut first = true;
-        for arg in args {
+        for (i, arg) in args.into_iter().enumerate() {
+            let it = arg.iterate(heap).map_err(|_| {
+                type_error!(
+                    "zip() argument #{} is not iterable, got value of type `{}`",
+                    i + 1,
+                    arg.get_type()
+                )
+            })?;
             let mut idx = 0;
-            for e in &arg.iterate(heap)? {
+            for e in &it {
                 if first {
                     v.push(heap.alloc((e,)));
                     idx += 1;
@@ -960,6 +970,47 @@ mod tests {
     #[test]
     fn test_error_codes() {
         assert::fail("chr(0x110000)", "not a valid UTF-8");
+        assert::fail("min()", "min() requires at least one positional argument");
+        assert::fail("max([])", "max() expect a non empty iterable");
+        assert::fail("min(1)", "min() argument is not iterable");
+        assert::fail("any(1)", "any() argument is not iterable");
+        assert::fail("sorted(1)", "sorted() argument is not iterable");
+        assert::fail("enumerate(None)", "enumerate() argument is not iterable");
+        assert::fail("zip([1], 1)", "zip() argument #2 is not iterable");
+    }
+
+    #[test]
+    fn test_attr_reflection() {
+        // dir, hasattr and getattr should agree, for members and attributes alike
+        assert::all_true(
+            r#"
+all([hasattr("x", a) for a in dir("x")])
+all([getattr("x", a, None) != None for a in dir("x")])
+all([hasattr(struct(a = 1), a) for a in dir(struct(a = 1))])
+dir(struct(to_json = 1, a = 2)) == ["a", "to_json"]
+hasattr(tuple, "type") and getattr(tuple, "type") == "tuple"
+"type" in dir(tuple)
+not hasattr(len, "type") and getattr(len, "type", 7) == 7
+getattr([], "nope", None) == None
+getattr(struct(a = 1), "b", "default") == "default"
+"#,
+        );
+        assert::fail("getattr([], 'nope')", "nope");
+    }
+
+    #[test]
+    fn test_key_functions() {
+        assert::all_true(
+            r#"
+min(5, -2, 1, 7, 3, key=lambda x: x*x) == 1
+max([5, -2, 1, 7, 3], key=lambda x: -x) == -2
+max(["a", "bb", "cc"], key=len) == "bb"
+min(["aa", "b", "c"], key=len) == "b"
+enumerate(["a", "b"], start=3) == [(3, "a"), (4, "b")]
+zip([1, 2, 3], "ab".split_codepoints()) == [(1, "a"), (2, "b")]
+reversed((1, 2, 3)) == [3, 2, 1]
+"#,
+        );
     }
 
     #[test]
@@ -971,6 +1022,8 @@ mod tests {
         assert::eq("2987074", "hash('abcd')");
         assert::eq("92599395", "hash('abcde')");
         assert::eq("-1424385949", "hash('abcdef')");
+        // Characters outside the BMP hash as their UTF-16 surrogate pair
+        assert::eq("1772899", "hash('😀')");
         assert::all_true(
             r#"
 hash("te") == hash("te")
@@ -0,0 +1,130 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Implementation of `Label` function.
+use crate as starlark;
+use crate::{environment::GlobalsBuilder, values::label::Label};
+
+#[starlark_module]
+pub fn global(builder: &mut GlobalsBuilder) {
+    /// Parse a label, in the syntax set by the embedder, by default that of Bazel,
+    /// e.g. `@repository//package:name`. Labels relative to a package, e.g. `:name`,
+    /// can only be parsed with `relative` on another label.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// str(Label("//foo/bar")) == "//foo/bar:bar"
+    /// # "#);
+    /// ```
+    #[starlark_type(Label::TYPE)]
+    fn Label(ref s: &str) -> Label {
+        ctx.label_syntax().parse(s, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert,
+        environment::{Globals, Module},
+        eval::Evaluator,
+        syntax::{AstModule, Dialect},
+        values::label::{Label, LabelSyntax},
+    };
+
+    #[test]
+    fn test_label() {
+        assert::all_true(
+            r#"
+Label("//foo/bar:baz").package == "foo/bar"
+Label("//foo/bar:baz").name == "baz"
+Label("//foo/bar:baz").repository == ""
+Label("@r//:x").repository == "r"
+Label("@r//:x").package == ""
+Label("//foo:a/b").name == "a/b"
+str(Label("@r//foo")) == "@r//foo:foo"
+repr(Label("//foo:bar")) == 'Label("//foo:bar")'
+repr([Label("@r//foo")]) == '[Label("@r//foo:foo")]'
+"%s" % Label("//foo:bar") == "//foo:bar"
+"{}".format(Label("//foo:bar")) == "//foo:bar"
+type(Label("//foo")) == "Label"
+Label.type == "Label"
+Label("//foo") == Label("//foo:foo")
+Label("//foo") != Label("//foo:bar")
+Label("//a:b") < Label("//a:c")
+Label("//b:a") > Label("//a:b")
+Label("@a//z:z") > Label("//z:z")
+{Label("//foo"): 1}[Label("//foo:foo")] == 1
+Label("@r//foo:bar").relative(":baz") == Label("@r//foo:baz")
+Label("//foo:bar").relative("baz") == Label("//foo:baz")
+Label("//foo:bar").relative("//x") == Label("//x:x")
+struct(x = Label("//foo")).to_json() == '{"x":"\\/\\/foo:foo"}'
+"#,
+        );
+        assert::fail("Label(':foo')", "relative to a package");
+        assert::fail("Label('@r')", "expected `//` after the repository");
+        assert::fail("Label('@r!//a')", "repository has invalid characters");
+        assert::fail("Label('//')", "the name is empty");
+        assert::fail("Label('//a//b:c')", "the package has an empty component");
+        assert::fail("Label('//a/..:c')", "the package has a `..` component");
+        assert::fail("Label('//a:b:c')", "the name contains `:`");
+        assert::fail("Label('//a') < 1", "compare");
+    }
+
+    struct BuckLabelSyntax;
+
+    impl LabelSyntax for BuckLabelSyntax {
+        fn parse(&self, s: &str, _base: Option<&Label>) -> anyhow::Result<Label> {
+            match (s.find("//"), s.rfind(':')) {
+                (Some(i), Some(j)) if i < j => {
+                    Ok(Label::new(&s[..i], &s[i + 2..j], &s[j + 1..], self))
+                }
+                _ => Err(anyhow::anyhow!("Bad label `{}`", s)),
+            }
+        }
+
+        fn format(&self, repository: &str, package: &str, name: &str) -> String {
+            format!("{}//{}:{}", repository, package, name)
+        }
+    }
+
+    #[test]
+    fn test_label_syntax() {
+        let globals = Globals::extended();
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        eval.set_label_syntax(&BuckLabelSyntax);
+        let res = eval
+            .eval_module(
+                AstModule::parse(
+                    "buck.star",
+                    r#"
+x = Label("cell//foo:bar")
+[x.repository, x.package, str(x), x.relative("other//:baz")]
+"#
+                    .to_owned(),
+                    &Dialect::Extended,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            res.to_repr(),
+            "[\"cell\", \"foo\", \"cell//foo:bar\", Label(\"other//:baz\")]"
+        );
+    }
+}
@@ -20,14 +20,13 @@
 use crate::{
     self as starlark,
     environment::GlobalsBuilder,
-    stdlib::util::{convert_index, convert_indices},
+    stdlib::util::{convert_index, convert_indices, sort_values},
     values::{
         list::List,
         none::{NoneOr, NoneType},
         StarlarkValue, Value, ValueError,
     },
 };
-use anyhow::anyhow;
 use gazebo::cell::ARef;
 
 #[starlark_module]
@@ -163,7 +162,7 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
                 return Ok((i + start) as i32);
             }
         }
-        Err(anyhow!(
+        Err(value_error!(
             "Element '{}' not found in '{}'",
             needle,
             this.to_repr()
@@ -207,8 +206,9 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
     /// `L.pop([index])` removes and returns the last element of the list L, or,
     /// if the optional index is provided, at that index.
     ///
-    /// `pop` fails if the index is negative or not less than the length of
-    /// the list, of if the list is frozen or has active iterators.
+    /// As usual, a negative index has the length of the list added to it.
+    /// `pop` fails if the index is not valid for `L[i]`, or if the list is
+    /// frozen or has active iterators.
     ///
     /// Examples:
     ///
@@ -218,9 +218,9 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
     /// # (
     /// x.pop() == 3
     /// # and
-    /// x.pop() == 2
+    /// x.pop(0) == 1
     /// # and
-    /// x == [1]
+    /// x == [2]
     /// # )"#);
     /// ```
     fn pop(this: Value, ref index: Option<Value>) -> Value<'v> {
@@ -230,11 +230,13 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
         };
 
         let mut this = List::from_value_mut(this, heap)?.unwrap();
-        let index = index.unwrap_or_else(|| (this.len() as i32) - 1);
-        if index < 0 || index >= this.len() as i32 {
+        let len = this.len() as i32;
+        let index = index.unwrap_or(len - 1);
+        let i = if index < 0 { index + len } else { index };
+        if i < 0 || i >= len {
             return Err(ValueError::IndexOutOfBound(index).into());
         }
-        Ok(this.content.remove(index as usize))
+        Ok(this.content.remove(i as usize))
     }
 
     /// [list.remove](
@@ -290,7 +292,11 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
                 None => {
                     let mut s = String::new();
                     this.collect_repr(&mut s);
-                    return Err(anyhow!("Element '{}' not found in list '{}'", needle, s));
+                    return Err(value_error!(
+                        "Element '{}' not found in list '{}'",
+                        needle,
+                        s
+                    ));
                 }
             }
         };
@@ -301,6 +307,55 @@ pub(crate) fn list_members(builder: &mut GlobalsBuilder) {
             Ok(NoneType)
         }
     }
+
+    /// [list.sort](
+    /// https://docs.python.org/3/library/stdtypes.html#list.sort
+    /// ): sort a list in place.
+    ///
+    /// `L.sort(key=None, reverse=False)` sorts the elements of the list L,
+    /// using the same ordering as `sorted`, and returns `None`.
+    /// The sort algorithm is stable.
+    ///
+    /// The optional named parameter `key` specifies a function of one
+    /// argument to apply to obtain the value's sort key, and `reverse`, if
+    /// true, sorts in descending order.
+    ///
+    /// `sort` fails if the elements are not comparable, if the list is
+    /// frozen or has active iterators, or if `key` modifies the list.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// x = ["two", "three", "four"]
+    /// x.sort()
+    /// # t = (
+    /// x == ["four", "three", "two"]
+    /// # )
+    /// x.sort(key=len, reverse=True)
+    /// # (t and (
+    /// x == ["three", "four", "two"]
+    /// # ))"#);
+    /// ```
+    fn sort(this: Value, key: Option<Value>, reverse @ false: bool) -> NoneType {
+        // As with `remove`, don't hold the list mutably while calling `key`
+        // or comparing elements, which may run arbitrary code.
+        let original = List::from_value_mut(this, heap)?.unwrap().content.clone();
+        let sorted = sort_values(original.clone(), key, reverse, ctx)?;
+        let mut list = List::from_value_mut(this, heap)?.unwrap();
+        // Writing back the sorted list would lose any changes made while sorting
+        if list.content.len() != original.len()
+            || list
+                .content
+                .iter()
+                .zip(&original)
+                .any(|(x, y)| !x.ptr_eq(*y))
+        {
+            return Err(value_error!("list modified during sort"));
+        }
+        list.content = sorted;
+        Ok(NoneType)
+    }
 }
 
 #[cfg(test)]
@@ -313,5 +368,42 @@ mod tests {
             "x = [1, 2, 3, 2]; x.remove(2); x.remove(2); x.remove(2)",
             "not found in list",
         );
+        assert::fail("x = [1, 2]; x.pop(2)", "out of bound");
+        assert::fail("x = [1, 2]; x.pop(-3)", "out of bound");
+        assert::fail("x = [1, 'a']; x.sort()", "not supported");
+        assert::fail(
+            "x = [3, 1, 2]\ndef f(v):\n    x.append(v)\n    return v\nx.sort(key=f)",
+            "list modified during sort",
+        );
+    }
+
+    #[test]
+    fn test_list_methods() {
+        assert::is_true(
+            r#"
+x = [1, 2, 3, 4]
+x.insert(10, 5)
+x.insert(-10, 0)
+x.pop(-1) == 5 and x.pop(1) == 1 and x == [0, 2, 3, 4]
+"#,
+        );
+        assert::is_true(
+            r#"
+x = [(2, "b"), (1, "z"), (2, "a"), (1, "y")]
+x.sort(key=lambda p: p[0])
+x == [(1, "z"), (1, "y"), (2, "b"), (2, "a")]
+"#,
+        );
+        assert::is_true(
+            r#"
+x = [3, 1, 2]
+x.sort(reverse=True)
+x == [3, 2, 1]
+"#,
+        );
+        assert::fail(
+            "x = [3, 1, 2]\nfor i in x:\n  x.sort()",
+            "mutate an iterable",
+        );
     }
 }
@@ -18,19 +18,68 @@
 //! A module with the standard function and constants that are by default in all
 //! dialect of Starlark
 
-use crate::environment::GlobalsBuilder;
+use crate::{environment::GlobalsBuilder, errors::ErrorKind};
+use thiserror::Error;
+
+// Like `anyhow!`, for a builtin given an argument of the wrong type
+macro_rules! type_error {
+    ($($x:tt)*) => {
+        anyhow::Error::from(crate::stdlib::BuiltinError::Type(format!($($x)*)))
+    };
+}
+
+// Like `anyhow!`, for a builtin given an argument of the right type, but an invalid value
+macro_rules! value_error {
+    ($($x:tt)*) => {
+        anyhow::Error::from(crate::stdlib::BuiltinError::Value(format!($($x)*)))
+    };
+}
 
 mod breakpoint;
 pub(crate) mod dict;
+#[cfg(feature = "digest")]
+mod digest;
 pub(crate) mod enumeration;
 mod extra;
 mod funcs;
+mod label;
 use gazebo::prelude::*;
 pub(crate) mod list;
+pub(crate) mod provider;
+mod random;
 pub(crate) mod record;
+mod regex;
+mod schema;
 pub(crate) mod string;
 pub(crate) mod structs;
+pub(crate) mod testing;
+#[cfg(feature = "time")]
+mod time;
+#[cfg(feature = "toml")]
+mod toml;
+pub(crate) mod transitive_set;
 pub(crate) mod util;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+/// Errors from the builtin functions, created with `type_error!` and `value_error!`,
+/// which exist to give the messages an [`ErrorKind`].
+#[derive(Debug, Error)]
+pub(crate) enum BuiltinError {
+    #[error("{0}")]
+    Type(String),
+    #[error("{0}")]
+    Value(String),
+}
+
+impl BuiltinError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Type(_) => ErrorKind::Type,
+            Self::Value(_) => ErrorKind::Value,
+        }
+    }
+}
 
 /// Return the default global environment, it is not yet frozen so that a caller
 /// can refine it.
@@ -42,7 +91,7 @@ pub(crate) fn standard_environment() -> GlobalsBuilder {
 }
 
 /// The extra library definitions available in this Starlark implementation, but not in the standard.
-#[derive(PartialEq, Eq, Copy, Clone, Dupe)]
+#[derive(PartialEq, Eq, Copy, Clone, Dupe, Debug)]
 pub enum LibraryExtension {
     /// Definitions to support the `struct` type, the `struct()` constructor.
     StructType,
@@ -50,6 +99,13 @@ pub enum LibraryExtension {
     RecordType,
     /// Definitions to support the `enum` type, the `enum()` constructor.
     EnumType,
+    /// Definitions to support the `provider` type, the `provider()` constructor.
+    ProviderType,
+    /// Definitions to support the `depset` type, the `depset()` constructor, modelled on Bazel.
+    DepsetType,
+    /// Definitions to support the `Label` type, the `Label()` constructor, parsing labels in the
+    /// [syntax](crate::eval::Evaluator::set_label_syntax) set by the embedder.
+    LabelType,
     /// A function `map(f, xs)` which applies `f` to each element of `xs` and returns the result.
     Map,
     /// A function `filter(f, xs)` which applies `f` to each element of `xs` and returns those for which `f` returns `True`.
@@ -63,11 +119,71 @@ pub enum LibraryExtension {
     /// Add a function `debug(x)` which shows the Rust [`Debug`](std::fmt::Debug) representation of a value.
     /// Useful when debugging, but the output should not be considered stable.
     Debug,
-    /// Add a function `print(x)` which prints to stdout.
+    /// Add a function `print(x)` which prints to stdout, or to the
+    /// [print handler](crate::eval::Evaluator::set_print_handler) if one is set,
+    /// and `pprint(x, width, indent, depth)` which prints `x` over multiple lines,
+    /// see [`Value::to_repr_pretty`](crate::values::Value::to_repr_pretty).
     Print,
     /// Add a function `breakpoint()` which will drop into a console-module evaluation prompt.
     Breakpoint,
-    // Make sure if you add anything new, you add it to `all` below.
+    /// Add functions `assert_eq(a, b)` and `assert_ne(a, b)`, and a struct `assert` with
+    /// `eq`, `ne`, `contains`, `true` and `fails`, for writing tests in Starlark.
+    /// Failing a test otherwise uses the standard `fail(msg)`.
+    Testing,
+    /// Add a function `isinstance(x, ty)` which checks whether `x` has type `ty`,
+    /// where `ty` is a constructor or type name with a `.type` attribute, e.g. `int` or one registered
+    /// with [`GlobalsBuilder::set_type_name`], or any type annotation, e.g. `"string"` or `[int.type, None]`.
+    Typing,
+    /// Add a function `memoize(f)` which returns a function calling `f`, but which remembers
+    /// the results, so calling it again with equal arguments returns the earlier result
+    /// without running `f`. Only suitable for functions whose result depends only on their
+    /// arguments. Results are only remembered once the module defining the memoized function
+    /// is frozen, e.g. when called from a module which loads it, and for the rest of the
+    /// [`Evaluator`](crate::eval::Evaluator), which counts them in
+    /// [`memoize_stats`](crate::eval::Evaluator::memoize_stats). Calls with arguments that
+    /// aren't hashable always run `f`. A remembered result is shared by every call returning
+    /// it, so shouldn't be modified.
+    Memoize,
+    /// Add a function `random(seed)` which returns a pseudo-random number generator with
+    /// methods `randint(a, b)`, `choice(xs)` and `shuffle(xs)`. The same `seed` always gives
+    /// the same results, on every platform and in every version, so it is suitable for
+    /// generating reproducible test data, but not for anything security related.
+    Random,
+    /// Add structs `hashlib`, with functions `sha256(x)` and `md5(x)` returning the hex digest
+    /// of a string, and `encoding`, with `base64_encode(x)` and `base64_decode(x)`.
+    /// The struct isn't called `hash`, as that would hide the standard `hash()` function.
+    /// Requires the `digest` cargo feature.
+    #[cfg(feature = "digest")]
+    Digest,
+    /// Add a struct `regex` with functions `match`, `search`, `findall`, `replace`, `split`,
+    /// `escape` and `check`, taking patterns in the syntax of the [`regex`](https://docs.rs/regex)
+    /// crate. Compiled patterns are cached, so using the same pattern repeatedly is cheap.
+    Regex,
+    /// Add a struct `time` with functions `parse(s)`, taking an RFC 3339 timestamp or a date,
+    /// `from_unix(seconds)`, `duration(days, hours, minutes, seconds, milliseconds)` and `now()`,
+    /// creating values of types `time` and `duration`, which support comparison and arithmetic.
+    /// The current time is only available if set with
+    /// [`Evaluator::set_current_time`](crate::eval::Evaluator::set_current_time), and is the
+    /// same throughout the evaluation, so results only depend on what the embedder provides.
+    /// Requires the `time` cargo feature.
+    #[cfg(feature = "time")]
+    Time,
+    /// Add a struct `schema` with functions `validate(x, schema)`, failing with the path to
+    /// every part of `x` which doesn't match `schema`, e.g. `config.servers[2].port`, and
+    /// `errors(x, schema)`, returning those messages as a list. Schemas are type annotations,
+    /// with structs giving the schema of each field, so decoded configuration can be checked.
+    Schema,
+    /// Add a struct `toml` with functions `decode(s)`, parsing a TOML document into a dictionary,
+    /// and `encode(x)`, writing a dictionary or struct as a TOML document.
+    /// Requires the `toml` cargo feature.
+    #[cfg(feature = "toml")]
+    Toml,
+    /// Add a struct `yaml` with functions `decode(s)`, parsing a YAML document into a value,
+    /// and `encode(x)`, writing a value as a YAML document.
+    /// Requires the `yaml` cargo feature.
+    #[cfg(feature = "yaml")]
+    Yaml,
+    // Make sure if you add anything new, you add it to `all` below, and `hermetic` if appropriate.
 }
 
 impl LibraryExtension {
@@ -75,8 +191,67 @@ impl LibraryExtension {
     pub fn all() -> &'static [Self] {
         use LibraryExtension::*;
         &[
-            StructType, RecordType, EnumType, Map, Filter, Partial, Dedupe, Debug, Print,
+            StructType,
+            RecordType,
+            EnumType,
+            ProviderType,
+            DepsetType,
+            LabelType,
+            Map,
+            Filter,
+            Partial,
+            Dedupe,
+            Debug,
+            Print,
             Breakpoint,
+            Testing,
+            Typing,
+            Memoize,
+            Random,
+            #[cfg(feature = "digest")]
+            Digest,
+            Regex,
+            #[cfg(feature = "time")]
+            Time,
+            Schema,
+            #[cfg(feature = "toml")]
+            Toml,
+            #[cfg(feature = "yaml")]
+            Yaml,
+        ]
+    }
+
+    /// The extensions which neither interact with the outside world (e.g. stdout)
+    /// nor produce output that may vary between versions, suitable for untrusted code.
+    /// Excludes `Print`, `Breakpoint` and `Debug`.
+    /// Everything in the Starlark standard is already hermetic.
+    pub fn hermetic() -> &'static [Self] {
+        use LibraryExtension::*;
+        &[
+            StructType,
+            RecordType,
+            EnumType,
+            ProviderType,
+            DepsetType,
+            LabelType,
+            Map,
+            Filter,
+            Partial,
+            Dedupe,
+            Testing,
+            Typing,
+            Memoize,
+            Random,
+            #[cfg(feature = "digest")]
+            Digest,
+            Regex,
+            #[cfg(feature = "time")]
+            Time,
+            Schema,
+            #[cfg(feature = "toml")]
+            Toml,
+            #[cfg(feature = "yaml")]
+            Yaml,
         ]
     }
 
@@ -87,6 +262,9 @@ impl LibraryExtension {
             StructType => structs::global(builder),
             RecordType => record::global(builder),
             EnumType => enumeration::global(builder),
+            ProviderType => provider::global(builder),
+            DepsetType => transitive_set::global(builder),
+            LabelType => label::global(builder),
             Map => extra::map(builder),
             Filter => extra::filter(builder),
             Partial => extra::partial(builder),
@@ -94,6 +272,20 @@ impl LibraryExtension {
             Debug => extra::debug(builder),
             Print => extra::print(builder),
             Breakpoint => breakpoint::global(builder),
+            Testing => testing::global(builder),
+            Typing => extra::typing(builder),
+            Memoize => extra::memoize(builder),
+            Random => random::global(builder),
+            #[cfg(feature = "digest")]
+            Digest => digest::global(builder),
+            Regex => regex::global(builder),
+            #[cfg(feature = "time")]
+            Time => time::global(builder),
+            Schema => schema::global(builder),
+            #[cfg(feature = "toml")]
+            Toml => toml::global(builder),
+            #[cfg(feature = "yaml")]
+            Yaml => yaml::global(builder),
         }
     }
 }
@@ -104,6 +296,7 @@ mod tests {
         self as starlark,
         assert::Assert,
         environment::{Globals, GlobalsBuilder, GlobalsStatic},
+        syntax::{AstModule, Dialect},
         values::{none::NoneType, Heap, StarlarkValue, UnpackValue, Value},
     };
     use gazebo::prelude::*;
@@ -187,4 +380,99 @@ getattr(True2, "invert2")() == False2
 "#,
         );
     }
+
+    #[test]
+    fn test_starlark_methods() {
+        #[derive(Debug)]
+        struct Counter(i32);
+        starlark_simple_value!(Counter);
+
+        #[starlark_methods]
+        impl Counter {
+            #[attribute]
+            fn count(&self) -> i32 {
+                Ok(self.0)
+            }
+
+            fn add(&self, n @ 1: i32) -> Counter {
+                Ok(Self(self.0 + n))
+            }
+
+            fn same(&self, other: Value) -> bool {
+                Ok(other
+                    .downcast_ref::<Self>()
+                    .map_or(false, |x| x.0 == self.0))
+            }
+
+            fn text(&self) -> String {
+                // Only the receiver is rewritten, not `self` in a path
+                Ok(self::Value::new_int(self.0).to_str())
+            }
+        }
+
+        impl<'v> StarlarkValue<'v> for Counter {
+            starlark_type!("counter");
+
+            fn get_members(&self) -> Option<&'static Globals> {
+                Self::starlark_members()
+            }
+        }
+
+        #[starlark_module]
+        fn globals(builder: &mut GlobalsBuilder) {
+            const zero: Counter = Counter(0);
+        }
+
+        let mut a = Assert::new();
+        a.globals_add(globals);
+        a.all_true(
+            r#"
+zero.count == 0
+zero.add().count == 1
+zero.add(5).add(n = 2).count == 7
+zero.same(zero.add(0))
+not zero.same(zero.add())
+not zero.same(1)
+zero.add(3).text() == "3"
+dir(zero) == ["add", "count", "same", "text"]
+"#,
+        );
+        a.fail("zero.add('x')", "Type of parameter `n`");
+    }
+
+    #[test]
+    fn test_hermetic() {
+        let globals = Globals::hermetic();
+        let names = globals.names();
+        for name in &["len", "struct", "enum", "map", "partial"] {
+            assert!(names.contains(&(*name).to_owned()), "missing {}", name);
+        }
+        for name in &["print", "breakpoint", "debug"] {
+            assert!(!names.contains(&(*name).to_owned()), "present {}", name);
+        }
+
+        let module = AstModule::parse(
+            "audit.star",
+            r#"
+load("lib.star", "helper")
+def len(x):
+    return 0
+def f(xs):
+    if False:
+        print(xs)
+    return [str(x) for x in sorted(xs)] + [len(xs), helper]
+"#
+            .to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+        assert_eq!(
+            Globals::extended().referenced_by(&module),
+            vec!["False", "print", "sorted", "str"]
+        );
+        assert_eq!(
+            globals.referenced_by(&module),
+            vec!["False", "sorted", "str"]
+        );
+    }
 }
@@ -0,0 +1,117 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Implementation of `provider` function.
+use crate as starlark;
+use crate::{
+    collections::SmallMap,
+    environment::GlobalsBuilder,
+    values::{dict::Dict, none::NoneType, provider::ProviderCallable, Value},
+};
+
+#[starlark_module]
+pub fn global(builder: &mut GlobalsBuilder) {
+    /// Creates a provider, a constructor for struct-like values with their own type.
+    ///
+    /// `fields` is either a list of field names, or a dictionary from field names to
+    /// their documentation. If given, the provider only accepts those fields, otherwise
+    /// it accepts any keyword arguments.
+    ///
+    /// Examples:
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// DefaultInfo = provider(doc = "Default outputs", fields = {"files": "The files"})
+    /// info = DefaultInfo(files = ["a.txt"])
+    /// info.files == ["a.txt"] and DefaultInfo.type == "DefaultInfo"
+    /// # "#);
+    /// ```
+    fn provider(doc: Option<&str>, fields: Option<Value>) -> ProviderCallable<'v> {
+        let fields = match fields {
+            None => None,
+            Some(fields) => {
+                let mut res = SmallMap::new();
+                if let Some(dict) = Dict::from_value(fields) {
+                    for (k, v) in dict.iter() {
+                        let k = k.unpack_str().ok_or_else(|| {
+                            type_error!("provider() field names must be strings, got `{}`", k)
+                        })?;
+                        res.insert(k.to_owned(), v);
+                    }
+                } else {
+                    for k in &fields.iterate(heap)? {
+                        let k = k.unpack_str().ok_or_else(|| {
+                            type_error!("provider() field names must be strings, got `{}`", k)
+                        })?;
+                        res.insert(k.to_owned(), heap.alloc(NoneType));
+                    }
+                }
+                Some(res)
+            }
+        };
+        Ok(ProviderCallable::new(doc.map(str::to_owned), fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_provider() {
+        assert::pass(
+            r#"
+MyInfo = provider(fields = ["a", "b"])
+x = MyInfo(a = 1)
+assert_eq(x.a, 1)
+assert_eq(hasattr(x, "a"), True)
+assert_eq(hasattr(x, "b"), False)
+assert_eq(getattr(x, "b", 42), 42)
+assert_eq(dir(x), ["a"])
+assert_eq(x, MyInfo(a = 1))
+assert_eq(x == MyInfo(a = 2), False)
+assert_eq(type(x), "provider")
+assert_eq(MyInfo.type, "MyInfo")
+assert_eq(repr(x), "MyInfo(a=1)")
+assert_eq({x: 1}[MyInfo(a = 1)], 1)
+assert_eq(MyInfo(a = 1, b = 2), MyInfo(b = 2, a = 1))
+assert_eq({MyInfo(a = 1, b = 2): 1}[MyInfo(b = 2, a = 1)], 1)
+"#,
+        );
+        assert::pass(
+            r#"
+AnyInfo = provider()
+OtherInfo = provider()
+assert_eq(AnyInfo(z = 3).z, 3)
+assert_eq(AnyInfo(z = 3) == OtherInfo(z = 3), False)
+def f(x: AnyInfo.type) -> "AnyInfo":
+    return x
+f(AnyInfo())
+"#,
+        );
+        assert::fails(
+            r#"
+MyInfo = provider(fields = ["a"])
+MyInfo(b = 1)
+"#,
+            &["extra named", "b"],
+        );
+        assert::fail("provider()(1)", "positional");
+        assert::fail("provider(fields = [1])", "must be strings");
+        assert::fail("x = provider()(a = 1).b", "b");
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A seeded pseudo-random number generator, for generating reproducible test data.
+
+use crate::{
+    self as starlark,
+    environment::{Globals, GlobalsBuilder, GlobalsStatic},
+    values::{
+        list::List, none::NoneType, AllocValue, ComplexValue, Freezer, Heap, SimpleValue,
+        StarlarkValue, Value, Walker,
+    },
+};
+use gazebo::any::AnyLifetime;
+
+/// A generator using [SplitMix64](https://prng.di.unimi.it/splitmix64.c), which is
+/// tiny, fast, and gives the same sequence for a seed on every platform and in every version.
+/// Not suitable for anything security related.
+#[derive(Debug, AnyLifetime)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub(crate) const TYPE: &'static str = "random";
+
+    fn new(seed: i32) -> Self {
+        Self {
+            state: seed as i64 as u64,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // A number in `0 .. n`, without the bias of a plain `%`.
+    fn below(&mut self, n: u64) -> u64 {
+        debug_assert!(n > 0);
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.next();
+            if x < limit {
+                return x % n;
+            }
+        }
+    }
+}
+
+impl<'v> StarlarkValue<'v> for Random {
+    starlark_type!(Random::TYPE);
+
+    fn get_members(&self) -> Option<&'static Globals> {
+        static RES: GlobalsStatic = GlobalsStatic::new();
+        RES.members(random_members)
+    }
+}
+
+impl<'v> AllocValue<'v> for Random {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        heap.alloc_complex(self)
+    }
+}
+
+impl<'v> ComplexValue<'v> for Random {
+    // Once frozen, every method fails, as they all advance the state
+    fn freeze(self: Box<Self>, _freezer: &Freezer) -> Box<dyn SimpleValue> {
+        self
+    }
+
+    unsafe fn walk(&mut self, _walker: &Walker<'v>) {}
+}
+
+impl SimpleValue for Random {}
+
+#[starlark_module]
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    /// Create a pseudo-random number generator starting from `seed`, which
+    /// always produces the same sequence for the same `seed`. The generator is
+    /// advanced by each call to its methods, so can't be used once frozen.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// a = random(42)
+    /// b = random(42)
+    /// [a.randint(1, 6) for _ in range(10)] == [b.randint(1, 6) for _ in range(10)]
+    /// # "#);
+    /// ```
+    #[starlark_type(Random::TYPE)]
+    fn random(seed: i32) -> Random {
+        Ok(Random::new(seed))
+    }
+}
+
+#[starlark_module]
+fn random_members(builder: &mut GlobalsBuilder) {
+    /// `rng.randint(a, b)` returns a number `x` such that `a <= x <= b`, with each
+    /// being equally likely. Fails if `a > b`.
+    fn randint(this: Value, a: i32, b: i32) -> i32 {
+        if a > b {
+            return Err(value_error!(
+                "randint() called with empty range ({}, {})",
+                a,
+                b
+            ));
+        }
+        let mut rng = this.downcast_mut::<Random>(heap)?.unwrap();
+        let range = (b as i64 - a as i64 + 1) as u64;
+        Ok((a as i64 + rng.below(range) as i64) as i32)
+    }
+
+    /// `rng.choice(xs)` returns an element of the non-empty sequence `xs`, with each
+    /// position being equally likely.
+    fn choice(this: Value, ref seq: Value) -> Value<'v> {
+        let len = seq.length()?;
+        if len == 0 {
+            return Err(value_error!("choice() called with an empty sequence"));
+        }
+        let i = this
+            .downcast_mut::<Random>(heap)?
+            .unwrap()
+            .below(len as u64);
+        seq.at(Value::new_int(i as i32), heap)
+    }
+
+    /// `rng.shuffle(xs)` puts the elements of the list `xs` in a random order,
+    /// with each order being equally likely, and returns `None`.
+    /// Fails if the list is frozen.
+    fn shuffle(this: Value, ref xs: Value) -> NoneType {
+        let mut rng = this.downcast_mut::<Random>(heap)?.unwrap();
+        let mut xs = match List::from_value_mut(xs, heap)? {
+            Some(xs) => xs,
+            None => {
+                return Err(type_error!(
+                    "shuffle() requires a list, got {}",
+                    xs.get_type()
+                ));
+            }
+        };
+        for i in (1..xs.content.len()).rev() {
+            let j = rng.below(i as u64 + 1) as usize;
+            xs.content.swap(i, j);
+        }
+        Ok(NoneType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert::{self, Assert};
+
+    #[test]
+    fn test_random() {
+        // The exact values are part of the contract, any change breaks reproducibility
+        assert::pass(
+            r#"
+rng = random(42)
+assert_eq([rng.randint(1, 100) for _ in range(5)], [14, 92, 59, 65, 51])
+rng = random(42)
+xs = list(range(1, 11))
+assert_eq(rng.shuffle(xs), None)
+assert_eq(xs, [1, 10, 6, 9, 7, 5, 8, 3, 2, 4])
+rng = random(7)
+assert_eq([rng.choice("abcd") for _ in range(4)], ["d", "a", "c", "d"])
+assert_eq(random(-1).randint(0, 1000000), 667288)
+"#,
+        );
+        assert::all_true(
+            r#"
+type(random(1)) == "random"
+all([0 <= random(i).randint(0, 3) <= 3 for i in range(100)])
+random(5).randint(-2000000000, 2000000000) == random(5).randint(-2000000000, 2000000000)
+random(1).randint(3, 3) == 3
+random(1).choice((True,))
+"#,
+        );
+        assert::fail("random(1).randint(2, 1)", "empty range");
+        assert::fail("random(1).choice([])", "empty sequence");
+        assert::fail("random(1).shuffle((1, 2))", "requires a list");
+        assert::fail("random(1).shuffle(1)", "requires a list");
+    }
+
+    #[test]
+    fn test_random_frozen() {
+        let mut a = Assert::new();
+        a.module("rng.star", "rng = random(1)");
+        a.fail("load('rng.star', 'rng')\nrng.randint(1, 2)", "Immutable");
+    }
+}
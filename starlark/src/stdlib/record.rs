@@ -34,7 +34,10 @@ pub fn global(builder: &mut GlobalsBuilder) {
         let mut mp = SmallMap::with_capacity(kwargs.len());
         for (k, v) in kwargs.into_iter_hashed() {
             let field = match Field::from_value(v) {
-                None => Field::new(v, None),
+                None => {
+                    v.validate_type()?;
+                    Field::new(v, None)
+                }
                 Some(v) => v.dupe(),
             };
             mp.insert_hashed(k, field);
@@ -55,6 +58,7 @@ pub fn global(builder: &mut GlobalsBuilder) {
     /// # "#);
     /// ```
     fn field(_type: Value, default: Option<Value>) -> Field<'v> {
+        _type.validate_type()?;
         Ok(Field::new(_type, default))
     }
 }
@@ -0,0 +1,211 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Regular expressions, using the syntax of the [`regex`](https://docs.rs/regex) crate.
+
+use crate as starlark;
+use crate::{
+    environment::GlobalsBuilder,
+    values::{none::NoneType, Heap, Value},
+};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::{collections::HashMap, sync::Mutex};
+
+// Scripts tend to use a handful of patterns many times, so compiled patterns are cached.
+// The cache is emptied when full, rather than tracking which patterns were used recently.
+const CACHE_SIZE: usize = 256;
+static CACHE: Lazy<Mutex<HashMap<String, Regex>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn compile(pattern: &str) -> anyhow::Result<Regex> {
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(x) = cache.get(pattern) {
+        return Ok(x.clone());
+    }
+    let x = match Regex::new(pattern) {
+        Ok(x) => x,
+        Err(e) => return Err(value_error!("invalid regex `{}`: {}", pattern, e)),
+    };
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(pattern.to_owned(), x.clone());
+    Ok(x)
+}
+
+// The whole match followed by each group, with `None` for groups which didn't match
+fn groups<'v>(captures: Option<Captures>, heap: &'v Heap) -> Value<'v> {
+    match captures {
+        None => Value::new_none(),
+        Some(captures) => heap.alloc(
+            captures
+                .iter()
+                .map(|x| match x {
+                    None => Value::new_none(),
+                    Some(x) => heap.alloc(x.as_str()),
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    builder.struct_("regex", regex_members);
+}
+
+#[starlark_module]
+fn regex_members(builder: &mut GlobalsBuilder) {
+    /// If `pattern` matches at the start of `s`, return a list of the whole match
+    /// followed by each group, with `None` for groups that didn't take part in the match.
+    /// Otherwise return `None`.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// regex.match("(\\w+)-(\\d+)", "lib-42.so") == ["lib-42", "lib", "42"]
+    /// regex.match("\\d+", "lib-42.so") == None
+    /// # "#);
+    /// ```
+    fn r#match(ref pattern: &str, ref s: &str) -> Value<'v> {
+        // Check the pattern on its own first, as wrapping it could make an invalid one
+        // such as `a)|(b` valid. The group is non-capturing, so the numbering is the same.
+        compile(pattern)?;
+        let anchored = compile(&format!("^(?:{})", pattern))?;
+        Ok(groups(anchored.captures(s), heap))
+    }
+
+    /// Like `match`, but finds the first match anywhere in `s`.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// regex.search("\\d+", "lib-42.so") == ["42"]
+    /// regex.search("(x)|(\\.)", "lib-42.so") == [".", None, "."]
+    /// # "#);
+    /// ```
+    fn search(ref pattern: &str, ref s: &str) -> Value<'v> {
+        Ok(groups(compile(pattern)?.captures(s), heap))
+    }
+
+    /// All the non-overlapping matches of `pattern` in `s`, as in Python.
+    /// Without groups each element is the whole match, with one group it is that group,
+    /// and with several it is a tuple of the groups. Groups that didn't match are `""`.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// regex.findall("\\d+", "1.22.333") == ["1", "22", "333"]
+    /// regex.findall("(\\w)=(\\d)", "a=1, b=2") == [("a", "1"), ("b", "2")]
+    /// # "#);
+    /// ```
+    fn findall(ref pattern: &str, ref s: &str) -> Vec<Value<'v>> {
+        let re = compile(pattern)?;
+        let group = |x: &Captures, i| heap.alloc(x.get(i).map_or("", |x| x.as_str()));
+        Ok(re
+            .captures_iter(s)
+            .map(|x| match x.len() {
+                1 => group(&x, 0),
+                2 => group(&x, 1),
+                n => heap.alloc_tuple(&(1..n).map(|i| group(&x, i)).collect::<Vec<_>>()),
+            })
+            .collect())
+    }
+
+    /// Replace the first `count` matches of `pattern` in `s` with `repl`, or all of them
+    /// if `count` is `0`. In `repl`, `$1` or `${name}` stand for a group, and `$$` for `$`.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// regex.replace("(\\w+)\\.c", "a.c b.c", "${1}.o") == "a.o b.o"
+    /// regex.replace("\\s+", "a  b   c", " ", count = 1) == "a b   c"
+    /// # "#);
+    /// ```
+    fn replace(ref pattern: &str, ref s: &str, ref repl: &str, count @ 0: i32) -> String {
+        if count < 0 {
+            return Err(value_error!(
+                "replace: count must not be negative, got {}",
+                count
+            ));
+        }
+        Ok(compile(pattern)?
+            .replacen(s, count as usize, repl)
+            .into_owned())
+    }
+
+    /// Split `s` at the matches of `pattern`, at most `maxsplit` times if it isn't `0`.
+    /// Unlike Python, the text matched by groups isn't included in the result.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// regex.split("[,;]\\s*", "a, b;c") == ["a", "b", "c"]
+    /// regex.split(",", "a,b,c", maxsplit = 1) == ["a", "b,c"]
+    /// # "#);
+    /// ```
+    fn split(ref pattern: &str, ref s: &str, maxsplit @ 0: i32) -> Vec<Value<'v>> {
+        let re = compile(pattern)?;
+        Ok(if maxsplit <= 0 {
+            re.split(s).map(|x| heap.alloc(x)).collect()
+        } else {
+            re.splitn(s, maxsplit as usize + 1)
+                .map(|x| heap.alloc(x))
+                .collect()
+        })
+    }
+
+    /// Escape all the characters in `s` which are special in a pattern,
+    /// so the result matches `s` literally.
+    fn escape(ref s: &str) -> String {
+        Ok(regex::escape(s))
+    }
+
+    /// Return `None` if `pattern` is valid, otherwise fail explaining why, so
+    /// patterns taken from configuration can be reported early.
+    fn check(ref pattern: &str) -> NoneType {
+        compile(pattern)?;
+        Ok(NoneType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_regex() {
+        assert::all_true(
+            r#"
+regex.match("a+", "aaab") == ["aaa"]
+regex.match("a+", "baaa") == None
+regex.match("(a)|(b)", "b") == ["b", None, "b"]
+regex.match("(?m)b", "a\nb") == None
+regex.search("(?P<n>\\d+)", "x12y") == ["12", "12"]
+regex.search("z", "abc") == None
+regex.findall("x", "abc") == []
+regex.findall("(a)(b)?", "aab") == [("a", ""), ("a", "b")]
+regex.replace("a", "banana", "o") == "bonono"
+regex.replace("a", "banana", "$$", count = 2) == "b$n$na"
+regex.replace("(?P<v>[aeiou])", "cat", "<$v>") == "c<a>t"
+regex.split("x", "") == [""]
+regex.split("\\s+", " a b ") == ["", "a", "b", ""]
+regex.escape("a.b*") == "a\\.b\\*"
+regex.match(regex.escape("1+1"), "1+1=2") == ["1+1"]
+regex.check("a|b") == None
+"#,
+        );
+        assert::fail("regex.match('(', 'x')", "invalid regex `(`");
+        assert::fail("regex.match('a)|(b', 'b')", "invalid regex `a)|(b`");
+        assert::fail("regex.check('[')", "invalid regex");
+        assert::fail("regex.replace('a', 'a', 'b', count = -1)", "negative");
+    }
+}
@@ -0,0 +1,312 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checking a value, e.g. a decoded configuration file, against a schema,
+//! reporting the path to each mismatch.
+
+use crate as starlark;
+use crate::{
+    environment::GlobalsBuilder,
+    values::{
+        dict::Dict, list::List, none::NoneType, structs::Struct, tuple::Tuple, TypingError, Value,
+    },
+};
+
+// Collects every mismatch, so all the problems with a value are reported at once.
+// Mistakes in the schema itself are returned as errors immediately.
+struct Validator {
+    allow_extra: bool,
+    errors: Vec<String>,
+}
+
+impl Validator {
+    fn new(allow_extra: bool) -> Self {
+        Self {
+            allow_extra,
+            errors: Vec::new(),
+        }
+    }
+
+    fn mismatch(&mut self, path: &str, x: Value, expected: &str) {
+        self.errors.push(format!(
+            "{}: expected {}, got {} `{}`",
+            path,
+            expected,
+            x.get_type(),
+            x.to_repr_for_error()
+        ));
+    }
+
+    // Would `x` match `ty`, without recording anything
+    fn accepts<'v>(&self, x: Value<'v>, ty: Value<'v>) -> anyhow::Result<bool> {
+        let mut v = Validator::new(self.allow_extra);
+        v.validate(x, ty, "")?;
+        Ok(v.errors.is_empty())
+    }
+
+    fn validate<'v>(&mut self, x: Value<'v>, ty: Value<'v>, path: &str) -> anyhow::Result<()> {
+        // Both the value and the schema can contain themselves
+        let _guard = crate::eval::call_stack::try_inc()?;
+        if let Some(t) = Struct::from_value(ty) {
+            let fields = t.fields.iter().map(|(k, v)| (k.as_str(), *v));
+            self.validate_fields(x, fields, path)
+        } else if let Some(t) = Tuple::from_value(ty) {
+            match Tuple::from_value(x) {
+                Some(xs) if xs.len() == t.len() => {
+                    for (i, (x, t)) in xs.iter().zip(t.iter()).enumerate() {
+                        self.validate(x, t, &format!("{}[{}]", path, i))?;
+                    }
+                    Ok(())
+                }
+                _ => {
+                    self.mismatch(path, x, &format!("a tuple of length {}", t.len()));
+                    Ok(())
+                }
+            }
+        } else if let Some(t) = List::from_value(ty) {
+            match t.len() {
+                0 => Err(TypingError::InvalidTypeAnnotation(ty.to_str_for_error()).into()),
+                1 => {
+                    let t = t.iter().next().unwrap();
+                    match List::from_value(x) {
+                        Some(xs) => {
+                            for (i, x) in xs.iter().enumerate() {
+                                self.validate(x, t, &format!("{}[{}]", path, i))?;
+                            }
+                        }
+                        None => self.mismatch(path, x, "list"),
+                    }
+                    Ok(())
+                }
+                _ => {
+                    for t in t.iter() {
+                        if self.accepts(x, t)? {
+                            return Ok(());
+                        }
+                    }
+                    self.mismatch(path, x, &ty.type_to_str());
+                    Ok(())
+                }
+            }
+        } else if let Some(t) = Dict::from_value(ty) {
+            // Only `{key_type: value_type}`, named keys are given with a struct
+            if t.len() != 1 {
+                return Err(TypingError::InvalidTypeAnnotation(ty.to_str_for_error()).into());
+            }
+            let (kt, vt) = t.iter().next().unwrap();
+            kt.validate_type()?;
+            match Dict::from_value(x) {
+                Some(xs) => {
+                    for (k, v) in xs.iter() {
+                        let path = format!("{}[{}]", path, k.to_repr_for_error());
+                        if !k.is_type(kt)? {
+                            self.mismatch(&path, k, &format!("key {}", kt.type_to_str()));
+                        }
+                        self.validate(v, vt, &path)?;
+                    }
+                }
+                None => self.mismatch(path, x, "dict"),
+            }
+            Ok(())
+        } else {
+            ty.validate_type()?;
+            if !x.is_type(ty)? {
+                self.mismatch(path, x, &ty.type_to_str());
+            }
+            Ok(())
+        }
+    }
+
+    // A dict or struct `x` with the fields of a struct schema, written as `path.field`
+    fn validate_fields<'v, 'a>(
+        &mut self,
+        x: Value<'v>,
+        fields: impl Iterator<Item = (&'a str, Value<'v>)>,
+        path: &str,
+    ) -> anyhow::Result<()> {
+        // The field names, or the `repr` of dictionary keys which aren't strings
+        let (names, values): (Vec<Result<String, String>>, Vec<Value>) =
+            if let Some(xs) = Dict::from_value(x) {
+                let names = xs.keys().into_iter().map(|k| match k.unpack_str() {
+                    Some(k) => Ok(k.to_owned()),
+                    None => Err(k.to_repr_for_error()),
+                });
+                (names.collect(), xs.values())
+            } else if let Some(xs) = Struct::from_value(x) {
+                let names = xs.fields.keys().map(|k| Ok(k.clone())).collect();
+                (names, xs.fields.values().copied().collect())
+            } else {
+                self.mismatch(path, x, "dict or struct");
+                return Ok(());
+            };
+        let mut seen = Vec::new();
+        for (name, ty) in fields {
+            let path = format!("{}.{}", path, name);
+            match names.iter().position(|k| matches!(k, Ok(k) if k == name)) {
+                Some(i) => {
+                    seen.push(i);
+                    self.validate(values[i], ty, &path)?;
+                }
+                None => self.missing(&path, ty)?,
+            }
+        }
+        if !self.allow_extra {
+            for (i, k) in names.iter().enumerate() {
+                if !seen.contains(&i) {
+                    self.errors.push(match k {
+                        Ok(k) => format!("{}.{}: unexpected field", path, k),
+                        Err(k) => format!("{}: unexpected key `{}`", path, k),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Fields which may be `None` may also be left out
+    fn missing(&mut self, path: &str, ty: Value) -> anyhow::Result<()> {
+        if !self.accepts(Value::new_none(), ty)? {
+            self.errors.push(format!("{}: missing", path));
+        }
+        Ok(())
+    }
+}
+
+fn collect_errors(
+    x: Value,
+    schema: Value,
+    name: &str,
+    allow_extra: bool,
+) -> anyhow::Result<Vec<String>> {
+    let mut v = Validator::new(allow_extra);
+    v.validate(x, schema, name)?;
+    Ok(v.errors)
+}
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    builder.struct_("schema", schema_members);
+}
+
+#[starlark_module]
+fn schema_members(builder: &mut GlobalsBuilder) {
+    /// Check that `x` matches `schema`, failing with a message giving the path
+    /// to every mismatch, starting from `name`, e.g. `config.servers[2].port: expected int, got ...`.
+    ///
+    /// A schema is a type annotation, e.g. `int.type` or `[str.type]`, or a struct, whose
+    /// fields give the schema of each field of a dict or struct. Schemas nest, e.g. a list of
+    /// servers is `[struct(host = str.type, port = int.type)]`, and a dict with string keys and
+    /// int values is `{str.type: int.type}`. A field whose schema accepts `None` may be left
+    /// out. Unless `allow_extra` is `True`, fields not in the schema are reported, as they are
+    /// often misspellings.
+    ///
+    /// ```
+    /// # starlark::assert::fail(r#"
+    /// server = struct(host = str.type, port = int.type)
+    /// config = {"servers": [{"host": "a", "port": 80}, {"host": "b", "port": "80"}]}
+    /// schema.validate(config, struct(servers = [server]), name = "config")
+    /// # "#, "config.servers[1].port: expected int, got string `\"80\"`");
+    /// ```
+    fn validate(
+        ref x: Value,
+        ref schema: Value,
+        ref name @ "value": &str,
+        ref allow_extra @ false: bool,
+    ) -> NoneType {
+        let errors = collect_errors(x, schema, name, allow_extra)?;
+        if errors.is_empty() {
+            Ok(NoneType)
+        } else {
+            Err(value_error!("{}", errors.join("\n")))
+        }
+    }
+
+    /// Like `validate`, but return the list of mismatches, which is empty if `x` matches.
+    fn errors(
+        ref x: Value,
+        ref schema: Value,
+        ref name @ "value": &str,
+        ref allow_extra @ false: bool,
+    ) -> Vec<String> {
+        collect_errors(x, schema, name, allow_extra)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_schema() {
+        assert::pass(
+            r#"
+server = struct(host = str.type, port = int.type, tags = [[str.type], None])
+cfg = struct(name = str.type, servers = [server], limits = {str.type: int.type})
+good = {
+    "name": "x",
+    "servers": [{"host": "a", "port": 1}, struct(host = "b", port = 2, tags = ["t"])],
+    "limits": {"cpu": 2},
+}
+schema.validate(good, cfg, name = "config")
+assert_eq(schema.errors(good, cfg), [])
+
+bad = {
+    "name": 1,
+    "servers": [{"host": "a", "port": "1", "tgas": []}, {"port": 2}, 3],
+    "limits": {"cpu": "2", 4: 5},
+}
+assert_eq(schema.errors(bad, cfg, name = "config"), [
+    "config.name: expected string, got int `1`",
+    "config.servers[0].port: expected int, got string `\"1\"`",
+    "config.servers[0].tgas: unexpected field",
+    "config.servers[1].host: missing",
+    "config.servers[2]: expected dict or struct, got int `3`",
+    "config.limits[\"cpu\"]: expected int, got string `\"2\"`",
+    "config.limits[4]: expected key string, got int `4`",
+])
+assert_eq(schema.errors({"tgas": []}, struct(), allow_extra = True), [])
+
+assert_eq(schema.errors((1, "x"), (int.type, int.type)), ["value[1]: expected int, got string `\"x\"`"])
+assert_eq(schema.errors((1,), (int.type, int.type)), ["value: expected a tuple of length 2, got tuple `(1,)`"])
+assert_eq(schema.errors("x", [int.type, None]), ["value: expected int | None, got string `\"x\"`"])
+assert_eq(schema.errors(None, [int.type, None]), [])
+assert_eq(schema.errors({1: 2}, struct()), ["value: unexpected key `1`"])
+"#,
+        );
+        assert::fail(
+            "schema.validate(1, struct(a = int.type))",
+            "value: expected dict or struct, got int `1`",
+        );
+        assert::fail("schema.validate(1, str.type)", "value: expected string");
+        assert::fail("schema.validate(1, [])", "not a valid type annotation");
+        assert::fail(
+            "schema.validate({'a': 1}, struct(a = 1))",
+            "not a valid type annotation",
+        );
+        assert::fail(
+            "schema.validate({'a': 1}, {'a': int.type, 'b': str.type})",
+            "not a valid type annotation",
+        );
+        assert::fail(
+            "schema.validate({1: 1}, {1: int.type})",
+            "not a valid type annotation",
+        );
+        assert::fail(
+            "l = []; l.append(l); schema.validate(l, l)",
+            "Too many recursion levels",
+        );
+    }
+}
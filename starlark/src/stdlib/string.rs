@@ -22,83 +22,197 @@ use crate::{
     collections::SmallMap,
     environment::GlobalsBuilder,
     stdlib::util::convert_indices,
-    values::{none::NoneOr, Heap, StarlarkValue, UnpackValue, Value, ValueError},
+    values::{
+        fast_string,
+        interpolation::format_spec,
+        none::NoneOr,
+        string_iter::{StringIterable, StringIterableKind},
+        Heap, StarlarkValue, UnpackValue, Value, ValueError,
+    },
 };
-use anyhow::anyhow;
 use gazebo::prelude::*;
-use std::str::FromStr;
+use std::{borrow::Cow, str::FromStr};
 
+/// Split a replacement field into the field name, conversion and format spec,
+/// e.g. `0[a:b].c!r:>10` into `0[a:b].c`, `Some("r")` and `>10`.
+/// A `!` or `:` inside `[]` is part of the field name.
+fn split_capture(capture: &str) -> (&str, Option<&str>, &str) {
+    let mut in_brackets = false;
+    let mut field_end = capture.len();
+    for (i, c) in capture.char_indices() {
+        match c {
+            '[' => in_brackets = true,
+            ']' => in_brackets = false,
+            '!' | ':' if !in_brackets => {
+                field_end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (field, rest) = capture.split_at(field_end);
+    match rest.strip_prefix('!') {
+        Some(rest) => match rest.find(':') {
+            Some(i) => (field, Some(&rest[..i]), &rest[i + 1..]),
+            None => (field, Some(rest), ""),
+        },
+        None => (field, None, rest.strip_prefix(':').unwrap_or(rest)),
+    }
+}
+
+/// Look up the `.attr` and `[key]` accessors in a field name, e.g. `.a[0]` in `{x.a[0]}`.
+fn format_accessors<'v>(
+    field: &str,
+    mut accessors: &str,
+    mut v: Value<'v>,
+    heap: &'v Heap,
+) -> anyhow::Result<Value<'v>> {
+    while !accessors.is_empty() {
+        if let Some(rest) = accessors.strip_prefix('.') {
+            let end = rest.find(|c| c == '.' || c == '[').unwrap_or(rest.len());
+            let attr = &rest[..end];
+            if attr.is_empty() {
+                return Err(value_error!(
+                    "Empty attribute in format string field `{}`",
+                    field
+                ));
+            }
+            v = v.get_attr(attr, heap)?.1;
+            accessors = &rest[end..];
+        } else if let Some(rest) = accessors.strip_prefix('[') {
+            let end = match rest.find(']') {
+                Some(end) => end,
+                None => {
+                    return Err(value_error!(
+                        "Missing ']' in format string field `{}`",
+                        field
+                    ))
+                }
+            };
+            let key = &rest[..end];
+            if key.is_empty() {
+                return Err(value_error!(
+                    "Empty index in format string field `{}`",
+                    field
+                ));
+            }
+            let index = match i32::from_str(key) {
+                Ok(i) if key.chars().all(|c| c.is_ascii_digit()) => Value::new_int(i),
+                _ => heap.alloc(key),
+            };
+            v = v.at(index, heap)?;
+            accessors = &rest[end + 1..];
+        } else {
+            return Err(value_error!(
+                "Only '.' or '[' may follow ']' in format string field `{}`",
+                field
+            ));
+        }
+    }
+    Ok(v)
+}
+
+/// Format a single replacement field, given the text from the opening `{` up to,
+/// but excluding, the closing `}`. The format spec may contain nested `{}` fields.
 fn format_capture<'v, T: Iterator<Item = Value<'v>>>(
     capture: &str,
     it: &mut T,
     captured_by_index: &mut bool,
     captured_by_order: &mut bool,
-    args: &[Value],
-    kwargs: &SmallMap<&str, Value>,
+    args: &[Value<'v>],
+    kwargs: &SmallMap<&str, Value<'v>>,
+    heap: &'v Heap,
 ) -> anyhow::Result<String> {
-    let (n, conv) = {
-        if let Some(x) = capture.find('!') {
-            (capture.get(1..x).unwrap(), capture.get(x + 1..).unwrap())
-        } else {
-            (capture.get(1..).unwrap(), "s")
-        }
-    };
-    let conv_s = |x: Value| x.to_str();
-    let conv_r = |x: Value| x.to_repr();
-    let conv: &dyn Fn(Value) -> String = match conv {
-        "s" => &conv_s,
-        "r" => &conv_r,
-        c => {
-            return Err(anyhow!(
-                concat!(
-                    "'{}' is not a valid format string specifier, only ",
-                    "'s' and 'r' are valid specifiers",
-                ),
-                c
-            ));
-        }
-    };
-    if n.is_empty() {
+    let capture = &capture[1..];
+    let (field, conv, spec) = split_capture(capture);
+    let name_end = field.find(|c| c == '.' || c == '[').unwrap_or(field.len());
+    let (n, accessors) = field.split_at(name_end);
+
+    let v = if n.is_empty() {
         if *captured_by_index {
-            return Err(anyhow!(
+            return Err(value_error!(
                 "Cannot mix manual field specification and automatic field numbering in format string",
             ));
         } else {
             *captured_by_order = true;
             if let Some(x) = it.next() {
-                return Ok(conv(x));
+                x
             } else {
-                return Err(anyhow!("Not enough parameters in format string"));
+                return Err(value_error!("Not enough parameters in format string"));
             }
         }
     } else if n.chars().all(|c| c.is_ascii_digit()) {
         if *captured_by_order {
-            return Err(anyhow!(
+            return Err(value_error!(
                 "Cannot mix manual field specification and automatic field numbering in format string",
             ));
         } else {
             *captured_by_index = true;
-            let i = i32::from_str(n).unwrap();
+            let i = i32::from_str(n)?;
             if i < 0 || i >= (args.len() as i32) {
                 return Err(ValueError::IndexOutOfBound(i).into());
             }
-            Ok(conv(args[i as usize]))
+            args[i as usize]
         }
     } else {
-        if let Some(x) = n.chars().find(|c| match c {
-            '.' | ',' | '[' | ']' => true,
-            _ => false,
-        }) {
-            return Err(anyhow!(
+        if let Some(x) = n.chars().find(|c| matches!(c, ',' | ']' | '{' | '}')) {
+            return Err(value_error!(
                 "Invalid character '{}' inside replacement field",
                 x
             ));
         }
         match kwargs.get(n) {
-            None => Err(ValueError::KeyNotFound(Box::<str>::from(n).to_repr()).into()),
-            Some(v) => Ok(conv(*v)),
+            None => return Err(ValueError::KeyNotFound(Box::<str>::from(n).to_repr()).into()),
+            Some(v) => *v,
         }
-    }
+    };
+    let v = format_accessors(field, accessors, v, heap)?;
+
+    let converted = match conv {
+        None => None,
+        Some("s") => Some(v.to_str()),
+        Some("r") => Some(v.to_repr()),
+        Some(c) => {
+            return Err(value_error!(
+                concat!(
+                    "'{}' is not a valid format string specifier, only ",
+                    "'s' and 'r' are valid specifiers",
+                ),
+                c
+            ));
+        }
+    };
+
+    // The format spec may itself contain replacement fields, e.g. `{:>{width}}`
+    let spec = if spec.contains('{') {
+        let mut expanded = String::with_capacity(spec.len());
+        let mut rest = spec;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(value_error!("Unmatched '{{' in format spec `{}`", spec)),
+            };
+            expanded += &format_capture(
+                &rest[start..end],
+                it,
+                captured_by_index,
+                captured_by_order,
+                args,
+                kwargs,
+                heap,
+            )?;
+            rest = &rest[end + 1..];
+        }
+        expanded.push_str(rest);
+        Cow::Owned(expanded)
+    } else {
+        Cow::Borrowed(spec)
+    };
+
+    let mut res = String::new();
+    format_spec(&mut res, &spec, v, converted)?;
+    Ok(res)
 }
 
 // This does not exists in rust, split would cut the string incorrectly and
@@ -156,6 +270,50 @@ fn rsplitn_whitespace(s: &str, maxsplit: usize) -> Vec<String> {
     v
 }
 
+/// Convert the `start` and `end` arguments of a string method to a range of bytes in `s`.
+/// They count characters, or bytes if the dialect enables byte strings.
+fn byte_range(s: &str, start: NoneOr<i32>, end: NoneOr<i32>, byte_strings: bool) -> (usize, usize) {
+    if byte_strings {
+        return convert_indices(s.len() as i32, start, end);
+    }
+    let (start, end) = convert_indices(fast_string::len(s) as i32, start, end);
+    let offset = |i| s.char_indices().nth(i).map_or(s.len(), |x| x.0);
+    (offset(start), offset(end))
+}
+
+/// Convert a byte offset within `s` into a position to return from a string method,
+/// counting characters, or bytes if the dialect enables byte strings.
+fn position(s: &str, offset: usize, byte_strings: bool) -> i32 {
+    if byte_strings {
+        offset as i32
+    } else {
+        fast_string::len(&s[..offset]) as i32
+    }
+}
+
+/// Find the byte offset of the first (or last) `needle` within the byte range of `s`.
+/// With byte strings the range may split a character, so we search the raw bytes.
+fn find_in(s: &str, needle: &str, (start, end): (usize, usize), last: bool) -> Option<usize> {
+    if start > end {
+        return None;
+    }
+    let res = match s.get(start..end) {
+        Some(s) if last => s.rfind(needle),
+        Some(s) => s.find(needle),
+        None => {
+            let (s, needle) = (&s.as_bytes()[start..end], needle.as_bytes());
+            if needle.is_empty() {
+                Some(if last { s.len() } else { 0 })
+            } else if last {
+                s.windows(needle.len()).rposition(|x| x == needle)
+            } else {
+                s.windows(needle.len()).position(|x| x == needle)
+            }
+        }
+    };
+    res.map(|x| x + start)
+}
+
 struct StringOrTuple<'v>(Vec<&'v str>);
 
 impl<'v> UnpackValue<'v> for StringOrTuple<'v> {
@@ -171,94 +329,113 @@ impl<'v> UnpackValue<'v> for StringOrTuple<'v> {
 #[starlark_module]
 pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// [string.elems](
-    /// https://github.com/google/skylark/blob/3705afa472e466b8b061cce44b47c9ddc6db696d/doc/spec.md#string·elems
-    /// ): returns an iterable of the bytes values of a string.
+    /// https://github.com/bazelbuild/starlark/blob/master/spec.md#string·elems
+    /// ): returns an iterable of the elements of a string.
     ///
-    /// `S.elems()` returns an iterable value containing the
-    /// sequence of numeric bytes values in the string S.
+    /// `S.elems()` returns an iterable value containing the successive
+    /// one-character substrings of S. As strings are indexed by Unicode code
+    /// point, this is the same sequence as `S.codepoints()`.
     ///
-    /// To materialize the entire sequence of bytes, apply `list(...)` to the
-    /// result.
+    /// By returning an iterable, not a list, the cost of splitting the string
+    /// is deferred until actually needed; apply `list(...)` to the result to
+    /// materialize the entire sequence.
     ///
-    /// Example:
+    /// Examples:
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// list("Hello, 世界".elems()) == ["H", "e", "l", "l", "o", ",", " ", "世", "界"]
+    /// # "#);
+    /// ```
+    fn elems(this: Value) -> StringIterable<'v> {
+        Ok(StringIterable::new(this, StringIterableKind::Elems))
+    }
+
+    /// [string.elem_ords](
+    /// https://github.com/bazelbuild/starlark/blob/master/spec.md#string·elem_ords
+    /// ): returns an iterable of the integer values of the elements of a string.
+    ///
+    /// `S.elem_ords()` returns an iterable value containing the integer value
+    /// of each element of S, as would be returned by `ord`.
     ///
     /// Examples:
     ///
     /// ```
     /// # starlark::assert::is_true(r#"
-    /// list("Hello, 世界".elems()) == [
-    ///     72, 101, 108, 108, 111, 44, 32, 228, 184, 150, 231, 149, 140]
+    /// list("Hello, 世界".elem_ords()) == [72, 101, 108, 108, 111, 44, 32, 19990, 30028]
     /// # "#);
     /// ```
-    fn elems(this: &str) -> Vec<i32> {
-        // Note that we return a list here... Which is not equivalent to the go
-        // implementation.
-        Ok(this.as_bytes().map(|x| *x as i32))
+    fn elem_ords(this: Value) -> StringIterable<'v> {
+        Ok(StringIterable::new(this, StringIterableKind::ElemOrds))
     }
 
     /// [string.capitalize](
     /// https://github.com/google/skylark/blob/3705afa472e466b8b061cce44b47c9ddc6db696d/doc/spec.md#string·capitalize
-    /// ): returns a copy of string, with each first letter of a word in upper
-    /// case.
+    /// ): returns a copy of string, with the first letter in upper case.
     ///
-    /// `S.capitalize()` returns a copy of string S with all Unicode letters
-    /// that begin words changed to their title case.
+    /// `S.capitalize()` returns a copy of string S with its first code point
+    /// changed to its title case and all subsequent letters changed to their
+    /// lower case.
     ///
     /// Examples:
     ///
     /// ```
     /// # starlark::assert::all_true(r#"
-    /// "hello, world!".capitalize() == "Hello, World!"
+    /// "hello, world!".capitalize() == "Hello, world!"
+    /// "hElLo, WoRlD!".capitalize() == "Hello, world!"
+    /// "".capitalize() == ""
     /// # "#);
     /// ```
     fn capitalize(this: &str) -> String {
-        let mut last_space = true;
-        let mut result = String::new();
-        for c in this.chars() {
-            if !c.is_alphanumeric() {
-                last_space = true;
-                result.push(c);
-            } else {
-                if last_space {
-                    for c1 in c.to_uppercase() {
-                        result.push(c1);
-                    }
-                } else {
-                    result.push(c);
-                }
-                last_space = false;
-            }
+        let mut chars = this.chars();
+        let mut result = String::with_capacity(this.len());
+        if let Some(c) = chars.next() {
+            result.extend(c.to_uppercase());
+        }
+        for c in chars {
+            result.extend(c.to_lowercase());
         }
         Ok(result)
     }
 
     /// [string.codepoints](
-    /// https://github.com/google/skylark/blob/3705afa472e466b8b061cce44b47c9ddc6db696d/doc/spec.md#string·codepoints
-    /// ): returns an iterable of the unicode codepoint of a string.
+    /// https://github.com/bazelbuild/starlark/blob/master/spec.md#string·codepoints
+    /// ): returns an iterable of the unicode codepoints of a string.
     ///
-    /// `S.codepoints()` returns an iterable value containing the
-    /// sequence of integer Unicode code points encoded by the string S.
-    /// Each invalid code within the string is treated as if it encodes the
-    /// Unicode replacement character, U+FFFD.
+    /// `S.codepoints()` returns an iterable value containing the sequence of
+    /// substrings of S that each encode a single Unicode code point.
     ///
     /// By returning an iterable, not a list, the cost of decoding the string
     /// is deferred until actually needed; apply `list(...)` to the result to
     /// materialize the entire sequence.
     ///
-    /// Example:
+    /// Examples:
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// list("Hello, 世界".codepoints()) == ["H", "e", "l", "l", "o", ",", " ", "世", "界"]
+    /// # "#);
+    /// ```
+    fn codepoints(this: Value) -> StringIterable<'v> {
+        Ok(StringIterable::new(this, StringIterableKind::Codepoints))
+    }
+
+    /// [string.codepoint_ords](
+    /// https://github.com/bazelbuild/starlark/blob/master/spec.md#string·codepoint_ords
+    /// ): returns an iterable of the integer unicode codepoints of a string.
+    ///
+    /// `S.codepoint_ords()` returns an iterable value containing the sequence
+    /// of integer Unicode code points encoded by the string S.
     ///
     /// Examples:
     ///
     /// ```
     /// # starlark::assert::all_true(r#"
-    /// list("Hello, 世界".codepoints()) == [72, 101, 108, 108, 111, 44, 32, 19990, 30028]
+    /// list("Hello, 世界".codepoint_ords()) == [72, 101, 108, 108, 111, 44, 32, 19990, 30028]
     /// # "#);
     /// ```
-    fn codepoints(this: &str) -> Vec<i32> {
-        // Note that we return a list here... Which is not equivalent to the go
-        // implementation.
-        let v: Vec<i32> = this.chars().map(|x| u32::from(x) as i32).collect();
-        Ok(v)
+    fn codepoint_ords(this: Value) -> StringIterable<'v> {
+        Ok(StringIterable::new(this, StringIterableKind::CodepointOrds))
     }
 
     /// [string.count](
@@ -292,12 +469,20 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         ref start @ NoneOr::None: NoneOr<i32>,
         ref end @ NoneOr::None: NoneOr<i32>,
     ) -> i32 {
-        let (start, end) = convert_indices(this.len() as i32, start, end);
+        let (mut start, end) = byte_range(this, start, end, ctx.byte_strings);
+        if start > end {
+            return Ok(0);
+        }
+        if needle.is_empty() {
+            // Matches at every position, including the end
+            return Ok(position(this, end, ctx.byte_strings)
+                - position(this, start, ctx.byte_strings)
+                + 1);
+        }
         let mut counter = 0i32;
-        let mut s = this.get(start..end).unwrap();
-        while let Some(offset) = s.find(needle) {
+        while let Some(offset) = find_in(this, needle, (start, end), false) {
             counter += 1;
-            s = s.get(offset + needle.len()..).unwrap_or("");
+            start = offset + needle.len();
         }
         Ok(counter)
     }
@@ -355,11 +540,9 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         ref start @ NoneOr::None: NoneOr<i32>,
         ref end @ NoneOr::None: NoneOr<i32>,
     ) -> i32 {
-        let (start, end) = convert_indices(this.len() as i32, start, end);
-        if let Some(substring) = this.get(start..end) {
-            if let Some(offset) = substring.find(needle) {
-                return Ok((offset + start) as i32);
-            }
+        let range = byte_range(this, start, end, ctx.byte_strings);
+        if let Some(offset) = find_in(this, needle, range, false) {
+            return Ok(position(this, offset, ctx.byte_strings));
         }
         Ok(-1)
     }
@@ -394,6 +577,8 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// If all the numeric field names form the sequence 0, 1, 2, and so on,
     /// they may be omitted and those values will be implied; however,
     /// the explicit and implicit forms may not be mixed.
+    /// The field name may be followed by any number of `.attr` attribute
+    /// accesses and `[key]` index accesses, where a key of only digits is an integer.
     ///
     /// The *conversion* specifies how to convert an argument value `x` to a
     /// string. It may be either `!r`, which converts the value using
@@ -401,8 +586,11 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// the default.
     ///
     /// The *format specifier*, after a colon, specifies field width,
-    /// alignment, padding, and numeric precision.
-    /// Currently it must be empty, but it is reserved for future use.
+    /// alignment, padding, and numeric precision, following the
+    /// [Python format specification mini-language](https://docs.python.org/3/library/string.html#format-specification-mini-language),
+    /// `[[fill]align][sign][#][0][width][grouping][.precision][type]`,
+    /// except there are no floating point types.
+    /// It may contain nested replacement fields, e.g. `{:>{width}}`.
     ///
     /// Examples:
     ///
@@ -413,6 +601,10 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// "a{}b{}c".format(1, 2) == "a1b2c"
     /// "({1}, {0})".format("zero", "one") == "(one, zero)"
     /// "Is {0!r} {0!s}?".format("heterological") == "Is \"heterological\" heterological?"
+    /// "{0[1]} {x[k]}".format([1, 2], x={"k": 3}) == "2 3"
+    /// "[{:>5}] [{:<5}] [{:^5}]".format("a", "b", "c") == "[    a] [b    ] [  c  ]"
+    /// "{:+05} {:#x} {:,}".format(42, 255, 1234567) == "+0042 0xff 1,234,567"
+    /// "{:>{width}}".format("a", width=3) == "  a"
     /// # "#);
     /// ```
     fn format(this: &str, args: Vec<Value>, kwargs: SmallMap<&str, Value>) -> String {
@@ -420,41 +612,63 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         let mut captured_by_index = false;
         let mut captured_by_order = false;
         let mut result = String::new();
-        let mut capture = String::new();
-        for c in this.chars() {
-            match (c, capture.as_str()) {
-                ('{', "") | ('}', "") => capture.push(c),
-                (.., "") => result.push(c),
-                ('{', "{") => {
+        let mut chars = this.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|x| x.1) == Some('{') => {
+                    chars.next();
                     result.push('{');
-                    capture.clear();
                 }
-                ('{', "}") => return Err(anyhow!("Standalone '}}' in format string `{}`", this)),
-                ('{', ..) => return Err(anyhow!("Unmatched '{' in format string")),
-                ('}', "}") => {
+                '}' if chars.peek().map(|x| x.1) == Some('}') => {
+                    chars.next();
                     result.push('}');
-                    capture.clear();
                 }
-                ('}', ..) => {
+                '}' => {
+                    return Err(value_error!(
+                        "Standalone '}}' at position {} in format string `{}`",
+                        i,
+                        this
+                    ));
+                }
+                '{' => {
+                    // Find the matching `}`, allowing `{}` nested inside the format spec
+                    let mut depth = 0;
+                    let mut end = None;
+                    for (j, c) in chars.by_ref() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' if depth == 0 => {
+                                end = Some(j);
+                                break;
+                            }
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                    let end = match end {
+                        Some(end) => end,
+                        None => {
+                            return Err(value_error!(
+                                "Unmatched '{{' at position {} in format string `{}`",
+                                i,
+                                this
+                            ));
+                        }
+                    };
                     result += &format_capture(
-                        &capture,
+                        &this[i..end],
                         &mut it,
                         &mut captured_by_index,
                         &mut captured_by_order,
                         &args,
                         &kwargs,
+                        heap,
                     )?;
-                    capture.clear();
                 }
-                (.., "}") => return Err(anyhow!("Standalone '}}' in format string `{}`", this)),
-                _ => capture.push(c),
+                c => result.push(c),
             }
         }
-        match capture.as_str() {
-            "}" => Err(anyhow!("Standalone '}}' in format string `{}`", this)),
-            "" => Ok(result),
-            _ => Err(anyhow!("Unmatched '{' in format string")),
-        }
+        Ok(result)
     }
 
     /// [string.index](
@@ -482,13 +696,15 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         ref start @ NoneOr::None: NoneOr<i32>,
         ref end @ NoneOr::None: NoneOr<i32>,
     ) -> i32 {
-        let (start, end) = convert_indices(this.len() as i32, start, end);
-        if let Some(substring) = this.get(start..end) {
-            if let Some(offset) = substring.find(needle) {
-                return Ok((offset + start) as i32);
-            }
+        let range = byte_range(this, start, end, ctx.byte_strings);
+        if let Some(offset) = find_in(this, needle, range, false) {
+            return Ok(position(this, offset, ctx.byte_strings));
         }
-        Err(anyhow!("Substring '{}' not found in '{}'", needle, this))
+        Err(value_error!(
+            "Substring '{}' not found in '{}'",
+            needle,
+            this
+        ))
     }
 
     /// [string.isalnum](
@@ -796,7 +1012,9 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// ```
     fn partition(this: &str, ref needle @ " ": &str) -> (String, String, String) {
         if needle.is_empty() {
-            return Err(anyhow!("Empty separator cannot be used for partitioning"));
+            return Err(value_error!(
+                "Empty separator cannot be used for partitioning"
+            ));
         }
         if let Some(offset) = this.find(needle) {
             let offset2 = offset + needle.len();
@@ -833,7 +1051,10 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     fn replace(this: &str, ref old: &str, ref new: &str, ref count: Option<i32>) -> String {
         match count {
             Some(count) if count >= 0 => Ok(this.replacen(old, new, count as usize)),
-            Some(count) => Err(anyhow!("Replace final argument was negative '{}'", count)),
+            Some(count) => Err(value_error!(
+                "Replace final argument was negative '{}'",
+                count
+            )),
             None => Ok(this.replace(old, new)),
         }
     }
@@ -861,11 +1082,9 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         ref start @ NoneOr::None: NoneOr<i32>,
         ref end @ NoneOr::None: NoneOr<i32>,
     ) -> i32 {
-        let (start, end) = convert_indices(this.len() as i32, start, end);
-        if let Some(substring) = this.get(start..end) {
-            if let Some(offset) = substring.rfind(needle) {
-                return Ok((offset + start) as i32);
-            }
+        let range = byte_range(this, start, end, ctx.byte_strings);
+        if let Some(offset) = find_in(this, needle, range, true) {
+            return Ok(position(this, offset, ctx.byte_strings));
         }
         Ok(-1)
     }
@@ -895,13 +1114,15 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
         ref start @ NoneOr::None: NoneOr<i32>,
         ref end @ NoneOr::None: NoneOr<i32>,
     ) -> i32 {
-        let (start, end) = convert_indices(this.len() as i32, start, end);
-        if let Some(substring) = this.get(start..end) {
-            if let Some(offset) = substring.rfind(needle) {
-                return Ok((offset + start) as i32);
-            }
+        let range = byte_range(this, start, end, ctx.byte_strings);
+        if let Some(offset) = find_in(this, needle, range, true) {
+            return Ok(position(this, offset, ctx.byte_strings));
         }
-        Err(anyhow!("Substring '{}' not found in '{}'", needle, this))
+        Err(value_error!(
+            "Substring '{}' not found in '{}'",
+            needle,
+            this
+        ))
     }
 
     /// [string.rpartition](
@@ -920,7 +1141,9 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// ```
     fn rpartition(this: &str, ref needle @ " ": &str) -> (String, String, String) {
         if needle.is_empty() {
-            return Err(anyhow!("Empty separator cannot be used for partitioning"));
+            return Err(value_error!(
+                "Empty separator cannot be used for partitioning"
+            ));
         }
         if let Some(offset) = this.rfind(needle) {
             let offset2 = offset + needle.len();
@@ -966,7 +1189,11 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
                 }
             }
         };
-        Ok(match sep.into_option() {
+        let sep = sep.into_option();
+        if sep == Some("") {
+            return Err(value_error!("Empty separator cannot be used for splitting"));
+        }
+        Ok(match sep {
             None => match maxsplit {
                 None => this.split_whitespace().map(ToOwned::to_owned).collect(),
                 Some(maxsplit) => rsplitn_whitespace(this, maxsplit),
@@ -1053,7 +1280,11 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
                 }
             }
         };
-        Ok(match (sep.into_option(), maxsplit) {
+        let sep = sep.into_option();
+        if sep == Some("") {
+            return Err(value_error!("Empty separator cannot be used for splitting"));
+        }
+        Ok(match (sep, maxsplit) {
             (None, None) => this.split_whitespace().map(ToOwned::to_owned).collect(),
             (None, Some(maxsplit)) => splitn_whitespace(this, maxsplit),
             (Some(sep), None) => this.split(sep).map(ToOwned::to_owned).collect(),
@@ -1189,7 +1420,7 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// https://github.com/google/skylark/blob/3705afa472e466b8b061cce44b47c9ddc6db696d/doc/spec.md#string·title
     /// ): convert a string to title case.
     ///
-    /// `S.lower()` returns a copy of the string S with letters converted to
+    /// `S.title()` returns a copy of the string S with letters converted to
     /// titlecase.
     ///
     /// Letters are converted to uppercase at the start of words, lowercase
@@ -1231,8 +1462,8 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
     /// https://github.com/google/skylark/blob/3705afa472e466b8b061cce44b47c9ddc6db696d/doc/spec.md#string·upper
     /// ): convert a string to all uppercase.
     ///
-    /// `S.lower()` returns a copy of the string S with letters converted to
-    /// lowercase.
+    /// `S.upper()` returns a copy of the string S with letters converted to
+    /// uppercase.
     ///
     /// Examples:
     ///
@@ -1272,9 +1503,9 @@ pub(crate) fn string_members(builder: &mut GlobalsBuilder) {
 
     /// [string.removesuffix](
     /// https://docs.python.org/3.9/library/stdtypes.html#str.removesuffix
-    /// ): remove a prefix from a string. _Not part of standard Starlark._
+    /// ): remove a suffix from a string. _Not part of standard Starlark._
     ///
-    /// If the string starts with the prefix string, return `string[len(prefix):]`.
+    /// If the string ends with the suffix string, return `string[:-len(suffix)]`.
     /// Otherwise, return a copy of the original string:
     ///
     /// Examples:
@@ -1323,6 +1554,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "1"
@@ -1335,6 +1567,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "2"
@@ -1347,6 +1580,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "\"3\""
@@ -1359,6 +1593,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "\"x\""
@@ -1371,6 +1606,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "x"
@@ -1383,6 +1619,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .is_err()
         );
@@ -1398,6 +1635,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .unwrap(),
             "2"
@@ -1410,6 +1648,7 @@ mod tests {
                 &mut captured_by_order,
                 &args,
                 &kwargs,
+                &heap,
             )
             .is_err()
         );
@@ -1420,5 +1659,37 @@ mod tests {
         assert::fail(r#""bonbon".index("on", 2, 5)"#, "not found in");
         assert::fail(r#"("banana".replace("a", "o", -2))"#, "negative");
         assert::fail(r#""bonbon".rindex("on", 2, 5)"#, "not found in");
+        assert::fail(r#""banana".split("")"#, "Empty separator");
+        assert::fail(r#""banana".rsplit("", 1)"#, "Empty separator");
+        assert::fail(r#""banana".partition("")"#, "Empty separator");
+        assert::fail(r#""banana".rpartition("")"#, "Empty separator");
+    }
+
+    #[test]
+    fn test_string_edge_cases() {
+        assert::all_true(
+            r#"
+"".splitlines() == []
+"\n".splitlines() == [""]
+"a\r\nb\rc\n".splitlines() == ["a", "b", "c"]
+"a\r\nb\rc\n".splitlines(True) == ["a\r\n", "b\r", "c\n"]
+"  a b  c  ".split(None, 1) == ["a", "b  c  "]
+"  a b  c  ".rsplit(None, 1) == ["  a b", "c"]
+"a,b,c".rsplit(",", 0) == ["a,b,c"]
+"a,b,c".rsplit(",", -1) == ["a", "b", "c"]
+"".split(",") == [""]
+"   ".split() == []
+"a-b-c".partition("-") == ("a", "-", "b-c")
+"a-b-c".rpartition("-") == ("a-b", "-", "c")
+"abc".partition("-") == ("abc", "", "")
+"abc".rpartition("-") == ("", "", "abc")
+"abc".removeprefix("") == "abc"
+"abc".removesuffix("c") == "ab"
+"abc".removesuffix("abcd") == "abc"
+"they're bill's friends".title() == "They'Re Bill'S Friends"
+"hello1world".title() == "Hello1World"
+"ÉCOLE".capitalize() == "École"
+"#,
+        );
     }
 }
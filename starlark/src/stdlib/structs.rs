@@ -36,4 +36,28 @@ pub(crate) fn struct_members(builder: &mut GlobalsBuilder) {
     fn to_json(this: Value) -> String {
         Ok(this.to_json())
     }
+
+    /// Convert the struct to a message in protocol buffer text format, like Bazel's
+    /// `struct.to_proto()`. Fields are written in order of name. Nested structs become
+    /// nested messages, lists and tuples become repeated fields, and dictionaries become
+    /// repeated messages with `key` and `value` fields.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// struct(name = "x", sizes = [1, 2], opts = struct(on = True)).to_proto() == """name: "x"
+    /// opts {
+    ///   on: true
+    /// }
+    /// sizes: 1
+    /// sizes: 2
+    /// """
+    /// # "#);
+    /// ```
+    fn to_proto(this: Value) -> String {
+        let mut res = String::new();
+        Struct::from_value(this)
+            .unwrap()
+            .collect_proto_fields(0, &mut res)?;
+        Ok(res)
+    }
 }
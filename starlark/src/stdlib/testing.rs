@@ -0,0 +1,146 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Functions for writing tests in Starlark.
+use crate as starlark;
+use crate::{
+    environment::GlobalsBuilder,
+    values::{none::NoneType, Value},
+};
+
+fn assert_equals<'v>(a: Value<'v>, b: Value<'v>) -> anyhow::Result<NoneType> {
+    if !a.equals(b)? {
+        Err(value_error!("assert_eq: expected {}, got {}", a, b))
+    } else {
+        Ok(NoneType)
+    }
+}
+
+fn assert_different<'v>(func: &str, a: Value<'v>, b: Value<'v>) -> anyhow::Result<NoneType> {
+    if a.equals(b)? {
+        Err(value_error!(
+            "{}: expected {} and {} different, but the same",
+            func,
+            a,
+            b
+        ))
+    } else {
+        Ok(NoneType)
+    }
+}
+
+pub fn global(builder: &mut GlobalsBuilder) {
+    functions(builder);
+    builder.struct_("assert", assert_members);
+}
+
+#[starlark_module]
+fn functions(builder: &mut GlobalsBuilder) {
+    /// Fail unless `a == b`.
+    fn assert_eq(a: Value, b: Value) -> NoneType {
+        assert_equals(a, b)
+    }
+
+    /// Fail if `a == b`.
+    fn assert_ne(a: Value, b: Value) -> NoneType {
+        assert_different("assert_ne", a, b)
+    }
+}
+
+/// The members of the `assert` struct, matching `assert.star` from the Go test suite.
+#[starlark_module]
+pub(crate) fn assert_members(builder: &mut GlobalsBuilder) {
+    fn eq(a: Value, b: Value) -> NoneType {
+        assert_equals(a, b)
+    }
+
+    fn ne(a: Value, b: Value) -> NoneType {
+        assert_different("assert.ne", a, b)
+    }
+
+    fn contains(xs: Value, x: Value) -> NoneType {
+        if !xs.is_in(x)? {
+            Err(value_error!(
+                "assert.contains: expected {} to be in {}",
+                x,
+                xs
+            ))
+        } else {
+            Ok(NoneType)
+        }
+    }
+
+    fn r#true(x: Value) -> NoneType {
+        if !x.to_bool() {
+            Err(value_error!(
+                "assert.true: expected a true value, got {}",
+                x
+            ))
+        } else {
+            Ok(NoneType)
+        }
+    }
+
+    fn fails(f: Value, msg: &str) -> NoneType {
+        let invoke = f.new_invoker(heap)?;
+        match invoke.invoke(f, None, ctx) {
+            Err(e) => {
+                let e = format!("{:#}", e);
+                if e.contains(msg) {
+                    Ok(NoneType)
+                } else {
+                    Err(value_error!(
+                        "assert.fails: expected an error containing `{}`, got `{}`",
+                        msg,
+                        e
+                    ))
+                }
+            }
+            Ok(_) => Err(value_error!("assert.fails: didn't fail")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_testing() {
+        assert::pass(
+            r#"
+assert_eq([1, 2], [1, 2])
+assert_ne(1, 2)
+assert.eq("x", "x")
+assert.ne("x", "y")
+assert.true(1)
+assert.contains([1, 2], 2)
+def bad():
+    fail("oops")
+assert.fails(bad, "oops")
+"#,
+        );
+        assert::fail("assert_eq(1, 2)", "expected 1, got 2");
+        assert::fail("assert_ne(1, 1)", "different");
+        assert::fail("assert.true(0)", "expected a true value, got 0");
+        assert::fail("assert.fails(lambda: 1, 'x')", "didn't fail");
+        assert::fail(
+            "def bad():\n    fail('oops')\nassert.fails(bad, 'other')",
+            "expected an error containing `other`",
+        );
+    }
+}
@@ -0,0 +1,447 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Times and durations, where the current time is only available if the embedder
+//! provides it with [`Evaluator::set_current_time`](crate::eval::Evaluator::set_current_time).
+
+use crate as starlark;
+use crate::{
+    environment::{Globals, GlobalsBuilder, GlobalsStatic},
+    values::{Heap, StarlarkValue, UnpackValue, Value, ValueError},
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Datelike, FixedOffset, NaiveDate, SecondsFormat, TimeZone, Timelike, Utc,
+};
+use std::{
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+};
+
+/// An instant, along with the UTC offset it is shown in.
+/// Equality and ordering only consider the instant.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Time(DateTime<FixedOffset>);
+starlark_simple_value!(Time);
+
+/// The difference between two [`Time`]s, with millisecond precision when constructed,
+/// but exact when computed by subtraction.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Duration(chrono::Duration);
+starlark_simple_value!(Duration);
+
+fn utc_offset() -> FixedOffset {
+    FixedOffset::east(0)
+}
+
+fn hash<T: Hash>(x: &T) -> u64 {
+    let mut s = DefaultHasher::new();
+    x.hash(&mut s);
+    s.finish()
+}
+
+fn to_int(x: i64, what: &str) -> anyhow::Result<i32> {
+    i32::try_from(x).map_err(|_| value_error!("{} {} does not fit in an int", what, x))
+}
+
+fn out_of_range<T>(x: Option<T>) -> anyhow::Result<T> {
+    x.ok_or_else(|| value_error!("time out of range"))
+}
+
+impl Time {
+    pub(crate) const TYPE: &'static str = "time";
+
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        if let Ok(x) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self(x));
+        }
+        match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(x) => Ok(Self(utc_offset().from_utc_datetime(&x.and_hms(0, 0, 0)))),
+            Err(_) => Err(value_error!(
+                "time.parse: expected a timestamp like `2021-03-04T05:06:07Z` or a date like `2021-03-04`, got `{}`",
+                s
+            )),
+        }
+    }
+
+    fn to_iso(self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+}
+
+impl Duration {
+    pub(crate) const TYPE: &'static str = "duration";
+}
+
+impl<'v> UnpackValue<'v> for Time {
+    fn unpack_value(value: Value<'v>, _heap: &'v Heap) -> Option<Self> {
+        Time::from_value(value).map(|x| *x)
+    }
+}
+
+impl<'v> UnpackValue<'v> for Duration {
+    fn unpack_value(value: Value<'v>, _heap: &'v Heap) -> Option<Self> {
+        Duration::from_value(value).map(|x| *x)
+    }
+}
+
+impl<'v> StarlarkValue<'v> for Time {
+    starlark_type!(Time::TYPE);
+
+    fn get_members(&self) -> Option<&'static Globals> {
+        static RES: GlobalsStatic = GlobalsStatic::new();
+        RES.members(time_members)
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str(&format!("time.parse(\"{}\")", self.to_iso()));
+    }
+
+    fn collect_json(&self, collector: &mut String) {
+        collector.push_str(&format!("\"{}\"", self.to_iso()));
+    }
+
+    fn get_hash(&self) -> anyhow::Result<u64> {
+        Ok(hash(&self.0))
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        Ok(Time::from_value(other).map_or(false, |x| x.0 == self.0))
+    }
+
+    fn compare(&self, other: Value<'v>) -> anyhow::Result<Ordering> {
+        match Time::from_value(other) {
+            Some(x) => Ok(self.0.cmp(&x.0)),
+            None => ValueError::unsupported_with(self, "compare", other),
+        }
+    }
+
+    fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        match Duration::from_value(other) {
+            Some(x) => Ok(heap.alloc(Time(out_of_range(self.0.checked_add_signed(x.0))?))),
+            None => ValueError::unsupported_with(self, "+", other),
+        }
+    }
+
+    fn sub(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        if let Some(x) = Time::from_value(other) {
+            Ok(heap.alloc(Duration(self.0.signed_duration_since(x.0))))
+        } else if let Some(x) = Duration::from_value(other) {
+            Ok(heap.alloc(Time(out_of_range(self.0.checked_sub_signed(x.0))?)))
+        } else {
+            ValueError::unsupported_with(self, "-", other)
+        }
+    }
+}
+
+impl<'v> StarlarkValue<'v> for Duration {
+    starlark_type!(Duration::TYPE);
+
+    fn get_members(&self) -> Option<&'static Globals> {
+        static RES: GlobalsStatic = GlobalsStatic::new();
+        RES.members(duration_members)
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        let seconds = self.0.num_seconds();
+        let milliseconds = (self.0 - chrono::Duration::seconds(seconds)).num_milliseconds();
+        if milliseconds == 0 {
+            collector.push_str(&format!("time.duration(seconds = {})", seconds));
+        } else {
+            collector.push_str(&format!(
+                "time.duration(seconds = {}, milliseconds = {})",
+                seconds, milliseconds
+            ));
+        }
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.0.is_zero()
+    }
+
+    fn get_hash(&self) -> anyhow::Result<u64> {
+        Ok(hash(&self.0))
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        Ok(Duration::from_value(other).map_or(false, |x| x.0 == self.0))
+    }
+
+    fn compare(&self, other: Value<'v>) -> anyhow::Result<Ordering> {
+        match Duration::from_value(other) {
+            Some(x) => Ok(self.0.cmp(&x.0)),
+            None => ValueError::unsupported_with(self, "compare", other),
+        }
+    }
+
+    fn plus(&self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(heap.alloc(*self))
+    }
+
+    fn minus(&self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(heap.alloc(Duration(-self.0)))
+    }
+
+    fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        if let Some(x) = Duration::from_value(other) {
+            Ok(heap.alloc(Duration(out_of_range(self.0.checked_add(&x.0))?)))
+        } else if let Some(x) = Time::from_value(other) {
+            Ok(heap.alloc(Time(out_of_range(x.0.checked_add_signed(self.0))?)))
+        } else {
+            ValueError::unsupported_with(self, "+", other)
+        }
+    }
+
+    fn sub(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        match Duration::from_value(other) {
+            Some(x) => Ok(heap.alloc(Duration(out_of_range(self.0.checked_sub(&x.0))?))),
+            None => ValueError::unsupported_with(self, "-", other),
+        }
+    }
+
+    fn mul(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        match other.unpack_int() {
+            Some(x) => {
+                let res = self
+                    .0
+                    .num_nanoseconds()
+                    .and_then(|n| n.checked_mul(x as i64));
+                Ok(heap.alloc(Duration(chrono::Duration::nanoseconds(out_of_range(res)?))))
+            }
+            None => ValueError::unsupported_with(self, "*", other),
+        }
+    }
+
+    fn rmul(&self, lhs: Value<'v>, heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        Some(self.mul(lhs, heap))
+    }
+}
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    builder.struct_("time", time_functions);
+}
+
+#[starlark_module]
+fn time_functions(builder: &mut GlobalsBuilder) {
+    /// The current time, as set by the embedder, which is the same throughout
+    /// an evaluation. Fails if the embedder hasn't provided one.
+    fn now() -> Time {
+        match ctx.current_time() {
+            Some(x) => Ok(Time(DateTime::<Utc>::from(x).with_timezone(&utc_offset()))),
+            None => Err(value_error!(
+                "time.now: the current time is not available in this evaluation"
+            )),
+        }
+    }
+
+    /// Parse an [RFC 3339](https://tools.ietf.org/html/rfc3339) timestamp, e.g.
+    /// `2021-03-04T05:06:07Z` or `2021-03-04T05:06:07.5+01:00`, or a date, e.g. `2021-03-04`,
+    /// which means midnight UTC.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// time.parse("2021-03-04T06:06:07+01:00") == time.parse("2021-03-04T05:06:07Z")
+    /// time.parse("2021-03-04").isoformat() == "2021-03-04T00:00:00Z"
+    /// time.parse("2021-03-04") < time.parse("2021-03-05")
+    /// # "#);
+    /// ```
+    fn parse(ref s: &str) -> Time {
+        Time::parse(s)
+    }
+
+    /// The time `seconds` after the Unix epoch, `1970-01-01T00:00:00Z`.
+    fn from_unix(seconds: i32) -> Time {
+        Ok(Time(utc_offset().timestamp(seconds as i64, 0)))
+    }
+
+    /// A duration, the sum of the arguments, any of which may be negative.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// time.duration(hours = 1, minutes = 30) == time.duration(minutes = 90)
+    /// time.parse("2021-03-04") + time.duration(days = 1) == time.parse("2021-03-05")
+    /// time.parse("2021-03-05") - time.parse("2021-03-04") == time.duration(hours = 24)
+    /// # "#);
+    /// ```
+    fn duration(
+        days @ 0: i32,
+        hours @ 0: i32,
+        minutes @ 0: i32,
+        seconds @ 0: i32,
+        milliseconds @ 0: i32,
+    ) -> Duration {
+        Ok(Duration(
+            chrono::Duration::days(days as i64)
+                + chrono::Duration::hours(hours as i64)
+                + chrono::Duration::minutes(minutes as i64)
+                + chrono::Duration::seconds(seconds as i64)
+                + chrono::Duration::milliseconds(milliseconds as i64),
+        ))
+    }
+}
+
+#[starlark_module]
+fn time_members(builder: &mut GlobalsBuilder) {
+    #[attribute]
+    fn year(this: Time) -> i32 {
+        Ok(this.0.year())
+    }
+
+    #[attribute]
+    fn month(this: Time) -> i32 {
+        Ok(this.0.month() as i32)
+    }
+
+    #[attribute]
+    fn day(this: Time) -> i32 {
+        Ok(this.0.day() as i32)
+    }
+
+    #[attribute]
+    fn hour(this: Time) -> i32 {
+        Ok(this.0.hour() as i32)
+    }
+
+    #[attribute]
+    fn minute(this: Time) -> i32 {
+        Ok(this.0.minute() as i32)
+    }
+
+    #[attribute]
+    fn second(this: Time) -> i32 {
+        Ok(this.0.second() as i32)
+    }
+
+    /// The day of the week, where Monday is `0` and Sunday is `6`.
+    #[attribute]
+    fn weekday(this: Time) -> i32 {
+        Ok(this.0.weekday().num_days_from_monday() as i32)
+    }
+
+    /// Whole seconds since the Unix epoch, failing if that doesn't fit in an int.
+    #[attribute]
+    fn unix(this: Time) -> i32 {
+        to_int(this.0.timestamp(), "unix time")
+    }
+
+    /// The same instant in UTC.
+    fn utc(this: Time) -> Time {
+        Ok(Time(this.0.with_timezone(&utc_offset())))
+    }
+
+    /// The RFC 3339 timestamp, which `time.parse` accepts.
+    fn isoformat(this: Time) -> String {
+        Ok(this.to_iso())
+    }
+
+    /// Format using [`strftime`](https://docs.rs/chrono/0.4/chrono/format/strftime/index.html)
+    /// specifiers, e.g. `t.format("%Y%m%d")`.
+    fn format(this: Time, ref fmt: &str) -> String {
+        if StrftimeItems::new(fmt).any(|x| x == Item::Error) {
+            return Err(value_error!("time.format: invalid format `{}`", fmt));
+        }
+        Ok(this.0.format(fmt).to_string())
+    }
+}
+
+#[starlark_module]
+fn duration_members(builder: &mut GlobalsBuilder) {
+    /// The whole number of seconds, rounded towards zero.
+    #[attribute]
+    fn seconds(this: Duration) -> i32 {
+        to_int(this.0.num_seconds(), "duration of seconds")
+    }
+
+    /// The whole number of milliseconds, rounded towards zero.
+    #[attribute]
+    fn milliseconds(this: Duration) -> i32 {
+        to_int(this.0.num_milliseconds(), "duration of milliseconds")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert,
+        environment::{Globals, Module},
+        eval::Evaluator,
+        syntax::{AstModule, Dialect},
+    };
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_time() {
+        assert::all_true(
+            r#"
+t = time.parse("2021-03-04T05:06:07.25+01:00")
+[t.year, t.month, t.day, t.hour, t.minute, t.second, t.weekday] == [2021, 3, 4, 5, 6, 7, 3]
+t.isoformat() == "2021-03-04T05:06:07.250+01:00"
+t.utc().isoformat() == "2021-03-04T04:06:07.250Z"
+t == t.utc()
+repr(t) == 'time.parse("2021-03-04T05:06:07.250+01:00")'
+t.format("%Y/%m/%d %H:%M") == "2021/03/04 05:06"
+time.from_unix(0).isoformat() == "1970-01-01T00:00:00Z"
+time.from_unix(86400).unix == 86400
+time.parse("2021-01-01") - time.parse("2020-01-01") == time.duration(days = 366)
+time.parse("2021-03-04") - time.duration(seconds = 1) == time.parse("2021-03-03T23:59:59Z")
+{time.parse("2021-03-04T01:00:00+01:00"): 1}[time.parse("2021-03-04")] == 1
+sorted([time.parse("2022-01-01"), time.parse("2021-01-01")])[0].year == 2021
+"#,
+        );
+        assert::all_true(
+            r#"
+d = time.duration(minutes = 1, milliseconds = 500)
+d.seconds == 60
+d.milliseconds == 60500
+repr(d) == "time.duration(seconds = 60, milliseconds = 500)"
+repr(-time.duration(hours = 1)) == "time.duration(seconds = -3600)"
+2 * d == d + d
+d * 2 - d == d
+not time.duration()
+time.duration(days = 1) > time.duration(hours = 23)
+time.duration(hours = 1) + time.parse("2021-03-04") == time.parse("2021-03-04T01:00:00Z")
+"#,
+        );
+        assert::fail("time.parse('yesterday')", "expected a timestamp");
+        assert::fail("time.parse('2021-02-30')", "expected a timestamp");
+        assert::fail("time.parse('2021-01-01').format('%Q')", "invalid format");
+        assert::fail("time.parse('3000-01-01').unix", "does not fit in an int");
+        assert::fail("time.parse('2021-01-01') + 1", "not supported");
+        assert::fail("time.now()", "not available");
+    }
+
+    #[test]
+    fn test_time_now() {
+        let globals = Globals::extended();
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module, &globals);
+        eval.set_current_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000));
+        let res = eval
+            .eval_module(
+                AstModule::parse(
+                    "now.star",
+                    "[time.now().isoformat(), time.now() == time.now()]".to_owned(),
+                    &Dialect::Extended,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(res.to_repr(), "[\"2020-09-13T12:26:40Z\", True]");
+    }
+}
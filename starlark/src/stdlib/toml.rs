@@ -0,0 +1,188 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Converting between [TOML](https://toml.io) and Starlark values.
+
+use crate as starlark;
+use crate::{
+    collections::SmallMap,
+    environment::GlobalsBuilder,
+    values::{dict::Dict, list::List, structs::Struct, tuple::Tuple, Heap, Value},
+};
+use dep_toml::value::{Map, Value as Toml};
+use std::convert::TryFrom;
+
+// Deeper than any sensible document, so most likely a list which contains itself
+const MAX_DEPTH: usize = 1000;
+
+fn to_toml_array<'v>(xs: impl Iterator<Item = Value<'v>>, depth: usize) -> anyhow::Result<Toml> {
+    Ok(Toml::Array(
+        xs.map(|x| to_toml(x, depth + 1))
+            .collect::<anyhow::Result<_>>()?,
+    ))
+}
+
+fn to_toml(x: Value, depth: usize) -> anyhow::Result<Toml> {
+    if depth > MAX_DEPTH {
+        return Err(value_error!("toml.encode: value is too deeply nested"));
+    }
+    if let Some(x) = x.unpack_str() {
+        Ok(Toml::String(x.to_owned()))
+    } else if let Some(x) = x.unpack_bool() {
+        Ok(Toml::Boolean(x))
+    } else if let Some(x) = x.unpack_int() {
+        Ok(Toml::Integer(x as i64))
+    } else if let Some(x) = List::from_value(x) {
+        to_toml_array(x.iter(), depth)
+    } else if let Some(x) = Tuple::from_value(x) {
+        to_toml_array(x.content().iter().copied(), depth)
+    } else if let Some(x) = Dict::from_value(x) {
+        let mut res = Map::new();
+        for (k, v) in x.iter() {
+            let k = match k.unpack_str() {
+                Some(k) => k,
+                None => {
+                    return Err(value_error!(
+                        "toml.encode: keys must be strings, got `{}`",
+                        k.get_type()
+                    ));
+                }
+            };
+            res.insert(k.to_owned(), to_toml(v, depth + 1)?);
+        }
+        Ok(Toml::Table(res))
+    } else if let Some(x) = Struct::from_value(x) {
+        let mut res = Map::new();
+        for (k, v) in x.fields.iter() {
+            res.insert(k.clone(), to_toml(*v, depth + 1)?);
+        }
+        Ok(Toml::Table(res))
+    } else {
+        Err(value_error!(
+            "toml.encode: can't encode a value of type `{}`",
+            x.get_type()
+        ))
+    }
+}
+
+fn from_toml<'v>(x: Toml, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+    Ok(match x {
+        Toml::String(x) => heap.alloc(x),
+        Toml::Integer(x) => match i32::try_from(x) {
+            Ok(x) => Value::new_int(x),
+            Err(_) => return Err(value_error!("toml.decode: {} does not fit in an int", x)),
+        },
+        Toml::Float(x) => {
+            return Err(value_error!(
+                "toml.decode: floats are not supported, got {}",
+                x
+            ))
+        }
+        Toml::Boolean(x) => Value::new_bool(x),
+        Toml::Datetime(x) => heap.alloc(x.to_string()),
+        Toml::Array(xs) => heap.alloc(
+            xs.into_iter()
+                .map(|x| from_toml(x, heap))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        Toml::Table(xs) => {
+            let mut content = SmallMap::with_capacity(xs.len());
+            for (k, v) in xs {
+                content.insert_hashed(heap.alloc(k).get_hashed()?, from_toml(v, heap)?);
+            }
+            heap.alloc(Dict::new(content))
+        }
+    })
+}
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    builder.struct_("toml", toml_members);
+}
+
+#[starlark_module]
+fn toml_members(builder: &mut GlobalsBuilder) {
+    /// Parse a TOML document into a dictionary. Tables become dictionaries, arrays become
+    /// lists, and dates and times become strings. Fails on floats, as there is no float type,
+    /// and on integers too large for an int.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// toml.decode('name = "x"\n[deps]\nfoo = 1') == {"name": "x", "deps": {"foo": 1}}
+    /// # "#);
+    /// ```
+    fn decode(ref x: &str) -> Value<'v> {
+        match dep_toml::from_str(x) {
+            Ok(x) => from_toml(x, heap),
+            Err(e) => Err(value_error!("toml.decode: {}", e)),
+        }
+    }
+
+    /// Write a dictionary or struct as a TOML document, keeping the order of the keys,
+    /// other than putting nested tables last, as TOML requires. Keys must be strings, and
+    /// values strings, ints, bools, lists, tuples, dictionaries or structs.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// toml.encode({"deps": {"foo": 1}, "name": "x"}) == 'name = "x"\n\n[deps]\nfoo = 1\n'
+    /// # "#);
+    /// ```
+    fn encode(ref x: Value) -> String {
+        let x = to_toml(x, 0)?;
+        if !x.is_table() {
+            return Err(value_error!(
+                "toml.encode: expected a dict or struct, got `{}`",
+                x.type_str()
+            ));
+        }
+        match dep_toml::to_string(&x) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(value_error!("toml.encode: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_toml() {
+        assert::all_true(
+            r#"
+toml.decode("") == {}
+toml.decode('a = [1, "b", true]\nwhen = 2021-03-04') == {"a": [1, "b", True], "when": "2021-03-04"}
+toml.decode('[[x]]\ny = 1\n[[x]]\ny = 2') == {"x": [{"y": 1}, {"y": 2}]}
+toml.encode({"b": 1, "a": [True, (2, "c")]}) == 'b = 1\na = [true, [2, "c"]]\n'
+toml.encode(struct(x = struct(y = "z"))) == '[x]\ny = "z"\n'
+x = {"a": {"b": [1, 2]}, "c": "d"}; toml.decode(toml.encode(x)) == x
+"#,
+        );
+        assert::fail("toml.decode('x = 1.5')", "floats are not supported");
+        assert::fail("toml.decode('x = 3000000000')", "does not fit in an int");
+        assert::fail("toml.decode('x = ')", "toml.decode");
+        assert::fail("toml.encode([1])", "expected a dict or struct");
+        assert::fail("toml.encode({1: 2})", "keys must be strings");
+        assert::fail(
+            "toml.encode({'x': None})",
+            "can't encode a value of type `NoneType`",
+        );
+        assert::fail(
+            "x = []\nx.append(x)\ntoml.encode({'x': x})",
+            "too deeply nested",
+        );
+    }
+}
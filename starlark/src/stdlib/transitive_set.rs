@@ -0,0 +1,157 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Implementation of `depset` function.
+use crate as starlark;
+use crate::{
+    environment::GlobalsBuilder,
+    values::{
+        transitive_set::{TransitiveSet, TransitiveSetOrder},
+        Value,
+    },
+};
+
+#[starlark_module]
+pub fn global(builder: &mut GlobalsBuilder) {
+    /// Creates a depset, a set of the `direct` elements, which must be hashable, and the
+    /// elements of the depsets in `transitive`, without copying them. Duplicates are removed
+    /// when flattened with `to_list()`, which returns the elements in the given `order`:
+    ///
+    /// * `"default"` or `"postorder"`, the elements of `transitive`, left to right, then `direct`.
+    /// * `"preorder"`, the elements of `direct`, then those of `transitive`, left to right.
+    /// * `"topological"`, every depset before the depsets in its `transitive`.
+    ///
+    /// The depsets in `transitive` must have the same order, or `"default"`.
+    ///
+    /// ```
+    /// # starlark::assert::all_true(r#"
+    /// a = depset(["a"]); b = depset(["b"], transitive = [a]); depset(["c"], transitive = [b, a]).to_list() == ["a", "b", "c"]
+    /// a = depset(["a"]); b = depset(["b"], transitive = [a]); depset(["c"], transitive = [b, a], order = "preorder").to_list() == ["c", "b", "a"]
+    /// # "#);
+    /// ```
+    #[starlark_type(TransitiveSet::TYPE)]
+    fn depset(
+        direct: Option<Value>,
+        order @ "default": &str,
+        #[kw_only] transitive: Option<Value>,
+    ) -> Value<'v> {
+        let order = TransitiveSetOrder::from_name(order).ok_or_else(|| {
+            value_error!(
+                "depset: unknown order `{}`, expected `default`, `postorder`, `preorder` or `topological`",
+                order
+            )
+        })?;
+        let direct = match direct {
+            Some(x) if !x.is_none() => x.iterate_collect(heap)?,
+            _ => Vec::new(),
+        };
+        let transitive = match transitive {
+            Some(x) if !x.is_none() => x.iterate_collect(heap)?,
+            _ => Vec::new(),
+        };
+        TransitiveSet::new(order, direct, transitive, heap)
+    }
+}
+
+#[starlark_module]
+pub(crate) fn transitive_set_members(builder: &mut GlobalsBuilder) {
+    /// All the elements of the depset, without duplicates, in the order of the depset.
+    fn to_list(this: Value) -> Vec<Value<'v>> {
+        Ok(TransitiveSet::from_value(this).unwrap().to_list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert::{self, Assert};
+
+    #[test]
+    fn test_depset() {
+        assert::pass(
+            r#"
+a = depset(["a", "x"])
+b = depset(["b"], transitive = [a])
+c = depset(["c", "x"], transitive = [a])
+assert_eq(depset(["d"], transitive = [b, c]).to_list(), ["a", "x", "b", "c", "d"])
+assert_eq(depset(transitive = [b, c]).to_list(), ["a", "x", "b", "c"])
+assert_eq(depset([1, 1, 2]).to_list(), [1, 2])
+assert_eq(depset().to_list(), [])
+assert_eq(depset(None, transitive = None).to_list(), [])
+assert_eq(type(a), "depset")
+assert_eq(depset.type, "depset")
+assert_eq(bool(depset()), False)
+assert_eq(bool(depset(transitive = [depset()])), False)
+assert_eq(bool(a), True)
+assert_eq(repr(b), 'depset(["a", "x", "b"])')
+
+# A depset made only from another depset is that depset
+assert_eq(depset(transitive = [depset(), b]) == b, True)
+assert_eq(depset(transitive = [b], order = "preorder") == b, False)
+
+p = lambda xs, ts = []: depset(xs, "preorder", transitive = ts)
+pa = p(["a", "x"])
+assert_eq(p(["d"], [p(["b"], [pa]), p(["c", "x"], [pa])]).to_list(), ["d", "b", "a", "x", "c"])
+assert_eq(repr(pa), 'depset(["a", "x"], order = "preorder")')
+
+t = lambda xs, ts = []: depset(xs, "topological", transitive = ts)
+ta = t(["a1", "a2"])
+assert_eq(t(["d"], [t(["b"], [ta]), t(["c"], [ta])]).to_list(), ["d", "b", "c", "a1", "a2"])
+assert_eq(t(["d"], [depset(["b"])]).to_list(), ["d", "b"])
+"#,
+        );
+        assert::fail("depset(order = 'random')", "unknown order `random`");
+        assert::fail("depset([[1]])", "not hashable");
+        assert::fail(
+            "depset(transitive = [[1]])",
+            "must only contain depsets, got `list`",
+        );
+        assert::fail(
+            "depset(order = 'preorder', transitive = [depset(order = 'postorder')])",
+            "incompatible",
+        );
+    }
+
+    #[test]
+    fn test_depset_deep() {
+        // Flattening mustn't recurse once per level of nesting
+        assert::pass(
+            r#"
+def test():
+    x = depset()
+    for i in range(100000):
+        x = depset([i], transitive = [x])
+    assert_eq(len(x.to_list()), 100000)
+    assert_eq(x.to_list()[:3], [0, 1, 2])
+    assert_eq(repr(x).startswith("depset([0, 1, 2, "), True)
+test()
+"#,
+        );
+    }
+
+    #[test]
+    fn test_depset_frozen() {
+        let mut a = Assert::new();
+        a.module("a.star", "a = depset(['a'], transitive = [depset(['b'])])");
+        a.pass(
+            r#"
+load('a.star', 'a')
+assert_eq(a.to_list(), ["b", "a"])
+assert_eq(depset(["c"], transitive = [a]).to_list(), ["b", "a", "c"])
+"#,
+        );
+    }
+}
@@ -15,7 +15,12 @@
  * limitations under the License.
  */
 
-use crate::values::none::NoneOr;
+use crate::{
+    eval::Evaluator,
+    values::{none::NoneOr, Value},
+};
+use gazebo::prelude::*;
+use std::cmp::Ordering;
 
 fn bound(val: i32, limit: i32) -> usize {
     if val < 0 {
@@ -39,3 +44,46 @@ pub(crate) fn convert_index(len: i32, start: i32) -> usize {
     let start = if start < 0 { start + len } else { start };
     bound(start, len)
 }
+
+/// Stable sort of `values`, comparing the result of calling `key` on each element
+/// (or the elements themselves), shared by `sorted` and `list.sort`.
+pub(crate) fn sort_values<'v>(
+    values: Vec<Value<'v>>,
+    key: Option<Value<'v>>,
+    reverse: bool,
+    ctx: &mut Evaluator<'v, '_>,
+) -> anyhow::Result<Vec<Value<'v>>> {
+    let mut it = match key {
+        None => values.into_map(|x| (x, x)),
+        Some(key) => {
+            let mut v = Vec::with_capacity(values.len());
+            for el in values {
+                let mut inv = key.new_invoker(ctx.heap())?;
+                inv.push_pos(el);
+                v.push((el, inv.invoke(key, None, ctx)?));
+            }
+            v
+        }
+    };
+
+    let mut compare_ok = Ok(());
+
+    it.sort_by(|x: &(Value, Value), y: &(Value, Value)| {
+        let ord_or_err = if reverse {
+            x.1.compare(y.1).map(Ordering::reverse)
+        } else {
+            x.1.compare(y.1)
+        };
+        match ord_or_err {
+            Ok(r) => r,
+            Err(e) => {
+                compare_ok = Err(e);
+                Ordering::Equal // does not matter
+            }
+        }
+    });
+
+    compare_ok?;
+
+    Ok(it.into_map(|x| x.0))
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Converting between [YAML](https://yaml.org) and Starlark values.
+
+use crate as starlark;
+use crate::{
+    collections::SmallMap,
+    environment::GlobalsBuilder,
+    values::{dict::Dict, list::List, structs::Struct, tuple::Tuple, Heap, Value},
+};
+use serde_yaml::{Mapping, Value as Yaml};
+use std::convert::TryFrom;
+
+// Deeper than any sensible document, so most likely a list which contains itself
+const MAX_DEPTH: usize = 1000;
+
+fn to_yaml_sequence<'v>(xs: impl Iterator<Item = Value<'v>>, depth: usize) -> anyhow::Result<Yaml> {
+    Ok(Yaml::Sequence(
+        xs.map(|x| to_yaml(x, depth + 1))
+            .collect::<anyhow::Result<_>>()?,
+    ))
+}
+
+fn to_yaml(x: Value, depth: usize) -> anyhow::Result<Yaml> {
+    if depth > MAX_DEPTH {
+        return Err(value_error!("yaml.encode: value is too deeply nested"));
+    }
+    if x.is_none() {
+        Ok(Yaml::Null)
+    } else if let Some(x) = x.unpack_str() {
+        Ok(Yaml::String(x.to_owned()))
+    } else if let Some(x) = x.unpack_bool() {
+        Ok(Yaml::Bool(x))
+    } else if let Some(x) = x.unpack_int() {
+        Ok(Yaml::Number(x.into()))
+    } else if let Some(x) = List::from_value(x) {
+        to_yaml_sequence(x.iter(), depth)
+    } else if let Some(x) = Tuple::from_value(x) {
+        to_yaml_sequence(x.content().iter().copied(), depth)
+    } else if let Some(x) = Dict::from_value(x) {
+        let mut res = Mapping::new();
+        for (k, v) in x.iter() {
+            res.insert(to_yaml(k, depth + 1)?, to_yaml(v, depth + 1)?);
+        }
+        Ok(Yaml::Mapping(res))
+    } else if let Some(x) = Struct::from_value(x) {
+        let mut res = Mapping::new();
+        for (k, v) in x.fields.iter() {
+            res.insert(Yaml::String(k.clone()), to_yaml(*v, depth + 1)?);
+        }
+        Ok(Yaml::Mapping(res))
+    } else {
+        Err(value_error!(
+            "yaml.encode: can't encode a value of type `{}`",
+            x.get_type()
+        ))
+    }
+}
+
+fn from_yaml<'v>(x: Yaml, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+    Ok(match x {
+        Yaml::Null => Value::new_none(),
+        Yaml::Bool(x) => Value::new_bool(x),
+        Yaml::Number(x) => match x.as_i64().and_then(|x| i32::try_from(x).ok()) {
+            Some(x) => Value::new_int(x),
+            None => {
+                return Err(value_error!(
+                    "yaml.decode: only ints are supported, got {}",
+                    x
+                ));
+            }
+        },
+        Yaml::String(x) => heap.alloc(x),
+        Yaml::Sequence(xs) => heap.alloc(
+            xs.into_iter()
+                .map(|x| from_yaml(x, heap))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        Yaml::Mapping(xs) => {
+            let mut content = SmallMap::with_capacity(xs.len());
+            for (k, v) in xs {
+                content.insert_hashed(from_yaml(k, heap)?.get_hashed()?, from_yaml(v, heap)?);
+            }
+            heap.alloc(Dict::new(content))
+        }
+    })
+}
+
+pub(crate) fn global(builder: &mut GlobalsBuilder) {
+    builder.struct_("yaml", yaml_members);
+}
+
+#[starlark_module]
+fn yaml_members(builder: &mut GlobalsBuilder) {
+    /// Parse a YAML document. Mappings become dictionaries, sequences become lists,
+    /// and `null` becomes `None`. Fails on floats, as there is no float type, and on
+    /// integers too large for an int.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// yaml.decode("name: x\ndeps:\n- foo\n- bar") == {"name": "x", "deps": ["foo", "bar"]}
+    /// # "#);
+    /// ```
+    fn decode(ref x: &str) -> Value<'v> {
+        match serde_yaml::from_str(x) {
+            Ok(x) => from_yaml(x, heap),
+            Err(e) => Err(value_error!("yaml.decode: {}", e)),
+        }
+    }
+
+    /// Write a value as a YAML document, starting with `---`. Values must be `None`,
+    /// strings, ints, bools, lists, tuples, dictionaries or structs.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// yaml.encode({"name": "x", "deps": ["foo"]}) == "---\nname: x\ndeps:\n  - foo\n"
+    /// # "#);
+    /// ```
+    fn encode(ref x: Value) -> String {
+        match serde_yaml::to_string(&to_yaml(x, 0)?) {
+            Ok(x) => Ok(x),
+            Err(e) => Err(value_error!("yaml.encode: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_yaml() {
+        assert::all_true(
+            r#"
+yaml.decode("[1, true, null, x]") == [1, True, None, "x"]
+yaml.decode("a: {b: [1, 2]}") == {"a": {"b": [1, 2]}}
+yaml.decode("'1': one\n1: uno") == {"1": "one", 1: "uno"}
+yaml.encode(None) == "---\n~\n"
+yaml.decode(yaml.encode([1, (True, "x")])) == [1, [True, "x"]]
+yaml.encode(struct(a = 1)) == "---\na: 1\n"
+x = {"a": [{"b": None}], 1: "c"}; yaml.decode(yaml.encode(x)) == x
+"#,
+        );
+        assert::fail("yaml.decode('x: 1.5')", "only ints are supported, got 1.5");
+        assert::fail("yaml.decode('{[2]: b}')", "not hashable");
+        assert::fail("yaml.decode('x: [')", "yaml.decode");
+        assert::fail(
+            "yaml.encode(lambda: 1)",
+            "can't encode a value of type `function`",
+        );
+        assert::fail("x = []\nx.append(x)\nyaml.encode(x)", "too deeply nested");
+    }
+}
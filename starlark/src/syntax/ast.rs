@@ -16,8 +16,14 @@
  */
 
 //! AST for parsed starlark files.
+//!
+//! These types are exposed so tools can analyse code with a [`Visitor`](crate::syntax::Visitor),
+//! but they follow the needs of the evaluator, so change more regularly than the rest of the API.
 
-use crate::codemap::{CodeMap, Span, Spanned};
+use crate::{
+    codemap::{CodeMap, Span, Spanned},
+    syntax::{Comments, Dialect},
+};
 use derivative::Derivative;
 use gazebo::prelude::*;
 use static_assertions::assert_eq_size;
@@ -47,13 +53,18 @@ assert_eq_size!(AstExpr, [usize; 7]);
 /// and evaluated with [`eval_module`](crate::eval::Evaluator::eval_module).
 ///
 /// The internal details (statements/expressions) are deliberately omitted, as they change
-/// more regularly. A few methods to obtain information about the AST are provided.
+/// more regularly. A few methods to obtain information about the AST are provided, along with
+/// [`visit`](AstModule::visit) for tools which need to traverse the [`ast`](crate::syntax::ast) nodes.
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct AstModule {
     #[derivative(Debug = "ignore")]
     pub(crate) codemap: Arc<CodeMap>,
     pub(crate) statement: AstStmt,
+    pub(crate) comments: Comments,
+    // The Dialect it was parsed with, as some options (e.g. `enable_recursion`) are only
+    // checked when the code is compiled or run
+    pub(crate) dialect: Dialect,
 }
 
 // A trait rather than a function to allow .ast() chaining in the parser.
@@ -187,6 +198,7 @@ pub enum Stmt {
     If(AstExpr, Box<AstStmt>),
     IfElse(AstExpr, Box<(AstStmt, AstStmt)>),
     For(Box<(AstExpr, AstExpr, AstStmt)>),
+    While(AstExpr, Box<AstStmt>),
     Def(
         AstString,
         Vec<AstParameter>,
@@ -208,6 +220,21 @@ impl Argument {
     }
 }
 
+impl Stmt {
+    /// The docstring of a module or function body, being a string literal
+    /// written as the first statement.
+    pub fn docstring(&self) -> Option<&str> {
+        match self {
+            Stmt::Statements(stmts) => stmts.first()?.node.docstring(),
+            Stmt::Expression(Spanned {
+                node: Expr::Literal(AstLiteral::StringLiteral(x)),
+                ..
+            }) => Some(&x.node),
+            _ => None,
+        }
+    }
+}
+
 impl Display for BinOp {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
@@ -455,6 +482,10 @@ impl Stmt {
                 writeln!(f, "{}for {} in {}:", tab, bind.node, coll.node)?;
                 suite.node.fmt_with_tab(f, tab + "  ")
             }
+            Stmt::While(cond, box suite) => {
+                writeln!(f, "{}while {}:", tab, cond.node)?;
+                suite.node.fmt_with_tab(f, tab + "  ")
+            }
             Stmt::Def(name, params, return_type, suite) => {
                 write!(f, "{}def {}(", tab, name.node)?;
                 comma_separated_fmt(f, params, |x, f| x.node.fmt(f), false)?;
@@ -0,0 +1,192 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Comments retained by [`AstModule::parse_with_comments`](crate::syntax::AstModule::parse_with_comments).
+
+use crate::{
+    codemap::{CodeMap, Span},
+    syntax::ast::{AstStmt, Stmt},
+};
+
+/// Which statement a [`Comment`] is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentAttachment {
+    /// The comment comes before the statement with this [`Span`],
+    /// e.g. on the lines above it.
+    Leading(Span),
+    /// The comment comes after the statement with this [`Span`],
+    /// on the same line as the end of the statement.
+    Trailing(Span),
+    /// The comment isn't followed by any statement, e.g. at the end of the file.
+    Dangling,
+}
+
+/// A single comment in the source, from the `#` to the end of the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// The location of the comment, including the leading `#`.
+    pub span: Span,
+    /// The text of the comment, including the leading `#`.
+    pub text: String,
+    /// The statement the comment is attached to.
+    pub attachment: CommentAttachment,
+}
+
+/// All the comments in a module, in source order.
+#[derive(Debug, Clone, Default)]
+pub struct Comments {
+    comments: Vec<Comment>,
+}
+
+impl Comments {
+    /// Attach the comments found by the lexer to the statements of a module.
+    pub(crate) fn new(codemap: &CodeMap, statement: &AstStmt, raw: Vec<(usize, usize)>) -> Self {
+        // All statements, except blocks, in pre-order, which is also the order they start in
+        fn collect(x: &AstStmt, res: &mut Vec<Span>) {
+            match &x.node {
+                Stmt::Statements(_) => {}
+                _ => res.push(x.span),
+            }
+            x.visit_stmt(|x| collect(x, res));
+        }
+
+        let mut stmts = Vec::new();
+        collect(statement, &mut stmts);
+
+        let file = codemap.get_file();
+        let comments = raw
+            .into_iter()
+            .map(|(begin, end)| {
+                let span = file.span.subspan(begin as u64, end as u64);
+                let line = file.find_line(span.low());
+                // If several statements end on this line, prefer the innermost
+                let trailing = stmts
+                    .iter()
+                    .filter(|s| s.high() <= span.low() && file.find_line(s.high()) == line)
+                    .max_by_key(|s| (s.high(), s.low()));
+                let attachment = match trailing {
+                    Some(s) => CommentAttachment::Trailing(*s),
+                    None => match stmts.iter().find(|s| s.low() >= span.high()) {
+                        Some(s) => CommentAttachment::Leading(*s),
+                        None => CommentAttachment::Dangling,
+                    },
+                };
+                Comment {
+                    span,
+                    text: file.source_slice(span).trim_end_matches('\r').to_owned(),
+                    attachment,
+                }
+            })
+            .collect();
+        Self { comments }
+    }
+
+    /// The number of comments.
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    /// Are there no comments.
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+
+    /// Iterate over all the comments, in source order.
+    pub fn iter(&self) -> impl Iterator<Item = &Comment> {
+        self.comments.iter()
+    }
+
+    /// The comments that come before the statement with the given [`Span`].
+    pub fn leading(&self, stmt: Span) -> impl Iterator<Item = &Comment> {
+        self.comments
+            .iter()
+            .filter(move |c| c.attachment == CommentAttachment::Leading(stmt))
+    }
+
+    /// The comments that come after the statement with the given [`Span`], on the same line.
+    pub fn trailing(&self, stmt: Span) -> impl Iterator<Item = &Comment> {
+        self.comments
+            .iter()
+            .filter(move |c| c.attachment == CommentAttachment::Trailing(stmt))
+    }
+
+    /// The comments that aren't attached to any statement.
+    pub fn dangling(&self) -> impl Iterator<Item = &Comment> {
+        self.comments
+            .iter()
+            .filter(|c| c.attachment == CommentAttachment::Dangling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{AstModule, Dialect};
+
+    fn parse(program: &str) -> AstModule {
+        AstModule::parse_with_comments("test.star", program.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn source(m: &AstModule, span: Span) -> &str {
+        m.codemap.get_file().source_slice(span)
+    }
+
+    #[test]
+    fn test_comments_not_retained() {
+        let m = AstModule::parse(
+            "test.star",
+            "# hello\nx = 1 # world\n".to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+        assert!(m.comments().is_empty());
+    }
+
+    #[test]
+    fn test_comments_attached() {
+        let m = parse(
+            r#"
+# leading x
+# more x
+x = 1  # trailing x
+def f(
+    a, # inside f
+):
+    # leading return
+    return a # trailing return
+# at the end
+"#,
+        );
+        let cs: Vec<_> = m.comments().iter().collect();
+        assert_eq!(cs.len(), 7);
+        assert_eq!(cs[0].text, "# leading x");
+
+        let attached = |c: &Comment| match c.attachment {
+            CommentAttachment::Leading(s) => format!("leading {}", source(&m, s)),
+            CommentAttachment::Trailing(s) => format!("trailing {}", source(&m, s)),
+            CommentAttachment::Dangling => "dangling".to_owned(),
+        };
+        assert_eq!(attached(cs[0]), "leading x = 1");
+        assert_eq!(attached(cs[1]), "leading x = 1");
+        assert_eq!(attached(cs[2]), "trailing x = 1");
+        assert_eq!(attached(cs[3]), "leading return a");
+        assert_eq!(attached(cs[4]), "leading return a");
+        assert_eq!(attached(cs[5]), "trailing return a");
+        assert_eq!(attached(cs[6]), "dangling");
+        assert_eq!(m.comments().dangling().count(), 1);
+    }
+}
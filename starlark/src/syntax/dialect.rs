@@ -25,7 +25,7 @@ use std::sync::Arc;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-enum DialectError {
+pub(crate) enum DialectError {
     #[error("`def` is not allowed in this dialect")]
     Def,
     #[error("`lambda` is not allowed in this dialect")]
@@ -36,6 +36,10 @@ enum DialectError {
     KeywordOnlyArguments,
     #[error("type annotations are not allowed in this dialect")]
     Types,
+    #[error("`while` is not allowed in this dialect")]
+    While,
+    #[error("f-strings are not allowed in this dialect")]
+    FString,
 }
 
 /// Starlark language features to enable, e.g. [`Standard`](Dialect::Standard) to follow the Starlark standard.
@@ -47,7 +51,8 @@ pub struct Dialect {
     /// Are `lambda` expressions permitted.
     /// Enabled in both [`Standard`](Dialect::Standard) and [`Extended`](Dialect::Extended).
     pub enable_lambda: bool,
-    /// Are `load` statements permitted.
+    /// Are `load` statements permitted. If not, any `load` is rejected when parsing,
+    /// so no [`FileLoader`](crate::eval::FileLoader) will ever be consulted.
     /// Enabled in both [`Standard`](Dialect::Standard) and [`Extended`](Dialect::Extended).
     pub enable_load: bool,
     /// Are `*` keyword-only arguments allowed as per [PEP 3102](https://www.python.org/dev/peps/pep-3102/).
@@ -66,6 +71,36 @@ pub struct Dialect {
     /// Are `for`, `if` and other statements allowed at the top level.
     /// Only enabled in [`Extended`](Dialect::Extended).
     pub enable_top_level_stmt: bool,
+    /// Are `while` loops permitted.
+    /// Only enabled in [`Extended`](Dialect::Extended).
+    pub enable_while: bool,
+    /// May a function call itself, directly or indirectly. If not, calling a
+    /// function which is already on the call stack raises an error. If so, a
+    /// function ending in `return f(...)`, where `f` is itself, reuses its call
+    /// rather than adding to the call stack, so isn't limited in depth.
+    /// Enabled in both [`Standard`](Dialect::Standard) and [`Extended`](Dialect::Extended),
+    /// although the Starlark standard forbids recursion, so disable it to follow the standard.
+    pub enable_recursion: bool,
+    /// Are f-strings such as `f"hello {name}"` permitted. Only identifiers may
+    /// be interpolated, and `f"x{y}"` is equivalent to `"x{}".format(y)`.
+    /// Only enabled in [`Extended`](Dialect::Extended).
+    pub enable_f_strings: bool,
+    /// May a `for` loop or comprehension iterate directly over a string, visiting
+    /// each character in turn. If not, iterate over `elems()` or `codepoints()`.
+    /// Only enabled in [`Extended`](Dialect::Extended).
+    pub enable_string_iteration: bool,
+    /// Are strings measured and indexed in UTF-8 bytes, as in the Go implementation,
+    /// rather than in Unicode code points. Affects `len`, indexing, slicing and the
+    /// positions used by string methods such as `find`. Indexing or slicing part way
+    /// through a multi-byte character produces the replacement character U+FFFD.
+    /// Not enabled in either [`Standard`](Dialect::Standard) or [`Extended`](Dialect::Extended).
+    pub enable_byte_strings: bool,
+    /// Do `keys()`, `values()` and `items()` on a dictionary return a view of it, rather than
+    /// a new list, so iterating over them doesn't copy the dictionary. A view behaves like a
+    /// list in most ways, but has a different `type()`, reflects later changes to the dictionary,
+    /// and the dictionary can't be mutated while iterating over it.
+    /// Not enabled in either [`Standard`](Dialect::Standard) or [`Extended`](Dialect::Extended).
+    pub enable_dict_views: bool,
 }
 
 // These are morally enumerations, so give them enumeration-like names
@@ -82,6 +117,12 @@ impl Dialect {
         enable_tabs: true,
         enable_load_reexport: true, // But they plan to change it
         enable_top_level_stmt: false,
+        enable_while: false,
+        enable_recursion: true,
+        enable_f_strings: false,
+        enable_string_iteration: false,
+        enable_byte_strings: false,
+        enable_dict_views: false,
     };
 
     /// A superset of [`Standard`](Dialect::Standard), including extra features (types, top-level statements etc).
@@ -94,6 +135,12 @@ impl Dialect {
         enable_tabs: true,
         enable_load_reexport: true,
         enable_top_level_stmt: true,
+        enable_while: true,
+        enable_recursion: true,
+        enable_f_strings: true,
+        enable_string_iteration: true,
+        enable_byte_strings: false,
+        enable_dict_views: false,
     };
 }
 
@@ -163,6 +210,30 @@ impl Dialect {
         }
     }
 
+    pub(crate) fn check_while<T>(
+        &self,
+        codemap: &Arc<CodeMap>,
+        x: Spanned<T>,
+    ) -> anyhow::Result<Spanned<T>> {
+        if self.enable_while {
+            Ok(x)
+        } else {
+            err(codemap, x.span, DialectError::While)
+        }
+    }
+
+    pub(crate) fn check_f_string<T>(
+        &self,
+        codemap: &Arc<CodeMap>,
+        x: Spanned<T>,
+    ) -> anyhow::Result<Spanned<T>> {
+        if self.enable_f_strings {
+            Ok(x)
+        } else {
+            err(codemap, x.span, DialectError::FString)
+        }
+    }
+
     pub(crate) fn load_visibility(&self) -> Visibility {
         if self.enable_load_reexport {
             Visibility::Public
@@ -15,7 +15,12 @@
  * limitations under the License.
  */
 
-use crate::{assert, assert::Assert, syntax::ast::Stmt};
+use crate::{
+    assert,
+    assert::Assert,
+    errors::Diagnostic,
+    syntax::{ast::Stmt, AstModule, Dialect},
+};
 use gazebo::prelude::*;
 
 #[test]
@@ -92,6 +97,19 @@ fn test_top_level_def() {
     assert_eq!(assert::parse("def t():\n\n  pass"), "def t():\n  pass\n");
 }
 
+#[test]
+fn test_load_disabled() {
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_load = false);
+    a.parse_fail("x = 1\n!load('a', 'b')!\n");
+    // Rejected even though the module is available to be loaded
+    a.fail(
+        "load('assert.star', 'eq')\neq(1, 1)",
+        "`load` is not allowed in this dialect",
+    );
+    assert_eq!(a.parse("x = 1"), "x = 1\n");
+}
+
 #[test]
 fn test_top_level_statements() {
     let mut a = Assert::new();
@@ -111,6 +129,45 @@ fn test_top_level_statements() {
     assert_eq!(assert::parse("pass"), "pass\n");
 }
 
+#[test]
+fn test_while() {
+    assert_eq!(
+        assert::parse("while x < 10:\n  x += 1\n"),
+        "while (x < 10):\n  x += 1\n"
+    );
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_while = false);
+    a.parse_fail("def f():\n  !while True:\n    pass\n!");
+    a.dialect_set(|x| {
+        x.enable_while = true;
+        x.enable_top_level_stmt = false;
+    });
+    a.parse_fail("!while True:\n  pass\n!");
+    assert_eq!(
+        a.parse("def f():\n  while True:\n    break\n"),
+        "def f():\n  while True:\n    break\n"
+    );
+}
+
+#[test]
+fn test_f_string() {
+    assert_eq!(
+        assert::parse("f'hello {name}, {{x}} {_y2}'"),
+        "\"hello {}, {{x}} {}\".format(name, _y2)\n"
+    );
+    assert_eq!(
+        assert::parse("f'no {{interpolation}}'"),
+        "\"no {interpolation}\"\n"
+    );
+    assert_eq!(assert::parse("r'{x}'"), "\"{x}\"\n");
+    assert::fail("f'{x + 1}'", "f-string can only interpolate identifiers");
+    assert::fail("f'{x'", "unmatched");
+    assert::fail("f'x}'", "unmatched");
+    let mut a = Assert::new();
+    a.dialect_set(|x| x.enable_f_strings = false);
+    a.parse_fail("x = !f'{y}'!");
+}
+
 #[test]
 fn test_top_level_def_with_docstring() {
     assert_eq!(
@@ -292,3 +349,27 @@ fn test_op_associativity() {
     // TODO - create a better error message for this case
     assert::fail("0 <= 1 < 2", "Parse error");
 }
+
+#[test]
+fn test_parse_all_errors() {
+    let parse_all = |program: &str| {
+        AstModule::parse_all("x.star", program.to_owned(), &Dialect::Standard)
+            .map(|_| ())
+            .unwrap_err()
+    };
+    let line = |e: &Diagnostic| {
+        let (span, codemap) = e.span.as_ref().unwrap();
+        codemap.look_up_span(*span).begin.line
+    };
+    assert!(AstModule::parse_all("x.star", "x = 1\ny = 2".to_owned(), &Dialect::Standard).is_ok());
+    // Each error is reported, and we carry on parsing from the next line
+    let program = "x = 1 +\ny = 2\nz = * 3\nprint(x)\nw = = 1\n";
+    let errors = parse_all(program);
+    assert_eq!(errors.map(line), vec![0, 2, 4]);
+    for e in &errors {
+        assert!(e.message.to_string().contains("Parse error"), "{}", e);
+    }
+    // Whereas parse stops at the first
+    let first = AstModule::parse("x.star", program.to_owned(), &Dialect::Standard).unwrap_err();
+    assert_eq!(first.to_string(), errors[0].to_string());
+}
@@ -25,7 +25,7 @@ use crate::{
 };
 use gazebo::dupe::Dupe;
 use logos::Logos;
-use std::{char, collections::VecDeque, fmt, fmt::Display, sync::Arc};
+use std::{char, collections::VecDeque, fmt, fmt::Display, mem, sync::Arc};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -62,10 +62,33 @@ pub(crate) struct Lexer<'a> {
     lexer: logos::Lexer<'a, Token>,
     done: bool,
     dialect_allow_tabs: bool,
+    /// The `(start, end)` of each comment seen, if we are retaining them
+    comments: Option<Vec<(usize, usize)>>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str, dialect: &Dialect, codemap: Arc<CodeMap>, filespan: Span) -> Self {
+        Self::new_impl(input, dialect, codemap, filespan, false)
+    }
+
+    /// Like [`new`](Lexer::new), but records the position of every comment,
+    /// which can be obtained with [`take_comments`](Lexer::take_comments).
+    pub fn new_retaining_comments(
+        input: &'a str,
+        dialect: &Dialect,
+        codemap: Arc<CodeMap>,
+        filespan: Span,
+    ) -> Self {
+        Self::new_impl(input, dialect, codemap, filespan, true)
+    }
+
+    fn new_impl(
+        input: &'a str,
+        dialect: &Dialect,
+        codemap: Arc<CodeMap>,
+        filespan: Span,
+        retain_comments: bool,
+    ) -> Self {
         let lexer = Token::lexer(input);
         let mut lexer2 = Self {
             codemap,
@@ -77,6 +100,11 @@ impl<'a> Lexer<'a> {
             parens: 0,
             done: false,
             dialect_allow_tabs: dialect.enable_tabs,
+            comments: if retain_comments {
+                Some(Vec::new())
+            } else {
+                None
+            },
         };
         if let Err(e) = lexer2.calculate_indent() {
             lexer2.buffer.push_back(Err(e));
@@ -84,6 +112,15 @@ impl<'a> Lexer<'a> {
         lexer2
     }
 
+    /// The `(start, end)` offsets of the comments seen so far, in source order.
+    /// Empty unless created with [`new_retaining_comments`](Lexer::new_retaining_comments).
+    pub fn take_comments(&mut self) -> Vec<(usize, usize)> {
+        match &mut self.comments {
+            None => Vec::new(),
+            Some(xs) => mem::take(xs),
+        }
+    }
+
     fn err_pos<T>(&self, msg: LexemeError, pos: usize) -> anyhow::Result<T> {
         self.err_span(msg, pos, pos)
     }
@@ -139,9 +176,15 @@ impl<'a> Lexer<'a> {
                     // Remove skip now, so we can freely add it on later
                     spaces = 0;
                     tabs = 0;
+                    // Record the comment directly, since `it` still borrows the lexer
+                    let comment_start = self.lexer.span().end + it.pos() - 1;
                     loop {
                         match it.next_char() {
                             None => {
+                                let comment_end = self.lexer.span().end + it.pos();
+                                if let Some(xs) = &mut self.comments {
+                                    xs.push((comment_start, comment_end));
+                                }
                                 self.lexer.bump(it.pos());
                                 return Ok(());
                             }
@@ -149,6 +192,10 @@ impl<'a> Lexer<'a> {
                             Some(_) => {}
                         }
                     }
+                    let comment_end = self.lexer.span().end + it.pos() - 1;
+                    if let Some(xs) = &mut self.comments {
+                        xs.push((comment_start, comment_end));
+                    }
                     indent_start = self.lexer.span().end + it.pos();
                 }
                 _ => break,
@@ -382,6 +429,14 @@ impl<'a> Lexer<'a> {
         )
     }
 
+    /// If the string started with `f`, turn it into an f-string literal.
+    fn f_string(f_string: bool, x: Lexeme) -> Lexeme {
+        match x {
+            Ok((l, Token::StringLiteral(s), r)) if f_string => Ok((l, Token::FStringLiteral(s), r)),
+            x => x,
+        }
+    }
+
     pub fn next(&mut self) -> Option<Lexeme> {
         loop {
             // Note that this function doesn't always return - a few branches use `continue`
@@ -421,6 +476,13 @@ impl<'a> Lexer<'a> {
                                 continue;
                             }
                         }
+                        Token::Comment => {
+                            if let Some(xs) = &mut self.comments {
+                                let span = self.lexer.span();
+                                xs.push((span.start, span.end));
+                            }
+                            continue;
+                        }
                         Token::Reserved => Some(self.err_now(LexemeError::ReservedKeyword)),
                         Token::Error => Some(self.err_now(LexemeError::InvalidInput)),
                         Token::IntegerLiteral(radix) => {
@@ -445,10 +507,11 @@ impl<'a> Lexer<'a> {
                             }
                         }
                         Token::RawDoubleQuote => {
-                            let raw = self.lexer.span().len() == 2;
-                            if self.lexer.remainder().starts_with("\"\"") {
+                            let raw = self.lexer.slice().starts_with('r');
+                            let f_string = self.lexer.slice().starts_with('f');
+                            let res = if self.lexer.remainder().starts_with("\"\"") {
                                 let mut qs = 0;
-                                Some(self.string(true, raw, |c| {
+                                self.string(true, raw, |c| {
                                     if c == '\"' {
                                         qs += 1;
                                         qs == 3
@@ -456,16 +519,18 @@ impl<'a> Lexer<'a> {
                                         qs = 0;
                                         false
                                     }
-                                }))
+                                })
                             } else {
-                                Some(self.string(false, raw, |c| c == '\"'))
-                            }
+                                self.string(false, raw, |c| c == '\"')
+                            };
+                            Some(Self::f_string(f_string, res))
                         }
                         Token::RawSingleQuote => {
-                            let raw = self.lexer.span().len() == 2;
-                            if self.lexer.remainder().starts_with("''") {
+                            let raw = self.lexer.slice().starts_with('r');
+                            let f_string = self.lexer.slice().starts_with('f');
+                            let res = if self.lexer.remainder().starts_with("''") {
                                 let mut qs = 0;
-                                Some(self.string(true, raw, |c| {
+                                self.string(true, raw, |c| {
                                     if c == '\'' {
                                         qs += 1;
                                         qs == 3
@@ -473,10 +538,11 @@ impl<'a> Lexer<'a> {
                                         qs = 0;
                                         false
                                     }
-                                }))
+                                })
                             } else {
-                                Some(self.string(false, raw, |c| c == '\''))
-                            }
+                                self.string(false, raw, |c| c == '\'')
+                            };
+                            Some(Self::f_string(f_string, res))
                         }
                         Token::OpeningCurly | Token::OpeningRound | Token::OpeningSquare => {
                             self.parens += 1;
@@ -500,10 +566,12 @@ pub enum Token {
     #[regex(" +", logos::skip)] // Whitespace
     #[token("\\\n", logos::skip)] // Escaped newline
     #[token("\\\r\n", logos::skip)] // Escaped newline (Windows line ending)
-    #[regex(r#"#[^\n]*"#, logos::skip)] // Comments
     #[error]
     Error,
 
+    #[regex(r#"#[^\n]*"#)] // Comments, never returned by the lexer
+    Comment,
+
     #[regex("\t+")] // Tabs (might be an error)
     Tabs,
 
@@ -517,14 +585,14 @@ pub enum Token {
     // things ourselves
     #[token("'")]
     #[token("r'")]
+    #[token("f'")]
     RawSingleQuote,
     #[token("\"")]
     #[token("r\"")]
+    #[token("f\"")]
     RawDoubleQuote,
 
-    #[regex(
-        "as|import|is|class|nonlocal|del|raise|except|try|finally|while|from|with|global|yield"
-    )]
+    #[regex("as|import|is|class|nonlocal|del|raise|except|try|finally|from|with|global|yield")]
     Reserved, // One of the reserved keywords
 
     #[regex(
@@ -538,7 +606,8 @@ pub enum Token {
     #[regex("0[oO][0-7]+", |_| 8)]
     IntegerLiteral(i32), // An integer literal (123, 0x1, 0b1011, 0o755, ...)
 
-    StringLiteral(String), // A string literal
+    StringLiteral(String),  // A string literal
+    FStringLiteral(String), // An f-string literal, before interpolation
 
     // Keywords
     #[token("and")]
@@ -571,6 +640,8 @@ pub enum Token {
     Return,
     #[token("lambda")]
     Lambda,
+    #[token("while")]
+    While,
     // Symbols
     #[token(",")]
     Comma,
@@ -686,6 +757,7 @@ impl Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Token::Error => write!(f, "lexical error"),
+            Token::Comment => write!(f, "comment"),
             Token::Indent => write!(f, "new indentation block"),
             Token::Dedent => write!(f, "end of indentation block"),
             Token::Newline => write!(f, "new line"),
@@ -704,6 +776,7 @@ impl Display for Token {
             Token::Elif => write!(f, "keyword 'elif'"),
             Token::Return => write!(f, "keyword 'return'"),
             Token::Lambda => write!(f, "keyword 'lambda'"),
+            Token::While => write!(f, "keyword 'while'"),
             Token::Comma => write!(f, "symbol ','"),
             Token::Semicolon => write!(f, "symbol ';'"),
             Token::Colon => write!(f, "symbol ':'"),
@@ -750,6 +823,7 @@ impl Display for Token {
             Token::Identifier(s) => write!(f, "identifier '{}'", s),
             Token::IntegerLiteral(i) => write!(f, "integer literal '{}'", i),
             Token::StringLiteral(s) => write!(f, "string literal '{}'", s),
+            Token::FStringLiteral(s) => write!(f, "f-string literal '{}'", s),
             Token::RawSingleQuote => write!(f, "starting '"),
             Token::RawDoubleQuote => write!(f, "starting \""),
             Token::Tabs => Ok(()),
@@ -0,0 +1,92 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Find the AST nodes at a given position, as required for IDE features like hovers.
+
+use crate::{
+    codemap::{LineCol, Pos, Span},
+    syntax::{
+        ast::{AstExpr, AstStmt},
+        AstModule,
+    },
+};
+use either::Either;
+
+// Record the smallest expression containing `pos`. Children are visited after their parents,
+// so for equal spans the innermost expression wins.
+fn go(x: Either<&AstStmt, &AstExpr>, pos: Pos, res: &mut Option<Span>) {
+    match x {
+        Either::Left(x) => x.visit_children(|x| go(x, pos, res)),
+        Either::Right(x) => {
+            if x.span.low() <= pos
+                && pos < x.span.high()
+                && res.map_or(true, |r| x.span.len() <= r.len())
+            {
+                *res = Some(x.span);
+            }
+            x.visit_expr(|x| go(Either::Right(x), pos, res))
+        }
+    }
+}
+
+impl AstModule {
+    /// Find the span of the innermost expression at a given line and column, both 0-indexed
+    /// as in [`LineCol`]. For `foo.bar(x)`, the position of `foo` finds `foo`, the position of
+    /// `bar` finds `foo.bar`, and the position of `(` finds the whole call.
+    ///
+    /// The text and location of the expression can be obtained with
+    /// [`look_up_span`](AstModule::look_up_span).
+    pub fn find_expr_at(&self, line: usize, column: usize) -> Option<Span> {
+        let pos = self.codemap.get_file().find_pos(LineCol { line, column })?;
+        let mut res = None;
+        go(Either::Left(&self.statement), pos, &mut res);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::{AstModule, Dialect};
+
+    #[test]
+    fn test_find_expr_at() {
+        let module = AstModule::parse(
+            "test.bzl",
+            "x = 1\ny = foo(x, bar.baz)\ndef f(a):\n    return [a + 1]\n".to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+        let find = |line, column| {
+            module
+                .find_expr_at(line, column)
+                .map(|span| module.look_up_span(span).file.source_slice(span).to_owned())
+        };
+        assert_eq!(find(0, 0).as_deref(), Some("x"));
+        assert_eq!(find(0, 4).as_deref(), Some("1"));
+        assert_eq!(find(0, 5), None);
+        assert_eq!(find(1, 4).as_deref(), Some("foo"));
+        assert_eq!(find(1, 7).as_deref(), Some("foo(x, bar.baz)"));
+        assert_eq!(find(1, 8).as_deref(), Some("x"));
+        assert_eq!(find(1, 12).as_deref(), Some("bar"));
+        assert_eq!(find(1, 15).as_deref(), Some("bar.baz"));
+        assert_eq!(find(2, 6), None);
+        assert_eq!(find(3, 12).as_deref(), Some("a"));
+        assert_eq!(find(3, 14).as_deref(), Some("a + 1"));
+        assert_eq!(find(3, 11).as_deref(), Some("[a + 1]"));
+        assert_eq!(find(10, 0), None);
+    }
+}
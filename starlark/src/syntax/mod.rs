@@ -18,7 +18,10 @@
 //! The AST of Starlark as [`AstModule`], along with a [`parse`](AstModule::parse) function.
 
 pub use ast::AstModule;
+pub use comments::{Comment, CommentAttachment, Comments};
 pub use dialect::Dialect;
+pub(crate) use dialect::DialectError;
+pub use visit::Visitor;
 
 #[cfg(test)]
 mod grammar_tests;
@@ -27,10 +30,12 @@ mod lexer_tests;
 #[cfg(test)]
 mod testcases;
 
-pub(crate) mod ast;
+pub mod ast;
+mod comments;
 pub(crate) mod cursors;
 mod dialect;
 pub(crate) mod lexer;
+mod lookup;
 pub(crate) mod validate;
 
 #[allow(clippy::all)]
@@ -45,3 +50,4 @@ mod grammar {
 
 pub(crate) mod parser;
 pub(crate) mod uniplate;
+mod visit;
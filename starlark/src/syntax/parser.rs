@@ -23,12 +23,18 @@ use crate::{
         dialect::Dialect,
         grammar::StarlarkParser,
         lexer::{Lexer, Token},
+        Comments,
     },
 };
-use anyhow::anyhow;
 use gazebo::prelude::*;
 use lalrpop_util as lu;
 use std::{fs, path::Path, sync::Arc};
+use thiserror::Error;
+
+// A parse error reported by the grammar, rather than one of ours
+#[derive(Error, Debug)]
+#[error("{0}")]
+pub(crate) struct SyntaxError(String);
 
 fn one_of(expected: &[String]) -> String {
     let mut result = String::new();
@@ -88,7 +94,11 @@ pub(crate) fn parse_error_add_span(
         lu::ParseError::User { .. } => unreachable!(),
     };
 
-    Diagnostic::new(anyhow!(message), span, codemap)
+    Diagnostic::new(SyntaxError(message), span, codemap)
+}
+
+fn first_error(errors: Vec<anyhow::Error>) -> anyhow::Error {
+    errors.into_iter().next().unwrap()
 }
 
 impl AstModule {
@@ -96,9 +106,15 @@ impl AstModule {
         codemap: Arc<CodeMap>,
         statement: AstStmt,
         dialect: &Dialect,
+        comments: Comments,
     ) -> anyhow::Result<AstModule> {
         Stmt::validate(&codemap, &statement, dialect)?;
-        Ok(AstModule { codemap, statement })
+        Ok(AstModule {
+            codemap,
+            statement,
+            comments,
+            dialect: dialect.clone(),
+        })
     }
 
     /// Parse a file stored on disk. For details see [`parse`](AstModule::parse).
@@ -111,18 +127,83 @@ impl AstModule {
     /// The `filename` is for error messages only, and does not have to be a valid file.
     /// The [`Dialect`] selects which Starlark constructs are valid.
     pub fn parse(filename: &str, content: String, dialect: &Dialect) -> anyhow::Result<Self> {
+        Self::parse_impl(filename, content, dialect, false).map_err(first_error)
+    }
+
+    /// Like [`parse`](AstModule::parse), but after a syntax error carries on parsing from the
+    /// next line, so reports every syntax error in the module rather than just the first, in
+    /// the order they occur. Useful for editors, which want to show all the problems at once.
+    pub fn parse_all(
+        filename: &str,
+        content: String,
+        dialect: &Dialect,
+    ) -> Result<Self, Vec<Diagnostic>> {
+        Self::parse_impl(filename, content, dialect, false)
+            .map_err(|errors| errors.into_map(Diagnostic::from_anyhow))
+    }
+
+    /// Like [`parse`](AstModule::parse), but also retains the comments in the source,
+    /// each attached to the statement it precedes or follows. The comments are available
+    /// with [`comments`](AstModule::comments), and are useful for formatters and documentation tools.
+    pub fn parse_with_comments(
+        filename: &str,
+        content: String,
+        dialect: &Dialect,
+    ) -> anyhow::Result<Self> {
+        Self::parse_impl(filename, content, dialect, true).map_err(first_error)
+    }
+
+    // On failure, returns at least one error
+    fn parse_impl(
+        filename: &str,
+        content: String,
+        dialect: &Dialect,
+        retain_comments: bool,
+    ) -> Result<Self, Vec<anyhow::Error>> {
         let codemap = CodeMap::new(filename.to_owned(), content);
         let file = codemap.get_file().dupe();
         let codemap = Arc::new(codemap);
-        let lexer = Lexer::new(file.source(), dialect, codemap.dupe(), file.span);
-        match StarlarkParser::new().parse(&codemap, file.span, dialect, lexer) {
-            Ok(v) => Ok(AstModule::create(codemap, v, dialect)?),
-            Err(p) => Err(parse_error_add_span(p, file.span, codemap)),
+        let mut lexer = if retain_comments {
+            Lexer::new_retaining_comments(file.source(), dialect, codemap.dupe(), file.span)
+        } else {
+            Lexer::new(file.source(), dialect, codemap.dupe(), file.span)
+        };
+        let mut recovered = Vec::new();
+        let res =
+            StarlarkParser::new().parse(&codemap, file.span, dialect, &mut recovered, &mut lexer);
+        let mut errors =
+            recovered.into_map(|x| parse_error_add_span(x.error, file.span, codemap.dupe()));
+        match res {
+            Ok(v) if errors.is_empty() => {
+                let comments = if retain_comments {
+                    Comments::new(&codemap, &v, lexer.take_comments())
+                } else {
+                    Comments::default()
+                };
+                AstModule::create(codemap, v, dialect, comments).map_err(|e| vec![e])
+            }
+            Ok(_) => Err(errors),
+            Err(p) => {
+                errors.push(parse_error_add_span(p, file.span, codemap));
+                Err(errors)
+            }
         }
     }
 
+    /// The comments retained by [`parse_with_comments`](AstModule::parse_with_comments),
+    /// in source order. Empty if the module was parsed with [`parse`](AstModule::parse).
+    pub fn comments(&self) -> &Comments {
+        &self.comments
+    }
+
+    /// The docstring of the module, if it starts with a string literal.
+    pub fn docstring(&self) -> Option<&str> {
+        self.statement.docstring()
+    }
+
     /// Return the file names of all the `load` statements in the module.
     /// If the [`Dialect`] had [`enable_load`](Dialect::enable_load) set to [`false`] this will be an empty list.
+    /// The names are as written, before [`FileLoader::resolve`](crate::eval::FileLoader::resolve).
     pub fn loads(&self) -> Vec<&str> {
         // We know that `load` statements must be at the top-level, so no need to descend inside `if`, `for`, `def` etc.
         // There is a suggestion that `load` statements should be at the top of a file, but we tolerate that not being true.
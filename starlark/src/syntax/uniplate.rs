@@ -46,6 +46,10 @@ impl Stmt {
                 f(Either::Right(over));
                 f(Either::Left(body));
             }
+            Stmt::While(condition, body) => {
+                f(Either::Right(condition));
+                f(Either::Left(body));
+            }
             // Nothing else contains nested statements
             Stmt::Break => {}
             Stmt::Continue => {}
@@ -18,11 +18,12 @@
 //! AST for parsed starlark files.
 
 use crate::{
-    codemap::{CodeMap, Spanned},
+    codemap::{CodeMap, Span, Spanned},
     errors::Diagnostic,
     syntax::{
         ast::{
-            Argument, AstArgument, AstExpr, AstParameter, AstStmt, AstString, Expr, Parameter, Stmt,
+            Argument, AstArgument, AstExpr, AstLiteral, AstParameter, AstStmt, AstString, Expr,
+            Parameter, Stmt,
         },
         Dialect,
     },
@@ -32,7 +33,7 @@ use std::{collections::HashSet, sync::Arc};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-enum ValidateError {
+pub(crate) enum ValidateError {
     #[error("`break` cannot be used outside of a `for` loop")]
     BreakOutsideLoop,
     #[error("`continue` cannot be used outside of a `for` loop")]
@@ -45,6 +46,12 @@ enum ValidateError {
     NoTopLevelIf,
     #[error("`for` cannot be used outside `def` in this dialect")]
     NoTopLevelFor,
+    #[error("`while` cannot be used outside `def` in this dialect")]
+    NoTopLevelWhile,
+    #[error("f-string can only interpolate identifiers, got `{0}`")]
+    FStringNotIdentifier(String),
+    #[error("f-string has an unmatched `{0}`")]
+    FStringUnmatched(char),
 }
 
 #[derive(Eq, PartialEq, Ord, PartialOrd)]
@@ -56,7 +63,7 @@ enum ArgsStage {
 }
 
 #[derive(Error, Debug)]
-enum ArgumentDefinitionOrderError {
+pub(crate) enum ArgumentDefinitionOrderError {
     #[error("positional argument after non positional")]
     PositionalThenNonPositional,
     #[error("named argument after *args or **kwargs")]
@@ -135,6 +142,74 @@ impl Expr {
         }
         Ok(Expr::Call(box f, args))
     }
+
+    /// Desugar the contents of an f-string, so `f"x{y}"` becomes `"x{}".format(y)`.
+    /// Only identifiers may be interpolated, and `{{` and `}}` are escapes, as with `format`.
+    /// All the generated expressions are given the span of the whole f-string.
+    pub fn check_f_string(
+        contents: String,
+        span: Span,
+        codemap: &Arc<CodeMap>,
+    ) -> anyhow::Result<Expr> {
+        let err = |msg| Err(Diagnostic::new(msg, span, codemap.dupe()));
+        fn ast<T>(span: Span, node: T) -> Spanned<T> {
+            Spanned { span, node }
+        }
+
+        // The template passed to `format`, and the string if there is nothing to interpolate
+        let mut template = String::with_capacity(contents.len());
+        let mut plain = String::with_capacity(contents.len());
+        let mut names = Vec::new();
+        let mut it = contents.chars().peekable();
+        while let Some(c) = it.next() {
+            match c {
+                '{' if it.peek() == Some(&'{') => {
+                    it.next();
+                    template.push_str("{{");
+                    plain.push('{');
+                }
+                '}' if it.peek() == Some(&'}') => {
+                    it.next();
+                    template.push_str("}}");
+                    plain.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match it.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return err(ValidateError::FStringUnmatched('{')),
+                        }
+                    }
+                    let mut cs = name.chars();
+                    let valid = matches!(cs.next(), Some(c) if c == '_' || c.is_ascii_alphabetic());
+                    let valid = valid && cs.all(|c| c == '_' || c.is_ascii_alphanumeric());
+                    if !valid {
+                        return err(ValidateError::FStringNotIdentifier(name));
+                    }
+                    template.push_str("{}");
+                    names.push(name);
+                }
+                '}' => return err(ValidateError::FStringUnmatched('}')),
+                c => {
+                    template.push(c);
+                    plain.push(c);
+                }
+            }
+        }
+
+        if names.is_empty() {
+            return Ok(Expr::Literal(AstLiteral::StringLiteral(ast(span, plain))));
+        }
+        let template = Expr::Literal(AstLiteral::StringLiteral(ast(span, template)));
+        let format = Expr::Dot(box ast(span, template), ast(span, "format".to_owned()));
+        let args = names.into_map(|x| {
+            let x = Expr::Identifier(ast(span, x));
+            ast(span, Argument::Positional(ast(span, x)))
+        });
+        Ok(Expr::Call(box ast(span, format), args))
+    }
 }
 
 fn test_param_name<'a, T>(
@@ -155,7 +230,7 @@ fn test_param_name<'a, T>(
 }
 
 #[derive(Error, Debug)]
-enum ArgumentUseOrderError {
+pub(crate) enum ArgumentUseOrderError {
     #[error("duplicated parameter name")]
     DuplicateParameterName,
     #[error("positional parameter after non positional")]
@@ -256,6 +331,13 @@ impl Stmt {
                         f(codemap, dialect, body, false, true, inside_def)
                     }
                 }
+                Stmt::While(_, body) => {
+                    if top_level && !dialect.enable_top_level_stmt {
+                        err(ValidateError::NoTopLevelWhile)
+                    } else {
+                        f(codemap, dialect, body, false, true, inside_def)
+                    }
+                }
                 Stmt::If(..) | Stmt::IfElse(..) => {
                     if top_level && !dialect.enable_top_level_stmt {
                         err(ValidateError::NoTopLevelIf)
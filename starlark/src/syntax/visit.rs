@@ -0,0 +1,132 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A [`Visitor`] over the statements and expressions of an [`AstModule`].
+
+use crate::syntax::{
+    ast::{AstExpr, AstStmt},
+    AstModule,
+};
+use either::Either;
+
+/// Called for every statement and expression of an [`AstModule`] by [`visit`](AstModule::visit).
+/// Each node carries its [`Span`](crate::codemap::Span), which can be turned into a file, line and column
+/// with [`look_up_span`](AstModule::look_up_span).
+///
+/// ```
+/// use starlark::syntax::{ast::{AstExpr, Expr}, AstModule, Dialect, Visitor};
+///
+/// struct Calls(usize);
+///
+/// impl Visitor for Calls {
+///     fn visit_expr(&mut self, x: &AstExpr) {
+///         if let Expr::Call(..) = &x.node {
+///             self.0 += 1;
+///         }
+///     }
+/// }
+///
+/// let code = "print(len([1, 2]))";
+/// let ast = AstModule::parse("x.star", code.to_owned(), &Dialect::Standard).unwrap();
+/// let mut calls = Calls(0);
+/// ast.visit(&mut calls);
+/// assert_eq!(calls.0, 2);
+/// ```
+pub trait Visitor {
+    /// Called on each statement, before any statements or expressions it contains.
+    fn visit_stmt(&mut self, _x: &AstStmt) {}
+
+    /// Called on each expression, before any expressions it contains.
+    fn visit_expr(&mut self, _x: &AstExpr) {}
+}
+
+fn go<V: Visitor + ?Sized>(x: Either<&AstStmt, &AstExpr>, visitor: &mut V) {
+    match x {
+        Either::Left(x) => {
+            visitor.visit_stmt(x);
+            x.visit_children(|x| go(x, visitor))
+        }
+        Either::Right(x) => {
+            visitor.visit_expr(x);
+            x.visit_expr(|x| go(Either::Right(x), visitor))
+        }
+    }
+}
+
+impl AstModule {
+    /// Traverse all the statements and expressions in the module, parents before children.
+    pub fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        go(Either::Left(&self.statement), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::syntax::{
+        ast::{AstExpr, AstStmt, Expr, Stmt},
+        AstModule, Dialect, Visitor,
+    };
+
+    struct Collect<'a> {
+        module: &'a AstModule,
+        defs: Vec<String>,
+        names: Vec<(usize, String)>,
+    }
+
+    impl Visitor for Collect<'_> {
+        fn visit_stmt(&mut self, x: &AstStmt) {
+            if let Stmt::Def(name, ..) = &x.node {
+                self.defs.push(name.node.clone());
+            }
+        }
+
+        fn visit_expr(&mut self, x: &AstExpr) {
+            if let Expr::Identifier(name) = &x.node {
+                let line = self.module.look_up_span(x.span).begin.line;
+                self.names.push((line, name.node.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let module = AstModule::parse(
+            "test.bzl",
+            "x = 1\ndef f(a = y):\n    return [a + z for z in x]\n".to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+        let mut collect = Collect {
+            module: &module,
+            defs: Vec::new(),
+            names: Vec::new(),
+        };
+        module.visit(&mut collect);
+        assert_eq!(collect.defs, vec!["f".to_owned()]);
+        assert_eq!(
+            collect.names,
+            vec![
+                (0, "x".to_owned()),
+                (1, "y".to_owned()),
+                (2, "z".to_owned()),
+                (2, "x".to_owned()),
+                (2, "a".to_owned()),
+                (2, "z".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Attributes of native types which are computed when accessed, see [`ComputedAttrs`].
+
+use crate::{
+    collections::SmallMap,
+    values::{Heap, Value},
+};
+
+type AttrFn<T> = Box<dyn for<'v> Fn(&T, &'v Heap) -> anyhow::Result<Value<'v>> + Send + Sync>;
+
+/// A table of named attributes on a native type `T`, each computed by a function only when
+/// it is accessed. A Rust value can then be passed to Starlark without first converting all
+/// its fields to Starlark values, which matters if some are expensive and rarely used.
+///
+/// The table is usually stored in a static, and used to implement
+/// [`get_attr`](crate::values::StarlarkValue::get_attr),
+/// [`has_attr`](crate::values::StarlarkValue::has_attr) and
+/// [`dir_attr`](crate::values::StarlarkValue::dir_attr):
+///
+/// ```
+/// # fn main() {
+/// use starlark::assert::Assert;
+/// use starlark::values::{ComputedAttrs, Heap, StarlarkValue, Value, ValueError};
+/// use starlark::{starlark_simple_value, starlark_type};
+/// use once_cell::sync::Lazy;
+///
+/// #[derive(Debug)]
+/// struct Target {
+///     name: String,
+///     srcs: Vec<String>,
+/// }
+/// starlark_simple_value!(Target);
+///
+/// static ATTRS: Lazy<ComputedAttrs<Target>> = Lazy::new(|| {
+///     ComputedAttrs::new()
+///         .with("name", |x: &Target, heap| Ok(heap.alloc(x.name.as_str())))
+///         .with("srcs", |x: &Target, heap| Ok(heap.alloc(x.srcs.clone())))
+/// });
+///
+/// impl<'v> StarlarkValue<'v> for Target {
+///     starlark_type!("target");
+///
+///     fn get_attr(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+///         ATTRS
+///             .get_attr(self, attribute, heap)
+///             .unwrap_or_else(|| ValueError::unsupported(self, &format!(".{}", attribute)))
+///     }
+///
+///     fn has_attr(&self, attribute: &str) -> bool {
+///         ATTRS.has_attr(attribute)
+///     }
+///
+///     fn dir_attr(&self) -> Vec<String> {
+///         ATTRS.dir_attr()
+///     }
+/// }
+///
+/// let mut a = Assert::new();
+/// a.globals_add(|builder| builder.set("t", Target {
+///     name: "foo".to_owned(),
+///     srcs: vec!["a.c".to_owned()],
+/// }));
+/// a.all_true(r#"
+/// t.name == "foo"
+/// t.srcs == ["a.c"]
+/// dir(t) == ["name", "srcs"]
+/// "#);
+/// # }
+/// ```
+pub struct ComputedAttrs<T> {
+    attrs: SmallMap<String, AttrFn<T>>,
+}
+
+impl<T> ComputedAttrs<T> {
+    /// Create a table with no attributes.
+    pub fn new() -> Self {
+        Self {
+            attrs: SmallMap::new(),
+        }
+    }
+
+    /// Add an attribute `name`, whose value is computed by `f` each time it is accessed.
+    /// If an attribute with the same name was already added, it is replaced.
+    pub fn with(
+        mut self,
+        name: &str,
+        f: impl for<'v> Fn(&T, &'v Heap) -> anyhow::Result<Value<'v>> + Send + Sync + 'static,
+    ) -> Self {
+        self.attrs.insert(name.to_owned(), box f);
+        self
+    }
+
+    /// Compute the attribute `attribute` of `this`, or [`None`] if there is no such attribute.
+    pub fn get_attr<'v>(
+        &self,
+        this: &T,
+        attribute: &str,
+        heap: &'v Heap,
+    ) -> Option<anyhow::Result<Value<'v>>> {
+        self.attrs.get(attribute).map(|f| f(this, heap))
+    }
+
+    /// Is there an attribute named `attribute`. Doesn't compute its value.
+    pub fn has_attr(&self, attribute: &str) -> bool {
+        self.attrs.contains_key(attribute)
+    }
+
+    /// The names of all the attributes, in the order they were added.
+    pub fn dir_attr(&self) -> Vec<String> {
+        self.attrs.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assert::Assert,
+        values::{StarlarkValue, ValueError},
+    };
+    use once_cell::sync::Lazy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    struct Lazily(i32);
+    starlark_simple_value!(Lazily);
+
+    static ATTRS: Lazy<ComputedAttrs<Lazily>> = Lazy::new(|| {
+        ComputedAttrs::new()
+            .with("value", |x: &Lazily, _| Ok(Value::new_int(x.0)))
+            .with("expensive", |x: &Lazily, heap| {
+                COMPUTED.fetch_add(1, Ordering::SeqCst);
+                Ok(heap.alloc(vec![x.0; 3]))
+            })
+    });
+
+    impl<'v> StarlarkValue<'v> for Lazily {
+        starlark_type!("lazily");
+
+        fn get_attr(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+            ATTRS
+                .get_attr(self, attribute, heap)
+                .unwrap_or_else(|| ValueError::unsupported(self, &format!(".{}", attribute)))
+        }
+
+        fn has_attr(&self, attribute: &str) -> bool {
+            ATTRS.has_attr(attribute)
+        }
+
+        fn dir_attr(&self) -> Vec<String> {
+            ATTRS.dir_attr()
+        }
+    }
+
+    #[test]
+    fn test_computed_attrs() {
+        let mut a = Assert::new();
+        a.globals_add(|builder| builder.set("x", Lazily(7)));
+        a.all_true(
+            r#"
+x.value == 7
+hasattr(x, "expensive")
+not hasattr(x, "missing")
+dir(x) == ["expensive", "value"]
+getattr(x, "missing", None) == None
+"#,
+        );
+        assert_eq!(COMPUTED.load(Ordering::SeqCst), 0);
+        a.is_true("x.expensive == [7, 7, 7]");
+        assert_ne!(COMPUTED.load(Ordering::SeqCst), 0);
+        a.fail("x.missing", "not supported");
+    }
+}
@@ -15,11 +15,200 @@
  * limitations under the License.
  */
 
-use crate::collections::SmallMap;
-use gazebo::prelude::*;
+use crate::{
+    collections::SmallMap,
+    values::{
+        dict::Dict, dict_view::DictView, error::ControlError, list::List, tuple::Tuple, Value,
+    },
+};
+use gazebo::{cell::ARef, prelude::*};
 use indexmap::Equivalent;
 use itertools::Itertools;
-use std::{cmp::Ordering, hash::Hash};
+use std::{
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+// The functions below compare values using an explicit work-list, rather than
+// recursing into nested lists, tuples and dicts, so that deeply nested values
+// (which are easy to construct in Starlark) can't overflow the Rust stack.
+// Cyclic values would grow the work-list forever, so it is bounded.
+const MAX_DEPTH: usize = 100_000;
+
+// Elements of two containers of the same type, still to be compared pairwise.
+enum Pending<'v> {
+    // Two sequences of the same length, and the index of the next pair to compare
+    Sequence(ARef<'v, [Value<'v>]>, ARef<'v, [Value<'v>]>, usize),
+    // The pairs of values still to compare, e.g. the values of dictionaries under the same key
+    Pairs(Vec<(Value<'v>, Value<'v>)>),
+}
+
+impl<'v> Pending<'v> {
+    fn next(&mut self) -> Option<(Value<'v>, Value<'v>)> {
+        match self {
+            Self::Sequence(xs, ys, i) => {
+                let res = xs.get(*i).map(|x| (*x, ys[*i]));
+                *i += 1;
+                res
+            }
+            Self::Pairs(xs) => xs.pop(),
+        }
+    }
+}
+
+// If `x` and `y` are both lists, or both tuples, their elements.
+fn sequences<'v>(
+    x: Value<'v>,
+    y: Value<'v>,
+) -> Option<(ARef<'v, [Value<'v>]>, ARef<'v, [Value<'v>]>)> {
+    if let (Some(xs), Some(ys)) = (List::from_value(x), List::from_value(y)) {
+        Some((
+            ARef::map(xs, |x| x.content.as_slice()),
+            ARef::map(ys, |y| y.content.as_slice()),
+        ))
+    } else if let (Some(xs), Some(ys)) = (Tuple::from_value(x), Tuple::from_value(y)) {
+        Some((
+            ARef::map(xs, |x| x.content()),
+            ARef::map(ys, |y| y.content()),
+        ))
+    } else {
+        None
+    }
+}
+
+fn push<'v>(todo: &mut Vec<Pending<'v>>, x: Pending<'v>) -> anyhow::Result<()> {
+    if todo.len() >= MAX_DEPTH {
+        return Err(ControlError::TooManyRecursionLevel.into());
+    }
+    todo.push(x);
+    Ok(())
+}
+
+/// Are two values equal, following [`StarlarkValue::equals`](crate::values::StarlarkValue::equals)
+/// for everything other than lists, tuples and dicts.
+pub(crate) fn equals_value<'v>(x: Value<'v>, y: Value<'v>) -> anyhow::Result<bool> {
+    let mut todo = Vec::new();
+    let mut next = Some((x, y));
+    loop {
+        if let Some((x, y)) = next {
+            if x.ptr_eq(y) {
+                // Equal without looking inside
+            } else if x.unpack_int().is_some() {
+                // Ints are stored in the pointer, so equal ints are always ptr_eq,
+                // and ints are never equal to anything else
+                return Ok(false);
+            } else if let Some((xs, ys)) = sequences(x, y) {
+                if xs.len() != ys.len() {
+                    return Ok(false);
+                }
+                push(&mut todo, Pending::Sequence(xs, ys, 0))?;
+            } else if let (Some(xs), Some(ys)) = (Dict::from_value(x), Dict::from_value(y)) {
+                if xs.content.len() != ys.content.len() {
+                    return Ok(false);
+                }
+                let mut pairs = Vec::with_capacity(xs.content.len());
+                for (k, xv) in xs.content.iter_hashed() {
+                    match ys.content.get_hashed(k) {
+                        None => return Ok(false),
+                        Some(yv) => pairs.push((*xv, *yv)),
+                    }
+                }
+                push(&mut todo, Pending::Pairs(pairs))?;
+            } else if DictView::from_value(y).is_some() {
+                // Views compare equal to lists, which don't know about views,
+                // so let the view decide whichever side it is on
+                if !y.get_aref().equals(x)? {
+                    return Ok(false);
+                }
+            } else if !x.get_aref().equals(y)? {
+                return Ok(false);
+            }
+        }
+        next = loop {
+            match todo.last_mut() {
+                None => return Ok(true),
+                Some(p) => match p.next() {
+                    None => {
+                        todo.pop();
+                    }
+                    res => break res,
+                },
+            }
+        };
+    }
+}
+
+/// Compare two values, following [`StarlarkValue::compare`](crate::values::StarlarkValue::compare)
+/// for everything other than lists and tuples.
+pub(crate) fn compare_value<'v>(x: Value<'v>, y: Value<'v>) -> anyhow::Result<Ordering> {
+    let mut todo = Vec::new();
+    let mut next = Some((x, y));
+    loop {
+        if let Some((x, y)) = next {
+            let res = match sequences(x, y) {
+                Some((xs, ys)) => {
+                    let res = xs.len().cmp(&ys.len());
+                    if res == Ordering::Equal {
+                        push(&mut todo, Pending::Sequence(xs, ys, 0))?;
+                    }
+                    res
+                }
+                None => x.get_aref().compare(y)?,
+            };
+            if res != Ordering::Equal {
+                return Ok(res);
+            }
+        }
+        next = loop {
+            match todo.last_mut() {
+                None => return Ok(Ordering::Equal),
+                Some(p) => match p.next() {
+                    None => {
+                        todo.pop();
+                    }
+                    res => break res,
+                },
+            }
+        };
+    }
+}
+
+/// Hash a value, following [`StarlarkValue::get_hash`](crate::values::StarlarkValue::get_hash)
+/// for everything other than tuples.
+pub(crate) fn hash_value<'v>(x: Value<'v>) -> anyhow::Result<u64> {
+    // Each tuple being hashed, how many elements have been hashed,
+    // and the hasher those element hashes have been written to
+    let mut todo: Vec<(ARef<'v, Tuple<'v>>, usize, DefaultHasher)> = Vec::new();
+    let mut next = x;
+    loop {
+        // Ints (common as dict keys) hash to themselves, so don't need any dispatch
+        let mut hash = match next.unpack_int() {
+            Some(i) => Some(i as u64),
+            None => match Tuple::from_value(next) {
+                None => Some(next.get_aref().get_hash()?),
+                Some(t) => {
+                    todo.push((t, 0, DefaultHasher::new()));
+                    None
+                }
+            },
+        };
+        next = loop {
+            let top = match todo.last_mut() {
+                None => return Ok(hash.unwrap()),
+                Some(top) => top,
+            };
+            if let Some(hash) = hash.take() {
+                top.2.write_u64(hash);
+            }
+            if let Some(v) = top.0.content().get(top.1) {
+                top.1 += 1;
+                break *v;
+            }
+            hash = Some(todo.pop().unwrap().2.finish());
+        };
+    }
+}
 
 pub fn equals_slice<E, X1, X2>(
     xs: &[X1],
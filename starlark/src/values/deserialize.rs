@@ -0,0 +1,417 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Convert a [`Value`] into any Rust type implementing [`Deserialize`],
+//! as used by [`Value::deserialize`].
+//!
+//! Lists and tuples become sequences, dicts and structs become maps,
+//! and enums are either a string naming the variant, or a dict with
+//! a single key naming the variant, mapping to its contents.
+
+use crate::values::{dict::Dict, list::List, structs::Struct, tuple::Tuple, Value};
+use serde::{
+    de::{
+        self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Deserializer,
+};
+use std::{fmt::Display, vec};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("{0}")]
+struct DeserializeError(String);
+
+impl de::Error for DeserializeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+// The same limit as serde_json. Deeper than any sensible value, so most likely
+// a list which contains itself.
+const MAX_DEPTH: usize = 128;
+
+pub(crate) fn deserialize<'v, T: Deserialize<'v>>(value: Value<'v>) -> anyhow::Result<T> {
+    Ok(T::deserialize(ValueDeserializer::new(value, 0)?)?)
+}
+
+struct ValueDeserializer<'v> {
+    value: Value<'v>,
+    // How many lists, dicts or enums the value is nested inside
+    depth: usize,
+}
+
+impl<'v> ValueDeserializer<'v> {
+    fn new(value: Value<'v>, depth: usize) -> Result<Self, DeserializeError> {
+        if depth > MAX_DEPTH {
+            Err(DeserializeError(
+                "Cannot deserialize a value which is too deeply nested".to_owned(),
+            ))
+        } else {
+            Ok(Self { value, depth })
+        }
+    }
+}
+
+// The key of a map, either a dict key or a struct field name.
+enum Key<'v> {
+    Value(Value<'v>),
+    Name(String),
+}
+
+impl<'v> Key<'v> {
+    fn deserialize<T: DeserializeSeed<'v>>(
+        self,
+        seed: T,
+        depth: usize,
+    ) -> Result<T::Value, DeserializeError> {
+        match self {
+            Self::Value(x) => seed.deserialize(ValueDeserializer::new(x, depth)?),
+            Self::Name(x) => seed.deserialize(x.into_deserializer()),
+        }
+    }
+}
+
+fn seq_items<'v>(x: Value<'v>) -> Option<Vec<Value<'v>>> {
+    match List::from_value(x) {
+        Some(x) => Some(x.content.clone()),
+        None => Tuple::from_value(x).map(|x| x.content().to_vec()),
+    }
+}
+
+fn map_items<'v>(x: Value<'v>) -> Option<Vec<(Key<'v>, Value<'v>)>> {
+    match Dict::from_value(x) {
+        Some(x) => Some(
+            x.content
+                .iter()
+                .map(|(k, v)| (Key::Value(*k), *v))
+                .collect(),
+        ),
+        None => Struct::from_value(x).map(|x| {
+            x.fields
+                .iter()
+                .map(|(k, v)| (Key::Name(k.clone()), *v))
+                .collect()
+        }),
+    }
+}
+
+fn unsupported<T>(x: Value) -> Result<T, DeserializeError> {
+    Err(DeserializeError(format!(
+        "Cannot deserialize a value of type `{}`",
+        x.get_type()
+    )))
+}
+
+impl<'v> Deserializer<'v> for ValueDeserializer<'v> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'v>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let x = self.value;
+        let depth = self.depth + 1;
+        if x.is_none() {
+            visitor.visit_unit()
+        } else if let Some(x) = x.unpack_bool() {
+            visitor.visit_bool(x)
+        } else if let Some(x) = x.unpack_int() {
+            visitor.visit_i32(x)
+        } else if let Some(x) = x.unpack_str() {
+            visitor.visit_borrowed_str(x)
+        } else if let Some(xs) = seq_items(x) {
+            visitor.visit_seq(SeqDeserializer {
+                items: xs.into_iter(),
+                depth,
+            })
+        } else if let Some(xs) = map_items(x) {
+            visitor.visit_map(MapDeserializer {
+                items: xs.into_iter(),
+                value: None,
+                depth,
+            })
+        } else {
+            unsupported(x)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'v>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if self.value.is_none() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'v>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'v>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let x = self.value;
+        if let Some(variant) = x.unpack_str() {
+            return visitor.visit_enum(variant.into_deserializer());
+        }
+        match map_items(x) {
+            Some(items) if items.len() == 1 => {
+                let (variant, value) = items.into_iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant,
+                    value,
+                    depth: self.depth + 1,
+                })
+            }
+            _ => Err(DeserializeError(format!(
+                "Expected a string or a dict with a single key for enum `{}`, got a value of type `{}`",
+                name,
+                x.get_type()
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqDeserializer<'v> {
+    items: vec::IntoIter<Value<'v>>,
+    // The depth of the items
+    depth: usize,
+}
+
+impl<'v> SeqAccess<'v> for SeqDeserializer<'v> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'v>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.items.next() {
+            Some(x) => seed
+                .deserialize(ValueDeserializer::new(x, self.depth)?)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapDeserializer<'v> {
+    items: vec::IntoIter<(Key<'v>, Value<'v>)>,
+    // The value corresponding to the last key returned
+    value: Option<Value<'v>>,
+    // The depth of the keys and values
+    depth: usize,
+}
+
+impl<'v> MapAccess<'v> for MapDeserializer<'v> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'v>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.items.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                k.deserialize(seed, self.depth).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'v>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        // Serde always asks for a key before its value
+        let value = self.value.take().unwrap();
+        seed.deserialize(ValueDeserializer::new(value, self.depth)?)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct EnumDeserializer<'v> {
+    variant: Key<'v>,
+    value: Value<'v>,
+    // The depth of the variant and its contents
+    depth: usize,
+}
+
+impl<'v> EnumAccess<'v> for EnumDeserializer<'v> {
+    type Error = DeserializeError;
+    type Variant = ValueDeserializer<'v>;
+
+    fn variant_seed<V: DeserializeSeed<'v>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = self.variant.deserialize(seed, self.depth)?;
+        Ok((variant, ValueDeserializer::new(self.value, self.depth)?))
+    }
+}
+
+impl<'v> VariantAccess<'v> for ValueDeserializer<'v> {
+    type Error = DeserializeError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        <()>::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'v>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'v>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'v>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        self as starlark,
+        assert::Assert,
+        environment::GlobalsBuilder,
+        values::{none::NoneType, Value},
+    };
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Fast,
+        Slow { delay: u32 },
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Config<'a> {
+        name: &'a str,
+        port: u16,
+        debug: bool,
+        tags: Vec<String>,
+        limits: HashMap<String, i64>,
+        owner: Option<String>,
+        pair: (i32, char),
+        modes: Vec<Mode>,
+    }
+
+    #[derive(Deserialize)]
+    struct Nested(Vec<Nested>);
+
+    impl Nested {
+        fn depth(&self) -> i32 {
+            1 + self.0.iter().map(Nested::depth).max().unwrap_or(0)
+        }
+    }
+
+    #[starlark_module]
+    fn module(builder: &mut GlobalsBuilder) {
+        fn check_config(ref x: Value<'v>) -> NoneType {
+            let config: Config = x.deserialize()?;
+            assert_eq!(
+                config,
+                Config {
+                    name: "server",
+                    port: 8080,
+                    debug: false,
+                    tags: vec!["a".to_owned(), "b".to_owned()],
+                    limits: vec![("cpu".to_owned(), 4)].into_iter().collect(),
+                    owner: None,
+                    pair: (1, 'x'),
+                    modes: vec![Mode::Fast, Mode::Slow { delay: 10 }],
+                }
+            );
+            Ok(NoneType)
+        }
+
+        fn deserialize_nested(ref x: Value<'v>) -> i32 {
+            let res: Nested = x.deserialize()?;
+            Ok(res.depth())
+        }
+
+        fn deserialize_u8(ref x: Value<'v>) -> i32 {
+            let res: u8 = x.deserialize()?;
+            Ok(res as i32)
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let mut a = Assert::new();
+        a.globals_add(module);
+        a.pass(
+            r#"
+check_config({
+    "name": "server",
+    "port": 8080,
+    "debug": False,
+    "tags": ("a", "b"),
+    "limits": struct(cpu = 4),
+    "owner": None,
+    "pair": [1, "x"],
+    "modes": ["Fast", {"Slow": {"delay": 10}}],
+})
+assert_eq(deserialize_u8(255), 255)
+assert_eq(deserialize_nested([[], [[]]]), 3)
+"#,
+        );
+        a.fail("check_config({'name': 'server'})", "missing field");
+        a.fail("deserialize_u8(256)", "invalid value");
+        a.fail("deserialize_u8('1')", "invalid type");
+        a.fail(
+            "x = []; x.append(x); deserialize_nested(x)",
+            "too deeply nested",
+        );
+        a.fail(
+            "deserialize_u8(len)",
+            "Cannot deserialize a value of type `function`",
+        );
+    }
+}
@@ -17,7 +17,10 @@
 
 //! Define a common set of errors.
 
-use crate::values::{StarlarkValue, Value};
+use crate::{
+    errors::ErrorKind,
+    values::{StarlarkValue, Value},
+};
 use thiserror::Error;
 
 /// Common errors returned by Starlark evaluation.
@@ -53,11 +56,43 @@ pub(crate) enum ControlError {
     NotHashableValue(String),
     #[error("Too many recursion levels")]
     TooManyRecursionLevel,
+    #[error("Function `{0}` called recursively, which is not allowed in this dialect")]
+    RecursionNotAllowed(String),
+    // Records the `ptr_value` of what was mutated, so the iteration can add its location
     #[error("This operation mutate an iterable for an iterator while iterating.")]
-    MutationDuringIteration,
+    MutationDuringIteration(usize),
+    #[error("This operation mutate an iterable for an iterator while iterating. The iteration started at {0}")]
+    MutationDuringIterationStartedAt(String),
+}
+
+impl ControlError {
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Self::NotHashableValue(..) => ErrorKind::Type,
+            Self::TooManyRecursionLevel => ErrorKind::Limit,
+            Self::CannotMutateImmutableValue
+            | Self::RecursionNotAllowed(..)
+            | Self::MutationDuringIteration(..)
+            | Self::MutationDuringIterationStartedAt(..) => ErrorKind::Control,
+        }
+    }
 }
 
 impl ValueError {
+    /// The [`ErrorKind`] of this error, either [`Type`](ErrorKind::Type) or [`Value`](ErrorKind::Value).
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::OperationNotSupported { .. }
+            | Self::OperationNotSupportedBinary { .. }
+            | Self::IncorrectParameterType
+            | Self::IncorrectParameterTypeNamed(..) => ErrorKind::Type,
+            Self::DivisionByZero
+            | Self::IntegerOverflow
+            | Self::IndexOutOfBound(..)
+            | Self::KeyNotFound(..) => ErrorKind::Value,
+        }
+    }
+
     pub(crate) fn unsupported_owned<T>(
         left: &str,
         op: &str,
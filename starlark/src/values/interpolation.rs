@@ -17,17 +17,18 @@
 
 //! String interpolation-related code.
 //! Based on <https://docs.python.org/3/library/stdtypes.html#printf-style-string-formatting>
+//! and <https://docs.python.org/3/library/string.html#format-specification-mini-language>.
 
 use crate::values::{tuple::Tuple, Heap, Value, ValueLike};
 use gazebo::prelude::*;
-use std::{fmt::Write, iter};
+use std::{iter, iter::Peekable};
 use thiserror::Error;
 
-const AFTER_PERCENT: &str = "'%' must be followed by an optional name and a specifier ('s', 'r', 'd', 'i', 'o', 'x', 'X', 'c') or '%'";
+const AFTER_PERCENT: &str = "'%' must be followed by an optional name, optional flags, width and precision, and a specifier ('s', 'r', 'd', 'i', 'o', 'x', 'X', 'c') or '%'";
 
 /// Operator `%` format or evaluation errors
 #[derive(Clone, Dupe, Debug, Error)]
-enum StringInterpolationError {
+pub(crate) enum StringInterpolationError {
     #[error(
         "Unexpected EOF in format string. Could not find ')' when parsing '%(name)f' expression"
     )]
@@ -35,8 +36,8 @@ enum StringInterpolationError {
     /// `%` must be followed by specifier.
     #[error("Unexpected EOF in format string. {}", AFTER_PERCENT)]
     UnexpectedEOFPercent,
-    #[error("Unknown format string specifier '{}'. {}", .0.escape_default(), AFTER_PERCENT)]
-    UnknownSpecifier(char),
+    #[error("Unknown format string specifier '{}' at position {}. {}", .0.escape_default(), .1, AFTER_PERCENT)]
+    UnknownSpecifier(char, usize),
     #[error("Invalid UTF-8 codepoint 0x{:x} passed for %c formatter", .0)]
     ValueNotInUTFRange(u32),
     /// Interpolation parameter is too big for the format string.
@@ -49,6 +50,158 @@ enum StringInterpolationError {
     ValueNotChar,
 }
 
+/// Errors from the format specification mini-language, e.g. the `>10` in `"{:>10}".format(x)`.
+#[derive(Debug, Error)]
+pub(crate) enum FormatSpecError {
+    #[error("Invalid format specifier `{0}`, unexpected `{1}` at position {2}")]
+    Invalid(String, char, usize),
+    #[error("Invalid format specifier `{0}`, expected digits after `.`")]
+    MissingPrecision(String),
+    #[error("Unknown format code '{0}' for value of type '{1}'")]
+    UnknownCode(char, String),
+    #[error("{0} not allowed in {1} format specifier")]
+    NotAllowed(&'static str, &'static str),
+    #[error("'=' alignment not allowed in string format specifier")]
+    AlignNotAllowed,
+    #[error(
+        "Width or precision in format string is too large, the maximum is {}",
+        MAX_WIDTH
+    )]
+    TooLarge,
+}
+
+/// The largest width or precision allowed in a format string, so a typo can't make
+/// formatting try to allocate a huge string.
+const MAX_WIDTH: usize = 10_000;
+
+/// Where a value goes within a field which is wider than the value.
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+    /// Padding goes between the sign (and any `0x` prefix) and the digits
+    AfterSign,
+}
+
+/// When to show the sign of an integer.
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq)]
+enum Sign {
+    /// Only for negative numbers
+    Minus,
+    /// For all numbers
+    Plus,
+    /// A space for positive numbers, a `-` for negative numbers
+    Space,
+}
+
+impl Default for Sign {
+    fn default() -> Self {
+        Sign::Minus
+    }
+}
+
+/// Write `head` then `body` into `out`, padded with `fill` to at least `width` characters.
+fn pad(out: &mut String, head: &str, body: &str, width: usize, fill: char, align: Align) {
+    let len = head.chars().count() + body.chars().count();
+    let n = width.saturating_sub(len);
+    let fill_n = |out: &mut String, n: usize| out.extend(iter::repeat(fill).take(n));
+    match align {
+        Align::Left => {
+            out.push_str(head);
+            out.push_str(body);
+            fill_n(out, n);
+        }
+        Align::Right => {
+            fill_n(out, n);
+            out.push_str(head);
+            out.push_str(body);
+        }
+        Align::Center => {
+            fill_n(out, n / 2);
+            out.push_str(head);
+            out.push_str(body);
+            fill_n(out, n - n / 2);
+        }
+        Align::AfterSign => {
+            out.push_str(head);
+            fill_n(out, n);
+            out.push_str(body);
+        }
+    }
+}
+
+/// How to render an integer.
+struct IntFormat {
+    radix: u32,
+    upper: bool,
+    /// Prefix with `0b`, `0o` or `0x`
+    alternate: bool,
+    sign: Sign,
+    /// Separator between groups of digits, if any
+    grouping: Option<char>,
+    /// Minimum number of digits, padded with zeros
+    min_digits: usize,
+}
+
+impl IntFormat {
+    fn decimal() -> Self {
+        Self {
+            radix: 10,
+            upper: false,
+            alternate: false,
+            sign: Sign::Minus,
+            grouping: None,
+            min_digits: 0,
+        }
+    }
+
+    /// Returns the sign and prefix, then the digits, so padding can go between them.
+    fn render(&self, v: i32) -> (String, String) {
+        let abs = (v as i64).abs() as u64;
+        let mut digits = match self.radix {
+            2 => format!("{:b}", abs),
+            8 => format!("{:o}", abs),
+            16 if self.upper => format!("{:X}", abs),
+            16 => format!("{:x}", abs),
+            _ => abs.to_string(),
+        };
+        if digits.len() < self.min_digits {
+            digits.insert_str(0, &"0".repeat(self.min_digits - digits.len()));
+        }
+        if let Some(sep) = self.grouping {
+            let every = if self.radix == 10 { 3 } else { 4 };
+            let mut grouped = String::with_capacity(digits.len() + digits.len() / every);
+            for (i, c) in digits.chars().enumerate() {
+                if i != 0 && (digits.len() - i) % every == 0 {
+                    grouped.push(sep);
+                }
+                grouped.push(c);
+            }
+            digits = grouped;
+        }
+
+        let mut head = String::new();
+        if v < 0 {
+            head.push('-');
+        } else if self.sign == Sign::Plus {
+            head.push('+');
+        } else if self.sign == Sign::Space {
+            head.push(' ');
+        }
+        if self.alternate {
+            head.push_str(match (self.radix, self.upper) {
+                (2, _) => "0b",
+                (8, _) => "0o",
+                (16, false) => "0x",
+                (16, true) => "0X",
+                _ => "",
+            });
+        }
+        (head, digits)
+    }
+}
+
 /// Format char
 enum ArgFormat {
     // str(x)
@@ -69,66 +222,99 @@ enum ArgFormat {
     Percent,
 }
 
+/// The flags, width and precision of a `%` conversion, e.g. the `-5.2` in `%-5.2s`.
+#[derive(Default)]
+struct Conversion {
+    // `-`, left justify within the width
+    left: bool,
+    // `0`, pad numbers with zeros
+    zero: bool,
+    // `+` or ` `
+    sign: Sign,
+    // `#`, prefix octal and hexadecimal with `0o` and `0x`
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Conversion {
+    fn is_plain(&self) -> bool {
+        self.width == 0 && self.precision.is_none() && self.sign == Sign::Minus && !self.alternate
+    }
+
+    fn string(&self, out: &mut String, x: &str) {
+        let x = match self.precision {
+            Some(p) => match x.char_indices().nth(p) {
+                Some((i, _)) => &x[..i],
+                None => x,
+            },
+            None => x,
+        };
+        let align = if self.left { Align::Left } else { Align::Right };
+        pad(out, "", x, self.width, ' ', align)
+    }
+
+    fn int(&self, out: &mut String, v: i32, radix: u32, upper: bool) {
+        let (head, digits) = IntFormat {
+            radix,
+            upper,
+            alternate: self.alternate,
+            sign: self.sign,
+            grouping: None,
+            min_digits: self.precision.unwrap_or(0),
+        }
+        .render(v);
+        let (fill, align) = match (self.left, self.zero) {
+            (true, _) => (' ', Align::Left),
+            (false, true) => ('0', Align::AfterSign),
+            (false, false) => (' ', Align::Right),
+        };
+        pad(out, &head, &digits, self.width, fill, align)
+    }
+}
+
 impl ArgFormat {
-    fn format_arg(&self, out: &mut String, arg: Value) -> anyhow::Result<()> {
+    fn format_arg(&self, conv: &Conversion, out: &mut String, arg: Value) -> anyhow::Result<()> {
         match self {
             // Equivalent to `write!(out, "{}", arg.to_str()).unwrap()`, but avoid
             // allocating a separate `String` on the way.
-            ArgFormat::Str => match arg.unpack_str() {
-                None => arg.collect_repr(out),
+            ArgFormat::Str if conv.is_plain() => match arg.unpack_str() {
+                None => match arg.get_aref().to_str() {
+                    None => arg.collect_repr(out),
+                    Some(v) => out.push_str(&v),
+                },
                 Some(v) => out.push_str(v),
             },
-            ArgFormat::Repr => arg.collect_repr(out),
-            ArgFormat::Dec => write!(out, "{}", arg.to_int()?).unwrap(),
-            ArgFormat::Oct => {
-                let v = arg.to_int()?;
-                write!(
-                    out,
-                    "{}{:o}",
-                    if v < 0 { "-" } else { "" },
-                    v.wrapping_abs() as u64
-                )
-                .unwrap();
-            }
-            ArgFormat::HexLower => {
-                let v = arg.to_int()?;
-                write!(
-                    out,
-                    "{}{:x}",
-                    if v < 0 { "-" } else { "" },
-                    v.wrapping_abs() as u64
-                )
-                .unwrap();
-            }
-            ArgFormat::HexUpper => {
-                let v = arg.to_int()?;
-                write!(
-                    out,
-                    "{}{:X}",
-                    if v < 0 { "-" } else { "" },
-                    v.wrapping_abs() as u64
-                )
-                .unwrap();
-            }
-            ArgFormat::Char => match arg.unpack_str() {
-                Some(arg) => {
-                    let mut chars = arg.chars();
-                    let c = chars.next();
-                    match c {
-                        Some(c) if chars.next().is_none() => out.push(c),
-                        _ => return Err(StringInterpolationError::ValueNotChar.into()),
+            ArgFormat::Repr if conv.is_plain() => arg.collect_repr(out),
+            ArgFormat::Str => conv.string(out, &arg.to_str()),
+            ArgFormat::Repr => conv.string(out, &arg.to_repr()),
+            ArgFormat::Dec => conv.int(out, arg.to_int()?, 10, false),
+            ArgFormat::Oct => conv.int(out, arg.to_int()?, 8, false),
+            ArgFormat::HexLower => conv.int(out, arg.to_int()?, 16, false),
+            ArgFormat::HexUpper => conv.int(out, arg.to_int()?, 16, true),
+            ArgFormat::Char => {
+                let c = match arg.unpack_str() {
+                    Some(arg) => {
+                        let mut chars = arg.chars();
+                        let c = chars.next();
+                        match c {
+                            Some(c) if chars.next().is_none() => c,
+                            _ => return Err(StringInterpolationError::ValueNotChar.into()),
+                        }
                     }
-                }
-                None => {
-                    let i = arg.to_int()? as u32;
-                    match std::char::from_u32(i) {
-                        Some(c) => write!(out, "{}", c).unwrap(),
-                        None => {
-                            return Err(StringInterpolationError::ValueNotInUTFRange(i).into());
+                    None => {
+                        let i = arg.to_int()? as u32;
+                        match std::char::from_u32(i) {
+                            Some(c) => c,
+                            None => {
+                                return Err(StringInterpolationError::ValueNotInUTFRange(i).into());
+                            }
                         }
                     }
-                }
-            },
+                };
+                let mut buf = [0; 4];
+                conv.string(out, c.encode_utf8(&mut buf));
+            }
             ArgFormat::Percent => {
                 out.push('%');
             }
@@ -152,13 +338,46 @@ pub struct Interpolation {
     /// Number of named arguments
     named_count: usize,
     /// Arguments followed by uninterpreted strings
-    parameters: Vec<(NamedOrPositional, ArgFormat, String)>,
+    parameters: Vec<(NamedOrPositional, ArgFormat, Conversion, String)>,
+}
+
+/// Parse a width or precision, or [`None`] if there are no digits.
+/// Fails if the number is larger than [`MAX_WIDTH`].
+fn parse_digits(
+    chars: &mut Peekable<impl Iterator<Item = (usize, char)>>,
+) -> anyhow::Result<Option<usize>> {
+    let mut res = None;
+    while let Some(d) = chars.peek().and_then(|x| x.1.to_digit(10)) {
+        let x = res
+            .unwrap_or(0usize)
+            .checked_mul(10)
+            .and_then(|x| x.checked_add(d as usize))
+            .filter(|x| *x <= MAX_WIDTH)
+            .ok_or(FormatSpecError::TooLarge)?;
+        res = Some(x);
+        chars.next();
+    }
+    Ok(res)
+}
+
+/// Consume the next character if it matches the predicate.
+fn next_if(
+    chars: &mut Peekable<impl Iterator<Item = (usize, char)>>,
+    f: impl Fn(char) -> bool,
+) -> Option<char> {
+    let c = chars.peek()?.1;
+    if f(c) {
+        chars.next();
+        Some(c)
+    } else {
+        None
+    }
 }
 
 impl Interpolation {
     fn append_literal(&mut self, c: char) {
         if let Some(p) = self.parameters.last_mut() {
-            p.2.push(c);
+            p.3.push(c);
         } else {
             self.init.push(c)
         }
@@ -172,15 +391,13 @@ impl Interpolation {
             named_count: 0,
             parameters: Vec::new(),
         };
-        let mut chars = format.chars();
-        while let Some(c) = chars.next() {
+        let mut chars = format.char_indices().peekable();
+        while let Some((_, c)) = chars.next() {
             if c != '%' {
                 result.append_literal(c);
             } else {
-                let next = chars
-                    .next()
-                    .ok_or(StringInterpolationError::UnexpectedEOFPercent)?;
-                let (named_or_positional, format_char) = if next == '(' {
+                let named_or_positional = if chars.peek().map(|x| x.1) == Some('(') {
+                    chars.next();
                     let mut name = String::new();
                     loop {
                         match chars.next() {
@@ -189,21 +406,39 @@ impl Interpolation {
                                     StringInterpolationError::UnexpectedEOFClosingParen.into()
                                 );
                             }
-                            Some(')') => {
+                            Some((_, ')')) => {
                                 break;
                             }
-                            Some(c) => name.push(c),
+                            Some((_, c)) => name.push(c),
                         }
                     }
-                    (
-                        NamedOrPositional::Named(name),
-                        chars
-                            .next()
-                            .ok_or(StringInterpolationError::UnexpectedEOFPercent)?,
-                    )
+                    NamedOrPositional::Named(name)
                 } else {
-                    (NamedOrPositional::Positional, next)
+                    NamedOrPositional::Positional
                 };
+
+                let mut conv = Conversion::default();
+                while let Some(c) = chars.peek().map(|x| x.1) {
+                    match c {
+                        '-' => conv.left = true,
+                        '0' => conv.zero = true,
+                        '+' => conv.sign = Sign::Plus,
+                        ' ' if conv.sign != Sign::Plus => conv.sign = Sign::Space,
+                        ' ' => {}
+                        '#' => conv.alternate = true,
+                        _ => break,
+                    }
+                    chars.next();
+                }
+                conv.width = parse_digits(&mut chars)?.unwrap_or(0);
+                if chars.peek().map(|x| x.1) == Some('.') {
+                    chars.next();
+                    conv.precision = Some(parse_digits(&mut chars)?.unwrap_or(0));
+                }
+
+                let (pos, format_char) = chars
+                    .next()
+                    .ok_or(StringInterpolationError::UnexpectedEOFPercent)?;
                 let format = match format_char {
                     's' => ArgFormat::Str,
                     'r' => ArgFormat::Repr,
@@ -225,7 +460,7 @@ impl Interpolation {
                             ArgFormat::Percent
                         }
                     },
-                    c => return Err(StringInterpolationError::UnknownSpecifier(c).into()),
+                    c => return Err(StringInterpolationError::UnknownSpecifier(c, pos).into()),
                 };
                 match named_or_positional {
                     NamedOrPositional::Positional => {
@@ -237,7 +472,7 @@ impl Interpolation {
                 }
                 result
                     .parameters
-                    .push((named_or_positional, format, String::new()));
+                    .push((named_or_positional, format, conv, String::new()));
             }
         }
         Ok(result)
@@ -259,7 +494,7 @@ impl Interpolation {
                     None => box iter::once(argument),
                 }
             };
-        for (named_or_positional, format, tail) in self.parameters {
+        for (named_or_positional, format, conv, tail) in self.parameters {
             let arg = match named_or_positional {
                 NamedOrPositional::Positional => match arg_iter.next() {
                     Some(a) => a,
@@ -267,7 +502,7 @@ impl Interpolation {
                 },
                 NamedOrPositional::Named(name) => argument.at(heap.alloc(name), heap)?,
             };
-            format.format_arg(&mut r, arg)?;
+            format.format_arg(&conv, &mut r, arg)?;
             r.push_str(&tail);
         }
 
@@ -278,3 +513,258 @@ impl Interpolation {
         Ok(r)
     }
 }
+
+/// A parsed format specification, the part after the `:` in `"{:>10}".format(x)`.
+/// The syntax is `[[fill]align][sign][#][0][width][grouping][.precision][type]`.
+struct FormatSpec {
+    fill: Option<char>,
+    align: Option<Align>,
+    sign: Option<Sign>,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    grouping: Option<char>,
+    precision: Option<usize>,
+    typ: Option<char>,
+}
+
+impl FormatSpec {
+    fn parse(spec: &str) -> anyhow::Result<Self> {
+        fn align(c: char) -> Option<Align> {
+            match c {
+                '<' => Some(Align::Left),
+                '>' => Some(Align::Right),
+                '^' => Some(Align::Center),
+                '=' => Some(Align::AfterSign),
+                _ => None,
+            }
+        }
+
+        let mut res = FormatSpec {
+            fill: None,
+            align: None,
+            sign: None,
+            alternate: false,
+            zero: false,
+            width: 0,
+            grouping: None,
+            precision: None,
+            typ: None,
+        };
+
+        let mut chars = spec.chars().enumerate().peekable();
+        let mut first_two = spec.chars();
+        match (first_two.next(), first_two.next().and_then(align)) {
+            (Some(fill), Some(a)) => {
+                res.fill = Some(fill);
+                res.align = Some(a);
+                chars.next();
+                chars.next();
+            }
+            (Some(c), _) if align(c).is_some() => {
+                res.align = align(c);
+                chars.next();
+            }
+            _ => {}
+        }
+
+        res.sign = next_if(&mut chars, |c| c == '+' || c == '-' || c == ' ').map(|c| match c {
+            '+' => Sign::Plus,
+            ' ' => Sign::Space,
+            _ => Sign::Minus,
+        });
+        res.alternate = next_if(&mut chars, |c| c == '#').is_some();
+        res.zero = next_if(&mut chars, |c| c == '0').is_some();
+        res.width = parse_digits(&mut chars)?.unwrap_or(0);
+        res.grouping = next_if(&mut chars, |c| c == ',' || c == '_');
+        if next_if(&mut chars, |c| c == '.').is_some() {
+            match parse_digits(&mut chars)? {
+                None => return Err(FormatSpecError::MissingPrecision(spec.to_owned()).into()),
+                p => res.precision = p,
+            }
+        }
+        res.typ = chars.next().map(|x| x.1);
+        if let Some((i, c)) = chars.next() {
+            return Err(FormatSpecError::Invalid(spec.to_owned(), c, i).into());
+        }
+        Ok(res)
+    }
+
+    fn string(&self, out: &mut String, x: &str, typ: &str) -> anyhow::Result<()> {
+        match self.typ {
+            None | Some('s') => {}
+            Some(c) => return Err(FormatSpecError::UnknownCode(c, typ.to_owned()).into()),
+        }
+        if self.sign.is_some() {
+            return Err(FormatSpecError::NotAllowed("Sign", "string").into());
+        }
+        if self.alternate {
+            return Err(FormatSpecError::NotAllowed("Alternate form (#)", "string").into());
+        }
+        if self.grouping.is_some() {
+            return Err(FormatSpecError::NotAllowed("Grouping", "string").into());
+        }
+        let align = match self.align {
+            Some(Align::AfterSign) => return Err(FormatSpecError::AlignNotAllowed.into()),
+            Some(a) => a,
+            None => Align::Left,
+        };
+        let fill = self.fill.unwrap_or(if self.zero && self.align.is_none() {
+            '0'
+        } else {
+            ' '
+        });
+        let x = match self.precision {
+            Some(p) => match x.char_indices().nth(p) {
+                Some((i, _)) => &x[..i],
+                None => x,
+            },
+            None => x,
+        };
+        pad(out, "", x, self.width, fill, align);
+        Ok(())
+    }
+
+    fn int(&self, out: &mut String, v: i32) -> anyhow::Result<()> {
+        let (radix, upper) = match self.typ {
+            None | Some('d') | Some('n') => (10, false),
+            Some('b') => (2, false),
+            Some('o') => (8, false),
+            Some('x') => (16, false),
+            Some('X') => (16, true),
+            Some('c') => {
+                if self.sign.is_some() {
+                    return Err(FormatSpecError::NotAllowed("Sign", "integer 'c'").into());
+                }
+                let c = match std::char::from_u32(v as u32) {
+                    Some(c) => c,
+                    None => {
+                        return Err(StringInterpolationError::ValueNotInUTFRange(v as u32).into());
+                    }
+                };
+                let mut buf = [0; 4];
+                let fill = self.fill.unwrap_or(' ');
+                let align = self.align.unwrap_or(Align::Left);
+                pad(out, "", c.encode_utf8(&mut buf), self.width, fill, align);
+                return Ok(());
+            }
+            Some(c) => return Err(FormatSpecError::UnknownCode(c, "int".to_owned()).into()),
+        };
+        if self.precision.is_some() {
+            return Err(FormatSpecError::NotAllowed("Precision", "integer").into());
+        }
+        let (head, digits) = IntFormat {
+            radix,
+            upper,
+            alternate: self.alternate,
+            sign: self.sign.unwrap_or_default(),
+            grouping: self.grouping,
+            ..IntFormat::decimal()
+        }
+        .render(v);
+        let (fill, align) = match (self.fill, self.align) {
+            (fill, Some(align)) => (fill.unwrap_or(' '), align),
+            (_, None) if self.zero => ('0', Align::AfterSign),
+            (_, None) => (' ', Align::Right),
+        };
+        pad(out, &head, &digits, self.width, fill, align);
+        Ok(())
+    }
+}
+
+/// Format a value according to a format specification, as used by `format`.
+/// If `converted` is set, the value was converted with `!s` or `!r` and is formatted as that string.
+pub(crate) fn format_spec(
+    out: &mut String,
+    spec: &str,
+    value: Value,
+    converted: Option<String>,
+) -> anyhow::Result<()> {
+    if spec.is_empty() {
+        match converted {
+            Some(x) => out.push_str(&x),
+            None => match value.unpack_str() {
+                Some(x) => out.push_str(x),
+                None => value.collect_repr(out),
+            },
+        }
+        return Ok(());
+    }
+    let spec = FormatSpec::parse(spec)?;
+    match converted {
+        Some(x) => spec.string(out, &x, "string"),
+        None => match value.unpack_int() {
+            Some(i) => spec.int(out, i),
+            None => spec.string(out, &value.to_str(), value.get_type()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert;
+
+    #[test]
+    fn test_percent_flags() {
+        assert::all_true(
+            r#"
+"%5s|%-5s|" % ("ab", "cd") == "   ab|cd   |"
+"%.2s" % "abc" == "ab"
+"%5.2r" % "abc" == "   \"a"
+"%05d" % -42 == "-0042"
+"%+d %+d" % (1, -1) == "+1 -1"
+"% d" % 7 == " 7"
+"%.3d" % 5 == "005"
+"%#x %#X %#o" % (255, 255, 8) == "0xff 0XFF 0o10"
+"%-4c|" % "z" == "z   |"
+"%(a)3d" % {"a": 7} == "  7"
+"%%" % () == "%"
+"#,
+        );
+        assert::fail(
+            "'%5q' % 1",
+            "Unknown format string specifier 'q' at position 2",
+        );
+        assert::fail("'%99999999999999999999999s' % 1", "too large");
+        assert::fail("'%.10001s' % 'a'", "too large");
+    }
+
+    #[test]
+    fn test_format_spec() {
+        assert::all_true(
+            r#"
+"{:>6}".format("ab") == "    ab"
+"{:<6}|".format("ab") == "ab    |"
+"{:^6}".format("ab") == "  ab  "
+"{:*^7}".format("ab") == "**ab***"
+"{:6}".format(42) == "    42"
+"{:06}".format(-42) == "-00042"
+"{:=+6}".format(42) == "+   42"
+"{:,}".format(1234567) == "1,234,567"
+"{:_b}".format(255) == "1111_1111"
+"{:#x} {:#o} {:#b} {:X}".format(255, 8, 2, 255) == "0xff 0o10 0b10 FF"
+"{:.2}".format("abc") == "ab"
+"{:c}".format(65) == "A"
+"{!r:>5}".format("a") == "  \"a\""
+"{:5}|".format(True) == "True |"
+"{:>4}".format([1]) == " [1]"
+"#,
+        );
+        assert::fail(
+            "'{:d}'.format('a')",
+            "Unknown format code 'd' for value of type 'string'",
+        );
+        assert::fail(
+            "'{:.2}'.format(1)",
+            "Precision not allowed in integer format specifier",
+        );
+        assert::fail(
+            "'{:+}'.format('a')",
+            "Sign not allowed in string format specifier",
+        );
+        assert::fail("'{:10z1}'.format(1)", "unexpected `1` at position 3");
+        assert::fail("'{:99999999999999999999999}'.format(1)", "too large");
+        assert::fail("'{:.10001}'.format('a')", "too large");
+        assert::eq("len('{:10000}'.format(1))", "10000");
+    }
+}
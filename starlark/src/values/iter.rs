@@ -17,7 +17,7 @@
 
 //! Iterable for Starlark objects.
 
-use crate::values::{Heap, Value};
+use crate::values::{Heap, StarlarkValue, Value};
 use gazebo::cell::ARef;
 
 /// Used in the definition of [`StarlarkValue::iterate`](crate::values::StarlarkValue::iterate).
@@ -31,12 +31,31 @@ pub trait StarlarkIterable<'v> {
 /// Used in the definition of [`StarlarkValue::iterate`](crate::values::StarlarkValue::iterate).
 pub struct RefIterable<'v> {
     heap: &'v Heap,
-    r: ARef<'v, dyn StarlarkIterable<'v>>,
+    r: Iterated<'v>,
+}
+
+// The value being iterated over, which stays borrowed until the iteration finishes.
+enum Iterated<'v> {
+    // From StarlarkValue::iterate
+    Iterable(ARef<'v, dyn StarlarkIterable<'v>>),
+    // From StarlarkValue::iterator
+    Value(ARef<'v, dyn StarlarkValue<'v>>),
 }
 
 impl<'v> RefIterable<'v> {
     pub(crate) fn new(heap: &'v Heap, r: ARef<'v, dyn StarlarkIterable<'v>>) -> Self {
-        RefIterable { heap, r }
+        RefIterable {
+            heap,
+            r: Iterated::Iterable(r),
+        }
+    }
+
+    /// For a value whose [`has_iterator`](StarlarkValue::has_iterator) is `true`.
+    pub(crate) fn new_value(heap: &'v Heap, r: ARef<'v, dyn StarlarkValue<'v>>) -> Self {
+        RefIterable {
+            heap,
+            r: Iterated::Value(r),
+        }
     }
 
     /// Convert a [`RefIterable`] into an actual [`Iterator`].
@@ -44,7 +63,10 @@ impl<'v> RefIterable<'v> {
     where
         'v: 'a,
     {
-        self.r.to_iter(self.heap)
+        match &self.r {
+            Iterated::Iterable(r) => r.to_iter(self.heap),
+            Iterated::Value(r) => r.iterator(self.heap),
+        }
     }
 }
 
@@ -17,15 +17,39 @@
 
 use bumpalo::Bump;
 use gazebo::prelude::*;
-use std::{marker::PhantomData, mem::MaybeUninit, ptr};
+use std::{alloc::Layout, marker::PhantomData, mem, mem::MaybeUninit, ptr};
+
+/// Something stored in an [`Arena`]. Values allocated with [`Arena::alloc_extra`]
+/// are preceded by some extra cells which don't contain a `T`, so must be skipped
+/// when iterating over or dropping the arena.
+pub(crate) trait ArenaItem {
+    /// The number of extra cells before this value.
+    fn extra_cells(&self) -> usize;
+}
+
+/// The number of cells of type `T` required to store `len` values of type `U`.
+pub(crate) fn extra_cells<T, U>(len: usize) -> usize {
+    let bytes = len * mem::size_of::<U>();
+    (bytes + mem::size_of::<T>() - 1) / mem::size_of::<T>()
+}
+
+/// Replace the extra cells holding `len` values of type `U` at `data` with
+/// copies of `x`, once the value that owned them has moved elsewhere.
+/// Afterwards the cells are ordinary values in the arena.
+pub(crate) unsafe fn fill_extra<T, U>(data: *const U, len: usize, x: impl Fn() -> T) {
+    let data = data as *mut T;
+    for i in 0..extra_cells::<T, U>(len) {
+        ptr::write(data.add(i), x());
+    }
+}
 
 #[derive(Default_)]
-pub(crate) struct Arena<T> {
+pub(crate) struct Arena<T: ArenaItem> {
     bump: Bump,
     phantom: PhantomData<T>,
 }
 
-impl<T> Arena<T> {
+impl<T: ArenaItem> Arena<T> {
     pub fn new() -> Self {
         Self {
             bump: Bump::new(),
@@ -42,6 +66,38 @@ impl<T> Arena<T> {
         self.bump.alloc(x)
     }
 
+    /// Allocate a `T` along with the `len` values of type `U` produced by `xs`,
+    /// which are stored in extra cells directly before it. The function `f` is given
+    /// the address of those values, and must produce a `T` whose
+    /// [`extra_cells`](ArenaItem::extra_cells) is [`extra_cells::<T, U>(len)`](extra_cells).
+    #[allow(clippy::mut_from_ref)] // This is fine for arenas
+    pub fn alloc_extra<U>(
+        &self,
+        len: usize,
+        xs: impl IntoIterator<Item = U>,
+        f: impl FnOnce(*const U) -> T,
+    ) -> &mut T {
+        assert!(mem::align_of::<U>() <= mem::align_of::<T>());
+        let extra = extra_cells::<T, U>(len);
+        let layout = Layout::array::<T>(extra + 1).unwrap();
+        let p = self.bump.alloc_layout(layout).as_ptr() as *mut T;
+        unsafe {
+            // The value goes after its extra cells, since we iterate from the end of a chunk.
+            // We write it first, so the extra cells are always skipped, even if `xs` panics.
+            let data = p as *mut U;
+            let res = p.add(extra);
+            ptr::write(res, f(data));
+            let mut n = 0;
+            for x in xs {
+                assert!(n < len, "Arena::alloc_extra given too many values");
+                ptr::write(data.add(n), x);
+                n += 1;
+            }
+            assert_eq!(n, len, "Arena::alloc_extra given too few values");
+            &mut *res
+        }
+    }
+
     fn iter_chunks(&mut self) -> impl Iterator<Item = &[T]> {
         self.bump.iter_allocated_chunks().map(|chunk| {
             // Safe because we only allocate values of type T into the heap, for particular
@@ -59,10 +115,18 @@ impl<T> Arena<T> {
     // there is no spec to the resulting order.
     pub fn for_each<'a>(&'a mut self, mut f: impl FnMut(&'a T)) {
         let chunks = self.iter_chunks().collect::<Vec<_>>();
-        chunks
-            .iter()
-            .rev()
-            .for_each(|xs| xs.iter().rev().for_each(|x| f(x)))
+        chunks.iter().rev().for_each(|xs| for_each_rev(xs, &mut f))
+    }
+}
+
+// Iterate over the values in a chunk from the end, skipping any extra cells.
+fn for_each_rev<'a, T: ArenaItem>(xs: &'a [T], mut f: impl FnMut(&'a T)) {
+    let mut i = xs.len();
+    while i > 0 {
+        i -= 1;
+        let x = &xs[i];
+        i -= x.extra_cells();
+        f(x);
     }
 }
 
@@ -85,14 +149,14 @@ unsafe fn slice_cast<T, U>(e: &[T]) -> &[U] {
     )
 }
 
-impl<T> Drop for Arena<T> {
+impl<T: ArenaItem> Drop for Arena<T> {
     fn drop(&mut self) {
         for chunk in self.iter_chunks() {
-            for x in chunk {
+            for_each_rev(chunk, |x| {
                 // Safe to convert to *mut because we are the only owner
                 let x = x as *const T as *mut T;
                 unsafe { ptr::drop_in_place(x) }
-            }
+            })
         }
     }
 }
@@ -101,6 +165,25 @@ impl<T> Drop for Arena<T> {
 mod test {
     use super::*;
 
+    impl ArenaItem for usize {
+        fn extra_cells(&self) -> usize {
+            0
+        }
+    }
+
+    // Records the values stored in the extra cells before it
+    struct Extra {
+        cells: usize,
+        data: *const u16,
+        len: usize,
+    }
+
+    impl ArenaItem for Extra {
+        fn extra_cells(&self) -> usize {
+            self.cells
+        }
+    }
+
     #[test]
     fn test_arena_iteration() {
         // We want iteration to proceed in the same order as allocation,
@@ -123,4 +206,23 @@ mod test {
         });
         assert_eq!(j, LIMIT);
     }
+
+    #[test]
+    fn test_arena_extra() {
+        let mut x = Arena::new();
+        let mut expect = Vec::new();
+        for i in 0..1000 {
+            let xs = (0..i % 7).map(|j| (i + j) as u16).collect::<Vec<_>>();
+            let len = xs.len();
+            x.alloc_extra(len, xs.iter().copied(), |data| Extra {
+                cells: extra_cells::<Extra, u16>(len),
+                data,
+                len,
+            });
+            expect.push(xs);
+        }
+        let mut seen = Vec::new();
+        x.for_each(|e| seen.push(unsafe { std::slice::from_raw_parts(e.data, e.len) }.to_vec()));
+        assert_eq!(seen, expect);
+    }
 }
@@ -18,27 +18,33 @@
 // Possible optimisations:
 // Preallocate int, none, bool etc slots in Value, so they are shared
 // Encoding none, bool etc in the pointer of frozen value
+// Store the payload inline after a per-value header (a vtable pointer), rather than
+// in a fixed size ValueMem which boxes most values, so Copied/Forward only rewrite the header
 
 use crate::values::{
+    dict::FrozenDict,
     layout::{
-        arena::Arena,
+        arena::{fill_extra, Arena},
         pointer::Pointer,
         thawable_cell::ThawableCell,
         value::{FrozenValue, FrozenValueMem, Value, ValueMem},
         ValueRef,
     },
+    list::FrozenList,
+    tuple::{FrozenTuple, Tuple},
     AllocFrozenValue, ComplexValue, SimpleValue,
 };
 use gazebo::{cast, prelude::*};
 use std::{
     cell::{Cell, RefCell},
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt,
     fmt::{Debug, Formatter},
     hash::{Hash, Hasher},
+    iter,
     ops::Deref,
     ptr,
-    sync::Arc,
+    sync::{Arc, Mutex},
     time::Instant,
 };
 
@@ -47,8 +53,43 @@ use std::{
 pub struct Heap {
     // Should really be ValueMem<'v>, where &'v self
     arena: RefCell<Arena<ValueMem<'static>>>,
+    // Empty buffers used for the slots of function calls, so that each call
+    // can reuse the storage of a previous one rather than allocating.
+    // Always empty, so the lifetime is irrelevant.
+    slots: RefCell<Vec<Vec<ValueRef<'static>>>>,
+    // The live values which have been given a ValueIdentity, keyed by that identity.
+    // These are not GC roots: after a GC they are moved along with the value,
+    // or removed if nothing else kept the value alive.
+    identities: RefCell<HashMap<ValueIdentity, Value<'static>>>,
+    // Map from the ptr_value of each value in `identities` to its identity.
+    identity_index: RefCell<HashMap<usize, ValueIdentity>>,
+    // The identity to give the next value, never reused, even once the value is collected.
+    next_identity: Cell<usize>,
+    // Counts of lists and dicts which share a frozen value until written, see `thaw_on_write_stats`
+    thaw_on_write: Cell<ThawOnWriteStats>,
 }
 
+/// How often lists and dicts on a [`Heap`] shared the storage of a frozen value, rather than
+/// copying it, and how often they then had to copy it after all, see [`Heap::thaw_on_write_stats`].
+///
+/// Sharing happens for list and dict literals whose elements are all constants, and for
+/// `list(x)`, `dict(x)` and `x[:]` when `x` is a frozen list or dict (e.g. one loaded from
+/// another module), or a shared one which hasn't been written. It can be turned off with
+/// [`Evaluator::disable_thaw_on_write`](crate::eval::Evaluator::disable_thaw_on_write).
+#[derive(Debug, Clone, Copy, Dupe, Default, PartialEq, Eq)]
+pub struct ThawOnWriteStats {
+    /// The number of lists and dicts allocated sharing a frozen value.
+    pub shared: usize,
+    /// The number of those which were later mutated, so copied the frozen value.
+    pub thawed: usize,
+}
+
+/// Identifies a [`Value`] on a [`Heap`], remaining the same when garbage collection moves the value,
+/// so suitable as a key in maps maintained by the embedder. Obtained with [`Heap::identity`].
+/// Does not keep the value alive, see [`Heap::lookup_identity`].
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueIdentity(usize);
+
 impl Debug for Heap {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let mut x = f.debug_struct("Heap");
@@ -66,6 +107,17 @@ impl Debug for Heap {
 pub struct FrozenHeap {
     arena: Arena<FrozenValueMem>,          // My memory
     refs: RefCell<HashSet<FrozenHeapRef>>, // Memory I depend on
+    // Strings computed from values on the heap, keyed by ptr_value, see `FrozenHeapRef::cached`.
+    // A Mutex since a FrozenHeapRef is shared between threads.
+    strings: Mutex<HashMap<(usize, CachedString), Arc<str>>>,
+}
+
+/// Which string [`FrozenHeapRef::cached`] is caching.
+#[derive(Clone, Copy, Dupe, PartialEq, Eq, Hash)]
+pub(crate) enum CachedString {
+    Repr,
+    Str,
+    Json,
 }
 
 impl Debug for FrozenHeap {
@@ -104,6 +156,33 @@ impl PartialEq<FrozenHeapRef> for FrozenHeapRef {
 
 impl Eq for FrozenHeapRef {}
 
+impl FrozenHeapRef {
+    /// The string `f` produces for `value`, which must be kept alive by this heap,
+    /// only computing it the first time it is asked for.
+    /// Values are immutable once frozen, so the result can't go stale.
+    pub(crate) fn cached(
+        &self,
+        value: FrozenValue,
+        kind: CachedString,
+        f: impl FnOnce(Value) -> String,
+    ) -> Arc<str> {
+        let value = Value::new_frozen(value);
+        let key = (value.ptr_value(), kind);
+        if let Some(s) = self.0.strings.lock().unwrap().get(&key) {
+            return s.dupe();
+        }
+        // Don't hold the lock while computing, if two threads race they get the same answer
+        let s: Arc<str> = f(value).into();
+        self.0
+            .strings
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(s)
+            .dupe()
+    }
+}
+
 impl FrozenHeap {
     /// Create a new [`FrozenHeap`].
     pub fn new() -> Self {
@@ -124,6 +203,11 @@ impl FrozenHeap {
         self.refs.borrow_mut().get_or_insert_owned(heap);
     }
 
+    /// The number of bytes the [`FrozenHeap`] has reserved for values.
+    pub fn allocated_bytes(&self) -> usize {
+        self.arena.allocated_bytes()
+    }
+
     fn alloc_raw(&self, x: FrozenValueMem) -> FrozenValue {
         let v: &mut FrozenValueMem = self.arena.alloc(x);
         FrozenValue(Pointer::new_ptr1(unsafe { cast::ptr_lifetime(v) }))
@@ -140,16 +224,97 @@ impl FrozenHeap {
     }
 }
 
+/// A table of strings and tuples which have already been frozen, shared between
+/// calls to [`Module::freeze_dedup`](crate::environment::Module::freeze_dedup),
+/// so that structurally equal values freeze to the same [`FrozenValue`].
+///
+/// The table keeps alive every [`FrozenHeap`] it has taken values from, so is best
+/// used for a group of modules with the same lifetime, e.g. one build.
+#[derive(Clone, Dupe, Default)]
+pub struct FrozenDedup(Arc<Mutex<DedupTable<(FrozenValue, FrozenHeapRef)>>>);
+
+impl Debug for FrozenDedup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut x = f.debug_struct("FrozenDedup");
+        if let Ok(table) = self.0.try_lock() {
+            x.field("strings", &table.strings.len());
+            x.field("tuples", &table.tuples.len());
+        }
+        x.finish()
+    }
+}
+
+impl FrozenDedup {
+    /// Create a new, empty, [`FrozenDedup`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Record the values frozen onto `heap`, unless some other heap got there first
+    fn commit(&self, local: DedupTable<FrozenValue>, heap: &FrozenHeapRef) {
+        let mut shared = self.0.lock().unwrap();
+        for (k, v) in local.strings {
+            shared.strings.entry(k).or_insert_with(|| (v, heap.dupe()));
+        }
+        for (k, v) in local.tuples {
+            shared.tuples.entry(k).or_insert_with(|| (v, heap.dupe()));
+        }
+    }
+}
+
+// Ints, bools and None are stored in the pointer, so never need deduplicating.
+// Tuples are keyed by the pointers of their (already deduplicated) elements.
+#[derive(Default_)]
+struct DedupTable<V> {
+    strings: HashMap<Box<str>, V>,
+    tuples: HashMap<Box<[usize]>, V>,
+}
+
+#[derive(Clone, Copy)]
+enum DedupKey<'a> {
+    Str(&'a str),
+    Tuple(&'a [usize]),
+}
+
+impl<V> DedupTable<V> {
+    fn get(&self, key: DedupKey) -> Option<&V> {
+        match key {
+            DedupKey::Str(x) => self.strings.get(x),
+            DedupKey::Tuple(x) => self.tuples.get(x),
+        }
+    }
+
+    fn insert(&mut self, key: DedupKey, v: V) {
+        match key {
+            DedupKey::Str(x) => self.strings.insert(x.into(), v),
+            DedupKey::Tuple(x) => self.tuples.insert(x.into(), v),
+        };
+    }
+}
+
+// The deduplication state of a single freeze
+struct Dedup {
+    shared: FrozenDedup,
+    // Values first frozen onto our own heap. They can only be shared
+    // once we have a FrozenHeapRef, at the end of freezing.
+    local: RefCell<DedupTable<FrozenValue>>,
+}
+
 /// Used to `freeze` values by [`ComplexValue::freeze`].
 // A freezer is a pair of the FrozenHeap and a "magic" value,
 // which we happen to use for the slots (see `FrozenSlotsRef`)
-// but could be used for anything.
-pub struct Freezer(FrozenHeap, FrozenValue);
+// but could be used for anything. If deduplicating, it also
+// has the tables to look up equal values in.
+pub struct Freezer(FrozenHeap, FrozenValue, Option<Dedup>);
 
 impl Freezer {
-    pub(crate) fn new(x: FrozenHeap) -> Self {
+    pub(crate) fn new(x: FrozenHeap, dedup: Option<FrozenDedup>) -> Self {
         let fv = x.alloc_raw(FrozenValueMem::Blackhole);
-        Self(x, fv)
+        let dedup = dedup.map(|shared| Dedup {
+            shared,
+            local: Default::default(),
+        });
+        Self(x, fv, dedup)
     }
 
     pub(crate) fn get_magic(&self) -> FrozenValue {
@@ -163,7 +328,29 @@ impl Freezer {
     }
 
     pub(crate) fn into_ref(self) -> FrozenHeapRef {
-        self.0.into_ref()
+        let heap = self.0.into_ref();
+        if let Some(dedup) = self.2 {
+            dedup.shared.commit(dedup.local.into_inner(), &heap);
+        }
+        heap
+    }
+
+    // Find a previously frozen value equal to `key`, if we are deduplicating
+    fn dedup_get(&self, key: DedupKey) -> Option<FrozenValue> {
+        let dedup = self.2.as_ref()?;
+        if let Some(x) = dedup.local.borrow().get(key) {
+            return Some(*x);
+        }
+        let shared = dedup.shared.0.lock().unwrap();
+        let (x, heap) = shared.get(key)?;
+        self.0.add_reference(heap);
+        Some(*x)
+    }
+
+    fn dedup_insert(&self, key: DedupKey, x: FrozenValue) {
+        if let Some(dedup) = &self.2 {
+            dedup.local.borrow_mut().insert(key, x)
+        }
     }
 
     /// Allocate a new value while freezing. Usually not a great idea.
@@ -189,6 +376,16 @@ impl Freezer {
         // Case 3: We need to be moved to the new heap
         // Invariant: After this method completes ValueMem must be of type Forward
         let value_mut = value as *const ValueMem as *mut ValueMem;
+        match value {
+            ValueMem::Tuple(x) => return self.freeze_tuple(value_mut, x.len()),
+            ValueMem::Str(x) => {
+                if let Some(fv) = self.dedup_get(DedupKey::Str(x)) {
+                    unsafe { ptr::replace(value_mut, ValueMem::Forward(fv)) };
+                    return fv;
+                }
+            }
+            _ => {}
+        }
         let fvmem: &mut FrozenValueMem = self.0.arena.alloc(FrozenValueMem::Blackhole);
         let fv = FrozenValue(Pointer::new_ptr1(unsafe { cast::ptr_lifetime(fvmem) }));
         // Important we allocate the location for the frozen value _before_ we copy it
@@ -196,7 +393,10 @@ impl Freezer {
         let v = unsafe { ptr::replace(value_mut, ValueMem::Forward(fv)) };
 
         match v {
-            ValueMem::Str(i) => *fvmem = FrozenValueMem::Str(i),
+            ValueMem::Str(i) => {
+                self.dedup_insert(DedupKey::Str(&i), fv);
+                *fvmem = FrozenValueMem::Str(i)
+            }
             ValueMem::Simple(x) => *fvmem = FrozenValueMem::Simple(x),
             ValueMem::Immutable(x) => *fvmem = FrozenValueMem::Simple(x.freeze(self)),
             ValueMem::Mutable(x) => *fvmem = FrozenValueMem::Simple(x.into_inner().freeze(self)),
@@ -211,6 +411,43 @@ impl Freezer {
         }
         fv
     }
+
+    // Move a tuple from the heap, along with its elements
+    fn freeze_tuple(&self, value_mut: *mut ValueMem, len: usize) -> FrozenValue {
+        // Fill the elements with None, so the tuple is valid while we freeze them
+        let nones = iter::repeat(FrozenValue::new_none()).take(len);
+        let fvmem = self.0.arena.alloc_extra(len, nones, |p| {
+            FrozenValueMem::Tuple(unsafe { FrozenTuple::from_raw(len, p) })
+        });
+        let fv = FrozenValue(Pointer::new_ptr1(unsafe { cast::ptr_lifetime(fvmem) }));
+        // As with other values, we must be a Forward before freezing our elements,
+        // so that cycles still work
+        let v = unsafe { ptr::replace(value_mut, ValueMem::Forward(fv)) };
+        match (v, fvmem) {
+            (ValueMem::Tuple(x), FrozenValueMem::Tuple(frozen)) => {
+                let frozen = unsafe { frozen.content_mut() };
+                for (i, v) in x.content().iter().enumerate() {
+                    frozen[i] = self.freeze(*v);
+                }
+                // The old elements are no longer needed, and mustn't be skipped any more
+                unsafe { fill_extra(x.content().as_ptr(), len, || ValueMem::Blackhole) };
+                if self.2.is_some() {
+                    let key = frozen.map(|x| x.0.ptr_value());
+                    match self.dedup_get(DedupKey::Tuple(&key)) {
+                        Some(found) => {
+                            // Anything which referred to us while freezing our elements
+                            // keeps our copy, but everything after can share
+                            unsafe { ptr::write(value_mut, ValueMem::Forward(found)) };
+                            return found;
+                        }
+                        None => self.dedup_insert(DedupKey::Tuple(&key), fv),
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        fv
+    }
 }
 
 impl Heap {
@@ -231,10 +468,73 @@ impl Heap {
         }
     }
 
-    pub(crate) fn allocated_bytes(&self) -> usize {
+    /// The number of bytes the [`Heap`] has reserved for values, which is at least
+    /// the size of the values currently allocated. Only reduced by garbage collection.
+    pub fn allocated_bytes(&self) -> usize {
         self.arena().borrow().allocated_bytes()
     }
 
+    /// Obtain `len` unassigned slots for a function call, reusing a buffer
+    /// previously returned by [`free_slots`](Heap::free_slots) if there is one.
+    pub(crate) fn alloc_slots<'v>(&'v self, len: usize) -> Vec<ValueRef<'v>> {
+        let xs = self.slots.borrow_mut().pop().unwrap_or_default();
+        // Safe because the buffer is empty, so contains no values of the wrong lifetime
+        let mut xs = unsafe { transmute!(Vec<ValueRef<'static>>, Vec<ValueRef<'v>>, xs) };
+        xs.resize(len, ValueRef::new_unassigned());
+        xs
+    }
+
+    /// The [`ValueIdentity`] of a value on this heap. Calling this again with the same value,
+    /// including after it has been moved by garbage collection, returns the same identity.
+    pub fn identity<'v>(&'v self, x: Value<'v>) -> ValueIdentity {
+        let mut index = self.identity_index.borrow_mut();
+        *index.entry(x.ptr_value()).or_insert_with(|| {
+            let id = ValueIdentity(self.next_identity.get());
+            self.next_identity.set(id.0 + 1);
+            self.identities
+                .borrow_mut()
+                .insert(id, unsafe { transmute!(Value<'v>, Value<'static>, x) });
+            id
+        })
+    }
+
+    /// The [`ValueIdentity`] previously given to a value by [`identity`](Heap::identity), if any.
+    pub(crate) fn existing_identity<'v>(&'v self, x: Value<'v>) -> Option<ValueIdentity> {
+        self.identity_index.borrow().get(&x.ptr_value()).copied()
+    }
+
+    /// The value with a given [`ValueIdentity`], or [`None`] if that value has since
+    /// been garbage collected.
+    pub fn lookup_identity<'v>(&'v self, id: ValueIdentity) -> Option<Value<'v>> {
+        let x = *self.identities.borrow().get(&id)?;
+        Some(unsafe { transmute!(Value<'static>, Value<'v>, x) })
+    }
+
+    // After a GC has copied the live values, point each identity at the new location,
+    // or remove it if the value wasn't copied. Must run before the old arena is freed.
+    fn update_identities(&self) {
+        let mut identities = self.identities.borrow_mut();
+        let mut index = self.identity_index.borrow_mut();
+        index.clear();
+        identities.retain(|id, v| {
+            match v.0.unpack_ptr2() {
+                None => {} // Not on this heap, so didn't move
+                Some(ValueMem::Copied(new)) => *v = *new,
+                Some(_) => return false,
+            }
+            index.insert(v.ptr_value(), *id);
+            true
+        });
+    }
+
+    /// Return the slots of a finished function call, so a later call can reuse them.
+    pub(crate) fn free_slots<'v>(&'v self, mut xs: Vec<ValueRef<'v>>) {
+        xs.clear();
+        // Safe because we just emptied the buffer
+        let xs = unsafe { transmute!(Vec<ValueRef<'v>>, Vec<ValueRef<'static>>, xs) };
+        self.slots.borrow_mut().push(xs);
+    }
+
     pub(crate) fn alloc_raw<'v>(&'v self, v: ValueMem<'v>) -> Value<'v> {
         let arena_ref = self.arena().borrow_mut();
         let arena = &*arena_ref;
@@ -251,6 +551,26 @@ impl Heap {
         self.alloc_raw(ValueMem::Str(x))
     }
 
+    /// Allocate the concatenation of `parts` as a string, e.g. for `a + b + c`,
+    /// without allocating the intermediate `a + b`.
+    pub fn alloc_str_concat<'v>(&'v self, parts: &[&str]) -> Value<'v> {
+        self.alloc_str(parts.concat().into_boxed_str())
+    }
+
+    /// Allocate a tuple on the [`Heap`]. The elements are stored in the same
+    /// allocation as the tuple itself, rather than in a separate [`Vec`].
+    pub fn alloc_tuple<'v>(&'v self, elems: &[Value<'v>]) -> Value<'v> {
+        let arena_ref = self.arena().borrow_mut();
+        let arena = &*arena_ref;
+        // See alloc_raw for why this is safe
+        let arena = unsafe { transmute!(&Arena<ValueMem<'v>>, &'v Arena<ValueMem<'v>>, arena) };
+        let len = elems.len();
+        let mem = arena.alloc_extra(len, elems.iter().copied(), |p| {
+            ValueMem::Tuple(unsafe { Tuple::from_raw(len, p) })
+        });
+        Value(Pointer::new_ptr2(mem))
+    }
+
     /// Allocate a [`SimpleValue`] on the [`Heap`].
     pub fn alloc_simple<'v>(&'v self, x: impl SimpleValue) -> Value<'v> {
         self.alloc_raw(ValueMem::Simple(box x))
@@ -258,9 +578,43 @@ impl Heap {
 
     /// Invariant: Must be called on Dict or List
     pub(crate) fn alloc_thaw_on_write<'v>(&'v self, x: FrozenValue) -> Value<'v> {
+        let mut stats = self.thaw_on_write.get();
+        stats.shared += 1;
+        self.thaw_on_write.set(stats);
         self.alloc_raw(ValueMem::ThawOnWrite(ThawableCell::new(x)))
     }
 
+    /// A copy of the list or dict `x` which shares its storage until it is written, if `x` is
+    /// frozen, or is itself sharing a frozen value and hasn't been written.
+    pub(crate) fn alloc_copy_on_write<'v>(&'v self, x: Value<'v>) -> Option<Value<'v>> {
+        let fv = match x.0.unpack_ptr2() {
+            Some(ValueMem::ThawOnWrite(state)) => state.get_value().unpack_frozen()?,
+            Some(_) => return None,
+            None => x.unpack_frozen()?,
+        };
+        if FrozenList::from_frozen_value(&fv).is_some()
+            || FrozenDict::from_frozen_value(&fv).is_some()
+        {
+            Some(self.alloc_thaw_on_write(fv))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn record_thaw(&self) {
+        let mut stats = self.thaw_on_write.get();
+        stats.thawed += 1;
+        self.thaw_on_write.set(stats);
+    }
+
+    /// How many lists and dicts have been allocated on this heap sharing a frozen value, and
+    /// how many of those were then copied because they were written. If nearly all of them
+    /// are copied, sharing is only adding overhead, so consider
+    /// [`Evaluator::disable_thaw_on_write`](crate::eval::Evaluator::disable_thaw_on_write).
+    pub fn thaw_on_write_stats(&self) -> ThawOnWriteStats {
+        self.thaw_on_write.get()
+    }
+
     /// Allocate a [`ComplexValue`] on the [`Heap`].
     pub fn alloc_complex<'v>(&'v self, x: impl ComplexValue<'v>) -> Value<'v> {
         self.alloc_complex_box(box x)
@@ -316,6 +670,7 @@ impl Heap {
             arena: Arena::new(),
         };
         f(&walker);
+        self.update_identities();
         *arena = walker.arena;
     }
 }
@@ -371,14 +726,19 @@ impl<'v> Walker<'v> {
         // Case 3: We need to be moved to the new heap
         // Invariant: After this method completes ValueMem must be of type Copied
         let old_mem = old_val as *const ValueMem<'v> as *mut ValueMem<'v>;
-        // We know the arena we are allocating into will live for 'v
-        let new_mem = unsafe {
-            transmute!(
-                &mut ValueMem<'v>,
-                &'v mut ValueMem<'v>,
-                self.arena.alloc(ValueMem::Blackhole)
-            )
+        // Tuples are moved along with their elements, which are then walked in place
+        let new_mem = match old_val {
+            ValueMem::Tuple(x) => {
+                let len = x.len();
+                let elems = x.content().iter().copied();
+                self.arena.alloc_extra(len, elems, |p| {
+                    ValueMem::Tuple(unsafe { Tuple::from_raw(len, p) })
+                })
+            }
+            _ => self.arena.alloc(ValueMem::Blackhole),
         };
+        // We know the arena we are allocating into will live for 'v
+        let new_mem = unsafe { transmute!(&mut ValueMem<'v>, &'v mut ValueMem<'v>, new_mem) };
         let mut new_val: Value<'v> = Value(Pointer::new_ptr2(new_mem));
         if value.0.get_user_tag() {
             // SUPER IMPORTANT:
@@ -388,6 +748,15 @@ impl<'v> Walker<'v> {
 
         let mut old_mem = unsafe { ptr::replace(old_mem, ValueMem::Copied(new_val)) };
 
+        if let (ValueMem::Tuple(old), ValueMem::Tuple(new)) = (&old_mem, &*new_mem) {
+            unsafe {
+                // The old elements are no longer needed, and mustn't be skipped any more
+                fill_extra(old.content().as_ptr(), old.len(), || ValueMem::Blackhole);
+                new.walk(self);
+            }
+            return new_val;
+        }
+
         match &mut old_mem {
             ValueMem::Ref(x) => self.walk_cell(x),
             ValueMem::Mutable(x) => unsafe {
@@ -412,3 +781,39 @@ where
     FrozenHeapRef: Send + Sync,
 {
 }
+
+#[test]
+fn test_value_identity() {
+    let heap = Heap::new();
+    let mut kept = heap.alloc("kept");
+    let dropped = heap.alloc("dropped");
+    let id = heap.identity(kept);
+    let id_dropped = heap.identity(dropped);
+    assert_eq!(heap.identity(kept), id);
+    assert_ne!(id, id_dropped);
+
+    unsafe { heap.garbage_collect(|walker| walker.walk(&mut kept)) };
+    assert_eq!(heap.identity(kept), id);
+    assert_eq!(heap.lookup_identity(id).unwrap().unpack_str(), Some("kept"));
+    assert!(heap.lookup_identity(id_dropped).is_none());
+    // Identities aren't reused once the value they named is gone
+    assert_eq!(heap.identities.borrow().len(), 1);
+    assert_ne!(heap.identity(heap.alloc("new")), id_dropped);
+}
+
+#[test]
+fn test_allocated_bytes() {
+    let heap = Heap::new();
+    let before = heap.allocated_bytes();
+    for i in 0..10000 {
+        heap.alloc(i.to_string());
+    }
+    assert!(heap.allocated_bytes() > before);
+
+    let frozen = FrozenHeap::new();
+    let before = frozen.allocated_bytes();
+    for i in 0..10000 {
+        frozen.alloc(i.to_string());
+    }
+    assert!(frozen.allocated_bytes() > before);
+}
@@ -28,7 +28,11 @@ mod thawable_cell;
 mod value;
 
 pub use constant::ConstFrozenValue;
-pub use heap::{Freezer, FrozenHeap, FrozenHeapRef, Heap, Walker};
+pub(crate) use heap::CachedString;
+pub use heap::{
+    Freezer, FrozenDedup, FrozenHeap, FrozenHeapRef, Heap, ThawOnWriteStats, ValueIdentity, Walker,
+};
 pub(crate) use pointer_i32::PointerI32;
+pub use profiling::FunctionProfile;
 pub(crate) use value::ValueRef;
 pub use value::{FrozenValue, Value};
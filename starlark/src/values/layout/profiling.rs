@@ -176,6 +176,8 @@ impl Info {
                 self.info[name.0].time_rec =
                     time_rec + now.checked_duration_since(start).unwrap_or_default();
             }
+            // Left behind in the extra cells of a value that has since moved
+            ValueMem::Blackhole => {}
             _ => {
                 // For references, the type they point at isn't held by this object, but another heap value.
                 // Therefore the true cost is just the reference itself.
@@ -189,10 +191,22 @@ impl Info {
     }
 }
 
+/// The time spent in a single function, as reported by [`Heap::profile_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// The function, as shown by `str` (e.g. `file.bzl.f(x, y = ...)`).
+    pub name: String,
+    /// Number of times the function was called.
+    pub calls: usize,
+    /// Time spent directly in this function, excluding the functions it called.
+    pub self_time: Duration,
+    /// Time spent in this function, including the functions it called.
+    pub total_time: Duration,
+}
+
 impl Heap {
-    /// Write a profile (as a `.csv` file) to a file.
-    /// Only works if [`enable_profiling`](crate::eval::Evaluator::enable_profiling) was called before execution began.
-    pub fn write_profile<P: AsRef<Path>>(&self, file: P) -> io::Result<()> {
+    // Replay the profiling records from the heap, in the order they happened
+    fn gather_profile(&self) -> Info {
         let mut ids = FunctionIds::default();
         let root = ids.get_string("(root)".to_owned());
         let start = Instant::now();
@@ -202,9 +216,40 @@ impl Heap {
             last_changed: start,
             call_stack: vec![(root, Duration::default(), start)],
         };
+        info.ensure(root);
         self.for_each(|x| info.process(x));
         // Just has root left on it
         assert!(info.call_stack.len() == 1);
+        info
+    }
+
+    /// Aggregate the time spent in each function that was called, most expensive
+    /// (by [`self_time`](FunctionProfile::self_time)) first.
+    /// Only works if [`enable_profiling`](crate::eval::Evaluator::enable_profiling) was called before execution began,
+    /// otherwise the result will be empty.
+    pub fn profile_report(&self) -> Vec<FunctionProfile> {
+        let info = self.gather_profile();
+        let names = info.ids.invert();
+        let mut res = info
+            .info
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.calls > 0)
+            .map(|(i, x)| FunctionProfile {
+                name: names[i].to_owned(),
+                calls: x.calls,
+                self_time: x.time,
+                total_time: x.time_rec,
+            })
+            .collect::<Vec<_>>();
+        res.sort_by(|a, b| b.self_time.cmp(&a.self_time));
+        res
+    }
+
+    /// Write a profile (as a `.csv` file) to a file.
+    /// Only works if [`enable_profiling`](crate::eval::Evaluator::enable_profiling) was called before execution began.
+    pub fn write_profile<P: AsRef<Path>>(&self, file: P) -> io::Result<()> {
+        let mut info = self.gather_profile();
 
         // Add a totals column
         let total_id = info.ids.get_string("TOTALS".to_owned());
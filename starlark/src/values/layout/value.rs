@@ -31,12 +31,14 @@
 
 use crate::values::{
     layout::{
+        arena::{extra_cells, ArenaItem},
         heap::{Freezer, Heap},
         pointer::{Pointer, PointerUnpack},
         pointer_i32::PointerI32,
         thawable_cell::ThawableCell,
     },
     none::NoneType,
+    tuple::{FrozenTuple, Tuple},
     ComplexValue, ControlError, SimpleValue, StarlarkValue,
 };
 use either::Either;
@@ -99,6 +101,8 @@ pub(crate) enum FrozenValueMem {
     Uninitialized(Void), // Never created (see Value::Uninitialized)
     Blackhole, // Only occurs during a GC
     Str(Box<str>),
+    // The elements are stored in the extra cells before it
+    Tuple(FrozenTuple),
     Simple(Box<dyn SimpleValue>),
 }
 
@@ -128,6 +132,8 @@ pub(crate) enum ValueMem<'v> {
     Blackhole,
     // A literal string
     Str(Box<str>),
+    // The elements are stored in the extra cells before it
+    Tuple(Tuple<'v>),
     // Things that aren't mutable and don't point to other Value's
     Simple(Box<dyn SimpleValue>),
     // Mutable things in my heap that aren't `is_mutable()`
@@ -145,6 +151,24 @@ pub(crate) enum ValueMem<'v> {
     CallExit(Instant),
 }
 
+impl ArenaItem for ValueMem<'_> {
+    fn extra_cells(&self) -> usize {
+        match self {
+            Self::Tuple(x) => extra_cells::<Self, Value>(x.len()),
+            _ => 0,
+        }
+    }
+}
+
+impl ArenaItem for FrozenValueMem {
+    fn extra_cells(&self) -> usize {
+        match self {
+            Self::Tuple(x) => extra_cells::<Self, FrozenValue>(x.len()),
+            _ => 0,
+        }
+    }
+}
+
 impl<'v> ValueMem<'v> {
     pub fn unexpected(&self, method: &str) -> ! {
         panic!(
@@ -176,19 +200,49 @@ impl<'v> ValueMem<'v> {
         }
     }
 
-    fn get_ref_mut(&self, heap: &'v Heap) -> anyhow::Result<RefMut<dyn ComplexValue<'v>>> {
+    // Like get_ref_mut_already, but fails if the value is mutable but already borrowed,
+    // e.g. because it is being iterated over
+    fn get_ref_mut_already_checked(
+        &self,
+        id: usize,
+    ) -> anyhow::Result<Option<RefMut<dyn ComplexValue<'v>>>> {
+        match self {
+            Self::Mutable(x) => match x.try_borrow_mut() {
+                Err(_) => Err(ControlError::MutationDuringIteration(id).into()),
+                Ok(state) => Ok(Some(RefMut::map(state, |x| &mut **x))),
+            },
+            Self::ThawOnWrite(state) => match state.get_thawed() {
+                Some(v) => match v.0.unpack_ptr2() {
+                    Some(x) => x.get_ref_mut_already_checked(id),
+                    None => Ok(None),
+                },
+                None => Ok(None),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    // The `id` is the `ptr_value` of the value being mutated, reported in errors
+    fn get_ref_mut(
+        &self,
+        heap: &'v Heap,
+        id: usize,
+    ) -> anyhow::Result<RefMut<dyn ComplexValue<'v>>> {
         match self {
             Self::Mutable(x) => match x.try_borrow_mut() {
                 // Could be called by something else having the ref locked, but iteration is
                 // definitely most likely
-                Err(_) => Err(ControlError::MutationDuringIteration.into()),
+                Err(_) => Err(ControlError::MutationDuringIteration(id).into()),
                 Ok(state) => Ok(RefMut::map(state, |x| &mut **x)),
             },
             Self::ThawOnWrite(state) => match state.get_thawed() {
-                Some(v) => v.get_ref_mut(heap),
+                Some(v) => v.get_ref_mut_id(heap, id),
                 None => match state.thaw(|fv| heap.alloc_complex_box(fv.thaw())) {
-                    None => Err(ControlError::MutationDuringIteration.into()),
-                    Some(v) => v.get_ref_mut(heap),
+                    None => Err(ControlError::MutationDuringIteration(id).into()),
+                    Some(v) => {
+                        heap.record_thaw();
+                        v.get_ref_mut_id(heap, id)
+                    }
                 },
             },
             _ => Err(ControlError::CannotMutateImmutableValue.into()),
@@ -199,6 +253,7 @@ impl<'v> ValueMem<'v> {
         match self {
             Self::Forward(x) => Some(x.get_ref()),
             Self::Str(x) => Some(x),
+            Self::Tuple(x) => Some(x),
             Self::Simple(x) => Some(simple_starlark_value(Box::as_ref(x))),
             Self::Immutable(x) => Some(x.as_starlark_value()),
             Self::Mutable(_) => None,
@@ -211,6 +266,7 @@ impl<'v> ValueMem<'v> {
         match self {
             Self::Forward(x) => ARef::Ptr(x.get_ref()),
             Self::Str(x) => ARef::Ptr(x),
+            Self::Tuple(x) => ARef::Ptr(x),
             Self::Simple(x) => ARef::Ptr(simple_starlark_value(Box::as_ref(x))),
             Self::Immutable(x) => ARef::Ptr(x.as_starlark_value()),
             Self::Mutable(x) => ARef::Ref(Ref::map(x.borrow(), |x| x.as_starlark_value())),
@@ -242,6 +298,7 @@ impl FrozenValueMem {
     fn get_ref<'v>(&self) -> &dyn StarlarkValue<'v> {
         match self {
             Self::Str(x) => x,
+            Self::Tuple(x) => x,
             Self::Simple(x) => simple_starlark_value(Box::as_ref(x)),
             _ => self.unexpected("get_ref"),
         }
@@ -358,12 +415,31 @@ impl<'v> Value<'v> {
         self.0.unpack_ptr2().and_then(|x| x.get_ref_mut_already())
     }
 
+    // Like get_ref_mut_already, but fails if the value is mutable but already borrowed
+    pub(crate) fn get_ref_mut_already_checked(
+        self,
+    ) -> anyhow::Result<Option<RefMut<'v, dyn ComplexValue<'v>>>> {
+        match self.0.unpack_ptr2() {
+            Some(x) => x.get_ref_mut_already_checked(self.ptr_value()),
+            None => Ok(None),
+        }
+    }
+
     pub(crate) fn get_ref_mut(
         self,
         heap: &'v Heap,
+    ) -> anyhow::Result<RefMut<'v, dyn ComplexValue<'v>>> {
+        self.get_ref_mut_id(heap, self.ptr_value())
+    }
+
+    // Like get_ref_mut, but errors report the value being mutated as `id`
+    fn get_ref_mut_id(
+        self,
+        heap: &'v Heap,
+        id: usize,
     ) -> anyhow::Result<RefMut<'v, dyn ComplexValue<'v>>> {
         if let Some(x) = self.0.unpack_ptr2() {
-            return x.get_ref_mut(heap);
+            return x.get_ref_mut(heap, id);
         }
         Err(ControlError::CannotMutateImmutableValue.into())
     }
@@ -481,6 +557,11 @@ impl<'v> ValueRef<'v> {
         Self(Cell::new(Value::new_frozen(x)))
     }
 
+    pub fn new(x: Value<'v>) -> Self {
+        assert!(!x.0.is_unassigned());
+        Self(Cell::new(x))
+    }
+
     pub fn set(&self, value: Value<'v>) {
         assert!(!value.0.is_unassigned());
         self.get_cell().set(value);
@@ -28,31 +28,44 @@
 //!   trait.
 //! * All the nested modules represent the built-in Starlark values. These are all defined using [`StarlarkValue`],
 //!   so may serve as interesting inspiration for writing your own values, in addition to occuring in Starlark programs.
-pub use crate::values::{error::*, iter::*, layout::*, owned::*, traits::*, types::*, unpack::*};
+pub use crate::values::{
+    attrs::*, error::*, iter::*, layout::*, owned::*, pretty::*, traits::*, types::*, unpack::*,
+};
 use crate::{
+    codemap::SpanLoc,
     collections::{Hashed, SmallHashResult},
+    eval::{
+        def::{Def, FrozenDef},
+        Parameter,
+    },
     values::types::function::FunctionInvoker,
 };
 pub use gazebo::{any::AnyLifetime, cell::ARef};
 use indexmap::Equivalent;
+use serde::Deserialize;
 use std::{
     cell::RefMut,
     cmp::Ordering,
     fmt,
     fmt::{Debug, Display},
 };
+pub(crate) use typing::TypingError;
 
 #[macro_use]
 mod comparison;
 
 // Submodules
+mod attrs;
+mod deserialize;
 mod error;
 pub(crate) mod fast_string;
 mod index;
-mod interpolation;
+pub(crate) mod interpolation;
 mod iter;
 mod layout;
 mod owned;
+mod pretty;
+mod repr;
 mod traits;
 mod types;
 mod typing;
@@ -137,6 +150,33 @@ pub trait AllocFrozenValue {
     fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue;
 }
 
+/// Trait for things that can be converted to a [`Value`], but where the conversion may fail,
+/// e.g. a `u64` which is too large for a Starlark int. Implemented for everything that implements
+/// [`AllocValue`], and used to convert the result of a
+/// [`#[starlark_module]`](macro@starlark_module) function.
+pub trait IntoValue<'v> {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>>;
+}
+
+impl<'v, T: AllocValue<'v>> IntoValue<'v> for T {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(self.alloc_value(heap))
+    }
+}
+
+/// Trait for things that can be converted to a [`FrozenValue`], but where the conversion may fail,
+/// the counterpart of [`IntoValue`] for a [`FrozenHeap`]. Implemented for everything that
+/// implements [`AllocFrozenValue`].
+pub trait IntoFrozenValue {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue>;
+}
+
+impl<T: AllocFrozenValue> IntoFrozenValue for T {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        Ok(self.alloc_frozen_value(heap))
+    }
+}
+
 impl FrozenHeap {
     /// Allocate a new value on a [`FrozenHeap`].
     pub fn alloc<T: AllocFrozenValue>(&self, val: T) -> FrozenValue {
@@ -168,7 +208,7 @@ pub trait ValueLike<'v>: Eq + Copy + Debug {
     }
 
     fn get_hash(self) -> anyhow::Result<u64> {
-        self.get_aref().get_hash()
+        comparison::hash_value(self.to_value())
     }
 
     fn get_hashed(self) -> anyhow::Result<Hashed<Self>> {
@@ -179,25 +219,42 @@ pub trait ValueLike<'v>: Eq + Copy + Debug {
     }
 
     fn collect_repr(self, collector: &mut String) {
-        self.get_aref().collect_repr(collector);
+        repr::collect_repr(self.to_value(), collector)
+    }
+
+    fn collect_json(self, collector: &mut String) {
+        self.get_aref().collect_json(collector)
     }
 
     fn to_json(self) -> String {
-        self.get_aref().to_json()
+        let mut s = String::new();
+        self.collect_json(&mut s);
+        s
     }
 
-    fn equals(self, other: Value<'v>) -> anyhow::Result<bool> {
+    fn collect_proto(
+        self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        // Lists can contain themselves, so guard against unbounded recursion
         let _guard = crate::eval::call_stack::try_inc()?;
-        if self.to_value().ptr_eq(other) {
-            Ok(true)
-        } else {
-            self.get_aref().equals(other)
+        self.get_aref().collect_proto(name, indent, collector)
+    }
+
+    fn equals(self, other: Value<'v>) -> anyhow::Result<bool> {
+        // Ints are common dict keys, and can't recurse, so skip the depth check
+        if let Some(x) = self.to_value().unpack_int() {
+            return Ok(other.unpack_int() == Some(x));
         }
+        let _guard = crate::eval::call_stack::try_inc()?;
+        comparison::equals_value(self.to_value(), other)
     }
 
     fn compare(self, other: Value<'v>) -> anyhow::Result<Ordering> {
         let _guard = crate::eval::call_stack::try_inc()?;
-        self.get_aref().compare(other)
+        comparison::compare_value(self.to_value(), other)
     }
 
     fn downcast_ref<T: AnyLifetime<'v>>(self) -> Option<ARef<'v, T>> {
@@ -275,11 +332,48 @@ impl<'v> Value<'v> {
     /// otherwise uses `repr()`.
     pub fn to_str(self) -> String {
         match self.unpack_str() {
-            None => self.to_repr(),
+            None => self.get_aref().to_str().unwrap_or_else(|| self.to_repr()),
             Some(s) => s.to_owned(),
         }
     }
 
+    /// Convert the value to any Rust type implementing [`Deserialize`]. Dicts and structs
+    /// become maps (or Rust structs), lists and tuples become sequences, and strings, ints,
+    /// bools and `None` become the corresponding primitive values. An enum is given either
+    /// as a string naming the variant, or as a dict with a single key naming the variant.
+    ///
+    /// ```
+    /// # fn run() -> anyhow::Result<()> {
+    /// use serde::Deserialize;
+    /// use starlark::environment::{Globals, Module};
+    /// use starlark::eval::Evaluator;
+    /// use starlark::syntax::{AstModule, Dialect};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Config {
+    ///     name: String,
+    ///     ports: Vec<u16>,
+    /// }
+    ///
+    /// let content = r#"
+    /// PORT = 8080
+    /// {"name": "server", "ports": [PORT, PORT + 1]}
+    /// "#;
+    /// let ast = AstModule::parse("config.star", content.to_owned(), &Dialect::Standard)?;
+    /// let globals = Globals::standard();
+    /// let module = Module::new();
+    /// let mut eval = Evaluator::new(&module, &globals);
+    /// let config: Config = eval.eval_module(ast)?.deserialize()?;
+    /// assert_eq!(config.name, "server");
+    /// assert_eq!(config.ports, vec![8080, 8081]);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn deserialize<T: Deserialize<'v>>(self) -> anyhow::Result<T> {
+        deserialize::deserialize(self)
+    }
+
     /// Implement the `repr()` function.
     pub fn to_repr(self) -> String {
         let mut s = String::new();
@@ -287,6 +381,35 @@ impl<'v> Value<'v> {
         s
     }
 
+    /// Like [`to_repr`](Value::to_repr), but bounded in size, so safe to use on huge values.
+    /// Once about `max_len` bytes have been produced, further nested values are shown as `...`,
+    /// and the result is truncated to `max_len` bytes followed by `...`.
+    pub fn to_repr_limited(self, max_len: usize) -> String {
+        repr::to_repr_limited(self, max_len)
+    }
+
+    /// Like [`to_repr`](Value::to_repr), but laid out over multiple lines to fit in a width,
+    /// with nested lists, tuples, dicts and structs indented, as configured by `config`.
+    pub fn to_repr_pretty(self, config: &PrettyConfig) -> String {
+        pretty::to_repr_pretty(self, config)
+    }
+
+    /// The [`to_repr`](Value::to_repr) of a value, bounded for use in error messages.
+    pub(crate) fn to_repr_for_error(self) -> String {
+        self.to_repr_limited(repr::ERROR_REPR_LEN)
+    }
+
+    /// The [`to_str`](Value::to_str) of a value, bounded for use in error messages.
+    pub(crate) fn to_str_for_error(self) -> String {
+        match self.unpack_str() {
+            None => match self.get_aref().to_str() {
+                None => self.to_repr_for_error(),
+                Some(s) => repr::truncate(s, repr::ERROR_REPR_LEN),
+            },
+            Some(s) => repr::truncate(s.to_owned(), repr::ERROR_REPR_LEN),
+        }
+    }
+
     /// Forwards to [`ComplexValue::set_attr`].
     pub fn set_attr(
         self,
@@ -318,6 +441,9 @@ impl<'v> Value<'v> {
     /// Produce an iterable from a value.
     pub fn iterate(self, heap: &'v Heap) -> anyhow::Result<RefIterable<'v>> {
         let me: ARef<'v, dyn StarlarkValue> = self.get_aref();
+        if me.has_iterator() {
+            return Ok(RefIterable::new_value(heap, me));
+        }
         me.iterate()?;
         Ok(RefIterable::new(
             heap,
@@ -387,6 +513,30 @@ impl<'v> Value<'v> {
         }
     }
 
+    /// The signature of the function, e.g. `f(x, y = ...)`, if this value is a function
+    /// that knows its parameters. Returns [`None`] for all other values.
+    pub fn signature(self) -> Option<String> {
+        self.get_aref().signature()
+    }
+
+    /// The parameters of the function, if this value is a function that knows its parameters.
+    /// Returns [`None`] for all other values.
+    pub fn parameters(self) -> Option<Vec<Parameter<'v>>> {
+        self.get_aref().parameters()
+    }
+
+    /// Where the function was defined, if this value is a function written in Starlark
+    /// with `def` or `lambda`. Returns [`None`] for all other values, including native functions.
+    pub fn definition_location(self) -> Option<SpanLoc> {
+        if let Some(x) = self.downcast_ref::<Def<'v>>() {
+            Some(x.location())
+        } else if let Some(x) = self.downcast_ref::<FrozenDef>() {
+            Some(x.location())
+        } else {
+            None
+        }
+    }
+
     /// Call `export_as` on the underlying value, but only if the type is mutable.
     /// Otherwise, does nothing.
     pub fn export_as(self, name: &str, heap: &'v Heap) {
@@ -435,6 +585,8 @@ impl<'v> Value<'v> {
             aref.dir_attr()
         };
         result.sort();
+        // A member may share a name with an attribute, but only occurs once
+        result.dedup();
         result
     }
 }
@@ -469,6 +621,10 @@ impl<'v> Value<'v> {
     }
 
     pub fn is_in(self, other: Value<'v>) -> anyhow::Result<bool> {
+        // Substring tests are common, so avoid the dynamic dispatch
+        if let (Some(s), Some(needle)) = (self.unpack_str(), other.unpack_str()) {
+            return Ok(s.contains(needle));
+        }
         self.get_aref().is_in(other)
     }
 
@@ -480,31 +636,80 @@ impl<'v> Value<'v> {
         self.get_aref().minus(heap)
     }
 
+    pub fn bit_not(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.get_aref().bit_not(heap)
+    }
+
+    /// Subtract `other` from this [`Value`]. Will first try using [`rsub`](StarlarkValue::rsub),
+    /// before falling back to [`sub`](StarlarkValue::sub).
     pub fn sub(self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
-        self.get_aref().sub(other, heap)
+        if let Some(v) = other.get_aref().rsub(self, heap) {
+            v
+        } else {
+            self.get_aref().sub(other, heap)
+        }
     }
 
+    /// Multiply two [`Value`]s together. Will first try using [`rmul`](StarlarkValue::rmul),
+    /// before falling back to [`mul`](StarlarkValue::mul).
     pub fn mul(self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
-        self.get_aref().mul(other, heap)
+        if let Some(v) = other.get_aref().rmul(self, heap) {
+            v
+        } else {
+            self.get_aref().mul(other, heap)
+        }
     }
 
+    /// Apply the `%` operator to two [`Value`]s. Will first try using [`rpercent`](StarlarkValue::rpercent),
+    /// before falling back to [`percent`](StarlarkValue::percent).
     pub fn percent(self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
-        self.get_aref().percent(other, heap)
+        if let Some(v) = other.get_aref().rpercent(self, heap) {
+            v
+        } else {
+            self.get_aref().percent(other, heap)
+        }
     }
 
+    /// Floor divide this [`Value`] by `other`. Will first try using [`rfloor_div`](StarlarkValue::rfloor_div),
+    /// before falling back to [`floor_div`](StarlarkValue::floor_div).
     pub fn floor_div(self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
-        self.get_aref().floor_div(other, heap)
+        if let Some(v) = other.get_aref().rfloor_div(self, heap) {
+            v
+        } else {
+            self.get_aref().floor_div(other, heap)
+        }
     }
 
+    /// Bitwise `&` of two [`Value`]s. Will first try using [`rbit_and`](StarlarkValue::rbit_and),
+    /// before falling back to [`bit_and`](StarlarkValue::bit_and).
     pub fn bit_and(self, other: Value<'v>) -> anyhow::Result<Value<'v>> {
-        self.get_aref().bit_and(other)
+        if let Some(v) = other.get_aref().rbit_and(self) {
+            v
+        } else {
+            self.get_aref().bit_and(other)
+        }
     }
+
+    /// Bitwise `|` of two [`Value`]s. Will first try using [`rbit_or`](StarlarkValue::rbit_or),
+    /// before falling back to [`bit_or`](StarlarkValue::bit_or).
     pub fn bit_or(self, other: Value<'v>) -> anyhow::Result<Value<'v>> {
-        self.get_aref().bit_or(other)
+        if let Some(v) = other.get_aref().rbit_or(self) {
+            v
+        } else {
+            self.get_aref().bit_or(other)
+        }
     }
+
+    /// Bitwise `^` of two [`Value`]s. Will first try using [`rbit_xor`](StarlarkValue::rbit_xor),
+    /// before falling back to [`bit_xor`](StarlarkValue::bit_xor).
     pub fn bit_xor(self, other: Value<'v>) -> anyhow::Result<Value<'v>> {
-        self.get_aref().bit_xor(other)
+        if let Some(v) = other.get_aref().rbit_xor(self) {
+            v
+        } else {
+            self.get_aref().bit_xor(other)
+        }
     }
+
     pub fn left_shift(self, other: Value<'v>) -> anyhow::Result<Value<'v>> {
         self.get_aref().left_shift(other)
     }
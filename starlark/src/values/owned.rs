@@ -17,10 +17,12 @@
 
 use crate::{
     environment::Module,
-    values::{AllocFrozenValue, FrozenHeap, FrozenHeapRef, FrozenValue, Value},
+    values::{
+        AllocFrozenValue, CachedString, FrozenHeap, FrozenHeapRef, FrozenValue, Value, ValueLike,
+    },
 };
 use gazebo::prelude::*;
-use std::{fmt, fmt::Display};
+use std::{fmt, fmt::Display, sync::Arc};
 
 /// A [`FrozenValue`] along with a [`FrozenHeapRef`] that ensures it is kept alive.
 ///
@@ -96,4 +98,27 @@ impl OwnedFrozenValue {
     pub fn value<'v>(&'v self) -> Value<'v> {
         Value::new_frozen(self.value)
     }
+
+    /// The [`to_repr`](Value::to_repr) of the value, computed the first time it is asked for,
+    /// then stored alongside the heap which owns the value, so later calls
+    /// (on any [`OwnedFrozenValue`] for the same value and heap) are cheap.
+    /// The cached strings are only freed along with the heap.
+    pub fn to_repr_cached(&self) -> Arc<str> {
+        self.owner
+            .cached(self.value, CachedString::Repr, |x| x.to_repr())
+    }
+
+    /// Like [`to_repr_cached`](OwnedFrozenValue::to_repr_cached), but for
+    /// [`to_str`](Value::to_str).
+    pub fn to_str_cached(&self) -> Arc<str> {
+        self.owner
+            .cached(self.value, CachedString::Str, |x| x.to_str())
+    }
+
+    /// Like [`to_repr_cached`](OwnedFrozenValue::to_repr_cached), but for
+    /// [`to_json`](ValueLike::to_json).
+    pub fn to_json_cached(&self) -> Arc<str> {
+        self.owner
+            .cached(self.value, CachedString::Json, |x| x.to_json())
+    }
 }
@@ -0,0 +1,243 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Pretty printing of values over multiple lines, see [`Value::to_repr_pretty`].
+
+use crate::values::{dict::Dict, list::List, repr, structs::Struct, tuple::Tuple, Value};
+use gazebo::prelude::*;
+
+/// How [`Value::to_repr_pretty`] lays out a value.
+#[derive(Debug, Clone, Copy, Dupe)]
+pub struct PrettyConfig {
+    /// The width, in bytes, lines should fit in. A list, tuple, dict or struct which doesn't
+    /// fit on the rest of its line is split with one element per line. Other values are never
+    /// split, so may still go over. Defaults to 80.
+    pub width: usize,
+    /// The number of spaces to indent each level of nesting by. Defaults to 4.
+    pub indent: usize,
+    /// If set, lists, tuples, dicts and structs nested more deeply than this are shown
+    /// with their contents as `...`, e.g. `[...]`. Defaults to [`None`].
+    pub max_depth: Option<usize>,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 4,
+            max_depth: None,
+        }
+    }
+}
+
+// The containers we know how to split: the opening and closing brackets, and each
+// element preceded by its key, if any (e.g. `"a": ` for a dict).
+struct Container<'v> {
+    open: &'static str,
+    close: &'static str,
+    items: Vec<(String, Value<'v>)>,
+    // Tuples of one element need a trailing comma
+    single_comma: bool,
+}
+
+impl<'v> Container<'v> {
+    fn new(v: Value<'v>) -> Option<Self> {
+        let (open, close, items) = if let Some(x) = List::from_value(v) {
+            ("[", "]", x.iter().map(|x| (String::new(), x)).collect())
+        } else if let Some(x) = Tuple::from_value(v) {
+            ("(", ")", x.iter().map(|x| (String::new(), x)).collect())
+        } else if let Some(x) = Dict::from_value(v) {
+            let items = x.iter().map(|(k, v)| (format!("{}: ", k.to_repr()), v));
+            ("{", "}", items.collect())
+        } else if let Some(x) = Struct::from_value(v) {
+            let items = x.fields.iter().map(|(k, v)| (format!("{}=", k), *v));
+            ("struct(", ")", items.collect())
+        } else {
+            return None;
+        };
+        Some(Self {
+            open,
+            close,
+            single_comma: open == "(" && items.len() == 1,
+            items,
+        })
+    }
+}
+
+struct Printer<'a> {
+    config: &'a PrettyConfig,
+    // The ptr_value of the containers being printed, so we can spot cycles
+    stack: Vec<usize>,
+}
+
+impl Printer<'_> {
+    fn too_deep(&self, depth: usize) -> bool {
+        self.config.max_depth.map_or(false, |x| depth > x)
+    }
+
+    // Append `v` on one line, returning `false` if `out` would grow longer than `max_len`,
+    // in which case `out` is left partially written.
+    fn flat(&mut self, v: Value, depth: usize, max_len: usize, out: &mut String) -> bool {
+        let p = v.ptr_value();
+        match Container::new(v) {
+            None => match repr::to_repr_within(v, max_len.saturating_sub(out.len())) {
+                None => return false,
+                Some(s) => out.push_str(&s),
+            },
+            Some(_) if self.stack.contains(&p) => v.get_aref().collect_repr_cycle(out),
+            Some(x) if self.too_deep(depth) => {
+                out.push_str(x.open);
+                out.push_str("...");
+                out.push_str(x.close);
+            }
+            Some(x) => {
+                self.stack.push(p);
+                out.push_str(x.open);
+                for (i, (key, v)) in x.items.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(key);
+                    if out.len() > max_len || !self.flat(*v, depth + 1, max_len, out) {
+                        self.stack.pop();
+                        return false;
+                    }
+                }
+                if x.single_comma {
+                    out.push(',');
+                }
+                out.push_str(x.close);
+                self.stack.pop();
+            }
+        }
+        out.len() <= max_len
+    }
+
+    // Append `v`, starting at the current end of `out`, where the lines are indented by `indent`,
+    // and `v` will be followed by `suffix` bytes on the same line.
+    fn pretty(&mut self, v: Value, depth: usize, indent: usize, suffix: usize, out: &mut String) {
+        let column = out.len() - out.rfind('\n').map_or(0, |x| x + 1);
+        let mut line = String::new();
+        let max_len = self.config.width.saturating_sub(column + suffix);
+        if self.flat(v, depth, max_len, &mut line) {
+            out.push_str(&line);
+            return;
+        }
+
+        let p = v.ptr_value();
+        match Container::new(v) {
+            Some(x) if !x.items.is_empty() && !self.stack.contains(&p) && !self.too_deep(depth) => {
+                self.stack.push(p);
+                let inner = indent + self.config.indent;
+                out.push_str(x.open);
+                for (key, v) in &x.items {
+                    out.push('\n');
+                    out.extend(std::iter::repeat(' ').take(inner));
+                    out.push_str(key);
+                    self.pretty(*v, depth + 1, inner, 1, out);
+                    out.push(',');
+                }
+                out.push('\n');
+                out.extend(std::iter::repeat(' ').take(indent));
+                out.push_str(x.close);
+                self.stack.pop();
+            }
+            // Too long, but can't be split, so has to go on one line anyway
+            _ => {
+                self.flat(v, depth, usize::MAX, out);
+            }
+        }
+    }
+}
+
+pub(crate) fn to_repr_pretty(v: Value, config: &PrettyConfig) -> String {
+    let mut printer = Printer {
+        config,
+        stack: Vec::new(),
+    };
+    let mut out = String::new();
+    printer.pretty(v, 0, 0, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert;
+
+    fn pretty(program: &str, config: PrettyConfig) -> String {
+        assert::pass(program).value().to_repr_pretty(&config)
+    }
+
+    fn width(width: usize) -> PrettyConfig {
+        PrettyConfig {
+            width,
+            ..PrettyConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_pretty() {
+        let x = "[[1, 2, 3], ('a', 1), (4,)]";
+        assert_eq!(pretty(x, width(80)), "[[1, 2, 3], (\"a\", 1), (4,)]");
+        assert_eq!(
+            pretty(x, width(20)),
+            "[\n    [1, 2, 3],\n    (\"a\", 1),\n    (4,),\n]"
+        );
+        assert_eq!(
+            pretty(x, width(10)),
+            "[\n    [\n        1,\n        2,\n        3,\n    ],\n    (\n        \"a\",\n        1,\n    ),\n    (4,),\n]"
+        );
+        // Values which can't be split go over the width
+        assert_eq!(pretty("'long string'", width(5)), "\"long string\"");
+        assert_eq!(pretty("[]", width(1)), "[]");
+    }
+
+    #[test]
+    fn test_pretty_dict_struct() {
+        let x = "{'a': struct(b = [1, 2], c = {}), 'd': None}";
+        assert_eq!(
+            pretty(x, width(32)),
+            "{\n    \"a\": struct(b=[1, 2], c={}),\n    \"d\": None,\n}"
+        );
+        let config = PrettyConfig {
+            width: 20,
+            indent: 2,
+            max_depth: None,
+        };
+        assert_eq!(
+            pretty(x, config),
+            "{\n  \"a\": struct(\n    b=[1, 2],\n    c={},\n  ),\n  \"d\": None,\n}"
+        );
+    }
+
+    #[test]
+    fn test_pretty_depth_and_cycles() {
+        let depth = |max_depth| PrettyConfig {
+            max_depth: Some(max_depth),
+            ..PrettyConfig::default()
+        };
+        let x = "[[1, [2]], {'a': (3,)}, 4]";
+        assert_eq!(pretty(x, depth(0)), "[[...], {...}, 4]");
+        assert_eq!(pretty(x, depth(1)), "[[1, [...]], {\"a\": (...)}, 4]");
+        assert_eq!(pretty(x, depth(2)), "[[1, [2]], {\"a\": (3,)}, 4]");
+
+        let x = "x = [1]\nx.append(x)\nx";
+        assert_eq!(pretty(x, width(80)), "[1, [...]]");
+        assert_eq!(pretty(x, width(5)), "[\n    1,\n    [...],\n]");
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Guards used while producing the `repr` of a value.
+//!
+//! Every nested value goes through [`collect_repr`], which keeps track of the values
+//! currently being printed, so that cyclic values (common after freezing, e.g. a list
+//! appended to itself) don't recurse forever, and shows values nested very deeply as
+//! `...`, so they don't overflow the stack. When the `repr` is bounded, e.g. for an
+//! error message, it also stops early once a length budget is used up.
+
+use crate::values::{StarlarkValue, Value};
+use std::cell::RefCell;
+
+// Values nested deeper than this are shown as `...`.
+const MAX_REPR_DEPTH: usize = 1000;
+
+/// The length budget used for values mentioned in error messages.
+pub(crate) const ERROR_REPR_LEN: usize = 1000;
+
+#[derive(Default)]
+struct ReprState {
+    // The ptr_value of the values being printed, innermost last.
+    stack: Vec<usize>,
+    // Once the collector reaches this length, stop printing nested values.
+    limit: Option<usize>,
+}
+
+thread_local!(static REPR_STATE: RefCell<ReprState> = RefCell::new(ReprState::default()));
+
+struct StackGuard;
+
+impl Drop for StackGuard {
+    fn drop(&mut self) {
+        REPR_STATE.with(|x| x.borrow_mut().stack.pop());
+    }
+}
+
+struct LimitGuard(Option<usize>);
+
+impl Drop for LimitGuard {
+    fn drop(&mut self) {
+        REPR_STATE.with(|x| x.borrow_mut().limit = self.0);
+    }
+}
+
+enum Next {
+    Repr(StackGuard),
+    Cycle,
+    Elide,
+}
+
+fn push(v: Value, len: usize) -> Next {
+    REPR_STATE.with(|x| {
+        let mut state = x.borrow_mut();
+        let p = v.ptr_value();
+        if state.stack.len() >= MAX_REPR_DEPTH || state.limit.map_or(false, |limit| len >= limit) {
+            Next::Elide
+        } else if state.stack.contains(&p) {
+            Next::Cycle
+        } else {
+            state.stack.push(p);
+            Next::Repr(StackGuard)
+        }
+    })
+}
+
+pub(crate) fn collect_repr(v: Value, collector: &mut String) {
+    match push(v, collector.len()) {
+        Next::Repr(_guard) => v.get_aref().collect_repr(collector),
+        Next::Cycle => v.get_aref().collect_repr_cycle(collector),
+        Next::Elide => collector.push_str("..."),
+    }
+}
+
+pub(crate) fn to_repr_limited(v: Value, max_len: usize) -> String {
+    let mut s = String::new();
+    {
+        let _guard = LimitGuard(REPR_STATE.with(|x| x.borrow_mut().limit.replace(max_len)));
+        collect_repr(v, &mut s);
+    }
+    truncate(s, max_len)
+}
+
+/// The `repr` of `v`, or [`None`] if it is longer than `max_len` bytes.
+/// Gives up as soon as the limit is passed, so is cheap even on huge values.
+pub(crate) fn to_repr_within(v: Value, max_len: usize) -> Option<String> {
+    let mut s = String::new();
+    {
+        let limit = max_len.saturating_add(1);
+        let _guard = LimitGuard(REPR_STATE.with(|x| x.borrow_mut().limit.replace(limit)));
+        collect_repr(v, &mut s);
+    }
+    if s.len() <= max_len {
+        Some(s)
+    } else {
+        None
+    }
+}
+
+/// Cut `s` down to at most `max_len` bytes (rounded down to a character boundary),
+/// followed by `...` if anything was removed.
+pub(crate) fn truncate(mut s: String, max_len: usize) -> String {
+    if s.len() > max_len {
+        let mut end = max_len;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push_str("...");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assert, values::Heap};
+
+    #[test]
+    fn test_repr_limited() {
+        let heap = Heap::new();
+        let xs = heap.alloc((0..1000).collect::<Vec<i32>>());
+        let s = xs.to_repr_limited(20);
+        assert_eq!(s, "[0, 1, 2, 3, 4, 5, 6...");
+        assert_eq!(heap.alloc(vec![1, 2]).to_repr_limited(20), "[1, 2]");
+
+        assert::eq("str([[[1]]])", "'[[[1]]]'");
+        assert::eq("x = [1]\nx.append(x)\nrepr(x)", "'[1, [...]]'");
+
+        // Deeply nested values which aren't cycles are shown in full
+        let mut x = heap.alloc(Vec::<i32>::new());
+        for _ in 0..300 {
+            x = heap.alloc(vec![x]);
+        }
+        assert_eq!(
+            x.to_repr(),
+            format!("{}{}", "[".repeat(301), "]".repeat(301))
+        );
+
+        // Up to a depth limit, even without a length limit
+        for _ in 0..1000 {
+            x = heap.alloc(vec![x]);
+        }
+        assert_eq!(
+            x.to_repr(),
+            format!("{}...{}", "[".repeat(1000), "]".repeat(1000))
+        );
+    }
+}
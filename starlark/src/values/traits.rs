@@ -30,9 +30,10 @@
 //! hold several values.
 use crate::{
     environment::Globals,
+    eval::{Evaluator, Parameter, ParametersParser, ParametersSpec},
     values::{
-        function::FunctionInvoker, ConstFrozenValue, ControlError, Freezer, Heap, StarlarkIterable,
-        Value, ValueError, Walker,
+        function::FunctionInvoker, ConstFrozenValue, ControlError, Freezer, FrozenValue, Heap,
+        StarlarkIterable, Value, ValueError, Walker,
     },
 };
 use gazebo::any::AnyLifetime;
@@ -185,6 +186,11 @@ pub trait ComplexValue<'v>: StarlarkValue<'v> {
 
     /// Freeze a value. The frozen value _must_ be equal to the original,
     /// and produce the same hash.
+    ///
+    /// Cycles are handled by the [`Freezer`]: if this value is reachable from
+    /// its own fields, [`Freezer::freeze`] on that field returns the (not yet filled in)
+    /// frozen version of this value. As a result, values returned by [`Freezer::freeze`]
+    /// must be stored, but not inspected, during `freeze`.
     fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue>;
 
     /// Called by the garbage collection, and must walk over every contained `Value` in the type.
@@ -217,6 +223,16 @@ pub trait ComplexValue<'v>: StarlarkValue<'v> {
     fn set_attr(&mut self, attribute: &str, _new_value: Value<'v>) -> anyhow::Result<()> {
         ValueError::unsupported(self, &format!(".{}=", attribute))
     }
+
+    /// Implement `x += other` by mutating the current value in place, in the way
+    /// that `list` does. Return [`None`] (the default) to fall back to `x = x + other`,
+    /// which is also used if the value is frozen or is `other`. If the value is being
+    /// iterated over, `x += other` fails, as it does for a list.
+    /// While this method runs the current value is mutably borrowed, so `other` must
+    /// not be inspected in a way that reaches back to the current value.
+    fn add_assign(&mut self, _other: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<()>> {
+        None
+    }
 }
 
 /// A trait representing Starlark values which are simple - they
@@ -312,6 +328,19 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
         false
     }
 
+    /// The signature of a function type, as shown by its `repr`, e.g. `f(x, y = ...)`.
+    /// Defaults to the signature of [`invoke_parameters`](StarlarkValue::invoke_parameters), if any,
+    /// otherwise [`None`], which is also appropriate for functions that don't know their parameters.
+    fn signature(&self) -> Option<String> {
+        self.invoke_parameters().map(|x| x.signature())
+    }
+
+    /// The parameters of a function type, see [`ParametersSpec::parameters`].
+    /// Defaults to those of [`invoke_parameters`](StarlarkValue::invoke_parameters), if any.
+    fn parameters(&self) -> Option<Vec<Parameter<'v>>> {
+        self.invoke_parameters().map(|x| x.parameters())
+    }
+
     /// Is this value a match for a named type. Usually returns `true` for
     /// values matching `get_type`, but might also work for subtypes it implements.
     fn matches_type(&self, ty: &str) -> bool {
@@ -322,7 +351,8 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
     /// These members will have `dir`/`getattr`/`hasattr` properly implemented,
     /// so it is the preferred way to go if possible. See
     /// [`GlobalsStatic`](crate::environment::GlobalsStatic) for an example of how
-    /// to define this method.
+    /// to define this method, or [`#[starlark_methods]`](macro@starlark_methods)
+    /// to generate the members from an `impl` of the type.
     fn get_members(&self) -> Option<&'static Globals> {
         None
     }
@@ -351,16 +381,55 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
     /// repr([]) == '[]'
     /// # "#);
     /// ```
+    ///
+    /// Values which can be called using [`invoke`](StarlarkValue::invoke) default to
+    /// showing their signature, like native functions, e.g. `f(x, y = ...)`.
     fn collect_repr(&self, collector: &mut String) {
-        // Rust won't return Err when writing to a String, so safe unwrap
-        write!(collector, "{:?}", self).unwrap()
+        match self.invoke_parameters() {
+            Some(parameters) => parameters.collect_repr(collector),
+            // Rust won't return Err when writing to a String, so safe unwrap
+            None => write!(collector, "{:?}", self).unwrap(),
+        }
     }
 
-    /// Convert the type to a JSON string.
-    fn to_json(&self) -> String {
+    /// The result of `str()` on this value, if it differs from its `repr()`, e.g. a value
+    /// whose `repr()` is the call that creates it. Defaults to [`None`], so `str()` is the
+    /// same as `repr()`, as it is for all the built-in types other than strings.
+    fn to_str(&self) -> Option<String> {
+        None
+    }
+
+    /// Used in place of [`collect_repr`](StarlarkValue::collect_repr) when this value
+    /// is nested inside itself, e.g. a list which has been appended to itself.
+    /// Defaults to `...`.
+    fn collect_repr_cycle(&self, collector: &mut String) {
+        collector.push_str("...");
+    }
+
+    /// Append the JSON of this value to `collector`, as used by `struct.to_json()`
+    /// and [`ValueLike::to_json`](crate::values::ValueLike::to_json).
+    /// Containers should write their elements with
+    /// [`ValueLike::collect_json`](crate::values::ValueLike::collect_json),
+    /// so large nested values are written in one pass without building
+    /// a string for each element. Like [`collect_repr`](StarlarkValue::collect_repr)
+    /// this writes to a [`String`], which can't fail, rather than a general
+    /// [`fmt::Write`](std::fmt::Write). Panics by default.
+    fn collect_json(&self, _collector: &mut String) {
         panic!("unsupported for type {}", self.get_type())
     }
 
+    /// Write the value as the field `name` of a message in protocol buffer
+    /// text format, as used by `struct.to_proto()`, indenting each line by
+    /// `indent` spaces. Repeated fields (e.g. from a list) write one entry per element.
+    fn collect_proto(
+        &self,
+        _name: &str,
+        _indent: usize,
+        _collector: &mut String,
+    ) -> anyhow::Result<()> {
+        ValueError::unsupported(self, "to_proto()")
+    }
+
     /// Convert self to a boolean, as returned by the bool() function.
     /// The default implementation returns [`true`].
     fn to_bool(&self) -> bool {
@@ -407,13 +476,93 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
         ValueError::unsupported_with(self, "compare", other)
     }
 
+    /// The parameters taken when this value is called like a function, e.g. `x(1, y = 2)`.
+    /// Returning [`Some`] makes the value callable: the arguments at each call are matched
+    /// against the [`ParametersSpec`], supporting positional, named, `*args` and `**kwargs`
+    /// arguments and reporting any mismatch, before being passed to
+    /// [`invoke`](StarlarkValue::invoke). Defaults to [`None`], meaning the value can't be called.
+    ///
+    /// A call shows up in the call stack and in profiles as the `repr` of the value,
+    /// which defaults to the signature of these parameters. Since the parameters outlive
+    /// any single heap, default values must be [`FrozenValue`]s.
+    ///
+    /// ```
+    /// # fn main() {
+    /// use starlark::assert::Assert;
+    /// use starlark::eval::{Evaluator, ParametersParser, ParametersSpec};
+    /// use starlark::values::{FrozenValue, StarlarkValue, Value};
+    /// use starlark::{starlark_simple_value, starlark_type};
+    ///
+    /// #[derive(Debug)]
+    /// struct Adder {
+    ///     parameters: ParametersSpec<FrozenValue>,
+    /// }
+    /// starlark_simple_value!(Adder);
+    ///
+    /// impl<'v> StarlarkValue<'v> for Adder {
+    ///     starlark_type!("adder");
+    ///
+    ///     fn invoke_parameters(&self) -> Option<&ParametersSpec<FrozenValue>> {
+    ///         Some(&self.parameters)
+    ///     }
+    ///
+    ///     fn invoke(
+    ///         &self,
+    ///         _me: Value<'v>,
+    ///         mut args: ParametersParser<'v, '_>,
+    ///         eval: &mut Evaluator<'v, '_>,
+    ///     ) -> anyhow::Result<Value<'v>> {
+    ///         let x: i32 = args.next("x", eval.heap())?;
+    ///         let y: Option<i32> = args.next_opt("y", eval.heap())?;
+    ///         Ok(Value::new_int(x + y.unwrap_or(1)))
+    ///     }
+    /// }
+    ///
+    /// let mut parameters = ParametersSpec::new("add".to_owned());
+    /// parameters.required("x");
+    /// parameters.optional("y");
+    /// let mut a = Assert::new();
+    /// a.globals_add(|builder| builder.set("add", Adder { parameters }));
+    /// a.all_true(r#"
+    /// add(1) == 2
+    /// add(1, y = 2) == 3
+    /// add(*[1, 3]) == 4
+    /// add(**{"x": 2}) == 3
+    /// repr(add) == "add(x, y = ...)"
+    /// "#);
+    /// # }
+    /// ```
+    fn invoke_parameters(&self) -> Option<&ParametersSpec<FrozenValue>> {
+        None
+    }
+
+    /// Call this value, with the arguments collected according to
+    /// [`invoke_parameters`](StarlarkValue::invoke_parameters), which are consumed
+    /// from `args` in order, as in [`#[starlark_module]`](macro@crate::starlark_module).
+    /// `me` is this value. Only called if `invoke_parameters` returns [`Some`].
+    fn invoke(
+        &self,
+        _me: Value<'v>,
+        _args: ParametersParser<'v, '_>,
+        _eval: &mut Evaluator<'v, '_>,
+    ) -> anyhow::Result<Value<'v>> {
+        ValueError::unsupported(self, "call()")
+    }
+
     /// Create a [`FunctionInvoker`] for this object, allowing it to be invoked.
+    /// Defaults to calling [`invoke`](StarlarkValue::invoke) for values with
+    /// [`invoke_parameters`](StarlarkValue::invoke_parameters), and otherwise fails.
+    /// Usually only overridden by values which forward the call to another function.
     fn new_invoker<'a>(
         &self,
-        _me: Value<'v>,
-        _heap: &'v Heap,
+        me: Value<'v>,
+        heap: &'v Heap,
     ) -> anyhow::Result<FunctionInvoker<'v, 'a>> {
-        ValueError::unsupported(self, "call()")
+        if self.invoke_parameters().is_some() {
+            Ok(FunctionInvoker::new_value(me, heap))
+        } else {
+            ValueError::unsupported(self, "call()")
+        }
     }
 
     /// Return the result of `a[index]` if `a` is indexable.
@@ -459,6 +608,25 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
         ValueError::unsupported(self, "(iter)")
     }
 
+    /// Can this value be iterated over using [`iterator`](StarlarkValue::iterator),
+    /// in which case [`iterate`](StarlarkValue::iterate) is not used. Defaults to `false`.
+    fn has_iterator(&self) -> bool {
+        false
+    }
+
+    /// Returns an iterator over the values of this container, a simpler alternative to
+    /// implementing [`iterate`](StarlarkValue::iterate) with a separate [`StarlarkIterable`].
+    /// Only called if [`has_iterator`](StarlarkValue::has_iterator) returns `true`.
+    ///
+    /// The value stays borrowed until the iteration finishes, so for mutable values,
+    /// any attempt to mutate them during the iteration fails, just like for a list.
+    fn iterator<'a>(&'a self, _heap: &'v Heap) -> Box<dyn Iterator<Item = Value<'v>> + 'a>
+    where
+        'v: 'a,
+    {
+        panic!("iterator() unsupported for type {}", self.get_type())
+    }
+
     /// Returns the length of the value, if this value is a sequence.
     fn length(&self) -> anyhow::Result<i32> {
         ValueError::unsupported(self, "len()")
@@ -495,6 +663,13 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
 
     /// Tell wether `other` is in the current value, if it is a container.
     ///
+    /// This is the only hook for membership: `x in y` calls `is_in` on `y` with `x`,
+    /// and `x not in y` negates the result. There is no fallback to
+    /// [`iterate`](StarlarkValue::iterate), so a container must implement `is_in` itself.
+    /// If `other` is of a type that can never be in the container, either return `false`
+    /// (as `range` does) or an error (as `string` does, since only a string can be a
+    /// substring). The default implementation is an error, since the value isn't a container.
+    ///
     /// # Examples
     ///
     /// ```rust
@@ -534,12 +709,67 @@ pub trait StarlarkValue<'v>: 'v + AsStarlarkValue<'v> + Debug {
         ValueError::unsupported(self, "-")
     }
 
+    /// Apply the `~` unary operator to the current value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # starlark::assert::all_true(r#"
+    /// ~1 == -2
+    /// # "#);
+    /// ```
+    fn bit_not(&self, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        ValueError::unsupported(self, "~")
+    }
+
     /// Add with the arguments the other way around. Should return [`None`]
     /// to fall through to normal add.
     fn radd(&self, _lhs: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
         None
     }
 
+    /// Subtract with the arguments the other way around, i.e. compute `lhs - self`.
+    /// Should return [`None`] to fall through to normal sub.
+    fn rsub(&self, _lhs: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Multiply with the arguments the other way around. Should return [`None`]
+    /// to fall through to normal mul.
+    fn rmul(&self, _lhs: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Apply the percent operator with the arguments the other way around.
+    /// Should return [`None`] to fall through to normal percent.
+    fn rpercent(&self, _lhs: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Floor division with the arguments the other way around. Should return [`None`]
+    /// to fall through to normal floor_div.
+    fn rfloor_div(&self, _lhs: Value<'v>, _heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Bitwise `&` with the arguments the other way around. Should return [`None`]
+    /// to fall through to normal bit_and.
+    fn rbit_and(&self, _lhs: Value<'v>) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Bitwise `|` with the arguments the other way around. Should return [`None`]
+    /// to fall through to normal bit_or.
+    fn rbit_or(&self, _lhs: Value<'v>) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
+    /// Bitwise `^` with the arguments the other way around. Should return [`None`]
+    /// to fall through to normal bit_xor.
+    fn rbit_xor(&self, _lhs: Value<'v>) -> Option<anyhow::Result<Value<'v>>> {
+        None
+    }
+
     /// Add `other` to the current value. Pass both self and
     /// the Value form of self as original.
     ///
@@ -21,8 +21,8 @@
 //! Unlike most Starlark values, these aren't actually represented on the [`Heap`], but as special values.
 
 use crate::values::{
-    AllocFrozenValue, AllocValue, FrozenHeap, FrozenValue, Heap, StarlarkValue, UnpackValue, Value,
-    ValueError,
+    structs::collect_proto_scalar, AllocFrozenValue, AllocValue, FrozenHeap, FrozenValue, Heap,
+    StarlarkValue, UnpackValue, Value, ValueError,
 };
 use std::cmp::Ordering;
 
@@ -58,13 +58,28 @@ impl StarlarkValue<'_> for bool {
             s.push_str("False")
         }
     }
-    fn to_json(&self) -> String {
+    fn collect_json(&self, s: &mut String) {
         if *self {
-            "true".to_owned()
+            s.push_str("true")
         } else {
-            "false".to_owned()
+            s.push_str("false")
         }
     }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_scalar(
+            name,
+            indent,
+            if *self { "true" } else { "false" },
+            collector,
+        );
+        Ok(())
+    }
     fn to_int(&self) -> anyhow::Result<i32> {
         Ok(if *self { 1 } else { 0 })
     }
@@ -22,22 +22,28 @@ use crate::{
     environment::{Globals, GlobalsStatic},
     values::{
         comparison::equals_small_map, error::ValueError, iter::StarlarkIterable,
-        string::hash_string_value, ComplexValue, Freezer, FrozenValue, Heap, SimpleValue,
-        StarlarkValue, UnpackValue, Value, ValueLike, Walker,
+        string::hash_string_value, structs::collect_proto_nested, AllocFrozenValue, AllocValue,
+        ComplexValue, Freezer, FrozenHeap, FrozenValue, Heap, IntoFrozenValue, IntoValue,
+        SimpleValue, StarlarkValue, UnpackValue, Value, ValueLike, Walker,
     },
 };
 use gazebo::{any::AnyLifetime, cell::ARef, prelude::*};
 use indexmap::Equivalent;
 use std::{
+    collections::{BTreeMap, HashMap},
     hash::{Hash, Hasher},
     marker::PhantomData,
     ops::Deref,
 };
 
 /// Define the dictionary type. See [`Dict`] and [`FrozenDict`] as the two aliases.
+/// Iteration is in insertion order, including after entries are removed, see [`SmallMap`].
 #[derive(Clone, Default_, Debug)]
 pub struct DictGen<T> {
     /// The data stored by the dictionary. The keys must all be hashable values.
+    // There is no separate layout for int keys: an int is stored in the `Value` itself and
+    // hashes to itself (see `hash_value`), so `SmallMap` already holds it in one word and
+    // finds it without dynamic dispatch.
     pub content: SmallMap<T, T>,
 }
 
@@ -64,6 +70,64 @@ impl<V> DictGen<V> {
     }
 }
 
+/// Allocate a dictionary, failing if any of the keys are not hashable or values can't be converted.
+fn alloc_dict<'v, K: AllocValue<'v>, V: IntoValue<'v>>(
+    items: impl ExactSizeIterator<Item = (K, V)>,
+    heap: &'v Heap,
+) -> anyhow::Result<Value<'v>> {
+    let mut content = SmallMap::with_capacity(items.len());
+    for (k, v) in items {
+        content.insert_hashed(k.alloc_value(heap).get_hashed()?, v.into_value(heap)?);
+    }
+    Ok(heap.alloc(Dict::new(content)))
+}
+
+impl<'v, K: AllocValue<'v>, V: IntoValue<'v>> IntoValue<'v> for BTreeMap<K, V> {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        alloc_dict(self.into_iter(), heap)
+    }
+}
+
+/// Sorted by key so the resulting dictionary doesn't depend on the iteration order of the map.
+impl<'v, K: AllocValue<'v> + Ord, V: IntoValue<'v>, S> IntoValue<'v> for HashMap<K, V, S> {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        alloc_dict(sorted(self).into_iter(), heap)
+    }
+}
+
+/// Like [`alloc_dict`], but for a [`FrozenHeap`].
+fn alloc_frozen_dict<K: AllocFrozenValue, V: IntoFrozenValue>(
+    items: impl ExactSizeIterator<Item = (K, V)>,
+    heap: &FrozenHeap,
+) -> anyhow::Result<FrozenValue> {
+    let mut content = SmallMap::with_capacity(items.len());
+    for (k, v) in items {
+        content.insert_hashed(
+            k.alloc_frozen_value(heap).get_hashed()?,
+            v.into_frozen_value(heap)?,
+        );
+    }
+    Ok(heap.alloc_simple(FrozenDict::new(content)))
+}
+
+impl<K: AllocFrozenValue, V: IntoFrozenValue> IntoFrozenValue for BTreeMap<K, V> {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        alloc_frozen_dict(self.into_iter(), heap)
+    }
+}
+
+impl<K: AllocFrozenValue + Ord, V: IntoFrozenValue, S> IntoFrozenValue for HashMap<K, V, S> {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        alloc_frozen_dict(sorted(self).into_iter(), heap)
+    }
+}
+
+fn sorted<K: Ord, V, S>(x: HashMap<K, V, S>) -> Vec<(K, V)> {
+    let mut items = x.into_iter().collect::<Vec<_>>();
+    items.sort_by(|a, b| a.0.cmp(&b.0));
+    items
+}
+
 /// Helper type for lookups, not useful.
 #[derive(Eq, PartialEq)]
 pub struct ValueStr<'a>(&'a str);
@@ -223,19 +287,37 @@ where
         r.push('}');
     }
 
-    fn to_json(&self) -> String {
-        format!(
-            "{{{}}}",
-            self.content
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k.to_json(), v.to_json()))
-                .enumerate()
-                .fold(String::new(), |accum, s| if s.0 == 0 {
-                    accum + &s.1
-                } else {
-                    accum + ", " + &s.1
-                })
-        )
+    fn collect_repr_cycle(&self, r: &mut String) {
+        r.push_str("{...}");
+    }
+
+    fn collect_json(&self, s: &mut String) {
+        s.push('{');
+        for (i, (k, v)) in self.content.iter().enumerate() {
+            if i != 0 {
+                s.push_str(", ");
+            }
+            k.collect_json(s);
+            s.push_str(": ");
+            v.collect_json(s);
+        }
+        s.push('}');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        // Maps are written as repeated messages with `key` and `value` fields
+        for (k, v) in self.content.iter() {
+            collect_proto_nested(name, indent, collector, |c| {
+                k.collect_proto("key", indent + 2, c)?;
+                v.collect_proto("value", indent + 2, c)
+            })?;
+        }
+        Ok(())
     }
 
     fn to_bool(&self) -> bool {
@@ -252,7 +334,7 @@ where
     fn at(&self, index: Value<'v>, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         match self.content.get_hashed(index.get_hashed()?.borrow()) {
             Some(v) => Ok(v.to_value()),
-            None => Err(ValueError::KeyNotFound(index.to_repr()).into()),
+            None => Err(ValueError::KeyNotFound(index.to_repr_for_error()).into()),
         }
     }
 
@@ -358,6 +440,41 @@ b1 and b2 and b3
         );
     }
 
+    #[test]
+    fn test_dict_insertion_order() {
+        assert::all_true(
+            r#"
+small = {"c": 1, "a": 2, "b": 3}
+small.pop("a") == 2
+small["a"] = 4
+small.keys() == ["c", "b", "a"]
+big = {}
+[big.setdefault(i, i) for i in range(20)] == list(range(20))
+big.pop(3) == 3
+big.popitem() == (0, 0)
+big.update([(3, 3), (0, 0), (5, 50)]) == None
+big.keys() == [1, 2] + list(range(4, 20)) + [3, 0]
+big[5] == 50
+str({2: 1, 1: 2}) == "{2: 1, 1: 2}"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_int_keys() {
+        assert::all_true(
+            r#"
+x = {i * 7: i for i in range(-50, 50)}
+[x[i * 7] for i in range(-50, 50)] == list(range(-50, 50))
+(3 in x) == False
+x.get(2147483647) == None
+{1: "a", True: "b"} == {True: "b", 1: "a"}
+len({1: "a", True: "b", "1": "c"}) == 3
+{(1, 2): 3}[(1, 2)] == 3
+"#,
+        );
+    }
+
     #[test]
     fn test_get_str() -> anyhow::Result<()> {
         let heap = Heap::new();
@@ -0,0 +1,415 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Views over a dictionary, returned by `keys()`, `values()` and `items()` when the dialect
+//! enables [`enable_dict_views`](crate::syntax::Dialect::enable_dict_views).
+//!
+//! A view doesn't copy the dictionary, but reads through to it, so always reflects
+//! the current contents. It behaves like a list for the purposes of `len`, `in`,
+//! indexing, slicing, `+` and equality, so `d.keys() == ["a", "b"]` holds.
+//! Iterating over a view locks the dictionary, in the same way as iterating over the
+//! dictionary itself, so mutating the dictionary in the body of the loop is an error.
+//! Use `list(d.keys())` to take a copy first.
+
+use crate::values::{
+    dict::Dict,
+    error::ValueError,
+    index::{convert_index, convert_slice_indices},
+    iter::StarlarkIterable,
+    list::List,
+    tuple::{self, Tuple},
+    ComplexValue, ConstFrozenValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike,
+    Walker,
+};
+use gazebo::{any::AnyLifetime, cell::ARef};
+
+/// Which part of the dictionary a view exposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DictViewKind {
+    /// The keys, as returned by `keys()`.
+    Keys,
+    /// The values, as returned by `values()`.
+    Values,
+    /// The key/value pairs as tuples, as returned by `items()`.
+    Items,
+}
+
+/// Define the dictionary view type. See [`DictView`] and [`FrozenDictView`] as the two aliases.
+#[derive(Clone, Debug)]
+pub struct DictViewGen<T> {
+    // Must be a dictionary
+    dict: T,
+    kind: DictViewKind,
+}
+
+starlark_complex_value!(pub DictView);
+
+impl<T> DictViewGen<T> {
+    /// The result of calling `type()` on `keys()`.
+    pub const KEYS_TYPE: &'static str = "dict_keys";
+    /// The result of calling `type()` on `values()`.
+    pub const VALUES_TYPE: &'static str = "dict_values";
+    /// The result of calling `type()` on `items()`.
+    pub const ITEMS_TYPE: &'static str = "dict_items";
+
+    /// Which part of the dictionary this view exposes.
+    pub fn kind(&self) -> DictViewKind {
+        self.kind
+    }
+}
+
+impl<'v> DictView<'v> {
+    /// Create a view over `dict`, which must be a dictionary.
+    pub(crate) fn new(dict: Value<'v>, kind: DictViewKind) -> Self {
+        Self { dict, kind }
+    }
+}
+
+impl<'v> ComplexValue<'v> for DictView<'v> {
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        box FrozenDictView {
+            dict: self.dict.freeze(freezer),
+            kind: self.kind,
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        walker.walk(&mut self.dict);
+    }
+}
+
+/// An element of a view. Items are kept as a pair, so we only allocate a tuple
+/// when the element escapes to Starlark.
+#[derive(Clone, Copy)]
+enum Element<'v> {
+    Single(Value<'v>),
+    Pair(Value<'v>, Value<'v>),
+}
+
+impl<'v> Element<'v> {
+    fn get(dict: &Dict<'v>, kind: DictViewKind, index: usize) -> Option<Self> {
+        let (k, v) = dict.content.get_index(index)?;
+        Some(Self::new(kind, *k, *v))
+    }
+
+    fn new(kind: DictViewKind, k: Value<'v>, v: Value<'v>) -> Self {
+        match kind {
+            DictViewKind::Keys => Self::Single(k),
+            DictViewKind::Values => Self::Single(v),
+            DictViewKind::Items => Self::Pair(k, v),
+        }
+    }
+
+    fn to_value(self, heap: &'v Heap) -> Value<'v> {
+        match self {
+            Self::Single(x) => x,
+            Self::Pair(k, v) => heap.alloc_tuple(&[k, v]),
+        }
+    }
+
+    fn equals(self, other: Value<'v>) -> anyhow::Result<bool> {
+        match self {
+            Self::Single(x) => x.equals(other),
+            Self::Pair(k, v) => match Tuple::from_value(other) {
+                Some(t) => match t.content() {
+                    [k2, v2] => Ok(k.equals(*k2)? && v.equals(*v2)?),
+                    _ => Ok(false),
+                },
+                None => Ok(false),
+            },
+        }
+    }
+
+    fn equals_element(self, other: Self) -> anyhow::Result<bool> {
+        match (self, other) {
+            (Self::Pair(k1, v1), Self::Pair(k2, v2)) => Ok(k1.equals(k2)? && v1.equals(v2)?),
+            (x, Self::Single(y)) => x.equals(y),
+            (Self::Single(x), y) => y.equals(x),
+        }
+    }
+
+    fn collect_repr(self, s: &mut String) {
+        match self {
+            Self::Single(x) => x.collect_repr(s),
+            Self::Pair(k, v) => {
+                s.push('(');
+                k.collect_repr(s);
+                s.push_str(", ");
+                v.collect_repr(s);
+                s.push(')');
+            }
+        }
+    }
+
+    fn collect_json(self, s: &mut String) {
+        match self {
+            Self::Single(x) => x.collect_json(s),
+            Self::Pair(k, v) => {
+                s.push('[');
+                k.collect_json(s);
+                s.push(',');
+                v.collect_json(s);
+                s.push(']');
+            }
+        }
+    }
+}
+
+/// Implementation of an iterator over [`DictView`], which holds the dictionary
+/// borrowed until the iteration finishes.
+struct DictViewIterator<'v> {
+    dict: ARef<'v, Dict<'v>>,
+    kind: DictViewKind,
+    index: usize,
+    heap: &'v Heap,
+}
+
+impl<'v> Iterator for DictViewIterator<'v> {
+    type Item = Value<'v>;
+
+    fn next(&mut self) -> Option<Value<'v>> {
+        let x = Element::get(&self.dict, self.kind, self.index)?;
+        self.index += 1;
+        Some(x.to_value(self.heap))
+    }
+}
+
+impl<'v, T: ValueLike<'v>> DictViewGen<T> {
+    fn get_dict(&self) -> ARef<'v, Dict<'v>> {
+        Dict::from_value(self.dict.to_value()).unwrap()
+    }
+
+    fn elements(&self, heap: &'v Heap) -> Vec<Value<'v>> {
+        let dict = self.get_dict();
+        dict.content
+            .iter()
+            .map(|(k, v)| Element::new(self.kind, *k, *v).to_value(heap))
+            .collect()
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for DictViewGen<T>
+where
+    Self: AnyLifetime<'v>,
+{
+    fn get_type(&self) -> &'static str {
+        match self.kind {
+            DictViewKind::Keys => DictView::KEYS_TYPE,
+            DictViewKind::Values => DictView::VALUES_TYPE,
+            DictViewKind::Items => DictView::ITEMS_TYPE,
+        }
+    }
+
+    fn get_type_value(&self) -> &'static ConstFrozenValue {
+        static KEYS: ConstFrozenValue = ConstFrozenValue::new(DictView::KEYS_TYPE);
+        static VALUES: ConstFrozenValue = ConstFrozenValue::new(DictView::VALUES_TYPE);
+        static ITEMS: ConstFrozenValue = ConstFrozenValue::new(DictView::ITEMS_TYPE);
+        match self.kind {
+            DictViewKind::Keys => &KEYS,
+            DictViewKind::Values => &VALUES,
+            DictViewKind::Items => &ITEMS,
+        }
+    }
+
+    fn collect_repr(&self, s: &mut String) {
+        s.push('[');
+        for (i, (k, v)) in self.get_dict().content.iter().enumerate() {
+            if i != 0 {
+                s.push_str(", ");
+            }
+            Element::new(self.kind, *k, *v).collect_repr(s);
+        }
+        s.push(']');
+    }
+
+    fn collect_repr_cycle(&self, s: &mut String) {
+        s.push_str("[...]");
+    }
+
+    fn collect_json(&self, s: &mut String) {
+        s.push('[');
+        for (i, (k, v)) in self.get_dict().content.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            Element::new(self.kind, *k, *v).collect_json(s);
+        }
+        s.push(']');
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.get_dict().content.is_empty()
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        let dict = self.get_dict();
+        if let Some(other) = DictView::from_value(other) {
+            let other_dict = other.get_dict();
+            if dict.content.len() != other_dict.content.len() {
+                return Ok(false);
+            }
+            for ((k1, v1), (k2, v2)) in dict.content.iter().zip(other_dict.content.iter()) {
+                let x = Element::new(self.kind, *k1, *v1);
+                if !x.equals_element(Element::new(other.kind, *k2, *v2))? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+        let xs = match List::from_value(other) {
+            Some(xs) => xs,
+            None => return Ok(false),
+        };
+        if dict.content.len() != xs.len() {
+            return Ok(false);
+        }
+        for ((k, v), y) in dict.content.iter().zip(xs.iter()) {
+            if !Element::new(self.kind, *k, *v).equals(y)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn at(&self, index: Value, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        let dict = self.get_dict();
+        let i = convert_index(index, dict.content.len() as i32)? as usize;
+        Ok(Element::get(&dict, self.kind, i).unwrap().to_value(heap))
+    }
+
+    fn length(&self) -> anyhow::Result<i32> {
+        Ok(self.get_dict().content.len() as i32)
+    }
+
+    fn is_in(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        let dict = self.get_dict();
+        match self.kind {
+            DictViewKind::Keys => Ok(dict
+                .content
+                .contains_key_hashed(other.get_hashed()?.borrow())),
+            DictViewKind::Values => {
+                for v in dict.content.values() {
+                    if v.equals(other)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            DictViewKind::Items => match Tuple::from_value(other) {
+                Some(t) => match t.content() {
+                    [k, v] => match dict.content.get_hashed(k.get_hashed()?.borrow()) {
+                        Some(x) => x.equals(*v),
+                        None => Ok(false),
+                    },
+                    _ => Ok(false),
+                },
+                None => Ok(false),
+            },
+        }
+    }
+
+    fn slice(
+        &self,
+        start: Option<Value>,
+        stop: Option<Value>,
+        stride: Option<Value>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        let xs = self.elements(heap);
+        let (start, stop, stride) = convert_slice_indices(xs.len() as i32, start, stop, stride)?;
+        Ok(heap.alloc(tuple::slice_vector(start, stop, stride, xs.iter())))
+    }
+
+    fn iterate(&self) -> anyhow::Result<&(dyn StarlarkIterable<'v> + 'v)> {
+        Ok(self)
+    }
+
+    fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        if List::from_value(other).is_some() || DictView::from_value(other).is_some() {
+            let mut xs = self.elements(heap);
+            xs.extend(&other.iterate(heap)?);
+            Ok(heap.alloc(xs))
+        } else {
+            ValueError::unsupported_with(self, "+", other)
+        }
+    }
+
+    fn radd(&self, lhs: Value<'v>, heap: &'v Heap) -> Option<anyhow::Result<Value<'v>>> {
+        let mut xs = List::from_value(lhs)?.content.clone();
+        xs.extend(self.elements(heap));
+        Some(Ok(heap.alloc(xs)))
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkIterable<'v> for DictViewGen<T> {
+    fn to_iter<'a>(&'a self, heap: &'v Heap) -> Box<dyn Iterator<Item = Value<'v>> + 'a>
+    where
+        'v: 'a,
+    {
+        box DictViewIterator {
+            dict: self.get_dict(),
+            kind: self.kind,
+            index: 0,
+            heap,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assert::Assert;
+
+    #[test]
+    fn test_dict_view() {
+        let mut a = Assert::new();
+        a.dialect_set(|x| x.enable_dict_views = true);
+        a.pass(
+            r#"
+x = {"a": 1, "b": 2}
+keys = x.keys()
+assert_eq(type(keys), "dict_keys")
+assert_eq(keys, ["a", "b"])
+assert_eq(["a", "b"], keys)
+assert_eq(x.values(), [1, 2])
+assert_ne(x.values(), (1, 2))
+assert_eq(x.items(), [("a", 1), ("b", 2)])
+assert_eq(repr(x.items()), '[("a", 1), ("b", 2)]')
+assert_eq(len(keys), 2)
+assert_eq(keys[-1], "b")
+assert_eq(x.items()[1:], [("b", 2)])
+assert_eq(keys + ["c"], ["a", "b", "c"])
+assert_eq(["c"] + keys, ["c", "a", "b"])
+assert.true("a" in keys)
+assert.true(2 in x.values())
+assert.true(("b", 2) in x.items())
+assert.true(("b", 1) not in x.items())
+x["c"] = 3
+assert_eq(keys, ["a", "b", "c"])
+assert_eq([k + str(v) for k, v in x.items()], ["a1", "b2", "c3"])
+assert_eq(dict(x.items()), x)
+"#,
+        );
+        a.fail(
+            r#"
+x = {"a": 1}
+for k in x.keys():
+    x["b"] = 2
+"#,
+            "mutate an iterable",
+        );
+    }
+}
@@ -50,7 +50,7 @@ use gazebo::{any::AnyLifetime, cell::ARef};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-enum EnumError {
+pub(crate) enum EnumError {
     #[error("enum values must all be distinct, but repeated `{0}`")]
     DuplicateEnumValue(String),
     #[error("Unknown enum element `{0}`, given to `{1}`")]
@@ -145,7 +145,7 @@ impl<'v> EnumType<'v> {
                 value: *x,
             });
             if res.insert_hashed(x.get_hashed()?, v).is_some() {
-                return Err(EnumError::DuplicateEnumValue(x.to_string()).into());
+                return Err(EnumError::DuplicateEnumValue(x.to_str_for_error()).into());
             }
         }
 
@@ -202,9 +202,11 @@ where
                 let typ = EnumType::from_value(typ_val).unwrap();
                 match typ.elements.get_hashed(val.get_hashed()?.borrow()) {
                     Some(v) => Ok(*v),
-                    None => {
-                        Err(EnumError::InvalidElement(val.to_string(), typ_val.to_string()).into())
-                    }
+                    None => Err(EnumError::InvalidElement(
+                        val.to_str_for_error(),
+                        typ_val.to_str_for_error(),
+                    )
+                    .into()),
                 }
             },
             signature,
@@ -268,8 +270,17 @@ where
         ty == EnumValue::TYPE || Some(ty) == self.get_enum_type().typ.as_deref()
     }
 
-    fn to_json(&self) -> String {
-        self.value.to_json()
+    fn collect_json(&self, s: &mut String) {
+        self.value.collect_json(s)
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        self.value.collect_proto(name, indent, collector)
     }
 
     fn collect_repr(&self, collector: &mut String) {
@@ -21,16 +21,18 @@ use crate::{
     codemap::Span,
     eval::{
         def::{DefInvoker, DefInvokerFrozen},
-        Evaluator, ParametersCollect, ParametersParser, ParametersSpec,
+        Evaluator, Parameter, ParametersCollect, ParametersParser, ParametersSpec, ResolvedNames,
+        Symbol,
     },
     values::{
         AllocFrozenValue, AllocValue, ComplexValue, ConstFrozenValue, Freezer, FrozenHeap,
         FrozenValue, Hashed, Heap, SimpleValue, StarlarkValue, Value, ValueError, ValueLike,
-        Walker,
+        ValueRef, Walker,
     },
 };
 use derivative::Derivative;
 use gazebo::{any::AnyLifetime, cell::ARef, prelude::*};
+use std::mem;
 
 pub const FUNCTION_TYPE: &str = "function";
 
@@ -42,9 +44,21 @@ pub(crate) enum FunctionInvokerInner<'v, 'a> {
     Native(NativeFunctionInvoker<'v, 'a>),
     Def(DefInvoker<'v, 'a>),
     DefFrozen(DefInvokerFrozen<'v, 'a>),
+    Value(ValueInvoker<'v, 'a>),
 }
 
 impl<'v, 'a> FunctionInvoker<'v, 'a> {
+    /// Create an invoker for a value which implements [`StarlarkValue::invoke`],
+    /// collecting the arguments according to its [`invoke_parameters`](StarlarkValue::invoke_parameters),
+    /// which must not be [`None`].
+    pub(crate) fn new_value(me: Value<'v>, heap: &'v Heap) -> Self {
+        let parameters = ARef::map(me.get_aref(), |x| x.invoke_parameters().unwrap());
+        FunctionInvoker(FunctionInvokerInner::Value(ValueInvoker {
+            me,
+            collect: ParametersSpec::collect(parameters, 0, heap),
+        }))
+    }
+
     /// Actually invoke the underlying function, giving call-stack information.
     /// If provided, the `location` must use the currently active [`CodeMap`](crate::codemap::CodeMap)
     /// from the [`Evaluator`].
@@ -62,15 +76,30 @@ impl<'v, 'a> FunctionInvoker<'v, 'a> {
             FunctionInvokerInner::Native(inv) => inv.invoke(context),
             FunctionInvokerInner::Def(inv) => inv.invoke(context),
             FunctionInvokerInner::DefFrozen(inv) => inv.invoke(context),
+            FunctionInvokerInner::Value(inv) => inv.invoke(context),
         })
     }
 
+    /// If this invokes a `def`, the slots for its parameters, so a call in tail position
+    /// can reuse the frame of the running function. Otherwise gives back the invoker.
+    pub(crate) fn into_def_slots(
+        self,
+        heap: &'v Heap,
+    ) -> Result<anyhow::Result<Vec<ValueRef<'v>>>, Self> {
+        match self.0 {
+            FunctionInvokerInner::Def(inv) => Ok(inv.into_slots(heap)),
+            FunctionInvokerInner::DefFrozen(inv) => Ok(inv.into_slots(heap)),
+            inner => Err(Self(inner)),
+        }
+    }
+
     /// Add a positional argument.
     pub fn push_pos(&mut self, v: Value<'v>) {
         match &mut self.0 {
-            FunctionInvokerInner::Native(x) => x.collect().positional(v),
+            FunctionInvokerInner::Native(x) => x.push_pos(v),
             FunctionInvokerInner::Def(x) => x.collect().positional(v),
             FunctionInvokerInner::DefFrozen(x) => x.collect().positional(v),
+            FunctionInvokerInner::Value(x) => x.collect.positional(v),
         }
     }
 
@@ -80,6 +109,7 @@ impl<'v, 'a> FunctionInvoker<'v, 'a> {
             FunctionInvokerInner::Native(x) => x.collect().args(v, heap),
             FunctionInvokerInner::Def(x) => x.collect().args(v, heap),
             FunctionInvokerInner::DefFrozen(x) => x.collect().args(v, heap),
+            FunctionInvokerInner::Value(x) => x.collect.args(v, heap),
         }
     }
 
@@ -89,15 +119,50 @@ impl<'v, 'a> FunctionInvoker<'v, 'a> {
             FunctionInvokerInner::Native(x) => x.collect().named(name, name_value, v),
             FunctionInvokerInner::Def(x) => x.collect().named(name, name_value, v),
             FunctionInvokerInner::DefFrozen(x) => x.collect().named(name, name_value, v),
+            FunctionInvokerInner::Value(x) => x.collect.named(name, name_value, v),
         }
     }
 
-    /// Add a `**kargs` argument.
+    /// Resolve the named arguments at a callsite, see [`ResolvedNames`].
+    pub(crate) fn resolve<'c>(
+        &mut self,
+        symbols: &[Symbol],
+        cache: &'c ResolvedNames,
+    ) -> Option<&'c [Option<usize>]> {
+        match &mut self.0 {
+            FunctionInvokerInner::Native(x) => x.collect().resolve(symbols, cache),
+            FunctionInvokerInner::Def(x) => x.collect().resolve(symbols, cache),
+            FunctionInvokerInner::DefFrozen(x) => x.collect().resolve(symbols, cache),
+            FunctionInvokerInner::Value(x) => x.collect.resolve(symbols, cache),
+        }
+    }
+
+    /// Add a named argument, whose slot was obtained from [`resolve`](FunctionInvoker::resolve).
+    pub(crate) fn push_named_slot(&mut self, slot: Option<usize>, name: &Symbol, v: Value<'v>) {
+        let name_value = name.hashed_value();
+        match &mut self.0 {
+            FunctionInvokerInner::Native(x) => {
+                x.collect().named_slot(slot, name.name(), name_value, v)
+            }
+            FunctionInvokerInner::Def(x) => {
+                x.collect().named_slot(slot, name.name(), name_value, v)
+            }
+            FunctionInvokerInner::DefFrozen(x) => {
+                x.collect().named_slot(slot, name.name(), name_value, v)
+            }
+            FunctionInvokerInner::Value(x) => {
+                x.collect.named_slot(slot, name.name(), name_value, v)
+            }
+        }
+    }
+
+    /// Add a `**kargs` argument, which must be a dictionary with string keys, or a struct.
     pub fn push_kwargs(&mut self, v: Value<'v>) {
         match &mut self.0 {
             FunctionInvokerInner::Native(x) => x.collect().kwargs(v),
             FunctionInvokerInner::Def(x) => x.collect().kwargs(v),
             FunctionInvokerInner::DefFrozen(x) => x.collect().kwargs(v),
+            FunctionInvokerInner::Value(x) => x.collect.kwargs(v),
         }
     }
 }
@@ -122,33 +187,105 @@ impl<T> NativeFunc for T where
 /// A function that can be evaluated which can also collect parameters
 pub(crate) struct NativeFunctionInvoker<'v, 'a> {
     function: ARef<'a, dyn NativeFunc>,
-    collect: ParametersCollect<'v, 'a, FrozenValue>,
+    arguments: NativeArguments<'v, 'a>,
+    heap: &'v Heap,
+}
+
+enum NativeArguments<'v, 'a> {
+    // For a function with `arity` parameters, all required and positional,
+    // while it has only been given positional arguments. Each argument goes
+    // straight into the next slot, bypassing ParametersCollect.
+    Positional {
+        parameters: ARef<'a, ParametersSpec<FrozenValue>>,
+        arity: usize,
+        slots: Vec<ValueRef<'v>>,
+    },
+    Collect(ParametersCollect<'v, 'a, FrozenValue>),
 }
 
 impl<'v, 'a> NativeFunctionInvoker<'v, 'a> {
-    pub fn new<F: NativeFunc>(func: ARef<'a, NativeFunction<F>>) -> Self {
+    pub fn new<F: NativeFunc>(func: ARef<'a, NativeFunction<F>>, heap: &'v Heap) -> Self {
         // Used to help guide the type checker
         fn convert(x: &impl NativeFunc) -> &(dyn NativeFunc) {
             x
         }
 
+        let fixed_arity = func.fixed_arity;
         let (function, parameters) =
             ARef::map_split(func, |x| (convert(&x.function), &x.parameters));
+        let arguments = match fixed_arity {
+            Some(arity) => NativeArguments::Positional {
+                parameters,
+                arity,
+                slots: heap.alloc_slots(0),
+            },
+            None => NativeArguments::Collect(ParametersSpec::collect(parameters, 0, heap)),
+        };
         Self {
             function,
-            collect: ParametersSpec::collect(parameters, 0),
+            arguments,
+            heap,
         }
     }
 
-    pub fn invoke(self, context: &mut Evaluator<'v, '_>) -> anyhow::Result<Value<'v>> {
-        let slots = self.collect.done(context.heap)?;
-        let slots = slots.map(|x| x.get());
-        let parser = ParametersParser::new(&slots);
-        (*self.function)(context, parser)
+    pub fn invoke(mut self, context: &mut Evaluator<'v, '_>) -> anyhow::Result<Value<'v>> {
+        if let NativeArguments::Positional { arity, slots, .. } = &self.arguments {
+            if slots.len() != *arity {
+                // Let ParametersCollect report the wrong number of arguments
+                self.collect();
+            }
+        }
+        let slots = match self.arguments {
+            NativeArguments::Positional { slots, .. } => slots,
+            NativeArguments::Collect(collect) => collect.done(context.heap)?,
+        };
+        let res = (*self.function)(context, ParametersParser::new(&slots));
+        context.heap.free_slots(slots);
+        res
+    }
+
+    fn push_pos(&mut self, v: Value<'v>) {
+        match &mut self.arguments {
+            NativeArguments::Positional { slots, .. } => slots.push(ValueRef::new(v)),
+            NativeArguments::Collect(collect) => collect.positional(v),
+        }
     }
 
     fn collect(&mut self) -> &mut ParametersCollect<'v, 'a, FrozenValue> {
-        &mut self.collect
+        if let NativeArguments::Positional {
+            parameters, slots, ..
+        } = &mut self.arguments
+        {
+            // Switch to collecting the arguments, replaying those we've already seen
+            let mut collect = ParametersSpec::collect(ARef::clone(parameters), 0, self.heap);
+            for x in slots.iter() {
+                collect.positional(x.get().unwrap());
+            }
+            self.heap.free_slots(mem::take(slots));
+            self.arguments = NativeArguments::Collect(collect);
+        }
+        match &mut self.arguments {
+            NativeArguments::Collect(collect) => collect,
+            NativeArguments::Positional { .. } => unreachable!(),
+        }
+    }
+}
+
+/// Invokes a value which implements [`StarlarkValue::invoke`].
+pub(crate) struct ValueInvoker<'v, 'a> {
+    me: Value<'v>,
+    collect: ParametersCollect<'v, 'a, FrozenValue>,
+}
+
+impl<'v, 'a> ValueInvoker<'v, 'a> {
+    fn invoke(self, context: &mut Evaluator<'v, '_>) -> anyhow::Result<Value<'v>> {
+        let slots = self.collect.done(context.heap)?;
+        let res = self
+            .me
+            .get_aref()
+            .invoke(self.me, ParametersParser::new(&slots), context);
+        context.heap.free_slots(slots);
+        res
     }
 }
 
@@ -164,6 +301,8 @@ pub struct NativeFunction<F: NativeFunc> {
     #[derivative(Debug = "ignore")]
     function: F,
     parameters: ParametersSpec<FrozenValue>,
+    // Computed from `parameters`, see NativeArguments::Positional
+    fixed_arity: Option<usize>,
     typ: Option<FrozenValue>,
 }
 
@@ -189,6 +328,7 @@ impl<
     pub fn new(function: F, parameters: ParametersSpec<FrozenValue>) -> Self {
         NativeFunction {
             function,
+            fixed_arity: parameters.fixed_arity(),
             parameters,
             typ: None,
         }
@@ -220,15 +360,26 @@ impl<'v, F: NativeFunc> StarlarkValue<'v> for NativeFunction<F> {
         self.parameters.collect_repr(s)
     }
 
+    fn signature(&self) -> Option<String> {
+        Some(self.parameters.signature())
+    }
+
+    fn parameters(&self) -> Option<Vec<Parameter<'v>>> {
+        Some(self.parameters.parameters())
+    }
+
     fn new_invoker<'a>(
         &self,
         me: Value<'v>,
-        _heap: &'v Heap,
+        heap: &'v Heap,
     ) -> anyhow::Result<FunctionInvoker<'v, 'a>> {
         Ok(FunctionInvoker(FunctionInvokerInner::Native(
-            NativeFunctionInvoker::new(ARef::map(me.get_aref(), |x| {
-                x.as_dyn_any().downcast_ref::<Self>().unwrap()
-            })),
+            NativeFunctionInvoker::new(
+                ARef::map(me.get_aref(), |x| {
+                    x.as_dyn_any().downcast_ref::<Self>().unwrap()
+                }),
+                heap,
+            ),
         )))
     }
 
@@ -241,6 +392,10 @@ impl<'v, F: NativeFunc> StarlarkValue<'v> for NativeFunction<F> {
         ValueError::unsupported(self, &format!(".{}", attribute))
     }
 
+    fn has_attr(&self, attribute: &str) -> bool {
+        self.typ.is_some() && attribute == "type"
+    }
+
     fn dir_attr(&self) -> Vec<String> {
         if self.typ.is_some() {
             vec!["type".to_owned()]
@@ -298,6 +453,10 @@ impl<'v> WrappedMethod<'v> {
     pub(crate) fn get_method(&self) -> Value<'v> {
         self.method
     }
+
+    pub(crate) fn get_self(&self) -> Value<'v> {
+        self.self_obj
+    }
 }
 
 impl<'v, V: ValueLike<'v>> WrappedMethodGen<V> {
@@ -24,10 +24,16 @@
 //! integer values will be stored on the heap.
 
 use crate::values::{
-    error::ValueError, layout::PointerI32, AllocFrozenValue, AllocValue, FrozenHeap, FrozenValue,
-    Heap, StarlarkValue, UnpackValue, Value,
+    error::ValueError, layout::PointerI32, structs::collect_proto_scalar, AllocFrozenValue,
+    AllocValue, FrozenHeap, FrozenValue, Heap, IntoFrozenValue, IntoValue, StarlarkValue,
+    UnpackValue, Value,
+};
+use anyhow::anyhow;
+use std::{
+    cmp::Ordering,
+    convert::{TryFrom, TryInto},
+    time::Duration,
 };
-use std::cmp::Ordering;
 
 /// The result of calling `type()` on integers.
 pub const INT_TYPE: &str = "int";
@@ -49,6 +55,83 @@ impl UnpackValue<'_> for i32 {
     }
 }
 
+// Integer types which always fit in an `i32`.
+macro_rules! alloc_int_lossless {
+    ($($t:ty),*) => {
+        $(
+            impl<'v> AllocValue<'v> for $t {
+                fn alloc_value(self, _heap: &'v Heap) -> Value<'v> {
+                    Value::new_int(i32::from(self))
+                }
+            }
+
+            impl AllocFrozenValue for $t {
+                fn alloc_frozen_value(self, _heap: &FrozenHeap) -> FrozenValue {
+                    FrozenValue::new_int(i32::from(self))
+                }
+            }
+        )*
+    };
+}
+
+// Integer types which may be out of range, in which case conversion fails.
+macro_rules! into_value_int_checked {
+    ($($t:ty),*) => {
+        $(
+            impl<'v> IntoValue<'v> for $t {
+                fn into_value(self, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+                    Ok(Value::new_int(checked_int(self)?))
+                }
+            }
+
+            impl IntoFrozenValue for $t {
+                fn into_frozen_value(self, _heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+                    Ok(FrozenValue::new_int(checked_int(self)?))
+                }
+            }
+        )*
+    };
+}
+
+fn checked_int<T: TryInto<i32>>(x: T) -> anyhow::Result<i32> {
+    x.try_into().map_err(|_| ValueError::IntegerOverflow.into())
+}
+
+alloc_int_lossless!(i8, i16, u8, u16);
+into_value_int_checked!(u32, i64, u64, isize, usize);
+
+// A duration as a number of seconds, failing rather than losing any fraction of a second.
+fn duration_secs(x: Duration) -> anyhow::Result<i32> {
+    if x.subsec_nanos() != 0 {
+        return Err(anyhow!(
+            "Duration `{:?}` is not a whole number of seconds, so cannot be converted to an int",
+            x
+        ));
+    }
+    checked_int(x.as_secs())
+}
+
+/// A [`Duration`] is converted to a number of seconds, failing if it isn't a whole number.
+impl<'v> IntoValue<'v> for Duration {
+    fn into_value(self, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(Value::new_int(duration_secs(self)?))
+    }
+}
+
+impl IntoFrozenValue for Duration {
+    fn into_frozen_value(self, _heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        Ok(FrozenValue::new_int(duration_secs(self)?))
+    }
+}
+
+/// A [`Duration`] is unpacked from a non-negative number of seconds.
+impl UnpackValue<'_> for Duration {
+    fn unpack_value(value: Value, _heap: &Heap) -> Option<Self> {
+        let secs = u64::try_from(value.unpack_int()?).ok()?;
+        Some(Duration::from_secs(secs))
+    }
+}
+
 fn i64_arith_bin_op<'v, F>(
     left: i32,
     right: Value,
@@ -80,8 +163,18 @@ impl<'v> StarlarkValue<'v> for PointerI32 {
         s.push_str(&self.get().to_string());
     }
 
-    fn to_json(&self) -> String {
-        self.get().to_string()
+    fn collect_json(&self, s: &mut String) {
+        s.push_str(&self.get().to_string());
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_scalar(name, indent, &self.get().to_string(), collector);
+        Ok(())
     }
     fn to_int(&self) -> anyhow::Result<i32> {
         Ok(self.get())
@@ -101,6 +194,9 @@ impl<'v> StarlarkValue<'v> for PointerI32 {
             .map(Value::new_int)
             .ok_or_else(|| ValueError::IntegerOverflow.into())
     }
+    fn bit_not(&self, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(Value::new_int(!self.get()))
+    }
     fn add(&self, other: Value, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         if let Some(other) = other.unpack_int() {
             self.get()
@@ -0,0 +1,271 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A `Label` type, naming a target in a build, created with `Label()`.
+//!
+//! A label has a repository, a package and a name. How labels are written is decided by
+//! the embedder, with [`Evaluator::set_label_syntax`](crate::eval::Evaluator::set_label_syntax),
+//! defaulting to [`BazelLabelSyntax`].
+//!
+//! ```
+//! # starlark::assert::is_true(r#"
+//! x = Label("@repo//foo/bar")
+//! x.repository == "repo" and x.package == "foo/bar" and x.name == "bar"
+//! # "#);
+//! ```
+
+use crate as starlark;
+use crate::{
+    environment::Globals,
+    values::{StarlarkValue, Value, ValueError},
+};
+use gazebo::prelude::*;
+use std::{
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
+    fmt::{self, Display},
+    hash::{Hash, Hasher},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum LabelError {
+    #[error("Invalid label `{0}`, {1}")]
+    Invalid(String, String),
+    #[error("Label `{0}` is relative to a package, but there is no package to resolve it against")]
+    Relative(String),
+}
+
+/// How labels are written, so an embedder can use the syntax of its build system.
+pub trait LabelSyntax {
+    /// Parse `s` as a label. Labels written relative to a package are resolved against
+    /// the repository and package of `base`, and are an error if `base` is `None`.
+    fn parse(&self, s: &str, base: Option<&Label>) -> anyhow::Result<Label>;
+
+    /// Write the label with the given parts, in a form [`parse`](LabelSyntax::parse) accepts.
+    fn format(&self, repository: &str, package: &str, name: &str) -> String;
+}
+
+/// The syntax of Bazel labels, `@repository//package:name`, used if the embedder doesn't
+/// set one. The repository is empty, and `@repository` left out, for the main repository.
+/// If `:name` is left out, the name is the last component of the package, so `//foo/bar`
+/// is `//foo/bar:bar`. Labels relative to a package are written `:name` or `name`.
+#[derive(Debug, Clone, Copy, Dupe, Default)]
+pub struct BazelLabelSyntax;
+
+// A package or name, where `/` separates the components
+fn check_path(s: &str, label: &str, what: &str) -> anyhow::Result<()> {
+    for x in s.split('/') {
+        let problem = match x {
+            "" => "an empty component".to_owned(),
+            "." | ".." => format!("a `{}` component", x),
+            _ => continue,
+        };
+        let reason = format!("{} has {}", what, problem);
+        return Err(LabelError::Invalid(label.to_owned(), reason).into());
+    }
+    Ok(())
+}
+
+impl LabelSyntax for BazelLabelSyntax {
+    fn parse(&self, s: &str, base: Option<&Label>) -> anyhow::Result<Label> {
+        let invalid = |reason: &str| LabelError::Invalid(s.to_owned(), reason.to_owned());
+        let (repository, rest) = match s.strip_prefix('@') {
+            None => ("", s),
+            Some(rest) => match rest.find("//") {
+                Some(i) => (&rest[..i], &rest[i..]),
+                None => return Err(invalid("expected `//` after the repository").into()),
+            },
+        };
+        if !repository
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(invalid("the repository has invalid characters").into());
+        }
+        let (repository, package, name) = match rest.strip_prefix("//") {
+            Some(rest) => match rest.find(':') {
+                Some(i) => (repository, &rest[..i], &rest[i + 1..]),
+                None => (repository, rest, rest.rsplit('/').next().unwrap()),
+            },
+            None => match base {
+                Some(base) => (
+                    base.repository(),
+                    base.package(),
+                    rest.strip_prefix(':').unwrap_or(rest),
+                ),
+                None => return Err(LabelError::Relative(s.to_owned()).into()),
+            },
+        };
+        if !package.is_empty() {
+            check_path(package, s, "the package")?;
+        }
+        if name.is_empty() {
+            return Err(invalid("the name is empty").into());
+        }
+        check_path(name, s, "the name")?;
+        if name.contains(':') {
+            return Err(invalid("the name contains `:`").into());
+        }
+        Ok(Label::new(repository, package, name, self))
+    }
+
+    fn format(&self, repository: &str, package: &str, name: &str) -> String {
+        if repository.is_empty() {
+            format!("//{}:{}", package, name)
+        } else {
+            format!("@{}//{}:{}", repository, package, name)
+        }
+    }
+}
+
+/// The result of calling `Label()`. Labels are equal if their repository, package and name
+/// are, and are ordered by repository, then package, then name. The `str()` of a label is
+/// the label as written by the [`LabelSyntax`] which created it, and the `repr()` is the
+/// call to `Label()` which creates it, e.g. `Label("//foo:bar")`.
+#[derive(Clone, Debug)]
+pub struct Label {
+    repository: String,
+    package: String,
+    name: String,
+    // The label as written by its syntax, since values can't refer to the evaluator
+    text: Box<str>,
+}
+
+starlark_simple_value!(Label);
+
+impl Label {
+    /// The result of calling `type()` on a label.
+    pub const TYPE: &'static str = "Label";
+
+    /// Create a label, written in the given syntax. Doesn't check that the parts are valid
+    /// in that syntax, which is the job of [`LabelSyntax::parse`].
+    pub fn new(
+        repository: impl Into<String>,
+        package: impl Into<String>,
+        name: impl Into<String>,
+        syntax: &dyn LabelSyntax,
+    ) -> Self {
+        let repository = repository.into();
+        let package = package.into();
+        let name = name.into();
+        let text = syntax.format(&repository, &package, &name).into_boxed_str();
+        Self {
+            repository,
+            package,
+            name,
+            text,
+        }
+    }
+
+    /// The repository, which is empty for the main repository.
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    /// The package, with components separated by `/`.
+    pub fn package(&self) -> &str {
+        &self.package
+    }
+
+    /// The name of the target within the package.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parts(&self) -> (&str, &str, &str) {
+        (&self.repository, &self.package, &self.name)
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[starlark_methods]
+impl Label {
+    /// The repository of the label, which is `""` for the main repository.
+    #[attribute]
+    fn repository(&self) -> String {
+        Ok(self.repository.clone())
+    }
+
+    /// The package of the label, e.g. `"foo/bar"` for `//foo/bar:baz`.
+    #[attribute]
+    fn package(&self) -> String {
+        Ok(self.package.clone())
+    }
+
+    /// The name of the label, e.g. `"baz"` for `//foo/bar:baz`.
+    #[attribute]
+    fn name(&self) -> String {
+        Ok(self.name.clone())
+    }
+
+    /// Parse `s` as a label, resolving labels relative to a package against the package
+    /// of this label.
+    ///
+    /// ```
+    /// # starlark::assert::is_true(r#"
+    /// str(Label("//foo:bar").relative(":baz")) == "//foo:baz"
+    /// # "#);
+    /// ```
+    fn relative(&self, ref s: &str) -> Label {
+        ctx.label_syntax().parse(s, Some(self))
+    }
+}
+
+impl<'v> StarlarkValue<'v> for Label {
+    starlark_type!(Label::TYPE);
+
+    fn get_members(&self) -> Option<&'static Globals> {
+        Self::starlark_members()
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str("Label(");
+        self.text.collect_repr(collector);
+        collector.push(')');
+    }
+
+    fn to_str(&self) -> Option<String> {
+        Some(self.text.to_string())
+    }
+
+    fn collect_json(&self, collector: &mut String) {
+        self.text.collect_json(collector)
+    }
+
+    fn get_hash(&self) -> anyhow::Result<u64> {
+        let mut s = DefaultHasher::new();
+        self.parts().hash(&mut s);
+        Ok(s.finish())
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        Ok(Label::from_value(other).map_or(false, |x| x.parts() == self.parts()))
+    }
+
+    fn compare(&self, other: Value<'v>) -> anyhow::Result<Ordering> {
+        match Label::from_value(other) {
+            Some(x) => Ok(self.parts().cmp(&x.parts())),
+            None => ValueError::unsupported_with(self, "compare", other),
+        }
+    }
+}
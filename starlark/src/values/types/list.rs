@@ -29,7 +29,12 @@ use crate::{
     },
 };
 use gazebo::{any::AnyLifetime, cell::ARef, prelude::*};
-use std::{cmp::Ordering, marker::PhantomData, ops::Deref};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashSet},
+    marker::PhantomData,
+    ops::Deref,
+};
 
 /// Define the list type. See [`List`] and [`FrozenList`] as the two aliases.
 #[derive(Clone, Default_, Debug)]
@@ -61,6 +66,38 @@ impl<'v, T: AllocFrozenValue> AllocFrozenValue for Vec<T> {
     }
 }
 
+/// Sets are allocated as a sorted list, since Starlark has no set type.
+impl<'v, T: AllocValue<'v>> AllocValue<'v> for BTreeSet<T> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        self.into_iter().collect::<Vec<_>>().alloc_value(heap)
+    }
+}
+
+impl<T: AllocFrozenValue> AllocFrozenValue for BTreeSet<T> {
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        self.into_iter()
+            .collect::<Vec<_>>()
+            .alloc_frozen_value(heap)
+    }
+}
+
+/// Sorted so the resulting list doesn't depend on the iteration order of the set.
+impl<'v, T: AllocValue<'v> + Ord, S> AllocValue<'v> for HashSet<T, S> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        let mut xs = self.into_iter().collect::<Vec<_>>();
+        xs.sort();
+        xs.alloc_value(heap)
+    }
+}
+
+impl<T: AllocFrozenValue + Ord, S> AllocFrozenValue for HashSet<T, S> {
+    fn alloc_frozen_value(self, heap: &FrozenHeap) -> FrozenValue {
+        let mut xs = self.into_iter().collect::<Vec<_>>();
+        xs.sort();
+        xs.alloc_frozen_value(heap)
+    }
+}
+
 impl FrozenList {
     /// Obtain the [`FrozenList`] pointed at by a [`FrozenValue`].
     #[allow(clippy::trivially_copy_pass_by_ref)]
@@ -169,19 +206,31 @@ where
         s.push(']');
     }
 
-    fn to_json(&self) -> String {
-        format!(
-            "[{}]",
-            self.content
-                .iter()
-                .map(|e| e.to_json())
-                .enumerate()
-                .fold(String::new(), |accum, s| if s.0 == 0 {
-                    accum + &s.1
-                } else {
-                    accum + "," + &s.1
-                },)
-        )
+    fn collect_repr_cycle(&self, s: &mut String) {
+        s.push_str("[...]");
+    }
+
+    fn collect_json(&self, s: &mut String) {
+        s.push('[');
+        for (i, e) in self.content.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            e.collect_json(s);
+        }
+        s.push(']');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        for v in &self.content {
+            v.collect_proto(name, indent, collector)?;
+        }
+        Ok(())
     }
 
     fn to_bool(&self) -> bool {
@@ -239,16 +288,10 @@ where
 
     fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         if let Some(other) = List::from_value(other) {
-            let mut result = List {
-                content: Vec::with_capacity(self.len() + other.len()),
-            };
-            for x in &self.content {
-                result.content.push(x.to_value());
-            }
-            for x in other.iter() {
-                result.content.push(x);
-            }
-            Ok(heap.alloc(result))
+            let mut content = Vec::with_capacity(self.len() + other.len());
+            content.extend(self.content.iter().map(|x| x.to_value()));
+            content.extend(other.content.iter().copied());
+            Ok(heap.alloc(List { content }))
         } else {
             ValueError::unsupported_with(self, "+", other)
         }
@@ -257,8 +300,12 @@ where
     fn mul(&self, other: Value, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         match other.unpack_int() {
             Some(l) => {
+                let len = self
+                    .len()
+                    .checked_mul(l.max(0) as usize)
+                    .ok_or(ValueError::IntegerOverflow)?;
                 let mut result = List {
-                    content: Vec::new(),
+                    content: Vec::with_capacity(len),
                 };
                 for _i in 0..l {
                     result
@@ -400,4 +447,34 @@ def list_result():
         );
         a.is_true("load('x','list_result')\nx = list_result()\nx += [8]\nx == [1, 2, 4, 8]");
     }
+
+    #[test]
+    fn test_slice_and_concat_copy() {
+        assert::all_true(
+            r#"
+xs = [1, 2, 3, 4, 5]
+ys = xs[1:4]
+ys[0] = 0
+ys == [0, 3, 4] and xs == [1, 2, 3, 4, 5]
+xs[::2] == [1, 3, 5] and xs[3:0:-1] == [4, 3, 2] and xs[4:1] == []
+zs = xs + ys
+zs[0] = 9
+zs == [9, 2, 3, 4, 5, 0, 3, 4] and xs[0] == 1
+[1, 2] * 0 == [] and [1, 2] * -1 == [] and [1] * 3 == [1, 1, 1]
+"#,
+        );
+        // `+=` mutates in place, including when the right side is a list, or the same list
+        assert::is_true(
+            r#"
+xs = [1]
+alias = xs
+xs += [2, 3]
+xs += xs
+ys = [4]
+xs += ys
+ys.append(5)
+alias == [1, 2, 3, 1, 2, 3, 4] and ys == [4, 5]
+"#,
+        );
+    }
 }
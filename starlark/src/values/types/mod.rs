@@ -18,13 +18,19 @@
 pub mod any;
 pub mod bool;
 pub mod dict;
+pub mod dict_view;
 pub mod enumeration;
 pub mod function;
 pub mod int;
+pub mod label;
 pub mod list;
 pub mod none;
+pub mod provider;
 pub mod range;
 pub mod record;
 pub mod string;
+pub mod string_iter;
 pub mod structs;
+pub mod transitive_set;
 pub mod tuple;
+pub mod type_name;
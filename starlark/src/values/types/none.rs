@@ -43,8 +43,8 @@ impl<'v> StarlarkValue<'v> for NoneType {
         s.push_str("None");
     }
 
-    fn to_json(&self) -> String {
-        "null".to_owned()
+    fn collect_json(&self, s: &mut String) {
+        s.push_str("null");
     }
     fn to_bool(&self) -> bool {
         false
@@ -92,3 +92,16 @@ impl<'v, T: UnpackValue<'v>> UnpackValue<'v> for NoneOr<T> {
         }
     }
 }
+
+/// Maps a Starlark `None` to [`None`]. Note that in a [`#[starlark_module]`](macro@starlark_module)
+/// signature an [`Option`] parameter means the argument may be omitted, so use [`NoneOr`] there,
+/// but this instance is useful for nested positions, e.g. `Vec<Option<i32>>`.
+impl<'v, T: UnpackValue<'v>> UnpackValue<'v> for Option<T> {
+    fn unpack_value(value: Value<'v>, heap: &'v Heap) -> Option<Self> {
+        if value.is_none() {
+            Some(None)
+        } else {
+            T::unpack_value(value, heap).map(Some)
+        }
+    }
+}
@@ -0,0 +1,328 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A `provider` type, modelled on the Bazel function of the same name.
+//!
+//! Calling `provider()` produces a [`ProviderCallable`]. Calling [`ProviderCallable`]
+//! with keyword arguments produces a [`ProviderInstance`], a struct-like value whose
+//! type is specific to that provider. The provider takes its name from the variable
+//! it is first assigned to.
+//!
+//! ```
+//! # starlark::assert::is_true(r#"
+//! CcInfo = provider(fields = ["headers", "libs"])
+//! info = CcInfo(headers = ["a.h"])
+//! info.headers == ["a.h"] and not hasattr(info, "libs")
+//! # "#);
+//! ```
+//!
+//! Unlike a record, the fields of a provider are untyped and optional. If `fields`
+//! is not given, the provider accepts any keyword arguments.
+
+use crate::{
+    collections::SmallMap,
+    eval::{ParametersParser, ParametersSpec},
+    values::{
+        comparison::equals_small_map,
+        error::ValueError,
+        function::{FunctionInvoker, NativeFunction, FUNCTION_TYPE},
+        structs::{collect_proto_message, collect_proto_nested},
+        ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike, Walker,
+    },
+};
+use gazebo::{any::AnyLifetime, cell::ARef};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// The result of `provider()`, being the constructor of provider instances.
+#[derive(Clone, Debug)]
+pub struct ProviderCallableGen<V> {
+    typ: Option<String>,
+    doc: Option<String>,
+    // The field names, with their documentation (or `None`).
+    // If `None`, any field names are allowed.
+    fields: Option<SmallMap<String, V>>,
+}
+
+/// A value created by calling a [`ProviderCallable`].
+#[derive(Clone, Debug)]
+pub struct ProviderInstanceGen<V> {
+    typ: V, // Must be ProviderCallable
+    fields: SmallMap<String, V>,
+}
+
+starlark_complex_value!(pub ProviderCallable);
+starlark_complex_value!(pub ProviderInstance);
+
+impl<V> ProviderCallableGen<V> {
+    pub(crate) fn new(doc: Option<String>, fields: Option<SmallMap<String, V>>) -> Self {
+        Self {
+            typ: None,
+            doc,
+            fields,
+        }
+    }
+
+    /// The name of the provider, if it has been assigned to a variable.
+    pub fn name(&self) -> Option<&str> {
+        self.typ.as_deref()
+    }
+
+    /// The documentation passed to `provider()`.
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+}
+
+impl<'v, T: ValueLike<'v>> ProviderInstanceGen<T> {
+    pub const TYPE: &'static str = "provider";
+
+    fn get_provider(&self) -> ARef<'v, ProviderCallable<'v>> {
+        // Safe to unwrap because we always ensure typ is ProviderCallable
+        ProviderCallable::from_value(self.typ.to_value()).unwrap()
+    }
+}
+
+impl<'v> ComplexValue<'v> for ProviderCallable<'v> {
+    // So we can get the name set
+    fn is_mutable(&self) -> bool {
+        true
+    }
+
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        box FrozenProviderCallable {
+            typ: self.typ,
+            doc: self.doc,
+            fields: self.fields.map(|fields| {
+                let mut frozen = SmallMap::with_capacity(fields.len());
+                for (k, v) in fields.into_iter_hashed() {
+                    frozen.insert_hashed(k, v.freeze(freezer));
+                }
+                frozen
+            }),
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        if let Some(fields) = &mut self.fields {
+            fields.values_mut().for_each(|v| walker.walk(v))
+        }
+    }
+
+    fn export_as(&mut self, _heap: &'v Heap, variable_name: &str) {
+        if self.typ.is_none() {
+            self.typ = Some(variable_name.to_owned())
+        }
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for ProviderCallableGen<T>
+where
+    Self: AnyLifetime<'v>,
+{
+    starlark_type!(FUNCTION_TYPE);
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str("provider(");
+        collector.push_str(self.typ.as_deref().unwrap_or("anon"));
+        collector.push(')');
+    }
+
+    fn new_invoker<'a>(
+        &self,
+        me: Value<'v>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<FunctionInvoker<'v, 'a>> {
+        let name = self
+            .typ
+            .as_deref()
+            .unwrap_or(ProviderInstance::TYPE)
+            .to_owned();
+        let mut signature =
+            ParametersSpec::with_capacity(name, self.fields.as_ref().map_or(1, |x| x.len()) + 1);
+        signature.required("me"); // Hidden first argument
+        signature.no_args();
+        match &self.fields {
+            Some(fields) => fields.keys().for_each(|name| signature.optional(name)),
+            None => signature.kwargs("kwargs"),
+        }
+
+        // As with record, we can't capture `me` in the function, so pass it as an argument.
+        let fun = NativeFunction::new(
+            move |context, mut param_parser: ParametersParser| {
+                let me = param_parser.next("me", context.heap())?;
+                let info = ProviderCallable::from_value(me).unwrap();
+                let fields = match &info.fields {
+                    None => param_parser.next("kwargs", context.heap())?,
+                    Some(names) => {
+                        let mut fields = SmallMap::with_capacity(names.len());
+                        for name in names.keys() {
+                            let v: Option<Value> = param_parser.next_opt(name, context.heap())?;
+                            if let Some(v) = v {
+                                fields.insert(name.clone(), v);
+                            }
+                        }
+                        fields
+                    }
+                };
+                Ok(context
+                    .heap()
+                    .alloc_complex(ProviderInstance { typ: me, fields }))
+            },
+            signature,
+        );
+        let mut f = heap.alloc(fun).new_invoker(heap)?;
+        f.push_pos(me);
+        Ok(f)
+    }
+
+    fn dir_attr(&self) -> Vec<String> {
+        vec!["type".to_owned()]
+    }
+
+    fn has_attr(&self, attribute: &str) -> bool {
+        attribute == "type"
+    }
+
+    fn get_attr(&self, attribute: &str, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        if attribute == "type" {
+            Ok(heap.alloc(self.typ.as_deref().unwrap_or(ProviderInstance::TYPE)))
+        } else {
+            Err(ValueError::OperationNotSupported {
+                op: attribute.to_owned(),
+                typ: self.to_repr(),
+            }
+            .into())
+        }
+    }
+}
+
+impl<'v> ComplexValue<'v> for ProviderInstance<'v> {
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        let mut fields = SmallMap::with_capacity(self.fields.len());
+        for (k, v) in self.fields.into_iter_hashed() {
+            fields.insert_hashed(k, v.freeze(freezer));
+        }
+        box FrozenProviderInstance {
+            typ: self.typ.freeze(freezer),
+            fields,
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        walker.walk(&mut self.typ);
+        self.fields.values_mut().for_each(|v| walker.walk(v))
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for ProviderInstanceGen<T>
+where
+    Self: AnyLifetime<'v>,
+{
+    starlark_type!(ProviderInstance::TYPE);
+
+    fn matches_type(&self, ty: &str) -> bool {
+        ty == ProviderInstance::TYPE || Some(ty) == self.get_provider().typ.as_deref()
+    }
+
+    fn collect_json(&self, s: &mut String) {
+        s.push('{');
+        for (i, (k, v)) in self.fields.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            s.push('"');
+            s.push_str(k);
+            s.push_str("\":");
+            v.collect_json(s);
+        }
+        s.push('}');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_nested(name, indent, collector, |c| {
+            collect_proto_message(
+                self.fields.iter().map(|(k, v)| (k.as_str(), *v)),
+                indent + 2,
+                c,
+            )
+        })
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str(self.get_provider().typ.as_deref().unwrap_or("anon"));
+        collector.push('(');
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i != 0 {
+                collector.push_str(", ");
+            }
+            collector.push_str(name);
+            collector.push('=');
+            value.collect_repr(collector);
+        }
+        collector.push(')');
+    }
+
+    fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
+        // Instances of different providers are never equal, even with the same fields.
+        match ProviderInstance::from_value(other) {
+            Some(other) if self.typ.to_value().ptr_eq(other.typ) => {
+                equals_small_map(&self.fields, &other.fields, |x, y| x.equals(*y))
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn get_attr(&self, attribute: &str, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        match self.fields.get(attribute) {
+            Some(v) => Ok(v.to_value()),
+            None => Err(ValueError::OperationNotSupported {
+                op: attribute.to_owned(),
+                typ: self.to_repr(),
+            }
+            .into()),
+        }
+    }
+
+    fn get_hash(&self) -> anyhow::Result<u64> {
+        let mut s = DefaultHasher::new();
+        self.get_provider().typ.hash(&mut s);
+        // Hash in field name order, since `equals` ignores the order of the fields
+        let mut fields = self.fields.iter().collect::<Vec<_>>();
+        fields.sort_by_key(|x| x.0);
+        for (k, v) in fields {
+            k.hash(&mut s);
+            s.write_u64(v.get_hash()?);
+        }
+        Ok(s.finish())
+    }
+
+    fn has_attr(&self, attribute: &str) -> bool {
+        self.fields.contains_key(attribute)
+    }
+
+    fn dir_attr(&self) -> Vec<String> {
+        self.fields.keys().cloned().collect()
+    }
+}
@@ -80,6 +80,15 @@ impl<'a> Iterator for RangeIterator<'a> {
         self.0.start = self.0.start.saturating_add(self.0.step.get());
         Some(Value::new_int(old_start))
     }
+
+    // So that `list(range(n))` allocates the list at the right size up front
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.0.length() {
+            Ok(n) => (n as usize, Some(n as usize)),
+            // The length doesn't fit in an i32
+            Err(_) => (0, None),
+        }
+    }
 }
 
 impl<'v> StarlarkValue<'v> for Range {
@@ -237,7 +246,7 @@ impl<'v> StarlarkIterable<'v> for Range {
 #[cfg(test)]
 mod tests {
     use crate::values::{range::Range, Heap, StarlarkValue, Value};
-    use std::num::NonZeroI32;
+    use std::{iter, num::NonZeroI32};
 
     fn range(start: i32, stop: i32, range: i32) -> Range {
         Range {
@@ -311,6 +320,29 @@ mod tests {
             for (i, v) in full.iter().enumerate() {
                 assert_eq!(x.at(Value::new_int(i as i32), &heap).unwrap(), *v);
             }
+            let size = x.iterate().unwrap().to_iter(&heap).size_hint();
+            assert_eq!(size, (full.len(), Some(full.len())));
+            for i in -6..7 {
+                let i = Value::new_int(i);
+                assert_eq!(x.is_in(i).unwrap(), full.contains(&i));
+            }
+
+            // Slicing a range gives the same elements as slicing the list of its elements
+            let list = heap.alloc(full.clone());
+            let indices = iter::once(None).chain((-4..5).map(|i| Some(Value::new_int(i))));
+            for start in indices.clone() {
+                for stop in indices.clone() {
+                    for stride in &[-3, -2, -1, 1, 2, 3] {
+                        let stride = Some(Value::new_int(*stride));
+                        let sliced = x.slice(start, stop, stride, &heap).unwrap();
+                        let expect = list.slice(start, stop, stride, &heap).unwrap();
+                        assert_eq!(
+                            sliced.iterate_collect(&heap).unwrap(),
+                            expect.iterate_collect(&heap).unwrap()
+                        );
+                    }
+                }
+            }
         }
 
         // Takes 294^2 steps - but completes instantly
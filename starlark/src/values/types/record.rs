@@ -48,6 +48,7 @@ use crate::{
         comparison::equals_slice,
         error::ValueError,
         function::{FunctionInvoker, NativeFunction, FUNCTION_TYPE},
+        structs::{collect_proto_message, collect_proto_nested},
         ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike, Walker,
     },
 };
@@ -319,18 +320,38 @@ where
         ty == Record::TYPE || Some(ty) == self.get_record_type().typ.as_deref()
     }
 
-    fn to_json(&self) -> String {
-        let mut s = "{".to_owned();
-        s += &self
-            .get_record_type()
-            .fields
-            .keys()
-            .zip(&self.values)
-            .map(|(k, v)| format!("\"{}\":{}", k, v.to_json()))
-            .collect::<Vec<String>>()
-            .join(",");
-        s += "}";
-        s
+    fn collect_json(&self, s: &mut String) {
+        s.push('{');
+        let fields = self.get_record_type().fields.keys().zip(&self.values);
+        for (i, (k, v)) in fields.enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            s.push('"');
+            s.push_str(k);
+            s.push_str("\":");
+            v.collect_json(s);
+        }
+        s.push('}');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_nested(name, indent, collector, |c| {
+            collect_proto_message(
+                self.get_record_type()
+                    .fields
+                    .keys()
+                    .map(|k| k.as_str())
+                    .zip(self.values.iter().copied()),
+                indent + 2,
+                c,
+            )
+        })
     }
 
     fn collect_repr(&self, collector: &mut String) {
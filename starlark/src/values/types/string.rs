@@ -20,14 +20,20 @@
 use crate::{
     environment::{Globals, GlobalsStatic},
     values::{
-        fast_string, index::convert_slice_indices, interpolation::Interpolation, AllocFrozenValue,
-        AllocValue, FrozenHeap, FrozenValue, Heap, StarlarkValue, UnpackValue, Value, ValueError,
+        fast_string,
+        index::{convert_index, convert_slice_indices},
+        interpolation::Interpolation,
+        structs::collect_proto_scalar,
+        AllocFrozenValue, AllocValue, FrozenHeap, FrozenValue, Heap, IntoFrozenValue, IntoValue,
+        StarlarkValue, UnpackValue, Value, ValueError,
     },
 };
+use anyhow::anyhow;
 use std::{
     cmp::Ordering,
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
+    path::{Path, PathBuf},
 };
 
 /// The result of calling `type()` on strings.
@@ -57,6 +63,40 @@ impl<'v> AllocValue<'v> for &'_ str {
     }
 }
 
+fn path_str(x: &Path) -> anyhow::Result<&str> {
+    x.to_str().ok_or_else(|| {
+        anyhow!(
+            "Path `{}` is not valid UTF-8 so cannot be converted to a string",
+            x.display()
+        )
+    })
+}
+
+/// Paths are converted to strings, failing if the path is not valid UTF-8.
+impl<'v> IntoValue<'v> for &'_ Path {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        Ok(heap.alloc(path_str(self)?))
+    }
+}
+
+impl<'v> IntoValue<'v> for PathBuf {
+    fn into_value(self, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        self.as_path().into_value(heap)
+    }
+}
+
+impl IntoFrozenValue for &'_ Path {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        Ok(heap.alloc(path_str(self)?))
+    }
+}
+
+impl IntoFrozenValue for PathBuf {
+    fn into_frozen_value(self, heap: &FrozenHeap) -> anyhow::Result<FrozenValue> {
+        self.as_path().into_frozen_value(heap)
+    }
+}
+
 impl<'v> UnpackValue<'v> for &'v str {
     fn unpack_value(value: Value<'v>, _heap: &Heap) -> Option<Self> {
         value.unpack_str()
@@ -74,6 +114,47 @@ pub(crate) fn hash_string_value<H: Hasher>(x: &str, state: &mut H) {
     x.hash(state)
 }
 
+/// Index a string by UTF-8 byte, rather than by character, as used when the
+/// [`Dialect`](crate::syntax::Dialect) enables byte strings.
+/// A byte which is part of a multi-byte character becomes U+FFFD.
+pub(crate) fn byte_at<'v>(x: &str, index: Value, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+    let i = convert_index(index, x.len() as i32)? as usize;
+    Ok(heap.alloc(String::from_utf8_lossy(&x.as_bytes()[i..i + 1]).into_owned()))
+}
+
+/// Slice a string by UTF-8 byte, rather than by character, as used when the
+/// [`Dialect`](crate::syntax::Dialect) enables byte strings.
+/// Any character which is split by the slice becomes U+FFFD.
+pub(crate) fn byte_slice<'v>(
+    x: &str,
+    start: Option<Value>,
+    stop: Option<Value>,
+    stride: Option<Value>,
+    heap: &'v Heap,
+) -> anyhow::Result<Value<'v>> {
+    let (start, stop, stride) = convert_slice_indices(x.len() as i32, start, stop, stride)?;
+    let (low, take, astride) = if stride < 0 {
+        (stop + 1, start - stop, -stride)
+    } else {
+        (start, stop - start, stride)
+    };
+    if take <= 0 {
+        return Ok(heap.alloc(""));
+    }
+    let bytes = &x.as_bytes()[low as usize..(low + take) as usize];
+    let res: Vec<u8> = if stride > 0 {
+        bytes.iter().step_by(astride as usize).copied().collect()
+    } else {
+        bytes
+            .iter()
+            .rev()
+            .step_by(astride as usize)
+            .copied()
+            .collect()
+    };
+    Ok(heap.alloc(String::from_utf8_lossy(&res).into_owned()))
+}
+
 impl<'v> StarlarkValue<'v> for Box<str> {
     starlark_type!(STRING_TYPE);
 
@@ -130,18 +211,51 @@ impl<'v> StarlarkValue<'v> for Box<str> {
         buffer.push('"');
     }
 
-    fn to_json(&self) -> String {
-        let mut escaped = self.as_ref().to_owned();
+    fn collect_json(&self, s: &mut String) {
+        s.reserve(2 + self.len());
+        s.push('"');
         // Escape as per ECMA-404 standard
-        escaped = escaped.replace("\u{005C}", "\\\\");
-        escaped = escaped.replace("\u{0022}", "\\\"");
-        escaped = escaped.replace("\u{002F}", "\\/");
-        escaped = escaped.replace("\u{0008}", "\\b");
-        escaped = escaped.replace("\u{000C}", "\\f");
-        escaped = escaped.replace("\u{000A}", "\\n");
-        escaped = escaped.replace("\u{000D}", "\\r");
-        escaped = escaped.replace("\u{0009}", "\\t");
-        format!("\"{}\"", escaped)
+        for c in self.as_ref().chars() {
+            match c {
+                '\u{005C}' => s.push_str("\\\\"),
+                '\u{0022}' => s.push_str("\\\""),
+                '\u{002F}' => s.push_str("\\/"),
+                '\u{0008}' => s.push_str("\\b"),
+                '\u{000C}' => s.push_str("\\f"),
+                '\u{000A}' => s.push_str("\\n"),
+                '\u{000D}' => s.push_str("\\r"),
+                '\u{0009}' => s.push_str("\\t"),
+                c => s.push(c),
+            }
+        }
+        s.push('"');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        // Escape as per the protocol buffer text format, which uses C-style escapes
+        let mut escaped = String::with_capacity(self.len() + 2);
+        escaped.push('"');
+        for c in self.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                    escaped.push_str(&format!("\\{:03o}", c as u32))
+                }
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        collect_proto_scalar(name, indent, &escaped, collector);
+        Ok(())
     }
 
     fn to_bool(&self) -> bool {
@@ -204,7 +318,7 @@ impl<'v> StarlarkValue<'v> for Box<str> {
     fn is_in(&self, other: Value) -> anyhow::Result<bool> {
         match other.unpack_str() {
             Some(s) => Ok(self.contains(s)),
-            None => Err(ValueError::IncorrectParameterType.into()),
+            None => ValueError::unsupported_owned(other.get_type(), "in", Some(STRING_TYPE)),
         }
     }
 
@@ -215,7 +329,8 @@ impl<'v> StarlarkValue<'v> for Box<str> {
         stride: Option<Value>,
         heap: &'v Heap,
     ) -> anyhow::Result<Value<'v>> {
-        let (start, stop, stride) = convert_slice_indices(self.len() as i32, start, stop, stride)?;
+        let (start, stop, stride) =
+            convert_slice_indices(fast_string::len(self) as i32, start, stop, stride)?;
         let (low, take, astride) = if stride < 0 {
             (stop + 1, start - stop, -stride)
         } else {
@@ -348,6 +463,49 @@ len("😿") == 1
         );
     }
 
+    #[test]
+    fn test_string_unicode() {
+        assert::all_true(
+            r#"
+"世界"[-1:] == "界"
+"a世b界"[1:3] == "世b"
+"a世b界"[::-2] == "界世"
+len("a世b界") == 4
+"a世b界".find("b") == 2
+"a世b界".rfind("界", 1) == 3
+"世世".count("世", 1) == 1
+"abc".count("") == 4
+"#,
+        );
+    }
+
+    #[test]
+    fn test_byte_strings() {
+        let mut a = assert::Assert::new();
+        a.dialect_set(|x| x.enable_byte_strings = true);
+        a.all_true(
+            r#"
+len("a世") == 4
+"a世"[0] == "a"
+"a世"[1] == "\ufffd"
+"a世b"[1:4] == "世"
+"a世b"[:2] == "a\ufffd"
+"a世b"[::-4] == "ba"
+"a世b".find("b") == 4
+"a世b".index("世") == 1
+"a世b世".rfind("世") == 5
+"a世b".count("b", 2) == 1
+"#,
+        );
+        a.is_true(
+            r#"
+def f(x):
+    return len(x)
+f("世") == 3
+"#,
+        );
+    }
+
     #[test]
     fn test_string_is_in() {
         assert::all_true(
@@ -0,0 +1,133 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The iterables returned by `elems()`, `elem_ords()`, `codepoints()` and `codepoint_ords()`.
+//!
+//! Our strings are indexed by Unicode code point, so the elements of a string are its
+//! code points, and `elems()` and `codepoints()` produce the same sequence.
+
+use crate::values::{
+    iter::StarlarkIterable, ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value,
+    ValueLike, Walker,
+};
+use gazebo::any::AnyLifetime;
+
+/// Which method produced a [`StringIterable`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StringIterableKind {
+    /// `elems()`, producing one character strings.
+    Elems,
+    /// `elem_ords()`, producing the integer value of each character.
+    ElemOrds,
+    /// `codepoints()`, producing one character strings.
+    Codepoints,
+    /// `codepoint_ords()`, producing the integer value of each character.
+    CodepointOrds,
+}
+
+impl StringIterableKind {
+    fn method(self) -> &'static str {
+        match self {
+            Self::Elems => "elems",
+            Self::ElemOrds => "elem_ords",
+            Self::Codepoints => "codepoints",
+            Self::CodepointOrds => "codepoint_ords",
+        }
+    }
+
+    fn ords(self) -> bool {
+        match self {
+            Self::Elems | Self::Codepoints => false,
+            Self::ElemOrds | Self::CodepointOrds => true,
+        }
+    }
+}
+
+/// Define the string iterable type. See [`StringIterable`] and [`FrozenStringIterable`] as the two aliases.
+#[derive(Clone, Debug)]
+pub struct StringIterableGen<T> {
+    // Must be a string
+    string: T,
+    kind: StringIterableKind,
+}
+
+starlark_complex_value!(pub StringIterable);
+
+impl<T> StringIterableGen<T> {
+    /// The result of calling `type()` on a string iterable.
+    pub const TYPE: &'static str = "iterable";
+
+    /// Which method produced this iterable.
+    pub fn kind(&self) -> StringIterableKind {
+        self.kind
+    }
+}
+
+impl<'v> StringIterable<'v> {
+    /// Create an iterable over `string`, which must be a string.
+    pub(crate) fn new(string: Value<'v>, kind: StringIterableKind) -> Self {
+        Self { string, kind }
+    }
+}
+
+impl<'v> ComplexValue<'v> for StringIterable<'v> {
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        box FrozenStringIterable {
+            string: self.string.freeze(freezer),
+            kind: self.kind,
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        walker.walk(&mut self.string);
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for StringIterableGen<T>
+where
+    Self: AnyLifetime<'v>,
+{
+    starlark_type!(StringIterable::TYPE);
+
+    fn collect_repr(&self, s: &mut String) {
+        self.string.collect_repr(s);
+        s.push('.');
+        s.push_str(self.kind.method());
+        s.push_str("()");
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn iterate(&self) -> anyhow::Result<&(dyn StarlarkIterable<'v> + 'v)> {
+        Ok(self)
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkIterable<'v> for StringIterableGen<T> {
+    fn to_iter<'a>(&'a self, heap: &'v Heap) -> Box<dyn Iterator<Item = Value<'v>> + 'a>
+    where
+        'v: 'a,
+    {
+        let s = self.string.to_value().unpack_str().unwrap();
+        if self.kind.ords() {
+            return box s.chars().map(|c| Value::new_int(u32::from(c) as i32));
+        }
+        box s.chars().map(move |c| heap.alloc(c.to_string()))
+    }
+}
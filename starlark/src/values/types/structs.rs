@@ -37,11 +37,13 @@
 use crate::{
     collections::SmallMap,
     environment::{Globals, GlobalsStatic},
+    eval::{Evaluator, ParametersParser, ParametersSpec},
     values::{
         comparison::{compare_small_map, equals_small_map},
         error::ValueError,
-        AllocValue, ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike,
-        Walker,
+        function::NativeFunction,
+        AllocValue, ComplexValue, Freezer, FrozenValue, Heap, SimpleValue, StarlarkValue, Value,
+        ValueLike, Walker,
     },
 };
 use gazebo::any::AnyLifetime;
@@ -63,6 +65,61 @@ impl<T> StructGen<T> {
 
 starlark_complex_value!(pub Struct);
 
+impl<'v, T: ValueLike<'v>> StructGen<T> {
+    /// Write the fields of the struct as the body of a message in protocol buffer
+    /// text format, as returned by `struct.to_proto()`.
+    pub(crate) fn collect_proto_fields(
+        &self,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_message(
+            self.fields.iter().map(|(k, v)| (k.as_str(), *v)),
+            indent,
+            collector,
+        )
+    }
+}
+
+/// Write the fields of a message in protocol buffer text format, in order of field name.
+pub(crate) fn collect_proto_message<'a, 'v, T: ValueLike<'v>>(
+    fields: impl Iterator<Item = (&'a str, T)>,
+    indent: usize,
+    collector: &mut String,
+) -> anyhow::Result<()> {
+    let mut fields: Vec<_> = fields.collect();
+    fields.sort_by_key(|x| x.0);
+    for (k, v) in fields {
+        v.collect_proto(k, indent, collector)?;
+    }
+    Ok(())
+}
+
+/// Write a nested message field in protocol buffer text format,
+/// with `body` writing the fields of the message.
+pub(crate) fn collect_proto_nested(
+    name: &str,
+    indent: usize,
+    collector: &mut String,
+    body: impl FnOnce(&mut String) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    collector.push_str(&format!("{:indent$}{} {{\n", "", name, indent = indent));
+    body(collector)?;
+    collector.push_str(&format!("{:indent$}}}\n", "", indent = indent));
+    Ok(())
+}
+
+/// Write a scalar field in protocol buffer text format.
+pub(crate) fn collect_proto_scalar(name: &str, indent: usize, value: &str, collector: &mut String) {
+    collector.push_str(&format!(
+        "{:indent$}{}: {}\n",
+        "",
+        name,
+        value,
+        indent = indent
+    ));
+}
+
 /// The result of calling `struct()`.
 #[derive(Clone, Default, Debug)]
 pub struct StructGen<T> {
@@ -89,6 +146,56 @@ impl<'v> StructBuilder<'v> {
         self.1.insert(key.into(), self.0.alloc(val));
     }
 
+    /// Add a function to the underlying [`Struct`], which calls `f` with its arguments
+    /// collected according to `parameters`, as for a [`NativeFunction`]. Builds a one-off
+    /// value with methods from closures, without defining a new type, e.g. to pass the
+    /// capabilities of a single evaluation:
+    ///
+    /// ```
+    /// # fn main() {
+    /// use starlark::environment::{Globals, Module};
+    /// use starlark::eval::{Evaluator, ParametersSpec};
+    /// use starlark::syntax::{AstModule, Dialect};
+    /// use starlark::values::{structs::StructBuilder, Value};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let logged = Arc::new(Mutex::new(Vec::new()));
+    /// let module = Module::new();
+    /// let mut ctx = StructBuilder::new(module.heap());
+    /// ctx.add("name", "test");
+    /// let mut parameters = ParametersSpec::new("log".to_owned());
+    /// parameters.required("message");
+    /// let log = logged.clone();
+    /// ctx.add_function("log", parameters, move |eval, mut args| {
+    ///     let message: String = args.next("message", eval.heap())?;
+    ///     log.lock().unwrap().push(message);
+    ///     Ok(Value::new_none())
+    /// });
+    /// module.inject("ctx", module.heap().alloc(ctx.build()));
+    ///
+    /// let globals = Globals::standard();
+    /// let mut eval = Evaluator::new(&module, &globals);
+    /// let program = "ctx.log('hello ' + ctx.name)".to_owned();
+    /// let ast = AstModule::parse("ctx.star", program, &Dialect::Standard).unwrap();
+    /// eval.eval_module(ast).unwrap();
+    /// assert_eq!(*logged.lock().unwrap(), vec!["hello test"]);
+    /// # }
+    /// ```
+    pub fn add_function(
+        &mut self,
+        key: impl Into<String>,
+        parameters: ParametersSpec<FrozenValue>,
+        f: impl for<'a> Fn(
+                &mut Evaluator<'a, '_>,
+                ParametersParser<'a, '_>,
+            ) -> anyhow::Result<Value<'a>>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.add(key, NativeFunction::new(f, parameters));
+    }
+
     /// Finish building and produce a [`Struct`].
     pub fn build(self) -> Struct<'v> {
         Struct { fields: self.1 }
@@ -121,16 +228,29 @@ where
         RES.members(crate::stdlib::structs::struct_members)
     }
 
-    fn to_json(&self) -> String {
-        let mut s = "{".to_owned();
-        s += &self
-            .fields
-            .iter()
-            .map(|(k, v)| format!("\"{}\":{}", k, v.to_json()))
-            .collect::<Vec<String>>()
-            .join(",");
-        s += "}";
-        s
+    fn collect_json(&self, s: &mut String) {
+        s.push('{');
+        for (i, (k, v)) in self.fields.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            s.push('"');
+            s.push_str(k);
+            s.push_str("\":");
+            v.collect_json(s);
+        }
+        s.push('}');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        collect_proto_nested(name, indent, collector, |c| {
+            self.collect_proto_fields(indent + 2, c)
+        })
     }
 
     fn collect_repr(&self, r: &mut String) {
@@ -191,7 +311,10 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::assert;
+    use crate::{
+        assert,
+        values::{Heap, ValueLike},
+    };
 
     #[test]
     fn test_to_json() {
@@ -217,4 +340,43 @@ struct(foo = [struct(bar = "some")]).to_json() == '{"foo":[{"bar":"some"}]}'
 "#,
         );
     }
+
+    #[test]
+    fn test_to_json_nested() {
+        assert::all_true(
+            r#"
+struct(a = (1, [True, None]), b = {"x": "y"}).to_json() == '{"a":[1,[true,null]],"b":{"x": "y"}}'
+struct(a = struct(), b = [], c = ()).to_json() == '{"a":{},"b":[],"c":[]}'
+struct(a = {"k": 1}.items()).to_json() == '{"a":[["k",1]]}'
+len(struct(a = [["x"] * 100] * 100).to_json()) == len('{"a":[]}') + 100 * len('[' + ','.join(['"x"'] * 100) + ']') + 99
+"#,
+        );
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        // Values only implement `collect_json`, which `to_json` builds on
+        let heap = Heap::new();
+        let x = heap.alloc(vec![heap.alloc("a"), heap.alloc(1)]);
+        assert_eq!(x.to_json(), "[\"a\",1]");
+    }
+
+    #[test]
+    fn test_to_proto() {
+        assert::all_true(
+            r#"
+struct().to_proto() == ""
+struct(b = 1, a = "x").to_proto() == 'a: "x"\nb: 1\n'
+struct(key = 'a"b\\c\n').to_proto() == 'key: "a\\"b\\\\c\\n"\n'
+struct(key = [True, False]).to_proto() == 'key: true\nkey: false\n'
+struct(key = (struct(x = 1), struct(x = 2))).to_proto() == 'key {\n  x: 1\n}\nkey {\n  x: 2\n}\n'
+struct(key = {"a": struct(x = 1)}).to_proto() == 'key {\n  key: "a"\n  value {\n    x: 1\n  }\n}\n'
+"#,
+        );
+        assert::fail("struct(key = None).to_proto()", "not supported");
+        assert::fail(
+            "x = []; x.append(x); struct(a = x).to_proto()",
+            "Too many recursion levels",
+        );
+    }
 }
@@ -0,0 +1,284 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A `depset` type, modelled on the Bazel type of the same name, created with `depset()`.
+//!
+//! A [`TransitiveSet`] holds its direct elements and the sets it was created from,
+//! rather than a flattened copy of their elements, so creating a set from others is cheap,
+//! and sets shared by many others are only stored once. The elements are only flattened,
+//! removing duplicates, when calling `to_list()`.
+//!
+//! ```
+//! # starlark::assert::is_true(r#"
+//! a = depset(["a.h"])
+//! b = depset(["b.h"], transitive = [a])
+//! depset(["c.h"], transitive = [a, b]).to_list() == ["a.h", "b.h", "c.h"]
+//! # "#);
+//! ```
+
+use crate::{
+    collections::SmallSet,
+    environment::{Globals, GlobalsStatic},
+    values::{ComplexValue, Freezer, Heap, SimpleValue, StarlarkValue, Value, ValueLike, Walker},
+};
+use gazebo::{any::AnyLifetime, prelude::*};
+use std::collections::HashSet;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+enum TransitiveSetError {
+    #[error("depset `transitive` must only contain depsets, got `{0}`")]
+    NotTransitiveSet(String),
+    #[error("depset order `{0}` is incompatible with the order `{1}` of a transitive depset")]
+    IncompatibleOrder(&'static str, &'static str),
+}
+
+/// The order in which the elements of a [`TransitiveSet`] are flattened.
+#[derive(Clone, Copy, Dupe, Debug, PartialEq, Eq)]
+pub enum TransitiveSetOrder {
+    /// The order if none is given, currently the same as `Postorder`.
+    /// Compatible with sets of any order.
+    Default,
+    /// The elements of the transitive sets, left to right, then the direct elements.
+    Postorder,
+    /// The direct elements, then the elements of the transitive sets, left to right.
+    Preorder,
+    /// Each set before the sets it was created from, and an element shared by several sets
+    /// after all of them, e.g. for linking libraries before their dependencies.
+    Topological,
+}
+
+impl TransitiveSetOrder {
+    const ALL: &'static [Self] = &[
+        Self::Default,
+        Self::Postorder,
+        Self::Preorder,
+        Self::Topological,
+    ];
+
+    /// The name of the order, as passed to `depset()`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Postorder => "postorder",
+            Self::Preorder => "preorder",
+            Self::Topological => "topological",
+        }
+    }
+
+    /// The order with a given name, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|x| x.name() == name)
+    }
+
+    fn is_compatible(self, other: Self) -> bool {
+        self == other || self == Self::Default || other == Self::Default
+    }
+}
+
+/// The result of calling `depset()`.
+#[derive(Clone, Debug)]
+pub struct TransitiveSetGen<V> {
+    order: TransitiveSetOrder,
+    // The elements given to this set, which are hashable
+    direct: Vec<V>,
+    // The sets this set was created from, which are non-empty `TransitiveSet` values
+    transitive: Vec<V>,
+}
+
+starlark_complex_value!(pub TransitiveSet);
+
+impl<V> TransitiveSetGen<V> {
+    /// The result of calling `type()` on a depset.
+    pub const TYPE: &'static str = "depset";
+
+    /// The order in which the elements are flattened.
+    pub fn order(&self) -> TransitiveSetOrder {
+        self.order
+    }
+
+    fn is_empty(&self) -> bool {
+        self.direct.is_empty() && self.transitive.is_empty()
+    }
+}
+
+impl<'v> TransitiveSet<'v> {
+    /// Create a set with the `direct` elements, which must be hashable, and the elements
+    /// of the `transitive` sets, which must be [`TransitiveSet`] values with an order
+    /// compatible with `order`. If there are no direct elements, and only one non-empty
+    /// transitive set, with the same order, that set is returned rather than a new one.
+    pub fn new(
+        order: TransitiveSetOrder,
+        direct: Vec<Value<'v>>,
+        transitive: Vec<Value<'v>>,
+        heap: &'v Heap,
+    ) -> anyhow::Result<Value<'v>> {
+        for x in &direct {
+            x.get_hashed()?;
+        }
+        let mut children = Vec::with_capacity(transitive.len());
+        for x in transitive {
+            let set = TransitiveSet::from_value(x)
+                .ok_or_else(|| TransitiveSetError::NotTransitiveSet(x.get_type().to_owned()))?;
+            if !order.is_compatible(set.order) {
+                return Err(
+                    TransitiveSetError::IncompatibleOrder(order.name(), set.order.name()).into(),
+                );
+            }
+            if !set.is_empty() {
+                children.push((x, set.order));
+            }
+        }
+        match children.as_slice() {
+            [(x, child_order)] if direct.is_empty() && *child_order == order => Ok(*x),
+            _ => Ok(heap.alloc(TransitiveSet {
+                order,
+                direct,
+                transitive: children.into_map(|x| x.0),
+            })),
+        }
+    }
+}
+
+impl<'v, T: ValueLike<'v>> TransitiveSetGen<T> {
+    /// All the elements of the set, without duplicates, in the order of the set.
+    pub fn to_list(&self) -> Vec<Value<'v>> {
+        let mut flatten = Flatten {
+            order: self.order,
+            visited: HashSet::new(),
+            work: Vec::new(),
+            elements: SmallSet::new(),
+        };
+        flatten.schedule(self);
+        flatten.run();
+        let mut res = flatten.elements.into_iter().collect::<Vec<_>>();
+        if self.order == TransitiveSetOrder::Topological {
+            res.reverse();
+        }
+        res
+    }
+}
+
+// What is left to do when flattening a set.
+enum Work<'v> {
+    // Visit a set, unless it has been visited already
+    Visit(Value<'v>),
+    // Add a direct element
+    Add(Value<'v>),
+}
+
+// Flattening a set, visiting each set reachable from it once, however many sets it was
+// created from. A topological order is produced in reverse, then reversed by the caller.
+// The work is kept on a stack, rather than recursing, so sets nested arbitrarily deeply
+// don't overflow the Rust stack.
+struct Flatten<'v> {
+    order: TransitiveSetOrder,
+    // The pointers of the sets already visited
+    visited: HashSet<usize>,
+    // The work still to do, with the next item at the end
+    work: Vec<Work<'v>>,
+    elements: SmallSet<Value<'v>>,
+}
+
+impl<'v> Flatten<'v> {
+    // Push the work for a set, in the reverse of the order it should be done.
+    fn schedule<T: ValueLike<'v>>(&mut self, set: &TransitiveSetGen<T>) {
+        let visit = |x: &T| Work::Visit(x.to_value());
+        let add = |x: &T| Work::Add(x.to_value());
+        match self.order {
+            TransitiveSetOrder::Default | TransitiveSetOrder::Postorder => {
+                self.work.extend(set.direct.iter().rev().map(add));
+                self.work.extend(set.transitive.iter().rev().map(visit));
+            }
+            TransitiveSetOrder::Preorder => {
+                self.work.extend(set.transitive.iter().rev().map(visit));
+                self.work.extend(set.direct.iter().rev().map(add));
+            }
+            TransitiveSetOrder::Topological => {
+                self.work.extend(set.direct.iter().map(add));
+                self.work.extend(set.transitive.iter().map(visit));
+            }
+        }
+    }
+
+    fn run(&mut self) {
+        while let Some(x) = self.work.pop() {
+            match x {
+                Work::Visit(x) => {
+                    if self.visited.insert(x.ptr_value()) {
+                        // Safe to unwrap because we check the transitive values are sets
+                        // when creating them
+                        self.schedule(&*TransitiveSet::from_value(x).unwrap());
+                    }
+                }
+                Work::Add(x) => {
+                    // Safe to unwrap because we check the direct values are hashable
+                    // when creating them
+                    self.elements.insert_hashed(x.get_hashed().unwrap());
+                }
+            }
+        }
+    }
+}
+
+impl<'v> ComplexValue<'v> for TransitiveSet<'v> {
+    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
+        box FrozenTransitiveSet {
+            order: self.order,
+            direct: self.direct.into_map(|x| x.freeze(freezer)),
+            transitive: self.transitive.into_map(|x| x.freeze(freezer)),
+        }
+    }
+
+    unsafe fn walk(&mut self, walker: &Walker<'v>) {
+        self.direct.iter_mut().for_each(|x| walker.walk(x));
+        self.transitive.iter_mut().for_each(|x| walker.walk(x));
+    }
+}
+
+impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for TransitiveSetGen<T>
+where
+    Self: AnyLifetime<'v>,
+{
+    starlark_type!(TransitiveSet::TYPE);
+
+    fn get_members(&self) -> Option<&'static Globals> {
+        static RES: GlobalsStatic = GlobalsStatic::new();
+        RES.members(crate::stdlib::transitive_set::transitive_set_members)
+    }
+
+    fn collect_repr(&self, collector: &mut String) {
+        collector.push_str("depset([");
+        for (i, x) in self.to_list().iter().enumerate() {
+            if i != 0 {
+                collector.push_str(", ");
+            }
+            x.collect_repr(collector);
+        }
+        collector.push(']');
+        if self.order != TransitiveSetOrder::Default {
+            collector.push_str(", order = \"");
+            collector.push_str(self.order.name());
+            collector.push('"');
+        }
+        collector.push(')');
+    }
+
+    fn to_bool(&self) -> bool {
+        !self.is_empty()
+    }
+}
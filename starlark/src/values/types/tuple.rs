@@ -20,11 +20,18 @@
 use crate::values::{
     comparison::{compare_slice, equals_slice},
     index::{convert_index, convert_slice_indices},
-    AllocValue, ComplexValue, Freezer, Heap, SimpleValue, StarlarkIterable, StarlarkValue,
-    UnpackValue, Value, ValueError, ValueLike, Walker,
+    AllocValue, FromValue, FrozenValue, Heap, StarlarkIterable, StarlarkValue, UnpackValue, Value,
+    ValueError, ValueLike, Walker,
+};
+use gazebo::{any::AnyLifetime, cell::ARef, prelude::*};
+use std::{
+    cmp::Ordering,
+    collections::hash_map::DefaultHasher,
+    fmt,
+    fmt::{Debug, Formatter},
+    hash::Hasher,
+    slice,
 };
-use gazebo::{any::AnyLifetime, prelude::*};
-use std::{cmp::Ordering, collections::hash_map::DefaultHasher, hash::Hasher};
 
 /// Used by both list and tuple to implement the slice function
 pub(crate) fn slice_vector<'a, 'v, V: ValueLike<'v> + 'a, I: Iterator<Item = &'a V>>(
@@ -41,11 +48,15 @@ pub(crate) fn slice_vector<'a, 'v, V: ValueLike<'v> + 'a, I: Iterator<Item = &'a
     if take <= 0 {
         return Vec::new();
     }
-    let mut v: Vec<Value> = content
+    let v = content
         .skip(low as usize)
         .take(take as usize)
-        .map(|e| e.to_value())
-        .collect();
+        .map(|e| e.to_value());
+    if stride == 1 {
+        // The common case, which can be collected directly with the right capacity
+        return v.collect();
+    }
+    let mut v: Vec<Value> = v.collect();
     if stride < 0 {
         v.reverse();
     }
@@ -62,50 +73,112 @@ pub(crate) fn slice_vector<'a, 'v, V: ValueLike<'v> + 'a, I: Iterator<Item = &'a
 }
 
 /// Define the tuple type. See [`Tuple`] and [`FrozenTuple`] as the two aliases.
-#[derive(Clone, Default_, Debug)]
+///
+/// Tuples are allocated with [`Heap::alloc_tuple`], which stores the elements
+/// in the same allocation as the tuple, so there is no separate [`Vec`].
 pub struct TupleGen<T> {
-    /// The data stored by the tuple.
-    pub content: Vec<T>,
+    len: usize,
+    // Points at the elements, which are stored immediately before the tuple.
+    // Valid for as long as the tuple is.
+    content: *const T,
 }
 
-starlark_complex_value!(pub Tuple);
+/// A tuple on the [`Heap`].
+pub type Tuple<'v> = TupleGen<Value<'v>>;
 
-impl<'v, V: ValueLike<'v>> TupleGen<V> {
-    /// Create a new tuple.
-    pub fn new(content: Vec<V>) -> Self {
-        Self { content }
+/// A tuple on the [`FrozenHeap`](crate::values::FrozenHeap).
+pub type FrozenTuple = TupleGen<FrozenValue>;
+
+any_lifetime!(Tuple<'v>);
+any_lifetime!(FrozenTuple);
+
+impl<T> TupleGen<T> {
+    pub const TYPE: &'static str = "tuple";
+
+    // The caller must ensure there are `len` elements at `content`, which live as long as the tuple.
+    pub(crate) unsafe fn from_raw(len: usize, content: *const T) -> Self {
+        Self { len, content }
+    }
+
+    /// The elements of the tuple.
+    pub fn content(&self) -> &[T] {
+        unsafe { slice::from_raw_parts(self.content, self.len) }
+    }
+
+    // Only safe while nothing else is looking at the elements, e.g. during GC or freezing.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn content_mut(&self) -> &mut [T] {
+        slice::from_raw_parts_mut(self.content as *mut T, self.len)
     }
 
     /// Get the length of the tuple.
     pub fn len(&self) -> usize {
-        self.content.len()
+        self.len
     }
+}
 
-    /// Iterate over the elements of the tuple.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Value<'v>> + 'a
-    where
-        'v: 'a,
-    {
-        self.content.iter().map(|e| e.to_value())
+impl<T: Debug> Debug for TupleGen<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TupleGen")
+            .field("content", &self.content())
+            .finish()
     }
 }
 
-impl<'v> ComplexValue<'v> for Tuple<'v> {
-    fn freeze(self: Box<Self>, freezer: &Freezer) -> Box<dyn SimpleValue> {
-        let mut frozen = Vec::with_capacity(self.content.len());
-        for v in self.content {
-            frozen.push(v.freeze(freezer))
+impl<'v> Tuple<'v> {
+    /// Downcast a [`Value`] to a [`Tuple`], if it is one.
+    pub fn from_value(x: Value<'v>) -> Option<ARef<'v, Self>> {
+        fn promote<'v>(x: &FrozenTuple) -> &Tuple<'v> {
+            unsafe {
+                // Safe because we know Value and FrozenValue have the same bit patterns where they overlap
+                &*(x as *const FrozenTuple as *const Tuple)
+            }
         }
-        box FrozenTuple { content: frozen }
+
+        x.downcast_ref::<FrozenTuple>()
+            .map(|o| ARef::map(o, |e| promote(e)))
+            .or_else(|| x.downcast_ref::<Tuple<'v>>())
     }
 
-    unsafe fn walk(&mut self, walker: &Walker<'v>) {
-        self.content.iter_mut().for_each(|x| walker.walk(x))
+    // Only safe during garbage collection, when the elements belong to the new heap.
+    pub(crate) unsafe fn walk(&self, walker: &Walker<'v>) {
+        self.content_mut().iter_mut().for_each(|x| walker.walk(x))
     }
 }
 
-impl<T> TupleGen<T> {
-    pub const TYPE: &'static str = "tuple";
+impl<'v> Tuple<'v> {
+    /// Create a tuple with the given elements, to be allocated with [`Heap::alloc`],
+    /// e.g. `heap.alloc(Tuple::new(vec![x, y]))`.
+    pub fn new<T: AllocValue<'v>>(content: Vec<T>) -> AllocTuple<T> {
+        AllocTuple(content)
+    }
+}
+
+/// The elements of a tuple not yet allocated, as created by [`Tuple::new`].
+/// Becomes a [`Tuple`] when allocated with [`Heap::alloc`].
+#[derive(Debug)]
+pub struct AllocTuple<T>(Vec<T>);
+
+impl<'v, T: AllocValue<'v>> AllocValue<'v> for AllocTuple<T> {
+    fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
+        heap.alloc_tuple(&self.0.into_map(|x| x.alloc_value(heap)))
+    }
+}
+
+impl<'v> FromValue<'v> for Tuple<'v> {
+    fn from_value(x: Value<'v>) -> Option<ARef<'v, Self>> {
+        Tuple::from_value(x)
+    }
+}
+
+impl<'v, V: ValueLike<'v>> TupleGen<V> {
+    /// Iterate over the elements of the tuple.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Value<'v>> + 'a
+    where
+        'v: 'a,
+    {
+        self.content().iter().map(|e| e.to_value())
+    }
 }
 
 impl<'v, T: ValueLike<'v>> StarlarkValue<'v> for TupleGen<T>
@@ -117,7 +190,7 @@ where
     fn collect_repr(&self, s: &mut String) {
         s.push('(');
         let mut first = true;
-        for v in &self.content {
+        for v in self.content() {
             if first {
                 first = false;
             } else {
@@ -126,62 +199,70 @@ where
             v.collect_repr(s);
         }
 
-        if self.content.len() == 1 {
+        if self.len() == 1 {
             s.push(',');
         }
         s.push(')');
     }
     fn to_bool(&self) -> bool {
-        !self.content.is_empty()
+        !self.content().is_empty()
     }
     fn get_hash(&self) -> anyhow::Result<u64> {
         let mut s = DefaultHasher::new();
-        for v in self.content.iter() {
+        for v in self.content().iter() {
             s.write_u64(v.get_hash()?)
         }
         Ok(s.finish())
     }
 
-    fn to_json(&self) -> String {
-        format!(
-            "[{}]",
-            self.content
-                .iter()
-                .map(|e| e.to_json())
-                .enumerate()
-                .fold(String::new(), |accum, s| if s.0 == 0 {
-                    accum + &s.1
-                } else {
-                    accum + "," + &s.1
-                },)
-        )
+    fn collect_json(&self, s: &mut String) {
+        s.push('[');
+        for (i, e) in self.content().iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            e.collect_json(s);
+        }
+        s.push(']');
+    }
+
+    fn collect_proto(
+        &self,
+        name: &str,
+        indent: usize,
+        collector: &mut String,
+    ) -> anyhow::Result<()> {
+        for v in self.content().iter() {
+            v.collect_proto(name, indent, collector)?;
+        }
+        Ok(())
     }
 
     fn equals(&self, other: Value<'v>) -> anyhow::Result<bool> {
         match Tuple::from_value(other) {
             None => Ok(false),
-            Some(other) => equals_slice(&self.content, &other.content, |x, y| x.equals(*y)),
+            Some(other) => equals_slice(self.content(), other.content(), |x, y| x.equals(*y)),
         }
     }
 
     fn compare(&self, other: Value<'v>) -> anyhow::Result<Ordering> {
         match Tuple::from_value(other) {
             None => ValueError::unsupported_with(self, "cmp()", other),
-            Some(other) => compare_slice(&self.content, &other.content, |x, y| x.compare(*y)),
+            Some(other) => compare_slice(self.content(), other.content(), |x, y| x.compare(*y)),
         }
     }
 
     fn at(&self, index: Value, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
-        let i = convert_index(index, self.content.len() as i32)? as usize;
-        Ok(self.content[i].to_value())
+        let i = convert_index(index, self.len() as i32)? as usize;
+        Ok(self.content()[i].to_value())
     }
 
     fn length(&self) -> anyhow::Result<i32> {
-        Ok(self.content.len() as i32)
+        Ok(self.len() as i32)
     }
 
     fn is_in(&self, other: Value<'v>) -> anyhow::Result<bool> {
-        for x in self.content.iter() {
+        for x in self.content().iter() {
             if x.equals(other)? {
                 return Ok(true);
             }
@@ -196,14 +277,8 @@ where
         stride: Option<Value>,
         heap: &'v Heap,
     ) -> anyhow::Result<Value<'v>> {
-        let (start, stop, stride) =
-            convert_slice_indices(self.content.len() as i32, start, stop, stride)?;
-        Ok(heap.alloc(Tuple::new(slice_vector(
-            start,
-            stop,
-            stride,
-            self.content.iter(),
-        ))))
+        let (start, stop, stride) = convert_slice_indices(self.len() as i32, start, stop, stride)?;
+        Ok(heap.alloc_tuple(&slice_vector(start, stop, stride, self.content().iter())))
     }
 
     fn iterate(&self) -> anyhow::Result<&(dyn StarlarkIterable<'v> + 'v)> {
@@ -212,16 +287,10 @@ where
 
     fn add(&self, other: Value<'v>, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         if let Some(other) = Tuple::from_value(other) {
-            let mut result = Tuple {
-                content: Vec::with_capacity(self.content.len() + other.len()),
-            };
-            for x in self.iter() {
-                result.content.push(x);
-            }
-            for x in other.iter() {
-                result.content.push(x);
-            }
-            Ok(heap.alloc(result))
+            let mut result = Vec::with_capacity(self.len() + other.len());
+            result.extend(self.iter());
+            result.extend(other.iter());
+            Ok(heap.alloc_tuple(&result))
         } else {
             ValueError::unsupported_with(self, "a", other)
         }
@@ -230,15 +299,11 @@ where
     fn mul(&self, other: Value, heap: &'v Heap) -> anyhow::Result<Value<'v>> {
         match other.unpack_int() {
             Some(l) => {
-                let mut result = Tuple {
-                    content: Vec::new(),
-                };
+                let mut result = Vec::new();
                 for _i in 0..l {
-                    result
-                        .content
-                        .extend(self.content.iter().map(|e| e.to_value()));
+                    result.extend(self.iter());
                 }
-                Ok(heap.alloc(result))
+                Ok(heap.alloc_tuple(&result))
             }
             None => Err(ValueError::IncorrectParameterType.into()),
         }
@@ -256,17 +321,13 @@ impl<'v, T: ValueLike<'v> + 'v> StarlarkIterable<'v> for TupleGen<T> {
 
 impl<'v, T1: AllocValue<'v>> AllocValue<'v> for (T1,) {
     fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
-        heap.alloc(Tuple {
-            content: vec![self.0.alloc_value(heap)],
-        })
+        heap.alloc_tuple(&[self.0.alloc_value(heap)])
     }
 }
 
 impl<'v, T1: AllocValue<'v>, T2: AllocValue<'v>> AllocValue<'v> for (T1, T2) {
     fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
-        heap.alloc(Tuple {
-            content: vec![self.0.alloc_value(heap), self.1.alloc_value(heap)],
-        })
+        heap.alloc_tuple(&[self.0.alloc_value(heap), self.1.alloc_value(heap)])
     }
 }
 
@@ -274,13 +335,11 @@ impl<'v, T1: AllocValue<'v>, T2: AllocValue<'v>, T3: AllocValue<'v>> AllocValue<
     for (T1, T2, T3)
 {
     fn alloc_value(self, heap: &'v Heap) -> Value<'v> {
-        heap.alloc(Tuple {
-            content: vec![
-                self.0.alloc_value(heap),
-                self.1.alloc_value(heap),
-                self.2.alloc_value(heap),
-            ],
-        })
+        heap.alloc_tuple(&[
+            self.0.alloc_value(heap),
+            self.1.alloc_value(heap),
+            self.2.alloc_value(heap),
+        ])
     }
 }
 
@@ -291,15 +350,34 @@ impl<'v, T1: UnpackValue<'v>, T2: UnpackValue<'v>> UnpackValue<'v> for (T1, T2)
             return None;
         }
         Some((
-            T1::unpack_value(t.content[0], heap)?,
-            T2::unpack_value(t.content[1], heap)?,
+            T1::unpack_value(t.content()[0], heap)?,
+            T2::unpack_value(t.content()[1], heap)?,
+        ))
+    }
+}
+
+impl<'v, T1: UnpackValue<'v>, T2: UnpackValue<'v>, T3: UnpackValue<'v>> UnpackValue<'v>
+    for (T1, T2, T3)
+{
+    fn unpack_value(value: Value<'v>, heap: &'v Heap) -> Option<Self> {
+        let t = Tuple::from_value(value)?;
+        if t.len() != 3 {
+            return None;
+        }
+        Some((
+            T1::unpack_value(t.content()[0], heap)?,
+            T2::unpack_value(t.content()[1], heap)?,
+            T3::unpack_value(t.content()[2], heap)?,
         ))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::assert;
+    use crate::{
+        assert::{self, Assert},
+        values::{tuple::Tuple, Heap},
+    };
 
     #[test]
     fn test_to_str() {
@@ -308,6 +386,42 @@ mod tests {
 str((1, 2, 3)) == "(1, 2, 3)"
 str((1, (2, 3))) == "(1, (2, 3))"
 str((1,)) == "(1,)"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_tuple_new() {
+        let heap = Heap::new();
+        let x = heap.alloc(Tuple::new(vec![heap.alloc(1), heap.alloc("a")]));
+        assert_eq!(x.to_repr(), "(1, \"a\")");
+        assert_eq!(Tuple::from_value(x).unwrap().len(), 2);
+        assert_eq!(heap.alloc(Tuple::new(Vec::<i32>::new())).to_repr(), "()");
+    }
+
+    #[test]
+    fn test_tuple_moves() {
+        // Tuples keep their elements inline, so check they survive being moved
+        let mut a = Assert::new();
+        a.module(
+            "t.bzl",
+            r#"
+x = [1]
+t = (x, (2, "three"), ())
+x.append(t)
+"#,
+        );
+        a.pass(
+            r#"
+load("t.bzl", "t")
+u = (t, [t], (4,) * 3, tuple([5, 6]))
+garbage_collect()
+assert_eq(u[0][1], (2, "three"))
+assert_eq(u[0][0][1][1], (2, "three"))
+assert_eq(u[2], (4, 4, 4))
+assert_eq(u[3] + u[2][:1], (5, 6, 4))
+garbage_collect()
+assert_eq(u[1][0][0][0], 1)
 "#,
         );
     }
@@ -0,0 +1,67 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! A global naming a type, usually one defined by the embedder.
+
+use crate::values::{FrozenValue, Heap, StarlarkValue, Value, ValueError};
+
+/// A value standing for a type, registered with
+/// [`GlobalsBuilder::set_type_name`](crate::environment::GlobalsBuilder::set_type_name).
+/// Exposes the type as `.type`, for use in type annotations, and can be passed to `isinstance`.
+#[derive(Debug)]
+pub struct TypeName {
+    name: String,
+    typ: FrozenValue, // Must be a string, the result of `get_type` on matching values
+}
+
+starlark_simple_value!(TypeName);
+
+impl TypeName {
+    /// Create a [`TypeName`] called `name` which matches values whose
+    /// [`get_type`](StarlarkValue::get_type) is the string `typ`.
+    pub fn new(name: &str, typ: FrozenValue) -> Self {
+        Self {
+            name: name.to_owned(),
+            typ,
+        }
+    }
+}
+
+impl<'v> StarlarkValue<'v> for TypeName {
+    starlark_type!("type");
+
+    fn collect_repr(&self, s: &mut String) {
+        s.push_str("<type ");
+        s.push_str(&self.name);
+        s.push('>');
+    }
+
+    fn get_attr(&self, attribute: &str, _heap: &'v Heap) -> anyhow::Result<Value<'v>> {
+        if attribute == "type" {
+            Ok(self.typ.to_value())
+        } else {
+            ValueError::unsupported(self, &format!(".{}", attribute))
+        }
+    }
+
+    fn has_attr(&self, attribute: &str) -> bool {
+        attribute == "type"
+    }
+
+    fn dir_attr(&self) -> Vec<String> {
+        vec!["type".to_owned()]
+    }
+}
@@ -19,7 +19,7 @@ use crate::values::{dict::Dict, list::List, tuple::Tuple, Value};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
-enum TypingError {
+pub(crate) enum TypingError {
     /// The value does not have the specified type
     #[error("Value `{0}` of type `{1}` does not match the type annotation `{2}` for {3}")]
     TypeAnnotationMismatch(String, String, String, String),
@@ -28,6 +28,11 @@ enum TypingError {
     InvalidTypeAnnotation(String),
 }
 
+// Dictionary with a single element
+fn unpack_singleton_dictionary<'v>(x: &Dict<'v>) -> Option<(Value<'v>, Value<'v>)> {
+    if x.len() == 1 { x.iter().next() } else { None }
+}
+
 impl<'v> Value<'v> {
     pub(crate) fn is_type(self, ty: Value<'v>) -> anyhow::Result<bool> {
         // Types that are "" are start with "_" are wildcard - they match everything
@@ -35,11 +40,6 @@ impl<'v> Value<'v> {
             x == "" || x.starts_with('_')
         }
 
-        // Dictionary with a single element
-        fn unpack_singleton_dictionary<'v>(x: &Dict<'v>) -> Option<(Value<'v>, Value<'v>)> {
-            if x.len() == 1 { x.iter().next() } else { None }
-        }
-
         if let Some(s) = ty.unpack_str() {
             if is_wildcard(s) {
                 Ok(true)
@@ -62,7 +62,7 @@ impl<'v> Value<'v> {
             }
         } else if let Some(t) = List::from_value(ty) {
             match t.len() {
-                0 => Err(TypingError::InvalidTypeAnnotation(ty.to_str()).into()),
+                0 => Err(TypingError::InvalidTypeAnnotation(ty.to_str_for_error()).into()),
                 1 => {
                     // Must be a list with all elements of this type
                     match List::from_value(self) {
@@ -120,7 +120,82 @@ impl<'v> Value<'v> {
                 }
             }
         } else {
-            Err(TypingError::InvalidTypeAnnotation(ty.to_str()).into())
+            Err(TypingError::InvalidTypeAnnotation(ty.to_str_for_error()).into())
+        }
+    }
+
+    /// Check that this value is a valid type annotation, so that a mistake in an annotation
+    /// is reported where it is written, rather than only once a value reaches a part of it.
+    /// The error names the first invalid part, e.g. `1` in `[int.type, 1]`.
+    pub(crate) fn validate_type(self) -> anyhow::Result<()> {
+        // A list annotation can contain itself, so guard against unbounded recursion
+        let _guard = crate::eval::call_stack::try_inc()?;
+        if self.unpack_str().is_some() || self.is_none() {
+            Ok(())
+        } else if let Some(t) = Tuple::from_value(self) {
+            t.iter().try_for_each(Value::validate_type)
+        } else if let Some(t) = List::from_value(self) {
+            if t.len() == 0 {
+                Err(TypingError::InvalidTypeAnnotation(self.to_str_for_error()).into())
+            } else {
+                t.iter().try_for_each(Value::validate_type)
+            }
+        } else if let Some(t) = Dict::from_value(self) {
+            if let Some((kt, vt)) = unpack_singleton_dictionary(&t) {
+                kt.validate_type()?;
+                vt.validate_type()
+            } else {
+                // The keys are labels, which can be any value
+                t.iter().try_for_each(|(_, vt)| vt.validate_type())
+            }
+        } else {
+            Err(TypingError::InvalidTypeAnnotation(self.to_str_for_error()).into())
+        }
+    }
+
+    /// Describe a type annotation for an error message. Strings are shown without quotes,
+    /// as `int.type` is written rather than `"int"`, and unions are shown separated by `|`,
+    /// e.g. `[int.type, None]` as `int | None`.
+    pub(crate) fn type_to_str(self) -> String {
+        if let Some(s) = self.unpack_str() {
+            if s.is_empty() {
+                "\"\"".to_owned()
+            } else {
+                s.to_owned()
+            }
+        } else if self.is_none() {
+            "None".to_owned()
+        } else if let Some(t) = Tuple::from_value(self) {
+            let xs = t.iter().map(Value::type_to_str).collect::<Vec<_>>();
+            if xs.len() == 1 {
+                format!("({},)", xs[0])
+            } else {
+                format!("({})", xs.join(", "))
+            }
+        } else if let Some(t) = List::from_value(self) {
+            let xs = t.iter().map(Value::type_to_str).collect::<Vec<_>>();
+            if xs.len() == 1 {
+                format!("[{}]", xs[0])
+            } else {
+                xs.join(" | ")
+            }
+        } else if let Some(t) = Dict::from_value(self) {
+            // Only in a singleton dictionary is the key a type, otherwise it is a label
+            let singleton = t.len() == 1;
+            let xs = t
+                .iter()
+                .map(|(k, v)| {
+                    let k = if singleton {
+                        k.type_to_str()
+                    } else {
+                        k.to_repr()
+                    };
+                    format!("{}: {}", k, v.type_to_str())
+                })
+                .collect::<Vec<_>>();
+            format!("{{{}}}", xs.join(", "))
+        } else {
+            self.to_str_for_error()
         }
     }
 
@@ -129,9 +204,9 @@ impl<'v> Value<'v> {
             Ok(())
         } else {
             Err(TypingError::TypeAnnotationMismatch(
-                self.to_str(),
+                self.to_str_for_error(),
                 self.get_type().to_owned(),
-                ty.to_str(),
+                ty.type_to_str(),
                 match arg_name {
                     None => "return type".to_owned(),
                     Some(x) => format!("argument `{}`", x),
@@ -218,4 +293,55 @@ is_type([1,2,"test"], ["_a"])
         a.fail("is_type(None, is_type)", "not a valid type");
         a.fail("is_type(None, [])", "not a valid type");
     }
+
+    #[test]
+    fn test_union_types() {
+        let a = assert::Assert::new();
+        a.all_true(
+            r#"
+is_type(1, [int.type, None])
+is_type(None, [int.type, None])
+is_type([1, None], [[int.type, None]])
+is_type((None, "x"), ([int.type, None], str.type))
+not is_type("x", [int.type, None])
+not is_type([1, "x"], [[int.type, None]])
+"#,
+        );
+        a.pass("def f(x: [int.type, None] = None):\n pass\nf()\nf(1)");
+
+        // Mismatches show the annotation as a type, with unions separated by `|`
+        a.fails(
+            "def f(x: [int.type, None]):\n pass\nf('x')",
+            &["`x`", "`string`", "`int | None`", "`x`"],
+        );
+        a.fail(
+            "def f() -> [str.type]:\n return [1]\nf()",
+            "type annotation `[string]`",
+        );
+        a.fail(
+            "def f(x: ([int.type, None], {str.type: bool.type})):\n pass\nf(1)",
+            "type annotation `(int | None, {string: bool})`",
+        );
+        a.fail(
+            "def f(x: {'a': int.type, 'b': ''}):\n pass\nf(1)",
+            r#"type annotation `{"a": int, "b": ""}`"#,
+        );
+
+        // Invalid annotations are reported when the function is defined, even if never called,
+        // naming the part which is wrong
+        a.fails(
+            "def f(x: [int.type, 1]):\n pass",
+            &["`1`", "not a valid type annotation"],
+        );
+        a.fails(
+            "def f() -> {str.type: []}:\n pass",
+            &["`[]`", "not a valid type annotation"],
+        );
+        a.fail("record(x = 1)", "not a valid type annotation");
+        a.fail("field([int.type, is_type])", "not a valid type annotation");
+        a.fail(
+            "t = [int.type]\nt.append(t)\ndef f(x: t):\n pass",
+            "Too many recursion levels",
+        );
+    }
 }
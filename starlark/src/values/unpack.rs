@@ -17,7 +17,8 @@
 
 //! Parameter conversion utilities for `starlark_module` macros.
 
-use crate::values::{ComplexValue, Heap, Value};
+use crate::values::{dict::Dict, structs::Struct, ComplexValue, Heap, Value};
+use either::Either;
 use gazebo::{any::AnyLifetime, cell::ARef};
 use std::{cell::RefMut, ops::Deref};
 
@@ -88,3 +89,48 @@ impl<'v, T: ComplexValue<'v> + AnyLifetime<'v>> UnpackValue<'v> for RefMut<'v, T
         value.downcast_mut(heap).ok()?
     }
 }
+
+/// Unpack as either of two types, trying [`Left`](Either::Left) first.
+impl<'v, A: UnpackValue<'v>, B: UnpackValue<'v>> UnpackValue<'v> for Either<A, B> {
+    fn unpack_value(value: Value<'v>, heap: &'v Heap) -> Option<Self> {
+        match A::unpack_value(value, heap) {
+            Some(a) => Some(Either::Left(a)),
+            None => B::unpack_value(value, heap).map(Either::Right),
+        }
+    }
+}
+
+/// The named fields of a dict (with string keys) or a struct. Used by the code
+/// generated by [`#[derive(UnpackValue)]`](derive@UnpackValue), which unpacks a Rust
+/// struct with named fields from either.
+pub struct UnpackFields<'v>(Either<ARef<'v, Dict<'v>>, ARef<'v, Struct<'v>>>);
+
+impl<'v> UnpackFields<'v> {
+    /// Returns [`None`] if the value is neither a dict nor a struct.
+    pub fn new(value: Value<'v>) -> Option<Self> {
+        match Dict::from_value(value) {
+            Some(x) => Some(Self(Either::Left(x))),
+            None => Struct::from_value(value).map(|x| Self(Either::Right(x))),
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<Value<'v>> {
+        match &self.0 {
+            Either::Left(x) => x.get_str(name),
+            Either::Right(x) => x.fields.get(name).copied(),
+        }
+    }
+
+    /// Unpack a field which must be present.
+    pub fn field<T: UnpackValue<'v>>(&self, name: &str, heap: &'v Heap) -> Option<T> {
+        T::unpack_value(self.get(name)?, heap)
+    }
+
+    /// Unpack a field which may be missing or `None`.
+    pub fn field_opt<T: UnpackValue<'v>>(&self, name: &str, heap: &'v Heap) -> Option<Option<T>> {
+        match self.get(name) {
+            None => Some(None),
+            Some(v) => Option::<T>::unpack_value(v, heap),
+        }
+    }
+}
@@ -23,6 +23,7 @@ extern crate proc_macro;
 
 use gazebo::prelude::*;
 use proc_macro::TokenStream;
+use proc_macro2::{Delimiter, Group, Spacing, TokenTree};
 use quote::{quote, ToTokens};
 use syn::*;
 
@@ -58,7 +59,7 @@ use syn::*;
 //             })
 //         }
 //         match inner(ctx, args) {
-//             Ok(v) => Ok(ctx.heap().alloc(v)),
+//             Ok(v) => starlark::values::IntoValue::into_value(v, ctx.heap()),
 //             Err(e) => Err(e),
 //         }
 //     }
@@ -89,20 +90,31 @@ use syn::*;
 /// }
 /// ```
 ///
-/// Parameters operate as named parameters of a given type, with five possible tweaks:
+/// Parameters operate as named parameters of a given type, with seven possible tweaks:
 ///
-/// * `args` means the argument is the `*args`.
+/// * `args` means the argument is the `*args`, e.g. `args: Vec<ValueOf<i32>>`.
 /// * `kwargs` means the argument is the `**kwargs`.
 /// * `ref name` means the argument must be passed by position, not by name.
+/// * `#[kw_only] name` means the argument must be passed by name, not by position.
+///   All the arguments after it must also be `#[kw_only]`, and arguments after `args`
+///   are always passed by name.
 /// * A type of `Option` means the argument is optional.
 /// * A pattern `x @ foo : bool` means the argument defaults to `foo` if not
 ///   specified.
+/// * `#[default(expr)] x: bool` means the argument defaults to the expression `expr`,
+///   which can be anything valid in a `const`, not just a pattern.
 ///
-/// During execution there are two local variables injected into scope:
+/// During execution there are three local variables injected into scope:
 ///
 /// * `ctx` is the `Evaluator`.
+/// * `eval` is also the `Evaluator`, borrowed from `ctx`, so only use one of them.
 /// * `heap` is the `Heap`, obtained from `ctx.heap()`.
 ///
+/// Those names, along with `starlark_args`, are reserved and can't be used for parameters.
+///
+/// The result is converted to a Starlark value with `IntoValue`, so may be anything implementing
+/// `AllocValue`, or a type such as `u64` or `HashMap` where the conversion can fail.
+///
 /// A function with the `#[starlark_module]` attribute can be added to a `GlobalsBuilder` value
 /// using the `with` function. Those `Globals` can be passed to `Evaluator` to provide global functions.
 /// Alternatively, you can return `Globals` from `get_members` to _attach_ functions to
@@ -138,23 +150,215 @@ pub fn starlark_module(attr: TokenStream, input: TokenStream) -> TokenStream {
     result.into()
 }
 
+/// Define the methods of a Rust type used from Starlark, without writing a separate
+/// `#[starlark_module]` and [`GlobalsStatic`].
+///
+/// Applied to an `impl` of the type, each method must take `&self`, and otherwise follows
+/// the rules of [`#[starlark_module]`](macro@starlark_module), including `#[attribute]`.
+/// The `impl` gains a private function `starlark_members`, which should be returned from
+/// `StarlarkValue::get_members`:
+///
+/// ```ignore
+/// #[starlark_methods]
+/// impl Bool2 {
+///     #[attribute]
+///     fn invert(&self) -> Bool2 {
+///         Ok(Bool2(!self.0))
+///     }
+/// }
+///
+/// impl<'v> StarlarkValue<'v> for Bool2 {
+///     starlark_type!("bool2");
+///
+///     fn get_members(&self) -> Option<&'static Globals> {
+///         Self::starlark_members()
+///     }
+/// }
+/// ```
+///
+/// The type can't have generic or lifetime parameters, so is usually defined with
+/// `starlark_simple_value!`.
+#[proc_macro_attribute]
+pub fn starlark_methods(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemImpl);
+    assert!(attr.is_empty());
+    assert!(
+        input.trait_.is_none(),
+        "Can only use #[starlark_methods] on an `impl` of a type, not of a trait"
+    );
+    assert!(
+        input.generics.params.is_empty(),
+        "Can't use #[starlark_methods] on a type with generic or lifetime parameters"
+    );
+
+    let ty = &input.self_ty;
+    let methods = input.items.map(|x| match x {
+        ImplItem::Method(x) => add_method(x, ty),
+        _ => panic!("Can only put methods inside a #[starlark_methods]"),
+    });
+    let result = quote! {
+        impl #ty {
+            /// The methods defined with `#[starlark_methods]`.
+            fn starlark_members() -> Option<&'static starlark::environment::Globals> {
+                static RES: starlark::environment::GlobalsStatic =
+                    starlark::environment::GlobalsStatic::new();
+                fn members(globals_builder: &mut starlark::environment::GlobalsBuilder) {
+                    #( #methods )*
+                }
+                RES.members(members)
+            }
+        }
+    };
+    result.into()
+}
+
+/// Derive [`UnpackValue`] for a struct with named fields, so it can be passed to a
+/// `#[starlark_module]` function as either a dict with string keys or a struct.
+///
+/// Fields of type `Option` may be missing or `None`, all other fields must be present.
+///
+/// ```ignore
+/// #[derive(UnpackValue)]
+/// struct Target<'v> {
+///     name: &'v str,
+///     srcs: Vec<&'v str>,
+///     visibility: Option<String>,
+/// }
+/// ```
+///
+/// The struct may have at most one lifetime parameter, which is used as the `'v` lifetime.
+/// Any type parameters must themselves implement [`UnpackValue`].
+#[proc_macro_derive(UnpackValue)]
+pub fn derive_unpack_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(FieldsNamed { named, .. }),
+            ..
+        }) => named,
+        _ => panic!("Can only derive UnpackValue for a struct with named fields"),
+    };
+    assert!(
+        input.generics.lifetimes().count() <= 1,
+        "Can only derive UnpackValue for a struct with at most one lifetime parameter, for `{}`",
+        name
+    );
+
+    // Values are unpacked for the struct's lifetime, or a new one if it has none,
+    // and any type parameters must also be unpackable
+    let mut generics = input.generics.clone();
+    let lifetime = match generics.lifetimes().next() {
+        Some(x) => x.lifetime.clone(),
+        None => {
+            let v: Lifetime = parse_quote!('v);
+            generics.params.insert(0, parse_quote!(#v));
+            v
+        }
+    };
+    for x in generics.type_params_mut() {
+        x.bounds
+            .push(parse_quote!(starlark::values::UnpackValue<#lifetime>));
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let unpack_fields = fields.iter().map(|field| {
+        let ident = field.ident.as_ref().unwrap();
+        let ident_string = ident.to_string();
+        let ident_str = ident_string.trim_start_match("r#");
+        if is_type_option(&field.ty) {
+            quote! { #ident: starlark_fields.field_opt(#ident_str, heap)? }
+        } else {
+            quote! { #ident: starlark_fields.field(#ident_str, heap)? }
+        }
+    });
+    let result = quote! {
+        impl #impl_generics starlark::values::UnpackValue<#lifetime>
+            for #name #ty_generics #where_clause
+        {
+            fn unpack_value(
+                value: starlark::values::Value<#lifetime>,
+                #[allow(unused_variables)]
+                heap: &#lifetime starlark::values::Heap,
+            ) -> Option<Self> {
+                #[allow(unused_variables)]
+                let starlark_fields = starlark::values::UnpackFields::new(value)?;
+                Some(Self {
+                    #( #unpack_fields, )*
+                })
+            }
+        }
+    };
+    result.into()
+}
+
+// Names injected into the body of a function, so can't be used for parameters
+const RESERVED: &[&str] = &["ctx", "eval", "heap", "starlark_args"];
+
 #[derive(Clone)]
 struct Arg<'a> {
-    attrs: &'a Vec<Attribute>,
+    attrs: Vec<&'a Attribute>,
     ident: &'a PatIdent,
     ty: &'a Type,
+    // Specified with `#[default(expr)]` or `name @ default : type`
+    default: Option<proc_macro2::TokenStream>,
+    // Specified with `#[kw_only]`
+    kw_only: bool,
 }
 
 impl<'a> Arg<'a> {
     fn new(x: &'a FnArg) -> Self {
-        match x {
+        let (attrs, ident, ty) = match x {
             FnArg::Typed(PatType {
                 attrs,
                 pat: box Pat::Ident(ident),
                 ty: box ty,
                 ..
-            }) => Self { attrs, ident, ty },
+            }) => (attrs, ident, ty),
             arg => panic!("Unexpected argument, {:?}", arg),
+        };
+        let name = ident.ident.to_string();
+        assert!(
+            !RESERVED.contains(&name.as_str()),
+            "Can't use the reserved name `{}` for an argument",
+            name
+        );
+
+        let mut rest = Vec::with_capacity(attrs.len());
+        let mut default = ident.subpat.as_ref().map(|(_, x)| x.to_token_stream());
+        let mut kw_only = false;
+        for x in attrs {
+            if x.path.is_ident("kw_only") {
+                kw_only = true;
+            } else if x.path.is_ident("default") {
+                let expr = x.parse_args::<Expr>().unwrap_or_else(|_| {
+                    panic!(
+                        "Couldn't parse attribute `{:?}`. Expected `#[default(expr)]`",
+                        x
+                    )
+                });
+                assert!(
+                    default.is_none(),
+                    "Can't have more than one default, for `{}`",
+                    name
+                );
+                default = Some(expr.to_token_stream());
+            } else {
+                rest.push(x);
+            }
+        }
+        assert!(
+            !(kw_only && ident.by_ref.is_some()),
+            "Can't have an argument which is both `ref` and `#[kw_only]`, for `{}`",
+            name
+        );
+        Self {
+            attrs: rest,
+            ident,
+            ty,
+            default,
+            kw_only,
         }
     }
 }
@@ -227,7 +431,7 @@ fn add_function(func: &ItemFn) -> proc_macro2::TokenStream {
     let body = &func.block;
     let args = func.sig.inputs.iter().map(Arg::new).collect::<Vec<_>>();
     let bind_args = args.map(bind_argument);
-    let signature = args.map(record_argument);
+    let signature = record_arguments(&args);
     let setter = if is_attribute {
         quote! {
             let func = globals_builder.alloc(
@@ -271,10 +475,12 @@ fn add_function(func: &ItemFn) -> proc_macro2::TokenStream {
                 #[allow(unused_variables)]
                 let heap = ctx.heap();
                 #( #bind_args )*
+                #[allow(unused_variables)]
+                let eval = &mut *ctx;
                 #body
             }
             match inner(ctx, starlark_args) {
-                Ok(v) => Ok(ctx.heap().alloc(v)),
+                Ok(v) => starlark::values::IntoValue::into_value(v, ctx.heap()),
                 Err(e) => Err(e),
             }
         }
@@ -287,11 +493,88 @@ fn add_function(func: &ItemFn) -> proc_macro2::TokenStream {
     }
 }
 
+// Turn a method taking `&self` into a function taking `this`, then add it like any other.
+fn add_method(method: &ImplItemMethod, ty: &Type) -> proc_macro2::TokenStream {
+    let sig = &method.sig;
+    let name = &sig.ident;
+    match sig.inputs.first() {
+        Some(FnArg::Receiver(Receiver {
+            reference: Some(_),
+            mutability: None,
+            ..
+        })) => {}
+        _ => panic!(
+            "Method `{}` in a #[starlark_methods] must take `&self`",
+            name
+        ),
+    }
+    let attrs = &method.attrs;
+    let args = sig.inputs.iter().skip(1);
+    let output = &sig.output;
+    let body = replace_self(method.block.to_token_stream(), ty);
+    let func: ItemFn = parse_quote! {
+        #( #attrs )*
+        fn #name(this: starlark::values::Value, #( #args ),*) #output {
+            let this = this.downcast_ref::<#ty>().ok_or_else(|| {
+                starlark::values::ValueError::IncorrectParameterTypeNamed("this".to_owned())
+            })?;
+            let this: &#ty = &*this;
+            #body
+        }
+    };
+    add_function(&func)
+}
+
+// Replace `self` with `this`, and `Self` with the type, so a method body works as a function body
+fn replace_self(xs: proc_macro2::TokenStream, ty: &Type) -> proc_macro2::TokenStream {
+    replace_self_in(xs, ty, false)
+}
+
+// Like `replace_self`, but only the receiver `self` is replaced, not one in a path such as
+// `self::helper` or `super::{self}`. If `in_path` is set the tokens are the `{...}` of a path.
+fn replace_self_in(
+    xs: proc_macro2::TokenStream,
+    ty: &Type,
+    in_path: bool,
+) -> proc_macro2::TokenStream {
+    let xs: Vec<TokenTree> = xs.into_iter().collect();
+    // Does a `::` start at index `i`
+    let path_sep = |i: usize| match (xs.get(i), xs.get(i + 1)) {
+        (Some(TokenTree::Punct(a)), Some(TokenTree::Punct(b))) => {
+            a.as_char() == ':' && a.spacing() == Spacing::Joint && b.as_char() == ':'
+        }
+        _ => false,
+    };
+    let after_path_sep = |i: usize| i >= 2 && path_sep(i - 2);
+
+    let mut res = proc_macro2::TokenStream::new();
+    for (i, x) in xs.iter().enumerate() {
+        match x {
+            TokenTree::Ident(x)
+                if x == "self" && !in_path && !path_sep(i + 1) && !after_path_sep(i) =>
+            {
+                res.extend(quote! {this});
+            }
+            TokenTree::Ident(x) if x == "Self" => {
+                res.extend(ty.to_token_stream());
+            }
+            TokenTree::Group(x) => {
+                let in_path = x.delimiter() == Delimiter::Brace && after_path_sep(i);
+                let mut group = Group::new(x.delimiter(), replace_self_in(x.stream(), ty, in_path));
+                group.set_span(x.span());
+                res.extend(Some(TokenTree::Group(group)));
+            }
+            x => res.extend(Some(x.clone())),
+        }
+    }
+    res
+}
+
 fn bind_argument(arg: &Arg) -> proc_macro2::TokenStream {
     let name = &arg.ident.ident;
     let name_str = name.to_string();
     let ty = arg.ty;
-    let default = get_default(arg);
+    let default = arg.default.as_ref();
 
     let next = if is_type_option(ty) {
         assert!(
@@ -315,23 +598,66 @@ fn bind_argument(arg: &Arg) -> proc_macro2::TokenStream {
     }
 }
 
+fn record_arguments(args: &[Arg]) -> Vec<proc_macro2::TokenStream> {
+    let mut res = Vec::with_capacity(args.len());
+    // Have we seen `*args` or `#[kw_only]`, after which arguments can only be passed by name
+    let mut named_only = false;
+    let mut seen_kw_only = false;
+    for arg in args {
+        let name = arg.ident.ident.to_string();
+        if arg.kw_only {
+            if !named_only {
+                res.push(quote! {signature.kw_only();});
+                named_only = true;
+            }
+            seen_kw_only = true;
+        } else {
+            assert!(
+                !seen_kw_only || name == "kwargs",
+                "Argument `{}` comes after a `#[kw_only]` argument, so must also be `#[kw_only]`",
+                name
+            );
+        }
+        if name == "args" && arg.ident.by_ref.is_none() {
+            assert!(
+                !named_only,
+                "Can't have `args` after a `#[kw_only]` argument"
+            );
+            named_only = true;
+        }
+        res.push(record_argument(arg));
+    }
+    res
+}
+
 fn record_argument(arg: &Arg) -> proc_macro2::TokenStream {
     let name = &arg.ident.ident;
-    let mut name_str_full = (if arg.ident.by_ref.is_some() { "$" } else { "" }).to_owned();
-    name_str_full += &name.to_string();
+    let name_str_full = name.to_string();
     let name_str = name_str_full.trim_matches('_');
-    let default = get_default(arg);
+    let default = arg.default.as_ref();
+    // Arguments marked `ref` can only be supplied by position, as can `this`, which is
+    // always the first argument of a method, so comes before any `ref` arguments
+    let pos_only = arg.ident.by_ref.is_some() || name_str_full == "this";
+    let (required, optional, defaulted) = if pos_only {
+        (
+            quote! {required_pos_only},
+            quote! {optional_pos_only},
+            quote! {defaulted_pos_only},
+        )
+    } else {
+        (quote! {required}, quote! {optional}, quote! {defaulted})
+    };
     match name_str_full.as_str() {
-        "args" => {
+        "args" if !pos_only => {
             assert!(default.is_none(), "Can't have *args with a default");
             quote! {signature.args("args");}
         }
-        "kwargs" => {
+        "kwargs" if !pos_only => {
             assert!(default.is_none(), "Can't have **kwargs with a default");
             quote! {signature.kwargs("kwargs");}
         }
         _ if is_type_option(arg.ty) => {
-            quote! {signature.optional(#name_str);}
+            quote! {signature.#optional(#name_str);}
         }
         _ if default.is_some() => {
             let default = default.unwrap();
@@ -340,13 +666,13 @@ fn record_argument(arg: &Arg) -> proc_macro2::TokenStream {
             // For things that aren't type value, use optional and then next_opt/unwrap
             // to avoid the to/from value conversion.
             if is_type_value(ty) {
-                quote! {signature.defaulted(#name_str, globals_builder.alloc(#default));}
+                quote! {signature.#defaulted(#name_str, globals_builder.alloc(#default));}
             } else {
-                quote! {signature.optional(#name_str);}
+                quote! {signature.#optional(#name_str);}
             }
         }
         _ => {
-            quote! {signature.required(#name_str);}
+            quote! {signature.#required(#name_str);}
         }
     }
 }
@@ -365,11 +691,6 @@ fn is_type_name(x: &Type, name: &str) -> bool {
     false
 }
 
-// Get the default value, specified with `name @ default : type`
-fn get_default<'a>(arg: &'a Arg) -> Option<&'a Pat> {
-    arg.ident.subpat.as_ref().map(|(_, x)| &**x)
-}
-
 // Is the type `Option<foo>`
 fn is_type_option(x: &Type) -> bool {
     is_type_name(x, "Option")